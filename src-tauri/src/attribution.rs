@@ -0,0 +1,172 @@
+// src-tauri/src/attribution.rs
+//! Blame-style attribution of the current text.
+//!
+//! Walks the Save snapshots in order and carries an owner for every byte of
+//! text through each diff, so surviving spans keep the patch that introduced
+//! them. Ranges use UTF-16 offsets like the hunks sent to the editor.
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::db_utils::ensure_schema;
+use crate::document_manager::{author_display_name, row_to_patch, DocumentManager};
+use crate::hunk_calculator::{calculate_hunks, DEFAULT_HUNK_COLOR};
+
+/// A span of the current text attributed to the patch that introduced it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttributedRange {
+    /// Starting character index (UTF-16, inclusive)
+    pub start: usize,
+    /// Ending character index (UTF-16, exclusive)
+    pub end: usize,
+    pub patch_id: i64,
+    pub patch_uuid: Option<String>,
+    pub author: String,
+    pub author_name: String,
+    pub author_color: String,
+    pub timestamp: i64,
+}
+
+/// Attribute the last snapshot to the snapshots that introduced each span
+///
+/// Returns `(start, end, snapshot_index)` triples in UTF-16 offsets.
+pub fn attribute_snapshots(snapshots: &[&str]) -> Vec<(usize, usize, usize)> {
+    let mut text = "";
+    let mut owners: Vec<usize> = Vec::new();
+
+    for (index, next) in snapshots.iter().enumerate() {
+        let mut next_owners = Vec::with_capacity(next.len());
+        let mut cursor = 0;
+
+        for hunk in calculate_hunks(text, next) {
+            next_owners.extend_from_slice(&owners[cursor..hunk.base_start_byte]);
+
+            let mut pos = hunk.base_start_byte;
+            for part in &hunk.parts {
+                let len = part.text.len();
+                match part.part_type.as_str() {
+                    "equal" => {
+                        next_owners.extend_from_slice(&owners[pos..pos + len]);
+                        pos += len;
+                    }
+                    "delete" => pos += len,
+                    _ => next_owners.extend(std::iter::repeat_n(index, len)),
+                }
+            }
+            cursor = hunk.base_end_byte;
+        }
+        next_owners.extend_from_slice(&owners[cursor..]);
+
+        // Guard against drift; anything unaccounted for belongs to this snapshot
+        next_owners.resize(next.len(), index);
+
+        text = next;
+        owners = next_owners;
+    }
+
+    let mut ranges: Vec<(usize, usize, usize)> = Vec::new();
+    let mut offset = 0;
+    for (byte_idx, c) in text.char_indices() {
+        let owner = owners[byte_idx];
+        let len = c.len_utf16();
+        match ranges.last_mut() {
+            Some(last) if last.2 == owner => last.1 += len,
+            _ => ranges.push((offset, offset + len, owner)),
+        }
+        offset += len;
+    }
+
+    ranges
+}
+
+/// Compute per-span authorship of the latest Save snapshot
+#[tauri::command]
+pub fn compute_attribution(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+) -> Result<Vec<AttributedRange>, String> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+
+    let doc = manager
+        .documents
+        .get(&doc_id)
+        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+
+    if !doc.history_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    ensure_schema(&conn)?;
+
+    let mut stmt = conn
+        .prepare("SELECT id, timestamp, author, kind, data, uuid, parent_uuid FROM patches WHERE kind = 'Save' ORDER BY id ASC")
+        .map_err(|e| e.to_string())?;
+
+    let patches = stmt
+        .query_map([], row_to_patch)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    // Only Save patches carrying a snapshot contribute to the text
+    let patches: Vec<_> = patches
+        .into_iter()
+        .filter(|p| p.data.get("snapshot").and_then(|s| s.as_str()).is_some())
+        .collect();
+    let snapshots: Vec<&str> = patches
+        .iter()
+        .filter_map(|p| p.data.get("snapshot").and_then(|s| s.as_str()))
+        .collect();
+
+    Ok(attribute_snapshots(&snapshots)
+        .into_iter()
+        .map(|(start, end, index)| {
+            let patch = &patches[index];
+            AttributedRange {
+                start,
+                end,
+                patch_id: patch.id,
+                patch_uuid: patch.uuid.clone(),
+                author: patch.author.clone(),
+                author_name: author_display_name(&doc.meta, &patch.author),
+                author_color: DEFAULT_HUNK_COLOR.to_string(),
+                timestamp: patch.timestamp,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_surviving_spans_keep_original_author() {
+        let snapshots = ["Hello world", "Hello brave world", "Hi brave world again"];
+        let ranges = attribute_snapshots(&snapshots);
+
+        let owner_of = |needle: &str| {
+            let start = snapshots[2].find(needle).unwrap();
+            ranges.iter().find(|r| r.0 <= start && start < r.1).unwrap().2
+        };
+
+        assert_eq!(owner_of("Hi"), 2);
+        assert_eq!(owner_of("brave"), 1);
+        assert_eq!(owner_of("world"), 0);
+        assert_eq!(owner_of("again"), 2);
+        assert_eq!(ranges.last().unwrap().1, snapshots[2].encode_utf16().count());
+    }
+
+    #[test]
+    fn test_utf16_offsets() {
+        let snapshots = ["😊 a", "😊 a b"];
+        let ranges = attribute_snapshots(&snapshots);
+
+        assert_eq!(ranges.first().unwrap().0, 0);
+        assert_eq!(ranges.last().unwrap().1, 6);
+        assert_eq!(ranges.last().unwrap().2, 1);
+    }
+}
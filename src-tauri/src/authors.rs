@@ -0,0 +1,321 @@
+// src-tauri/src/authors.rs
+//! Author identities of a document: merging and renaming.
+//!
+//! The same person often records patches under several IDs, e.g. one
+//! derived from the host name before a profile existed and the profile ID
+//! after. `merge_authors` rewrites one ID into another in the history
+//! database of the document and of its sections: patches, reviews, review
+//! comments, orphaned patches and comments. The merge is remembered in the
+//! `author_aliases` table so patches imported later under the old ID are
+//! rewritten too. Yjs updates keep the ID they were recorded under, as they
+//! only use it to keep sequence numbers apart.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::comments::init_comments_table;
+use crate::db_utils::ensure_schema;
+use crate::document_manager::DocumentManager;
+use crate::error::KorppiError;
+use crate::kmd::AuthorRef;
+
+/// Rows rewritten by an author merge
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct AuthorMergeResult {
+    pub patches: usize,
+    pub reviews: usize,
+    pub review_comments: usize,
+    pub comments: usize,
+}
+
+impl AuthorMergeResult {
+    fn add(&mut self, other: AuthorMergeResult) {
+        self.patches += other.patches;
+        self.reviews += other.reviews;
+        self.review_comments += other.review_comments;
+        self.comments += other.comments;
+    }
+}
+
+/// Rewrite an author ID in a history database
+///
+/// When both IDs reviewed a patch, the more recent decision is kept.
+fn rewrite_author_rows(conn: &Connection, from_id: &str, into_id: &str) -> Result<AuthorMergeResult, String> {
+    init_comments_table(conn)?;
+    let run = |sql: &str| conn.execute(sql, params![from_id, into_id]).map_err(|e| e.to_string());
+
+    let patches = run("UPDATE patches SET author = ?2 WHERE author = ?1")?;
+    run(
+        "DELETE FROM patch_reviews AS old WHERE reviewer_id = ?1 AND EXISTS (
+             SELECT 1 FROM patch_reviews AS new
+             WHERE new.reviewer_id = ?2 AND new.patch_uuid = old.patch_uuid AND new.reviewed_at >= old.reviewed_at)",
+    )?;
+    let reviews = run("UPDATE OR REPLACE patch_reviews SET reviewer_id = ?2 WHERE reviewer_id = ?1")?;
+    let review_comments = run("UPDATE patch_review_comments SET author_id = ?2 WHERE author_id = ?1")?;
+    run("UPDATE orphaned_patches SET reviewer_id = ?2 WHERE reviewer_id = ?1")?;
+    let comments = run("UPDATE comments SET author = ?2 WHERE author = ?1")?;
+    run("UPDATE comment_events SET actor = ?2 WHERE actor = ?1")?;
+
+    Ok(AuthorMergeResult {
+        patches,
+        reviews,
+        review_comments,
+        comments,
+    })
+}
+
+/// Merge one author ID into another in a history database and remember
+/// the alias
+pub(crate) fn merge_author_ids(conn: &Connection, from_id: &str, into_id: &str) -> Result<AuthorMergeResult, String> {
+    let result = rewrite_author_rows(conn, from_id, into_id)?;
+    // Earlier aliases of the merged ID now lead to the new one
+    conn.execute("UPDATE author_aliases SET author_id = ?2 WHERE author_id = ?1", params![from_id, into_id])
+        .map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO author_aliases (alias_id, author_id, merged_at) VALUES (?1, ?2, ?3)",
+        params![from_id, into_id, chrono::Utc::now().timestamp_millis()],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+/// Rewrite the aliased author IDs in a history database, e.g. after
+/// importing patches
+pub(crate) fn apply_author_aliases(conn: &Connection) -> Result<(), String> {
+    let mut stmt = conn
+        .prepare("SELECT alias_id, author_id FROM author_aliases")
+        .map_err(|e| e.to_string())?;
+    let aliases = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    for (alias_id, author_id) in aliases {
+        rewrite_author_rows(conn, &alias_id, &author_id)?;
+    }
+    Ok(())
+}
+
+/// Set the display name stored with an author's patches, reviews and
+/// review comments
+fn set_author_name(conn: &Connection, author_id: &str, name: &str) -> Result<(), String> {
+    let mut stmt = conn
+        .prepare("SELECT id, data FROM patches WHERE author = ?1")
+        .map_err(|e| e.to_string())?;
+    let patches = stmt
+        .query_map(params![author_id], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    for (id, data) in patches {
+        let Ok(mut data) = serde_json::from_str::<serde_json::Value>(&data) else {
+            continue;
+        };
+        if let Some(stored) = data.get_mut("authorName").filter(|n| n.as_str() != Some(name)) {
+            *stored = serde_json::Value::String(name.to_string());
+            conn.execute("UPDATE patches SET data = ?1 WHERE id = ?2", params![data.to_string(), id])
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    conn.execute(
+        "UPDATE patch_reviews SET reviewer_name = ?2 WHERE reviewer_id = ?1",
+        params![author_id, name],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "UPDATE patch_review_comments SET author_name = ?2 WHERE author_id = ?1",
+        params![author_id, name],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Merge the `from_id` entry of a document's author list into `into_id`
+///
+/// The merged entry keeps the name of `into_id` and fills its missing
+/// details from `from_id`; if only `from_id` is listed, it is renamed.
+fn merge_author_refs(authors: &mut Vec<AuthorRef>, from_id: &str, into_id: &str) {
+    let Some(from_index) = authors.iter().position(|a| a.id == from_id) else {
+        return;
+    };
+    let from = authors.remove(from_index);
+    match authors.iter_mut().find(|a| a.id == into_id) {
+        Some(into) => {
+            into.email = into.email.take().or(from.email);
+            into.role = into.role.take().or(from.role);
+            into.joined_at = match (into.joined_at.take(), from.joined_at) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+        }
+        None => authors.insert(
+            from_index,
+            AuthorRef {
+                id: into_id.to_string(),
+                ..from
+            },
+        ),
+    }
+}
+
+/// Merge an author ID of a document into another
+///
+/// Rewrites the history of the document and its sections, remembers the
+/// alias for later imports and merges the two entries of the author list.
+#[tauri::command]
+pub fn merge_authors(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    from_id: String,
+    into_id: String,
+) -> Result<AuthorMergeResult, KorppiError> {
+    if from_id.is_empty() || into_id.is_empty() {
+        return Err(KorppiError::InvalidInput("Author ID is empty".to_string()));
+    }
+    if from_id == into_id {
+        return Err(KorppiError::InvalidInput("Cannot merge an author into itself".to_string()));
+    }
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager
+        .documents
+        .get_mut(&doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.clone()))?;
+    if doc.handle.read_only {
+        return Err("Document is open read-only".into());
+    }
+
+    merge_author_refs(&mut doc.meta.authors, &from_id, &into_id);
+    let name = doc.meta.authors.iter().find(|a| a.id == into_id).map(|a| a.name.clone());
+
+    let mut result = AuthorMergeResult::default();
+    let histories = std::iter::once(&doc.history_path).chain(doc.sections.values().map(|s| &s.history_path));
+    for history_path in histories {
+        let conn = Connection::open(history_path)?;
+        ensure_schema(&conn)?;
+        result.add(merge_author_ids(&conn, &from_id, &into_id)?);
+        if let Some(name) = &name {
+            set_author_name(&conn, &into_id, name)?;
+        }
+    }
+    doc.handle.is_modified = true;
+    Ok(result)
+}
+
+/// Change the display name of an author of a document
+#[tauri::command]
+pub fn rename_author(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    author_id: String,
+    name: String,
+) -> Result<(), KorppiError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(KorppiError::InvalidInput("Author name is empty".to_string()));
+    }
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager
+        .documents
+        .get_mut(&doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.clone()))?;
+    if doc.handle.read_only {
+        return Err("Document is open read-only".into());
+    }
+
+    match doc.meta.authors.iter_mut().find(|a| a.id == author_id) {
+        Some(author) => author.name = name.to_string(),
+        None => doc.meta.authors.push(AuthorRef {
+            id: author_id.clone(),
+            name: name.to_string(),
+            email: None,
+            joined_at: None,
+            role: None,
+        }),
+    }
+
+    let histories = std::iter::once(&doc.history_path).chain(doc.sections.values().map(|s| &s.history_path));
+    for history_path in histories {
+        let conn = Connection::open(history_path)?;
+        ensure_schema(&conn)?;
+        set_author_name(&conn, &author_id, name)?;
+    }
+    doc.handle.is_modified = true;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn author(id: &str, name: &str, email: Option<&str>) -> AuthorRef {
+        AuthorRef {
+            id: id.to_string(),
+            name: name.to_string(),
+            email: email.map(str::to_string),
+            joined_at: None,
+            role: None,
+        }
+    }
+
+    #[test]
+    fn test_merge_author_ids() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+        init_comments_table(&conn).unwrap();
+        conn.execute_batch(
+            r#"
+            INSERT INTO patches (timestamp, author, kind, data, uuid) VALUES
+                (1, 'laptop-ann', 'Save', '{"authorName":"laptop-ann"}', 'p1'),
+                (2, 'ann', 'Save', '{}', 'p2');
+            INSERT INTO patch_reviews (patch_uuid, reviewer_id, decision, reviewed_at) VALUES
+                ('p2', 'laptop-ann', 'rejected', 5),
+                ('p2', 'ann', 'accepted', 3),
+                ('p1', 'laptop-ann', 'accepted', 4);
+            INSERT INTO comments (timestamp, author, start_anchor, end_anchor, selected_text, content)
+                VALUES (1, 'laptop-ann', 'a', 'b', 'x', 'Check this');
+            INSERT INTO author_aliases (alias_id, author_id, merged_at) VALUES ('old-ann', 'laptop-ann', 0);
+            "#,
+        )
+        .unwrap();
+
+        let result = merge_author_ids(&conn, "laptop-ann", "ann").unwrap();
+        set_author_name(&conn, "ann", "Ann").unwrap();
+        assert_eq!((result.patches, result.reviews, result.comments), (1, 2, 1));
+
+        // The later rejection wins over the earlier acceptance
+        let decision: String = conn
+            .query_row("SELECT decision FROM patch_reviews WHERE patch_uuid = 'p2' AND reviewer_id = 'ann'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(decision, "rejected");
+        let data: String = conn.query_row("SELECT data FROM patches WHERE uuid = 'p1'", [], |r| r.get(0)).unwrap();
+        assert!(data.contains("\"Ann\""));
+
+        // Patches imported later under either old ID are rewritten
+        conn.execute(
+            "INSERT INTO patches (timestamp, author, kind, data, uuid) VALUES (3, 'old-ann', 'Save', '{}', 'p3'), (4, 'laptop-ann', 'Save', '{}', 'p4')",
+            [],
+        )
+        .unwrap();
+        apply_author_aliases(&conn).unwrap();
+        let others: i64 = conn.query_row("SELECT COUNT(*) FROM patches WHERE author != 'ann'", [], |r| r.get(0)).unwrap();
+        assert_eq!(others, 0);
+    }
+
+    #[test]
+    fn test_merge_author_refs() {
+        let mut authors = vec![author("laptop-ann", "laptop-ann", Some("ann@example.org")), author("ann", "Ann", None)];
+        merge_author_refs(&mut authors, "laptop-ann", "ann");
+        assert_eq!(authors.len(), 1);
+        assert_eq!(authors[0].name, "Ann");
+        assert_eq!(authors[0].email.as_deref(), Some("ann@example.org"));
+
+        let mut authors = vec![author("bob", "Bob", None), author("laptop-ann", "Ann", None)];
+        merge_author_refs(&mut authors, "laptop-ann", "ann");
+        let ids: Vec<&str> = authors.iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, vec!["bob", "ann"]);
+        assert_eq!(authors[1].name, "Ann");
+    }
+}
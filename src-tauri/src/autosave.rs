@@ -0,0 +1,306 @@
+// src-tauri/src/autosave.rs
+//! Automatic snapshots taken independently of manual saves.
+//!
+//! Edits reported by the editor are counted per document. Once a document has
+//! seen `change_threshold` changes, or has been edited for `interval_minutes`
+//! since the last snapshot, an "AutoSave" patch is recorded with a snapshot of
+//! the text decoded from the Yjs state. Redundant autosnapshots are pruned when
+//! the history is compacted.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::db_utils::ensure_schema;
+use crate::document_manager::{current_document_text, DocumentManager, DocumentState};
+use crate::kmd::AutoSaveSettings;
+use crate::patch_log::PatchInput;
+use crate::patch_store::PatchStore;
+use crate::profile::load_saved_profile;
+
+/// Patch kind of automatic snapshots
+pub const AUTOSAVE_KIND: &str = "AutoSave";
+
+/// Edits since the last snapshot of a document
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Activity {
+    /// Time of the first change since the last snapshot (ms)
+    pub started_at: i64,
+    pub changes: u32,
+}
+
+/// Tracks activity of open documents
+#[derive(Default)]
+pub struct AutoSaveTracker {
+    activity: HashMap<String, Activity>,
+}
+
+impl AutoSaveTracker {
+    /// Count changes to a document and return its activity so far
+    pub fn note_changes(&mut self, doc_id: &str, changes: u32, now: i64) -> Activity {
+        let activity = self.activity.entry(doc_id.to_string()).or_insert(Activity {
+            started_at: now,
+            changes: 0,
+        });
+        activity.changes = activity.changes.saturating_add(changes);
+        *activity
+    }
+
+    /// Forget the activity of a document after a snapshot
+    pub fn reset(&mut self, doc_id: &str) {
+        self.activity.remove(doc_id);
+    }
+}
+
+/// Whether the policy calls for a snapshot
+pub fn is_due(settings: &AutoSaveSettings, activity: &Activity, now: i64) -> bool {
+    if !settings.enabled || activity.changes == 0 {
+        return false;
+    }
+    let by_changes = settings.change_threshold > 0 && activity.changes >= settings.change_threshold;
+    let by_time = settings.interval_minutes > 0
+        && now - activity.started_at >= i64::from(settings.interval_minutes) * 60_000;
+    by_changes || by_time
+}
+
+/// Record an AutoSave patch and snapshot; returns None when the text matches the latest snapshot
+pub fn record_autosnapshot(
+    conn: &Connection,
+    author: &str,
+    timestamp: i64,
+    snapshot: &str,
+    changes: u32,
+) -> Result<Option<i64>, String> {
+    ensure_schema(conn)?;
+
+    let latest: Option<Vec<u8>> = conn
+        .query_row("SELECT state FROM snapshot_states ORDER BY id DESC LIMIT 1", [], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if latest.as_deref() == Some(snapshot.as_bytes()) {
+        return Ok(None);
+    }
+
+    let profile = load_saved_profile().ok().flatten();
+    let data = serde_json::json!({
+        "snapshot": snapshot,
+        "changes": changes,
+        "authorName": profile.as_ref().map(|p| p.name.clone()).unwrap_or_else(|| "Local User".to_string()),
+        "authorColor": profile.as_ref().map(|p| p.color.clone()).unwrap_or_else(|| "#3498db".to_string()),
+    });
+
+    let patch = PatchInput {
+        timestamp,
+        author: author.to_string(),
+        kind: AUTOSAVE_KIND.to_string(),
+        data,
+        uuid: None,
+        parent_uuid: None,
+    };
+    let stored = PatchStore::on(conn).insert_patch(&patch, Some(snapshot.as_bytes()))?;
+
+    Ok(stored.map(|p| p.id))
+}
+
+/// Count changes to a document and take an autosnapshot when one is due
+pub(crate) fn note_document_activity(
+    tracker: &mut AutoSaveTracker,
+    doc: &DocumentState,
+    changes: u32,
+) -> Result<Option<i64>, String> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let activity = tracker.note_changes(&doc.handle.id, changes, now);
+    if !is_due(&doc.meta.settings.autosave, &activity, now) {
+        return Ok(None);
+    }
+
+    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    ensure_schema(&conn)?;
+
+    // A manual save since the activity started already covers these changes
+    let last_snapshot: Option<i64> = conn
+        .query_row("SELECT MAX(timestamp) FROM snapshots", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    tracker.reset(&doc.handle.id);
+    if last_snapshot.is_some_and(|t| t >= activity.started_at) {
+        return Ok(None);
+    }
+
+    let author = load_saved_profile()?
+        .map(|p| p.id)
+        .unwrap_or_else(|| "local".to_string());
+    let text = current_document_text(doc, false)?;
+    record_autosnapshot(&conn, &author, now, &text, activity.changes)
+}
+
+/// Remove autosnapshots that duplicate a neighbouring snapshot
+///
+/// An autosnapshot is redundant when its text equals the previous snapshot
+/// kept, or when the next snapshot is a manual save of the same text.
+pub fn prune_autosnapshots(conn: &Connection) -> Result<usize, String> {
+    ensure_schema(conn)?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT s.patch_id, p.kind, s.state FROM snapshot_states s
+             JOIN patches p ON p.id = s.patch_id
+             ORDER BY s.timestamp ASC, s.id ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let snapshots: Vec<(i64, String, Vec<u8>)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut kept: Vec<&(i64, String, Vec<u8>)> = Vec::new();
+    let mut redundant = Vec::new();
+    for snapshot in &snapshots {
+        let duplicate = kept.last().is_some_and(|prev| prev.2 == snapshot.2);
+        if snapshot.1 == AUTOSAVE_KIND && duplicate {
+            redundant.push(snapshot.0);
+        } else {
+            kept.push(snapshot);
+        }
+    }
+    for pair in kept.windows(2) {
+        let (current, next) = (pair[0], pair[1]);
+        if current.1 == AUTOSAVE_KIND && next.1 != AUTOSAVE_KIND && current.2 == next.2 {
+            redundant.push(current.0);
+        }
+    }
+
+    let store = PatchStore::on(conn);
+    for patch_id in &redundant {
+        store.delete_snapshots(*patch_id)?;
+        conn.execute("DELETE FROM patches WHERE id = ?1", params![patch_id])
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(redundant.len())
+}
+
+/// Report edits to a document; returns the patch id of an autosnapshot if one was taken
+#[tauri::command]
+pub fn record_document_activity(
+    manager: State<'_, Mutex<DocumentManager>>,
+    autosave: State<'_, Mutex<AutoSaveTracker>>,
+    id: String,
+    changes: Option<u32>,
+) -> Result<Option<i64>, String> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get(&id)
+        .ok_or_else(|| format!("Document not found: {}", id))?;
+    let mut tracker = autosave.lock().map_err(|e| e.to_string())?;
+
+    note_document_activity(&mut tracker, doc, changes.unwrap_or(1))
+}
+
+/// Compact a document's history: prune redundant autosnapshots and reclaim space
+#[tauri::command]
+pub fn compact_document_history(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+) -> Result<usize, String> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get(&id)
+        .ok_or_else(|| format!("Document not found: {}", id))?;
+
+    if !doc.history_path.exists() {
+        return Ok(0);
+    }
+
+    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    let pruned = prune_autosnapshots(&conn)?;
+    conn.execute_batch("VACUUM").map_err(|e| e.to_string())?;
+
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn insert_snapshot(conn: &Connection, timestamp: i64, kind: &str, text: &str) -> i64 {
+        conn.execute(
+            "INSERT INTO patches (timestamp, author, kind, data, uuid) VALUES (?1, 'a', ?2, '{}', ?3)",
+            params![timestamp, kind, Uuid::new_v4().to_string()],
+        )
+        .unwrap();
+        let patch_id = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO snapshots (timestamp, patch_id, state) VALUES (?1, ?2, ?3)",
+            params![timestamp, patch_id, text.as_bytes()],
+        )
+        .unwrap();
+        patch_id
+    }
+
+    #[test]
+    fn test_policy_due_by_changes_or_time() {
+        let settings = AutoSaveSettings {
+            enabled: true,
+            interval_minutes: 5,
+            change_threshold: 100,
+        };
+        let mut tracker = AutoSaveTracker::default();
+
+        let activity = tracker.note_changes("doc", 60, 0);
+        assert!(!is_due(&settings, &activity, 60_000));
+        let activity = tracker.note_changes("doc", 40, 60_000);
+        assert!(is_due(&settings, &activity, 60_000));
+
+        tracker.reset("doc");
+        let activity = tracker.note_changes("doc", 1, 1_000_000);
+        assert_eq!(activity.started_at, 1_000_000);
+        assert!(!is_due(&settings, &activity, 1_000_000 + 4 * 60_000));
+        assert!(is_due(&settings, &activity, 1_000_000 + 5 * 60_000));
+
+        let disabled = AutoSaveSettings { enabled: false, ..settings };
+        assert!(!is_due(&disabled, &activity, i64::MAX));
+    }
+
+    #[test]
+    fn test_record_autosnapshot_skips_unchanged_text() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+
+        let first = record_autosnapshot(&conn, "alice", 1, "Hello", 3).unwrap();
+        assert!(first.is_some());
+        assert_eq!(record_autosnapshot(&conn, "alice", 2, "Hello", 1).unwrap(), None);
+
+        let kind: String = conn
+            .query_row("SELECT kind FROM patches WHERE id = ?1", params![first.unwrap()], |row| row.get(0))
+            .unwrap();
+        assert_eq!(kind, AUTOSAVE_KIND);
+    }
+
+    #[test]
+    fn test_prune_redundant_autosnapshots() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+
+        insert_snapshot(&conn, 1, "Save", "A");
+        let dup = insert_snapshot(&conn, 2, AUTOSAVE_KIND, "A");
+        let kept = insert_snapshot(&conn, 3, AUTOSAVE_KIND, "B");
+        let superseded = insert_snapshot(&conn, 4, AUTOSAVE_KIND, "C");
+        insert_snapshot(&conn, 5, "Save", "C");
+
+        assert_eq!(prune_autosnapshots(&conn).unwrap(), 2);
+
+        let remaining: Vec<i64> = conn
+            .prepare("SELECT patch_id FROM snapshots ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(remaining.contains(&kept));
+        assert!(!remaining.contains(&dup));
+        assert!(!remaining.contains(&superseded));
+        let patches: i64 = conn.query_row("SELECT COUNT(*) FROM patches", [], |row| row.get(0)).unwrap();
+        assert_eq!(patches, 3);
+    }
+}
@@ -0,0 +1,341 @@
+// src-tauri/src/backups.rs
+//! Backups of saved documents to a directory of the user's choosing.
+//!
+//! The directory is set in the app settings, or per document in its own
+//! settings, and is meant to sit on an external drive or a NAS. After a save
+//! the .kmd is copied there once the newest copy is older than the backup
+//! interval. Each document has a folder of copies named after the time they
+//! were taken; after every backup the folder is rotated so that only the
+//! newest copy of each of the last `keep_daily` days and of each of the last
+//! `keep_weekly` weeks remains.
+
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::document_manager::DocumentManager;
+use crate::error::KorppiError;
+use crate::kmd::BackupSettings;
+use crate::settings::load_app_settings;
+
+/// File name format of a backup, in UTC
+const BACKUP_NAME_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// One backup of a document
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BackupGeneration {
+    pub path: PathBuf,
+    /// Time the backup was taken (ms)
+    pub created_at: i64,
+    pub size: u64,
+}
+
+pub fn validate_backup_settings(settings: &BackupSettings) -> Result<(), String> {
+    if settings.directory.as_os_str().is_empty() {
+        return Err("Backup directory must not be empty".to_string());
+    }
+    if settings.interval_hours == 0 {
+        return Err("Backup interval must be at least one hour".to_string());
+    }
+    Ok(())
+}
+
+/// Backup settings of a document: its own, or else the app's
+pub fn effective_backup_settings(document: Option<&BackupSettings>) -> Option<BackupSettings> {
+    document
+        .cloned()
+        .or_else(|| load_app_settings().ok().and_then(|settings| settings.backup))
+}
+
+/// Folder holding the backups of a document
+///
+/// Named after the file, with a hash of its full path so that two documents
+/// with the same name do not share a folder.
+pub fn backup_folder(settings: &BackupSettings, document: &Path) -> PathBuf {
+    let full_path = fs::canonicalize(document).unwrap_or_else(|_| document.to_path_buf());
+    let hash = format!("{:x}", Sha256::digest(full_path.to_string_lossy().as_bytes()));
+    let stem = document
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "document".to_string());
+    settings.directory.join(format!("{}-{}", stem, &hash[..8]))
+}
+
+/// Backups in a folder, newest first
+pub fn list_generations(folder: &Path) -> Result<Vec<BackupGeneration>, String> {
+    if !folder.exists() {
+        return Ok(Vec::new());
+    }
+    let mut generations = Vec::new();
+    for entry in fs::read_dir(folder).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("kmd") {
+            continue;
+        }
+        let Some(taken) = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| NaiveDateTime::parse_from_str(s, BACKUP_NAME_FORMAT).ok())
+        else {
+            continue;
+        };
+        let size = fs::metadata(&path).map_err(|e| e.to_string())?.len();
+        generations.push(BackupGeneration {
+            path,
+            created_at: taken.and_utc().timestamp_millis(),
+            size,
+        });
+    }
+    generations.sort_by_key(|g| std::cmp::Reverse(g.created_at));
+    Ok(generations)
+}
+
+/// Backups kept by the rotation: the newest one, and the newest of each of
+/// the last `keep_daily` days and `keep_weekly` ISO weeks that have backups
+///
+/// `generations` must be newest first.
+pub fn generations_to_keep(generations: &[BackupGeneration], keep_daily: u32, keep_weekly: u32) -> HashSet<PathBuf> {
+    let mut keep = HashSet::new();
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    for (i, generation) in generations.iter().enumerate() {
+        let Some(taken) = DateTime::from_timestamp_millis(generation.created_at) else {
+            continue;
+        };
+        let day = taken.date_naive();
+        let week = day.iso_week();
+        let newest_of_day = days.len() < keep_daily as usize && days.insert(day);
+        let newest_of_week = weeks.len() < keep_weekly as usize && weeks.insert((week.year(), week.week()));
+        if i == 0 || newest_of_day || newest_of_week {
+            keep.insert(generation.path.clone());
+        }
+    }
+    keep
+}
+
+/// Delete the backups the rotation does not keep; returns how many
+fn rotate(folder: &Path, settings: &BackupSettings) -> Result<usize, String> {
+    let generations = list_generations(folder)?;
+    let keep = generations_to_keep(&generations, settings.keep_daily, settings.keep_weekly);
+    let mut removed = 0;
+    for generation in generations.iter().filter(|g| !keep.contains(&g.path)) {
+        fs::remove_file(&generation.path).map_err(|e| e.to_string())?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Copy a saved document to its backup folder and rotate the folder
+pub fn back_up(document: &Path, settings: &BackupSettings, now: DateTime<Utc>) -> Result<BackupGeneration, String> {
+    let folder = backup_folder(settings, document);
+    fs::create_dir_all(&folder).map_err(|e| format!("Cannot create backup folder {}: {}", folder.display(), e))?;
+
+    // Copy under a temporary name so an interrupted copy is never listed
+    let path = folder.join(format!("{}.kmd", now.format(BACKUP_NAME_FORMAT)));
+    let part = path.with_extension("kmd.part");
+    let copied = fs::copy(document, &part).and_then(|size| fs::rename(&part, &path).map(|_| size));
+    let size = copied.map_err(|e| {
+        let _ = fs::remove_file(&part);
+        format!("Cannot back up {}: {}", document.display(), e)
+    })?;
+
+    rotate(&folder, settings)?;
+    Ok(BackupGeneration {
+        path,
+        created_at: now.timestamp_millis(),
+        size,
+    })
+}
+
+/// Back up a document when its newest backup is older than the interval
+pub fn back_up_if_due(
+    document: &Path,
+    settings: &BackupSettings,
+    now: DateTime<Utc>,
+) -> Result<Option<BackupGeneration>, String> {
+    let interval_ms = i64::from(settings.interval_hours) * 3_600_000;
+    let newest = list_generations(&backup_folder(settings, document))?.into_iter().next();
+    if newest.is_some_and(|g| now.timestamp_millis() - g.created_at < interval_ms) {
+        return Ok(None);
+    }
+    back_up(document, settings, now).map(Some)
+}
+
+/// Backup settings of the document at `path`: its own if it is open,
+/// or else the app's
+fn settings_for_path(manager: &Mutex<DocumentManager>, path: &Path) -> Result<Option<BackupSettings>, KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let own = manager
+        .documents
+        .values()
+        .find(|doc| doc.handle.path.as_deref() == Some(path))
+        .and_then(|doc| doc.meta.settings.backup.clone());
+    Ok(effective_backup_settings(own.as_ref()))
+}
+
+/// Set the backups of a document; `None` falls back to the app settings
+#[tauri::command]
+pub fn set_document_backup(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    backup: Option<BackupSettings>,
+) -> Result<(), KorppiError> {
+    if let Some(settings) = &backup {
+        validate_backup_settings(settings).map_err(KorppiError::InvalidInput)?;
+    }
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager
+        .documents
+        .get_mut(&id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
+    if doc.meta.settings.backup != backup {
+        doc.meta.settings.backup = backup;
+        doc.handle.is_modified = true;
+    }
+    Ok(())
+}
+
+/// Back up a saved document now, whatever the interval
+#[tauri::command]
+pub fn run_backup_now(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+) -> Result<BackupGeneration, KorppiError> {
+    let (path, own) = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
+        (doc.handle.path.clone(), doc.meta.settings.backup.clone())
+    };
+    let path = path.ok_or_else(|| KorppiError::InvalidInput("Save the document before backing it up".to_string()))?;
+    let settings = effective_backup_settings(own.as_ref())
+        .ok_or_else(|| KorppiError::InvalidInput("No backup directory is set".to_string()))?;
+    Ok(back_up(&path, &settings, Utc::now())?)
+}
+
+/// Backups of the document at `path`, newest first
+#[tauri::command]
+pub fn list_backups(
+    manager: State<'_, Mutex<DocumentManager>>,
+    path: String,
+) -> Result<Vec<BackupGeneration>, KorppiError> {
+    let path = PathBuf::from(path);
+    match settings_for_path(&manager, &path)? {
+        Some(settings) => Ok(list_generations(&backup_folder(&settings, &path))?),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Copy a backup of the document at `path` back out of the backup folder
+///
+/// The copy goes to `target`, or next to the document under a name with the
+/// time of the backup; the document itself is never overwritten. Returns
+/// the path of the restored file, ready to be opened.
+#[tauri::command]
+pub fn restore_from_backup(
+    manager: State<'_, Mutex<DocumentManager>>,
+    path: String,
+    backup_path: String,
+    target: Option<String>,
+) -> Result<String, KorppiError> {
+    let path = PathBuf::from(path);
+    let settings = settings_for_path(&manager, &path)?
+        .ok_or_else(|| KorppiError::InvalidInput("No backup directory is set".to_string()))?;
+    let generation = list_generations(&backup_folder(&settings, &path))?
+        .into_iter()
+        .find(|g| g.path == Path::new(&backup_path))
+        .ok_or_else(|| KorppiError::InvalidInput(format!("Not a backup of this document: {}", backup_path)))?;
+
+    let target = match target {
+        Some(target) => PathBuf::from(target),
+        None => {
+            let taken = DateTime::from_timestamp_millis(generation.created_at).unwrap_or_default();
+            let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            path.with_file_name(format!("{} (backup {}).kmd", stem, taken.format("%Y-%m-%d %H.%M")))
+        }
+    };
+    let is_open = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        manager.documents.values().any(|doc| doc.handle.path.as_deref() == Some(target.as_path()))
+    };
+    if target == path || is_open {
+        return Err(KorppiError::InvalidInput("Cannot restore over an open document".to_string()));
+    }
+    fs::copy(&generation.path, &target).map_err(|e| format!("Cannot restore backup: {}", e))?;
+    Ok(target.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(y: i32, m: u32, d: u32, h: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_rotation_keeps_newest_per_day_and_week() {
+        let taken = [
+            at(2026, 3, 18, 17), // Wednesday, newest
+            at(2026, 3, 18, 9),
+            at(2026, 3, 17, 12),
+            at(2026, 3, 16, 12), // Monday
+            at(2026, 3, 13, 12), // previous week
+            at(2026, 3, 11, 12),
+            at(2026, 3, 4, 12),  // two weeks back
+        ];
+        let generations: Vec<BackupGeneration> = taken
+            .iter()
+            .map(|t| BackupGeneration {
+                path: PathBuf::from(format!("{}.kmd", t.format(BACKUP_NAME_FORMAT))),
+                created_at: t.timestamp_millis(),
+                size: 1,
+            })
+            .collect();
+
+        let keep = generations_to_keep(&generations, 3, 2);
+        let kept: Vec<usize> = (0..taken.len()).filter(|&i| keep.contains(&generations[i].path)).collect();
+        assert_eq!(kept, vec![0, 2, 3, 4]);
+
+        let keep = generations_to_keep(&generations, 0, 0);
+        assert_eq!(keep.len(), 1);
+        assert!(keep.contains(&generations[0].path));
+    }
+
+    #[test]
+    fn test_back_up_if_due() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let document = dir.path().join("thesis.kmd");
+        fs::write(&document, b"first").unwrap();
+        let settings = BackupSettings {
+            directory: dir.path().join("backups"),
+            interval_hours: 24,
+            keep_daily: 2,
+            keep_weekly: 0,
+        };
+
+        let first = back_up_if_due(&document, &settings, at(2026, 3, 16, 8)).unwrap().unwrap();
+        assert_eq!(first.size, 5);
+        assert!(back_up_if_due(&document, &settings, at(2026, 3, 16, 20)).unwrap().is_none());
+
+        fs::write(&document, b"second").unwrap();
+        back_up_if_due(&document, &settings, at(2026, 3, 17, 9)).unwrap().unwrap();
+        back_up_if_due(&document, &settings, at(2026, 3, 18, 10)).unwrap().unwrap();
+
+        let folder = backup_folder(&settings, &document);
+        assert!(folder.file_name().unwrap().to_string_lossy().starts_with("thesis-"));
+        let generations = list_generations(&folder).unwrap();
+        let times: Vec<i64> = generations.iter().map(|g| g.created_at).collect();
+        assert_eq!(times, vec![at(2026, 3, 18, 10).timestamp_millis(), at(2026, 3, 17, 9).timestamp_millis()]);
+        assert_eq!(generations[0].size, 6);
+        assert_eq!(fs::read(&generations[0].path).unwrap(), b"second");
+    }
+}
@@ -0,0 +1,276 @@
+// src-tauri/src/bundle_crypto.rs
+//! Encrypting patch bundles for one recipient.
+//!
+//! Every profile has an X25519 key pair. The secret key stays in
+//! `identity.key` in the config directory; the public key is stored in the
+//! profile and travels in the author profiles of KMD files, so collaborators
+//! learn each other's keys from the documents they share.
+//!
+//! A bundle is encrypted with a fresh ephemeral key: the X25519 shared
+//! secret with the recipient's public key goes through HKDF-SHA256 to give
+//! an AES-256-GCM key. Only the recipient's secret key can recompute it.
+//!
+//! Bundles are also signed, so a receiver can tell who sent them. The
+//! Ed25519 signing key is derived from the same secret key through HKDF;
+//! its public half travels next to the encryption key in the profile.
+
+use aes_gcm::aead::{Aead, OsRng, Payload};
+use aes_gcm::{AeadCore, Aes256Gcm, Key, KeyInit, Nonce};
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fs;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+use crate::profile::get_config_dir;
+
+/// File holding the secret key, next to the profile
+const IDENTITY_FILE: &str = "identity.key";
+
+/// HKDF context, so keys derived here are never reused elsewhere
+const KEY_INFO: &[u8] = b"korppi patch bundle v1";
+
+/// HKDF context of the signing key
+const SIGNING_INFO: &[u8] = b"korppi bundle signing v1";
+
+/// Who can decrypt a bundle, and what they need to do so
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BundleEncryption {
+    /// Author ID of the recipient
+    pub recipient_id: String,
+    /// Recipient's public key, base64
+    pub recipient_key: String,
+    /// Sender's ephemeral public key, base64
+    pub ephemeral_key: String,
+    /// AES-GCM nonce, base64
+    pub nonce: String,
+}
+
+/// Who signed a bundle
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BundleSignature {
+    /// Author ID of the sender
+    pub signer_id: String,
+    /// Sender's signing key, base64
+    pub signer_key: String,
+    /// Ed25519 signature, base64
+    pub signature: String,
+}
+
+/// A collaborator to encrypt for
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recipient {
+    pub id: String,
+    /// Public key, base64
+    pub public_key: String,
+}
+
+fn encode(bytes: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+fn decode_key(key: &str) -> Result<[u8; 32], String> {
+    base64::engine::general_purpose::STANDARD
+        .decode(key.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| "Invalid encryption key".to_string())
+}
+
+/// The secret key of this profile, created on first use
+pub fn local_identity() -> Result<StaticSecret, String> {
+    if let Some(content) = read_identity()? {
+        return Ok(StaticSecret::from(decode_key(&content)?));
+    }
+    let secret = StaticSecret::random_from_rng(OsRng);
+    write_identity(&secret)?;
+    Ok(secret)
+}
+
+/// The secret key file of this profile (base64), if it was created
+pub(crate) fn read_identity() -> Result<Option<String>, String> {
+    let path = get_config_dir()?.join(IDENTITY_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    fs::read_to_string(&path)
+        .map(Some)
+        .map_err(|e| format!("Failed to read encryption key: {}", e))
+}
+
+fn write_identity(secret: &StaticSecret) -> Result<(), String> {
+    let config_dir = get_config_dir()?;
+    let path = config_dir.join(IDENTITY_FILE);
+    fs::create_dir_all(&config_dir).map_err(|e| format!("Failed to create config directory: {}", e))?;
+    fs::write(&path, encode(secret.as_bytes())).map_err(|e| format!("Failed to write encryption key: {}", e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).ok();
+    }
+    Ok(())
+}
+
+/// Install the secret key of this profile from another computer, unless
+/// this one already has a different key
+///
+/// Returns whether the key is now the local one.
+pub(crate) fn restore_identity(key: &str) -> Result<bool, String> {
+    let secret = StaticSecret::from(decode_key(key)?);
+    match read_identity()? {
+        Some(local) => Ok(decode_key(&local)? == secret.to_bytes()),
+        None => write_identity(&secret).map(|_| true),
+    }
+}
+
+/// Base64 public key matching a secret key
+pub fn public_key_of(secret: &StaticSecret) -> String {
+    encode(PublicKey::from(secret).as_bytes())
+}
+
+/// Ed25519 key derived from a secret key
+fn signing_key(secret: &StaticSecret) -> SigningKey {
+    let mut seed = [0u8; 32];
+    Hkdf::<Sha256>::new(None, secret.as_bytes())
+        .expand(SIGNING_INFO, &mut seed)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    SigningKey::from_bytes(&seed)
+}
+
+/// Base64 public signing key matching a secret key
+pub fn signing_key_of(secret: &StaticSecret) -> String {
+    encode(signing_key(secret).verifying_key().as_bytes())
+}
+
+/// Sign a message; returns the base64 signature
+pub fn sign_with(secret: &StaticSecret, message: &[u8]) -> String {
+    encode(&signing_key(secret).sign(message).to_bytes())
+}
+
+/// Check a base64 signature of a message against a base64 signing key
+pub fn verify_signature(signing_key: &str, message: &[u8], signature: &str) -> Result<(), String> {
+    let key = VerifyingKey::from_bytes(&decode_key(signing_key)?).map_err(|_| "Invalid signing key".to_string())?;
+    let signature = base64::engine::general_purpose::STANDARD
+        .decode(signature.trim())
+        .ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        .ok_or("Invalid signature")?;
+    key.verify(message, &signature).map_err(|_| "Signature does not match".to_string())
+}
+
+/// AES-256 key shared by the ephemeral and recipient keys
+fn bundle_key(shared: &[u8; 32], ephemeral: &PublicKey, recipient: &PublicKey) -> Key<Aes256Gcm> {
+    let mut salt = Vec::with_capacity(64);
+    salt.extend_from_slice(ephemeral.as_bytes());
+    salt.extend_from_slice(recipient.as_bytes());
+    let mut key = Key::<Aes256Gcm>::default();
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(KEY_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+/// Encrypt data for a recipient
+///
+/// `context` (e.g. the document UUID) is authenticated but not encrypted;
+/// decryption fails unless the same context is given.
+pub fn encrypt_for(recipient: &Recipient, plaintext: &[u8], context: &[u8]) -> Result<(BundleEncryption, Vec<u8>), String> {
+    let recipient_key = PublicKey::from(decode_key(&recipient.public_key)?);
+    let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+    let ephemeral_key = PublicKey::from(&ephemeral);
+    let shared = ephemeral.diffie_hellman(&recipient_key);
+
+    let cipher = Aes256Gcm::new(&bundle_key(shared.as_bytes(), &ephemeral_key, &recipient_key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: plaintext, aad: context })
+        .map_err(|_| "Encryption failed".to_string())?;
+
+    Ok((
+        BundleEncryption {
+            recipient_id: recipient.id.clone(),
+            recipient_key: encode(recipient_key.as_bytes()),
+            ephemeral_key: encode(ephemeral_key.as_bytes()),
+            nonce: encode(&nonce),
+        },
+        ciphertext,
+    ))
+}
+
+/// Decrypt data encrypted for the holder of `secret`
+pub fn decrypt_with(secret: &StaticSecret, header: &BundleEncryption, ciphertext: &[u8], context: &[u8]) -> Result<Vec<u8>, String> {
+    let own_key = PublicKey::from(secret);
+    if encode(own_key.as_bytes()) != header.recipient_key {
+        return Err(format!(
+            "This bundle is encrypted for another collaborator ({})",
+            header.recipient_id
+        ));
+    }
+    let ephemeral_key = PublicKey::from(decode_key(&header.ephemeral_key)?);
+    let nonce = base64::engine::general_purpose::STANDARD
+        .decode(&header.nonce)
+        .ok()
+        .filter(|n| n.len() == 12)
+        .ok_or("Invalid bundle nonce")?;
+    let shared = secret.diffie_hellman(&ephemeral_key);
+
+    let cipher = Aes256Gcm::new(&bundle_key(shared.as_bytes(), &ephemeral_key, &own_key));
+    cipher
+        .decrypt(Nonce::from_slice(&nonce), Payload { msg: ciphertext, aad: context })
+        .map_err(|_| "Failed to decrypt bundle: it is damaged or was altered".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipient(secret: &StaticSecret) -> Recipient {
+        Recipient {
+            id: "bob".to_string(),
+            public_key: public_key_of(secret),
+        }
+    }
+
+    #[test]
+    fn test_encrypt_roundtrip() {
+        let bob = StaticSecret::random_from_rng(OsRng);
+        let (header, ciphertext) = encrypt_for(&recipient(&bob), b"confidential", b"doc-1").unwrap();
+        assert_eq!(header.recipient_id, "bob");
+        assert_ne!(ciphertext, b"confidential");
+        assert_eq!(decrypt_with(&bob, &header, &ciphertext, b"doc-1").unwrap(), b"confidential");
+
+        // Bound to the context
+        assert!(decrypt_with(&bob, &header, &ciphertext, b"doc-2").is_err());
+        // Tampering is detected
+        let mut altered = ciphertext.clone();
+        altered[0] ^= 1;
+        assert!(decrypt_with(&bob, &header, &altered, b"doc-1").unwrap_err().contains("altered"));
+    }
+
+    #[test]
+    fn test_signatures() {
+        let alice = StaticSecret::random_from_rng(OsRng);
+        let mallory = StaticSecret::random_from_rng(OsRng);
+        let signature = sign_with(&alice, b"bundle");
+        assert!(verify_signature(&signing_key_of(&alice), b"bundle", &signature).is_ok());
+        assert!(verify_signature(&signing_key_of(&alice), b"altered", &signature).is_err());
+        assert!(verify_signature(&signing_key_of(&mallory), b"bundle", &signature).is_err());
+        // Derived, so the same secret always signs with the same key
+        assert_eq!(signing_key_of(&alice), signing_key_of(&StaticSecret::from(alice.to_bytes())));
+        assert_ne!(signing_key_of(&alice), public_key_of(&alice));
+    }
+
+    #[test]
+    fn test_other_recipient_cannot_decrypt() {
+        let bob = StaticSecret::random_from_rng(OsRng);
+        let carol = StaticSecret::random_from_rng(OsRng);
+        let (header, ciphertext) = encrypt_for(&recipient(&bob), b"confidential", b"doc-1").unwrap();
+        assert!(decrypt_with(&carol, &header, &ciphertext, b"doc-1").unwrap_err().contains("bob"));
+
+        // Even when the header is rewritten to name Carol
+        let forged = BundleEncryption { recipient_key: public_key_of(&carol), ..header };
+        assert!(decrypt_with(&carol, &forged, &ciphertext, b"doc-1").is_err());
+    }
+}
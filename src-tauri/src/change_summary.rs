@@ -0,0 +1,426 @@
+// src-tauri/src/change_summary.rs
+//! Summary of the changes between two versions of a document.
+//!
+//! Written in the style of a response to reviewers: the changes are grouped
+//! by the section they fall in, each shown as the paragraph before and
+//! after, followed by the document comments on that text. Review comments
+//! on the patches in between are listed at the end. The summary is
+//! markdown, and is exported to any format the normal export supports.
+
+use chrono::DateTime;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::cli::{export_content, ExportFormat};
+use crate::comments::{init_comments_table, load_all_comments};
+use crate::db_utils::ensure_schema;
+use crate::document_manager::{author_display_name, snapshot_at_patch, DocumentManager};
+use crate::error::KorppiError;
+use crate::hunk_calculator::calculate_hunks_with;
+use crate::kmd::DocumentMeta;
+use crate::outline::{build_outline, OutlineNode};
+use crate::review_comments::load_review_comments;
+
+/// Longest excerpt shown, in characters
+const MAX_EXCERPT_CHARS: usize = 600;
+
+/// Section name of text before the first heading
+const PREAMBLE: &str = "Before the first heading";
+
+/// Byte range in a text
+type Range = (usize, usize);
+
+/// One end of the compared range
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SummaryVersion {
+    pub patch_uuid: String,
+    pub timestamp: i64,
+    pub author_name: String,
+}
+
+/// A changed paragraph, or run of paragraphs
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChangeExcerpt {
+    /// Text before the change; None for added text
+    pub before: Option<String>,
+    /// Text after the change; None for removed text
+    pub after: Option<String>,
+    /// Document comments on the changed text, "Author: comment"
+    pub comments: Vec<String>,
+}
+
+/// The changes in one section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SectionChanges {
+    /// Heading path, e.g. "Methods › Data"
+    pub section: String,
+    pub changes: Vec<ChangeExcerpt>,
+}
+
+/// A review comment on a patch in the compared range
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewNote {
+    pub author_name: String,
+    pub content: String,
+    pub created_at: i64,
+    /// Author of the commented patch
+    pub patch_author_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeSummary {
+    pub title: String,
+    pub from: SummaryVersion,
+    pub to: SummaryVersion,
+    /// Sections in the order of the newer version
+    pub sections: Vec<SectionChanges>,
+    pub review_notes: Vec<ReviewNote>,
+}
+
+/// Byte offset of a UTF-16 offset
+fn byte_offset(text: &str, utf16: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in text.char_indices() {
+        if units >= utf16 {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn utf16_offset(text: &str, byte: usize) -> usize {
+    text[..byte].encode_utf16().count()
+}
+
+/// Byte range of the paragraphs around `[start, end)`
+///
+/// An insertion at the start of a line is a new paragraph, with nothing
+/// around it.
+fn paragraph_around(text: &str, start: usize, end: usize) -> Option<Range> {
+    if start == end && (start == 0 || text[..start].ends_with('\n')) {
+        return None;
+    }
+    let from = text[..start].rfind("\n\n").map(|i| i + 2).unwrap_or(0);
+    let to = text[end..].find("\n\n").map(|i| end + i).unwrap_or(text.len());
+    Some((from, to))
+}
+
+fn overlaps(a: Option<Range>, b: Option<Range>) -> bool {
+    matches!((a, b), (Some(a), Some(b)) if a.0 <= b.1 && b.0 <= a.1)
+}
+
+fn union(a: Option<Range>, b: Option<Range>) -> Option<Range> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some((a.0.min(b.0), a.1.max(b.1))),
+        (a, b) => a.or(b),
+    }
+}
+
+fn excerpt(text: &str, (start, end): Range) -> String {
+    let excerpt = text[start..end].trim();
+    if excerpt.chars().count() <= MAX_EXCERPT_CHARS {
+        return excerpt.to_string();
+    }
+    let cut: String = excerpt.chars().take(MAX_EXCERPT_CHARS).collect();
+    format!("{}…", cut.trim_end())
+}
+
+/// Heading path of the innermost section containing a UTF-16 offset
+fn section_path(outline: &[OutlineNode], offset: usize) -> String {
+    let mut path = Vec::new();
+    let mut nodes = outline;
+    while let Some(node) = nodes.iter().find(|n| n.start <= offset && offset < n.end) {
+        path.push(node.title.as_str());
+        nodes = &node.children;
+    }
+    if path.is_empty() {
+        PREAMBLE.to_string()
+    } else {
+        path.join(" › ")
+    }
+}
+
+/// Group the changes from `base` to `modified` by paragraph and section
+fn section_changes(base: &str, modified: &str, meta: &DocumentMeta, comments: &[(String, String)]) -> Vec<SectionChanges> {
+    let mut hunks = calculate_hunks_with(base, modified, &meta.settings.diff);
+    hunks.sort_by_key(|h| h.base_start);
+
+    // Paragraph ranges (bytes) of each hunk in both texts, merged when they
+    // share a paragraph
+    let mut groups: Vec<(Option<Range>, Option<Range>)> = Vec::new();
+    let mut shift: isize = 0;
+    for hunk in &hunks {
+        let mod_start = (hunk.base_start as isize + shift).max(0) as usize;
+        let mod_end = mod_start + hunk.modified_length;
+        shift += hunk.modified_length as isize - (hunk.base_end - hunk.base_start) as isize;
+
+        let before = paragraph_around(base, byte_offset(base, hunk.base_start), byte_offset(base, hunk.base_end));
+        let after = paragraph_around(modified, byte_offset(modified, mod_start), byte_offset(modified, mod_end));
+        match groups.last_mut() {
+            Some(last) if overlaps(last.0, before) || overlaps(last.1, after) => {
+                *last = (union(last.0, before), union(last.1, after));
+            }
+            _ => groups.push((before, after)),
+        }
+    }
+
+    let base_outline = build_outline(base);
+    let modified_outline = build_outline(modified);
+    let mut sections: Vec<SectionChanges> = Vec::new();
+    for (before, after) in groups {
+        let section = match (after, before) {
+            (Some((start, _)), _) => section_path(&modified_outline, utf16_offset(modified, start)),
+            (None, Some((start, _))) => section_path(&base_outline, utf16_offset(base, start)),
+            (None, None) => continue,
+        };
+
+        let on_text = |text: &str, range: Option<Range>, selected: &str| {
+            range.is_some_and(|(start, end)| text[start..end].contains(selected))
+        };
+        let change = ChangeExcerpt {
+            before: before.map(|range| excerpt(base, range)),
+            after: after.map(|range| excerpt(modified, range)),
+            comments: comments
+                .iter()
+                .filter(|(selected, _)| on_text(base, before, selected) || on_text(modified, after, selected))
+                .map(|(_, note)| note.clone())
+                .collect(),
+        };
+        if change.before == change.after {
+            continue;
+        }
+
+        match sections.iter_mut().find(|s| s.section == section) {
+            Some(existing) => existing.changes.push(change),
+            None => sections.push(SectionChanges { section, changes: vec![change] }),
+        }
+    }
+    sections
+}
+
+fn version(conn: &Connection, meta: &DocumentMeta, patch_uuid: &str) -> Result<(i64, SummaryVersion), String> {
+    let (id, timestamp, author): (i64, i64, String) = conn
+        .query_row(
+            "SELECT id, timestamp, author FROM patches WHERE uuid = ?1",
+            params![patch_uuid],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Patch not found: {}", patch_uuid))?;
+    Ok((
+        id,
+        SummaryVersion {
+            patch_uuid: patch_uuid.to_string(),
+            timestamp,
+            author_name: author_display_name(meta, &author),
+        },
+    ))
+}
+
+/// Summarize the changes from one patch to a later one
+pub fn build_change_summary(
+    conn: &Connection,
+    meta: &DocumentMeta,
+    from_uuid: &str,
+    to_uuid: &str,
+) -> Result<ChangeSummary, String> {
+    let (from_id, from) = version(conn, meta, from_uuid)?;
+    let (to_id, to) = version(conn, meta, to_uuid)?;
+    if from_id >= to_id {
+        return Err("The first version must be older than the second".to_string());
+    }
+    let base = snapshot_at_patch(conn, from_id)?;
+    let modified = snapshot_at_patch(conn, to_id)?;
+
+    init_comments_table(conn)?;
+    let comments: Vec<(String, String)> = load_all_comments(conn)?
+        .into_iter()
+        .filter(|c| c.parent_id.is_none() && !c.selected_text.trim().is_empty())
+        .map(|c| (c.selected_text, format!("{}: {}", c.author, c.content)))
+        .collect();
+
+    let mut stmt = conn
+        .prepare("SELECT uuid, author FROM patches WHERE id > ?1 AND id <= ?2 AND uuid IS NOT NULL ORDER BY id")
+        .map_err(|e| e.to_string())?;
+    let patches = stmt
+        .query_map(params![from_id, to_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let mut review_notes = Vec::new();
+    for (uuid, author) in patches {
+        for comment in load_review_comments(conn, &uuid)? {
+            review_notes.push(ReviewNote {
+                author_name: comment.author_name.unwrap_or_else(|| author_display_name(meta, &comment.author_id)),
+                content: comment.content,
+                created_at: comment.created_at,
+                patch_author_name: author_display_name(meta, &author),
+            });
+        }
+    }
+
+    Ok(ChangeSummary {
+        title: meta.title.clone(),
+        sections: section_changes(&base, &modified, meta, &comments),
+        from,
+        to,
+        review_notes,
+    })
+}
+
+fn format_date(timestamp: i64) -> String {
+    DateTime::from_timestamp_millis(timestamp)
+        .map(|d| d.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+fn quote(text: &str) -> String {
+    text.lines().map(|line| format!("> {}", line).trim_end().to_string()).collect::<Vec<_>>().join("\n")
+}
+
+/// Render the summary as markdown
+pub fn render_summary_markdown(summary: &ChangeSummary) -> String {
+    let mut out = format!("# Summary of changes: {}\n\n", summary.title);
+    out.push_str(&format!(
+        "From the version of {} by {} to the version of {} by {}.\n",
+        format_date(summary.from.timestamp),
+        summary.from.author_name,
+        format_date(summary.to.timestamp),
+        summary.to.author_name
+    ));
+    if summary.sections.is_empty() {
+        out.push_str("\nThe text did not change.\n");
+    }
+
+    for section in &summary.sections {
+        out.push_str(&format!("\n## {}\n", section.section));
+        for change in &section.changes {
+            let label = match (&change.before, &change.after) {
+                (None, _) => "Added",
+                (_, None) => "Removed",
+                _ => "Changed",
+            };
+            out.push_str(&format!("\n**{}**\n", label));
+            if let Some(before) = &change.before {
+                out.push_str(&format!("\nBefore:\n\n{}\n", quote(before)));
+            }
+            if let Some(after) = &change.after {
+                out.push_str(&format!("\nAfter:\n\n{}\n", quote(after)));
+            }
+            if !change.comments.is_empty() {
+                out.push_str("\nComments:\n\n");
+                for comment in &change.comments {
+                    out.push_str(&format!("- {}\n", comment));
+                }
+            }
+        }
+    }
+
+    if !summary.review_notes.is_empty() {
+        out.push_str("\n## Review notes\n\n");
+        for note in &summary.review_notes {
+            out.push_str(&format!(
+                "- {}, {} (on a change by {}): {}\n",
+                note.author_name,
+                format_date(note.created_at),
+                note.patch_author_name,
+                note.content
+            ));
+        }
+    }
+    out
+}
+
+/// Write a summary of the changes between two patches of a document
+///
+/// The format follows the extension of `path`: `.md`, `.docx`, `.html` or
+/// `.pdf`.
+#[tauri::command]
+pub fn export_change_summary(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    from_uuid: String,
+    to_uuid: String,
+    path: String,
+) -> Result<(), KorppiError> {
+    let output = Path::new(&path);
+    let extension = output.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    let format = ExportFormat::from_name(extension)
+        .ok_or_else(|| KorppiError::InvalidInput(format!("Unsupported summary format: {}", extension)))?;
+    let (history_path, meta) = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.clone()))?;
+        (doc.history_path.clone(), doc.meta.clone())
+    };
+
+    let conn = Connection::open(&history_path)?;
+    ensure_schema(&conn)?;
+    let summary = build_change_summary(&conn, &meta, &from_uuid, &to_uuid)?;
+    export_content(format, output, render_summary_markdown(&summary), meta.settings, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_section_changes() {
+        let base = "# Intro\n\nWe study birds.\n\n# Methods\n\nWe counted them.\n\nOld closing remark.\n";
+        let modified = "# Intro\n\nWe study urban birds.\n\n# Methods\n\nWe counted them twice.\n\nA new paragraph.\n";
+        let comments = vec![("counted".to_string(), "Reviewer 2: How often?".to_string())];
+        let sections = section_changes(base, modified, &DocumentMeta::default(), &comments);
+
+        let names: Vec<&str> = sections.iter().map(|s| s.section.as_str()).collect();
+        assert_eq!(names, vec!["Intro", "Methods"]);
+        assert_eq!(sections[0].changes.len(), 1);
+        assert_eq!(sections[0].changes[0].before.as_deref(), Some("We study birds."));
+        assert_eq!(sections[0].changes[0].after.as_deref(), Some("We study urban birds."));
+        assert!(sections[0].changes[0].comments.is_empty());
+
+        let methods = &sections[1].changes;
+        assert_eq!(methods[0].after.as_deref().map(|a| a.starts_with("We counted them twice.")), Some(true));
+        assert_eq!(methods[0].comments, vec!["Reviewer 2: How often?"]);
+    }
+
+    #[test]
+    fn test_render_summary_markdown() {
+        let version = |uuid: &str, name: &str| SummaryVersion {
+            patch_uuid: uuid.to_string(),
+            timestamp: 0,
+            author_name: name.to_string(),
+        };
+        let summary = ChangeSummary {
+            title: "Paper".to_string(),
+            from: version("p1", "Ada"),
+            to: version("p2", "Bob"),
+            sections: vec![SectionChanges {
+                section: "Methods".to_string(),
+                changes: vec![ChangeExcerpt {
+                    before: None,
+                    after: Some("A new paragraph.".to_string()),
+                    comments: Vec::new(),
+                }],
+            }],
+            review_notes: vec![ReviewNote {
+                author_name: "Carol".to_string(),
+                content: "Clearer now".to_string(),
+                created_at: 0,
+                patch_author_name: "Bob".to_string(),
+            }],
+        };
+        let markdown = render_summary_markdown(&summary);
+
+        assert!(markdown.starts_with("# Summary of changes: Paper\n"));
+        assert!(markdown.contains("## Methods\n\n**Added**\n\nAfter:\n\n> A new paragraph.\n"));
+        assert!(!markdown.contains("Before:"));
+        assert!(markdown.contains("- Carol, 1970-01-01 00:00 (on a change by Bob): Clearer now\n"));
+    }
+}
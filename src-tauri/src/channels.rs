@@ -0,0 +1,341 @@
+// src-tauri/src/channels.rs
+//! Per-collaborator channels for incoming patch bundles.
+//!
+//! Instead of importing a bundle wholesale, its patches can be staged in a
+//! channel named after each patch's author, stored in the `channel_patches`
+//! table of the document history. Reviewers then cherry-pick individual
+//! patches into the main history, where the usual review workflow applies,
+//! and drop the rest. Reviews recorded in the bundle are not staged.
+//!
+//! Bundles are checked like imported ones: a signature by another key than
+//! the sender's on record is refused, and each staged patch keeps who signed
+//! its bundle. A cherry-picked patch its author did not sign is flagged as
+//! unverified, as `apply_bundle` does.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::db_utils::ensure_schema;
+use crate::document_manager::{row_to_patch, DocumentManager};
+use crate::kmd::AuthorRef;
+use crate::patch_bundle::{open_bundle, verified_signer};
+use crate::patch_log::{Patch, PatchInput};
+use crate::patch_store::PatchStore;
+
+/// A channel with staged patches
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChannelSummary {
+    /// Channel name, the profile ID of the collaborator
+    pub channel: String,
+    /// Display name of the collaborator, when known to the document
+    pub author_name: Option<String>,
+    pub patch_count: usize,
+    /// Timestamp of the newest staged patch
+    pub latest_timestamp: i64,
+    /// Author whose verified signature covers every staged patch; unset
+    /// when some came from unsigned bundles or several signers
+    pub signed_by: Option<String>,
+}
+
+/// Stage the patches of a bundle history that the document does not have,
+/// signed by `signed_by` when its signature was verified; returns how many
+/// were staged
+pub fn stage_patches(conn: &Connection, bundle: &Connection, signed_by: Option<&str>) -> Result<usize, String> {
+    ensure_schema(conn)?;
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let history = PatchStore::on(conn);
+    let source = PatchStore::on(bundle);
+    let mut staged = 0;
+    for patch in source.list()? {
+        let Some(uuid) = &patch.uuid else {
+            continue;
+        };
+        if history.patch_exists_by_uuid(uuid)? {
+            continue;
+        }
+
+        let snapshot = source.snapshot(patch.id)?.map(|(_, state)| state);
+        staged += conn
+            .execute(
+                "INSERT OR IGNORE INTO channel_patches (channel, uuid, timestamp, author, kind, data, parent_uuid, snapshot, signed_by)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    patch.author,
+                    uuid,
+                    patch.timestamp,
+                    patch.author,
+                    patch.kind,
+                    patch.data.to_string(),
+                    patch.parent_uuid,
+                    snapshot,
+                    signed_by
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(staged)
+}
+
+/// Channels with staged patches, by channel name
+pub fn list_channels(conn: &Connection) -> Result<Vec<ChannelSummary>, String> {
+    ensure_schema(conn)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT channel, COUNT(*), MAX(timestamp),
+                    CASE WHEN COUNT(signed_by) = COUNT(*) AND COUNT(DISTINCT signed_by) = 1 THEN MIN(signed_by) END
+             FROM channel_patches GROUP BY channel ORDER BY channel",
+        )
+        .map_err(|e| e.to_string())?;
+    let channels = stmt
+        .query_map([], |row| {
+            Ok(ChannelSummary {
+                channel: row.get(0)?,
+                author_name: None,
+                patch_count: row.get::<_, i64>(1)? as usize,
+                latest_timestamp: row.get(2)?,
+                signed_by: row.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(channels)
+}
+
+/// Staged patches of a channel, oldest first
+pub fn channel_patches(conn: &Connection, channel: &str) -> Result<Vec<Patch>, String> {
+    ensure_schema(conn)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, timestamp, author, kind, data, uuid, parent_uuid FROM channel_patches
+             WHERE channel = ?1 ORDER BY timestamp ASC, id ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let patches = stmt
+        .query_map(params![channel], row_to_patch)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(patches)
+}
+
+/// Move one staged patch into the main history, flagged as unverified
+/// unless its bundle was signed by its author
+pub fn cherry_pick(conn: &Connection, channel: &str, uuid: &str) -> Result<Patch, String> {
+    ensure_schema(conn)?;
+
+    let staged = conn
+        .query_row(
+            "SELECT id, timestamp, author, kind, data, uuid, parent_uuid FROM channel_patches
+             WHERE channel = ?1 AND uuid = ?2",
+            params![channel, uuid],
+            row_to_patch,
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Patch not found in channel {}: {}", channel, uuid))?;
+    let (snapshot, signed_by): (Option<Vec<u8>>, Option<String>) = conn
+        .query_row("SELECT snapshot, signed_by FROM channel_patches WHERE id = ?1", params![staged.id], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let input = PatchInput {
+        timestamp: staged.timestamp,
+        author: staged.author,
+        kind: staged.kind,
+        data: staged.data,
+        uuid: Some(uuid.to_string()),
+        parent_uuid: staged.parent_uuid,
+    };
+    let store = PatchStore::on(conn);
+    let patch = store
+        .insert_patch(&input, snapshot.as_deref())?
+        .ok_or_else(|| format!("Patch is already in the history: {}", uuid))?;
+    store.mark_unverified(&HashSet::from([uuid.to_string()]), signed_by.as_deref())?;
+    conn.execute("DELETE FROM channel_patches WHERE id = ?1", params![staged.id])
+        .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(patch)
+}
+
+/// Discard the staged patches of a channel; returns how many were dropped
+pub fn drop_channel(conn: &Connection, channel: &str) -> Result<usize, String> {
+    ensure_schema(conn)?;
+    conn.execute("DELETE FROM channel_patches WHERE channel = ?1", params![channel])
+        .map_err(|e| e.to_string())
+}
+
+/// History database of an open document
+struct DocumentHistory {
+    conn: Connection,
+    path: PathBuf,
+    authors: Vec<AuthorRef>,
+    document_uuid: String,
+}
+
+/// Open the history database of a document
+fn open_history(manager: &State<'_, Mutex<DocumentManager>>, id: &str) -> Result<DocumentHistory, String> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get(id)
+        .ok_or_else(|| format!("Document not found: {}", id))?;
+    Ok(DocumentHistory {
+        conn: Connection::open(&doc.history_path).map_err(|e| e.to_string())?,
+        path: doc.history_path.clone(),
+        authors: doc.meta.authors.clone(),
+        document_uuid: doc.meta.uuid.clone(),
+    })
+}
+
+fn with_author_names(mut channels: Vec<ChannelSummary>, authors: &[AuthorRef]) -> Vec<ChannelSummary> {
+    for channel in &mut channels {
+        channel.author_name = authors.iter().find(|a| a.id == channel.channel).map(|a| a.name.clone());
+    }
+    channels
+}
+
+/// Stage a patch bundle in per-collaborator channels instead of importing it
+///
+/// A bundle signed with another key than its sender's on record is refused.
+#[tauri::command]
+pub fn stage_patch_bundle(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    path: String,
+) -> Result<Vec<ChannelSummary>, String> {
+    let history = open_history(&manager, &id)?;
+    let bundle = open_bundle(Path::new(&path), &history.document_uuid)?;
+    let signed_by = verified_signer(&history.path, &bundle.manifest)?;
+    stage_patches(&history.conn, &bundle.history, signed_by.as_deref())?;
+
+    Ok(with_author_names(list_channels(&history.conn)?, &history.authors))
+}
+
+/// List the channels of a document that hold staged patches
+#[tauri::command]
+pub fn list_patch_channels(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+) -> Result<Vec<ChannelSummary>, String> {
+    let history = open_history(&manager, &id)?;
+    Ok(with_author_names(list_channels(&history.conn)?, &history.authors))
+}
+
+/// List the staged patches of a channel
+#[tauri::command]
+pub fn list_channel_patches(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    channel: String,
+) -> Result<Vec<Patch>, String> {
+    channel_patches(&open_history(&manager, &id)?.conn, &channel)
+}
+
+/// Move a staged patch from a channel into the document history
+#[tauri::command]
+pub fn cherry_pick_channel_patch(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    channel: String,
+    uuid: String,
+) -> Result<Patch, String> {
+    cherry_pick(&open_history(&manager, &id)?.conn, &channel, &uuid)
+}
+
+/// Discard the remaining staged patches of a channel
+#[tauri::command]
+pub fn drop_patch_channel(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    channel: String,
+) -> Result<usize, String> {
+    drop_channel(&open_history(&manager, &id)?.conn, &channel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::patch_store::{insert_test_patch, test_patch};
+
+    fn insert_patch(conn: &Connection, uuid: &str, author: &str, timestamp: i64, snapshot: &str) {
+        let patch = PatchInput { author: author.to_string(), timestamp, ..test_patch(uuid, None, snapshot) };
+        insert_test_patch(conn, patch);
+    }
+
+    #[test]
+    fn test_stage_and_cherry_pick() {
+        let main = Connection::open_in_memory().unwrap();
+        ensure_schema(&main).unwrap();
+        insert_patch(&main, "p1", "alice", 1, "a");
+
+        let bundle = Connection::open_in_memory().unwrap();
+        ensure_schema(&bundle).unwrap();
+        insert_patch(&bundle, "p1", "alice", 1, "a");
+        insert_patch(&bundle, "b1", "bob", 2, "ab");
+        insert_patch(&bundle, "b2", "bob", 3, "abc");
+        insert_patch(&bundle, "c1", "carol", 4, "abcd");
+
+        assert_eq!(stage_patches(&main, &bundle, None).unwrap(), 3);
+        // Staging again adds nothing
+        assert_eq!(stage_patches(&main, &bundle, None).unwrap(), 0);
+
+        let channels = list_channels(&main).unwrap();
+        let names: Vec<(&str, usize)> = channels.iter().map(|c| (c.channel.as_str(), c.patch_count)).collect();
+        assert_eq!(names, vec![("bob", 2), ("carol", 1)]);
+
+        let picked = cherry_pick(&main, "bob", "b2").unwrap();
+        assert_eq!(picked.uuid.as_deref(), Some("b2"));
+        assert_eq!(picked.data["snapshot"], "abc");
+        let snapshot: Vec<u8> = main
+            .query_row("SELECT state FROM snapshot_states WHERE patch_id = ?1", params![picked.id], |row| row.get(0))
+            .unwrap();
+        assert_eq!(snapshot, b"abc");
+
+        let remaining: Vec<_> = channel_patches(&main, "bob").unwrap().into_iter().filter_map(|p| p.uuid).collect();
+        assert_eq!(remaining, vec!["b1"]);
+        assert!(cherry_pick(&main, "bob", "b2").is_err());
+
+        assert_eq!(drop_channel(&main, "bob").unwrap(), 1);
+        let uuids: Vec<_> = PatchStore::on(&main).list().unwrap().into_iter().filter_map(|p| p.uuid).collect();
+        assert_eq!(uuids, vec!["p1", "b2"]);
+        assert_eq!(list_channels(&main).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_cherry_pick_flags_forged_authors() {
+        let main = Connection::open_in_memory().unwrap();
+        ensure_schema(&main).unwrap();
+
+        // Carol signed the bundle, but it also carries a patch claiming to be Bob's
+        let signed = Connection::open_in_memory().unwrap();
+        ensure_schema(&signed).unwrap();
+        insert_patch(&signed, "c1", "carol", 1, "a");
+        insert_patch(&signed, "b1", "bob", 2, "ab");
+        stage_patches(&main, &signed, Some("carol")).unwrap();
+
+        let unsigned = Connection::open_in_memory().unwrap();
+        ensure_schema(&unsigned).unwrap();
+        insert_patch(&unsigned, "c2", "carol", 3, "abc");
+        stage_patches(&main, &unsigned, None).unwrap();
+
+        let signers: Vec<(String, Option<String>)> =
+            list_channels(&main).unwrap().into_iter().map(|c| (c.channel, c.signed_by)).collect();
+        assert_eq!(signers, vec![("bob".to_string(), Some("carol".to_string())), ("carol".to_string(), None)]);
+
+        for (channel, uuid) in [("bob", "b1"), ("carol", "c1"), ("carol", "c2")] {
+            cherry_pick(&main, channel, uuid).unwrap();
+        }
+        let unverified = PatchStore::on(&main).unverified_uuids().unwrap();
+        assert_eq!(unverified, HashSet::from(["b1".to_string(), "c2".to_string()]));
+    }
+}
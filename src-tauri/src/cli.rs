@@ -0,0 +1,346 @@
+// src-tauri/src/cli.rs
+//! Headless command line.
+//!
+//! `korppi --export docx input.kmd -o out.docx` runs the export pipeline
+//! and exits without starting the GUI, so manuscripts can be built in CI.
+//! The output defaults to the input path with the format's extension.
+//!
+//! `korppi patch export doc.kmd --since <uuid> -o changes.kmd-patch` and
+//! `korppi patch apply doc.kmd changes.kmd-patch` exchange patch bundles,
+//! so collaboration can be scripted over git or email. `--to <author-id>`
+//! encrypts the bundle for one collaborator. `--patches <uuid,...>` or
+//! `--branch <uuid>` export only some patches instead of all since one.
+
+use std::path::{Path, PathBuf};
+
+use crate::document_manager::read_kmd_markdown;
+use crate::error::KorppiError;
+use crate::kmd::{export_html, export_pdf, write_docx_export, write_markdown_export, DocumentMeta, DocumentSettings};
+use crate::patch_bundle::{apply_bundle_to_kmd, export_bundle_from_kmd, PatchSelection, BUNDLE_EXTENSION};
+
+const USAGE: &str = "Usage:
+  korppi --export <markdown|docx|html|pdf> <input.kmd> [-o <output>]
+  korppi patch export <doc.kmd> [--since <uuid> | --patches <uuid,...> | --branch <uuid>] [--to <author-id>] [-o <changes.kmd-patch>]
+  korppi patch apply <doc.kmd> <changes.kmd-patch>";
+
+/// Output format of a command-line export
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Markdown,
+    Docx,
+    Html,
+    Pdf,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "markdown" | "md" => Some(Self::Markdown),
+            "docx" => Some(Self::Docx),
+            "html" => Some(Self::Html),
+            "pdf" => Some(Self::Pdf),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Docx => "docx",
+            Self::Html => "html",
+            Self::Pdf => "pdf",
+        }
+    }
+}
+
+/// A parsed `--export` invocation
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportRequest {
+    pub format: ExportFormat,
+    pub input: PathBuf,
+    pub output: PathBuf,
+}
+
+/// A headless invocation
+#[derive(Debug, Clone, PartialEq)]
+pub enum CliCommand {
+    Export(ExportRequest),
+    PatchExport {
+        document: PathBuf,
+        selection: PatchSelection,
+        /// Author ID to encrypt the bundle for
+        recipient: Option<String>,
+        output: PathBuf,
+    },
+    PatchApply {
+        document: PathBuf,
+        bundle: PathBuf,
+    },
+}
+
+/// Parse command-line arguments (without the program name)
+///
+/// Returns `None` when the arguments are not a headless command and the GUI
+/// should start.
+pub fn parse_args(args: &[String]) -> Option<Result<CliCommand, String>> {
+    if args.first().is_some_and(|a| a == "patch") {
+        return Some(parse_patch_command(&args[1..]));
+    }
+    let position = args.iter().position(|a| a == "--export" || a.starts_with("--export="))?;
+    Some(parse_export_request(&args[position..]).map(CliCommand::Export))
+}
+
+fn parse_patch_command(args: &[String]) -> Result<CliCommand, String> {
+    let action = args.first().ok_or("Missing patch action")?;
+    let mut since = None;
+    let mut patches = None;
+    let mut branch = None;
+    let mut recipient = None;
+    let mut output = None;
+    let mut positional = Vec::new();
+
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--since" => since = Some(rest.next().ok_or("Missing value for --since")?.clone()),
+            "--patches" => {
+                let list = rest.next().ok_or("Missing value for --patches")?;
+                patches = Some(list.split(',').map(str::trim).filter(|u| !u.is_empty()).map(str::to_string).collect());
+            }
+            "--branch" => branch = Some(rest.next().ok_or("Missing value for --branch")?.clone()),
+            "--to" => recipient = Some(rest.next().ok_or("Missing value for --to")?.clone()),
+            "-o" | "--output" => {
+                output = Some(PathBuf::from(rest.next().ok_or_else(|| format!("Missing value for {}", arg))?));
+            }
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    match (action.as_str(), positional.as_slice()) {
+        ("export", [document]) => Ok(CliCommand::PatchExport {
+            output: output.unwrap_or_else(|| document.with_extension(BUNDLE_EXTENSION)),
+            document: document.clone(),
+            selection: PatchSelection::from_options(since, patches, branch)?,
+            recipient,
+        }),
+        ("apply", [document, bundle]) => Ok(CliCommand::PatchApply {
+            document: document.clone(),
+            bundle: bundle.clone(),
+        }),
+        ("export", _) => Err("patch export takes one document".to_string()),
+        ("apply", _) => Err("patch apply takes a document and a bundle".to_string()),
+        _ => Err(format!("Unknown patch action: {}", action)),
+    }
+}
+
+fn parse_export_request(args: &[String]) -> Result<ExportRequest, String> {
+    let mut format_name = args[0].strip_prefix("--export=").map(|s| s.to_string());
+    let mut input = None;
+    let mut output = None;
+
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                let value = rest.next().ok_or_else(|| format!("Missing value for {}", arg))?;
+                output = Some(PathBuf::from(value));
+            }
+            _ if format_name.is_none() => format_name = Some(arg.clone()),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+
+    let format_name = format_name.ok_or("Missing export format")?;
+    let format = ExportFormat::from_name(&format_name)
+        .ok_or_else(|| format!("Unsupported export format: {}", format_name))?;
+    let input: PathBuf = input.ok_or("Missing input file")?;
+    let output = output.unwrap_or_else(|| input.with_extension(format.extension()));
+
+    Ok(ExportRequest { format, input, output })
+}
+
+/// Export a KMD file to the requested format
+pub fn run_export(request: &ExportRequest) -> Result<(), String> {
+    if !request.input.exists() {
+        return Err(format!("File not found: {}", request.input.display()));
+    }
+    let (content, meta) = read_kmd_markdown(&request.input)?;
+    export_content(request.format, &request.output, content, meta.settings.clone(), Some(&meta)).map_err(String::from)
+}
+
+/// Run markdown through the export pipeline of a format, with the metadata
+/// of a document if given
+pub fn export_content(
+    format: ExportFormat,
+    output: &Path,
+    content: String,
+    settings: DocumentSettings,
+    meta: Option<&DocumentMeta>,
+) -> Result<(), KorppiError> {
+    let output = path_string(output);
+    match format {
+        ExportFormat::Markdown => write_markdown_export(output, content, Some(settings), meta),
+        ExportFormat::Docx => write_docx_export(output, content, None, Some(settings), meta),
+        ExportFormat::Html => export_html(&output, &content, Some(&settings), meta),
+        ExportFormat::Pdf => export_pdf(&output, &content, Some(&settings), meta),
+    }
+}
+
+fn path_string(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+/// Run a headless command, returning the message to print on success
+fn run_command(command: &CliCommand) -> Result<String, String> {
+    match command {
+        CliCommand::Export(request) => {
+            run_export(request)?;
+            Ok(format!("Exported {} to {}", request.input.display(), request.output.display()))
+        }
+        CliCommand::PatchExport { document, selection, recipient, output } => {
+            let manifest = export_bundle_from_kmd(document, selection, recipient.as_deref(), output)?;
+            let encrypted = match &manifest.encryption {
+                Some(encryption) => format!(", encrypted for {}", encryption.recipient_id),
+                None => String::new(),
+            };
+            let mut message = format!("Wrote {} patch(es) to {}{}", manifest.patch_count, output.display(), encrypted);
+            for parent in &manifest.missing_parents {
+                message.push_str(&format!(
+                    "\nWarning: patch {} is needed by the bundle but not included; the receiver must already have it",
+                    parent
+                ));
+            }
+            Ok(message)
+        }
+        CliCommand::PatchApply { document, bundle } => {
+            let result = apply_bundle_to_kmd(document, bundle)?;
+            let mut message = format!(
+                "Imported {} patch(es) into {} ({} already present{})",
+                result.imported_patches,
+                document.display(),
+                result.skipped_patches,
+                if result.state_changed { ", text merged" } else { "" }
+            );
+            match &result.signed_by {
+                Some(signer) => message.push_str(&format!("\nSigned by {}", signer)),
+                None => message.push_str("\nThe bundle is not signed by a known collaborator"),
+            }
+            if result.unverified_patches > 0 {
+                message.push_str(&format!("\n{} patch(es) marked unverified", result.unverified_patches));
+            }
+            for warning in &result.lock_warnings {
+                message.push_str(&format!(
+                    "\nWarning: patch {} by {} changes {}, locked by {}",
+                    warning.patch_uuid, warning.author, warning.label, warning.holder_name
+                ));
+            }
+            Ok(message)
+        }
+    }
+}
+
+/// Handle a headless invocation; returns the exit code, or `None` to start
+/// the GUI
+pub fn run(args: &[String]) -> Option<i32> {
+    let command = match parse_args(args)? {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return Some(2);
+        }
+    };
+
+    match run_command(&command) {
+        Ok(message) => {
+            println!("{}", message);
+            Some(0)
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Some(1)
+        }
+    }
+}
+
+/// The file a GUI launch should open: the first argument that is not a flag
+/// or a `korppi://` link, resolved against the working directory of the launching process
+pub fn file_argument(args: &[String], cwd: &Path) -> Option<PathBuf> {
+    args.iter()
+        .find(|arg| !arg.starts_with('-') && !crate::deep_links::is_position_link(arg))
+        .map(|arg| cwd.join(arg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn export_request(list: &[&str]) -> Result<ExportRequest, String> {
+        match parse_args(&args(list)).unwrap()? {
+            CliCommand::Export(request) => Ok(request),
+            other => panic!("not an export: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_export_args() {
+        assert!(parse_args(&args(&["paper.kmd"])).is_none());
+
+        let request = export_request(&["--export", "docx", "paper.kmd", "-o", "out.docx"]).unwrap();
+        assert_eq!(request.format, ExportFormat::Docx);
+        assert_eq!(request.input, PathBuf::from("paper.kmd"));
+        assert_eq!(request.output, PathBuf::from("out.docx"));
+
+        let request = export_request(&["--export=md", "paper.kmd"]).unwrap();
+        assert_eq!(request.format, ExportFormat::Markdown);
+        assert_eq!(request.output, PathBuf::from("paper.md"));
+
+        assert!(export_request(&["--export", "rtf", "paper.kmd"]).is_err());
+        assert!(export_request(&["--export", "pdf"]).is_err());
+        assert!(export_request(&["--export", "pdf", "a.kmd", "-o"]).is_err());
+    }
+
+    #[test]
+    fn test_file_argument() {
+        let cwd = Path::new("/home/user");
+        assert_eq!(file_argument(&args(&["paper.kmd"]), cwd), Some(PathBuf::from("/home/user/paper.kmd")));
+        assert_eq!(file_argument(&args(&["--flag", "/tmp/a.kmd"]), cwd), Some(PathBuf::from("/tmp/a.kmd")));
+        assert_eq!(file_argument(&args(&[]), cwd), None);
+    }
+
+    #[test]
+    fn test_parse_patch_args() {
+        let command = parse_args(&args(&["patch", "export", "doc.kmd", "--since", "p1"])).unwrap().unwrap();
+        assert_eq!(command, CliCommand::PatchExport {
+            document: PathBuf::from("doc.kmd"),
+            selection: PatchSelection::Since(Some("p1".to_string())),
+            recipient: None,
+            output: PathBuf::from("doc.kmd-patch"),
+        });
+
+        let command = parse_args(&args(&["patch", "export", "doc.kmd", "--to", "bob", "-o", "b.kmd-patch"])).unwrap().unwrap();
+        assert_eq!(command, CliCommand::PatchExport {
+            document: PathBuf::from("doc.kmd"),
+            selection: PatchSelection::Since(None),
+            recipient: Some("bob".to_string()),
+            output: PathBuf::from("b.kmd-patch"),
+        });
+
+        let command = parse_args(&args(&["patch", "export", "doc.kmd", "--patches", "p2, p4"])).unwrap().unwrap();
+        assert!(matches!(command, CliCommand::PatchExport { selection: PatchSelection::Only(ref uuids), .. } if uuids == &["p2", "p4"]));
+        assert!(parse_args(&args(&["patch", "export", "doc.kmd", "--since", "p1", "--branch", "p3"])).unwrap().is_err());
+
+        let command = parse_args(&args(&["patch", "apply", "doc.kmd", "changes.kmd-patch"])).unwrap().unwrap();
+        assert_eq!(command, CliCommand::PatchApply {
+            document: PathBuf::from("doc.kmd"),
+            bundle: PathBuf::from("changes.kmd-patch"),
+        });
+
+        assert!(parse_args(&args(&["patch", "apply", "doc.kmd"])).unwrap().is_err());
+        assert!(parse_args(&args(&["patch", "merge", "doc.kmd"])).unwrap().is_err());
+    }
+}
@@ -0,0 +1,121 @@
+// src-tauri/src/collation.rs
+//! Sorting names the way the document language does.
+//!
+//! Byte order puts "Zoë" before "adam" and "Åberg" after everything. Names
+//! are compared here on their letters first, ignoring case and accents,
+//! then on accents and then on case, as dictionaries do. Some languages
+//! treat accented letters as letters of their own: Finnish and Swedish put
+//! å, ä and ö after z, Danish and Norwegian æ, ø and å, and Spanish puts ñ
+//! after n. German expands ß to ss.
+
+use std::cmp::Ordering;
+
+/// Accented Latin letters and the letter they sort as
+const FOLDS: [(&str, char); 19] = [
+    ("àáâãäåāăą", 'a'),
+    ("çćĉċč", 'c'),
+    ("ďđ", 'd'),
+    ("èéêëēĕėęě", 'e'),
+    ("ĝğġģ", 'g'),
+    ("ĥħ", 'h'),
+    ("ìíîïĩīĭįı", 'i'),
+    ("ĵ", 'j'),
+    ("ķ", 'k'),
+    ("ĺļľŀł", 'l'),
+    ("ñńņňŉ", 'n'),
+    ("òóôõöøōŏő", 'o'),
+    ("ŕŗř", 'r'),
+    ("śŝşšș", 's'),
+    ("ţťŧț", 't'),
+    ("ùúûüũūŭůűų", 'u'),
+    ("ŵ", 'w'),
+    ("ýÿŷ", 'y'),
+    ("źżž", 'z'),
+];
+
+/// Letters a language sorts after z, in order
+fn letters_after_z(language: &str) -> &'static [char] {
+    match base_language(language).as_str() {
+        "fi" | "sv" => &['å', 'ä', 'ö'],
+        "da" | "nb" | "nn" | "no" => &['æ', 'ø', 'å'],
+        _ => &[],
+    }
+}
+
+fn base_language(language: &str) -> String {
+    language.split(['-', '_']).next().unwrap_or("").to_lowercase()
+}
+
+const LETTER_BASE: u32 = 100;
+
+/// Weight of a plain letter a-z
+fn letter_weight(letter: char) -> u32 {
+    LETTER_BASE + (letter as u32 - 'a' as u32) * 4
+}
+
+/// Primary weights of a character: what it sorts as, ignoring case and
+/// accents; punctuation has none
+fn primary_weights(c: char, language: &str, after_z: &[char]) -> Vec<u32> {
+    let lower = c.to_lowercase().next().unwrap_or(c);
+    if let Some(i) = after_z.iter().position(|l| *l == lower) {
+        return vec![letter_weight('z') + 4 + i as u32];
+    }
+    if lower == 'ñ' && base_language(language) == "es" {
+        return vec![letter_weight('n') + 2];
+    }
+    let expanded: &[char] = match lower {
+        'ß' => &['s', 's'],
+        'æ' => &['a', 'e'],
+        'œ' => &['o', 'e'],
+        'þ' => &['t', 'h'],
+        _ => &[],
+    };
+    if !expanded.is_empty() {
+        return expanded.iter().map(|l| letter_weight(*l)).collect();
+    }
+    let base = FOLDS
+        .iter()
+        .find(|(accented, _)| accented.contains(lower))
+        .map_or(lower, |(_, base)| *base);
+    match base {
+        'a'..='z' => vec![letter_weight(base)],
+        '0'..='9' => vec![10 + base as u32 - '0' as u32],
+        _ if base.is_whitespace() => vec![1],
+        _ if base.is_alphanumeric() => vec![LETTER_BASE * 10 + base as u32],
+        _ => Vec::new(),
+    }
+}
+
+/// Compare two strings as the language sorts them
+pub fn compare(a: &str, b: &str, language: &str) -> Ordering {
+    let after_z = letters_after_z(language);
+    let primary = |s: &str| -> Vec<u32> { s.chars().flat_map(|c| primary_weights(c, language, after_z)).collect() };
+    let lowercase = |s: &str| -> String { s.to_lowercase() };
+    primary(a)
+        .cmp(&primary(b))
+        .then_with(|| lowercase(a).cmp(&lowercase(b)))
+        // Lowercase first, as in dictionaries
+        .then_with(|| b.cmp(a))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(names: &[&str], language: &str) -> Vec<String> {
+        let mut names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        names.sort_by(|a, b| compare(a, b, language));
+        names
+    }
+
+    #[test]
+    fn test_locale_order() {
+        let names = ["Zoë", "Öberg", "adam", "Åke", "Oberg", "Émile", "Ozols"];
+        assert_eq!(sorted(&names, "en"), ["adam", "Åke", "Émile", "Oberg", "Öberg", "Ozols", "Zoë"]);
+        assert_eq!(sorted(&names, "fi-FI"), ["adam", "Émile", "Oberg", "Ozols", "Zoë", "Åke", "Öberg"]);
+        assert_eq!(sorted(&["Nuñez", "Nuno", "Nuzzo"], "es"), ["Nuno", "Nuñez", "Nuzzo"]);
+        assert_eq!(sorted(&["Nuñez", "Nuno", "Nuzzo"], "en"), ["Nuñez", "Nuno", "Nuzzo"]);
+        assert_eq!(compare("Strauss", "Strauß", "de"), Ordering::Less);
+        assert_eq!(sorted(&["Bob", "bob", "Anna Lee", "Annabel"], "en"), ["Anna Lee", "Annabel", "bob", "Bob"]);
+    }
+}
@@ -3,13 +3,19 @@
 //!
 //! Stores comments with Yjs relative position anchors for stable positioning.
 //! Supports threaded replies via parent_id.
+//! Status transitions are logged to comment_events as an audit trail.
+//! After the text is replaced wholesale (restore, import) the Yjs anchors
+//! dangle; `rebase_comment_anchors` finds the selected text again or marks
+//! the comment as orphaned.
 
 use rusqlite::{params, Connection};
 use serde::{Deserialize, Serialize};
 use std::sync::Mutex;
-use tauri::State;
+use tauri::{AppHandle, State};
 
 use crate::document_manager::DocumentManager;
+use crate::error::KorppiError;
+use crate::events::{emit_event, CommentAddedEvent, COMMENT_ADDED};
 
 /// Input for creating a new comment
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,6 +42,19 @@ pub struct Comment {
     pub content: String,
     pub status: String,
     pub parent_id: Option<i64>,
+    /// The selected text could not be found after the content was replaced
+    #[serde(default)]
+    pub orphaned: bool,
+}
+
+/// A logged comment status transition
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommentEvent {
+    pub id: i64,
+    pub comment_id: i64,
+    pub event: String, // "resolved", "reopened" or "deleted"
+    pub actor: Option<String>,
+    pub timestamp: i64,
 }
 
 /// Initialize comments table in a document's history database
@@ -56,29 +75,120 @@ pub fn init_comments_table(conn: &Connection) -> Result<(), String> {
             FOREIGN KEY (parent_id) REFERENCES comments(id)
         );
 
+        CREATE TABLE IF NOT EXISTS comment_events (
+            id              INTEGER PRIMARY KEY AUTOINCREMENT,
+            comment_id      INTEGER NOT NULL,
+            event           TEXT    NOT NULL CHECK (event IN ('resolved', 'reopened', 'deleted')),
+            actor           TEXT,
+            timestamp       INTEGER NOT NULL,
+            FOREIGN KEY (comment_id) REFERENCES comments(id)
+        );
+
         CREATE INDEX IF NOT EXISTS idx_comments_status ON comments(status);
         CREATE INDEX IF NOT EXISTS idx_comments_parent ON comments(parent_id);
+        CREATE INDEX IF NOT EXISTS idx_comment_events_comment_id ON comment_events(comment_id);
         "#,
     )
     .map_err(|e| e.to_string())?;
+
+    // Added after the first release; fails harmlessly if the column exists
+    conn.execute("ALTER TABLE comments ADD COLUMN orphaned INTEGER NOT NULL DEFAULT 0", []).ok();
+    Ok(())
+}
+
+/// Log a status transition for a comment
+pub fn record_comment_event(
+    conn: &Connection,
+    comment_id: i64,
+    event: &str,
+    actor: Option<&str>,
+) -> Result<(), String> {
+    let timestamp = chrono::Utc::now().timestamp_millis();
+
+    conn.execute(
+        "INSERT INTO comment_events (comment_id, event, actor, timestamp) VALUES (?1, ?2, ?3, ?4)",
+        params![comment_id, event, actor, timestamp],
+    )
+    .map_err(|e| e.to_string())?;
+
     Ok(())
 }
 
+/// Load the audit trail, optionally restricted to a single comment
+pub fn query_comment_events(
+    conn: &Connection,
+    comment_id: Option<i64>,
+) -> Result<Vec<CommentEvent>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, comment_id, event, actor, timestamp FROM comment_events
+             WHERE ?1 IS NULL OR comment_id = ?1
+             ORDER BY timestamp ASC, id ASC",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let events = stmt
+        .query_map(params![comment_id], |row| {
+            Ok(CommentEvent {
+                id: row.get(0)?,
+                comment_id: row.get(1)?,
+                event: row.get(2)?,
+                actor: row.get(3)?,
+                timestamp: row.get(4)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(events)
+}
+
+/// Load every comment in a history database, oldest first
+pub fn load_all_comments(conn: &Connection) -> Result<Vec<Comment>, String> {
+    let mut stmt = conn
+        .prepare("SELECT id, timestamp, author, author_color, start_anchor, end_anchor, selected_text, content, status, parent_id, orphaned FROM comments ORDER BY timestamp ASC, id ASC")
+        .map_err(|e| e.to_string())?;
+
+    let comments = stmt
+        .query_map([], |row| {
+            Ok(Comment {
+                id: row.get(0)?,
+                timestamp: row.get(1)?,
+                author: row.get(2)?,
+                author_color: row.get(3)?,
+                start_anchor: row.get(4)?,
+                end_anchor: row.get(5)?,
+                selected_text: row.get(6)?,
+                content: row.get(7)?,
+                status: row.get(8)?,
+                parent_id: row.get(9)?,
+                orphaned: row.get(10)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(comments)
+}
+
 /// Add a comment to a document
 #[tauri::command]
 pub fn add_comment(
+    app: AppHandle,
     manager: State<'_, Mutex<DocumentManager>>,
     doc_id: String,
     comment: CommentInput,
-) -> Result<i64, String> {
+) -> Result<i64, KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
 
     let doc = manager
         .documents
         .get(&doc_id)
-        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
 
-    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&doc.history_path)?;
     init_comments_table(&conn)?;
 
     let timestamp = chrono::Utc::now().timestamp_millis();
@@ -98,10 +208,15 @@ pub fn add_comment(
             comment.content,
             comment.parent_id,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     let id = conn.last_insert_rowid();
+    emit_event(&app, COMMENT_ADDED, CommentAddedEvent {
+        doc_id,
+        comment_id: id,
+        parent_id: comment.parent_id,
+        author: comment.author,
+    });
     Ok(id)
 }
 
@@ -111,18 +226,18 @@ pub fn list_comments(
     manager: State<'_, Mutex<DocumentManager>>,
     doc_id: String,
     status_filter: Option<String>,
-) -> Result<Vec<Comment>, String> {
+) -> Result<Vec<Comment>, KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
 
     let doc = manager
         .documents
         .get(&doc_id)
-        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
 
-    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&doc.history_path)?;
     init_comments_table(&conn)?;
 
-    let base_query = "SELECT id, timestamp, author, author_color, start_anchor, end_anchor, selected_text, content, status, parent_id FROM comments";
+    let base_query = "SELECT id, timestamp, author, author_color, start_anchor, end_anchor, selected_text, content, status, parent_id, orphaned FROM comments";
 
     // Helper closure to map rows to Comment
     let map_row = |row: &rusqlite::Row| -> rusqlite::Result<Comment> {
@@ -137,6 +252,7 @@ pub fn list_comments(
             content: row.get(7)?,
             status: row.get(8)?,
             parent_id: row.get(9)?,
+            orphaned: row.get(10)?,
         })
     };
 
@@ -144,28 +260,24 @@ pub fn list_comments(
         // Validate status to prevent injection (only allow known values)
         let valid_statuses = ["unresolved", "resolved", "deleted"];
         if !valid_statuses.contains(&status.as_str()) {
-            return Err(format!(
+            return Err(KorppiError::InvalidInput(format!(
                 "Invalid status filter: {}. Must be one of: unresolved, resolved, deleted",
                 status
-            ));
+            )));
         }
 
         let query = format!("{} WHERE status = ?1 ORDER BY timestamp ASC", base_query);
-        let mut stmt = conn.prepare(&query).map_err(|e| e.to_string())?;
+        let mut stmt = conn.prepare(&query)?;
         let comments: Vec<Comment> = stmt
-            .query_map(params![status], map_row)
-            .map_err(|e| e.to_string())?
-            .collect::<Result<Vec<_>, _>>()
-            .map_err(|e| e.to_string())?;
+            .query_map(params![status], map_row)?
+            .collect::<Result<Vec<_>, _>>()?;
         Ok(comments)
     } else {
         let query = format!("{} ORDER BY timestamp ASC", base_query);
-        let mut stmt = conn.prepare(&query).map_err(|e| e.to_string())?;
+        let mut stmt = conn.prepare(&query)?;
         let comments: Vec<Comment> = stmt
-            .query_map([], map_row)
-            .map_err(|e| e.to_string())?
-            .collect::<Result<Vec<_>, _>>()
-            .map_err(|e| e.to_string())?;
+            .query_map([], map_row)?
+            .collect::<Result<Vec<_>, _>>()?;
         Ok(comments)
     }
 }
@@ -173,27 +285,28 @@ pub fn list_comments(
 /// Add a reply to an existing comment
 #[tauri::command]
 pub fn add_reply(
+    app: AppHandle,
     manager: State<'_, Mutex<DocumentManager>>,
     doc_id: String,
     parent_id: i64,
     content: String,
     author: String,
     author_color: Option<String>,
-) -> Result<i64, String> {
+) -> Result<i64, KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
 
     let doc = manager
         .documents
         .get(&doc_id)
-        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
 
-    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&doc.history_path)?;
     init_comments_table(&conn)?;
 
     // Get parent comment's anchors
     let parent: Comment = conn
         .query_row(
-            "SELECT id, timestamp, author, author_color, start_anchor, end_anchor, selected_text, content, status, parent_id FROM comments WHERE id = ?1",
+            "SELECT id, timestamp, author, author_color, start_anchor, end_anchor, selected_text, content, status, parent_id, orphaned FROM comments WHERE id = ?1",
             params![parent_id],
             |row| {
                 Ok(Comment {
@@ -207,6 +320,7 @@ pub fn add_reply(
                     content: row.get(7)?,
                     status: row.get(8)?,
                     parent_id: row.get(9)?,
+                    orphaned: row.get(10)?,
                 })
             },
         )
@@ -230,10 +344,15 @@ pub fn add_reply(
             content,
             parent_id,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     let id = conn.last_insert_rowid();
+    emit_event(&app, COMMENT_ADDED, CommentAddedEvent {
+        doc_id,
+        comment_id: id,
+        parent_id: Some(parent_id),
+        author,
+    });
     Ok(id)
 }
 
@@ -243,21 +362,24 @@ pub fn resolve_comment(
     manager: State<'_, Mutex<DocumentManager>>,
     doc_id: String,
     comment_id: i64,
-) -> Result<(), String> {
+    actor: Option<String>,
+) -> Result<(), KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
 
     let doc = manager
         .documents
         .get(&doc_id)
-        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
 
-    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&doc.history_path)?;
+    init_comments_table(&conn)?;
 
     conn.execute(
         "UPDATE comments SET status = 'resolved' WHERE id = ?1",
         params![comment_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
+
+    record_comment_event(&conn, comment_id, "resolved", actor.as_deref())?;
 
     Ok(())
 }
@@ -268,22 +390,29 @@ pub fn delete_comment(
     manager: State<'_, Mutex<DocumentManager>>,
     doc_id: String,
     comment_id: i64,
-) -> Result<(), String> {
+) -> Result<(), KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
 
     let doc = manager
         .documents
         .get(&doc_id)
-        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+
+    let conn = Connection::open(&doc.history_path)?;
 
-    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    init_comments_table(&conn)?;
+
+    // Drop the audit trail of the comment and its replies along with them
+    conn.execute(
+        "DELETE FROM comment_events WHERE comment_id IN (SELECT id FROM comments WHERE id = ?1 OR parent_id = ?1)",
+        params![comment_id],
+    )?;
 
     // Delete the comment and its replies
     conn.execute(
         "DELETE FROM comments WHERE id = ?1 OR parent_id = ?1",
         params![comment_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     Ok(())
 }
@@ -294,22 +423,25 @@ pub fn mark_comment_deleted(
     manager: State<'_, Mutex<DocumentManager>>,
     doc_id: String,
     comment_id: i64,
-) -> Result<(), String> {
+    actor: Option<String>,
+) -> Result<(), KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
 
     let doc = manager
         .documents
         .get(&doc_id)
-        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
 
-    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&doc.history_path)?;
+    init_comments_table(&conn)?;
 
     // Mark this comment and its replies as deleted
     conn.execute(
         "UPDATE comments SET status = 'deleted' WHERE id = ?1 OR parent_id = ?1",
         params![comment_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
+
+    record_comment_event(&conn, comment_id, "deleted", actor.as_deref())?;
 
     Ok(())
 }
@@ -320,22 +452,217 @@ pub fn restore_comment(
     manager: State<'_, Mutex<DocumentManager>>,
     doc_id: String,
     comment_id: i64,
-) -> Result<(), String> {
+    actor: Option<String>,
+) -> Result<(), KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
 
     let doc = manager
         .documents
         .get(&doc_id)
-        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
 
-    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&doc.history_path)?;
+    init_comments_table(&conn)?;
 
     // Restore this comment and its replies
     conn.execute(
         "UPDATE comments SET status = 'unresolved' WHERE id = ?1 OR parent_id = ?1",
         params![comment_id],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
+
+    record_comment_event(&conn, comment_id, "reopened", actor.as_deref())?;
+
+    Ok(())
+}
+
+/// Get the status audit trail for a document's comments
+/// Pass a comment_id to restrict the trail to a single comment
+#[tauri::command]
+pub fn list_comment_events(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    comment_id: Option<i64>,
+) -> Result<Vec<CommentEvent>, KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+
+    let doc = manager
+        .documents
+        .get(&doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+
+    let conn = Connection::open(&doc.history_path)?;
+    init_comments_table(&conn)?;
+
+    Ok(query_comment_events(&conn, comment_id)?)
+}
+
+/// Minimum similarity for a fuzzy match of the selected text
+const FUZZY_MATCH_THRESHOLD: f32 = 0.8;
+
+/// Outcome of re-anchoring one comment thread
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommentRebase {
+    pub comment_id: i64,
+    pub orphaned: bool,
+    /// Where the selected text was found (UTF-16, like the editor)
+    pub start: Option<usize>,
+    pub end: Option<usize>,
+    /// The text found, when it differs from the stored selection
+    pub matched_text: Option<String>,
+}
+
+/// Find the selected text of a comment in new content
+///
+/// Tries an exact match, then a match ignoring case and whitespace
+/// differences, then the most similar run of as many words. Returns the
+/// byte range of the match.
+pub fn locate_selected_text(content: &str, selected: &str) -> Option<(usize, usize)> {
+    let selected = selected.trim();
+    if selected.is_empty() {
+        return None;
+    }
+    if let Some(start) = content.find(selected) {
+        return Some((start, start + selected.len()));
+    }
+
+    let words: Vec<&str> = selected.split_whitespace().collect();
+    let pattern = words.iter().map(|w| regex::escape(w)).collect::<Vec<_>>().join(r"\s+");
+    if let Ok(re) = regex::Regex::new(&format!("(?i){}", pattern)) {
+        if let Some(m) = re.find(content) {
+            return Some((m.start(), m.end()));
+        }
+    }
+
+    // Byte ranges of the words of the content
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    let mut word_start: Option<usize> = None;
+    for (i, c) in content.char_indices() {
+        match (c.is_whitespace(), word_start) {
+            (true, Some(start)) => {
+                spans.push((start, i));
+                word_start = None;
+            }
+            (false, None) => word_start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(start) = word_start {
+        spans.push((start, content.len()));
+    }
+
+    let mut best: Option<(f32, usize, usize)> = None;
+    for window in spans.windows(words.len()) {
+        let (start, end) = (window[0].0, window[words.len() - 1].1);
+        let ratio = similar::TextDiff::from_chars(selected, &content[start..end]).ratio();
+        if ratio >= FUZZY_MATCH_THRESHOLD && best.is_none_or(|(r, _, _)| ratio > r) {
+            best = Some((ratio, start, end));
+        }
+    }
+    best.map(|(_, start, end)| (start, end))
+}
+
+/// Re-anchor every live comment thread against new content
+///
+/// Found threads get the matched text as their selection and empty anchors,
+/// so the editor falls back to text matching until it anchors them again;
+/// the rest are flagged as orphaned. Replies follow their parent.
+pub fn rebase_anchors(conn: &Connection, content: &str) -> Result<Vec<CommentRebase>, String> {
+    let threads: Vec<Comment> = load_all_comments(conn)?
+        .into_iter()
+        .filter(|c| c.parent_id.is_none() && c.status != "deleted")
+        .collect();
+
+    let mut results = Vec::new();
+    for comment in threads {
+        let result = match locate_selected_text(content, &comment.selected_text) {
+            Some((start, end)) => {
+                let matched = &content[start..end];
+                conn.execute(
+                    "UPDATE comments SET selected_text = ?2, start_anchor = '', end_anchor = '', orphaned = 0
+                     WHERE id = ?1 OR parent_id = ?1",
+                    params![comment.id, matched],
+                )
+                .map_err(|e| e.to_string())?;
+
+                CommentRebase {
+                    comment_id: comment.id,
+                    orphaned: false,
+                    start: Some(content[..start].encode_utf16().count()),
+                    end: Some(content[..end].encode_utf16().count()),
+                    matched_text: (matched != comment.selected_text).then(|| matched.to_string()),
+                }
+            }
+            None => {
+                conn.execute(
+                    "UPDATE comments SET orphaned = 1 WHERE id = ?1 OR parent_id = ?1",
+                    params![comment.id],
+                )
+                .map_err(|e| e.to_string())?;
+
+                CommentRebase {
+                    comment_id: comment.id,
+                    orphaned: true,
+                    start: None,
+                    end: None,
+                    matched_text: None,
+                }
+            }
+        };
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+/// Re-anchor a document's comments after its text was replaced
+/// Uses the given content, or the document's current text
+#[tauri::command]
+pub fn rebase_comment_anchors(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    content: Option<String>,
+) -> Result<Vec<CommentRebase>, KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+
+    let doc = manager
+        .documents
+        .get(&doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+
+    let content = match content {
+        Some(content) => content,
+        None => crate::document_manager::current_document_text(doc, false)?,
+    };
+
+    let conn = Connection::open(&doc.history_path)?;
+    init_comments_table(&conn)?;
+
+    Ok(rebase_anchors(&conn, &content)?)
+}
+
+/// Store fresh Yjs anchors for a comment thread
+#[tauri::command]
+pub fn update_comment_anchors(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    comment_id: i64,
+    start_anchor: String,
+    end_anchor: String,
+) -> Result<(), KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+
+    let doc = manager
+        .documents
+        .get(&doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+
+    let conn = Connection::open(&doc.history_path)?;
+    init_comments_table(&conn)?;
+
+    conn.execute(
+        "UPDATE comments SET start_anchor = ?2, end_anchor = ?3 WHERE id = ?1 OR parent_id = ?1",
+        params![comment_id, start_anchor, end_anchor],
+    )?;
 
     Ok(())
 }
@@ -523,4 +850,67 @@ mod tests {
             .unwrap();
         assert_eq!(count, 2);
     }
+
+    #[test]
+    fn test_comment_events_audit_trail() {
+        let conn = create_test_db();
+        let first = insert_test_comment(&conn, "Author1", "First");
+        let second = insert_test_comment(&conn, "Author1", "Second");
+
+        record_comment_event(&conn, first, "resolved", Some("reviewer-1")).unwrap();
+        record_comment_event(&conn, first, "reopened", None).unwrap();
+        record_comment_event(&conn, second, "deleted", Some("reviewer-2")).unwrap();
+
+        let all = query_comment_events(&conn, None).unwrap();
+        assert_eq!(all.len(), 3);
+
+        let trail = query_comment_events(&conn, Some(first)).unwrap();
+        assert_eq!(trail.len(), 2);
+        assert_eq!(trail[0].event, "resolved");
+        assert_eq!(trail[0].actor.as_deref(), Some("reviewer-1"));
+        assert_eq!(trail[1].event, "reopened");
+        assert!(trail[1].actor.is_none());
+    }
+
+    #[test]
+    fn test_comment_event_rejects_unknown_event() {
+        let conn = create_test_db();
+        let id = insert_test_comment(&conn, "Author1", "Comment");
+
+        assert!(record_comment_event(&conn, id, "archived", None).is_err());
+    }
+
+    #[test]
+    fn test_rebase_anchors() {
+        let conn = create_test_db();
+        let kept = insert_test_comment(&conn, "Author1", "Kept");
+        let gone = insert_test_comment(&conn, "Author1", "Gone");
+        conn.execute("UPDATE comments SET selected_text = 'quick  Brown fox' WHERE id = ?1", params![kept]).unwrap();
+        conn.execute("UPDATE comments SET selected_text = 'lazy dog' WHERE id = ?1", params![gone]).unwrap();
+        conn.execute(
+            "INSERT INTO comments (timestamp, author, start_anchor, end_anchor, selected_text, content, parent_id) VALUES (1, 'Author2', 'a', 'b', 'lazy dog', 'Reply', ?1)",
+            params![gone],
+        ).unwrap();
+
+        let content = "The quick brown fox jumps over the sleeping cat.";
+        let results = rebase_anchors(&conn, content).unwrap();
+        assert_eq!(results.len(), 2);
+
+        let found = results.iter().find(|r| r.comment_id == kept).unwrap();
+        assert!(!found.orphaned);
+        assert_eq!(found.start, Some(4));
+        assert_eq!(found.matched_text.as_deref(), Some("quick brown fox"));
+
+        let comments = load_all_comments(&conn).unwrap();
+        let stored = comments.iter().find(|c| c.id == kept).unwrap();
+        assert_eq!(stored.selected_text, "quick brown fox");
+        assert!(stored.start_anchor.is_empty());
+
+        // The orphaned thread keeps its text; replies share the state
+        assert!(results.iter().find(|r| r.comment_id == gone).unwrap().orphaned);
+        assert!(comments.iter().filter(|c| c.selected_text == "lazy dog").all(|c| c.orphaned));
+
+        // A small edit still matches
+        assert_eq!(locate_selected_text("The quick browne fox jumps", "quick brown fox"), Some((4, 20)));
+    }
 }
@@ -1,7 +1,9 @@
 use tauri::AppHandle;
-use crate::models::{Conflict, ResolutionInput};
+use crate::models::{Conflict, ConflictResolution, ResolutionInput};
 use crate::conflict_detector::ConflictDetector;
 use crate::conflict_store;
+use crate::events::{emit_event, ConflictDetectedEvent, CONFLICT_DETECTED};
+use crate::hunk_calculator::{merge_three_way, ThreeWayMerge};
 use crate::patch_log;
 
 /// Scan patches and detect new conflicts
@@ -16,9 +18,20 @@ pub fn detect_conflicts(app: AppHandle) -> Result<Vec<Conflict>, String> {
 
     // Store new conflicts
     let conn = conflict_store::init_db(&app)?;
+    let mut new_ids = Vec::new();
     for conflict in &conflicts {
-        conflict_store::store_conflict(&conn, conflict)?;
+        if conflict_store::store_conflict(&conn, conflict)? {
+            new_ids.push(conflict.id.clone());
+        }
     }
+    if !new_ids.is_empty() {
+        emit_event(&app, CONFLICT_DETECTED, ConflictDetectedEvent { doc_id: None, conflict_ids: new_ids });
+    }
+
+    crate::session_log::log_event(&app, "detect_conflicts", None, serde_json::json!({
+        "patches": patches.len(),
+        "conflicts": conflicts.len(),
+    }));
 
     Ok(conflicts)
 }
@@ -37,7 +50,45 @@ pub fn resolve_conflict(
     resolution: ResolutionInput,
 ) -> Result<(), String> {
     let conn = conflict_store::init_db(&app)?;
-    conflict_store::resolve_conflict(&conn, &resolution)
+    let author = crate::profile::load_saved_profile()
+        .ok()
+        .flatten()
+        .map(|p| p.name)
+        .unwrap_or_else(|| "Unknown".to_string());
+    conflict_store::resolve_conflict(&conn, &resolution, &author)
+}
+
+/// Merge the two sides of a conflict word by word against its base
+///
+/// Non-overlapping changes are combined; words both sides changed
+/// differently are left as collision markers in `merged` for manual editing.
+#[tauri::command]
+pub fn auto_merge_conflict(app: AppHandle, conflict_id: String) -> Result<ThreeWayMerge, String> {
+    let conn = conflict_store::init_db(&app)?;
+    let conflict = conflict_store::get_conflict(&conn, &conflict_id)?
+        .ok_or_else(|| format!("Conflict not found: {}", conflict_id))?;
+    Ok(merge_three_way(
+        &conflict.base_version.content,
+        &conflict.local_version.content,
+        &conflict.remote_version.content,
+    ))
+}
+
+/// Undo a resolution, returning the conflict to the unresolved list
+#[tauri::command]
+pub fn reopen_conflict(app: AppHandle, conflict_id: String) -> Result<(), String> {
+    let conn = conflict_store::init_db(&app)?;
+    conflict_store::reopen_conflict(&conn, &conflict_id)
+}
+
+/// Get past resolutions, for one conflict or all of them
+#[tauri::command]
+pub fn list_resolution_history(
+    app: AppHandle,
+    conflict_id: Option<String>,
+) -> Result<Vec<ConflictResolution>, String> {
+    let conn = conflict_store::init_db(&app)?;
+    conflict_store::list_resolution_history(&conn, conflict_id.as_deref())
 }
 
 /// Get conflict count (for UI badge)
@@ -1,5 +1,5 @@
-use rusqlite::{params, Connection};
-use crate::models::{Conflict, ConflictStatus, ResolutionInput};
+use rusqlite::{params, Connection, OptionalExtension};
+use crate::models::{Conflict, ConflictResolution, ConflictStatus, ResolutionInput};
 use tauri::{AppHandle, Manager};
 use std::path::PathBuf;
 
@@ -14,7 +14,11 @@ fn db_path(app: &AppHandle) -> Result<PathBuf, String> {
 pub fn init_db(app: &AppHandle) -> Result<Connection, String> {
     let path = db_path(app)?;
     let conn = Connection::open(path).map_err(|e| e.to_string())?;
+    ensure_tables(&conn)?;
+    Ok(conn)
+}
 
+fn ensure_tables(conn: &Connection) -> Result<(), String> {
     // Using conflicts_v2 to ensure schema compatibility
     conn.execute_batch(
         r#"
@@ -47,14 +51,30 @@ pub fn init_db(app: &AppHandle) -> Result<Connection, String> {
 
         CREATE INDEX IF NOT EXISTS idx_conflicts_v2_status
         ON conflicts_v2(status);
+
+        -- Audit trail: one row per resolution, kept when a conflict is reopened
+        CREATE TABLE IF NOT EXISTS conflict_resolutions (
+            id              INTEGER PRIMARY KEY AUTOINCREMENT,
+            conflict_id     TEXT NOT NULL,
+            strategy        TEXT NOT NULL,
+            author          TEXT NOT NULL,
+            merged_content  TEXT,
+            patch_uuid      TEXT,
+            resolved_at     INTEGER NOT NULL,
+            reopened_at     INTEGER
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_conflict_resolutions_conflict
+        ON conflict_resolutions(conflict_id);
         "#,
     ).map_err(|e| e.to_string())?;
 
-    Ok(conn)
+    Ok(())
 }
 
-pub fn store_conflict(conn: &Connection, conflict: &Conflict) -> Result<(), String> {
-    conn.execute(
+/// Store a conflict, returning whether it was new
+pub fn store_conflict(conn: &Connection, conflict: &Conflict) -> Result<bool, String> {
+    let inserted = conn.execute(
         r#"
         INSERT OR IGNORE INTO conflicts_v2
         (id, conflict_type, base_content,
@@ -89,55 +109,57 @@ pub fn store_conflict(conn: &Connection, conflict: &Conflict) -> Result<(), Stri
         ],
     ).map_err(|e| e.to_string())?;
 
-    Ok(())
+    Ok(inserted > 0)
+}
+
+const CONFLICT_COLUMNS: &str = r#"
+    id, conflict_type, base_content,
+    local_content, local_author, local_start, local_end, local_ts,
+    remote_content, remote_author, remote_start, remote_end, remote_ts,
+    base_start, base_end,
+    detected_at, status
+"#;
+
+fn row_to_conflict(row: &rusqlite::Row) -> rusqlite::Result<Conflict> {
+    Ok(Conflict {
+        id: row.get(0)?,
+        conflict_type: parse_conflict_type(row.get::<_, String>(1)?),
+        base_version: crate::models::TextSpan {
+            start: row.get(13)?,
+            end: row.get(14)?,
+            content: row.get(2)?,
+            author: "base".to_string(),
+            timestamp: 0,
+        },
+        local_version: crate::models::TextSpan {
+            start: row.get(5)?,
+            end: row.get(6)?,
+            content: row.get(3)?,
+            author: row.get(4)?,
+            timestamp: row.get(7)?,
+        },
+        remote_version: crate::models::TextSpan {
+            start: row.get(10)?,
+            end: row.get(11)?,
+            content: row.get(8)?,
+            author: row.get(9)?,
+            timestamp: row.get(12)?,
+        },
+        status: parse_conflict_status(row.get::<_, String>(16)?),
+        detected_at: row.get(15)?,
+    })
 }
 
 pub fn get_unresolved_conflicts(conn: &Connection) -> Result<Vec<Conflict>, String> {
     let mut stmt = conn
-        .prepare(
-            r#"
-            SELECT id, conflict_type, base_content,
-                   local_content, local_author, local_start, local_end, local_ts,
-                   remote_content, remote_author, remote_start, remote_end, remote_ts,
-                   base_start, base_end,
-                   detected_at
-            FROM conflicts_v2
-            WHERE status = 'Unresolved'
-            ORDER BY detected_at DESC
-            "#
-        )
+        .prepare(&format!(
+            "SELECT {} FROM conflicts_v2 WHERE status = 'Unresolved' ORDER BY detected_at DESC",
+            CONFLICT_COLUMNS
+        ))
         .map_err(|e| e.to_string())?;
 
     let conflicts = stmt
-        .query_map([], |row| {
-            Ok(Conflict {
-                id: row.get(0)?,
-                conflict_type: parse_conflict_type(row.get::<_, String>(1)?),
-                base_version: crate::models::TextSpan {
-                    start: row.get(13)?,
-                    end: row.get(14)?,
-                    content: row.get(2)?,
-                    author: "base".to_string(),
-                    timestamp: 0,
-                },
-                local_version: crate::models::TextSpan {
-                    start: row.get(5)?,
-                    end: row.get(6)?,
-                    content: row.get(3)?,
-                    author: row.get(4)?,
-                    timestamp: row.get(7)?,
-                },
-                remote_version: crate::models::TextSpan {
-                    start: row.get(10)?,
-                    end: row.get(11)?,
-                    content: row.get(8)?,
-                    author: row.get(9)?,
-                    timestamp: row.get(12)?,
-                },
-                status: ConflictStatus::Unresolved,
-                detected_at: row.get(15)?,
-            })
-        })
+        .query_map([], row_to_conflict)
         .map_err(|e| e.to_string())?
         .collect::<Result<Vec<_>, _>>()
         .map_err(|e| e.to_string())?;
@@ -145,9 +167,21 @@ pub fn get_unresolved_conflicts(conn: &Connection) -> Result<Vec<Conflict>, Stri
     Ok(conflicts)
 }
 
+/// Get a conflict by ID, whatever its status
+pub fn get_conflict(conn: &Connection, conflict_id: &str) -> Result<Option<Conflict>, String> {
+    conn.query_row(
+        &format!("SELECT {} FROM conflicts_v2 WHERE id = ?1", CONFLICT_COLUMNS),
+        params![conflict_id],
+        row_to_conflict,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
 pub fn resolve_conflict(
     conn: &Connection,
     resolution: &ResolutionInput,
+    author: &str,
 ) -> Result<(), String> {
     let now = chrono::Utc::now().timestamp_millis();
 
@@ -165,9 +199,102 @@ pub fn resolve_conflict(
         ],
     ).map_err(|e| e.to_string())?;
 
+    conn.execute(
+        r#"
+        INSERT INTO conflict_resolutions
+        (conflict_id, strategy, author, merged_content, patch_uuid, resolved_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        "#,
+        params![
+            resolution.conflict_id,
+            format!("{:?}", resolution.resolution),
+            author,
+            resolution.merged_content,
+            resolution.patch_uuid,
+            now,
+        ],
+    ).map_err(|e| e.to_string())?;
+
     Ok(())
 }
 
+/// Mark a resolved conflict as unresolved again
+///
+/// The previous resolution stays in the history, stamped with the time it
+/// was reopened.
+pub fn reopen_conflict(conn: &Connection, conflict_id: &str) -> Result<(), String> {
+    let now = chrono::Utc::now().timestamp_millis();
+
+    let updated = conn.execute(
+        r#"
+        UPDATE conflicts_v2
+        SET status = 'Unresolved', resolved_content = NULL, resolved_at = NULL
+        WHERE id = ?1 AND status != 'Unresolved'
+        "#,
+        params![conflict_id],
+    ).map_err(|e| e.to_string())?;
+
+    if updated == 0 {
+        return Err(format!("No resolved conflict with id {}", conflict_id));
+    }
+
+    conn.execute(
+        r#"
+        UPDATE conflict_resolutions SET reopened_at = ?1
+        WHERE id = (SELECT MAX(id) FROM conflict_resolutions WHERE conflict_id = ?2)
+        "#,
+        params![now, conflict_id],
+    ).map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Resolutions recorded for one conflict, or for all conflicts, newest first
+pub fn list_resolution_history(
+    conn: &Connection,
+    conflict_id: Option<&str>,
+) -> Result<Vec<ConflictResolution>, String> {
+    let mut stmt = conn
+        .prepare(
+            r#"
+            SELECT id, conflict_id, strategy, author, merged_content, patch_uuid, resolved_at, reopened_at
+            FROM conflict_resolutions
+            WHERE ?1 IS NULL OR conflict_id = ?1
+            ORDER BY resolved_at DESC, id DESC
+            "#
+        )
+        .map_err(|e| e.to_string())?;
+
+    let resolutions = stmt
+        .query_map(params![conflict_id], |row| {
+            Ok(ConflictResolution {
+                id: row.get(0)?,
+                conflict_id: row.get(1)?,
+                strategy: parse_conflict_status(row.get::<_, String>(2)?),
+                author: row.get(3)?,
+                merged_content: row.get(4)?,
+                patch_uuid: row.get(5)?,
+                resolved_at: row.get(6)?,
+                reopened_at: row.get(7)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(resolutions)
+}
+
+fn parse_conflict_status(s: String) -> ConflictStatus {
+    match s.as_str() {
+        "ResolvedLocal" => ConflictStatus::ResolvedLocal,
+        "ResolvedRemote" => ConflictStatus::ResolvedRemote,
+        "ResolvedMerged" => ConflictStatus::ResolvedMerged,
+        "ResolvedBoth" => ConflictStatus::ResolvedBoth,
+        _ => ConflictStatus::Unresolved,
+    }
+}
+
 fn parse_conflict_type(s: String) -> crate::models::ConflictType {
     match s.as_str() {
         "OverlappingEdit" => crate::models::ConflictType::OverlappingEdit,
@@ -185,31 +312,7 @@ mod tests {
 
     fn create_test_db() -> Connection {
         let conn = Connection::open_in_memory().unwrap();
-        conn.execute_batch(
-            r#"
-            CREATE TABLE IF NOT EXISTS conflicts_v2 (
-                id              TEXT PRIMARY KEY,
-                conflict_type   TEXT NOT NULL,
-                base_content    TEXT NOT NULL,
-                local_content   TEXT NOT NULL,
-                local_author    TEXT NOT NULL,
-                local_start     INTEGER NOT NULL,
-                local_end       INTEGER NOT NULL,
-                local_ts        INTEGER NOT NULL,
-                remote_content  TEXT NOT NULL,
-                remote_author   TEXT NOT NULL,
-                remote_start    INTEGER NOT NULL,
-                remote_end      INTEGER NOT NULL,
-                remote_ts       INTEGER NOT NULL,
-                base_start      INTEGER NOT NULL,
-                base_end        INTEGER NOT NULL,
-                status          TEXT NOT NULL DEFAULT 'Unresolved',
-                resolved_content TEXT,
-                detected_at     INTEGER NOT NULL,
-                resolved_at     INTEGER
-            );
-            "#,
-        ).unwrap();
+        ensure_tables(&conn).unwrap();
         conn
     }
 
@@ -282,15 +385,48 @@ mod tests {
             conflict_id: "test-3".to_string(),
             resolution: ConflictStatus::ResolvedLocal,
             merged_content: Some("resolved content".to_string()),
+            patch_uuid: None,
         };
         
-        resolve_conflict(&conn, &resolution).unwrap();
+        resolve_conflict(&conn, &resolution, "Alice").unwrap();
         
         // Should no longer be unresolved
         let unresolved = get_unresolved_conflicts(&conn).unwrap();
         assert_eq!(unresolved.len(), 0);
     }
 
+    #[test]
+    fn test_reopen_conflict_keeps_history() {
+        let conn = create_test_db();
+        store_conflict(&conn, &create_test_conflict("test-4")).unwrap();
+
+        // Only resolved conflicts can be reopened
+        assert!(reopen_conflict(&conn, "test-4").is_err());
+
+        let resolution = ResolutionInput {
+            conflict_id: "test-4".to_string(),
+            resolution: ConflictStatus::ResolvedMerged,
+            merged_content: Some("merged".to_string()),
+            patch_uuid: Some("patch-1".to_string()),
+        };
+        resolve_conflict(&conn, &resolution, "Alice").unwrap();
+        reopen_conflict(&conn, "test-4").unwrap();
+
+        let unresolved = get_unresolved_conflicts(&conn).unwrap();
+        assert_eq!(unresolved.len(), 1);
+
+        let history = list_resolution_history(&conn, Some("test-4")).unwrap();
+        assert_eq!(history.len(), 1);
+        assert!(matches!(history[0].strategy, ConflictStatus::ResolvedMerged));
+        assert_eq!(history[0].author, "Alice");
+        assert_eq!(history[0].merged_content.as_deref(), Some("merged"));
+        assert_eq!(history[0].patch_uuid.as_deref(), Some("patch-1"));
+        assert!(history[0].reopened_at.is_some());
+
+        assert!(list_resolution_history(&conn, Some("other")).unwrap().is_empty());
+        assert_eq!(list_resolution_history(&conn, None).unwrap().len(), 1);
+    }
+
     #[test]
     fn test_parse_conflict_type() {
         assert!(matches!(parse_conflict_type("OverlappingEdit".to_string()), ConflictType::OverlappingEdit));
@@ -305,10 +441,10 @@ mod tests {
         let conn = create_test_db();
         
         let conflict = create_test_conflict("dup-1");
-        store_conflict(&conn, &conflict).unwrap();
+        assert!(store_conflict(&conn, &conflict).unwrap());
         
         // Insert again - should be ignored (INSERT OR IGNORE)
-        store_conflict(&conn, &conflict).unwrap();
+        assert!(!store_conflict(&conn, &conflict).unwrap());
         
         let count: i32 = conn
             .query_row("SELECT COUNT(*) FROM conflicts_v2", [], |r| r.get(0))
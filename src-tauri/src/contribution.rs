@@ -0,0 +1,293 @@
+// src-tauri/src/contribution.rs
+//! Per-author contribution report.
+//!
+//! Counts, for every author in a document's history, the patches they
+//! recorded, the words their Save snapshots added and deleted relative to
+//! the previous Save, the reviews they made and the comments they wrote.
+//! The report can be written as Markdown or CSV, e.g. as the starting
+//! point of a CRediT author contribution statement.
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::comments::{init_comments_table, load_all_comments};
+use crate::db_utils::ensure_schema;
+use crate::document_manager::{author_display_name, DocumentManager};
+use crate::history_rewrite::load_patches;
+use crate::hunk_calculator::calculate_hunks;
+use crate::kmd::DocumentMeta;
+
+/// Contribution counts of one author
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct AuthorContribution {
+    pub author: String,
+    pub author_name: String,
+    /// Patches of any kind
+    pub patches: usize,
+    pub saves: usize,
+    pub words_added: usize,
+    pub words_deleted: usize,
+    pub reviews_accepted: usize,
+    pub reviews_rejected: usize,
+    pub comments: usize,
+    pub replies: usize,
+    /// Timestamps (ms) of the first and last recorded activity
+    pub first_activity: Option<i64>,
+    pub last_activity: Option<i64>,
+}
+
+impl AuthorContribution {
+    fn touch(&mut self, timestamp: i64) {
+        self.first_activity = Some(self.first_activity.map_or(timestamp, |t| t.min(timestamp)));
+        self.last_activity = Some(self.last_activity.map_or(timestamp, |t| t.max(timestamp)));
+    }
+}
+
+/// Contribution report of a document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContributionReport {
+    pub title: String,
+    pub generated_at: String,
+    /// Authors ordered by words added, most first
+    pub authors: Vec<AuthorContribution>,
+}
+
+pub(crate) fn count_words(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+/// Counts of an author, keyed by author ID
+fn entry<'a>(
+    authors: &'a mut BTreeMap<String, AuthorContribution>,
+    meta: &DocumentMeta,
+    author: &str,
+) -> &'a mut AuthorContribution {
+    // Comments record the author's display name; map it back to the ID
+    let id = meta
+        .authors
+        .iter()
+        .find(|a| a.id == author || a.name == author)
+        .map(|a| a.id.clone())
+        .unwrap_or_else(|| author.to_string());
+    authors.entry(id.clone()).or_insert_with(|| AuthorContribution {
+        author_name: author_display_name(meta, &id),
+        author: id,
+        ..Default::default()
+    })
+}
+
+/// Build the report from a document's history database
+pub fn build_report(conn: &Connection, meta: &DocumentMeta) -> Result<ContributionReport, String> {
+    let mut authors: BTreeMap<String, AuthorContribution> = BTreeMap::new();
+
+    let mut previous = String::new();
+    for patch in load_patches(conn)? {
+        let stats = entry(&mut authors, meta, &patch.author);
+        stats.patches += 1;
+        stats.touch(patch.timestamp);
+
+        if patch.kind != "Save" {
+            continue;
+        }
+        stats.saves += 1;
+        if let Some(snapshot) = patch.data.get("snapshot").and_then(|s| s.as_str()) {
+            for hunk in calculate_hunks(&previous, snapshot) {
+                stats.words_added += count_words(&hunk.modified_text);
+                stats.words_deleted += count_words(&hunk.base_text);
+            }
+            previous = snapshot.to_string();
+        }
+    }
+
+    let mut stmt = conn
+        .prepare("SELECT reviewer_id, decision, reviewed_at FROM patch_reviews")
+        .map_err(|e| e.to_string())?;
+    let reviews = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    for (reviewer, decision, reviewed_at) in reviews {
+        let stats = entry(&mut authors, meta, &reviewer);
+        match decision.as_str() {
+            "accepted" => stats.reviews_accepted += 1,
+            "rejected" => stats.reviews_rejected += 1,
+            _ => {}
+        }
+        stats.touch(reviewed_at);
+    }
+
+    init_comments_table(conn)?;
+    for comment in load_all_comments(conn)? {
+        if comment.status == "deleted" {
+            continue;
+        }
+        let stats = entry(&mut authors, meta, &comment.author);
+        if comment.parent_id.is_some() {
+            stats.replies += 1;
+        } else {
+            stats.comments += 1;
+        }
+        stats.touch(comment.timestamp);
+    }
+
+    let mut authors: Vec<AuthorContribution> = authors.into_values().collect();
+    authors.sort_by(|a, b| b.words_added.cmp(&a.words_added).then_with(|| a.author_name.cmp(&b.author_name)));
+
+    Ok(ContributionReport {
+        title: meta.title.clone(),
+        generated_at: chrono::Utc::now().to_rfc3339(),
+        authors,
+    })
+}
+
+const COLUMNS: [&str; 9] = [
+    "Author", "Patches", "Saves", "Words added", "Words deleted",
+    "Accepted", "Rejected", "Comments", "Replies",
+];
+
+fn row(author: &AuthorContribution) -> [String; 9] {
+    [
+        author.author_name.clone(),
+        author.patches.to_string(),
+        author.saves.to_string(),
+        author.words_added.to_string(),
+        author.words_deleted.to_string(),
+        author.reviews_accepted.to_string(),
+        author.reviews_rejected.to_string(),
+        author.comments.to_string(),
+        author.replies.to_string(),
+    ]
+}
+
+/// Render the report as a Markdown table
+pub fn render_markdown(report: &ContributionReport) -> String {
+    let mut out = format!("# Contributions to {}\n\n", report.title);
+    out.push_str(&format!("Generated {}\n\n", report.generated_at));
+    out.push_str(&format!("| {} |\n", COLUMNS.join(" | ")));
+    out.push_str(&format!("|{}\n", "---|".repeat(COLUMNS.len())));
+    for author in &report.authors {
+        let cells: Vec<String> = row(author).iter().map(|c| c.replace('|', "\\|")).collect();
+        out.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+    out
+}
+
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Render the report as CSV, one row per author
+pub fn render_csv(report: &ContributionReport) -> String {
+    let mut out = format!("{}\n", COLUMNS.join(","));
+    for author in &report.authors {
+        let cells: Vec<String> = row(author).iter().map(|c| csv_field(c)).collect();
+        out.push_str(&format!("{}\n", cells.join(",")));
+    }
+    out
+}
+
+fn document_report(manager: &DocumentManager, doc_id: &str) -> Result<ContributionReport, String> {
+    let doc = manager
+        .documents
+        .get(doc_id)
+        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+
+    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    ensure_schema(&conn)?;
+    build_report(&conn, &doc.meta)
+}
+
+/// Compute per-author contribution statistics for a document
+#[tauri::command]
+pub fn generate_contribution_report(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+) -> Result<ContributionReport, String> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    document_report(&manager, &doc_id)
+}
+
+/// Write a document's contribution report as "markdown" or "csv"
+#[tauri::command]
+pub fn export_contribution_report(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    path: String,
+    format: String,
+) -> Result<(), String> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let report = document_report(&manager, &doc_id)?;
+
+    let content = match format.as_str() {
+        "markdown" | "md" => render_markdown(&report),
+        "csv" => render_csv(&report),
+        _ => return Err(format!("Unsupported report format: {}", format)),
+    };
+    std::fs::write(Path::new(&path), content).map_err(|e| format!("Failed to write report: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+
+    #[test]
+    fn test_build_report() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+        init_comments_table(&conn).unwrap();
+
+        for (author, text) in [("alice", "One two three"), ("bob", "One two, three four five")] {
+            conn.execute(
+                "INSERT INTO patches (timestamp, author, kind, data) VALUES (1, ?1, 'Save', ?2)",
+                params![author, serde_json::json!({ "snapshot": text }).to_string()],
+            ).unwrap();
+        }
+        conn.execute(
+            "INSERT INTO patch_reviews (patch_uuid, reviewer_id, decision, reviewed_at) VALUES ('p2', 'alice', 'accepted', 5)",
+            [],
+        ).unwrap();
+        conn.execute(
+            "INSERT INTO comments (timestamp, author, start_anchor, end_anchor, selected_text, content) VALUES (3, 'Bob', 'a', 'b', 'five', 'Why?')",
+            [],
+        ).unwrap();
+
+        let mut meta = DocumentMeta { title: "Paper".to_string(), ..Default::default() };
+        meta.authors.push(crate::kmd::AuthorRef {
+            id: "bob".to_string(),
+            name: "Bob".to_string(),
+            email: None,
+            joined_at: None,
+            role: None,
+        });
+
+        let report = build_report(&conn, &meta).unwrap();
+        assert_eq!(report.authors.len(), 2);
+
+        let alice = report.authors.iter().find(|a| a.author == "alice").unwrap();
+        assert_eq!(alice.words_added, 3);
+        assert_eq!(alice.reviews_accepted, 1);
+        assert_eq!(alice.last_activity, Some(5));
+
+        // The comment by display name is credited to the author ID
+        let bob = report.authors.iter().find(|a| a.author == "bob").unwrap();
+        assert_eq!(bob.author_name, "Bob");
+        assert_eq!(bob.saves, 1);
+        assert!(bob.words_added >= 2);
+        assert_eq!(bob.comments, 1);
+
+        let csv = render_csv(&report);
+        assert!(csv.starts_with("Author,Patches"));
+        assert_eq!(csv.lines().count(), 3);
+        assert!(render_markdown(&report).contains("| Bob | 1 | 1 |"));
+    }
+}
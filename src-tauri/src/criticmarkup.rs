@@ -0,0 +1,210 @@
+// src-tauri/src/criticmarkup.rs
+//! CriticMarkup as an interchange format for tracked changes.
+//!
+//! Markdown files are often reviewed outside Korppi with CriticMarkup:
+//! `{++added++}`, `{--deleted--}`, `{~~old~>new~~}`, `{==highlighted==}` and
+//! `{>>comment<<}`. Importing such a file keeps the text without the changes
+//! as the document, stores the changes as a suggestion against it and the
+//! comments as Korppi comments, the way HTML imports store `<ins>`/`<del>`.
+//! `export_diff_criticmarkup` writes the difference between two patches the
+//! other way round, as markdown with CriticMarkup changes.
+
+use regex::Regex;
+use rusqlite::Connection;
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::db_utils::ensure_schema;
+use crate::document_manager::{snapshot_at_patch, DocumentManager};
+use crate::error::KorppiError;
+use crate::html_import::{HtmlImport, ImportedComment};
+use crate::hunk_calculator::{calculate_hunks_with, DiffOptions, Hunk};
+use crate::kmd::write_text_file;
+
+/// Any CriticMarkup element; groups: addition, deletion, substitution
+/// (old, new), highlight, comment
+const CRITIC_PATTERN: &str = r"(?s)\{\+\+(.*?)\+\+\}|\{--(.*?)--\}|\{~~(.*?)~>(.*?)~~\}|\{==(.*?)==\}|\{>>(.*?)<<\}";
+
+/// Last word of a text, which a comment without a highlight is attached to
+fn last_word(text: &str) -> String {
+    text.split_whitespace().last().unwrap_or_default().to_string()
+}
+
+/// Split markdown with CriticMarkup into the original text, the text with
+/// the changes applied and the comments
+///
+/// A comment is attached to the highlight right before it, or else to the
+/// word before it. `None` when the markdown has no CriticMarkup.
+pub fn parse_criticmarkup(markdown: &str) -> Option<HtmlImport> {
+    let re = Regex::new(CRITIC_PATTERN).unwrap();
+    if !re.is_match(markdown) {
+        return None;
+    }
+
+    let mut original = String::with_capacity(markdown.len());
+    let mut suggested = String::with_capacity(markdown.len());
+    let mut comments = Vec::new();
+    let mut has_changes = false;
+    // Highlighted text the next comment belongs to
+    let mut highlight: Option<String> = None;
+    let mut cursor = 0;
+
+    for caps in re.captures_iter(markdown) {
+        let whole = caps.get(0).unwrap();
+        let between = &markdown[cursor..whole.start()];
+        if !between.is_empty() {
+            highlight = None;
+        }
+        original.push_str(between);
+        suggested.push_str(between);
+        cursor = whole.end();
+
+        if let Some(added) = caps.get(1) {
+            suggested.push_str(added.as_str());
+            has_changes = true;
+            highlight = None;
+        } else if let Some(deleted) = caps.get(2) {
+            original.push_str(deleted.as_str());
+            has_changes = true;
+            highlight = None;
+        } else if let (Some(old), Some(new)) = (caps.get(3), caps.get(4)) {
+            original.push_str(old.as_str());
+            suggested.push_str(new.as_str());
+            has_changes = true;
+            highlight = None;
+        } else if let Some(highlighted) = caps.get(5) {
+            original.push_str(highlighted.as_str());
+            suggested.push_str(highlighted.as_str());
+            highlight = Some(highlighted.as_str().to_string());
+        } else if let Some(comment) = caps.get(6) {
+            let content = comment.as_str().trim();
+            if !content.is_empty() {
+                comments.push(ImportedComment {
+                    selected_text: highlight.clone().unwrap_or_else(|| last_word(&original)),
+                    content: content.to_string(),
+                    replies: Vec::new(),
+                });
+            }
+        }
+    }
+    original.push_str(&markdown[cursor..]);
+    suggested.push_str(&markdown[cursor..]);
+
+    Some(HtmlImport {
+        suggested: (has_changes && suggested != original).then_some(suggested),
+        markdown: original,
+        comments,
+    })
+}
+
+/// Render the changes from `base` described by `hunks` as CriticMarkup
+pub fn render_criticmarkup(base: &str, hunks: &[Hunk]) -> String {
+    let mut ordered: Vec<&Hunk> = hunks.iter().collect();
+    ordered.sort_by_key(|h| h.base_start_byte);
+
+    let mut result = String::with_capacity(base.len());
+    let mut cursor = 0;
+    for hunk in ordered {
+        if hunk.base_start_byte < cursor || hunk.base_end_byte > base.len() {
+            continue;
+        }
+        result.push_str(&base[cursor..hunk.base_start_byte]);
+        let old = &base[hunk.base_start_byte..hunk.base_end_byte];
+        let new = hunk.modified_text.as_str();
+        match (old.is_empty(), new.is_empty()) {
+            (true, true) => {}
+            (true, false) => result.push_str(&format!("{{++{}++}}", new)),
+            (false, true) => result.push_str(&format!("{{--{}--}}", old)),
+            (false, false) => result.push_str(&format!("{{~~{}~>{}~~}}", old, new)),
+        }
+        cursor = hunk.base_end_byte;
+    }
+    result.push_str(&base[cursor..]);
+    result
+}
+
+/// Markdown of the text of patch `patch_b` with its changes since patch
+/// `patch_a` written as CriticMarkup
+pub fn criticmarkup_between(conn: &Connection, patch_a: i64, patch_b: i64, options: &DiffOptions) -> Result<String, String> {
+    let base = snapshot_at_patch(conn, patch_a)?;
+    let modified = snapshot_at_patch(conn, patch_b)?;
+    Ok(render_criticmarkup(&base, &calculate_hunks_with(&base, &modified, options)))
+}
+
+/// Write the changes between two patches of a document as markdown with CriticMarkup
+#[tauri::command]
+pub fn export_diff_criticmarkup(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    patch_a: i64,
+    patch_b: i64,
+    path: String,
+) -> Result<(), KorppiError> {
+    let (history_path, options) = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+        (doc.history_path.clone(), doc.meta.settings.diff.clone())
+    };
+    let conn = Connection::open(&history_path)?;
+    ensure_schema(&conn)?;
+    let content = criticmarkup_between(&conn, patch_a, patch_b, &options)?;
+    write_text_file(path, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hunk_calculator::{apply_hunks, calculate_hunks};
+    use rusqlite::params;
+
+    #[test]
+    fn test_parse_criticmarkup() {
+        assert!(parse_criticmarkup("No markup {+ here +}.").is_none());
+
+        let markdown = "The {~~quick~>slow~~} fox {++really ++}jumps{-- high--}.\n\n\
+                        A {==bold claim==}{>>Source?<<}{>>Agreed<<} and more{>>Why?<<}.";
+        let import = parse_criticmarkup(markdown).unwrap();
+        assert_eq!(import.markdown, "The quick fox jumps high.\n\nA bold claim and more.");
+        assert_eq!(import.suggested.as_deref(), Some("The slow fox really jumps.\n\nA bold claim and more."));
+        let anchors: Vec<(&str, &str)> = import
+            .comments
+            .iter()
+            .map(|c| (c.selected_text.as_str(), c.content.as_str()))
+            .collect();
+        assert_eq!(anchors, vec![("bold claim", "Source?"), ("bold claim", "Agreed"), ("more", "Why?")]);
+
+        // Comments alone make no suggestion
+        let import = parse_criticmarkup("Text{>>Note<<}").unwrap();
+        assert!(import.suggested.is_none());
+    }
+
+    #[test]
+    fn test_criticmarkup_between_patches() {
+        let base = "The quick brown fox jumps over the lazy dog.\n\nNothing changes here.\n\nOld ending.";
+        let modified = "The slow brown fox jumps over the lazy dog.\n\nNothing changes here.";
+        let hunks = calculate_hunks(base, modified);
+        let rendered = render_criticmarkup(base, &hunks);
+        assert!(rendered.starts_with("The {~~quick~>slow~~} brown fox"), "{}", rendered);
+        assert!(rendered.contains("{--"), "{}", rendered);
+
+        // Reading the rendered diff back gives both texts
+        let import = parse_criticmarkup(&rendered).unwrap();
+        assert_eq!(import.markdown, base);
+        assert_eq!(import.suggested.as_deref(), Some(modified));
+        assert_eq!(apply_hunks(base, &hunks), modified);
+
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+        for (uuid, snapshot) in [("p1", base), ("p2", modified)] {
+            conn.execute(
+                "INSERT INTO patches (timestamp, author, kind, data, uuid) VALUES (1, 'alice', 'Save', ?1, ?2)",
+                params![serde_json::json!({ "snapshot": snapshot }).to_string(), uuid],
+            )
+            .unwrap();
+        }
+        assert_eq!(criticmarkup_between(&conn, 1, 2, &DiffOptions::default()).unwrap(), rendered);
+    }
+}
@@ -1,16 +1,85 @@
 // src-tauri/src/db_utils.rs
+//! Schema of the history database (history.sqlite).
+//!
+//! The schema is built by an ordered list of migrations, and the
+//! `schema_version` table records which ones a database has run.
+//! `ensure_schema` applies the missing ones, so any history a document
+//! carries is upgraded when it is opened.
+//!
+//! Databases written before `schema_version` existed can be at any of the
+//! historical layouts, so every migration must also work on a database that
+//! already has some or all of its changes.
 use rusqlite::Connection;
-use uuid::Uuid;
+use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use uuid::{Builder, Uuid};
 
-pub fn ensure_schema(conn: &Connection) -> Result<(), String> {
-    // 1. Add columns first (ignore errors if they exist)
-    // Note: SQLite ALTER TABLE ADD COLUMN does not support UNIQUE constraint directly
-    conn.execute("ALTER TABLE patches ADD COLUMN uuid TEXT", []).ok();
-    conn.execute("ALTER TABLE patches ADD COLUMN parent_uuid TEXT", []).ok();
-
-    // 2. Create tables (for new docs) and Indices (for all)
-    // For new tables, we define the schema fully.
-    // For existing tables, IF NOT EXISTS will skip table creation, but indices will be created.
+/// Schema version written by this build: the last migration's version
+pub const SCHEMA_VERSION: u32 = 10;
+
+/// One step of the history schema
+struct Migration {
+    version: u32,
+    description: &'static str,
+    apply: fn(&Connection) -> Result<(), String>,
+}
+
+/// Every migration, oldest first
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "patches and snapshots",
+        apply: create_patch_tables,
+    },
+    Migration {
+        version: 2,
+        description: "patch UUIDs and parents",
+        apply: add_patch_uuids,
+    },
+    Migration {
+        version: 3,
+        description: "patch reviews",
+        apply: create_review_tables,
+    },
+    Migration {
+        version: 4,
+        description: "author aliases",
+        apply: create_author_aliases,
+    },
+    Migration {
+        version: 5,
+        description: "patch query indexes",
+        apply: create_patch_indexes,
+    },
+    Migration {
+        version: 6,
+        description: "snapshot hashes and shared snapshots",
+        apply: add_snapshot_hashes,
+    },
+    Migration {
+        version: 7,
+        description: "export history",
+        apply: create_export_table,
+    },
+    Migration {
+        version: 8,
+        description: "review baselines",
+        apply: create_review_baselines,
+    },
+    Migration {
+        version: 9,
+        description: "unverified patches",
+        apply: add_patch_verification,
+    },
+    Migration {
+        version: 10,
+        description: "patch channels",
+        apply: create_channel_tables,
+    },
+];
+
+/// Layout of the first releases
+fn create_patch_tables(conn: &Connection) -> Result<(), String> {
     conn.execute_batch(
         r#"
         CREATE TABLE IF NOT EXISTS patches (
@@ -18,9 +87,7 @@ pub fn ensure_schema(conn: &Connection) -> Result<(), String> {
             timestamp   INTEGER NOT NULL,
             author      TEXT    NOT NULL,
             kind        TEXT    NOT NULL,
-            data        TEXT    NOT NULL,
-            uuid        TEXT UNIQUE,
-            parent_uuid TEXT
+            data        TEXT    NOT NULL
         );
 
         CREATE TABLE IF NOT EXISTS snapshots (
@@ -31,6 +98,30 @@ pub fn ensure_schema(conn: &Connection) -> Result<(), String> {
             FOREIGN KEY (patch_id) REFERENCES patches(id)
         );
 
+        CREATE INDEX IF NOT EXISTS idx_snapshots_patch_id ON snapshots(patch_id);
+        "#,
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn add_patch_uuids(conn: &Connection) -> Result<(), String> {
+    // SQLite ALTER TABLE ADD COLUMN does not support UNIQUE, so uniqueness
+    // comes from the index below
+    for column in ["uuid", "parent_uuid"] {
+        if !has_column(conn, "patches", column)? {
+            conn.execute(&format!("ALTER TABLE patches ADD COLUMN {} TEXT", column), [])
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    backfill_patch_uuids(conn)?;
+    conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS idx_patches_uuid ON patches(uuid)", [])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn create_review_tables(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        r#"
         CREATE TABLE IF NOT EXISTS patch_reviews (
             patch_uuid   TEXT NOT NULL,
             reviewer_id  TEXT NOT NULL,
@@ -40,34 +131,492 @@ pub fn ensure_schema(conn: &Connection) -> Result<(), String> {
             PRIMARY KEY (patch_uuid, reviewer_id)
         );
 
-        CREATE INDEX IF NOT EXISTS idx_snapshots_patch_id ON snapshots(patch_id);
+        CREATE TABLE IF NOT EXISTS patch_review_comments (
+            uuid         TEXT PRIMARY KEY,
+            patch_uuid   TEXT NOT NULL,
+            hunk_id      TEXT,
+            author_id    TEXT NOT NULL,
+            author_name  TEXT,
+            content      TEXT NOT NULL,
+            created_at   INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS orphaned_patches (
+            patch_uuid    TEXT NOT NULL,
+            rejected_uuid TEXT NOT NULL,
+            reviewer_id   TEXT NOT NULL,
+            orphaned_at   INTEGER NOT NULL,
+            PRIMARY KEY (patch_uuid, rejected_uuid)
+        );
+
         CREATE INDEX IF NOT EXISTS idx_patch_reviews_reviewer_id ON patch_reviews(reviewer_id);
-        -- Use unique index to enforce uniqueness on the uuid column (covers both new and migrated tables)
-        CREATE UNIQUE INDEX IF NOT EXISTS idx_patches_uuid ON patches(uuid);
-        -- Performance indexes for common query patterns
+        CREATE INDEX IF NOT EXISTS idx_patch_reviews_patch_uuid ON patch_reviews(patch_uuid);
+        CREATE INDEX IF NOT EXISTS idx_patch_review_comments_patch_uuid ON patch_review_comments(patch_uuid);
+        CREATE INDEX IF NOT EXISTS idx_orphaned_patches_rejected_uuid ON orphaned_patches(rejected_uuid);
+        "#,
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn create_author_aliases(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS author_aliases (
+            alias_id     TEXT PRIMARY KEY,
+            author_id    TEXT NOT NULL,
+            merged_at    INTEGER NOT NULL
+        );
+        "#,
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn create_patch_indexes(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        r#"
         CREATE INDEX IF NOT EXISTS idx_patches_timestamp ON patches(timestamp);
         CREATE INDEX IF NOT EXISTS idx_patches_author ON patches(author);
         CREATE INDEX IF NOT EXISTS idx_patches_kind ON patches(kind);
-        CREATE INDEX IF NOT EXISTS idx_patch_reviews_patch_uuid ON patch_reviews(patch_uuid);
         "#,
     )
-    .map_err(|e| e.to_string())?;
+    .map_err(|e| e.to_string())
+}
 
-    // 3. Backfill UUIDs for existing patches that are NULL
-    // We do this in Rust to ensure consistent UUIDv4 formatting
-    {
-        let mut stmt = conn.prepare("SELECT id FROM patches WHERE uuid IS NULL").map_err(|e| e.to_string())?;
-        let ids: Vec<i64> = stmt.query_map([], |row| row.get(0))
-            .map_err(|e| e.to_string())?
-            .collect::<Result<Vec<_>, _>>()
+/// Hash snapshots and keep each distinct state once
+///
+/// A snapshot whose state an earlier one already holds stores an empty
+/// state and points at that row through `same_as`. `snapshot_states` reads
+/// every snapshot with its state resolved.
+fn add_snapshot_hashes(conn: &Connection) -> Result<(), String> {
+    for (column, kind) in [("hash", "TEXT"), ("same_as", "INTEGER")] {
+        if !has_column(conn, "snapshots", column)? {
+            conn.execute(&format!("ALTER TABLE snapshots ADD COLUMN {} {}", column, kind), [])
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    fold_duplicate_snapshots(conn)?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_snapshots_hash ON snapshots(hash)", [])
+        .map_err(|e| e.to_string())?;
+    create_snapshot_states_view(conn)
+}
+
+/// The view resolving snapshots stored as a reference to an identical one
+pub(crate) fn create_snapshot_states_view(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        r#"
+        CREATE VIEW IF NOT EXISTS snapshot_states AS
+            SELECT s.id, s.timestamp, s.patch_id, COALESCE(o.state, s.state) AS state, s.hash
+            FROM snapshots s LEFT JOIN snapshots o ON o.id = s.same_as;
+        "#,
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn create_export_table(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS exports (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            format       TEXT    NOT NULL,
+            path         TEXT    NOT NULL,
+            patch_uuid   TEXT    NOT NULL,
+            created_at   INTEGER NOT NULL,
+            status       TEXT    NOT NULL,
+            error        TEXT
+        );
+        "#,
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// The last patch each reviewer accepted, which their review queue is diffed
+/// against; filled from the reviews already recorded
+fn create_review_baselines(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS review_baselines (
+            reviewer_id  TEXT PRIMARY KEY,
+            patch_uuid   TEXT    NOT NULL,
+            updated_at   INTEGER NOT NULL
+        );
+
+        INSERT OR IGNORE INTO review_baselines (reviewer_id, patch_uuid, updated_at)
+            SELECT reviewer_id, uuid, reviewed_at FROM (
+                SELECT r.reviewer_id, p.uuid, r.reviewed_at, MAX(p.id)
+                FROM patch_reviews r JOIN patches p ON p.uuid = r.patch_uuid
+                WHERE r.decision = 'accepted'
+                GROUP BY r.reviewer_id
+            );
+        "#,
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Flag on patches imported from a bundle that no known key vouches for
+fn add_patch_verification(conn: &Connection) -> Result<(), String> {
+    if !has_column(conn, "patches", "unverified")? {
+        conn.execute("ALTER TABLE patches ADD COLUMN unverified INTEGER NOT NULL DEFAULT 0", [])
             .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
 
-        for id in ids {
-            let new_uuid = Uuid::new_v4().to_string();
-            conn.execute("UPDATE patches SET uuid = ?1 WHERE id = ?2", rusqlite::params![new_uuid, id])
+/// Patches staged per collaborator, see `channels`; histories from before
+/// this migration may already have the table without `signed_by`
+fn create_channel_tables(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS channel_patches (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            channel     TEXT    NOT NULL,
+            uuid        TEXT    NOT NULL,
+            timestamp   INTEGER NOT NULL,
+            author      TEXT    NOT NULL,
+            kind        TEXT    NOT NULL,
+            data        TEXT    NOT NULL,
+            parent_uuid TEXT,
+            snapshot    BLOB,
+            signed_by   TEXT,
+            UNIQUE (channel, uuid)
+        );
+        CREATE INDEX IF NOT EXISTS idx_channel_patches_channel ON channel_patches(channel);
+        "#,
+    )
+    .map_err(|e| e.to_string())?;
+    if !has_column(conn, "channel_patches", "signed_by")? {
+        conn.execute("ALTER TABLE channel_patches ADD COLUMN signed_by TEXT", [])
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// SHA-256 of a snapshot state, as stored in `snapshots.hash`
+pub fn snapshot_hash(state: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(state))
+}
+
+/// Hash the snapshots without a hash and point repeated states at the first copy
+fn fold_duplicate_snapshots(conn: &Connection) -> Result<(), String> {
+    let mut stmt = conn
+        .prepare("SELECT id, hash FROM snapshots WHERE same_as IS NULL ORDER BY id")
+        .map_err(|e| e.to_string())?;
+    let rows: Vec<(i64, Option<String>)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut first: HashMap<String, i64> = HashMap::new();
+    for (id, hash) in rows {
+        let hash = match hash {
+            Some(hash) => hash,
+            None => {
+                let state: Vec<u8> = conn
+                    .query_row("SELECT state FROM snapshots WHERE id = ?1", [id], |row| row.get(0))
+                    .map_err(|e| e.to_string())?;
+                let hash = snapshot_hash(&state);
+                conn.execute("UPDATE snapshots SET hash = ?1 WHERE id = ?2", rusqlite::params![hash, id])
+                    .map_err(|e| e.to_string())?;
+                hash
+            }
+        };
+        match first.get(&hash) {
+            Some(&holder) => {
+                conn.execute(
+                    "UPDATE snapshots SET state = x'', same_as = ?1 WHERE id = ?2",
+                    rusqlite::params![holder, id],
+                )
                 .map_err(|e| e.to_string())?;
+            }
+            None => {
+                first.insert(hash, id);
+            }
         }
     }
+    Ok(())
+}
 
+/// Whether a table has a column
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool, String> {
+    conn.prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")
+        .and_then(|mut stmt| stmt.exists([table, column]))
+        .map_err(|e| e.to_string())
+}
+
+/// UUID for a patch recorded before patches had one
+///
+/// Derived from the row rather than random, so every copy of a legacy
+/// history (e.g. the same old KMD imported twice) agrees on it and imports
+/// can dedupe by UUID.
+fn legacy_patch_uuid(id: i64, timestamp: i64, author: &str, kind: &str, data: &str) -> Uuid {
+    let mut hasher = Sha256::new();
+    for part in [&id.to_string(), &timestamp.to_string(), author, kind, data] {
+        hasher.update(part.as_bytes());
+        hasher.update(b"|");
+    }
+    let hash = hasher.finalize();
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    Builder::from_custom_bytes(bytes).into_uuid()
+}
+
+/// Give a UUID to every patch without one
+fn backfill_patch_uuids(conn: &Connection) -> Result<(), String> {
+    let mut stmt = conn
+        .prepare("SELECT id, timestamp, author, kind, data FROM patches WHERE uuid IS NULL")
+        .map_err(|e| e.to_string())?;
+    let rows: Vec<(i64, i64, String, String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    for (id, timestamp, author, kind, data) in rows {
+        let new_uuid = legacy_patch_uuid(id, timestamp, &author, &kind, &data).to_string();
+        conn.execute("UPDATE patches SET uuid = ?1 WHERE id = ?2", rusqlite::params![new_uuid, id])
+            .map_err(|e| e.to_string())?;
+    }
     Ok(())
 }
+
+/// Schema version of a database; 0 for one that never ran a migration
+pub fn schema_version(conn: &Connection) -> Result<u32, String> {
+    let tracked: bool = conn
+        .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'")
+        .and_then(|mut stmt| stmt.exists([]))
+        .map_err(|e| e.to_string())?;
+    if !tracked {
+        return Ok(0);
+    }
+    conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))
+        .map_err(|e| e.to_string())
+}
+
+/// Run the migrations a database is missing; returns the versions applied
+///
+/// Each migration runs in its own transaction together with its
+/// `schema_version` row. A database from a newer Korppi is refused rather
+/// than written with an older layout.
+pub fn migrate(conn: &Connection) -> Result<Vec<u32>, String> {
+    let current = schema_version(conn)?;
+    if current > SCHEMA_VERSION {
+        return Err(format!(
+            "This history uses schema version {}, but this version of Korppi only knows up to {}. Please update Korppi.",
+            current, SCHEMA_VERSION
+        ));
+    }
+
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+        tx.execute_batch(
+            r#"
+            CREATE TABLE IF NOT EXISTS schema_version (
+                version     INTEGER PRIMARY KEY,
+                description TEXT    NOT NULL,
+                applied_at  INTEGER NOT NULL
+            );
+            "#,
+        )
+        .map_err(|e| e.to_string())?;
+        (migration.apply)(&tx)
+            .map_err(|e| format!("History migration {} ({}) failed: {}", migration.version, migration.description, e))?;
+        tx.execute(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, ?2, ?3)",
+            rusqlite::params![migration.version, migration.description, chrono::Utc::now().timestamp_millis()],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        applied.push(migration.version);
+    }
+    Ok(applied)
+}
+
+/// Bring a history database up to the current schema
+pub fn ensure_schema(conn: &Connection) -> Result<(), String> {
+    let applied = migrate(conn)?;
+    if !applied.is_empty() {
+        log::info!("Upgraded history schema with migrations {:?}", applied);
+    }
+
+    // Older Korppi versions still write patches without a UUID into
+    // upgraded histories
+    backfill_patch_uuids(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn.prepare("SELECT name FROM pragma_table_info(?1)").unwrap();
+        stmt.query_map([table], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    fn table_exists(conn: &Connection, table: &str) -> bool {
+        conn.prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1")
+            .unwrap()
+            .exists([table])
+            .unwrap()
+    }
+
+    #[test]
+    fn test_new_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+        assert_eq!(migrate(&conn).unwrap(), vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+        assert_eq!(
+            columns(&conn, "patches"),
+            vec!["id", "timestamp", "author", "kind", "data", "uuid", "parent_uuid", "unverified"]
+        );
+
+        // Nothing left to do
+        assert!(migrate(&conn).unwrap().is_empty());
+        ensure_schema(&conn).unwrap();
+        let rows: i64 = conn.query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(rows, SCHEMA_VERSION as i64);
+    }
+
+    fn upgraded_legacy_uuids() -> Vec<Option<String>> {
+        // The layout of the first releases, with history in it
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            r#"
+            CREATE TABLE patches (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp INTEGER NOT NULL,
+                author TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                data TEXT NOT NULL
+            );
+            INSERT INTO patches (timestamp, author, kind, data) VALUES (1, 'alice', 'Save', '{}');
+            INSERT INTO patches (timestamp, author, kind, data) VALUES (2, 'bob', 'Save', '{}');
+            "#,
+        )
+        .unwrap();
+
+        ensure_schema(&conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+        assert!(table_exists(&conn, "snapshots"));
+        assert!(table_exists(&conn, "patch_reviews"));
+        assert!(table_exists(&conn, "author_aliases"));
+
+        let uuids: Vec<Option<String>> = conn
+            .prepare("SELECT uuid FROM patches ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(uuids.len(), 2);
+        assert!(uuids.iter().all(|u| u.as_ref().is_some_and(|u| Uuid::parse_str(u).is_ok())));
+        assert_ne!(uuids[0], uuids[1]);
+        uuids
+    }
+
+    #[test]
+    fn test_legacy_database_without_uuids() {
+        // Every copy of a legacy history gets the same UUIDs
+        assert_eq!(upgraded_legacy_uuids(), upgraded_legacy_uuids());
+    }
+
+    #[test]
+    fn test_untracked_current_layout() {
+        // Histories written before schema_version have every table already
+        let conn = Connection::open_in_memory().unwrap();
+        for migration in MIGRATIONS {
+            (migration.apply)(&conn).unwrap();
+        }
+        conn.execute(
+            "INSERT INTO patches (timestamp, author, kind, data, uuid) VALUES (1, 'alice', 'Save', '{}', 'p1')",
+            [],
+        )
+        .unwrap();
+
+        ensure_schema(&conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+        let uuid: String = conn.query_row("SELECT uuid FROM patches", [], |row| row.get(0)).unwrap();
+        assert_eq!(uuid, "p1");
+    }
+
+    #[test]
+    fn test_duplicate_snapshots_folded() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_patch_tables(&conn).unwrap();
+        conn.execute_batch(
+            r#"
+            INSERT INTO patches (timestamp, author, kind, data) VALUES (1, 'a', 'Save', '{}'), (2, 'a', 'Save', '{}'), (3, 'a', 'Save', '{}');
+            INSERT INTO snapshots (timestamp, patch_id, state) VALUES (1, 1, x'01');
+            INSERT INTO snapshots (timestamp, patch_id, state) VALUES (2, 2, x'02');
+            INSERT INTO snapshots (timestamp, patch_id, state) VALUES (3, 3, x'01');
+            "#,
+        )
+        .unwrap();
+
+        ensure_schema(&conn).unwrap();
+        let rows: Vec<(Vec<u8>, Option<i64>, Vec<u8>)> = conn
+            .prepare(
+                "SELECT s.state, s.same_as, v.state FROM snapshots s
+                 JOIN snapshot_states v ON v.id = s.id ORDER BY s.id",
+            )
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                (vec![1], None, vec![1]),
+                (vec![2], None, vec![2]),
+                (Vec::new(), Some(1), vec![1]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_partial_and_newer_versions() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            r#"
+            CREATE TABLE schema_version (version INTEGER PRIMARY KEY, description TEXT NOT NULL, applied_at INTEGER NOT NULL);
+            INSERT INTO schema_version VALUES (1, 'patches and snapshots', 0);
+            "#,
+        )
+        .unwrap();
+        create_patch_tables(&conn).unwrap();
+        assert_eq!(migrate(&conn).unwrap(), vec![2, 3, 4, 5, 6, 7, 8, 9, 10]);
+
+        conn.execute("INSERT INTO schema_version VALUES (?1, 'future', 0)", [SCHEMA_VERSION + 1])
+            .unwrap();
+        assert!(ensure_schema(&conn).unwrap_err().contains("update Korppi"));
+    }
+
+    #[test]
+    fn test_channel_table_gains_signers() {
+        // Channels were staged before the table was part of the schema
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            r#"
+            CREATE TABLE channel_patches (
+                id INTEGER PRIMARY KEY AUTOINCREMENT, channel TEXT NOT NULL, uuid TEXT NOT NULL,
+                timestamp INTEGER NOT NULL, author TEXT NOT NULL, kind TEXT NOT NULL, data TEXT NOT NULL,
+                parent_uuid TEXT, snapshot BLOB, UNIQUE (channel, uuid)
+            );
+            INSERT INTO channel_patches (channel, uuid, timestamp, author, kind, data) VALUES ('bob', 'p1', 1, 'bob', 'Save', '{}');
+            "#,
+        )
+        .unwrap();
+
+        ensure_schema(&conn).unwrap();
+        assert_eq!(columns(&conn, "channel_patches").last().unwrap(), "signed_by");
+        let signed_by: Option<String> = conn
+            .query_row("SELECT signed_by FROM channel_patches WHERE uuid = 'p1'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(signed_by, None);
+    }
+}
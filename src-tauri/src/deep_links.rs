@@ -0,0 +1,271 @@
+// src-tauri/src/deep_links.rs
+//! `korppi://` links to a position in a document.
+//!
+//! A link names a document by the UUID in its meta.json and, optionally, a
+//! place in it: a comment anchor (the JSON of a Yjs relative position, as
+//! the editor stores it) or a `{#sec:label}` section label:
+//!
+//! - `korppi://doc/<uuid>?pos=<anchor, base64url>`
+//! - `korppi://doc/<uuid>#sec:<label>`
+//!
+//! Links pasted into an e-mail or a note open Korppi through the `korppi`
+//! URL scheme. A link is resolved to the open document with that UUID, or
+//! else to a recent document whose file has it; the window is then sent
+//! `POSITION_LINK_OPENED` to open the file and jump to the position.
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, State, Window};
+
+use crate::document_manager::{current_document_text, load_recent_documents, DocumentManager, MAIN_WINDOW};
+use crate::error::KorppiError;
+use crate::events::POSITION_LINK_OPENED;
+use crate::outline::{build_outline, find_section};
+use crate::templates::read_kmd_meta;
+use crate::yjs_store::parse_anchor;
+
+/// URL scheme registered with the operating system
+pub const LINK_SCHEME: &str = "korppi";
+
+const LINK_PREFIX: &str = "korppi://doc/";
+
+/// A document and a position in it
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct PositionLink {
+    pub doc_uuid: String,
+    /// Yjs relative position JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<String>,
+    /// Section label, "sec:..."
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+}
+
+/// A link with the document it points to, if one was found
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ResolvedPositionLink {
+    #[serde(flatten)]
+    pub link: PositionLink,
+    /// The document is open with this ID
+    pub doc_id: Option<String>,
+    /// Path of the KMD file
+    pub path: Option<String>,
+}
+
+fn is_valid_uuid(uuid: &str) -> bool {
+    !uuid.is_empty() && uuid.len() <= 64 && uuid.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+fn is_valid_section(label: &str) -> bool {
+    label
+        .strip_prefix("sec:")
+        .is_some_and(|name| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | ':' | '.')))
+}
+
+/// Whether a command-line argument is a position link
+pub fn is_position_link(arg: &str) -> bool {
+    arg.get(..LINK_SCHEME.len() + 3)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("korppi://"))
+}
+
+/// Write a position link as a URL
+pub fn format_position_link(link: &PositionLink) -> String {
+    let mut url = format!("{}{}", LINK_PREFIX, link.doc_uuid);
+    if let Some(anchor) = &link.anchor {
+        url.push_str("?pos=");
+        url.push_str(&base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(anchor));
+    }
+    if let Some(section) = &link.section {
+        url.push('#');
+        url.push_str(section);
+    }
+    url
+}
+
+/// Read a position link from a URL
+pub fn parse_position_link(url: &str) -> Result<PositionLink, String> {
+    let invalid = || format!("Not a Korppi link: {}", url);
+    if !is_position_link(url) {
+        return Err(invalid());
+    }
+    let rest = url[LINK_SCHEME.len() + 3..].strip_prefix("doc/").ok_or_else(invalid)?;
+    let (rest, section) = match rest.split_once('#') {
+        Some((rest, fragment)) => (rest, Some(fragment)),
+        None => (rest, None),
+    };
+    let (doc_uuid, query) = match rest.split_once('?') {
+        Some((uuid, query)) => (uuid.trim_end_matches('/'), Some(query)),
+        None => (rest.trim_end_matches('/'), None),
+    };
+    if !is_valid_uuid(doc_uuid) {
+        return Err(invalid());
+    }
+
+    let mut link = PositionLink { doc_uuid: doc_uuid.to_string(), ..Default::default() };
+    if let Some(pos) = query.into_iter().flat_map(|q| q.split('&')).find_map(|p| p.strip_prefix("pos=")) {
+        let anchor = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(pos.trim_end_matches('='))
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .filter(|anchor| parse_anchor(anchor).is_some())
+            .ok_or_else(|| format!("Invalid position in link: {}", url))?;
+        link.anchor = Some(anchor);
+    }
+    if let Some(section) = section.filter(|s| !s.is_empty()) {
+        if !is_valid_section(section) {
+            return Err(format!("Invalid section in link: {}", url));
+        }
+        link.section = Some(section.to_string());
+    }
+    Ok(link)
+}
+
+/// Find the document a link points to, among the open documents and then
+/// the recent ones
+pub fn resolve_link(manager: &DocumentManager, link: PositionLink) -> ResolvedPositionLink {
+    if let Some(doc) = manager.documents.values().find(|d| d.meta.uuid == link.doc_uuid) {
+        return ResolvedPositionLink {
+            doc_id: Some(doc.handle.id.clone()),
+            path: doc.handle.path.as_ref().map(|p| p.to_string_lossy().to_string()),
+            link,
+        };
+    }
+    let path = load_recent_documents()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|recent| recent.path)
+        .find(|path| read_kmd_meta(path).is_ok_and(|meta| meta.uuid == link.doc_uuid));
+    ResolvedPositionLink {
+        doc_id: None,
+        path: path.map(|p| p.to_string_lossy().to_string()),
+        link,
+    }
+}
+
+/// The first position link among command-line arguments, resolved
+pub fn link_argument(manager: &DocumentManager, args: &[String]) -> Option<ResolvedPositionLink> {
+    let url = args.iter().find(|arg| is_position_link(arg))?;
+    match parse_position_link(url) {
+        Ok(link) => Some(resolve_link(manager, link)),
+        Err(e) => {
+            log::warn!("{}", e);
+            None
+        }
+    }
+}
+
+/// Hand a position link opened by the OS to the main window (or any
+/// window left)
+pub fn forward_position_link(app: &AppHandle, args: &[String]) -> bool {
+    if !args.iter().any(|arg| is_position_link(arg)) {
+        return false;
+    }
+    let resolved = match app.state::<Mutex<DocumentManager>>().lock() {
+        Ok(manager) => link_argument(&manager, args),
+        Err(_) => None,
+    };
+    let window = app
+        .get_webview_window(MAIN_WINDOW)
+        .or_else(|| app.webview_windows().into_values().next());
+    if let (Some(resolved), Some(window)) = (resolved, window) {
+        window.unminimize().ok();
+        window.set_focus().ok();
+        if let Err(e) = app.emit_to(window.label(), POSITION_LINK_OPENED, resolved) {
+            log::warn!("Failed to forward a position link: {}", e);
+        }
+    }
+    true
+}
+
+/// Link to a position in a document: a comment anchor (Yjs relative
+/// position JSON) or a section label ("sec:intro"); no anchor links to
+/// the document itself
+#[tauri::command]
+pub fn generate_position_link(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    anchor: Option<String>,
+) -> Result<String, KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager
+        .documents
+        .get(&doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+
+    let mut link = PositionLink { doc_uuid: doc.meta.uuid.clone(), ..Default::default() };
+    match anchor.as_deref().map(|a| a.trim().trim_start_matches('#')) {
+        None | Some("") => {}
+        Some(label) if label.starts_with("sec:") => {
+            if !is_valid_section(label) {
+                return Err(KorppiError::InvalidInput(format!("Invalid section label: {}", label)));
+            }
+            if find_section(&build_outline(&current_document_text(doc, false)?), label).is_none() {
+                return Err(KorppiError::InvalidInput(format!("No section labelled {}", label)));
+            }
+            link.section = Some(label.to_string());
+        }
+        Some(anchor) => {
+            if parse_anchor(anchor).is_none() {
+                return Err(KorppiError::InvalidInput("Invalid position anchor".to_string()));
+            }
+            link.anchor = Some(anchor.to_string());
+        }
+    }
+    Ok(format_position_link(&link))
+}
+
+/// Find the document a `korppi://` link points to
+///
+/// `doc_id` and `path` are both empty when the document is neither open
+/// nor among the recent documents.
+#[tauri::command]
+pub fn resolve_position_link(
+    manager: State<'_, Mutex<DocumentManager>>,
+    link: String,
+) -> Result<ResolvedPositionLink, KorppiError> {
+    let link = parse_position_link(link.trim()).map_err(KorppiError::InvalidInput)?;
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    Ok(resolve_link(&manager, link))
+}
+
+/// The position link this window was started with, if any
+#[tauri::command]
+pub fn get_initial_position_link(
+    window: Window,
+    manager: State<'_, Mutex<DocumentManager>>,
+) -> Option<ResolvedPositionLink> {
+    let mut manager = manager.lock().ok()?;
+    manager.pending_links.remove(window.label())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_links_round_trip() {
+        let anchor = r#"{"type":{"client":12,"clock":3},"tname":null,"item":{"client":12,"clock":40},"assoc":0}"#;
+        let link = PositionLink {
+            doc_uuid: "6f1c2a9e-0b7d-4c1e-9a55-3d2f8e7b1c04".to_string(),
+            anchor: Some(anchor.to_string()),
+            section: None,
+        };
+        let url = format_position_link(&link);
+        assert!(url.starts_with("korppi://doc/6f1c2a9e-0b7d-4c1e-9a55-3d2f8e7b1c04?pos="));
+        assert!(!url.split_once("?pos=").unwrap().1.contains(['+', '/', '=', '{']));
+        assert_eq!(parse_position_link(&url).unwrap(), link);
+
+        let section = parse_position_link("KORPPI://doc/abc-123/#sec:intro").unwrap();
+        assert_eq!(section.doc_uuid, "abc-123");
+        assert_eq!(section.section.as_deref(), Some("sec:intro"));
+        assert_eq!(format_position_link(&section), "korppi://doc/abc-123#sec:intro");
+
+        assert!(parse_position_link("korppi://doc/abc-123").unwrap().anchor.is_none());
+        assert!(parse_position_link("https://doc/abc-123").is_err());
+        assert!(parse_position_link("korppi://doc/../etc#sec:x").is_err());
+        assert!(parse_position_link("korppi://doc/abc?pos=bm90IGpzb24").is_err());
+        assert!(parse_position_link("korppi://doc/abc#fig:plot").is_err());
+        assert!(is_position_link("korppi://doc/abc") && !is_position_link("paper.kmd"));
+    }
+}
@@ -8,26 +8,47 @@
 use chrono::{DateTime, Utc};
 use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
 use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
-use tauri::{AppHandle, State};
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Emitter, Manager, State, WebviewUrl, WebviewWindowBuilder, Window};
 use uuid::Uuid;
-use zip::write::FileOptions;
 use zip::{ZipArchive, ZipWriter};
 
 use crate::kmd::{
-    check_version_compatibility, DocumentMeta, FormatInfo, AuthorProfile,
+    check_version_compatibility, format_info_for, is_path_safe, kmd_layout, DocumentMeta, FormatInfo, KmdCompression,
+    KmdLayout, SectionRef, YjsUpdate, UPDATES_DIR,
 };
+use crate::autosave::{note_document_activity, AutoSaveTracker};
+use crate::collation;
+use crate::backups::{back_up_if_due, effective_backup_settings};
 use crate::db_utils::ensure_schema;
+use crate::deep_links::ResolvedPositionLink;
+use crate::patch_store::{PatchFilter, PatchPage, PatchSortKey, PatchStore, PatchSummary, SnapshotReport};
+use crate::error::KorppiError;
+use crate::document_size::document_size_breakdown;
+use crate::events::{emit_event, PatchRecordedEvent, DOCUMENT_OPENED, DOCUMENT_SAVED, DOCUMENT_SIZE_WARNING, OPEN_FILE_REQUESTED, PATCH_RECORDED};
+use crate::hooks::{fire_hooks, HookEvent, HookPayload};
+use crate::file_lock::{acquire_lock, release_lock};
+use crate::file_progress::{FileJobs, FileProgress};
+use crate::journal::{self, find_orphaned_journal, journal_state, read_journal, reconcile, state_hash, JournalEntry, RecoveryReport};
+use crate::presence::{remove_presence, PresenceInfo};
+use crate::session_log::log_event;
+use crate::source_format::{split_source, SourceMetadata};
+use crate::pandoc::{is_pandoc_available, pandoc_command};
+use crate::patch_graph::{orphaned_patches, release_orphans};
+use crate::profile::{get_config_dir, kmd_author_profile, load_saved_profile, DEFAULT_AUTHOR_COLOR};
+use crate::review_comments::load_review_comments;
+use crate::review_queue::{advance_review_baseline, reset_review_baseline};
+use crate::safe_open::{check_archive, harden_history, is_scriptable_asset, is_trusted, sanitize_author_profile};
+use crate::hunk_calculator::{author_hunks, calculate_hunks_with, AuthoredHunk, DiffOptions, DEFAULT_HUNK_COLOR};
+use crate::yjs_store::{document_text, has_diverged, merge_states};
 use quick_xml::events::Event;
 use quick_xml::reader::Reader;
 
-/// Default author color for new profiles
-const DEFAULT_AUTHOR_COLOR: &str = "#3498db";
-
 /// A handle to an open document
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DocumentHandle {
@@ -37,6 +58,61 @@ pub struct DocumentHandle {
     pub is_modified: bool,
     #[serde(with = "chrono::serde::ts_milliseconds")]
     pub opened_at: DateTime<Utc>,
+    /// Opened without the file lock; edits and saves are refused
+    #[serde(default)]
+    pub read_only: bool,
+    /// Set when a save found the file changed on disk and merged it instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_merge: Option<ExternalMergeResult>,
+    /// Set when opening found the journal of a crashed session and replayed it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recovered: Option<RecoveryReport>,
+    /// Set when the file was opened in safe mode, with what was left out of it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safe_mode: Option<Vec<String>>,
+}
+
+/// Outcome of folding an externally modified KMD into the open document
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExternalMergeResult {
+    /// UUIDs of the patches imported from the file on disk
+    pub imported_patches: Vec<String>,
+    /// Both sides had edits the other lacked
+    pub diverged: bool,
+}
+
+/// Size, modification time and hash of a file, to detect external changes
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskFingerprint {
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    pub sha256: String,
+}
+
+impl DiskFingerprint {
+    pub fn read(path: &Path) -> Result<DiskFingerprint, String> {
+        use sha2::{Digest, Sha256};
+        let metadata = fs::metadata(path).map_err(|e| e.to_string())?;
+        let data = fs::read(path).map_err(|e| e.to_string())?;
+        Ok(DiskFingerprint {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            sha256: format!("{:x}", Sha256::digest(&data)),
+        })
+    }
+
+    /// Whether the file still has this content. A new size or mtime means it
+    /// changed; an equal mtime proves nothing on filesystems that keep it to
+    /// the second, so the content is hashed then
+    pub fn matches(&self, path: &Path) -> bool {
+        let Ok(metadata) = fs::metadata(path) else {
+            return false;
+        };
+        if metadata.len() != self.len || metadata.modified().ok() != self.modified {
+            return false;
+        }
+        DiskFingerprint::read(path).is_ok_and(|current| current.sha256 == self.sha256)
+    }
 }
 
 /// A recent document entry
@@ -46,36 +122,156 @@ pub struct RecentDocument {
     pub title: String,
     #[serde(with = "chrono::serde::ts_milliseconds")]
     pub last_opened: DateTime<Utc>,
+    /// Pinned documents never fall off the list
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+/// Current schema version of recent.json
+const RECENT_SCHEMA_VERSION: u32 = 1;
+
+fn default_max_recent() -> usize {
+    10
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// User preferences for the recent documents list
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentSettings {
+    #[serde(default = "default_max_recent")]
+    pub max_entries: usize,
+    /// When false, opened documents are not recorded (privacy)
+    #[serde(default = "default_true")]
+    pub tracking_enabled: bool,
+}
+
+impl Default for RecentSettings {
+    fn default() -> Self {
+        Self {
+            max_entries: default_max_recent(),
+            tracking_enabled: true,
+        }
+    }
+}
+
+/// On-disk layout of recent.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecentFile {
+    version: u32,
+    #[serde(default)]
+    settings: RecentSettings,
+    #[serde(default)]
+    documents: Vec<RecentDocument>,
+}
+
+impl Default for RecentFile {
+    fn default() -> Self {
+        Self {
+            version: RECENT_SCHEMA_VERSION,
+            settings: RecentSettings::default(),
+            documents: Vec::new(),
+        }
+    }
 }
 
 /// State for a single document
 pub struct DocumentState {
     pub handle: DocumentHandle,
     pub yjs_state: Vec<u8>,
+    /// Append-only Yjs updates (KMD v0.2), applied on top of yjs_state
+    pub yjs_updates: Vec<YjsUpdate>,
     pub history_path: PathBuf,
     pub meta: DocumentMeta,
+    /// Yjs state and history of each project section, keyed by section ID
+    pub sections: HashMap<String, SectionState>,
+    /// The KMD file as last read or written, to detect external changes
+    pub disk_fingerprint: Option<DiskFingerprint>,
+}
+
+/// Editor state of one section of a multi-file project
+#[derive(Debug, Clone)]
+pub struct SectionState {
+    pub yjs_state: Vec<u8>,
+    pub history_path: PathBuf,
 }
 
+/// Label of the window created from tauri.conf.json
+pub const MAIN_WINDOW: &str = "main";
+
+/// Label prefix of windows opened with `open_in_new_window`
+pub const DOCUMENT_WINDOW_PREFIX: &str = "document-";
+
 /// The document manager state
+///
+/// Documents are shared by all windows; each window has its own active
+/// document, keyed by window label.
 pub struct DocumentManager {
     pub documents: HashMap<String, DocumentState>,
-    pub active_document_id: Option<String>,
+    pub active_documents: HashMap<String, String>,
+    /// Files a new window opens on startup, keyed by window label
+    pub pending_opens: HashMap<String, PathBuf>,
+    /// `korppi://` links a new window jumps to once its file is open
+    pub pending_links: HashMap<String, ResolvedPositionLink>,
 }
 
 impl Default for DocumentManager {
     fn default() -> Self {
         Self {
             documents: HashMap::new(),
-            active_document_id: None,
+            active_documents: HashMap::new(),
+            pending_opens: HashMap::new(),
+            pending_links: HashMap::new(),
         }
     }
 }
 
-/// Get the config directory for korppi
-fn get_config_dir() -> Result<PathBuf, String> {
-    dirs::config_dir()
-        .map(|p| p.join("korppi"))
-        .ok_or_else(|| "Could not determine config directory".to_string())
+impl DocumentManager {
+    /// Active document of a window
+    pub fn active_document_id(&self, window: &str) -> Option<&String> {
+        self.active_documents.get(window)
+    }
+
+    /// Make a document the active one of a window
+    pub fn set_active(&mut self, window: &str, doc_id: String) {
+        self.active_documents.insert(window.to_string(), doc_id);
+    }
+
+    /// Point windows that showed a closed document at another open one
+    fn document_closed(&mut self, doc_id: &str) {
+        let next = self.documents.keys().next().cloned();
+        let windows: Vec<String> = self
+            .active_documents
+            .iter()
+            .filter(|(_, id)| id.as_str() == doc_id)
+            .map(|(window, _)| window.clone())
+            .collect();
+        for window in windows {
+            match &next {
+                Some(id) => self.set_active(&window, id.clone()),
+                None => {
+                    self.active_documents.remove(&window);
+                }
+            }
+        }
+    }
+
+    /// Forget the state of a closed window
+    pub fn window_closed(&mut self, window: &str) {
+        self.active_documents.remove(window);
+        self.pending_opens.remove(window);
+        self.pending_links.remove(window);
+    }
+
+    /// ID of the open document read from `path`
+    pub fn document_at(&self, path: &Path) -> Option<&String> {
+        self.documents
+            .iter()
+            .find(|(_, doc)| doc.handle.path.as_deref() == Some(path))
+            .map(|(id, _)| id)
+    }
 }
 
 /// Get the path to the recent documents file
@@ -91,15 +287,37 @@ fn get_temp_base_dir() -> Result<PathBuf, String> {
 }
 
 /// Create a temp directory for a document
-fn create_document_temp_dir(doc_id: &str) -> Result<PathBuf, String> {
+pub(crate) fn create_document_temp_dir(doc_id: &str) -> Result<PathBuf, String> {
     let base = get_temp_base_dir()?;
     let doc_dir = base.join(doc_id);
     fs::create_dir_all(&doc_dir).map_err(|e| e.to_string())?;
     Ok(doc_dir)
 }
 
+/// Directory of a document's images, stored under the same name in the KMD
+pub(crate) const ASSETS_DIR: &str = "assets";
+
+/// The assets directory next to a document's history database
+pub(crate) fn document_assets_dir(history_path: &Path) -> PathBuf {
+    history_path.with_file_name(ASSETS_DIR)
+}
+
+/// Directory of author profiles, stored under the same name in the KMD
+const AUTHORS_DIR: &str = "authors";
+
+/// The author profiles read from a document's KMD file, next to its
+/// history database
+pub(crate) fn document_roster_dir(history_path: &Path) -> PathBuf {
+    history_path.with_file_name(AUTHORS_DIR)
+}
+
+/// Get the history database path inside a document's temp directory
+pub fn get_document_history_path(doc_id: &str) -> Result<PathBuf, String> {
+    Ok(get_temp_base_dir()?.join(doc_id).join("history.sqlite"))
+}
+
 /// Clean up a document's temp directory
-fn cleanup_document_temp_dir(doc_id: &str) -> Result<(), String> {
+pub(crate) fn cleanup_document_temp_dir(doc_id: &str) -> Result<(), String> {
     let base = get_temp_base_dir()?;
     let doc_dir = base.join(doc_id);
     if doc_dir.exists() {
@@ -108,49 +326,288 @@ fn cleanup_document_temp_dir(doc_id: &str) -> Result<(), String> {
     Ok(())
 }
 
-/// Load recent documents list
-fn load_recent_documents() -> Result<Vec<RecentDocument>, String> {
+/// Temp directories untouched for this long are treated as abandoned at startup
+const ORPHAN_MAX_AGE_DAYS: u64 = 7;
+
+/// Result of a temp directory cleanup
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct CleanupReport {
+    pub removed_dirs: usize,
+    pub reclaimed_bytes: u64,
+}
+
+/// Total size of all files under a directory
+pub(crate) fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Most recent modification time of a directory or anything inside it
+fn dir_last_modified(path: &Path) -> Option<SystemTime> {
+    let mut latest = fs::metadata(path).and_then(|m| m.modified()).ok();
+    if let Ok(entries) = fs::read_dir(path) {
+        for entry in entries.flatten() {
+            let modified = if entry.path().is_dir() {
+                dir_last_modified(&entry.path())
+            } else {
+                entry.metadata().and_then(|m| m.modified()).ok()
+            };
+            latest = latest.max(modified);
+        }
+    }
+    latest
+}
+
+/// Remove document temp directories that do not belong to an open document
+///
+/// Directories holding a crash journal are kept for recovery when their file
+/// is opened again. With `max_age` set, only directories untouched for
+/// at least that long are removed so that recent sessions stay recoverable.
+pub fn collect_orphaned_temp_dirs(
+    base: &Path,
+    open_ids: &HashSet<String>,
+    max_age: Option<Duration>,
+) -> Result<CleanupReport, String> {
+    let mut report = CleanupReport::default();
+    let entries = fs::read_dir(base).map_err(|e| format!("Failed to read temp directory: {}", e))?;
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        if open_ids.contains(&name) || path.join(journal::JOURNAL_FILE).exists() {
+            continue;
+        }
+        if let Some(max_age) = max_age {
+            let age = dir_last_modified(&path)
+                .and_then(|t| SystemTime::now().duration_since(t).ok())
+                .unwrap_or_default();
+            if age < max_age {
+                continue;
+            }
+        }
+
+        let size = dir_size(&path);
+        if fs::remove_dir_all(&path).is_ok() {
+            report.removed_dirs += 1;
+            report.reclaimed_bytes += size;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Startup janitor for temp directories leaked by sessions that never closed
+pub fn run_temp_janitor() {
+    let max_age = Duration::from_secs(ORPHAN_MAX_AGE_DAYS * 24 * 60 * 60);
+    let result = get_temp_base_dir()
+        .and_then(|base| collect_orphaned_temp_dirs(&base, &HashSet::new(), Some(max_age)));
+    match result {
+        Ok(report) if report.removed_dirs > 0 => log::info!(
+            "Removed {} orphaned document directories ({} bytes)",
+            report.removed_dirs,
+            report.reclaimed_bytes
+        ),
+        Ok(_) => {}
+        Err(e) => log::warn!("Temp directory cleanup failed: {}", e),
+    }
+}
+
+/// Parse recent.json, upgrading the legacy bare-array layout
+fn parse_recent_file(content: &str) -> Result<RecentFile, String> {
+    let value: serde_json::Value = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    if value.is_array() {
+        let documents: Vec<RecentDocument> = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        return Ok(RecentFile {
+            documents,
+            ..RecentFile::default()
+        });
+    }
+    let mut file: RecentFile = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    file.version = RECENT_SCHEMA_VERSION;
+    Ok(file)
+}
+
+/// Load recent.json
+fn load_recent_file() -> Result<RecentFile, String> {
     let path = get_recent_path()?;
     if !path.exists() {
-        return Ok(Vec::new());
+        return Ok(RecentFile::default());
     }
     let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
-    serde_json::from_str(&content).map_err(|e| e.to_string())
+    parse_recent_file(&content)
 }
 
-/// Save recent documents list
-fn save_recent_documents(recent: &[RecentDocument]) -> Result<(), String> {
+/// Save recent.json
+fn save_recent_file(file: &RecentFile) -> Result<(), String> {
     let config_dir = get_config_dir()?;
     fs::create_dir_all(&config_dir).map_err(|e| e.to_string())?;
     let path = config_dir.join("recent.json");
-    let content = serde_json::to_string_pretty(recent).map_err(|e| e.to_string())?;
+    let content = serde_json::to_string_pretty(file).map_err(|e| e.to_string())?;
     fs::write(&path, content).map_err(|e| e.to_string())
 }
 
+/// Load recent documents list
+pub(crate) fn load_recent_documents() -> Result<Vec<RecentDocument>, String> {
+    Ok(load_recent_file()?.documents)
+}
+
+/// Drop the oldest unpinned entries beyond the configured limit
+fn trim_recent(recent: &mut Vec<RecentDocument>, max_entries: usize) {
+    let pinned = recent.iter().filter(|r| r.pinned).count();
+    let mut unpinned_allowed = max_entries.saturating_sub(pinned);
+    recent.retain(|r| {
+        if r.pinned {
+            return true;
+        }
+        if unpinned_allowed > 0 {
+            unpinned_allowed -= 1;
+            return true;
+        }
+        false
+    });
+}
+
 /// Add a document to the recent list
-fn add_to_recent(path: PathBuf, title: String) -> Result<(), String> {
-    let mut recent = load_recent_documents().unwrap_or_default();
+pub(crate) fn add_to_recent(path: PathBuf, title: String) -> Result<(), String> {
+    let mut file = load_recent_file().unwrap_or_default();
+    if !file.settings.tracking_enabled {
+        return Ok(());
+    }
     
-    // Remove if already exists
-    recent.retain(|r| r.path != path);
+    // Remove if already exists, keeping its pin
+    let pinned = file.documents.iter().any(|r| r.path == path && r.pinned);
+    file.documents.retain(|r| r.path != path);
     
     // Add to front
-    recent.insert(0, RecentDocument {
+    file.documents.insert(0, RecentDocument {
         path,
         title,
         last_opened: Utc::now(),
+        pinned,
     });
     
-    // Keep only 10 most recent
-    recent.truncate(10);
+    trim_recent(&mut file.documents, file.settings.max_entries);
     
-    save_recent_documents(&recent)
+    save_recent_file(&file)
 }
 
-/// Extract a KMD file to a document temp directory
-fn extract_kmd_to_temp(kmd_path: &PathBuf, doc_id: &str) -> Result<(Vec<u8>, PathBuf, DocumentMeta), String> {
-    let file = File::open(kmd_path).map_err(|e| format!("Failed to open file: {}", e))?;
-    let mut archive = ZipArchive::new(file).map_err(|e| format!("Invalid ZIP archive: {}", e))?;
+/// Remove a document from the recent list, e.g. after it was deleted
+pub(crate) fn remove_from_recent(path: &Path) -> Result<(), String> {
+    let mut file = load_recent_file()?;
+    let before = file.documents.len();
+    file.documents.retain(|r| r.path != path);
+    if file.documents.len() == before {
+        return Ok(());
+    }
+    save_recent_file(&file)
+}
+
+/// Set the pinned flag of a recent document
+fn set_recent_pinned(path: PathBuf, pinned: bool) -> Result<Vec<RecentDocument>, String> {
+    let mut file = load_recent_file()?;
+    let entry = file.documents.iter_mut()
+        .find(|r| r.path == path)
+        .ok_or_else(|| format!("Document not in recent list: {}", path.display()))?;
+    entry.pinned = pinned;
+    
+    trim_recent(&mut file.documents, file.settings.max_entries);
+    save_recent_file(&file)?;
+    Ok(file.documents)
+}
+
+/// Contents of a KMD file extracted for an open document
+pub(crate) struct ExtractedKmd {
+    /// The checkpoint with the file's updates folded in
+    pub(crate) yjs_state: Vec<u8>,
+    pub(crate) history_path: PathBuf,
+    pub(crate) meta: DocumentMeta,
+    pub(crate) sections: HashMap<String, SectionState>,
+    /// What safe mode left out of the file
+    pub(crate) stripped: Vec<String>,
+}
+
+/// Read the updates/ chunks of a v0.2 archive, ordered by author and sequence
+fn read_yjs_updates<R: Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+    progress: &mut FileProgress,
+) -> Result<Vec<YjsUpdate>, String> {
+    let names: Vec<String> = archive
+        .file_names()
+        .filter(|n| n.starts_with(UPDATES_DIR) && !n.ends_with('/'))
+        .map(|n| n.to_string())
+        .collect();
+
+    let mut updates = Vec::new();
+    for name in names {
+        let (author, seq) = YjsUpdate::parse_entry_name(&name)
+            .ok_or_else(|| format!("Invalid update entry in KMD file: {}", name))?;
+        let mut entry = archive.by_name(&name).map_err(|e| e.to_string())?;
+        let data = read_entry(&mut entry, progress)?;
+        updates.push(YjsUpdate { author, seq, data });
+    }
+    updates.sort_by(|a, b| a.author.cmp(&b.author).then(a.seq.cmp(&b.seq)));
+    Ok(updates)
+}
+
+/// Fold updates into a checkpoint, as the editor loads them
+fn fold_updates(yjs_state: Vec<u8>, updates: &[YjsUpdate]) -> Result<Vec<u8>, String> {
+    if updates.is_empty() {
+        return Ok(yjs_state);
+    }
+    let mut parts: Vec<&[u8]> = vec![&yjs_state];
+    parts.extend(updates.iter().map(|u| u.data.as_slice()));
+    merge_states(&parts)
+}
+
+/// Extract a KMD file to a document temp directory, in safe mode unless it
+/// is among the recent documents
+pub(crate) fn extract_kmd_to_temp(kmd_path: &PathBuf, doc_id: &str) -> Result<ExtractedKmd, String> {
+    Ok(extract_kmd_with_progress(kmd_path, doc_id, !is_trusted(kmd_path), &mut FileProgress::silent())?)
+}
+
+/// Read a whole archive entry, reporting its bytes
+fn read_entry<R: Read + ?Sized>(entry: &mut R, progress: &mut FileProgress) -> Result<Vec<u8>, KorppiError> {
+    let mut data = Vec::new();
+    progress.copy(entry, &mut data)?;
+    Ok(data)
+}
+
+/// Extract a KMD file to a document temp directory, reporting progress
+///
+/// In safe mode the archive and its histories are checked first and
+/// scriptable content is left out (see `safe_open`). The temp directory is
+/// left behind on failure; the caller removes it.
+pub(crate) fn extract_kmd_with_progress(
+    kmd_path: &PathBuf,
+    doc_id: &str,
+    safe_mode: bool,
+    progress: &mut FileProgress,
+) -> Result<ExtractedKmd, KorppiError> {
+    let file = File::open(kmd_path)?;
+    let mut archive = ZipArchive::new(file)?;
+    let mut stripped = Vec::new();
+    if safe_mode {
+        check_archive(&mut archive)?;
+    }
+    
+    let mut total = 0;
+    for i in 0..archive.len() {
+        total += archive.by_index(i)?.size();
+    }
+    progress.set_total(total);
+    progress.phase("metadata")?;
     
     let temp_dir = create_document_temp_dir(doc_id)?;
     
@@ -160,11 +617,12 @@ fn extract_kmd_to_temp(kmd_path: &PathBuf, doc_id: &str) -> Result<(Vec<u8>, Pat
             .by_name("format.json")
             .map_err(|_| "Missing format.json in KMD file")?;
         let mut content = String::new();
-        format_file.read_to_string(&mut content).map_err(|e| e.to_string())?;
+        format_file.read_to_string(&mut content)?;
         serde_json::from_str(&content).map_err(|e| format!("Invalid format.json: {}", e))?
     };
     
     check_version_compatibility(&format_info)?;
+    let layout = kmd_layout(&format_info);
     
     // Read meta.json
     let meta: DocumentMeta = {
@@ -172,95 +630,480 @@ fn extract_kmd_to_temp(kmd_path: &PathBuf, doc_id: &str) -> Result<(Vec<u8>, Pat
             .by_name("meta.json")
             .map_err(|_| "Missing meta.json in KMD file")?;
         let mut content = String::new();
-        meta_file.read_to_string(&mut content).map_err(|e| e.to_string())?;
+        meta_file.read_to_string(&mut content)?;
         serde_json::from_str(&content).map_err(|e| format!("Invalid meta.json: {}", e))?
     };
     
-    // Extract state.yjs
+    // Extract state.yjs (the whole document in v0.1, a checkpoint in v0.2)
+    progress.phase("state")?;
     let yjs_state = if let Ok(mut state_file) = archive.by_name("state.yjs") {
-        let mut state_data = Vec::new();
-        state_file.read_to_end(&mut state_data).map_err(|e| e.to_string())?;
-        state_data
+        read_entry(&mut state_file, progress)?
     } else {
         Vec::new()
     };
     
+    let yjs_updates = match layout {
+        KmdLayout::Chunked => read_yjs_updates(&mut archive, progress)?,
+        KmdLayout::Monolithic => Vec::new(),
+    };
+    
+    // Fold the updates into the state so the editor loads the whole document.
+    // They are not kept: the next save writes them as part of the checkpoint
+    let yjs_state = fold_updates(yjs_state, &yjs_updates)?;
+    
     // Extract history.sqlite to temp dir
+    progress.phase("history")?;
     let history_path = temp_dir.join("history.sqlite");
     if let Ok(mut history_file) = archive.by_name("history.sqlite") {
-        let mut history_data = Vec::new();
-        history_file.read_to_end(&mut history_data).map_err(|e| e.to_string())?;
-        fs::write(&history_path, &history_data).map_err(|e| e.to_string())?;
+        let mut out = File::create(&history_path)?;
+        progress.copy(&mut history_file, &mut out)?;
+        drop(out);
+        if safe_mode {
+            stripped.extend(harden_history(&history_path, "history.sqlite")?);
+        }
+        // Upgrade histories written by older versions right away
+        let conn = Connection::open(&history_path)?;
+        ensure_schema(&conn)?;
+    }
+    
+    // Extract assets/ next to the history
+    progress.phase("assets")?;
+    let asset_names: Vec<String> = archive
+        .file_names()
+        .filter(|n| n.starts_with(&format!("{}/", ASSETS_DIR)) && !n.ends_with('/'))
+        .map(|n| n.to_string())
+        .collect();
+    for name in asset_names {
+        let file_name = &name[ASSETS_DIR.len() + 1..];
+        if !is_path_safe(&name) || file_name.contains(['/', '\\']) {
+            return Err(format!("Invalid asset in KMD file: {}", name).into());
+        }
+        let assets_dir = document_assets_dir(&history_path);
+        fs::create_dir_all(&assets_dir)?;
+        let mut entry = archive.by_name(&name)?;
+        if safe_mode {
+            let data = read_entry(&mut entry, progress)?;
+            if is_scriptable_asset(&name, &data) {
+                stripped.push(name);
+                continue;
+            }
+            fs::write(assets_dir.join(file_name), &data)?;
+            continue;
+        }
+        let mut out = File::create(assets_dir.join(file_name))?;
+        progress.copy(&mut entry, &mut out)?;
+    }
+    
+    // Extract authors/ next to the history, so profiles survive a save
+    let profile_names: Vec<String> = archive
+        .file_names()
+        .filter(|n| n.starts_with(&format!("{}/", AUTHORS_DIR)) && n.ends_with(".json"))
+        .map(|n| n.to_string())
+        .collect();
+    for name in profile_names {
+        let file_name = &name[AUTHORS_DIR.len() + 1..];
+        if !is_path_safe(&name) || file_name.contains(['/', '\\']) {
+            continue;
+        }
+        let roster_dir = document_roster_dir(&history_path);
+        fs::create_dir_all(&roster_dir)?;
+        let mut entry = archive.by_name(&name)?;
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        if safe_mode {
+            let (profile, left_out) = sanitize_author_profile(&name, &data);
+            stripped.extend(left_out);
+            match profile {
+                Some(profile) => data = profile,
+                None => continue,
+            }
+        }
+        fs::write(roster_dir.join(file_name), &data)?;
+    }
+    
+    // Extract each project section to temp_dir/sections/<id>/
+    progress.phase("sections")?;
+    let mut sections = HashMap::new();
+    for section in &meta.sections {
+        if !is_path_safe(&section.id) || section.id.contains(['/', '\\']) {
+            return Err(format!("Invalid section ID: {}", section.id).into());
+        }
+        let section_dir = temp_dir.join("sections").join(&section.id);
+        fs::create_dir_all(&section_dir)?;
+        
+        let mut section_state = Vec::new();
+        if let Ok(mut state_file) = archive.by_name(&section.entry_name("state.yjs")) {
+            section_state = read_entry(&mut state_file, progress)?;
+        }
+        let section_history = section_dir.join("history.sqlite");
+        if let Ok(mut history_file) = archive.by_name(&section.entry_name("history.sqlite")) {
+            let mut out = File::create(&section_history)?;
+            progress.copy(&mut history_file, &mut out)?;
+            drop(out);
+            if safe_mode {
+                stripped.extend(harden_history(&section_history, &section.entry_name("history.sqlite"))?);
+            }
+        }
+        sections.insert(section.id.clone(), SectionState {
+            yjs_state: section_state,
+            history_path: section_history,
+        });
     }
     
-    Ok((yjs_state, history_path, meta))
+    Ok(ExtractedKmd {
+        yjs_state,
+        history_path,
+        meta,
+        sections,
+        stripped,
+    })
+}
+
+/// Whether two states each have changes the other lacks
+fn diverged_states(ours: &[u8], theirs: &[u8]) -> Result<bool, String> {
+    if ours.is_empty() || theirs.is_empty() {
+        return Ok(false);
+    }
+    has_diverged(ours, theirs)
+}
+
+/// Merge the sections of a copy of a project saved elsewhere into the open
+/// one: shared sections get both states and histories, and sections only
+/// the copy has are added after the section they follow there. Sections
+/// only we have are kept. Returns whether a shared section diverged.
+fn merge_sections(
+    sections: &mut HashMap<String, SectionState>,
+    order: &mut Vec<SectionRef>,
+    incoming: &ExtractedKmd,
+    sections_dir: &Path,
+) -> Result<bool, String> {
+    let mut diverged = false;
+    for (index, section) in incoming.meta.sections.iter().enumerate() {
+        let Some(theirs) = incoming.sections.get(&section.id) else {
+            continue;
+        };
+        if let Some(ours) = sections.get_mut(&section.id) {
+            diverged |= diverged_states(&ours.yjs_state, &theirs.yjs_state)?;
+            ours.yjs_state = merge_states(&[&ours.yjs_state, &theirs.yjs_state])?;
+            if theirs.history_path.exists() {
+                let source = Connection::open(&theirs.history_path).map_err(|e| e.to_string())?;
+                ensure_schema(&source)?;
+                crate::patch_log::import_history(&source, &PatchStore::open(&ours.history_path)?)?;
+            }
+            continue;
+        }
+        
+        let section_dir = sections_dir.join(&section.id);
+        fs::create_dir_all(&section_dir).map_err(|e| e.to_string())?;
+        let history_path = section_dir.join("history.sqlite");
+        if theirs.history_path.exists() {
+            fs::copy(&theirs.history_path, &history_path).map_err(|e| e.to_string())?;
+        }
+        sections.insert(section.id.clone(), SectionState { yjs_state: theirs.yjs_state.clone(), history_path });
+        let position = incoming.meta.sections[..index]
+            .iter()
+            .rev()
+            .find_map(|previous| order.iter().position(|s| s.id == previous.id))
+            .map_or(0, |p| p + 1);
+        order.insert(position, section.clone());
+    }
+    Ok(diverged)
+}
+
+/// Read the markdown and metadata of a KMD file without opening it
+///
+/// Projects are returned as their sections joined in order.
+pub(crate) fn read_kmd_markdown(kmd_path: &Path) -> Result<(String, DocumentMeta), String> {
+    let doc_id = format!("read-{}", Uuid::new_v4());
+    let result = extract_kmd_to_temp(&kmd_path.to_path_buf(), &doc_id).and_then(|extracted| {
+        let text = |state: &[u8]| if state.is_empty() { Ok(String::new()) } else { document_text(&[state], false) };
+        let content = if extracted.meta.sections.is_empty() {
+            text(&extracted.yjs_state)?
+        } else {
+            let texts = extracted
+                .meta
+                .sections
+                .iter()
+                .filter_map(|s| extracted.sections.get(&s.id))
+                .map(|s| text(&s.yjs_state))
+                .collect::<Result<Vec<_>, _>>()?;
+            crate::sections::join_sections(&texts)
+        };
+        Ok((content, extracted.meta))
+    });
+    cleanup_document_temp_dir(&doc_id).ok();
+    result
 }
 
 /// Bundle a document state into a KMD file
-fn bundle_to_kmd(
+///
+/// Always writes the v0.2 layout: state.yjs as a checkpoint (when known)
+/// plus one file per update under updates/. Project sections go under
+/// sections/<id>/.
+pub(crate) fn bundle_to_kmd(
     kmd_path: &PathBuf,
     yjs_state: &[u8],
+    yjs_updates: &[YjsUpdate],
     history_path: &PathBuf,
     meta: &DocumentMeta,
-) -> Result<(), String> {
-    let file = File::create(kmd_path).map_err(|e| format!("Failed to create file: {}", e))?;
+    sections: &HashMap<String, SectionState>,
+) -> Result<(), KorppiError> {
+    bundle_to_kmd_with_progress(kmd_path, yjs_state, yjs_updates, history_path, meta, sections, &mut FileProgress::silent())
+}
+
+/// Bundle a document state into a KMD file, reporting progress
+///
+/// The archive is written next to the destination and moved over it once
+/// complete, so a failed or cancelled save leaves the previous file intact.
+pub(crate) fn bundle_to_kmd_with_progress(
+    kmd_path: &PathBuf,
+    yjs_state: &[u8],
+    yjs_updates: &[YjsUpdate],
+    history_path: &PathBuf,
+    meta: &DocumentMeta,
+    sections: &HashMap<String, SectionState>,
+    progress: &mut FileProgress,
+) -> Result<(), KorppiError> {
+    let part_path = kmd_path.with_extension("kmd.part");
+    let result = write_kmd(&part_path, yjs_state, yjs_updates, history_path, meta, sections, progress)
+        .and_then(|_| Ok(fs::rename(&part_path, kmd_path)?));
+    if result.is_err() {
+        fs::remove_file(&part_path).ok();
+    }
+    result
+}
+
+/// Size of a file, 0 when missing
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+fn write_kmd(
+    kmd_path: &Path,
+    yjs_state: &[u8],
+    yjs_updates: &[YjsUpdate],
+    history_path: &PathBuf,
+    meta: &DocumentMeta,
+    sections: &HashMap<String, SectionState>,
+    progress: &mut FileProgress,
+) -> Result<(), KorppiError> {
+    let assets_dir = document_assets_dir(history_path);
+    let mut assets: Vec<_> = if assets_dir.is_dir() {
+        fs::read_dir(&assets_dir)
+            ?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_file())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    assets.sort_by_key(|entry| entry.file_name());
+    
+    let total = yjs_state.len() as u64
+        + yjs_updates.iter().map(|u| u.data.len() as u64).sum::<u64>()
+        + file_size(history_path)
+        + assets.iter().map(|a| file_size(&a.path())).sum::<u64>()
+        + sections.values().map(|s| s.yjs_state.len() as u64 + file_size(&s.history_path)).sum::<u64>();
+    progress.set_total(total);
+    progress.phase("state")?;
+    
+    let file = File::create(kmd_path)?;
     let mut zip = ZipWriter::new(file);
-    let options = FileOptions::default()
-        .compression_method(zip::CompressionMethod::Deflated)
-        .unix_permissions(0o644);
+    let compression = meta.settings.compression;
+    let options = compression.entry_options("meta.json");
     
-    // Write format.json
-    let format_info = FormatInfo::default();
-    let format_json = serde_json::to_string_pretty(&format_info).map_err(|e| e.to_string())?;
-    zip.start_file("format.json", options).map_err(|e| e.to_string())?;
-    zip.write_all(format_json.as_bytes()).map_err(|e| e.to_string())?;
+    // Write format.json; v0.1 readers only see the checkpoint, so files
+    // with updates need a v0.2 reader
+    let format_info = format_info_for(yjs_updates.is_empty(), !meta.sections.is_empty(), compression);
+    let format_json = serde_json::to_string_pretty(&format_info)?;
+    zip.start_file("format.json", options)?;
+    zip.write_all(format_json.as_bytes())?;
     
     // Write state.yjs
     if !yjs_state.is_empty() {
-        zip.start_file("state.yjs", options).map_err(|e| e.to_string())?;
-        zip.write_all(yjs_state).map_err(|e| e.to_string())?;
+        zip.start_file("state.yjs", compression.entry_options("state.yjs"))?;
+        progress.copy(&mut &yjs_state[..], &mut zip)?;
+    }
+    
+    // Write updates/<author>/<seq>.yjs
+    progress.phase("updates")?;
+    for update in yjs_updates {
+        zip.start_file(update.entry_name(), options)?;
+        progress.copy(&mut update.data.as_slice(), &mut zip)?;
     }
     
     // Write history.sqlite
+    progress.phase("history")?;
     if history_path.exists() {
-        let history_data = fs::read(history_path).map_err(|e| e.to_string())?;
-        zip.start_file("history.sqlite", options).map_err(|e| e.to_string())?;
-        zip.write_all(&history_data).map_err(|e| e.to_string())?;
+        let mut history_file = File::open(history_path)?;
+        zip.start_file("history.sqlite", compression.entry_options("history.sqlite"))?;
+        progress.copy(&mut history_file, &mut zip)?;
+    }
+    
+    // Write assets/<name>
+    progress.phase("assets")?;
+    for asset in assets {
+        let mut asset_file = File::open(asset.path())?;
+        zip.start_file(format!("{}/{}", ASSETS_DIR, asset.file_name().to_string_lossy()), options)?;
+        progress.copy(&mut asset_file, &mut zip)?;
+    }
+    
+    // Write sections/<id>/state.yjs and history.sqlite in project order
+    progress.phase("sections")?;
+    for section in &meta.sections {
+        let Some(state) = sections.get(&section.id) else {
+            continue;
+        };
+        let state_entry = section.entry_name("state.yjs");
+        zip.start_file(&state_entry, compression.entry_options(&state_entry))?;
+        progress.copy(&mut state.yjs_state.as_slice(), &mut zip)?;
+        if state.history_path.exists() {
+            let mut history_file = File::open(&state.history_path)?;
+            let history_entry = section.entry_name("history.sqlite");
+            zip.start_file(&history_entry, compression.entry_options(&history_entry))?;
+            progress.copy(&mut history_file, &mut zip)?;
+        }
     }
     
     // Write meta.json
-    let meta_json = serde_json::to_string_pretty(meta).map_err(|e| e.to_string())?;
-    zip.start_file("meta.json", options).map_err(|e| e.to_string())?;
-    zip.write_all(meta_json.as_bytes()).map_err(|e| e.to_string())?;
+    progress.phase("metadata")?;
+    let meta_json = serde_json::to_string_pretty(meta)?;
+    zip.start_file("meta.json", options)?;
+    zip.write_all(meta_json.as_bytes())?;
     
     // Write authors directory
-    zip.add_directory("authors/", options).map_err(|e| e.to_string())?;
+    zip.add_directory(format!("{}/", AUTHORS_DIR), options)?;
     
-    // Write author profiles
+    // Write author profiles, keeping collaborators' colors and avatars
+    let local_profile = load_saved_profile().unwrap_or_default();
+    let roster_dir = document_roster_dir(history_path);
     for author in &meta.authors {
-        let profile = AuthorProfile {
-            id: author.id.clone(),
-            name: author.name.clone(),
-            email: author.email.clone(),
-            color: DEFAULT_AUTHOR_COLOR.to_string(),
-            avatar_base64: None,
-            public_key: None,
-        };
-        let profile_json = serde_json::to_string_pretty(&profile).map_err(|e| e.to_string())?;
-        let author_file = format!("authors/{}.json", author.id);
-        zip.start_file(&author_file, options).map_err(|e| e.to_string())?;
-        zip.write_all(profile_json.as_bytes()).map_err(|e| e.to_string())?;
+        let profile = kmd_author_profile(author, &roster_dir, local_profile.as_ref());
+        let profile_json = serde_json::to_string_pretty(&profile)?;
+        let author_file = format!("{}/{}.json", AUTHORS_DIR, author.id);
+        zip.start_file(&author_file, options)?;
+        zip.write_all(profile_json.as_bytes())?;
+    }
+    
+    zip.finish()?;
+    Ok(())
+}
+
+/// What to keep when exporting a document without its history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanExportOptions {
+    /// Author IDs to keep in the metadata; all authors when absent
+    #[serde(default)]
+    pub authors: Option<Vec<String>>,
+    #[serde(default = "default_true")]
+    pub strip_comments: bool,
+    #[serde(default = "default_true")]
+    pub strip_reviews: bool,
+}
+
+/// Write a history database holding only the final snapshot patch
+///
+/// The latest Save patch is kept as is when its snapshot matches `content`,
+/// otherwise a new Save patch is recorded for the current text.
+fn build_clean_history(
+    source: &Path,
+    dest: &Path,
+    content: &str,
+    options: &CleanExportOptions,
+) -> Result<(), String> {
+    let conn = Connection::open(dest).map_err(|e| e.to_string())?;
+    ensure_schema(&conn)?;
+    
+    let last_save = if source.exists() {
+        let source_conn = Connection::open(source).map_err(|e| e.to_string())?;
+        ensure_schema(&source_conn)?;
+        source_conn
+            .query_row(
+                "SELECT id, timestamp, author, kind, data, uuid, parent_uuid FROM patches WHERE kind = 'Save' ORDER BY id DESC LIMIT 1",
+                [],
+                row_to_patch,
+            )
+            .optional()
+            .map_err(|e| e.to_string())?
+            .filter(|p| p.data.get("snapshot").and_then(|s| s.as_str()) == Some(content))
+    } else {
+        None
+    };
+    
+    let patch = match last_save {
+        Some(patch) => patch,
+        None => {
+            let profile = load_saved_profile()?;
+            crate::patch_log::Patch {
+                id: 0,
+                timestamp: Utc::now().timestamp_millis(),
+                author: profile.as_ref().map(|p| p.id.clone()).unwrap_or_else(|| "local".to_string()),
+                kind: "Save".to_string(),
+                data: serde_json::json!({
+                    "snapshot": content,
+                    "authorName": profile.as_ref().map(|p| p.name.clone()).unwrap_or_else(|| "Local User".to_string()),
+                    "authorColor": profile.as_ref().map(|p| p.color.clone()).unwrap_or_else(|| DEFAULT_AUTHOR_COLOR.to_string()),
+                }),
+                uuid: Some(Uuid::new_v4().to_string()),
+                parent_uuid: None,
+            }
+        }
+    };
+    
+    PatchStore::on(&conn).insert_patch(
+        &crate::patch_log::PatchInput {
+            timestamp: patch.timestamp,
+            author: patch.author,
+            kind: "Save".to_string(),
+            data: patch.data,
+            uuid: patch.uuid.clone(),
+            parent_uuid: None,
+        },
+        Some(content.as_bytes()),
+    )?;
+    
+    if !source.exists() || (options.strip_comments && options.strip_reviews) {
+        return Ok(());
+    }
+    
+    conn.execute("ATTACH DATABASE ?1 AS source", params![source.to_string_lossy()])
+        .map_err(|e| e.to_string())?;
+    
+    if !options.strip_reviews {
+        conn.execute(
+            "INSERT INTO patch_reviews SELECT * FROM source.patch_reviews WHERE patch_uuid = ?1",
+            params![patch.uuid],
+        ).map_err(|e| e.to_string())?;
+    }
+    
+    if !options.strip_comments {
+        crate::comments::init_comments_table(&conn)?;
+        let has_comments: bool = conn
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM source.sqlite_master WHERE type = 'table' AND name = 'comments'",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if has_comments {
+            conn.execute_batch(
+                "INSERT INTO comments SELECT * FROM source.comments;
+                 INSERT INTO comment_events SELECT * FROM source.comment_events;",
+            ).map_err(|e| e.to_string())?;
+        }
     }
     
-    zip.finish().map_err(|e| e.to_string())?;
+    conn.execute("DETACH DATABASE source", []).map_err(|e| e.to_string())?;
     Ok(())
 }
 
 /// Create a new empty document
 #[tauri::command]
 pub fn new_document(
+    app: AppHandle,
+    window: Window,
     manager: State<'_, Mutex<DocumentManager>>,
-) -> Result<DocumentHandle, String> {
+) -> Result<DocumentHandle, KorppiError> {
     let doc_id = Uuid::new_v4().to_string();
     let temp_dir = create_document_temp_dir(&doc_id)?;
     
@@ -270,6 +1113,10 @@ pub fn new_document(
         title: "Untitled Document".to_string(),
         is_modified: false,
         opened_at: Utc::now(),
+        read_only: false,
+        external_merge: None,
+        recovered: None,
+        safe_mode: None,
     };
     
     let meta = DocumentMeta::default();
@@ -277,24 +1124,39 @@ pub fn new_document(
     let state = DocumentState {
         handle: handle.clone(),
         yjs_state: Vec::new(),
+        yjs_updates: Vec::new(),
         history_path: temp_dir.join("history.sqlite"),
         meta,
+        sections: HashMap::new(),
+        disk_fingerprint: None,
     };
     
     let mut manager = manager.lock().map_err(|e| e.to_string())?;
     manager.documents.insert(doc_id.clone(), state);
-    manager.active_document_id = Some(doc_id);
+    manager.set_active(window.label(), doc_id);
     
+    emit_event(&app, DOCUMENT_OPENED, handle.clone());
     Ok(handle)
 }
 
 /// Open a document (shows file picker if path is None)
+///
+/// Progress is reported as `file-progress` events under `job_id` (the new
+/// document ID when not given), which `cancel_file_job` accepts. Files that
+/// are not among the recent documents open in safe mode unless `safe_mode`
+/// says otherwise.
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn open_document(
     app: AppHandle,
+    window: Window,
     manager: State<'_, Mutex<DocumentManager>>,
+    jobs: State<'_, Mutex<FileJobs>>,
     path: Option<String>,
-) -> Result<DocumentHandle, String> {
+    read_only: Option<bool>,
+    job_id: Option<String>,
+    safe_mode: Option<bool>,
+) -> Result<DocumentHandle, KorppiError> {
     use tauri_plugin_dialog::DialogExt;
     
     let file_path: PathBuf = if let Some(p) = path {
@@ -308,16 +1170,58 @@ pub async fn open_document(
         
         match file {
             Some(f) => f.into_path().map_err(|_| "Failed to convert file path".to_string())?,
-            None => return Err("No file selected".to_string()),
+            None => return Err("No file selected".into()),
         }
     };
     
     if !file_path.exists() {
-        return Err(format!("File not found: {:?}", file_path));
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("File not found: {:?}", file_path)).into());
+    }
+    
+    // Another instance holding the lock only allows a read-only open
+    let read_only = read_only.unwrap_or(false);
+    if !read_only {
+        acquire_lock(&file_path)?;
     }
     
+    let safe_mode = safe_mode.unwrap_or_else(|| !is_trusted(&file_path));
     let doc_id = Uuid::new_v4().to_string();
-    let (yjs_state, history_path, mut meta) = extract_kmd_to_temp(&file_path, &doc_id)?;
+    let job_id = job_id.unwrap_or_else(|| doc_id.clone());
+    let cancel = jobs.lock().map_err(|e| e.to_string())?.start(&job_id);
+    let mut progress = FileProgress::new(app.clone(), &job_id, "open", cancel);
+    let (kmd_path, extract_id) = (file_path.clone(), doc_id.clone());
+    let extracted = tauri::async_runtime::spawn_blocking(move || {
+        extract_kmd_with_progress(&kmd_path, &extract_id, safe_mode, &mut progress)
+    })
+    .await
+    .map_err(|e| KorppiError::from(e.to_string()))
+    .and_then(|result| result);
+    jobs.lock().map_err(|e| e.to_string())?.finish(&job_id);
+    
+    let ExtractedKmd {
+        mut yjs_state,
+        history_path,
+        mut meta,
+        sections,
+        stripped,
+    } = extracted.inspect_err(|_| {
+        cleanup_document_temp_dir(&doc_id).ok();
+        if !read_only {
+            release_lock(&file_path);
+        }
+    })?;
+    
+    let disk_fingerprint = DiskFingerprint::read(&file_path).ok();
+    
+    // A journal left by a crashed session of this file holds edits it lacks
+    let mut yjs_updates = Vec::new();
+    let mut recovered = None;
+    if let (false, Some(fingerprint)) = (read_only, &disk_fingerprint) {
+        recovered = recover_crashed_session(&manager, &file_path, &fingerprint.sha256, &history_path, &mut yjs_state, &mut yjs_updates);
+        if let Err(e) = journal::restart(&history_path, &file_path, &fingerprint.sha256, &[]) {
+            log::warn!("Cannot start the journal of {}: {}", file_path.display(), e);
+        }
+    }
     
     // Use filename as title if meta has default "Untitled Document"
     let title = if meta.title == "Untitled Document" {
@@ -335,48 +1239,131 @@ pub async fn open_document(
         id: doc_id.clone(),
         path: Some(file_path.clone()),
         title,
-        is_modified: false,
+        is_modified: recovered.is_some(),
         opened_at: Utc::now(),
+        read_only,
+        external_merge: None,
+        recovered: recovered.clone(),
+        safe_mode: safe_mode.then_some(stripped),
     };
     
     let state = DocumentState {
         handle: handle.clone(),
         yjs_state: yjs_state.clone(),
+        yjs_updates,
         history_path,
         meta,
+        sections,
+        disk_fingerprint,
     };
     
     // Add to recent documents
     add_to_recent(file_path.clone(), handle.title.clone())?;
     
+    log_event(&app, "open", Some(&doc_id), serde_json::json!({
+        "path": file_path,
+        "read_only": read_only,
+        "safe_mode": safe_mode,
+        "stripped": handle.safe_mode.as_ref().map_or(0, |s| s.len()),
+        "yjs_updates": state.yjs_updates.len(),
+        "sections": state.sections.len(),
+    }));
+    if let Some(report) = &recovered {
+        log_event(&app, "recovered", Some(&doc_id), serde_json::json!({
+            "path": file_path,
+            "updates": report.updates,
+            "patches": report.patches,
+            "dropped_patches": report.dropped_patches.len(),
+        }));
+    }
+    
     let mut manager = manager.lock().map_err(|e| e.to_string())?;
     manager.documents.insert(doc_id.clone(), state);
-    manager.active_document_id = Some(doc_id);
+    manager.set_active(window.label(), doc_id);
     
+    emit_event(&app, DOCUMENT_OPENED, handle.clone());
     Ok(handle)
 }
 
+/// Replay the journal of a crashed session of a file onto the state and
+/// history just read from it, then remove that session's directory
+fn recover_crashed_session(
+    manager: &Mutex<DocumentManager>,
+    path: &Path,
+    file_hash: &str,
+    history_path: &Path,
+    yjs_state: &mut Vec<u8>,
+    yjs_updates: &mut Vec<YjsUpdate>,
+) -> Option<RecoveryReport> {
+    let open_ids: HashSet<String> = manager.lock().ok()?.documents.keys().cloned().collect();
+    let journal = find_orphaned_journal(&get_temp_base_dir().ok()?, path, file_hash, &open_ids)?;
+    
+    let (mut state, mut updates) = (yjs_state.clone(), yjs_updates.clone());
+    let result = read_journal(&journal).and_then(|entries| {
+        let conn = Connection::open(history_path).map_err(|e| e.to_string())?;
+        ensure_schema(&conn)?;
+        let author = load_saved_profile()?
+            .map(|p| p.id)
+            .unwrap_or_else(|| "local".to_string());
+        let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+        let report = reconcile(entries, &mut state, &mut updates, &tx, &author, Utc::now().timestamp_millis())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(report)
+    });
+    match result {
+        Ok(report) => {
+            *yjs_state = state;
+            *yjs_updates = updates;
+            if let Some(dir) = journal.parent() {
+                fs::remove_dir_all(dir).ok();
+            }
+            Some(report)
+        }
+        Err(e) => {
+            log::warn!("Cannot recover the crashed session of {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
 /// Save document (Save As if path provided)
+///
+/// Progress is reported as `file-progress` events under `job_id` (the
+/// document ID when not given). A cancelled save leaves the file on disk as
+/// it was.
 #[tauri::command]
 pub async fn save_document(
     app: AppHandle,
     manager: State<'_, Mutex<DocumentManager>>,
+    jobs: State<'_, Mutex<FileJobs>>,
     id: String,
     path: Option<String>,
-) -> Result<DocumentHandle, String> {
+    job_id: Option<String>,
+) -> Result<DocumentHandle, KorppiError> {
     use tauri_plugin_dialog::DialogExt;
     
     // Get mutable reference to document state
-    let (yjs_state, history_path, mut meta, existing_path) = {
+    let (yjs_state, yjs_updates, history_path, mut meta, existing_path, sections, disk_fingerprint) = {
         let manager = manager.lock().map_err(|e| e.to_string())?;
         let doc = manager.documents.get(&id)
-            .ok_or_else(|| format!("Document not found: {}", id))?;
-        (doc.yjs_state.clone(), doc.history_path.clone(), doc.meta.clone(), doc.handle.path.clone())
+            .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
+        if doc.handle.read_only {
+            return Err("Document is open read-only".into());
+        }
+        (
+            doc.yjs_state.clone(),
+            doc.yjs_updates.clone(),
+            doc.history_path.clone(),
+            doc.meta.clone(),
+            doc.handle.path.clone(),
+            doc.sections.clone(),
+            doc.disk_fingerprint.clone(),
+        )
     };
     
     let save_path: PathBuf = if let Some(p) = path {
         PathBuf::from(p)
-    } else if let Some(p) = existing_path {
+    } else if let Some(p) = existing_path.clone() {
         p
     } else {
         // Show save dialog
@@ -388,7 +1375,7 @@ pub async fn save_document(
         
         match file {
             Some(f) => f.into_path().map_err(|_| "Failed to convert save path".to_string())?,
-            None => return Err("Save cancelled".to_string()),
+            None => return Err("Save cancelled".into()),
         }
     };
     
@@ -403,18 +1390,136 @@ pub async fn save_document(
         }
     }
     
-    // Bundle to KMD
-    bundle_to_kmd(&save_path, &yjs_state, &history_path, &meta)?;
-    
-    // Update document state
-    let mut manager = manager.lock().map_err(|e| e.to_string())?;
-    if let Some(doc) = manager.documents.get_mut(&id) {
-        doc.handle.path = Some(save_path.clone());
-        doc.handle.is_modified = false;
-        doc.meta = meta.clone();
+    // Saving under a new name moves the lock to the new file
+    let moved = existing_path.as_ref() != Some(&save_path);
+    
+    // Someone else saved the file since we read it: import their patches and
+    // merge their state instead of overwriting, and let the user save again
+    let changed_on_disk = disk_fingerprint.is_some_and(|f| !f.matches(&save_path));
+    if !moved && save_path.exists() && changed_on_disk {
+        let imported = crate::patch_log::import_patches_from_document(
+            save_path.to_string_lossy().to_string(),
+            id.clone(),
+            app.clone(),
+        )?;
+        let fingerprint = DiskFingerprint::read(&save_path).ok();
+        let sections_dir = create_document_temp_dir(&id)?.join("sections");
+        let read_id = format!("merge-{}", Uuid::new_v4());
+        let merged = extract_kmd_to_temp(&save_path, &read_id).and_then(|incoming| {
+            let mut manager = manager.lock().map_err(|e| e.to_string())?;
+            let doc = manager.documents.get_mut(&id)
+                .ok_or_else(|| "Document not found after merge".to_string())?;
+            let mut diverged = diverged_states(&doc.yjs_state, &incoming.yjs_state)?;
+            doc.yjs_state = merge_states(&[&doc.yjs_state, &incoming.yjs_state])?;
+            diverged |= merge_sections(&mut doc.sections, &mut doc.meta.sections, &incoming, &sections_dir)?;
+            journal_state(doc)?;
+            doc.handle.is_modified = true;
+            doc.disk_fingerprint = fingerprint;
+            Ok((diverged, doc.handle.clone()))
+        });
+        cleanup_document_temp_dir(&read_id).ok();
+        let (diverged, mut handle) = merged?;
         
-        // Update title from filename if untitled
-        if doc.handle.title == "Untitled Document" {
+        let imported_patches: Vec<String> = imported.into_iter().filter_map(|p| p.uuid).collect();
+        log_event(&app, "external_merge", Some(&id), serde_json::json!({
+            "path": save_path,
+            "imported_patches": imported_patches.len(),
+            "diverged": diverged,
+        }));
+        
+        handle.external_merge = Some(ExternalMergeResult {
+            imported_patches,
+            diverged,
+        });
+        return Ok(handle);
+    }
+    
+    if moved {
+        acquire_lock(&save_path)?;
+    }
+    
+    // Bundle to KMD
+    let job_id = job_id.unwrap_or_else(|| id.clone());
+    let cancel = jobs.lock().map_err(|e| e.to_string())?.start(&job_id);
+    let mut progress = FileProgress::new(app.clone(), &job_id, "save", cancel);
+    let update_count = yjs_updates.len();
+    let saved_state = yjs_state.clone();
+    let (kmd_path, bundle_history, bundle_meta) = (save_path.clone(), history_path.clone(), meta.clone());
+    let bundled = tauri::async_runtime::spawn_blocking(move || -> Result<Vec<u8>, KorppiError> {
+        bundle_to_kmd_with_progress(&kmd_path, &yjs_state, &yjs_updates, &bundle_history, &bundle_meta, &sections, &mut progress)?;
+        // The state the saved file opens with
+        Ok(fold_updates(yjs_state, &yjs_updates)?)
+    })
+    .await
+    .map_err(|e| KorppiError::from(e.to_string()))
+    .and_then(|result| result);
+    jobs.lock().map_err(|e| e.to_string())?.finish(&job_id);
+    let checkpoint = bundled.inspect_err(|e| {
+        log_event(&app, "save_failed", Some(&id), serde_json::json!({ "path": save_path, "error": e.to_string() }));
+        if moved {
+            release_lock(&save_path);
+        }
+    })?;
+    log_event(&app, "save", Some(&id), serde_json::json!({
+        "path": save_path,
+        "save_as": moved,
+        "yjs_updates": update_count,
+        "patches": count_patches(&history_path),
+    }));
+    if moved {
+        if let Some(old_path) = &existing_path {
+            release_lock(old_path);
+        }
+    }
+    
+    // Copy the saved file to the backup directory once a backup is due
+    if let Some(backup) = effective_backup_settings(meta.settings.backup.as_ref()) {
+        let (backup_path, backup_id, backup_app) = (save_path.clone(), id.clone(), app.clone());
+        tauri::async_runtime::spawn_blocking(move || {
+            match back_up_if_due(&backup_path, &backup, Utc::now()) {
+                Ok(Some(generation)) => log_event(&backup_app, "backup", Some(&backup_id), serde_json::json!({
+                    "path": generation.path,
+                    "size": generation.size,
+                })),
+                Ok(None) => {}
+                Err(e) => log::warn!("Backup of {} failed: {}", backup_path.display(), e),
+            }
+        });
+    }
+    
+    // Update document state
+    let disk_fingerprint = DiskFingerprint::read(&save_path).ok();
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    if let Some(doc) = manager.documents.get_mut(&id) {
+        doc.handle.path = Some(save_path.clone());
+        doc.handle.is_modified = false;
+        doc.meta = meta.clone();
+        
+        // Fold the saved updates into the checkpoint as reopening the file
+        // does, so a crashed session's journal replays onto the same state
+        doc.yjs_updates.drain(..update_count);
+        let state_changed = doc.yjs_state != saved_state;
+        doc.yjs_state = if state_changed {
+            merge_states(&[&checkpoint, &doc.yjs_state])?
+        } else {
+            checkpoint
+        };
+        
+        // Start the journal over, keeping the edits made during the save
+        if let Some(fingerprint) = &disk_fingerprint {
+            let mut entries = Vec::new();
+            if state_changed {
+                entries.push(JournalEntry::state(&doc.yjs_state));
+            }
+            entries.extend(doc.yjs_updates.iter().map(JournalEntry::update));
+            if let Err(e) = journal::restart(&doc.history_path, &save_path, &fingerprint.sha256, &entries) {
+                log::warn!("Cannot start the journal of {}: {}", save_path.display(), e);
+            }
+        }
+        doc.disk_fingerprint = disk_fingerprint;
+        
+        // Update title from filename if untitled
+        if doc.handle.title == "Untitled Document" {
             if let Some(stem) = save_path.file_stem() {
                 doc.handle.title = stem.to_string_lossy().to_string();
             }
@@ -423,10 +1528,88 @@ pub async fn save_document(
         // Add to recent documents
         add_to_recent(save_path, doc.handle.title.clone())?;
         
+        emit_event(&app, DOCUMENT_SAVED, doc.handle.clone());
+        fire_hooks(HookPayload::new(HookEvent::Save, &id, &doc.meta, doc.handle.path.as_deref(), &doc.handle));
+        
+        // Warn once the document grows past its soft size limits
+        let limits = crate::settings::load_app_settings().map(|s| s.size_limits).unwrap_or_default();
+        match document_size_breakdown(doc, &limits) {
+            Ok(breakdown) if !breakdown.warnings.is_empty() => emit_event(&app, DOCUMENT_SIZE_WARNING, breakdown),
+            Ok(_) => {}
+            Err(e) => log::warn!("Cannot measure document {}: {}", id, e),
+        }
         return Ok(doc.handle.clone());
     }
     
-    Err("Document not found after save".to_string())
+    Err("Document not found after save".into())
+}
+
+/// Export a copy of a document without its draft history
+///
+/// The copy holds the current Yjs state merged into a single checkpoint, the
+/// final snapshot patch and the selected authors. Comments and review records
+/// are dropped unless the options keep them.
+#[tauri::command]
+pub fn export_kmd_clean(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    path: String,
+    options: CleanExportOptions,
+) -> Result<(), KorppiError> {
+    let (yjs_state, content, history_path, mut meta, sections) = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager.documents.get(&id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
+        let mut parts: Vec<&[u8]> = vec![&doc.yjs_state];
+        parts.extend(doc.yjs_updates.iter().map(|u| u.data.as_slice()));
+        (
+            merge_states(&parts)?,
+            current_document_text(doc, false)?,
+            doc.history_path.clone(),
+            doc.meta.clone(),
+            doc.sections.clone(),
+        )
+    };
+    
+    if let Some(authors) = &options.authors {
+        meta.authors.retain(|a| authors.contains(&a.id));
+    }
+    meta.modified_at = Utc::now().to_rfc3339();
+    meta.sync_state = Default::default();
+    
+    let temp_dir = tempfile::TempDir::new()?;
+    let clean_history = temp_dir.path().join("history.sqlite");
+    build_clean_history(&history_path, &clean_history, &content, &options)?;
+    
+    // Images are part of the text, not of its history
+    let assets_dir = document_assets_dir(&history_path);
+    if assets_dir.is_dir() {
+        let clean_assets = document_assets_dir(&clean_history);
+        fs::create_dir_all(&clean_assets)?;
+        for entry in fs::read_dir(&assets_dir)?.flatten() {
+            if entry.path().is_file() {
+                fs::copy(entry.path(), clean_assets.join(entry.file_name()))?;
+            }
+        }
+    }
+    
+    // Sections get the same treatment as the main document
+    let mut clean_sections = HashMap::new();
+    for (section_id, section) in sections {
+        let section_text = if section.yjs_state.is_empty() {
+            String::new()
+        } else {
+            document_text(&[&section.yjs_state], false)?
+        };
+        let section_history = temp_dir.path().join(format!("{}.sqlite", section_id));
+        build_clean_history(&section.history_path, &section_history, &section_text, &options)?;
+        clean_sections.insert(section_id, SectionState {
+            yjs_state: merge_states(&[&section.yjs_state])?,
+            history_path: section_history,
+        });
+    }
+    
+    bundle_to_kmd(&PathBuf::from(path), &yjs_state, &[], &clean_history, &meta, &clean_sections)
 }
 
 /// Close a document (returns false if unsaved changes need confirmation)
@@ -435,7 +1618,7 @@ pub fn close_document(
     manager: State<'_, Mutex<DocumentManager>>,
     id: String,
     force: Option<bool>,
-) -> Result<bool, String> {
+) -> Result<bool, KorppiError> {
     let mut manager = manager.lock().map_err(|e| e.to_string())?;
     
     if let Some(doc) = manager.documents.get(&id) {
@@ -444,68 +1627,118 @@ pub fn close_document(
             return Ok(false);
         }
         
+        if let Some(path) = doc.handle.path.as_ref().filter(|_| !doc.handle.read_only) {
+            release_lock(path);
+        }
+        if let Some(path) = doc.handle.path.as_ref() {
+            remove_presence(path, &doc.meta.uuid, &PresenceInfo::current().author_id);
+        }
+        
         // Clean up temp directory
         let _ = cleanup_document_temp_dir(&id);
         
         // Remove from documents
         manager.documents.remove(&id);
         
-        // If this was the active document, switch to another
-        if manager.active_document_id.as_ref() == Some(&id) {
-            manager.active_document_id = manager.documents.keys().next().cloned();
-        }
+        // Windows showing this document switch to another
+        manager.document_closed(&id);
         
         return Ok(true);
     }
     
-    Err(format!("Document not found: {}", id))
+    Err(KorppiError::DocumentNotFound(id.to_string()))
 }
 
 /// Get all open documents
 #[tauri::command]
 pub fn get_open_documents(
     manager: State<'_, Mutex<DocumentManager>>,
-) -> Result<Vec<DocumentHandle>, String> {
+) -> Result<Vec<DocumentHandle>, KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
     Ok(manager.documents.values().map(|d| d.handle.clone()).collect())
 }
 
 /// Get recent documents list
 #[tauri::command]
-pub fn get_recent_documents() -> Result<Vec<RecentDocument>, String> {
-    load_recent_documents()
+pub fn get_recent_documents() -> Result<Vec<RecentDocument>, KorppiError> {
+    Ok(load_recent_documents()?)
+}
+
+/// Remove temp directories of documents that are no longer open
+#[tauri::command]
+pub fn cleanup_workspace(
+    manager: State<'_, Mutex<DocumentManager>>,
+    max_age_days: Option<u64>,
+) -> Result<CleanupReport, KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    
+    let open_ids: HashSet<String> = manager.documents.keys().cloned().collect();
+    let max_age = max_age_days.map(|days| Duration::from_secs(days * 24 * 60 * 60));
+    
+    Ok(collect_orphaned_temp_dirs(&get_temp_base_dir()?, &open_ids, max_age)?)
 }
 
 /// Clear recent documents list
 #[tauri::command]
-pub fn clear_recent_documents() -> Result<(), String> {
-    save_recent_documents(&[])
+pub fn clear_recent_documents() -> Result<(), KorppiError> {
+    let mut file = load_recent_file().unwrap_or_default();
+    file.documents.clear();
+    Ok(save_recent_file(&file)?)
+}
+
+/// Pin a recent document so it is never dropped from the list
+#[tauri::command]
+pub fn pin_recent_document(path: PathBuf) -> Result<Vec<RecentDocument>, KorppiError> {
+    Ok(set_recent_pinned(path, true)?)
+}
+
+/// Unpin a recent document
+#[tauri::command]
+pub fn unpin_recent_document(path: PathBuf) -> Result<Vec<RecentDocument>, KorppiError> {
+    Ok(set_recent_pinned(path, false)?)
 }
 
-/// Set which document is currently active
+/// Get the recent documents preferences
+#[tauri::command]
+pub fn get_recent_settings() -> Result<RecentSettings, KorppiError> {
+    Ok(load_recent_file()?.settings)
+}
+
+/// Update the recent documents preferences
+#[tauri::command]
+pub fn update_recent_settings(settings: RecentSettings) -> Result<(), KorppiError> {
+    let mut file = load_recent_file().unwrap_or_default();
+    file.settings = settings;
+    trim_recent(&mut file.documents, file.settings.max_entries);
+    Ok(save_recent_file(&file)?)
+}
+
+/// Set which document is active in the calling window
 #[tauri::command]
 pub fn set_active_document(
+    window: Window,
     manager: State<'_, Mutex<DocumentManager>>,
     id: String,
-) -> Result<(), String> {
+) -> Result<(), KorppiError> {
     let mut manager = manager.lock().map_err(|e| e.to_string())?;
     
     if manager.documents.contains_key(&id) {
-        manager.active_document_id = Some(id);
+        manager.set_active(window.label(), id);
         Ok(())
     } else {
-        Err(format!("Document not found: {}", id))
+        Err(KorppiError::DocumentNotFound(id.to_string()))
     }
 }
 
-/// Get the active document
+/// Get the active document of the calling window
 #[tauri::command]
 pub fn get_active_document(
+    window: Window,
     manager: State<'_, Mutex<DocumentManager>>,
-) -> Result<Option<DocumentHandle>, String> {
+) -> Result<Option<DocumentHandle>, KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
     
-    if let Some(id) = &manager.active_document_id {
+    if let Some(id) = manager.active_document_id(window.label()) {
         if let Some(doc) = manager.documents.get(id) {
             return Ok(Some(doc.handle.clone()));
         }
@@ -518,29 +1751,187 @@ pub fn get_active_document(
 pub fn get_document_state(
     manager: State<'_, Mutex<DocumentManager>>,
     id: String,
-) -> Result<Vec<u8>, String> {
+) -> Result<Vec<u8>, KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
     
     manager.documents.get(&id)
         .map(|d| d.yjs_state.clone())
-        .ok_or_else(|| format!("Document not found: {}", id))
+        .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))
+}
+
+/// Result of merging another copy's Yjs state into an open document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateMergeResult {
+    /// Merged state to load into the editor
+    pub state: Vec<u8>,
+    /// Both copies had edits the other had not seen; run conflict detection
+    pub diverged: bool,
+    /// The incoming copy added nothing new
+    pub unchanged: bool,
+}
+
+/// Read the full Yjs state of a KMD file (checkpoint plus updates)
+pub(crate) fn read_kmd_yjs_state(kmd_path: &Path) -> Result<Vec<u8>, String> {
+    let file = File::open(kmd_path).map_err(|e| format!("Failed to open file: {}", e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| format!("Invalid ZIP archive: {}", e))?;
+    
+    let format_info: FormatInfo = {
+        let mut format_file = archive
+            .by_name("format.json")
+            .map_err(|_| "Missing format.json in KMD file")?;
+        let mut content = String::new();
+        format_file.read_to_string(&mut content).map_err(|e| e.to_string())?;
+        serde_json::from_str(&content).map_err(|e| format!("Invalid format.json: {}", e))?
+    };
+    check_version_compatibility(&format_info)?;
+    
+    let mut state = Vec::new();
+    if let Ok(mut state_file) = archive.by_name("state.yjs") {
+        state_file.read_to_end(&mut state).map_err(|e| e.to_string())?;
+    }
+    let updates = match kmd_layout(&format_info) {
+        KmdLayout::Chunked => read_yjs_updates(&mut archive, &mut FileProgress::silent())?,
+        KmdLayout::Monolithic => Vec::new(),
+    };
+    
+    let mut parts: Vec<&[u8]> = vec![&state];
+    parts.extend(updates.iter().map(|u| u.data.as_slice()));
+    merge_states(&parts)
+}
+
+/// Merge the Yjs state of another copy of a document (a KMD file) into an
+/// open document. The CRDT merge keeps the edits of both sides.
+#[tauri::command]
+pub fn merge_document_state(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    source_path: String,
+) -> Result<StateMergeResult, KorppiError> {
+    let incoming = read_kmd_yjs_state(Path::new(&source_path))?;
+    
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get_mut(&id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
+    
+    let diverged = if doc.yjs_state.is_empty() || incoming.is_empty() {
+        false
+    } else {
+        has_diverged(&doc.yjs_state, &incoming)?
+    };
+    
+    let merged = merge_states(&[&doc.yjs_state, &incoming])?;
+    let unchanged = merged == merge_states(&[&doc.yjs_state])?;
+    if !unchanged {
+        doc.yjs_state = merged.clone();
+        journal_state(doc)?;
+        doc.handle.is_modified = true;
+    }
+    
+    Ok(StateMergeResult {
+        state: merged,
+        diverged,
+        unchanged,
+    })
+}
+
+/// Number of patches in a history database, for the session log
+fn count_patches(history_path: &Path) -> Option<i64> {
+    let conn = Connection::open(history_path).ok()?;
+    conn.query_row("SELECT COUNT(*) FROM patches", [], |row| row.get(0)).ok()
+}
+
+/// Current text of a document, decoded from its Yjs state and updates
+pub(crate) fn current_document_text(doc: &DocumentState, plain: bool) -> Result<String, String> {
+    let mut parts: Vec<&[u8]> = vec![&doc.yjs_state];
+    parts.extend(doc.yjs_updates.iter().map(|u| u.data.as_slice()));
+    document_text(&parts, plain)
+}
+
+/// Get the document text as markdown (or plain text) from its Yjs state
+#[tauri::command]
+pub fn get_document_text(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    plain: Option<bool>,
+) -> Result<String, KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get(&doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+    
+    Ok(current_document_text(doc, plain.unwrap_or(false))?)
+}
+
+/// Get the Yjs updates to apply on top of the document state
+#[tauri::command]
+pub fn get_document_updates(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+) -> Result<Vec<YjsUpdate>, KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    
+    manager.documents.get(&id)
+        .map(|d| d.yjs_updates.clone())
+        .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))
+}
+
+/// Append a Yjs update produced by an author; returns its sequence number
+#[tauri::command]
+pub fn append_document_update(
+    manager: State<'_, Mutex<DocumentManager>>,
+    autosave: State<'_, Mutex<AutoSaveTracker>>,
+    id: String,
+    author: String,
+    update: Vec<u8>,
+) -> Result<u32, KorppiError> {
+    if author.is_empty() || author.contains(['/', '\\']) || !is_path_safe(&author) {
+        return Err(KorppiError::InvalidInput(format!("Invalid update author: {}", author)));
+    }
+    
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get_mut(&id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
+    if doc.handle.read_only {
+        return Err("Document is open read-only".into());
+    }
+    
+    let seq = doc.yjs_updates.iter()
+        .filter(|u| u.author == author)
+        .map(|u| u.seq)
+        .max()
+        .unwrap_or(0) + 1;
+    let update = YjsUpdate { author, seq, data: update };
+    journal::append(&doc.history_path, &JournalEntry::update(&update))?;
+    doc.yjs_updates.push(update);
+    doc.handle.is_modified = true;
+    
+    let mut tracker = autosave.lock().map_err(|e| e.to_string())?;
+    note_document_activity(&mut tracker, doc, 1)?;
+    
+    Ok(seq)
 }
 
 /// Update document Yjs state
 #[tauri::command]
 pub fn update_document_state(
     manager: State<'_, Mutex<DocumentManager>>,
+    autosave: State<'_, Mutex<AutoSaveTracker>>,
     id: String,
     state: Vec<u8>,
-) -> Result<(), String> {
+) -> Result<(), KorppiError> {
     let mut manager = manager.lock().map_err(|e| e.to_string())?;
     
     if let Some(doc) = manager.documents.get_mut(&id) {
+        if doc.handle.read_only {
+            return Err("Document is open read-only".into());
+        }
         doc.yjs_state = state;
+        journal_state(doc)?;
         doc.handle.is_modified = true;
+        let mut tracker = autosave.lock().map_err(|e| e.to_string())?;
+        note_document_activity(&mut tracker, doc, 1)?;
         Ok(())
     } else {
-        Err(format!("Document not found: {}", id))
+        Err(KorppiError::DocumentNotFound(id.to_string()))
     }
 }
 
@@ -550,14 +1941,14 @@ pub fn mark_document_modified(
     manager: State<'_, Mutex<DocumentManager>>,
     id: String,
     modified: bool,
-) -> Result<(), String> {
+) -> Result<(), KorppiError> {
     let mut manager = manager.lock().map_err(|e| e.to_string())?;
     
     if let Some(doc) = manager.documents.get_mut(&id) {
         doc.handle.is_modified = modified;
         Ok(())
     } else {
-        Err(format!("Document not found: {}", id))
+        Err(KorppiError::DocumentNotFound(id.to_string()))
     }
 }
 
@@ -567,7 +1958,7 @@ pub fn update_document_title(
     manager: State<'_, Mutex<DocumentManager>>,
     id: String,
     title: String,
-) -> Result<(), String> {
+) -> Result<(), KorppiError> {
     let mut manager = manager.lock().map_err(|e| e.to_string())?;
     
     if let Some(doc) = manager.documents.get_mut(&id) {
@@ -576,105 +1967,262 @@ pub fn update_document_title(
         doc.handle.is_modified = true;
         Ok(())
     } else {
-        Err(format!("Document not found: {}", id))
+        Err(KorppiError::DocumentNotFound(id.to_string()))
+    }
+}
+
+/// Set how the document state and history are compressed when saving
+///
+/// Zstd saves and opens large histories faster and makes smaller files, but
+/// the file can then only be opened by Korppi versions reading KMD 0.4.
+#[tauri::command]
+pub fn set_document_compression(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    compression: KmdCompression,
+) -> Result<(), KorppiError> {
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+
+    if let Some(doc) = manager.documents.get_mut(&id) {
+        if doc.meta.settings.compression != compression {
+            doc.meta.settings.compression = compression;
+            doc.handle.is_modified = true;
+        }
+        Ok(())
+    } else {
+        Err(KorppiError::DocumentNotFound(id.to_string()))
+    }
+}
+
+/// Set whether unresolved comments on a patch's changes block accepting it
+#[tauri::command]
+pub fn set_comment_blocking_policy(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    enabled: bool,
+) -> Result<(), KorppiError> {
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+
+    if let Some(doc) = manager.documents.get_mut(&id) {
+        if doc.meta.settings.block_acceptance_on_comments != enabled {
+            doc.meta.settings.block_acceptance_on_comments = enabled;
+            doc.handle.is_modified = true;
+        }
+        Ok(())
+    } else {
+        Err(KorppiError::DocumentNotFound(id.to_string()))
     }
 }
 
 /// Record a patch for a specific document
 #[tauri::command]
 pub fn record_document_patch(
+    app: AppHandle,
     manager: State<'_, Mutex<DocumentManager>>,
     id: String,
     patch: crate::patch_log::PatchInput,
-) -> Result<(), String> {
+    suggestion: Option<bool>,
+) -> Result<(), KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
     
     let doc = manager.documents.get(&id)
-        .ok_or_else(|| format!("Document not found: {}", id))?;
+        .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
     
-    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    let store = PatchStore::open(&doc.history_path)?;
     
-    // Use shared schema definition
-    ensure_schema(&conn)?;
+    // Suggestions are stored under their own kind so they never become snapshots
+    let mut patch = patch;
+    if suggestion.unwrap_or(false) {
+        if patch.data.get("snapshot").and_then(|v| v.as_str()).is_none() {
+            return Err(KorppiError::InvalidInput("Suggestion patches must include a snapshot".to_string()));
+        }
+        patch.kind = crate::suggestions::SUGGESTION_KIND.to_string();
+    }
     
-    let data_str = serde_json::to_string(&patch.data).map_err(|e| e.to_string())?;
+    // Journal the patch with the state it describes before it reaches the history
+    patch.uuid.get_or_insert_with(|| Uuid::new_v4().to_string());
+    journal::append(&doc.history_path, &JournalEntry::Patch {
+        patch: patch.clone(),
+        state_hash: state_hash(&doc.yjs_state, &doc.yjs_updates),
+    })?;
+    
+    // Save patches get their snapshot text stored with them
+    let Some(stored) = store.insert_patch(&patch, None)? else {
+        // Already recorded
+        return Ok(());
+    };
     
-    // Use provided UUID or generate new one
-    let patch_uuid = patch.uuid.clone().unwrap_or_else(|| Uuid::new_v4().to_string());
+    let recorded = PatchRecordedEvent {
+        doc_id: Some(id.clone()),
+        patch_id: stored.id,
+        uuid: stored.uuid.clone(),
+        author: stored.author.clone(),
+        kind: stored.kind.clone(),
+        timestamp: stored.timestamp,
+    };
+    fire_hooks(HookPayload::new(HookEvent::PatchRecorded, &id, &doc.meta, doc.handle.path.as_deref(), &recorded));
+    emit_event(&app, PATCH_RECORDED, recorded);
     
-    conn.execute(
-        "INSERT INTO patches (timestamp, author, kind, data, uuid, parent_uuid) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        params![patch.timestamp, patch.author, patch.kind, data_str, patch_uuid, patch.parent_uuid],
-    ).map_err(|e| e.to_string())?;
-    
-    let patch_id = conn.last_insert_rowid();
-    
-    // If this is a Save patch with a snapshot, save it to the snapshots table
-    if patch.kind == "Save" {
-        if let Some(snapshot_str) = patch.data.get("snapshot") {
-            if let Some(snapshot_text) = snapshot_str.as_str() {
-                // Store the snapshot text as bytes
-                conn.execute(
-                    "INSERT INTO snapshots (timestamp, patch_id, state) VALUES (?1, ?2, ?3)",
-                    params![patch.timestamp, patch_id, snapshot_text.as_bytes()],
-                ).map_err(|e| e.to_string())?;
-            }
-        }
+    if stored.kind == "Save" {
+        crate::git_mirror::mirror_recorded_patch(doc, &stored);
     }
     
     Ok(())
 }
 
+/// A patch listed with its orphan status
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentPatch {
+    #[serde(flatten)]
+    pub patch: crate::patch_log::Patch,
+
+    /// Rejected ancestor that orphaned this patch, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orphaned_by: Option<String>,
+
+    /// Whether the patch carries a snapshot, also when it was left out of a listing
+    #[serde(default)]
+    pub has_snapshot: bool,
+
+    /// Imported from a bundle that no known key of its author signed
+    #[serde(default)]
+    pub unverified: bool,
+}
+
+impl DocumentPatch {
+    fn new(
+        mut patch: crate::patch_log::Patch,
+        orphans: &HashMap<String, String>,
+        unverified: &HashSet<String>,
+        include_snapshot: bool,
+    ) -> Self {
+        let orphaned_by = patch.uuid.as_ref().and_then(|uuid| orphans.get(uuid).cloned());
+        let unverified = patch.uuid.as_ref().is_some_and(|uuid| unverified.contains(uuid));
+        let has_snapshot = patch.data.get("snapshot").is_some_and(|s| s.as_str().is_some_and(|s| !s.is_empty()));
+        if !include_snapshot {
+            if let Some(data) = patch.data.as_object_mut() {
+                data.remove("snapshot");
+            }
+        }
+        DocumentPatch { patch, orphaned_by, has_snapshot, unverified }
+    }
+}
+
+/// Order filtered patches by the key of the filter; author names are
+/// compared in the order of the document language
+fn sort_patches(patches: &mut [crate::patch_log::Patch], filter: &PatchFilter, meta: &DocumentMeta) {
+    let recorded = |p: &crate::patch_log::Patch| (p.timestamp, p.id);
+    match filter.sort_by {
+        PatchSortKey::Timestamp => patches.sort_by_key(recorded),
+        PatchSortKey::Kind => patches.sort_by(|a, b| a.kind.cmp(&b.kind).then(recorded(a).cmp(&recorded(b)))),
+        PatchSortKey::Author => {
+            let names: HashMap<&str, String> = patches
+                .iter()
+                .map(|p| (p.author.as_str(), author_display_name(meta, &p.author)))
+                .collect();
+            let mut order: Vec<&str> = names.keys().copied().collect();
+            order.sort_by(|a, b| collation::compare(&names[a], &names[b], &meta.settings.language));
+            let rank: HashMap<String, usize> = order.into_iter().enumerate().map(|(i, a)| (a.to_string(), i)).collect();
+            patches.sort_by_key(|p| (rank[&p.author], recorded(p)));
+        }
+    }
+    if filter.descending {
+        patches.reverse();
+    }
+}
+
 /// List patches for a specific document
+///
+/// Without paging options every patch is returned, oldest first. With them
+/// the page is counted from the newest patch: `offset` skips the newest
+/// patches and `before_timestamp` keeps only older ones, so a timeline can
+/// load the latest page first and older pages as it scrolls. Setting
+/// `include_snapshots` to false leaves the snapshot text out of the patch
+/// data; `get_document_patch` returns it when a diff or restore needs it.
+///
+/// With a `filter`, only the matching patches are listed, in the order of
+/// the filter, and `offset` and `limit` count in that order.
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub fn list_document_patches(
     manager: State<'_, Mutex<DocumentManager>>,
     id: String,
-) -> Result<Vec<crate::patch_log::Patch>, String> {
+    limit: Option<usize>,
+    offset: Option<usize>,
+    before_timestamp: Option<i64>,
+    include_snapshots: Option<bool>,
+    filter: Option<PatchFilter>,
+) -> Result<Vec<DocumentPatch>, KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
     
     let doc = manager.documents.get(&id)
-        .ok_or_else(|| format!("Document not found: {}", id))?;
+        .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
     
     if !doc.history_path.exists() {
         return Ok(Vec::new());
     }
     
-    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
-
-    // Ensure schema exists and is migrated (especially on first open of legacy doc)
-    ensure_schema(&conn)?;
-    
-    let mut stmt = conn
-        .prepare("SELECT id, timestamp, author, kind, data, uuid, parent_uuid FROM patches ORDER BY id ASC")
-        .map_err(|e| e.to_string())?;
-    
-    let rows = stmt
-        .query_map([], |row| {
-            let data_str: String = row.get(4)?;
-            let data: serde_json::Value =
-                serde_json::from_str(&data_str).unwrap_or(serde_json::Value::Null);
-            
-            Ok(crate::patch_log::Patch {
-                id: row.get(0)?,
-                timestamp: row.get(1)?,
-                author: row.get(2)?,
-                kind: row.get(3)?,
-                data,
-                uuid: row.get(5).ok(),
-                parent_uuid: row.get(6).ok(),
-            })
-        })
-        .map_err(|e| e.to_string())?;
-    
-    let mut patches = Vec::new();
-    for row in rows {
-        patches.push(row.map_err(|e| e.to_string())?);
-    }
+    let store = PatchStore::open(&doc.history_path)?;
+    
+    let orphans = orphaned_patches(store.conn())?;
+    let unverified = store.unverified_uuids()?;
+    let page = PatchPage { limit, offset: offset.unwrap_or(0), before_timestamp };
+    let include_snapshots = include_snapshots.unwrap_or(true);
+    let listed = match filter {
+        None => store.page(&page)?,
+        Some(filter) => {
+            let mut patches = store.filtered(&filter)?;
+            if let Some(before) = before_timestamp {
+                patches.retain(|p| p.timestamp < before);
+            }
+            sort_patches(&mut patches, &filter, &doc.meta);
+            patches.into_iter().skip(page.offset).take(limit.unwrap_or(usize::MAX)).collect()
+        }
+    };
+    let patches = listed
+        .into_iter()
+        .map(|patch| DocumentPatch::new(patch, &orphans, &unverified, include_snapshots))
+        .collect();
     
     Ok(patches)
 }
 
+/// Get one patch of a document with its snapshot
+#[tauri::command]
+pub fn get_document_patch(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    patch_id: i64,
+) -> Result<Option<DocumentPatch>, KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get(&id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
+
+    if !doc.history_path.exists() {
+        return Ok(None);
+    }
+    let store = PatchStore::open(&doc.history_path)?;
+    let orphans = orphaned_patches(store.conn())?;
+    let unverified = store.unverified_uuids()?;
+    Ok(store.get(patch_id)?.map(|patch| DocumentPatch::new(patch, &orphans, &unverified, true)))
+}
+
+/// Count a document's patches by kind and author without listing them
+#[tauri::command]
+pub fn get_document_patch_summary(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+) -> Result<PatchSummary, KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get(&id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
+
+    if !doc.history_path.exists() {
+        return Ok(PatchSummary::default());
+    }
+    Ok(PatchStore::open(&doc.history_path)?.summary()?)
+}
+
 /// Record a review for a patch in a document
 #[tauri::command]
 pub fn record_document_patch_review(
@@ -684,20 +2232,31 @@ pub fn record_document_patch_review(
     reviewer_id: String,
     decision: String,
     reviewer_name: Option<String>,
-) -> Result<(), String> {
+    orphan_descendants: Option<bool>,
+) -> Result<(), KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
     
     let doc = manager.documents.get(&doc_id)
-        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
     
-    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&doc.history_path)?;
     
     // Ensure schema exists (needed for patch_reviews table)
     ensure_schema(&conn)?;
 
     // Validate decision
     if decision != "accepted" && decision != "rejected" {
-        return Err(format!("Invalid decision: {}. Must be 'accepted' or 'rejected'", decision));
+        return Err(KorppiError::InvalidInput(format!("Invalid decision: {}. Must be 'accepted' or 'rejected'", decision)));
+    }
+
+    if decision == "accepted" && doc.meta.settings.block_acceptance_on_comments {
+        let blocking = blocking_comments(&conn, &doc.meta, &patch_uuid)?;
+        if !blocking.is_empty() {
+            return Err(format!(
+                "Cannot accept patch: {} unresolved comment(s) on its changes",
+                blocking.len()
+            ).into());
+        }
     }
     
     let reviewed_at = std::time::SystemTime::now()
@@ -708,46 +2267,82 @@ pub fn record_document_patch_review(
     conn.execute(
         "INSERT OR REPLACE INTO patch_reviews (patch_uuid, reviewer_id, decision, reviewer_name, reviewed_at) VALUES (?1, ?2, ?3, ?4, ?5)",
         params![patch_uuid, reviewer_id, decision, reviewer_name, reviewed_at],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
+
+    // A new decision replaces the old one, and with it the orphans it made
+    release_orphans(&conn, &patch_uuid, &reviewer_id)?;
+    if decision == "accepted" {
+        advance_review_baseline(&conn, &reviewer_id, &patch_uuid, reviewed_at)?;
+    } else {
+        reset_review_baseline(&conn, &reviewer_id, reviewed_at)?;
+    }
+    if decision == "rejected" && orphan_descendants.unwrap_or(false) {
+        crate::patch_graph::orphan_descendants(&conn, &patch_uuid, &reviewer_id)?;
+    }
     
     Ok(())
 }
 
+/// Get the unresolved comments that overlap a patch's changes
+#[tauri::command]
+pub fn get_patch_blocking_comments(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    patch_uuid: String,
+) -> Result<Vec<BlockingComment>, KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+
+    let doc = manager.documents.get(&doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+
+    let conn = Connection::open(&doc.history_path)?;
+    ensure_schema(&conn)?;
+
+    Ok(blocking_comments(&conn, &doc.meta, &patch_uuid)?)
+}
+
 /// Get reviews for patches in a document
 #[tauri::command]
 pub fn get_document_patch_reviews(
     manager: State<'_, Mutex<DocumentManager>>,
     doc_id: String,
     patch_uuid: String,
-) -> Result<Vec<crate::patch_log::PatchReview>, String> {
+) -> Result<Vec<crate::patch_log::PatchReview>, KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
     
     let doc = manager.documents.get(&doc_id)
-        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
     
-    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&doc.history_path)?;
     
     // Ensure schema exists
     ensure_schema(&conn)?;
     
     let mut stmt = conn
-        .prepare("SELECT patch_uuid, reviewer_id, decision, reviewer_name, reviewed_at FROM patch_reviews WHERE patch_uuid = ?1 ORDER BY reviewed_at DESC")
-        .map_err(|e| e.to_string())?;
+        .prepare("SELECT patch_uuid, reviewer_id, decision, reviewer_name, reviewed_at FROM patch_reviews WHERE patch_uuid = ?1 ORDER BY reviewed_at DESC")?;
 
     let reviews = stmt
-        .query_map([patch_uuid], |row| {
+        .query_map([&patch_uuid], |row| {
             Ok(crate::patch_log::PatchReview {
                 patch_uuid: row.get(0)?,
                 reviewer_id: row.get(1)?,
                 decision: row.get(2)?,
                 reviewer_name: row.get(3)?,
                 reviewed_at: row.get(4)?,
+                comments: Vec::new(),
             })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Attach each reviewer's comments so authors see why a patch was rejected
+    let comments = load_review_comments(&conn, &patch_uuid)?;
+    let reviews = reviews
+        .into_iter()
+        .map(|mut review| {
+            review.comments = comments.iter().filter(|c| c.author_id == review.reviewer_id).cloned().collect();
+            review
         })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
+        .collect();
 
     Ok(reviews)
 }
@@ -759,27 +2354,25 @@ pub fn delete_document_reviews_after(
     doc_id: String,
     after_timestamp: i64,
     reviewer_id: String,
-) -> Result<u32, String> {
+) -> Result<u32, KorppiError> {
     eprintln!("[DEBUG] delete_document_reviews_after: doc_id={}, after_timestamp={}, reviewer_id={}", 
               doc_id, after_timestamp, reviewer_id);
     
     let manager = manager.lock().map_err(|e| e.to_string())?;
     
     let doc = manager.documents.get(&doc_id)
-        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
     
-    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&doc.history_path)?;
     
     // Ensure schema exists
     ensure_schema(&conn)?;
     
     // First, let's see what reviews exist for this reviewer
-    let mut stmt = conn.prepare("SELECT patch_uuid, reviewed_at FROM patch_reviews WHERE reviewer_id = ?1")
-        .map_err(|e| e.to_string())?;
+    let mut stmt = conn.prepare("SELECT patch_uuid, reviewed_at FROM patch_reviews WHERE reviewer_id = ?1")?;
     let reviews: Vec<(String, i64)> = stmt.query_map([&reviewer_id], |row| {
         Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
-    })
-    .map_err(|e| e.to_string())?
+    })?
     .filter_map(|r| r.ok())
     .collect();
     
@@ -793,8 +2386,12 @@ pub fn delete_document_reviews_after(
     let deleted = conn.execute(
         "DELETE FROM patch_reviews WHERE reviewer_id = ?1 AND reviewed_at > ?2",
         params![reviewer_id, after_timestamp],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
+    conn.execute(
+        "DELETE FROM orphaned_patches WHERE reviewer_id = ?1 AND orphaned_at > ?2",
+        params![reviewer_id, after_timestamp],
+    )?;
+    reset_review_baseline(&conn, &reviewer_id, Utc::now().timestamp_millis())?;
     
     eprintln!("[DEBUG] Deleted {} reviews", deleted);
     
@@ -807,13 +2404,13 @@ pub fn get_document_patches_needing_review(
     manager: State<'_, Mutex<DocumentManager>>,
     doc_id: String,
     reviewer_id: String,
-) -> Result<Vec<crate::patch_log::Patch>, String> {
+) -> Result<Vec<crate::patch_log::Patch>, KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
 
     let doc = manager.documents.get(&doc_id)
-        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
 
-    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&doc.history_path)?;
 
     // Ensure schema exists
     ensure_schema(&conn)?;
@@ -831,8 +2428,7 @@ pub fn get_document_patches_needing_review(
                  AND pr.reviewer_id = ?1
              )
              ORDER BY p.timestamp ASC"
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
 
     let patches = stmt
         .query_map([reviewer_id], |row| {
@@ -849,72 +2445,423 @@ pub fn get_document_patches_needing_review(
                 uuid: row.get(5).ok(),
                 parent_uuid: row.get(6).ok(),
             })
-        })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(patches)
+}
+
+/// Get the file the calling window opens on startup: the one passed on
+/// the command line for the main window, the one requested with
+/// `open_in_new_window` for the others
+#[tauri::command]
+pub fn get_initial_file(
+    window: Window,
+    manager: State<'_, Mutex<DocumentManager>>,
+) -> Option<String> {
+    let mut manager = manager.lock().ok()?;
+    manager
+        .pending_opens
+        .remove(window.label())
+        .map(|path| path.to_string_lossy().to_string())
+}
+
+/// Hand a file from another launch of the app to this instance
+///
+/// Called by the single-instance plugin with the arguments and working
+/// directory of the new process, which then exits. The main window (or any
+/// window left) is focused and asked to open the file with `open_document`,
+/// or to follow the `korppi://` link the new process was started with.
+pub fn forward_open_request(app: &AppHandle, args: &[String], cwd: &str) {
+    let window = app
+        .get_webview_window(MAIN_WINDOW)
+        .or_else(|| app.webview_windows().into_values().next());
+    let Some(window) = window else {
+        return;
+    };
+    window.unminimize().ok();
+    window.set_focus().ok();
+
+    if crate::deep_links::forward_position_link(app, args) {
+        return;
+    }
+    let Some(path) = crate::cli::file_argument(args.get(1..).unwrap_or_default(), Path::new(cwd)) else {
+        return;
+    };
+    log_event(app, "open_forwarded", None, serde_json::json!({ "path": path }));
+    if let Err(e) = app.emit_to(window.label(), OPEN_FILE_REQUESTED, path.to_string_lossy().to_string()) {
+        log::warn!("Failed to forward {:?} to the running instance: {}", path, e);
+    }
+}
+
+/// Open a document in a new window, or an empty document with no path
+///
+/// A document that is already open is not opened twice; the window showing
+/// it is focused instead. Returns the label of the window.
+#[tauri::command]
+pub fn open_in_new_window(
+    app: AppHandle,
+    manager: State<'_, Mutex<DocumentManager>>,
+    path: Option<PathBuf>,
+) -> Result<String, KorppiError> {
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+
+    if let Some(doc_id) = path.as_deref().and_then(|p| manager.document_at(p)) {
+        let showing = manager
+            .active_documents
+            .iter()
+            .find(|(_, id)| *id == doc_id)
+            .map(|(window, _)| window.clone());
+        if let Some(window) = showing.as_deref().and_then(|label| app.get_webview_window(label)) {
+            window.unminimize().ok();
+            window.set_focus().ok();
+            return Ok(window.label().to_string());
+        }
+    }
+
+    let label = format!("{}{}", DOCUMENT_WINDOW_PREFIX, Uuid::new_v4());
+    if let Some(path) = path {
+        manager.pending_opens.insert(label.clone(), path);
+    }
+    WebviewWindowBuilder::new(&app, &label, WebviewUrl::App("index.html".into()))
+        .title("Korppi")
+        .inner_size(800.0, 600.0)
+        .build()
+        .map_err(|e| {
+            manager.pending_opens.remove(&label);
+            KorppiError::Other(format!("Failed to open window: {}", e))
+        })?;
+
+    Ok(label)
+}
+
+/// Maximum allowed snapshot size (100 MB)
+const MAX_SNAPSHOT_SIZE: usize = 100 * 1024 * 1024;
+
+/// Save a Yjs state snapshot for a specific document at a given patch ID
+#[tauri::command]
+pub fn save_document_snapshot(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    patch_id: i64,
+    state: Vec<u8>,
+) -> Result<(), KorppiError> {
+    // Validate input
+    if state.is_empty() {
+        return Err(KorppiError::InvalidInput("Snapshot state cannot be empty".to_string()));
+    }
+    if state.len() > MAX_SNAPSHOT_SIZE {
+        return Err(KorppiError::InvalidInput(format!("Snapshot size exceeds maximum allowed ({} bytes)", MAX_SNAPSHOT_SIZE)));
+    }
+
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    
+    let doc = manager.documents.get(&id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
+    
+    let store = PatchStore::open(&doc.history_path)?;
+    
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_millis() as i64;
+    
+    store.add_snapshot(patch_id, timestamp, &state)?;
+    
+    Ok(())
+}
+
+/// Check a document's snapshots against their hashes and report damaged or missing ones
+#[tauri::command]
+pub fn verify_snapshots(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+) -> Result<SnapshotReport, KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get(&id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
+
+    if !doc.history_path.exists() {
+        return Ok(SnapshotReport::default());
+    }
+    let store = PatchStore::open(&doc.history_path)?;
+    Ok(store.verify_snapshots()?)
+}
+
+/// Map a `SELECT id, timestamp, author, kind, data, uuid, parent_uuid` row to a Patch
+pub(crate) fn row_to_patch(row: &rusqlite::Row) -> rusqlite::Result<crate::patch_log::Patch> {
+    let data_str: String = row.get(4)?;
+    let data: serde_json::Value =
+        serde_json::from_str(&data_str).unwrap_or(serde_json::Value::Null);
+    Ok(crate::patch_log::Patch {
+        id: row.get(0)?,
+        timestamp: row.get(1)?,
+        author: row.get(2)?,
+        kind: row.get(3)?,
+        data,
+        uuid: row.get(5)?,
+        parent_uuid: row.get(6)?,
+    })
+}
+
+/// Display name for an author ID, falling back to the ID itself
+pub(crate) fn author_display_name(meta: &DocumentMeta, author_id: &str) -> String {
+    meta.authors
+        .iter()
+        .find(|a| a.id == author_id)
+        .map(|a| a.name.clone())
+        .unwrap_or_else(|| author_id.to_string())
+}
+
+/// Load a patch together with the snapshot text stored in its data
+fn load_patch_snapshot(conn: &Connection, patch_id: i64) -> Result<(crate::patch_log::Patch, String), String> {
+    let patch = conn
+        .query_row(
+            "SELECT id, timestamp, author, kind, data, uuid, parent_uuid FROM patches WHERE id = ?1",
+            [patch_id],
+            row_to_patch,
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Patch not found: {}", patch_id))?;
+
+    let snapshot = patch
+        .data
+        .get("snapshot")
+        .and_then(|s| s.as_str())
+        .ok_or_else(|| format!("Patch {} has no snapshot", patch_id))?
+        .to_string();
+
+    Ok((patch, snapshot))
+}
+
+/// Snapshot text of the most recent Save before a patch
+fn previous_save_snapshot(conn: &Connection, patch_id: i64) -> Result<String, String> {
+    let mut stmt = conn
+        .prepare("SELECT data FROM patches WHERE kind = 'Save' AND id < ?1 ORDER BY id DESC")
+        .map_err(|e| e.to_string())?;
+    let previous: Vec<String> = stmt
+        .query_map([patch_id], |row| row.get(0))
         .map_err(|e| e.to_string())?
         .collect::<Result<Vec<_>, _>>()
         .map_err(|e| e.to_string())?;
 
-    Ok(patches)
+    // Walk back until a Save that actually carries a snapshot; the first patch diffs against empty
+    let base = previous
+        .iter()
+        .filter_map(|data_str| serde_json::from_str::<serde_json::Value>(data_str).ok())
+        .find_map(|data| data.get("snapshot").and_then(|s| s.as_str()).map(|s| s.to_string()))
+        .unwrap_or_default();
+
+    Ok(base)
 }
 
-/// Get file path passed as command line argument
-#[tauri::command]
-pub fn get_initial_file() -> Option<String> {
-    std::env::var("KORPPI_OPEN_FILE").ok()
+/// Diff a patch against the most recent Save snapshot before it
+///
+/// Uses the document's diff settings unless `options` is given.
+fn patch_diff(conn: &Connection, meta: &DocumentMeta, patch_id: i64, options: Option<&DiffOptions>) -> Result<Vec<AuthoredHunk>, String> {
+    let (patch, snapshot) = load_patch_snapshot(conn, patch_id)?;
+    let base = previous_save_snapshot(conn, patch_id)?;
+
+    let options = options.unwrap_or(&meta.settings.diff);
+    let author_name = author_display_name(meta, &patch.author);
+    Ok(author_hunks(calculate_hunks_with(&base, &snapshot, options), &patch, &author_name, DEFAULT_HUNK_COLOR))
 }
 
-/// Maximum allowed snapshot size (100 MB)
-const MAX_SNAPSHOT_SIZE: usize = 100 * 1024 * 1024;
+/// An unresolved comment on text a patch changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockingComment {
+    pub comment: crate::comments::Comment,
+    /// Hunks of the patch the comment overlaps
+    pub hunk_ids: Vec<String>,
+}
 
-/// Save a Yjs state snapshot for a specific document at a given patch ID
-#[tauri::command]
-pub fn save_document_snapshot(
-    manager: State<'_, Mutex<DocumentManager>>,
-    id: String,
-    patch_id: i64,
-    state: Vec<u8>,
-) -> Result<(), String> {
-    // Validate input
-    if state.is_empty() {
-        return Err("Snapshot state cannot be empty".to_string());
+/// UTF-16 range of the first occurrence of `needle`, matching hunk offsets
+fn utf16_range(text: &str, needle: &str) -> Option<(usize, usize)> {
+    let start = text.find(needle)?;
+    let start_utf16 = text[..start].encode_utf16().count();
+    Some((start_utf16, start_utf16 + needle.encode_utf16().count()))
+}
+
+/// Whether `[start, end)` touches `[from, to)`; an empty range touches
+/// anything it sits inside or at the edge of
+fn ranges_touch((start, end): (usize, usize), from: usize, to: usize) -> bool {
+    if from == to {
+        start <= from && from <= end
+    } else {
+        start < to && from < end
     }
-    if state.len() > MAX_SNAPSHOT_SIZE {
-        return Err(format!("Snapshot size exceeds maximum allowed ({} bytes)", MAX_SNAPSHOT_SIZE));
+}
+
+/// Unresolved top-level comments whose text overlaps the changes of a patch
+///
+/// Comments are located by their selected text, in the text before the
+/// patch (for edits and deletions) and in the patch's snapshot (for text it
+/// added), the same way exports place comment footnotes.
+pub(crate) fn blocking_comments(conn: &Connection, meta: &DocumentMeta, patch_uuid: &str) -> Result<Vec<BlockingComment>, String> {
+    let patch_id: i64 = conn
+        .query_row("SELECT id FROM patches WHERE uuid = ?1", [patch_uuid], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Patch not found: {}", patch_uuid))?;
+    let (_, snapshot) = load_patch_snapshot(conn, patch_id)?;
+    let base = previous_save_snapshot(conn, patch_id)?;
+    let mut hunks = patch_diff(conn, meta, patch_id, None)?;
+    hunks.sort_by_key(|h| h.hunk.base_start);
+
+    // Where each hunk ends up in the patch's snapshot
+    let mut shift: isize = 0;
+    let mut modified_ranges = Vec::with_capacity(hunks.len());
+    for h in &hunks {
+        let start = (h.hunk.base_start as isize + shift).max(0) as usize;
+        modified_ranges.push((start, start + h.hunk.modified_length));
+        shift += h.hunk.modified_length as isize - (h.hunk.base_end - h.hunk.base_start) as isize;
+    }
+
+    crate::comments::init_comments_table(conn)?;
+    let comments = crate::comments::load_all_comments(conn)?;
+
+    let mut blocking = Vec::new();
+    for comment in comments {
+        if comment.parent_id.is_some() || comment.status != "unresolved" || comment.selected_text.is_empty() {
+            continue;
+        }
+        let in_base = utf16_range(&base, &comment.selected_text);
+        let in_snapshot = utf16_range(&snapshot, &comment.selected_text);
+
+        let hunk_ids: Vec<String> = hunks
+            .iter()
+            .zip(&modified_ranges)
+            .filter(|(h, (mod_start, mod_end))| {
+                in_base.is_some_and(|range| ranges_touch(range, h.hunk.base_start, h.hunk.base_end))
+                    || in_snapshot.is_some_and(|range| ranges_touch(range, *mod_start, *mod_end))
+            })
+            .map(|(h, _)| h.hunk_id.clone())
+            .collect();
+        if !hunk_ids.is_empty() {
+            blocking.push(BlockingComment { comment, hunk_ids });
+        }
     }
 
+    Ok(blocking)
+}
+
+/// Diff two arbitrary patches, attributing the changes to the second one
+fn diff_between(conn: &Connection, meta: &DocumentMeta, patch_a: i64, patch_b: i64) -> Result<Vec<AuthoredHunk>, String> {
+    let (_, base) = load_patch_snapshot(conn, patch_a)?;
+    let (patch, snapshot) = load_patch_snapshot(conn, patch_b)?;
+
+    let author_name = author_display_name(meta, &patch.author);
+    Ok(author_hunks(calculate_hunks_with(&base, &snapshot, &meta.settings.diff), &patch, &author_name, DEFAULT_HUNK_COLOR))
+}
+
+/// Get the hunks a patch introduced relative to the previous Save snapshot
+#[tauri::command]
+pub fn get_patch_diff(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    patch_id: i64,
+    options: Option<DiffOptions>,
+) -> Result<Vec<AuthoredHunk>, KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
     
-    let doc = manager.documents.get(&id)
-        .ok_or_else(|| format!("Document not found: {}", id))?;
+    let doc = manager.documents.get(&doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
     
-    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&doc.history_path)?;
+    ensure_schema(&conn)?;
     
-    // Ensure tables exist
-    conn.execute_batch(
-        r#"
-        CREATE TABLE IF NOT EXISTS snapshots (
-            id          INTEGER PRIMARY KEY AUTOINCREMENT,
-            timestamp   INTEGER NOT NULL,
-            patch_id    INTEGER NOT NULL,
-            state       BLOB    NOT NULL,
-            FOREIGN KEY (patch_id) REFERENCES patches(id)
-        );
+    Ok(patch_diff(&conn, &doc.meta, patch_id, options.as_ref())?)
+}
 
-        CREATE INDEX IF NOT EXISTS idx_snapshots_patch_id ON snapshots(patch_id);
-        "#,
-    ).map_err(|e| e.to_string())?;
+/// Get the hunks between the snapshots of two arbitrary patches
+#[tauri::command]
+pub fn get_diff_between(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    patch_a: i64,
+    patch_b: i64,
+) -> Result<Vec<AuthoredHunk>, KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
     
-    let timestamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .map_err(|e| e.to_string())?
-        .as_millis() as i64;
+    let doc = manager.documents.get(&doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
     
-    conn.execute(
-        "INSERT INTO snapshots (timestamp, patch_id, state) VALUES (?1, ?2, ?3)",
-        params![timestamp, patch_id, state],
-    ).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&doc.history_path)?;
+    ensure_schema(&conn)?;
     
-    Ok(())
+    Ok(diff_between(&conn, &doc.meta, patch_a, patch_b)?)
+}
+
+/// Text of a document as of a patch: the newest snapshot recorded at or
+/// before the patch
+///
+/// Patches between snapshots only carry editor steps, so a patch without a
+/// snapshot of its own gets the text of the snapshot before it.
+pub(crate) fn snapshot_at_patch(conn: &Connection, patch_id: i64) -> Result<String, String> {
+    let stored: Option<(i64, Vec<u8>)> = conn
+        .query_row(
+            "SELECT patch_id, state FROM snapshot_states WHERE patch_id <= ?1 ORDER BY patch_id DESC, id DESC LIMIT 1",
+            [patch_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    // Snapshots recorded in patch data only, as by older versions
+    let mut stmt = conn
+        .prepare("SELECT id, data FROM patches WHERE id <= ?1 ORDER BY id DESC")
+        .map_err(|e| e.to_string())?;
+    let in_data = stmt
+        .query_map([patch_id], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+        .map_err(|e| e.to_string())?
+        .filter_map(|row| row.ok())
+        .find_map(|(id, data)| {
+            let data: serde_json::Value = serde_json::from_str(&data).ok()?;
+            data.get("snapshot").and_then(|s| s.as_str()).map(|s| (id, s.to_string()))
+        });
+
+    // Whichever is newer; the data wins a tie as it needs no decoding
+    let stored = stored.filter(|(stored_id, _)| in_data.as_ref().is_none_or(|(id, _)| id < stored_id));
+    match (stored, in_data) {
+        (Some((_, state)), _) => String::from_utf8(state).map_err(|_| "Snapshot is not valid UTF-8".to_string()),
+        (None, Some((_, text))) => Ok(text),
+        (None, None) => Err(format!("No snapshot at or before patch {}", patch_id)),
+    }
+}
+
+/// Export a document as it was at a patch
+///
+/// The text as of the patch goes through the same pipeline as a normal
+/// export, with the document's current settings and metadata.
+#[tauri::command]
+pub fn export_at_patch(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    patch_uuid: String,
+    format: String,
+    path: String,
+) -> Result<(), KorppiError> {
+    let export_format = crate::cli::ExportFormat::from_name(&format)
+        .ok_or_else(|| KorppiError::InvalidInput(format!("Unsupported export format: {}", format)))?;
+    let (history_path, meta) = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.clone()))?;
+        (doc.history_path.clone(), doc.meta.clone())
+    };
+
+    let conn = Connection::open(&history_path)?;
+    ensure_schema(&conn)?;
+    let patch_id: i64 = conn
+        .query_row("SELECT id FROM patches WHERE uuid = ?1", params![patch_uuid], |row| row.get(0))
+        .optional()?
+        .ok_or_else(|| KorppiError::PatchNotFound(patch_uuid.clone()))?;
+    let content = snapshot_at_patch(&conn, patch_id)?;
+
+    crate::cli::export_content(export_format, Path::new(&path), content, meta.settings.clone(), Some(&meta))
 }
 
 /// Result of a restore operation for a document
@@ -930,11 +2877,11 @@ pub fn restore_document_to_patch(
     manager: State<'_, Mutex<DocumentManager>>,
     id: String,
     patch_id: i64,
-) -> Result<DocumentRestoreResult, String> {
+) -> Result<DocumentRestoreResult, KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
     
     let doc = manager.documents.get(&id)
-        .ok_or_else(|| format!("Document not found: {}", id))?;
+        .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
     
     if !doc.history_path.exists() {
         return Ok(DocumentRestoreResult {
@@ -943,17 +2890,15 @@ pub fn restore_document_to_patch(
         });
     }
     
-    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&doc.history_path)?;
     
     // Try to get the patch to extract the snapshot field from data
     let mut stmt = conn
-        .prepare("SELECT data FROM patches WHERE id = ?1")
-        .map_err(|e| e.to_string())?;
+        .prepare("SELECT data FROM patches WHERE id = ?1")?;
     
     let data_str: Option<String> = stmt
         .query_row([patch_id], |row| row.get(0))
-        .optional()
-        .map_err(|e| e.to_string())?;
+        .optional()?;
     
     if let Some(data_str) = data_str {
         // Parse the JSON data and extract the snapshot field if present
@@ -991,13 +2936,13 @@ pub fn check_parent_patch_status(
     doc_id: String,
     patch_uuid: String,
     reviewer_id: String,
-) -> Result<ParentPatchStatus, String> {
+) -> Result<ParentPatchStatus, KorppiError> {
     let manager = manager.lock().map_err(|e| e.to_string())?;
 
     let doc = manager.documents.get(&doc_id)
-        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
 
-    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&doc.history_path)?;
 
     // Ensure schema exists
     ensure_schema(&conn)?;
@@ -1009,8 +2954,7 @@ pub fn check_parent_patch_status(
             params![&patch_uuid],
             |row| row.get(0)
         )
-        .optional()
-        .map_err(|e| e.to_string())?
+        .optional()?
         .flatten();
 
     // If no parent, nothing to check
@@ -1030,8 +2974,7 @@ pub fn check_parent_patch_status(
             params![&parent_uuid, &reviewer_id],
             |row| Ok((row.get(0)?, row.get(1)?))
         )
-        .optional()
-        .map_err(|e| e.to_string())?;
+        .optional()?;
 
     match rejection {
         Some((decision, reviewer_name)) if decision == "rejected" => {
@@ -1055,68 +2998,78 @@ pub fn check_parent_patch_status(
 
 /// Supported import file formats
 #[derive(Debug, Clone, Copy, PartialEq)]
-enum ImportFormat {
+pub(crate) enum ImportFormat {
     Markdown,
     RMarkdown,
     Quarto,
     Docx,
     Odt,
+    Html,
 }
 
 impl ImportFormat {
-    fn from_extension(ext: &str) -> Option<Self> {
+    pub(crate) fn from_extension(ext: &str) -> Option<Self> {
         match ext.to_lowercase().as_str() {
             "md" | "markdown" | "txt" => Some(ImportFormat::Markdown),
             "rmd" => Some(ImportFormat::RMarkdown),
             "qmd" => Some(ImportFormat::Quarto),
             "docx" => Some(ImportFormat::Docx),
             "odt" => Some(ImportFormat::Odt),
+            "html" | "htm" => Some(ImportFormat::Html),
             _ => None,
         }
     }
 }
 
-/// Strip YAML frontmatter from markdown content (for .rmd, .qmd files)
-fn strip_yaml_frontmatter(content: &str) -> String {
-    let lines: Vec<&str> = content.lines().collect();
-
-    // Check if the document starts with YAML frontmatter delimiter
-    if lines.is_empty() || lines[0].trim() != "---" {
-        return content.to_string();
-    }
-
-    // Find the closing delimiter
-    for (i, line) in lines.iter().enumerate().skip(1) {
-        if line.trim() == "---" || line.trim() == "..." {
-            // Return everything after the frontmatter
-            return lines[(i + 1)..].join("\n");
-        }
-    }
-
-    // No closing delimiter found, return original content
-    content.to_string()
+/// Tauri command to check if pandoc is available
+#[tauri::command]
+pub fn check_pandoc_available() -> bool {
+    is_pandoc_available()
 }
 
-/// Check if pandoc is available on the system
-fn is_pandoc_available() -> bool {
-    use std::process::Command;
-    Command::new("pandoc")
-        .arg("--version")
-        .output()
-        .map(|o| o.status.success())
-        .unwrap_or(false)
+/// An image stored with a document, referenced as `assets/<name>`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentAsset {
+    pub name: String,
+    /// Location in the document's temp directory
+    pub path: PathBuf,
 }
 
-/// Tauri command to check if pandoc is available
+/// List the images stored with a document, so the editor can display
+/// `assets/` references
 #[tauri::command]
-pub fn check_pandoc_available() -> bool {
-    is_pandoc_available()
+pub fn list_document_assets(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+) -> Result<Vec<DocumentAsset>, KorppiError> {
+    let assets_dir = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.clone()))?;
+        document_assets_dir(&doc.history_path)
+    };
+    if !assets_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut assets = Vec::new();
+    for entry in fs::read_dir(&assets_dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            assets.push(DocumentAsset { name, path });
+        }
+    }
+    assets.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(assets)
 }
 
 /// Tauri command to open a URL in the system's default browser
 #[tauri::command]
-pub fn open_url(url: String) -> Result<(), String> {
-    open::that(&url).map_err(|e| format!("Failed to open URL: {}", e))
+pub fn open_url(url: String) -> Result<(), KorppiError> {
+    open::that(&url).map_err(|e| KorppiError::Other(format!("Failed to open URL: {}", e)))
 }
 
 /// Extract content from a DOCX file and convert to Markdown
@@ -1141,9 +3094,7 @@ fn extract_docx_text(file_path: &PathBuf) -> Result<String, String> {
 
 /// Convert a document to markdown using pandoc
 fn convert_with_pandoc(file_path: &PathBuf, from_format: &str) -> Result<String, String> {
-    use std::process::Command;
-    
-    let output = Command::new("pandoc")
+    let output = pandoc_command()
         .arg("-f")
         .arg(from_format)
         .arg("-t")
@@ -1322,6 +3273,52 @@ fn extract_odt_text_basic(file_path: &PathBuf) -> Result<String, String> {
     Ok(text_parts.join("\n\n"))
 }
 
+/// Convert an imported file to markdown
+///
+/// HTML imports also write their images and comments into `temp_dir`, as
+/// do markdown imports with CriticMarkup;
+/// Quarto and R Markdown imports return what is needed to export them back.
+fn extract_import_content(
+    format: ImportFormat,
+    file_path: &PathBuf,
+    temp_dir: &Path,
+) -> Result<(String, Option<SourceMetadata>), String> {
+    let content = match format {
+        ImportFormat::Markdown => {
+            let markdown = fs::read_to_string(file_path)
+                .map_err(|e| format!("Failed to read markdown file: {}", e))?;
+            // CriticMarkup changes become a suggestion and its comments Korppi comments
+            match crate::criticmarkup::parse_criticmarkup(&markdown) {
+                Some(import) => {
+                    crate::html_import::store_annotations(&temp_dir.join("history.sqlite"), &import)?;
+                    import.markdown
+                }
+                None => markdown,
+            }
+        }
+        ImportFormat::RMarkdown | ImportFormat::Quarto => {
+            let raw_content = fs::read_to_string(file_path)
+                .map_err(|e| format!("Failed to read file: {}", e))?;
+            let source_format = if format == ImportFormat::Quarto { "quarto" } else { "rmarkdown" };
+            let (body, mut source) = split_source(&raw_content, source_format);
+            source.path = Some(file_path.clone());
+            return Ok((body, Some(source)));
+        }
+        ImportFormat::Docx => {
+            extract_docx_text(file_path)?
+        }
+        ImportFormat::Odt => {
+            extract_odt_text(file_path)?
+        }
+        ImportFormat::Html => {
+            let import = crate::html_import::import_html(file_path, temp_dir, true)?;
+            crate::html_import::store_annotations(&temp_dir.join("history.sqlite"), &import)?;
+            import.markdown
+        }
+    };
+    Ok((content, None))
+}
+
 /// Result of an import operation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImportResult {
@@ -1330,14 +3327,15 @@ pub struct ImportResult {
     pub source_format: String,
 }
 
-/// Import a document from various formats (markdown, docx, odt)
+/// Import a document from various formats (markdown, docx, odt, html)
 /// Shows file picker if path is None
 #[tauri::command]
 pub async fn import_document(
     app: AppHandle,
+    window: Window,
     manager: State<'_, Mutex<DocumentManager>>,
     path: Option<String>,
-) -> Result<ImportResult, String> {
+) -> Result<ImportResult, KorppiError> {
     use tauri_plugin_dialog::DialogExt;
 
     let file_path: PathBuf = if let Some(p) = path {
@@ -1346,22 +3344,23 @@ pub async fn import_document(
         // Show file picker with filters for all supported formats
         let file = app.dialog()
             .file()
-            .add_filter("All Supported", &["md", "markdown", "txt", "rmd", "qmd", "docx", "odt"])
+            .add_filter("All Supported", &["md", "markdown", "txt", "rmd", "qmd", "docx", "odt", "html", "htm"])
             .add_filter("Markdown", &["md", "markdown", "txt"])
             .add_filter("R Markdown", &["rmd"])
             .add_filter("Quarto", &["qmd"])
             .add_filter("Word Document", &["docx"])
             .add_filter("OpenDocument Text", &["odt"])
+            .add_filter("Web Page (Google Docs export)", &["html", "htm"])
             .blocking_pick_file();
 
         match file {
             Some(f) => f.into_path().map_err(|_| "Failed to convert file path".to_string())?,
-            None => return Err("No file selected".to_string()),
+            None => return Err("No file selected".into()),
         }
     };
 
     if !file_path.exists() {
-        return Err(format!("File not found: {:?}", file_path));
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("File not found: {:?}", file_path)).into());
     }
 
     // Determine format from extension
@@ -1373,29 +3372,14 @@ pub async fn import_document(
     let format = ImportFormat::from_extension(extension)
         .ok_or_else(|| format!("Unsupported file format: {}", extension))?;
 
-    // Extract content based on format
-    let content = match format {
-        ImportFormat::Markdown => {
-            fs::read_to_string(&file_path)
-                .map_err(|e| format!("Failed to read markdown file: {}", e))?
-        }
-        ImportFormat::RMarkdown | ImportFormat::Quarto => {
-            let raw_content = fs::read_to_string(&file_path)
-                .map_err(|e| format!("Failed to read file: {}", e))?;
-            strip_yaml_frontmatter(&raw_content)
-        }
-        ImportFormat::Docx => {
-            extract_docx_text(&file_path)?
-        }
-        ImportFormat::Odt => {
-            extract_odt_text(&file_path)?
-        }
-    };
-
-    // Create a new document
+    // Create a new document; HTML imports put images and comments in it
     let doc_id = Uuid::new_v4().to_string();
     let temp_dir = create_document_temp_dir(&doc_id)?;
 
+    let (content, source) = extract_import_content(format, &file_path, &temp_dir).inspect_err(|_| {
+        cleanup_document_temp_dir(&doc_id).ok();
+    })?;
+
     // Get title from filename
     let title = file_path
         .file_stem()
@@ -1408,21 +3392,29 @@ pub async fn import_document(
         title: title.clone(),
         is_modified: true, // Mark as modified since it's not saved as KMD yet
         opened_at: Utc::now(),
+        read_only: false,
+        external_merge: None,
+        recovered: None,
+        safe_mode: None,
     };
 
     let mut meta = DocumentMeta::default();
     meta.title = title;
+    meta.source = source;
 
     let state = DocumentState {
         handle: handle.clone(),
         yjs_state: Vec::new(), // Will be populated when editor loads
+        yjs_updates: Vec::new(),
         history_path: temp_dir.join("history.sqlite"),
         meta,
+        sections: HashMap::new(),
+        disk_fingerprint: None,
     };
 
     let mut manager = manager.lock().map_err(|e| e.to_string())?;
     manager.documents.insert(doc_id.clone(), state);
-    manager.active_document_id = Some(doc_id);
+    manager.set_active(window.label(), doc_id);
 
     let format_name = match format {
         ImportFormat::Markdown => "markdown",
@@ -1430,8 +3422,16 @@ pub async fn import_document(
         ImportFormat::Quarto => "quarto",
         ImportFormat::Docx => "docx",
         ImportFormat::Odt => "odt",
+        ImportFormat::Html => "html",
     };
 
+    log_event(&app, "import", Some(&handle.id), serde_json::json!({
+        "path": file_path,
+        "format": format_name,
+        "length": content.len(),
+    }));
+    emit_event(&app, DOCUMENT_OPENED, handle.clone());
+
     Ok(ImportResult {
         handle,
         content,
@@ -1451,6 +3451,10 @@ mod tests {
             title: "Test Document".to_string(),
             is_modified: false,
             opened_at: Utc::now(),
+            read_only: false,
+            external_merge: None,
+            recovered: None,
+            safe_mode: None,
         };
         
         let json = serde_json::to_string(&handle).unwrap();
@@ -1467,6 +3471,7 @@ mod tests {
             path: PathBuf::from("/test/path.kmd"),
             title: "Test Doc".to_string(),
             last_opened: Utc::now(),
+            pinned: false,
         };
         
         let json = serde_json::to_string(&recent).unwrap();
@@ -1476,11 +3481,80 @@ mod tests {
         assert_eq!(parsed.title, recent.title);
     }
     
+    #[test]
+    fn test_parse_legacy_recent_file() {
+        let legacy = r#"[{"path": "/a.kmd", "title": "A", "last_opened": 0}]"#;
+        let file = parse_recent_file(legacy).unwrap();
+        
+        assert_eq!(file.version, RECENT_SCHEMA_VERSION);
+        assert_eq!(file.documents.len(), 1);
+        assert!(!file.documents[0].pinned);
+        assert_eq!(file.settings.max_entries, 10);
+        assert!(file.settings.tracking_enabled);
+    }
+    
+    #[test]
+    fn test_trim_recent_keeps_pinned() {
+        let mut recent: Vec<RecentDocument> = (0..5)
+            .map(|i| RecentDocument {
+                path: PathBuf::from(format!("/doc{}.kmd", i)),
+                title: format!("Doc {}", i),
+                last_opened: Utc::now(),
+                pinned: i == 4,
+            })
+            .collect();
+        
+        trim_recent(&mut recent, 3);
+        
+        let paths: Vec<_> = recent.iter().map(|r| r.path.to_string_lossy().to_string()).collect();
+        assert_eq!(paths, vec!["/doc0.kmd", "/doc1.kmd", "/doc4.kmd"]);
+    }
+    
     #[test]
     fn test_document_manager_default() {
         let manager = DocumentManager::default();
         assert!(manager.documents.is_empty());
-        assert!(manager.active_document_id.is_none());
+        assert!(manager.active_documents.is_empty());
+    }
+    
+    #[test]
+    fn test_active_document_per_window() {
+        let mut manager = DocumentManager::default();
+        for id in ["a", "b"] {
+            manager.documents.insert(id.to_string(), DocumentState {
+                handle: DocumentHandle {
+                    id: id.to_string(),
+                    path: Some(PathBuf::from(format!("/{}.kmd", id))),
+                    title: id.to_string(),
+                    is_modified: false,
+                    opened_at: Utc::now(),
+                    read_only: false,
+                    external_merge: None,
+                    recovered: None,
+                    safe_mode: None,
+                },
+                yjs_state: Vec::new(),
+                yjs_updates: Vec::new(),
+                history_path: PathBuf::new(),
+                meta: DocumentMeta::default(),
+                sections: HashMap::new(),
+                disk_fingerprint: None,
+            });
+        }
+        manager.set_active(MAIN_WINDOW, "a".to_string());
+        manager.set_active("document-1", "b".to_string());
+        assert_eq!(manager.active_document_id(MAIN_WINDOW).map(String::as_str), Some("a"));
+        assert_eq!(manager.active_document_id("document-1").map(String::as_str), Some("b"));
+        assert_eq!(manager.document_at(Path::new("/b.kmd")).map(String::as_str), Some("b"));
+        
+        // Closing a document only moves the windows that showed it
+        manager.documents.remove("b");
+        manager.document_closed("b");
+        assert_eq!(manager.active_document_id("document-1").map(String::as_str), Some("a"));
+        assert_eq!(manager.active_document_id(MAIN_WINDOW).map(String::as_str), Some("a"));
+        
+        manager.window_closed("document-1");
+        assert!(manager.active_document_id("document-1").is_none());
     }
     
     #[test]
@@ -1515,6 +3589,10 @@ mod tests {
             title: "Imported Doc".to_string(),
             is_modified: false,
             opened_at: Utc::now(),
+            read_only: false,
+            external_merge: None,
+            recovered: None,
+            safe_mode: None,
         };
         
         let result = ImportResult {
@@ -1539,4 +3617,432 @@ mod tests {
         // We just verify it doesn't panic
         assert!(result.is_ok() || result.is_err());
     }
+    
+    #[test]
+    fn test_patch_diff_against_previous_save() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+        
+        let saves = [
+            ("alice", "Hello world"),
+            ("bob", "Hello brave world"),
+        ];
+        for (i, (author, text)) in saves.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO patches (timestamp, author, kind, data, uuid) VALUES (?1, ?2, 'Save', ?3, ?4)",
+                params![i as i64, author, serde_json::json!({"snapshot": text}).to_string(), format!("uuid-{}", i)],
+            ).unwrap();
+        }
+        
+        let mut meta = DocumentMeta::default();
+        meta.authors.push(crate::kmd::AuthorRef {
+            id: "bob".to_string(),
+            name: "Bob".to_string(),
+            email: None,
+            joined_at: None,
+            role: None,
+        });
+        
+        // First patch diffs against an empty document
+        let first = patch_diff(&conn, &meta, 1, None).unwrap();
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].hunk.modified_text, "Hello world");
+        
+        let second = patch_diff(&conn, &meta, 2, None).unwrap();
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].hunk.base_text, "");
+        assert_eq!(second[0].hunk.modified_text, "brave ");
+        assert_eq!(second[0].author_name, "Bob");
+        assert_eq!(second[0].patch_uuid.as_deref(), Some("uuid-1"));
+        
+        // Reversed comparison attributes the deletion to the first patch
+        let reversed = diff_between(&conn, &meta, 2, 1).unwrap();
+        assert_eq!(reversed.len(), 1);
+        assert_eq!(reversed[0].hunk.hunk_type, "delete");
+        assert_eq!(reversed[0].author, "alice");
+        
+        assert!(patch_diff(&conn, &meta, 99, None).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_at_patch() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+
+        let patches = [
+            ("Save", Some("Submitted version")),
+            ("semantic_group", None),
+            ("AutoSave", Some("Revised version")),
+        ];
+        for (i, (kind, snapshot)) in patches.iter().enumerate() {
+            let data = match snapshot {
+                Some(text) => serde_json::json!({ "snapshot": text }),
+                None => serde_json::json!({ "steps": [] }),
+            };
+            conn.execute(
+                "INSERT INTO patches (timestamp, author, kind, data, uuid) VALUES (?1, 'alice', ?2, ?3, ?4)",
+                params![i as i64, kind, data.to_string(), format!("uuid-{}", i)],
+            ).unwrap();
+        }
+        // Only the autosnapshot has a snapshot row; the Save predates them
+        conn.execute(
+            "INSERT INTO snapshots (timestamp, patch_id, state) VALUES (2, 3, ?1)",
+            params!["Revised version".as_bytes()],
+        ).unwrap();
+
+        assert_eq!(snapshot_at_patch(&conn, 1).unwrap(), "Submitted version");
+        assert_eq!(snapshot_at_patch(&conn, 2).unwrap(), "Submitted version");
+        assert_eq!(snapshot_at_patch(&conn, 3).unwrap(), "Revised version");
+        assert!(snapshot_at_patch(&conn, 0).is_err());
+    }
+    
+    #[test]
+    fn test_blocking_comments_overlap_patch_hunks() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+        crate::comments::init_comments_table(&conn).unwrap();
+
+        for (i, text) in ["The cat sat on the mat.", "The cat sat on the big mat."].iter().enumerate() {
+            conn.execute(
+                "INSERT INTO patches (timestamp, author, kind, data, uuid) VALUES (?1, 'bob', 'Save', ?2, ?3)",
+                params![i as i64, serde_json::json!({"snapshot": text}).to_string(), format!("uuid-{}", i)],
+            ).unwrap();
+        }
+        for (text, status) in [("big mat", "unresolved"), ("The cat", "unresolved"), ("the big", "resolved")] {
+            conn.execute(
+                "INSERT INTO comments (timestamp, author, start_anchor, end_anchor, selected_text, content, status) VALUES (1, 'alice', 'a', 'b', ?1, 'Hmm', ?2)",
+                params![text, status],
+            ).unwrap();
+        }
+
+        // Only the open comment on the added word blocks the second patch
+        let blocking = blocking_comments(&conn, &DocumentMeta::default(), "uuid-1").unwrap();
+        assert_eq!(blocking.len(), 1);
+        assert_eq!(blocking[0].comment.selected_text, "big mat");
+        assert_eq!(blocking[0].hunk_ids.len(), 1);
+
+        assert!(blocking_comments(&conn, &DocumentMeta::default(), "missing").is_err());
+    }
+
+    #[test]
+    fn test_kmd_roundtrip_with_update_chunks() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let kmd_path = dir.path().join("chunked.kmd");
+        let history_path = dir.path().join("history.sqlite");
+        
+        let yjs_text = |client: u64, text: &str| {
+            use yrs::{ReadTxn, StateVector, Text, Transact};
+            let doc = yrs::Doc::with_client_id(client);
+            let content = doc.get_or_insert_text("content");
+            content.insert(&mut doc.transact_mut(), 0, text);
+            let txn = doc.transact();
+            txn.encode_state_as_update_v1(&StateVector::default())
+        };
+        let checkpoint = yjs_text(1, "Hello");
+        let updates = vec![
+            YjsUpdate { author: "bob".to_string(), seq: 1, data: yjs_text(3, "Hi") },
+            YjsUpdate { author: "alice".to_string(), seq: 2, data: yjs_text(2, "Hey") },
+            YjsUpdate { author: "alice".to_string(), seq: 1, data: yjs_text(4, "Yo") },
+        ];
+        
+        let min_reader_version = |kmd_path: &PathBuf| {
+            let mut archive = ZipArchive::new(File::open(kmd_path).unwrap()).unwrap();
+            let mut content = String::new();
+            archive.by_name("format.json").unwrap().read_to_string(&mut content).unwrap();
+            serde_json::from_str::<FormatInfo>(&content).unwrap().min_reader_version
+        };
+        
+        bundle_to_kmd(&kmd_path, &checkpoint, &updates, &history_path, &DocumentMeta::default(), &HashMap::new()).unwrap();
+        // v0.1 readers would open the checkpoint without the updates
+        assert_eq!(min_reader_version(&kmd_path), crate::kmd::CHUNKED_MIN_READER_VERSION);
+        
+        let mut archive = ZipArchive::new(File::open(&kmd_path).unwrap()).unwrap();
+        let read = read_yjs_updates(&mut archive, &mut FileProgress::silent()).unwrap();
+        let order: Vec<(&str, u32)> = read.iter().map(|u| (u.author.as_str(), u.seq)).collect();
+        assert_eq!(order, vec![("alice", 1), ("alice", 2), ("bob", 1)]);
+        
+        // The editor state is the checkpoint with all updates applied
+        let doc_id = format!("test-{}", Uuid::new_v4());
+        let extracted = extract_kmd_to_temp(&kmd_path, &doc_id).unwrap();
+        cleanup_document_temp_dir(&doc_id).ok();
+        let mut parts: Vec<&[u8]> = vec![&checkpoint];
+        parts.extend(updates.iter().map(|u| u.data.as_slice()));
+        assert_eq!(extracted.yjs_state, merge_states(&parts).unwrap());
+        
+        // Saving the opened document again keeps no updates, and v0.1
+        // readers can open it
+        bundle_to_kmd(&kmd_path, &extracted.yjs_state, &[], &history_path, &DocumentMeta::default(), &HashMap::new()).unwrap();
+        assert_eq!(min_reader_version(&kmd_path), crate::kmd::MIN_READER_VERSION);
+        let mut archive = ZipArchive::new(File::open(&kmd_path).unwrap()).unwrap();
+        assert!(read_yjs_updates(&mut archive, &mut FileProgress::silent()).unwrap().is_empty());
+        
+        // Without a checkpoint v0.1 readers must refuse the file
+        bundle_to_kmd(&kmd_path, &[], &updates, &history_path, &DocumentMeta::default(), &HashMap::new()).unwrap();
+        assert_eq!(min_reader_version(&kmd_path), crate::kmd::CHUNKED_MIN_READER_VERSION);
+    }
+    
+    #[test]
+    fn test_build_clean_history() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = dir.path().join("source.sqlite");
+        let conn = Connection::open(&source).unwrap();
+        ensure_schema(&conn).unwrap();
+        crate::comments::init_comments_table(&conn).unwrap();
+        for (uuid, snapshot) in [("p1", "Draft"), ("p2", "Final")] {
+            conn.execute(
+                "INSERT INTO patches (timestamp, author, kind, data, uuid) VALUES (1, 'alice', 'Save', ?1, ?2)",
+                params![serde_json::json!({ "snapshot": snapshot }).to_string(), uuid],
+            ).unwrap();
+        }
+        for uuid in ["p1", "p2"] {
+            conn.execute(
+                "INSERT INTO patch_reviews (patch_uuid, reviewer_id, decision, reviewed_at) VALUES (?1, 'bob', 'accepted', 1)",
+                params![uuid],
+            ).unwrap();
+        }
+        conn.execute(
+            "INSERT INTO comments (timestamp, author, start_anchor, end_anchor, selected_text, content) VALUES (1, 'bob', 'a', 'b', 'Final', 'Nice')",
+            [],
+        ).unwrap();
+        drop(conn);
+        
+        let count = |path: &Path, sql: &str| -> i64 {
+            Connection::open(path).unwrap().query_row(sql, [], |row| row.get(0)).unwrap()
+        };
+        
+        // Keeping reviews and comments: only those of the final patch survive
+        let options = CleanExportOptions { authors: None, strip_comments: false, strip_reviews: false };
+        let kept = dir.path().join("kept.sqlite");
+        build_clean_history(&source, &kept, "Final", &options).unwrap();
+        assert_eq!(count(&kept, "SELECT COUNT(*) FROM patches WHERE uuid = 'p2'"), 1);
+        assert_eq!(count(&kept, "SELECT COUNT(*) FROM patches"), 1);
+        assert_eq!(count(&kept, "SELECT COUNT(*) FROM snapshots"), 1);
+        assert_eq!(count(&kept, "SELECT COUNT(*) FROM patch_reviews"), 1);
+        assert_eq!(count(&kept, "SELECT COUNT(*) FROM comments"), 1);
+        
+        // Unsaved text gets a fresh Save patch; stripped records are gone
+        let options = CleanExportOptions { authors: None, strip_comments: true, strip_reviews: true };
+        let clean = dir.path().join("clean.sqlite");
+        build_clean_history(&source, &clean, "Edited", &options).unwrap();
+        assert_eq!(count(&clean, "SELECT COUNT(*) FROM patches WHERE uuid IN ('p1', 'p2')"), 0);
+        assert_eq!(count(&clean, "SELECT COUNT(*) FROM patches"), 1);
+        assert_eq!(count(&clean, "SELECT COUNT(*) FROM patch_reviews"), 0);
+    }
+    
+    #[test]
+    fn test_kmd_roundtrip_with_sections() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let kmd_path = dir.path().join("project.kmd");
+        let history_path = dir.path().join("history.sqlite");
+        let section_history = dir.path().join("section.sqlite");
+        Connection::open(&section_history)
+            .unwrap()
+            .execute_batch("CREATE TABLE marker (name TEXT); INSERT INTO marker VALUES ('section history');")
+            .unwrap();
+        
+        let meta = DocumentMeta {
+            sections: vec![
+                crate::kmd::SectionRef { id: "s2".to_string(), title: "Methods".to_string() },
+                crate::kmd::SectionRef { id: "s1".to_string(), title: "Introduction".to_string() },
+            ],
+            ..DocumentMeta::default()
+        };
+        let mut sections = HashMap::new();
+        sections.insert("s1".to_string(), SectionState { yjs_state: vec![1, 2], history_path: section_history });
+        sections.insert("s2".to_string(), SectionState { yjs_state: vec![3], history_path: dir.path().join("missing.sqlite") });
+        
+        bundle_to_kmd(&kmd_path, &[9], &[], &history_path, &meta, &sections).unwrap();
+        
+        let doc_id = format!("test-{}", Uuid::new_v4());
+        let extracted = extract_kmd_to_temp(&kmd_path, &doc_id).unwrap();
+        
+        let titles: Vec<&str> = extracted.meta.sections.iter().map(|s| s.title.as_str()).collect();
+        assert_eq!(titles, vec!["Methods", "Introduction"]);
+        assert_eq!(extracted.sections["s1"].yjs_state, vec![1, 2]);
+        let marker: String = Connection::open(&extracted.sections["s1"].history_path)
+            .unwrap()
+            .query_row("SELECT name FROM marker", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(marker, "section history");
+        assert_eq!(extracted.sections["s2"].yjs_state, vec![3]);
+        assert!(!extracted.sections["s2"].history_path.exists());
+        cleanup_document_temp_dir(&doc_id).ok();
+        
+        let mut archive = ZipArchive::new(File::open(&kmd_path).unwrap()).unwrap();
+        let mut content = String::new();
+        archive.by_name("format.json").unwrap().read_to_string(&mut content).unwrap();
+        let format_info: FormatInfo = serde_json::from_str(&content).unwrap();
+        assert_eq!(format_info.min_reader_version, crate::kmd::PROJECT_MIN_READER_VERSION);
+    }
+    
+    #[test]
+    fn test_external_merge_of_sections() {
+        use crate::patch_store::{insert_test_patch, test_patch};
+        let dir = tempfile::TempDir::new().unwrap();
+        let history = |name: &str, uuids: &[&str]| {
+            let path = dir.path().join(name);
+            let conn = Connection::open(&path).unwrap();
+            ensure_schema(&conn).unwrap();
+            for uuid in uuids {
+                insert_test_patch(&conn, test_patch(uuid, None, uuid));
+            }
+            path
+        };
+        let section = |id: &str| SectionRef { id: id.to_string(), title: id.to_uppercase() };
+        let state = |history_path| SectionState { yjs_state: Vec::new(), history_path };
+        
+        let mut order = vec![section("s1"), section("s2")];
+        let mut sections = HashMap::from([
+            ("s1".to_string(), state(history("ours-s1.sqlite", &["p1"]))),
+            ("s2".to_string(), state(history("ours-s2.sqlite", &[]))),
+        ]);
+        let incoming = ExtractedKmd {
+            yjs_state: Vec::new(),
+            history_path: dir.path().join("history.sqlite"),
+            meta: DocumentMeta { sections: vec![section("s1"), section("s3")], ..DocumentMeta::default() },
+            sections: HashMap::from([
+                ("s1".to_string(), state(history("theirs-s1.sqlite", &["p1", "p2"]))),
+                ("s3".to_string(), state(history("theirs-s3.sqlite", &["p3"]))),
+            ]),
+            stripped: Vec::new(),
+        };
+        
+        let diverged = merge_sections(&mut sections, &mut order, &incoming, &dir.path().join("sections")).unwrap();
+        assert!(!diverged);
+        let ids: Vec<&str> = order.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["s1", "s3", "s2"]);
+        let uuids = |path: &Path| -> Vec<String> {
+            PatchStore::open(path).unwrap().list().unwrap().into_iter().filter_map(|p| p.uuid).collect()
+        };
+        assert_eq!(uuids(&sections["s1"].history_path), vec!["p1", "p2"]);
+        assert_eq!(sections["s3"].history_path, dir.path().join("sections").join("s3").join("history.sqlite"));
+        assert_eq!(uuids(&sections["s3"].history_path), vec!["p3"]);
+    }
+    
+    #[test]
+    fn test_unknown_files_are_read_in_safe_mode() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let kmd_path = dir.path().join("received.kmd");
+        let history_path = dir.path().join("history.sqlite");
+        let conn = Connection::open(&history_path).unwrap();
+        ensure_schema(&conn).unwrap();
+        conn.execute_batch("CREATE TRIGGER wipe AFTER INSERT ON patches BEGIN DELETE FROM patches; END;")
+            .unwrap();
+        drop(conn);
+        bundle_to_kmd(&kmd_path, &[], &[], &history_path, &DocumentMeta::default(), &HashMap::new()).unwrap();
+        
+        let doc_id = format!("test-{}", Uuid::new_v4());
+        let extracted = extract_kmd_to_temp(&kmd_path, &doc_id).unwrap();
+        assert_eq!(extracted.stripped, vec!["trigger wipe in history.sqlite"]);
+        let triggers: i64 = Connection::open(&extracted.history_path)
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'trigger'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(triggers, 0);
+        cleanup_document_temp_dir(&doc_id).ok();
+    }
+    
+    #[test]
+    fn test_kmd_roundtrip_with_assets() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let kmd_path = dir.path().join("figures.kmd");
+        let history_path = dir.path().join("history.sqlite");
+        fs::create_dir_all(document_assets_dir(&history_path)).unwrap();
+        fs::write(document_assets_dir(&history_path).join("image1.png"), b"png").unwrap();
+        
+        bundle_to_kmd(&kmd_path, &[9], &[], &history_path, &DocumentMeta::default(), &HashMap::new()).unwrap();
+        
+        let doc_id = format!("test-{}", Uuid::new_v4());
+        let extracted = extract_kmd_to_temp(&kmd_path, &doc_id).unwrap();
+        assert_eq!(fs::read(document_assets_dir(&extracted.history_path).join("image1.png")).unwrap(), b"png");
+        cleanup_document_temp_dir(&doc_id).ok();
+    }
+    
+    #[test]
+    fn test_cancelled_save_keeps_previous_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let kmd_path = dir.path().join("doc.kmd");
+        let history_path = dir.path().join("history.sqlite");
+        bundle_to_kmd(&kmd_path, &[9], &[], &history_path, &DocumentMeta::default(), &HashMap::new()).unwrap();
+        let saved = fs::read(&kmd_path).unwrap();
+        
+        let mut jobs = FileJobs::default();
+        let mut progress = FileProgress::silent_with(jobs.start("job"));
+        jobs.cancel("job");
+        let result = bundle_to_kmd_with_progress(&kmd_path, &[1, 2], &[], &history_path, &DocumentMeta::default(), &HashMap::new(), &mut progress);
+        assert_eq!(result.unwrap_err().to_string(), crate::file_progress::CANCELLED);
+        assert_eq!(fs::read(&kmd_path).unwrap(), saved);
+        assert!(!kmd_path.with_extension("kmd.part").exists());
+    }
+    
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_full_disk_reaches_the_frontend() {
+        // Every write to /dev/full fails with ENOSPC
+        let dir = tempfile::TempDir::new().unwrap();
+        let history_path = dir.path().join("history.sqlite");
+        let error = write_kmd(
+            Path::new("/dev/full"),
+            &[1; 4096],
+            &[],
+            &history_path,
+            &DocumentMeta::default(),
+            &HashMap::new(),
+            &mut FileProgress::silent(),
+        )
+        .unwrap_err();
+        assert_eq!(error.code(), "disk_full");
+        assert_eq!(serde_json::to_value(&error).unwrap()["code"], "disk_full");
+    }
+    
+    #[test]
+    fn test_collect_orphaned_temp_dirs_skips_open_documents() {
+        let base = tempfile::TempDir::new().unwrap();
+        for id in ["open-doc", "orphan-doc", "crashed-doc"] {
+            fs::create_dir_all(base.path().join(id)).unwrap();
+            fs::write(base.path().join(id).join("history.sqlite"), [0u8; 128]).unwrap();
+        }
+        fs::write(base.path().join("crashed-doc").join(journal::JOURNAL_FILE), "{}\n").unwrap();
+        
+        let open_ids: HashSet<String> = ["open-doc".to_string()].into_iter().collect();
+        
+        // Fresh directories survive an age-limited sweep
+        let report = collect_orphaned_temp_dirs(base.path(), &open_ids, Some(Duration::from_secs(3600))).unwrap();
+        assert_eq!(report.removed_dirs, 0);
+        
+        let report = collect_orphaned_temp_dirs(base.path(), &open_ids, None).unwrap();
+        assert_eq!(report.removed_dirs, 1);
+        assert_eq!(report.reclaimed_bytes, 128);
+        assert!(base.path().join("open-doc").exists());
+        assert!(!base.path().join("orphan-doc").exists());
+        // Unrecovered journals are kept
+        assert!(base.path().join("crashed-doc").exists());
+    }
+    
+    #[test]
+    fn test_disk_fingerprint_detects_external_changes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let kmd_path = dir.path().join("paper.kmd");
+        fs::write(&kmd_path, b"first version").unwrap();
+        
+        let fingerprint = DiskFingerprint::read(&kmd_path).unwrap();
+        assert!(fingerprint.matches(&kmd_path));
+        
+        // Same size, different content
+        fs::write(&kmd_path, b"other version").unwrap();
+        assert!(!fingerprint.matches(&kmd_path));
+        
+        // Also when the mtime did not change, as on filesystems with coarse mtime
+        let file = File::options().write(true).open(&kmd_path).unwrap();
+        file.set_modified(fingerprint.modified.unwrap()).unwrap();
+        drop(file);
+        assert_eq!(fs::metadata(&kmd_path).unwrap().modified().ok(), fingerprint.modified);
+        assert!(!fingerprint.matches(&kmd_path));
+        
+        fs::write(&kmd_path, b"a longer second version").unwrap();
+        assert!(!fingerprint.matches(&kmd_path));
+        
+        fs::remove_file(&kmd_path).unwrap();
+        assert!(!fingerprint.matches(&kmd_path));
+    }
 }
@@ -0,0 +1,245 @@
+// src-tauri/src/document_metadata.rs
+//! Descriptive metadata of a document: abstract, keywords and custom fields.
+//!
+//! The fields live in meta.json next to the title. Exports carry them as a
+//! YAML frontmatter block, which pandoc turns into the core and custom
+//! properties of a DOCX file and the `<meta>` tags of an HTML page, and
+//! which markdown tools read as they are. Workspace search looks through
+//! them as well as through the text.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::document_manager::DocumentManager;
+use crate::error::KorppiError;
+use crate::kmd::DocumentMeta;
+
+/// Frontmatter keys written from other parts of the metadata
+const RESERVED_FIELDS: [&str; 7] = ["title", "author", "abstract", "keywords", "description", "date", "lang"];
+
+/// Longest custom field name
+const MAX_FIELD_NAME_LEN: usize = 64;
+
+/// The descriptive metadata of a document
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct DocumentMetadata {
+    pub title: String,
+    #[serde(rename = "abstract")]
+    pub abstract_text: Option<String>,
+    pub keywords: Vec<String>,
+    pub custom_fields: BTreeMap<String, String>,
+}
+
+impl DocumentMetadata {
+    fn of(meta: &DocumentMeta) -> Self {
+        Self {
+            title: meta.title.clone(),
+            abstract_text: meta.abstract_text.clone(),
+            keywords: meta.keywords.clone(),
+            custom_fields: meta.custom_fields.clone(),
+        }
+    }
+}
+
+/// Trim keywords, dropping empty ones and repeats that differ only in case
+pub fn normalize_keywords(keywords: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(keywords.len());
+    for keyword in keywords {
+        let keyword = keyword.trim();
+        if !keyword.is_empty() && !normalized.iter().any(|k| k.eq_ignore_ascii_case(keyword)) {
+            normalized.push(keyword.to_string());
+        }
+    }
+    normalized
+}
+
+/// A custom field name usable as a YAML key: a letter, then letters,
+/// digits, `_` or `-`, and none of the keys the frontmatter already has
+pub fn validate_field_name(name: &str) -> Result<(), String> {
+    let valid = name.len() <= MAX_FIELD_NAME_LEN
+        && name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(format!("Invalid field name: {:?}", name));
+    }
+    if RESERVED_FIELDS.contains(&name.to_ascii_lowercase().as_str()) {
+        return Err(format!("{} is not a custom field", name));
+    }
+    Ok(())
+}
+
+/// A YAML double-quoted scalar; JSON string escapes are valid YAML
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+/// YAML frontmatter, delimiters included, with the title, authors and
+/// descriptive metadata of a document
+///
+/// `None` when the document has no abstract, keywords or custom fields, so
+/// exports of documents that never set any are unchanged.
+pub fn metadata_frontmatter(meta: &DocumentMeta) -> Option<String> {
+    if meta.abstract_text.is_none() && meta.keywords.is_empty() && meta.custom_fields.is_empty() {
+        return None;
+    }
+
+    let mut yaml = String::from("---\n");
+    if !meta.title.is_empty() && meta.title != DocumentMeta::default().title {
+        yaml.push_str(&format!("title: {}\n", yaml_string(&meta.title)));
+    }
+    if !meta.authors.is_empty() {
+        yaml.push_str("author:\n");
+        for author in &meta.authors {
+            yaml.push_str(&format!("  - {}\n", yaml_string(&author.name)));
+        }
+    }
+    if let Some(text) = &meta.abstract_text {
+        // pandoc shows the abstract; the description goes into the DOCX
+        // properties and the HTML meta tags
+        yaml.push_str(&format!("abstract: {}\n", yaml_string(text)));
+        yaml.push_str(&format!("description: {}\n", yaml_string(text)));
+    }
+    if !meta.keywords.is_empty() {
+        let keywords: Vec<String> = meta.keywords.iter().map(|k| yaml_string(k)).collect();
+        yaml.push_str(&format!("keywords: [{}]\n", keywords.join(", ")));
+    }
+    for (name, value) in &meta.custom_fields {
+        yaml.push_str(&format!("{}: {}\n", name, yaml_string(value)));
+    }
+    yaml.push_str("---\n\n");
+    Some(yaml)
+}
+
+/// Put the metadata of a document in front of exported markdown
+///
+/// Content that starts with frontmatter of its own is left as it is.
+pub fn with_metadata_frontmatter(content: String, meta: Option<&DocumentMeta>) -> String {
+    match meta.and_then(metadata_frontmatter) {
+        Some(frontmatter) if !content.trim_start().starts_with("---") => frontmatter + &content,
+        _ => content,
+    }
+}
+
+/// Metadata fields whose value contains a query, as (field, value) pairs
+pub fn metadata_matches(meta: &DocumentMeta, query: &str, case_sensitive: bool) -> Vec<(String, String)> {
+    let contains = |value: &str| {
+        if case_sensitive {
+            value.contains(query)
+        } else {
+            value.to_lowercase().contains(&query.to_lowercase())
+        }
+    };
+    let mut matches = Vec::new();
+    if let Some(text) = meta.abstract_text.as_deref().filter(|t| contains(t)) {
+        matches.push(("abstract".to_string(), text.to_string()));
+    }
+    matches.extend(
+        meta.keywords
+            .iter()
+            .filter(|k| contains(k))
+            .map(|k| ("keywords".to_string(), k.clone())),
+    );
+    matches.extend(
+        meta.custom_fields
+            .iter()
+            .filter(|(name, value)| contains(name) || contains(value))
+            .map(|(name, value)| (name.clone(), value.clone())),
+    );
+    matches
+}
+
+/// Get the title, abstract, keywords and custom fields of a document
+#[tauri::command]
+pub fn get_document_metadata(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+) -> Result<DocumentMetadata, KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager
+        .documents
+        .get(&doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+    Ok(DocumentMetadata::of(&doc.meta))
+}
+
+/// Set the abstract, keywords and custom fields of a document
+///
+/// An empty abstract clears it; custom fields replace the existing ones.
+#[tauri::command]
+pub fn set_document_metadata(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    abstract_text: Option<String>,
+    keywords: Vec<String>,
+    custom_fields: BTreeMap<String, String>,
+) -> Result<DocumentMetadata, KorppiError> {
+    let abstract_text = abstract_text.map(|t| t.trim().to_string()).filter(|t| !t.is_empty());
+    let keywords = normalize_keywords(keywords);
+    let mut fields = BTreeMap::new();
+    for (name, value) in custom_fields {
+        let name = name.trim().to_string();
+        validate_field_name(&name).map_err(KorppiError::InvalidInput)?;
+        fields.insert(name, value.trim().to_string());
+    }
+
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager
+        .documents
+        .get_mut(&doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+    let meta = &mut doc.meta;
+    if meta.abstract_text != abstract_text || meta.keywords != keywords || meta.custom_fields != fields {
+        meta.abstract_text = abstract_text;
+        meta.keywords = keywords;
+        meta.custom_fields = fields;
+        doc.handle.is_modified = true;
+    }
+    Ok(DocumentMetadata::of(&doc.meta))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kmd::AuthorRef;
+
+    #[test]
+    fn test_metadata_frontmatter() {
+        let mut meta = DocumentMeta::default();
+        assert_eq!(with_metadata_frontmatter("Text".to_string(), Some(&meta)), "Text");
+
+        meta.title = "On \"Ravens\"".to_string();
+        meta.authors.push(AuthorRef {
+            id: "a1".to_string(),
+            name: "Alice".to_string(),
+            email: None,
+            joined_at: None,
+            role: None,
+        });
+        meta.abstract_text = Some("Ravens: clever.\nVery.".to_string());
+        meta.keywords = normalize_keywords(vec![" corvids ".to_string(), "Corvids".to_string(), "".to_string(), "birds".to_string()]);
+        meta.custom_fields.insert("journal".to_string(), "Animal Cognition".to_string());
+
+        let exported = with_metadata_frontmatter("# Ravens\n".to_string(), Some(&meta));
+        assert_eq!(
+            exported,
+            "---\ntitle: \"On \\\"Ravens\\\"\"\nauthor:\n  - \"Alice\"\n\
+             abstract: \"Ravens: clever.\\nVery.\"\ndescription: \"Ravens: clever.\\nVery.\"\n\
+             keywords: [\"corvids\", \"birds\"]\njournal: \"Animal Cognition\"\n---\n\n# Ravens\n"
+        );
+        // Frontmatter of the document itself wins
+        let own = "---\ntitle: Mine\n---\n\nText";
+        assert_eq!(with_metadata_frontmatter(own.to_string(), Some(&meta)), own);
+
+        let fields: Vec<String> = metadata_matches(&meta, "COGNITION", false).into_iter().map(|(f, _)| f).collect();
+        assert_eq!(fields, vec!["journal"]);
+        assert_eq!(metadata_matches(&meta, "bird", false), vec![("keywords".to_string(), "birds".to_string())]);
+        assert!(metadata_matches(&meta, "Bird", true).is_empty());
+
+        assert!(validate_field_name("journal_target").is_ok());
+        assert!(validate_field_name("Keywords").is_err());
+        assert!(validate_field_name("x: y").is_err());
+        assert!(validate_field_name("1st").is_err());
+    }
+}
@@ -0,0 +1,182 @@
+// src-tauri/src/document_size.rs
+//! What a document's size is made of, and soft limits on it.
+//!
+//! A KMD file grows with its history until it is too large to send by mail.
+//! `get_document_size_breakdown` reports the bytes taken by the Yjs state,
+//! the snapshots, the patches, the comments and the assets, and compares
+//! them with the `size_limits` of the app settings. Saving a document that
+//! is over a limit emits `DOCUMENT_SIZE_WARNING` so the user can compact the
+//! history before the file becomes unwieldy.
+//!
+//! Sizes are uncompressed; the KMD file itself is zipped and usually smaller.
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::document_manager::{dir_size, document_assets_dir, DocumentManager, DocumentState};
+use crate::error::KorppiError;
+use crate::settings::{load_app_settings, SizeLimits};
+
+const MB: u64 = 1024 * 1024;
+
+/// A part of a document over its soft limit
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SizeWarning {
+    /// "total", "history" or "assets"
+    pub part: String,
+    pub size: u64,
+    pub limit: u64,
+}
+
+/// Bytes taken by each part of a document
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct DocumentSizeBreakdown {
+    pub doc_id: String,
+    /// Yjs state and the updates on top of it
+    pub yjs_state: u64,
+    pub snapshots: u64,
+    /// Patch payloads
+    pub patches: u64,
+    pub comments: u64,
+    pub assets: u64,
+    /// The history database as stored, with indexes and free pages
+    pub history_file: u64,
+    /// Yjs state, history database and assets together
+    pub total: u64,
+    /// Size of the saved KMD file, if there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_size: Option<u64>,
+    #[serde(default)]
+    pub warnings: Vec<SizeWarning>,
+    /// The history or the whole document is over its limit and compacting
+    /// the history would help
+    #[serde(default)]
+    pub suggest_compaction: bool,
+}
+
+/// Sum of an expression over a table, 0 when the table does not exist yet
+fn sum_bytes(conn: &Connection, table: &str, expr: &str) -> Result<u64, String> {
+    let exists: Option<i64> = conn
+        .query_row("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1", [table], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if exists.is_none() {
+        return Ok(0);
+    }
+    let sum: i64 = conn
+        .query_row(&format!("SELECT COALESCE(SUM({}), 0) FROM {}", expr, table), [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    Ok(sum as u64)
+}
+
+/// Bytes of snapshots, patches and comments in a history database
+pub fn history_breakdown(conn: &Connection) -> Result<(u64, u64, u64), String> {
+    let snapshots = sum_bytes(conn, "snapshots", "length(state)")?;
+    let patches = sum_bytes(conn, "patches", "length(data)")?;
+    let comments = sum_bytes(conn, "comments", "length(content) + length(selected_text)")?;
+    Ok((snapshots, patches, comments))
+}
+
+/// Add a warning for every part over its limit
+pub fn check_limits(breakdown: &mut DocumentSizeBreakdown, limits: &SizeLimits) {
+    let parts = [
+        ("total", breakdown.total, limits.total_mb),
+        ("history", breakdown.history_file, limits.history_mb),
+        ("assets", breakdown.assets, limits.assets_mb),
+    ];
+    breakdown.warnings = parts
+        .into_iter()
+        .filter(|&(_, size, limit_mb)| limit_mb > 0 && size > u64::from(limit_mb) * MB)
+        .map(|(part, size, limit_mb)| SizeWarning {
+            part: part.to_string(),
+            size,
+            limit: u64::from(limit_mb) * MB,
+        })
+        .collect();
+    breakdown.suggest_compaction = breakdown.warnings.iter().any(|w| w.part != "assets");
+}
+
+/// Size breakdown of an open document, checked against the limits
+pub fn document_size_breakdown(doc: &DocumentState, limits: &SizeLimits) -> Result<DocumentSizeBreakdown, String> {
+    let yjs_state = doc.yjs_state.len() as u64 + doc.yjs_updates.iter().map(|u| u.data.len() as u64).sum::<u64>();
+    let (snapshots, patches, comments, history_file) = if doc.history_path.exists() {
+        let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+        let (snapshots, patches, comments) = history_breakdown(&conn)?;
+        let history_file = fs::metadata(&doc.history_path).map_err(|e| e.to_string())?.len();
+        (snapshots, patches, comments, history_file)
+    } else {
+        (0, 0, 0, 0)
+    };
+    let assets = dir_size(&document_assets_dir(&doc.history_path));
+
+    let mut breakdown = DocumentSizeBreakdown {
+        doc_id: doc.handle.id.clone(),
+        yjs_state,
+        snapshots,
+        patches,
+        comments,
+        assets,
+        history_file,
+        total: yjs_state + history_file + assets,
+        file_size: doc.handle.path.as_ref().and_then(|p| fs::metadata(p).ok()).map(|m| m.len()),
+        warnings: Vec::new(),
+        suggest_compaction: false,
+    };
+    check_limits(&mut breakdown, limits);
+    Ok(breakdown)
+}
+
+/// Report what a document's size is made of and which limits it is over
+#[tauri::command]
+pub fn get_document_size_breakdown(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+) -> Result<DocumentSizeBreakdown, KorppiError> {
+    let limits = load_app_settings().map(|s| s.size_limits).unwrap_or_default();
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager
+        .documents
+        .get(&doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+    Ok(document_size_breakdown(doc, &limits)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db_utils::ensure_schema;
+    use rusqlite::params;
+
+    #[test]
+    fn test_history_breakdown_and_limits() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert_eq!(history_breakdown(&conn).unwrap(), (0, 0, 0));
+
+        ensure_schema(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO patches (timestamp, author, kind, data, uuid) VALUES (1, 'a', 'Save', ?1, 'p1')",
+            params![r#"{"snapshot":"abc"}"#],
+        )
+        .unwrap();
+        conn.execute("INSERT INTO snapshots (timestamp, patch_id, state) VALUES (1, 1, x'00112233')", []).unwrap();
+        assert_eq!(history_breakdown(&conn).unwrap(), (4, 18, 0));
+
+        let mut breakdown = DocumentSizeBreakdown {
+            history_file: 3 * MB,
+            assets: 6 * MB,
+            total: 9 * MB,
+            ..Default::default()
+        };
+        check_limits(&mut breakdown, &SizeLimits { total_mb: 10, history_mb: 2, assets_mb: 0 });
+        assert_eq!(breakdown.warnings, vec![SizeWarning { part: "history".to_string(), size: 3 * MB, limit: 2 * MB }]);
+        assert!(breakdown.suggest_compaction);
+
+        check_limits(&mut breakdown, &SizeLimits { total_mb: 10, history_mb: 0, assets_mb: 5 });
+        assert_eq!(breakdown.warnings.len(), 1);
+        assert_eq!(breakdown.warnings[0].part, "assets");
+        assert!(!breakdown.suggest_compaction);
+    }
+}
@@ -0,0 +1,231 @@
+// src-tauri/src/docx_styles.rs
+//! Heading style names and line numbers of DOCX exports.
+//!
+//! Journals often ask for their own style names, "Title 1" rather than
+//! Word's "Heading 1", and for line numbers in submitted manuscripts. Both
+//! are applied once a DOCX is written, by pandoc or by the built-in writer.
+//! The headings of each mapped level are moved to the named style; a style
+//! the file does not define, for instance through the reference document,
+//! is added based on the heading style it replaces, so headings look the
+//! same until the journal's template is attached. Line numbers are turned
+//! on in the properties of every section, counting through the whole
+//! document.
+
+use quick_xml::escape::escape;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use crate::error::KorppiError;
+
+const DOCUMENT_XML: &str = "word/document.xml";
+const STYLES_XML: &str = "word/styles.xml";
+
+/// Style ID Word uses for a style name: its letters and digits
+pub fn style_id(name: &str) -> String {
+    name.chars().filter(|c| c.is_alphanumeric()).collect()
+}
+
+pub fn validate_heading_styles(styles: &BTreeMap<u8, String>) -> Result<(), String> {
+    for (level, name) in styles {
+        if !(1..=6).contains(level) {
+            return Err(format!("Heading level must be between 1 and 6: {}", level));
+        }
+        if style_id(name).is_empty() {
+            return Err(format!("Invalid style name for heading {}: {:?}", level, name));
+        }
+    }
+    Ok(())
+}
+
+/// Move heading paragraphs to the style of their level
+fn restyle_headings(document_xml: &str, styles: &BTreeMap<u8, String>) -> String {
+    let heading_re = Regex::new(r#"<w:pStyle w:val="Heading([1-6])"\s*/>"#).unwrap();
+    heading_re
+        .replace_all(document_xml, |caps: &regex::Captures| {
+            let level: u8 = caps[1].parse().unwrap_or(0);
+            match styles.get(&level) {
+                Some(name) => format!(r#"<w:pStyle w:val="{}"/>"#, style_id(name)),
+                None => caps[0].to_string(),
+            }
+        })
+        .to_string()
+}
+
+/// Define the styles the file does not have yet
+fn add_missing_styles(styles_xml: &str, styles: &BTreeMap<u8, String>) -> String {
+    let mut definitions = String::new();
+    for (level, name) in styles {
+        let id = style_id(name);
+        if styles_xml.contains(&format!(r#"w:styleId="{}""#, id)) {
+            continue;
+        }
+        definitions.push_str(&format!(
+            r#"<w:style w:type="paragraph" w:customStyle="1" w:styleId="{}"><w:name w:val="{}"/><w:basedOn w:val="Heading{}"/><w:qFormat/><w:pPr><w:outlineLvl w:val="{}"/></w:pPr></w:style>"#,
+            id,
+            escape(name.as_str()),
+            level,
+            level - 1
+        ));
+    }
+    match styles_xml.rfind("</w:styles>") {
+        Some(end) if !definitions.is_empty() => format!("{}{}{}", &styles_xml[..end], definitions, &styles_xml[end..]),
+        _ => styles_xml.to_string(),
+    }
+}
+
+/// Elements that follow `w:lnNumType` in section properties
+const AFTER_LINE_NUMBERS: [&str; 12] = [
+    "w:pgNumType", "w:cols", "w:formProt", "w:vAlign", "w:noEndnote", "w:titlePg",
+    "w:textDirection", "w:bidi", "w:rtlGutter", "w:docGrid", "w:printerSettings", "w:sectPrChange",
+];
+
+const LINE_NUMBERS: &str = r#"<w:lnNumType w:countBy="1" w:restart="continuous"/>"#;
+
+/// Turn on continuous line numbers in every section of a document
+fn add_line_numbers(document_xml: &str) -> String {
+    let section_re = Regex::new(r"(?s)<w:sectPr(?:\s[^>]*)?(?:/>|>.*?</w:sectPr>)").unwrap();
+    if !section_re.is_match(document_xml) {
+        return document_xml.replacen("</w:body>", &format!("<w:sectPr>{}</w:sectPr></w:body>", LINE_NUMBERS), 1);
+    }
+    section_re
+        .replace_all(document_xml, |caps: &regex::Captures| {
+            let section = &caps[0];
+            if section.contains("<w:lnNumType") {
+                return section.to_string();
+            }
+            if let Some(open) = section.strip_suffix("/>") {
+                return format!("{}>{}</w:sectPr>", open.trim_end(), LINE_NUMBERS);
+            }
+            // The schema orders the properties; line numbers go before the
+            // first element that comes after them
+            let at = AFTER_LINE_NUMBERS
+                .iter()
+                .filter_map(|element| section.find(&format!("<{}", element)))
+                .min()
+                .unwrap_or(section.len() - "</w:sectPr>".len());
+            format!("{}{}{}", &section[..at], LINE_NUMBERS, &section[at..])
+        })
+        .to_string()
+}
+
+/// Write a copy of a DOCX file's entries to `part`, with the XML parts
+/// `edit` returns new text for
+fn write_edited(part: &Path, entries: &[(String, Vec<u8>)], edit: &dyn Fn(&str, &str) -> Option<String>) -> Result<(), KorppiError> {
+    let mut zip = ZipWriter::new(File::create(part)?);
+    for (name, data) in entries {
+        let edited = name
+            .ends_with(".xml")
+            .then(|| edit(name, &String::from_utf8_lossy(data)))
+            .flatten();
+        zip.start_file(name.as_str(), FileOptions::default())?;
+        zip.write_all(edited.as_ref().map_or(data.as_slice(), |xml| xml.as_bytes()))?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// Rewrite XML parts of a DOCX file in place
+fn edit_docx(path: &Path, edit: &dyn Fn(&str, &str) -> Option<String>) -> Result<(), KorppiError> {
+    let file = File::open(path)?;
+    let mut archive = ZipArchive::new(file)?;
+    let mut entries: Vec<(String, Vec<u8>)> = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        entries.push((entry.name().to_string(), data));
+    }
+
+    // Write next to the file and replace it, so a failure leaves the export as it was
+    let part = path.with_extension("docx.part");
+    let written = write_edited(&part, &entries, edit).and_then(|_| Ok(fs::rename(&part, path)?));
+    written.inspect_err(|_| {
+        let _ = fs::remove_file(&part);
+    })
+}
+
+/// Give the headings of a written DOCX file the mapped style names
+pub fn apply_heading_styles(path: &Path, styles: &BTreeMap<u8, String>) -> Result<(), KorppiError> {
+    validate_heading_styles(styles)?;
+    if styles.is_empty() {
+        return Ok(());
+    }
+    edit_docx(path, &|name, xml| match name {
+        DOCUMENT_XML => Some(restyle_headings(xml, styles)),
+        STYLES_XML => Some(add_missing_styles(xml, styles)),
+        _ => None,
+    })
+}
+
+/// Number the lines of a written DOCX file
+pub fn apply_line_numbers(path: &Path) -> Result<(), KorppiError> {
+    edit_docx(path, &|name, xml| (name == DOCUMENT_XML).then(|| add_line_numbers(xml)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_heading_styles() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("paper.docx");
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        let files = [
+            (DOCUMENT_XML, r#"<w:p><w:pPr><w:pStyle w:val="Heading1" /></w:pPr></w:p><w:p><w:pPr><w:pStyle w:val="Heading2"/></w:pPr></w:p><w:p><w:pPr><w:pStyle w:val="Heading3"/></w:pPr></w:p>"#),
+            (STYLES_XML, r#"<w:styles><w:style w:styleId="Heading1"/><w:style w:styleId="Section"/></w:styles>"#),
+            ("word/media/image1.png", "png"),
+        ];
+        for (name, content) in files {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let styles = BTreeMap::from([(1, "Title 1".to_string()), (2, "Section".to_string())]);
+        apply_heading_styles(&path, &styles).unwrap();
+
+        let mut archive = ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let read = |archive: &mut ZipArchive<File>, name: &str| {
+            let mut content = String::new();
+            archive.by_name(name).unwrap().read_to_string(&mut content).unwrap();
+            content
+        };
+        let document = read(&mut archive, DOCUMENT_XML);
+        assert!(document.contains(r#"<w:pStyle w:val="Title1"/>"#));
+        assert!(document.contains(r#"<w:pStyle w:val="Section"/>"#));
+        assert!(document.contains(r#"<w:pStyle w:val="Heading3"/>"#));
+        let defined = read(&mut archive, STYLES_XML);
+        assert!(defined.contains(r#"w:styleId="Title1"><w:name w:val="Title 1"/><w:basedOn w:val="Heading1"/>"#));
+        // The reference document already defines "Section"
+        assert_eq!(defined.matches(r#"w:styleId="Section""#).count(), 1);
+        assert_eq!(read(&mut archive, "word/media/image1.png"), "png");
+
+        assert!(validate_heading_styles(&BTreeMap::from([(7, "Deep".to_string())])).is_err());
+        assert!(validate_heading_styles(&BTreeMap::from([(1, " - ".to_string())])).is_err());
+    }
+
+    #[test]
+    fn test_line_numbers() {
+        let numbered = format!("<w:sectPr>{}</w:sectPr>", LINE_NUMBERS);
+        assert_eq!(add_line_numbers("<w:body><w:p/><w:sectPr/></w:body>"), format!("<w:body><w:p/>{}</w:body>", numbered));
+        assert_eq!(add_line_numbers("<w:body><w:p/></w:body>"), format!("<w:body><w:p/>{}</w:body>", numbered));
+
+        let section = r#"<w:sectPr w:rsidR="1"><w:pgSz w:w="11906"/><w:pgMar w:top="1440"/><w:cols w:space="720"/><w:docGrid w:linePitch="360"/></w:sectPr>"#;
+        assert_eq!(
+            add_line_numbers(section),
+            format!(r#"<w:sectPr w:rsidR="1"><w:pgSz w:w="11906"/><w:pgMar w:top="1440"/>{}<w:cols w:space="720"/><w:docGrid w:linePitch="360"/></w:sectPr>"#, LINE_NUMBERS)
+        );
+        // Sections of the reference document that number lines already keep their settings
+        let own = r#"<w:sectPr><w:lnNumType w:countBy="5"/></w:sectPr>"#;
+        assert_eq!(add_line_numbers(own), own);
+        // Every section is numbered
+        let two = "<w:p><w:pPr><w:sectPr><w:pgSz/></w:sectPr></w:pPr></w:p><w:sectPr><w:pgSz/></w:sectPr>";
+        assert_eq!(add_line_numbers(two).matches("<w:lnNumType").count(), 2);
+    }
+}
@@ -0,0 +1,411 @@
+// src-tauri/src/doi.rs
+//! Reference metadata from Crossref by DOI.
+//!
+//! `fetch_doi_metadata` asks the Crossref REST API for a work and returns a
+//! formatted citation and a BibTeX entry built from it. Answers are cached
+//! in the app data directory, so a DOI looked up once also works offline,
+//! and requests are spaced out to stay within Crossref's rate limits.
+//! `insert_doi_citation` adds the entry to the document's bibliography like
+//! the Zotero integration does.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+
+use crate::document_manager::{document_assets_dir, DocumentManager};
+use crate::error::KorppiError;
+use crate::zotero::{append_entries, bibtex_keys, citation_text, Citation, BIBLIOGRAPHY_FILE};
+
+const CROSSREF_WORKS_URL: &str = "https://api.crossref.org/works/";
+
+/// Identifies Korppi to Crossref, as their etiquette asks
+const USER_AGENT: &str = concat!("Korppi/", env!("CARGO_PKG_VERSION"), " (https://github.com/b-rodrigues/korppi)");
+
+/// Minimum time between two Crossref requests
+const MIN_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time of the last Crossref request
+static LAST_REQUEST: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Metadata of a work, as returned by fetch_doi_metadata
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DoiMetadata {
+    /// Normalized DOI, e.g. "10.1000/xyz123"
+    pub doi: String,
+    pub title: String,
+    /// "Family, Given" of each author
+    pub authors: Vec<String>,
+    /// Journal, book or proceedings the work appeared in
+    pub container_title: Option<String>,
+    pub year: Option<String>,
+    /// Crossref work type, e.g. "journal-article"
+    pub work_type: String,
+    /// Suggested citekey, e.g. "doe2020"
+    pub citekey: String,
+    /// Reference formatted for display
+    pub citation: String,
+    pub bibtex: String,
+    /// True when answered from the offline cache
+    #[serde(default)]
+    pub cached: bool,
+}
+
+/// Normalize a DOI given bare, as `doi:` or as a doi.org URL
+fn normalize_doi(input: &str) -> Result<String, KorppiError> {
+    let trimmed = input.trim();
+    let lower = trimmed.to_lowercase();
+    let start = ["https://doi.org/", "http://doi.org/", "https://dx.doi.org/", "http://dx.doi.org/", "doi:"]
+        .iter()
+        .find(|prefix| lower.starts_with(*prefix))
+        .map(|prefix| prefix.len())
+        .unwrap_or(0);
+    // DOIs are case-insensitive; Crossref reports them in lower case
+    let doi = lower[start..].trim().to_string();
+
+    if Regex::new(r"^10\.\d{4,9}/\S+$").unwrap().is_match(&doi) {
+        Ok(doi)
+    } else {
+        Err(KorppiError::InvalidInput(format!("Not a DOI: {}", trimmed)))
+    }
+}
+
+/// Cache file of a DOI
+fn cache_path(app: &AppHandle, doi: &str) -> Result<PathBuf, String> {
+    let mut hasher = Sha256::new();
+    hasher.update(doi.as_bytes());
+    app.path()
+        .app_data_dir()
+        .map(|p| p.join("doi-cache").join(format!("{:x}.json", hasher.finalize())))
+        .map_err(|e| format!("Failed to get app data dir: {}", e))
+}
+
+/// Wait until another Crossref request is allowed
+fn wait_for_rate_limit() {
+    let mut last = LAST_REQUEST.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(previous) = *last {
+        let elapsed = previous.elapsed();
+        if elapsed < MIN_REQUEST_INTERVAL {
+            thread::sleep(MIN_REQUEST_INTERVAL - elapsed);
+        }
+    }
+    *last = Some(Instant::now());
+}
+
+/// Fetch the Crossref record of a DOI
+fn fetch_work(doi: &str) -> Result<Value, KorppiError> {
+    wait_for_rate_limit();
+    let response = match ureq::get(&format!("{}{}", CROSSREF_WORKS_URL, doi))
+        .set("User-Agent", USER_AGENT)
+        .timeout(Duration::from_secs(15))
+        .call()
+    {
+        Ok(response) => response,
+        Err(ureq::Error::Status(404, _)) => {
+            return Err(KorppiError::InvalidInput(format!("DOI not found: {}", doi)))
+        }
+        Err(ureq::Error::Status(429, _)) => {
+            return Err("Crossref is rate limiting requests; try again in a minute".into())
+        }
+        Err(e) => return Err(format!("Could not reach Crossref: {}", e).into()),
+    };
+
+    let body: Value = response.into_json()?;
+    body.get("message")
+        .cloned()
+        .ok_or_else(|| "Unexpected answer from Crossref".into())
+}
+
+fn first_string(work: &Value, key: &str) -> Option<String> {
+    let value = work.get(key)?;
+    let text = value.as_str().or_else(|| value.get(0).and_then(Value::as_str))?;
+    // Titles may carry JATS or HTML markup
+    let text = Regex::new(r"<[^>]+>").unwrap().replace_all(text, "");
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+/// ASCII letters and digits of a name, lower-cased, for citekeys
+fn key_part(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// BibTeX entry type and the field naming the container
+fn bibtex_type(work_type: &str) -> (&'static str, &'static str) {
+    match work_type {
+        "journal-article" => ("article", "journal"),
+        "book" | "monograph" | "edited-book" => ("book", "series"),
+        "book-chapter" | "book-section" | "book-part" => ("incollection", "booktitle"),
+        "proceedings-article" => ("inproceedings", "booktitle"),
+        "dissertation" => ("phdthesis", "school"),
+        "report" => ("techreport", "institution"),
+        _ => ("misc", "howpublished"),
+    }
+}
+
+/// Build the metadata of a Crossref work
+fn metadata_from_work(work: &Value, doi: &str) -> DoiMetadata {
+    let title = first_string(work, "title").unwrap_or_else(|| "Untitled".to_string());
+    let container_title = first_string(work, "container-title");
+    let work_type = work.get("type").and_then(Value::as_str).unwrap_or("other").to_string();
+    let year = ["issued", "published", "published-print", "published-online"].iter().find_map(|key| {
+        work.get(*key)
+            .and_then(|d| d.pointer("/date-parts/0/0"))
+            .and_then(Value::as_i64)
+            .map(|y| y.to_string())
+    });
+
+    let people: Vec<(String, Option<String>)> = work
+        .get("author")
+        .or_else(|| work.get("editor"))
+        .and_then(Value::as_array)
+        .map(|people| {
+            people
+                .iter()
+                .filter_map(|p| {
+                    let family = p.get("family").or_else(|| p.get("name")).and_then(Value::as_str)?;
+                    Some((family.to_string(), p.get("given").and_then(Value::as_str).map(str::to_string)))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let authors: Vec<String> = people
+        .iter()
+        .map(|(family, given)| match given {
+            Some(given) => format!("{}, {}", family, given),
+            None => family.clone(),
+        })
+        .collect();
+
+    let mut citekey = people.first().map(|(family, _)| key_part(family)).unwrap_or_default();
+    if citekey.is_empty() {
+        citekey = format!("doi{}", key_part(doi.split('/').next_back().unwrap_or(doi)));
+    }
+    if let Some(year) = &year {
+        citekey.push_str(year);
+    }
+
+    let volume = work.get("volume").and_then(Value::as_str);
+    let issue = work.get("issue").and_then(Value::as_str);
+    let page = work.get("page").and_then(Value::as_str);
+    let publisher = work.get("publisher").and_then(Value::as_str);
+
+    // Author-date citation: Doe, J., & Roe, R. (2020). Title. Journal, 12(3), 45–67. https://doi.org/...
+    let initials: Vec<String> = people
+        .iter()
+        .map(|(family, given)| match given {
+            Some(given) => {
+                let initials: Vec<String> = given
+                    .split([' ', '-'])
+                    .filter_map(|part| part.chars().next())
+                    .map(|c| format!("{}.", c))
+                    .collect();
+                format!("{}, {}", family, initials.join(" "))
+            }
+            None => family.clone(),
+        })
+        .collect();
+    let mut citation = match initials.len() {
+        0 => String::new(),
+        1 => initials[0].clone(),
+        n => format!("{}, & {}", initials[..n - 1].join(", "), initials[n - 1]),
+    };
+    if !citation.is_empty() {
+        citation.push(' ');
+    }
+    citation.push_str(&format!("({}). {}.", year.as_deref().unwrap_or("n.d."), title.trim_end_matches('.')));
+    if let Some(container) = &container_title {
+        citation.push_str(&format!(" {}", container));
+        if let Some(volume) = volume {
+            citation.push_str(&format!(", {}", volume));
+            if let Some(issue) = issue {
+                citation.push_str(&format!("({})", issue));
+            }
+        }
+        if let Some(page) = page {
+            citation.push_str(&format!(", {}", page.replace('-', "–")));
+        }
+        citation.push('.');
+    } else if let Some(publisher) = publisher {
+        citation.push_str(&format!(" {}.", publisher));
+    }
+    citation.push_str(&format!(" https://doi.org/{}", doi));
+
+    let (entry_type, container_field) = bibtex_type(&work_type);
+    let mut fields: Vec<(&str, String)> = vec![("title", format!("{{{}}}", title))];
+    if !authors.is_empty() {
+        fields.push(("author", authors.join(" and ")));
+    }
+    if let Some(container) = &container_title {
+        fields.push((container_field, container.clone()));
+    }
+    if let Some(year) = &year {
+        fields.push(("year", year.clone()));
+    }
+    for (field, value) in [("volume", volume), ("number", issue), ("pages", page), ("publisher", publisher)] {
+        if let Some(value) = value {
+            let value = if field == "pages" { value.replace('-', "--") } else { value.to_string() };
+            fields.push((field, value));
+        }
+    }
+    fields.push(("doi", doi.to_string()));
+    fields.push(("url", format!("https://doi.org/{}", doi)));
+
+    let mut bibtex = format!("@{}{{{},\n", entry_type, citekey);
+    for (field, value) in fields {
+        bibtex.push_str(&format!("  {} = {{{}}},\n", field, value));
+    }
+    bibtex.push_str("}\n");
+
+    DoiMetadata {
+        doi: doi.to_string(),
+        title,
+        authors,
+        container_title,
+        year,
+        work_type,
+        citekey,
+        citation,
+        bibtex,
+        cached: false,
+    }
+}
+
+/// Look up a DOI, from the cache when it was looked up before
+fn lookup(app: &AppHandle, doi: &str) -> Result<DoiMetadata, KorppiError> {
+    let doi = normalize_doi(doi)?;
+    let cache = cache_path(app, &doi)?;
+    if let Some(mut metadata) = fs::read_to_string(&cache)
+        .ok()
+        .and_then(|json| serde_json::from_str::<DoiMetadata>(&json).ok())
+    {
+        metadata.cached = true;
+        return Ok(metadata);
+    }
+
+    let metadata = metadata_from_work(&fetch_work(&doi)?, &doi);
+    if let Some(parent) = cache.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&cache, serde_json::to_string_pretty(&metadata)?)?;
+    Ok(metadata)
+}
+
+/// Fetch the citation and BibTeX of a DOI from Crossref
+#[tauri::command]
+pub fn fetch_doi_metadata(app: AppHandle, doi: String) -> Result<DoiMetadata, KorppiError> {
+    lookup(&app, &doi)
+}
+
+/// Citekey for a work that does not clash with another entry of the
+/// bibliography: "doe2020", else "doe2020a", "doe2020b"...
+fn unique_citekey(bibliography: &str, metadata: &DoiMetadata) -> String {
+    let keys = bibtex_keys(bibliography);
+    let doi_re = Regex::new(r"(?i)doi\s*=\s*\{([^}]*)\}").unwrap();
+    let entry_doi = |key: &str| -> Option<String> {
+        let start = bibliography.find(&format!("{{{},", key))?;
+        let end = bibliography[start..].find("\n@").map(|e| start + e).unwrap_or(bibliography.len());
+        doi_re.captures(&bibliography[start..end]).map(|c| c[1].trim().to_lowercase())
+    };
+
+    std::iter::once(String::new())
+        .chain(('a'..='z').map(String::from))
+        .map(|suffix| format!("{}{}", metadata.citekey, suffix))
+        .find(|key| !keys.contains(key) || entry_doi(key).as_deref() == Some(metadata.doi.as_str()))
+        .unwrap_or_else(|| format!("{}-{}", metadata.citekey, key_part(&metadata.doi)))
+}
+
+/// Add a work to a document's bibliography by DOI and return the citation
+/// to insert
+#[tauri::command]
+pub fn insert_doi_citation(
+    app: AppHandle,
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    doi: String,
+) -> Result<Citation, KorppiError> {
+    let history_path = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.clone()))?;
+        doc.history_path.clone()
+    };
+    let metadata = lookup(&app, &doi)?;
+
+    let bib_path = document_assets_dir(&history_path).join(BIBLIOGRAPHY_FILE);
+    let citekey = unique_citekey(&fs::read_to_string(&bib_path).unwrap_or_default(), &metadata);
+    let bibtex = metadata.bibtex.replacen(&format!("{{{},", metadata.citekey), &format!("{{{},", citekey), 1);
+    let added = append_entries(&bib_path, &bibtex)?;
+    if !added.is_empty() {
+        let mut manager = manager.lock().map_err(|e| e.to_string())?;
+        if let Some(doc) = manager.documents.get_mut(&doc_id) {
+            doc.handle.is_modified = true;
+        }
+    }
+
+    Ok(Citation {
+        citation: citation_text(&[citekey]),
+        added,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_normalize_doi() {
+        assert_eq!(normalize_doi("https://doi.org/10.1000/XYZ123").unwrap(), "10.1000/xyz123");
+        assert_eq!(normalize_doi(" doi:10.18637/jss.v059.i10 ").unwrap(), "10.18637/jss.v059.i10");
+        assert!(normalize_doi("not a doi").is_err());
+    }
+
+    #[test]
+    fn test_metadata_from_work() {
+        let work = json!({
+            "type": "journal-article",
+            "title": ["Tidy <i>Data</i>"],
+            "container-title": ["Journal of Statistical Software"],
+            "author": [{"given": "Hadley", "family": "Wickham"}],
+            "issued": {"date-parts": [[2014, 9]]},
+            "volume": "59",
+            "issue": "10",
+            "page": "1-23",
+            "publisher": "Foundation for Open Access Statistic"
+        });
+        let metadata = metadata_from_work(&work, "10.18637/jss.v059.i10");
+
+        assert_eq!(metadata.citekey, "wickham2014");
+        assert_eq!(
+            metadata.citation,
+            "Wickham, H. (2014). Tidy Data. Journal of Statistical Software, 59(10), 1–23. https://doi.org/10.18637/jss.v059.i10"
+        );
+        assert!(metadata.bibtex.starts_with("@article{wickham2014,\n  title = {{Tidy Data}},\n  author = {Wickham, Hadley},"));
+        assert!(metadata.bibtex.contains("  pages = {1--23},\n"));
+        assert_eq!(bibtex_keys(&metadata.bibtex).into_iter().collect::<Vec<_>>(), vec!["wickham2014"]);
+    }
+
+    #[test]
+    fn test_unique_citekey() {
+        let work = json!({"type": "book", "title": ["R Packages"], "author": [{"family": "Wickham"}], "issued": {"date-parts": [[2015]]}});
+        let metadata = metadata_from_work(&work, "10.1000/packages");
+
+        assert_eq!(unique_citekey("", &metadata), "wickham2015");
+        let other = "@book{wickham2015,\n  doi = {10.1000/other},\n}\n";
+        assert_eq!(unique_citekey(other, &metadata), "wickham2015a");
+        let same = "@book{wickham2015,\n  doi = {10.1000/packages},\n}\n";
+        assert_eq!(unique_citekey(same, &metadata), "wickham2015");
+    }
+}
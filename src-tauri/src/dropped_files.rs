@@ -0,0 +1,120 @@
+// src-tauri/src/dropped_files.rs
+//! Routing of files dropped on a window.
+//!
+//! `.kmd` files are opened, text and office documents are imported as new
+//! documents and `.kmd-patch` bundles are only inspected: the frontend
+//! gets the bundle manifest and the open document it belongs to, and asks
+//! before applying it with `apply_patch_bundle`. Every file gets its own
+//! result, so one bad file does not stop the others.
+
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Mutex;
+use tauri::{AppHandle, State, Window};
+
+use crate::document_manager::{import_document, open_document, DocumentHandle, DocumentManager, ImportFormat, ImportResult};
+use crate::error::KorppiError;
+use crate::file_progress::FileJobs;
+use crate::patch_bundle::{read_bundle_manifest, BundleManifest, BUNDLE_EXTENSION};
+
+/// What happened to one dropped file
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DroppedFile {
+    /// A KMD document, now open
+    Opened { path: String, handle: DocumentHandle },
+    /// A Markdown, DOCX, ODT or HTML file, imported as a new document
+    Imported { path: String, import: ImportResult },
+    /// A patch bundle, not applied yet
+    PatchBundle {
+        path: String,
+        manifest: BundleManifest,
+        /// Open document the bundle belongs to, if any
+        target_doc_id: Option<String>,
+    },
+    /// A file of a type Korppi does not read
+    Unsupported { path: String },
+    /// Opening or importing the file failed
+    Failed { path: String, error: KorppiError },
+}
+
+/// How a dropped file is handled, decided by its extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DropAction {
+    Open,
+    Import,
+    InspectBundle,
+    Unsupported,
+}
+
+fn drop_action(path: &Path) -> DropAction {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    if extension == "kmd" {
+        DropAction::Open
+    } else if extension == BUNDLE_EXTENSION {
+        DropAction::InspectBundle
+    } else if ImportFormat::from_extension(&extension).is_some() {
+        DropAction::Import
+    } else {
+        DropAction::Unsupported
+    }
+}
+
+fn inspect_bundle(manager: &Mutex<DocumentManager>, path: &str) -> Result<DroppedFile, KorppiError> {
+    let manifest = read_bundle_manifest(Path::new(path))?;
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let target_doc_id = manager
+        .documents
+        .iter()
+        .find(|(_, doc)| doc.meta.uuid == manifest.document_uuid)
+        .map(|(id, _)| id.clone());
+    Ok(DroppedFile::PatchBundle {
+        path: path.to_string(),
+        manifest,
+        target_doc_id,
+    })
+}
+
+/// Open, import or inspect files dropped on the calling window
+#[tauri::command]
+pub async fn handle_dropped_files(
+    app: AppHandle,
+    window: Window,
+    manager: State<'_, Mutex<DocumentManager>>,
+    jobs: State<'_, Mutex<FileJobs>>,
+    paths: Vec<String>,
+) -> Result<Vec<DroppedFile>, KorppiError> {
+    let mut results = Vec::with_capacity(paths.len());
+
+    for path in paths {
+        let outcome = match drop_action(Path::new(&path)) {
+            DropAction::Open => open_document(app.clone(), window.clone(), manager.clone(), jobs.clone(), Some(path.clone()), None, None, None)
+                .await
+                .map(|handle| DroppedFile::Opened { path: path.clone(), handle }),
+            DropAction::Import => import_document(app.clone(), window.clone(), manager.clone(), Some(path.clone()))
+                .await
+                .map(|import| DroppedFile::Imported { path: path.clone(), import }),
+            DropAction::InspectBundle => inspect_bundle(manager.inner(), &path),
+            DropAction::Unsupported => Ok(DroppedFile::Unsupported { path: path.clone() }),
+        };
+        results.push(outcome.unwrap_or_else(|error| DroppedFile::Failed { path, error }));
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drop_action() {
+        assert_eq!(drop_action(Path::new("/a/paper.kmd")), DropAction::Open);
+        assert_eq!(drop_action(Path::new("/a/changes.kmd-patch")), DropAction::InspectBundle);
+        assert_eq!(drop_action(Path::new("/a/notes.MD")), DropAction::Import);
+        assert_eq!(drop_action(Path::new("/a/draft.docx")), DropAction::Import);
+        assert_eq!(drop_action(Path::new("/a/export.html")), DropAction::Import);
+        assert_eq!(drop_action(Path::new("/a/photo.png")), DropAction::Unsupported);
+        assert_eq!(drop_action(Path::new("/a/README")), DropAction::Unsupported);
+    }
+}
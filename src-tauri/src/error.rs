@@ -0,0 +1,152 @@
+// src-tauri/src/error.rs
+//! Structured error type returned by commands.
+//!
+//! Commands used to fail with plain strings, so the frontend could not tell
+//! "document not found" from "disk full". `KorppiError` serializes as
+//! `{ code, message, hint }`: `code` is stable and meant for matching,
+//! `message` is for display and `hint`, when present, suggests a fix.
+//!
+//! Internal helpers still return `Result<_, String>`; both directions
+//! convert with `?`, strings becoming `KorppiError::Other`. Code that reads
+//! and writes files for opening, saving and exporting returns `KorppiError`
+//! instead, so an I/O failure reaches the frontend with its kind.
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::io::ErrorKind;
+
+#[derive(Debug, thiserror::Error)]
+pub enum KorppiError {
+    #[error("Document not found: {0}")]
+    DocumentNotFound(String),
+    #[error("Patch not found: {0}")]
+    PatchNotFound(String),
+    #[error("Comment not found: {0}")]
+    CommentNotFound(i64),
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("{0}")]
+    PandocUnavailable(String),
+    #[error("{0}")]
+    QuartoUnavailable(String),
+    #[error("{0}")]
+    ZoteroUnavailable(String),
+    #[error("{0}")]
+    GrammarUnavailable(String),
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("Invalid data: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid archive: {0}")]
+    Archive(zip::result::ZipError),
+    #[error("{0}")]
+    Other(String),
+}
+
+impl KorppiError {
+    /// Stable identifier of the kind of error
+    pub fn code(&self) -> &'static str {
+        match self {
+            KorppiError::DocumentNotFound(_) => "document_not_found",
+            KorppiError::PatchNotFound(_) => "patch_not_found",
+            KorppiError::CommentNotFound(_) => "comment_not_found",
+            KorppiError::InvalidInput(_) => "invalid_input",
+            KorppiError::PandocUnavailable(_) => "pandoc_unavailable",
+            KorppiError::QuartoUnavailable(_) => "quarto_unavailable",
+            KorppiError::ZoteroUnavailable(_) => "zotero_unavailable",
+            KorppiError::GrammarUnavailable(_) => "grammar_unavailable",
+            KorppiError::Io(e) => match e.kind() {
+                ErrorKind::NotFound => "file_not_found",
+                ErrorKind::PermissionDenied => "permission_denied",
+                ErrorKind::StorageFull => "disk_full",
+                ErrorKind::ReadOnlyFilesystem => "read_only",
+                _ => "io_error",
+            },
+            KorppiError::Database(_) => "database_error",
+            KorppiError::Json(_) => "invalid_data",
+            KorppiError::Archive(_) => "invalid_archive",
+            KorppiError::Other(_) => "error",
+        }
+    }
+
+    /// Suggested remediation for common failures
+    pub fn hint(&self) -> Option<&'static str> {
+        match self.code() {
+            "document_not_found" => Some("The document may have been closed. Reopen it and try again."),
+            "pandoc_unavailable" => Some("Install pandoc (https://pandoc.org/installing.html) and make sure it is on your PATH, then restart Korppi."),
+            "quarto_unavailable" => Some("Install Quarto (https://quarto.org/docs/get-started/) or set its path in the settings, then try again."),
+            "zotero_unavailable" => Some("Start Zotero with the Better BibTeX extension (https://retorque.re/zotero-better-bibtex/) installed, then try again."),
+            "grammar_unavailable" => Some("Start a LanguageTool server (https://dev.languagetool.org/http-server) and set its URL in the settings."),
+            "file_not_found" => Some("Check that the file still exists and has not been moved."),
+            "permission_denied" => Some("Check that you have permission to read and write this location."),
+            "disk_full" => Some("Free up disk space and try again."),
+            "read_only" => Some("Save to a location that is not read-only."),
+            "invalid_archive" => Some("The file is not a valid Korppi document or is damaged."),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for KorppiError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("KorppiError", 3)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("hint", &self.hint())?;
+        state.end()
+    }
+}
+
+/// I/O failures while reading or writing an archive keep their kind
+impl From<zip::result::ZipError> for KorppiError {
+    fn from(error: zip::result::ZipError) -> Self {
+        match error {
+            zip::result::ZipError::Io(e) => KorppiError::Io(e),
+            e => KorppiError::Archive(e),
+        }
+    }
+}
+
+impl From<String> for KorppiError {
+    fn from(message: String) -> Self {
+        KorppiError::Other(message)
+    }
+}
+
+impl From<&str> for KorppiError {
+    fn from(message: &str) -> Self {
+        KorppiError::Other(message.to_string())
+    }
+}
+
+impl From<KorppiError> for String {
+    fn from(error: KorppiError) -> Self {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialized_error() {
+        let json = serde_json::to_value(KorppiError::DocumentNotFound("doc-1".to_string())).unwrap();
+        assert_eq!(json["code"], "document_not_found");
+        assert_eq!(json["message"], "Document not found: doc-1");
+        assert!(json["hint"].is_string());
+
+        let full = KorppiError::from(std::io::Error::from(ErrorKind::StorageFull));
+        assert_eq!(full.code(), "disk_full");
+        let denied = KorppiError::from(zip::result::ZipError::Io(ErrorKind::PermissionDenied.into()));
+        assert_eq!(denied.code(), "permission_denied");
+
+        let other: KorppiError = "Something went wrong".to_string().into();
+        let json = serde_json::to_value(&other).unwrap();
+        assert_eq!(json["code"], "error");
+        assert!(json["hint"].is_null());
+        assert_eq!(String::from(other), "Something went wrong");
+    }
+}
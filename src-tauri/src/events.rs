@@ -0,0 +1,129 @@
+// src-tauri/src/events.rs
+//! Document lifecycle events.
+//!
+//! Commands that open or save a document, record a patch, find conflicts
+//! or add a comment emit a Tauri event with a small payload, so every
+//! window and panel can refresh from the event instead of polling
+//! `get_open_documents` or `get_conflict_count`.
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+/// A document was created, opened or imported; the payload is its
+/// `DocumentHandle`
+pub const DOCUMENT_OPENED: &str = "document-opened";
+
+/// A document was saved to disk; the payload is its updated `DocumentHandle`
+pub const DOCUMENT_SAVED: &str = "document-saved";
+
+/// A saved document is over a soft size limit; the payload is its
+/// `DocumentSizeBreakdown`
+pub const DOCUMENT_SIZE_WARNING: &str = "document-size-warning";
+
+/// A patch was added to a document's history
+pub const PATCH_RECORDED: &str = "patch-recorded";
+
+/// Conflict detection stored new conflicts
+pub const CONFLICT_DETECTED: &str = "conflict-detected";
+
+/// A comment or reply was added
+pub const COMMENT_ADDED: &str = "comment-added";
+
+/// Another launch of the app asked this instance to open a file; sent to
+/// one window only, with the absolute path as payload
+pub const OPEN_FILE_REQUESTED: &str = "open-file-requested";
+
+/// A `korppi://` link was opened from outside the app; sent to one window
+/// only, with the `ResolvedPositionLink` as payload
+pub const POSITION_LINK_OPENED: &str = "position-link-opened";
+
+/// A queued export finished; the payload is its `ExportRecord`
+pub const EXPORT_FINISHED: &str = "export-finished";
+
+/// Progress of opening or saving a KMD file
+pub const FILE_PROGRESS: &str = "file-progress";
+
+/// A live session peer sent a Yjs update, already merged into the document
+pub const LIVE_UPDATE: &str = "live-update";
+
+/// A live session peer sent awareness data (cursor, name)
+pub const LIVE_AWARENESS: &str = "live-awareness";
+
+/// A live session peer disconnected; for a client, the host is gone
+pub const LIVE_PEER_LEFT: &str = "live-peer-left";
+
+/// Payload of `PATCH_RECORDED`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchRecordedEvent {
+    /// `None` for the legacy global patch log
+    pub doc_id: Option<String>,
+    pub patch_id: i64,
+    pub uuid: Option<String>,
+    pub author: String,
+    pub kind: String,
+    pub timestamp: i64,
+}
+
+/// Payload of `CONFLICT_DETECTED`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictDetectedEvent {
+    /// Document whose import produced the conflicts, if known
+    pub doc_id: Option<String>,
+    pub conflict_ids: Vec<String>,
+}
+
+/// Payload of `COMMENT_ADDED`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommentAddedEvent {
+    pub doc_id: String,
+    pub comment_id: i64,
+    /// Set for replies
+    pub parent_id: Option<i64>,
+    pub author: String,
+}
+
+/// Payload of `FILE_PROGRESS`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileProgressEvent {
+    /// Job ID to pass to `cancel_file_job`
+    pub job_id: String,
+    /// "open" or "save"
+    pub operation: String,
+    /// What is being read or written, e.g. "history" or "assets"
+    pub phase: String,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+}
+
+/// Payload of `LIVE_UPDATE`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiveUpdateEvent {
+    pub doc_id: String,
+    pub peer: String,
+    pub update: Vec<u8>,
+}
+
+/// Payload of `LIVE_AWARENESS`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiveAwarenessEvent {
+    pub doc_id: String,
+    pub peer: String,
+    pub data: Vec<u8>,
+}
+
+/// Payload of `LIVE_PEER_LEFT`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LivePeerEvent {
+    pub doc_id: String,
+    pub peer: String,
+}
+
+/// Emit an event to all windows, reporting failures to the log
+///
+/// The change the event reports has already happened, so a failed emit
+/// must not fail the command.
+pub fn emit_event<T: Serialize + Clone>(app: &AppHandle, event: &str, payload: T) {
+    if let Err(e) = app.emit(event, payload) {
+        log::warn!("Failed to emit {}: {}", event, e);
+    }
+}
@@ -0,0 +1,298 @@
+// src-tauri/src/exports.rs
+//! Export jobs and the history of what they produced.
+//!
+//! Exports are queued and run one at a time on a worker thread, so a slow
+//! pandoc run does not block the editor. Every export is recorded in the
+//! document's history database with its format, path and the UUID of the
+//! patch whose text it was made from, so the same file can be found again
+//! and regenerated from the same text.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::thread;
+use tauri::{AppHandle, State};
+
+use crate::cli::{export_content, ExportFormat};
+use crate::db_utils::ensure_schema;
+use crate::document_manager::{snapshot_at_patch, DocumentManager};
+use crate::error::KorppiError;
+use crate::events::{emit_event, EXPORT_FINISHED};
+use crate::hooks::{fire_hooks, HookEvent, HookPayload};
+use crate::kmd::DocumentMeta;
+
+/// One export of a document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportRecord {
+    pub id: i64,
+    pub doc_id: String,
+    /// File extension of the format: "md", "docx", "html" or "pdf"
+    pub format: String,
+    pub path: PathBuf,
+    /// Patch whose text was exported
+    pub patch_uuid: String,
+    pub created_at: i64,
+    /// "queued", "running", "done" or "failed"
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// An export waiting for the worker
+struct ExportJob {
+    record: ExportRecord,
+    history_path: PathBuf,
+    meta: DocumentMeta,
+    /// The .kmd file, for hooks
+    document_path: Option<PathBuf>,
+}
+
+/// Queue of exports, run in order by one worker thread
+#[derive(Default)]
+pub struct ExportQueue {
+    sender: Option<Sender<ExportJob>>,
+}
+
+impl ExportQueue {
+    fn submit(&mut self, app: &AppHandle, job: ExportJob) -> Result<(), String> {
+        let sender = self.sender.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel::<ExportJob>();
+            let app = app.clone();
+            thread::spawn(move || {
+                for job in receiver {
+                    let record = run_export_job(&job);
+                    let (doc_id, path) = (record.doc_id.clone(), job.document_path.as_deref());
+                    fire_hooks(HookPayload::new(HookEvent::ExportFinished, &doc_id, &job.meta, path, &record));
+                    emit_event(&app, EXPORT_FINISHED, record);
+                }
+            });
+            sender
+        });
+        sender.send(job).map_err(|e| format!("Export queue stopped: {}", e))
+    }
+}
+
+fn row_to_export(doc_id: &str, row: &rusqlite::Row) -> rusqlite::Result<ExportRecord> {
+    Ok(ExportRecord {
+        id: row.get(0)?,
+        doc_id: doc_id.to_string(),
+        format: row.get(1)?,
+        path: PathBuf::from(row.get::<_, String>(2)?),
+        patch_uuid: row.get(3)?,
+        created_at: row.get(4)?,
+        status: row.get(5)?,
+        error: row.get(6)?,
+    })
+}
+
+/// Record a queued export
+pub(crate) fn add_export(
+    conn: &Connection,
+    doc_id: &str,
+    format: ExportFormat,
+    path: &Path,
+    patch_uuid: &str,
+) -> Result<ExportRecord, String> {
+    let created_at = chrono::Utc::now().timestamp_millis();
+    conn.execute(
+        "INSERT INTO exports (format, path, patch_uuid, created_at, status) VALUES (?1, ?2, ?3, ?4, 'queued')",
+        params![format.extension(), path.to_string_lossy(), patch_uuid, created_at],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(ExportRecord {
+        id: conn.last_insert_rowid(),
+        doc_id: doc_id.to_string(),
+        format: format.extension().to_string(),
+        path: path.to_path_buf(),
+        patch_uuid: patch_uuid.to_string(),
+        created_at,
+        status: "queued".to_string(),
+        error: None,
+    })
+}
+
+/// Every export of a document, newest first
+pub(crate) fn load_exports(conn: &Connection, doc_id: &str) -> Result<Vec<ExportRecord>, String> {
+    let mut stmt = conn
+        .prepare("SELECT id, format, path, patch_uuid, created_at, status, error FROM exports ORDER BY created_at DESC, id DESC")
+        .map_err(|e| e.to_string())?;
+    let exports = stmt
+        .query_map([], |row| row_to_export(doc_id, row))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(exports)
+}
+
+fn set_status(conn: &Connection, id: i64, status: &str, error: Option<&str>) -> Result<(), String> {
+    conn.execute("UPDATE exports SET status = ?1, error = ?2 WHERE id = ?3", params![status, error, id])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Write the text of the job's patch and record the outcome
+fn run_export_job(job: &ExportJob) -> ExportRecord {
+    let mut record = job.record.clone();
+    let result = Connection::open(&job.history_path)
+        .map_err(|e| e.to_string())
+        .and_then(|conn| {
+            set_status(&conn, record.id, "running", None)?;
+            let outcome = export_patch_text(&conn, &record, &job.meta);
+            let error = outcome.as_ref().err().map(String::as_str);
+            set_status(&conn, record.id, if outcome.is_ok() { "done" } else { "failed" }, error)?;
+            outcome
+        });
+    match result {
+        Ok(()) => record.status = "done".to_string(),
+        Err(e) => {
+            log::warn!("Export {} failed: {}", record.id, e);
+            record.status = "failed".to_string();
+            record.error = Some(e);
+        }
+    }
+    record
+}
+
+fn export_patch_text(conn: &Connection, record: &ExportRecord, meta: &DocumentMeta) -> Result<(), String> {
+    let format = ExportFormat::from_name(&record.format)
+        .ok_or_else(|| format!("Unsupported export format: {}", record.format))?;
+    let patch_id: i64 = conn
+        .query_row("SELECT id FROM patches WHERE uuid = ?1", params![record.patch_uuid], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Patch not found: {}", record.patch_uuid))?;
+    let content = snapshot_at_patch(conn, patch_id)?;
+    export_content(format, &record.path, content, meta.settings.clone(), Some(meta)).map_err(String::from)
+}
+
+/// History path and metadata of an open document
+fn document_export_context(
+    manager: &Mutex<DocumentManager>,
+    doc_id: &str,
+) -> Result<(PathBuf, DocumentMeta, Option<PathBuf>), KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager
+        .documents
+        .get(doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+    Ok((doc.history_path.clone(), doc.meta.clone(), doc.handle.path.clone()))
+}
+
+/// Queue an export of a document as of a patch (the newest one by default)
+///
+/// Returns the queued record; an `export-finished` event reports the result.
+#[tauri::command]
+pub fn queue_export(
+    app: AppHandle,
+    manager: State<'_, Mutex<DocumentManager>>,
+    queue: State<'_, Mutex<ExportQueue>>,
+    doc_id: String,
+    format: String,
+    path: String,
+    patch_uuid: Option<String>,
+) -> Result<ExportRecord, KorppiError> {
+    let export_format = ExportFormat::from_name(&format)
+        .ok_or_else(|| KorppiError::InvalidInput(format!("Unsupported export format: {}", format)))?;
+    let (history_path, meta, document_path) = document_export_context(&manager, &doc_id)?;
+
+    let conn = Connection::open(&history_path)?;
+    ensure_schema(&conn)?;
+    let patch_uuid = match patch_uuid {
+        Some(uuid) => uuid,
+        None => conn
+            .query_row("SELECT uuid FROM patches WHERE uuid IS NOT NULL ORDER BY id DESC LIMIT 1", [], |row| row.get(0))
+            .optional()?
+            .ok_or_else(|| KorppiError::InvalidInput("Save the document before exporting it".to_string()))?,
+    };
+    let record = add_export(&conn, &doc_id, export_format, Path::new(&path), &patch_uuid)?;
+
+    let mut queue = queue.lock().map_err(|e| e.to_string())?;
+    queue.submit(&app, ExportJob { record: record.clone(), history_path, meta, document_path })?;
+    Ok(record)
+}
+
+/// Every export of a document, newest first
+#[tauri::command]
+pub fn list_exports(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+) -> Result<Vec<ExportRecord>, KorppiError> {
+    let (history_path, _, _) = document_export_context(&manager, &doc_id)?;
+    if !history_path.exists() {
+        return Ok(Vec::new());
+    }
+    let conn = Connection::open(&history_path)?;
+    ensure_schema(&conn)?;
+    Ok(load_exports(&conn, &doc_id)?)
+}
+
+/// Queue an earlier export again, from the same patch and in the same
+/// format, to its original path unless another one is given
+#[tauri::command]
+pub fn regenerate_export(
+    app: AppHandle,
+    manager: State<'_, Mutex<DocumentManager>>,
+    queue: State<'_, Mutex<ExportQueue>>,
+    doc_id: String,
+    export_id: i64,
+    path: Option<String>,
+) -> Result<ExportRecord, KorppiError> {
+    let (history_path, _, _) = document_export_context(&manager, &doc_id)?;
+    let conn = Connection::open(&history_path)?;
+    ensure_schema(&conn)?;
+    let previous = load_exports(&conn, &doc_id)?
+        .into_iter()
+        .find(|e| e.id == export_id)
+        .ok_or_else(|| KorppiError::InvalidInput(format!("Export not found: {}", export_id)))?;
+    drop(conn);
+
+    let path = path.unwrap_or_else(|| previous.path.to_string_lossy().to_string());
+    queue_export(app, manager, queue, doc_id, previous.format, path, Some(previous.patch_uuid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kmd::DocumentSettings;
+
+    #[test]
+    fn test_export_job_records_outcome() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let history_path = dir.path().join("history.sqlite");
+        let conn = Connection::open(&history_path).unwrap();
+        ensure_schema(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO patches (timestamp, author, kind, data, uuid) VALUES (1, 'alice', 'Save', ?1, 'p1')",
+            params![serde_json::json!({ "snapshot": "# Draft\n" }).to_string()],
+        )
+        .unwrap();
+
+        let output = dir.path().join("draft.md");
+        let record = add_export(&conn, "doc", ExportFormat::Markdown, &output, "p1").unwrap();
+        let missing = add_export(&conn, "doc", ExportFormat::Markdown, &dir.path().join("x.md"), "gone").unwrap();
+        let meta = DocumentMeta {
+            settings: DocumentSettings { smart_typography: false, ..DocumentSettings::default() },
+            ..DocumentMeta::default()
+        };
+        let job = |record: &ExportRecord| ExportJob {
+            record: record.clone(),
+            history_path: history_path.clone(),
+            meta: meta.clone(),
+            document_path: None,
+        };
+
+        assert_eq!(run_export_job(&job(&record)).status, "done");
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "# Draft\n");
+        let failed = run_export_job(&job(&missing));
+        assert_eq!(failed.status, "failed");
+        assert!(failed.error.unwrap().contains("gone"));
+
+        let listed = load_exports(&conn, "doc").unwrap();
+        let statuses: Vec<(&str, &str)> = listed.iter().map(|e| (e.patch_uuid.as_str(), e.status.as_str())).collect();
+        assert_eq!(statuses, vec![("gone", "failed"), ("p1", "done")]);
+        assert_eq!(listed[1].format, "md");
+        assert_eq!(listed[1].path, output);
+    }
+}
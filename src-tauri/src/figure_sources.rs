@@ -0,0 +1,404 @@
+// src-tauri/src/figure_sources.rs
+//! Figures drawn by R or Python scripts, kept in sync with their data.
+//!
+//! `attach_figure_source` records the script that draws a `{#fig:}` figure.
+//! `regenerate_figures` runs the scripts, each in its own folder so it finds
+//! its data, and stores the image it writes as a document asset. A script is
+//! told where to write through the `KORPPI_FIGURE_DIR` environment variable;
+//! the image it leaves there replaces the one the figure shows. Asset names
+//! carry a hash of the image, so the reference changes with the picture and
+//! the change is recorded like any edit.
+//!
+//! Like hooks, figure sources are kept in the app settings by document UUID
+//! and never in the .kmd file: opening a document someone sent must not
+//! decide which script runs. Scripts must be inside the document's folder
+//! and are stored relative to it.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::document_manager::{document_assets_dir, DocumentManager, ASSETS_DIR};
+use crate::error::KorppiError;
+use crate::quarto::FIGURE_EXTENSIONS;
+use crate::settings::{load_app_settings, save_app_settings};
+
+/// File name prefix of regenerated figures in `assets/`
+const FIGURE_ASSET_PREFIX: &str = "figure-";
+
+/// Script that draws a figure of a document
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FigureSource {
+    /// Cross-reference label of the figure ("fig:cars")
+    pub label: String,
+    /// R or Python script, relative to the document's folder
+    pub script: PathBuf,
+    /// Asset the last run produced ("assets/figure-cars-1a2b3c4d.png")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset: Option<String>,
+    /// Time of the last successful run (ms)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regenerated_at: Option<i64>,
+}
+
+/// Outcome of regenerating one figure
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RegeneratedFigure {
+    pub label: String,
+    /// New asset, when the script ran
+    pub asset: Option<String>,
+    /// Whether the content has the figure and now shows the new asset
+    pub placed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Result of regenerate_figures
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FigureRegeneration {
+    /// Editor content with the figures pointing at their new assets
+    pub content: String,
+    pub figures: Vec<RegeneratedFigure>,
+}
+
+/// A figure label as `fig:name`, also accepting `name` and Quarto's `fig-name`
+pub fn normalize_figure_label(label: &str) -> Result<String, String> {
+    let label = label.trim();
+    let name = label
+        .strip_prefix("fig:")
+        .or_else(|| label.strip_prefix("fig-"))
+        .unwrap_or(label);
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(format!("Invalid figure label: {}", label));
+    }
+    Ok(format!("fig:{}", name))
+}
+
+/// The interpreter of a script, by its extension
+fn interpreter(script: &Path) -> Result<Command, String> {
+    let extension = script.extension().map(|e| e.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("r") => Ok(Command::new("Rscript")),
+        Some("py") => Ok(Command::new(if cfg!(windows) { "python" } else { "python3" })),
+        _ => Err(format!("Figure scripts must be R (.R) or Python (.py) files: {}", script.display())),
+    }
+}
+
+/// Whether a stored script path stays inside the document's folder
+fn is_contained(script: &Path) -> bool {
+    script.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        && script.components().any(|c| matches!(c, Component::Normal(_)))
+}
+
+pub fn validate_figure_source(source: &FigureSource) -> Result<(), String> {
+    if normalize_figure_label(&source.label)? != source.label {
+        return Err(format!("Invalid figure label: {}", source.label));
+    }
+    if !is_contained(&source.script) {
+        return Err(format!("Figure script must be inside the document's folder: {}", source.script.display()));
+    }
+    interpreter(&source.script).map(|_| ())
+}
+
+/// Path to store for a script: relative to the document's folder, which
+/// must hold it, so the document and its scripts can move together
+fn stored_script_path(script: &Path, document: Option<&Path>) -> Result<PathBuf, String> {
+    let folder = document
+        .and_then(Path::parent)
+        .ok_or("Save the document before attaching figure scripts")?;
+    let canonical = |path: &Path| path.canonicalize().map_err(|e| format!("{}: {}", path.display(), e));
+    canonical(script)?
+        .strip_prefix(canonical(folder)?)
+        .map(Path::to_path_buf)
+        .map_err(|_| format!("Figure script must be inside the document's folder: {}", script.display()))
+}
+
+/// Where a stored script is on disk; paths that leave the document's
+/// folder are refused
+fn resolve_script(script: &Path, document: Option<&Path>) -> Result<PathBuf, String> {
+    if !is_contained(script) {
+        return Err(format!("Figure script must be inside the document's folder: {}", script.display()));
+    }
+    document
+        .and_then(Path::parent)
+        .map(|folder| folder.join(script))
+        .ok_or_else(|| format!("Save the document before running {}", script.display()))
+}
+
+/// Figure sources of a document, from the app settings
+fn load_figure_sources(document_uuid: &str) -> Result<Vec<FigureSource>, String> {
+    Ok(load_app_settings()?.figure_sources.remove(document_uuid).unwrap_or_default())
+}
+
+/// Change the figure sources of a document in the app settings
+fn update_figure_sources<T>(document_uuid: &str, update: impl FnOnce(&mut Vec<FigureSource>) -> T) -> Result<T, String> {
+    let mut settings = load_app_settings()?;
+    let sources = settings.figure_sources.entry(document_uuid.to_string()).or_default();
+    let result = update(sources);
+    if sources.is_empty() {
+        settings.figure_sources.remove(document_uuid);
+    }
+    save_app_settings(&settings)?;
+    Ok(result)
+}
+
+/// UUID and file of an open document
+fn document_of(manager: &Mutex<DocumentManager>, id: &str) -> Result<(String, Option<PathBuf>, PathBuf), KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager
+        .documents
+        .get(id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
+    Ok((doc.meta.uuid.clone(), doc.handle.path.clone(), doc.history_path.clone()))
+}
+
+/// Run a script and return the image it wrote to `out_dir`
+fn run_script(script: &Path, label: &str, out_dir: &Path) -> Result<PathBuf, String> {
+    if !script.is_file() {
+        return Err(format!("Figure script not found: {}", script.display()));
+    }
+    let mut command = interpreter(script)?;
+    command
+        .arg(script)
+        .env("KORPPI_FIGURE_DIR", out_dir)
+        .env("KORPPI_FIGURE_LABEL", label);
+    if let Some(folder) = script.parent().filter(|f| f.is_dir()) {
+        command.current_dir(folder);
+    }
+    let output = command
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", script.display(), e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let tail: Vec<&str> = stderr.trim().lines().rev().take(10).collect();
+        let tail: Vec<&str> = tail.into_iter().rev().collect();
+        return Err(format!("{} failed:\n{}", script.display(), tail.join("\n")));
+    }
+
+    let mut images: Vec<PathBuf> = fs::read_dir(out_dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|e| FIGURE_EXTENSIONS.contains(&e.to_string_lossy().to_lowercase().as_str()))
+        })
+        .collect();
+    images.sort();
+    images
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("{} wrote no image to KORPPI_FIGURE_DIR", script.display()))
+}
+
+/// Asset name of a regenerated image: the label and a hash of the image
+fn figure_asset_name(label: &str, image: &Path, data: &[u8]) -> String {
+    let hash = format!("{:x}", Sha256::digest(data));
+    let extension = image.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let name = label.strip_prefix("fig:").unwrap_or(label);
+    format!("{}{}-{}.{}", FIGURE_ASSET_PREFIX, name, &hash[..8], extension)
+}
+
+/// Point the figure labelled `label` at `asset`; `None` when the content
+/// has no such figure
+pub fn replace_figure_image(content: &str, label: &str, asset: &str) -> Option<String> {
+    let figure_re = Regex::new(&format!(r"(!\[[^\]]*\]\()[^)]+(\)\{{#{}(?:\s[^}}]*)?\}})", regex::escape(label))).unwrap();
+    if !figure_re.is_match(content) {
+        return None;
+    }
+    Some(figure_re.replace_all(content, |caps: &regex::Captures| format!("{}{}{}", &caps[1], asset, &caps[2])).to_string())
+}
+
+/// Run one figure's script and store its image in `assets_dir`; returns the
+/// asset path
+fn regenerate_figure(source: &FigureSource, document: Option<&Path>, assets_dir: &Path) -> Result<String, String> {
+    let script = resolve_script(&source.script, document)?;
+    let out_dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+    let image = run_script(&script, &source.label, out_dir.path())?;
+    let data = fs::read(&image).map_err(|e| e.to_string())?;
+    let name = figure_asset_name(&source.label, &image, &data);
+    fs::create_dir_all(assets_dir).map_err(|e| e.to_string())?;
+    fs::write(assets_dir.join(&name), data).map_err(|e| e.to_string())?;
+    Ok(format!("{}/{}", ASSETS_DIR, name))
+}
+
+/// Delete a regenerated asset once nothing shows it
+fn remove_replaced_asset(assets_dir: &Path, asset: &str, content: &str) {
+    let Some(name) = asset.strip_prefix(&format!("{}/", ASSETS_DIR)) else {
+        return;
+    };
+    if name.starts_with(FIGURE_ASSET_PREFIX) && !name.contains(['/', '\\']) && !content.contains(asset) {
+        if let Err(e) = fs::remove_file(assets_dir.join(name)) {
+            log::debug!("Cannot remove replaced figure {}: {}", name, e);
+        }
+    }
+}
+
+/// Scripts attached to the figures of a document
+#[tauri::command]
+pub fn list_figure_sources(manager: State<'_, Mutex<DocumentManager>>, id: String) -> Result<Vec<FigureSource>, KorppiError> {
+    let (document_uuid, _, _) = document_of(&manager, &id)?;
+    Ok(load_figure_sources(&document_uuid)?)
+}
+
+/// Attach the script that draws a figure, replacing the one it had; the
+/// script must be in the folder of the saved document
+#[tauri::command]
+pub fn attach_figure_source(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    label: String,
+    script_path: String,
+) -> Result<FigureSource, KorppiError> {
+    let label = normalize_figure_label(&label).map_err(KorppiError::InvalidInput)?;
+    let script = PathBuf::from(script_path);
+    interpreter(&script).map_err(KorppiError::InvalidInput)?;
+    if !script.is_file() {
+        return Err(KorppiError::InvalidInput(format!("Figure script not found: {}", script.display())));
+    }
+
+    let (document_uuid, document, _) = document_of(&manager, &id)?;
+    let source = FigureSource {
+        script: stored_script_path(&script, document.as_deref()).map_err(KorppiError::InvalidInput)?,
+        label,
+        asset: None,
+        regenerated_at: None,
+    };
+    update_figure_sources(&document_uuid, |sources| match sources.iter_mut().find(|s| s.label == source.label) {
+        Some(existing) => *existing = source.clone(),
+        None => sources.push(source.clone()),
+    })?;
+    Ok(source)
+}
+
+/// Forget the script of a figure; whether it had one
+#[tauri::command]
+pub fn detach_figure_source(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    label: String,
+) -> Result<bool, KorppiError> {
+    let label = normalize_figure_label(&label).map_err(KorppiError::InvalidInput)?;
+    let (document_uuid, _, _) = document_of(&manager, &id)?;
+    Ok(update_figure_sources(&document_uuid, |sources| {
+        let count = sources.len();
+        sources.retain(|s| s.label != label);
+        sources.len() != count
+    })?)
+}
+
+/// Run the scripts of a document's figures (all of them, or the given
+/// labels) and point the figures at the images they drew
+///
+/// `content` is the current editor content; the returned content replaces
+/// it. A failing script leaves its figure as it was.
+#[tauri::command]
+pub async fn regenerate_figures(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    content: String,
+    labels: Option<Vec<String>>,
+) -> Result<FigureRegeneration, KorppiError> {
+    let labels = labels
+        .map(|labels| labels.iter().map(|l| normalize_figure_label(l)).collect::<Result<Vec<_>, _>>())
+        .transpose()
+        .map_err(KorppiError::InvalidInput)?;
+    let (document_uuid, document, history_path) = document_of(&manager, &id)?;
+    let sources = load_figure_sources(&document_uuid)?;
+    let assets_dir = document_assets_dir(&history_path);
+
+    let mut content = content;
+    let mut figures = Vec::new();
+    let mut updated: Vec<FigureSource> = Vec::new();
+    for source in sources.iter().filter(|s| labels.as_ref().is_none_or(|labels| labels.contains(&s.label))) {
+        match regenerate_figure(source, document.as_deref(), &assets_dir) {
+            Ok(asset) => {
+                let replaced = replace_figure_image(&content, &source.label, &asset);
+                let placed = replaced.is_some();
+                if let Some(replaced) = replaced {
+                    content = replaced;
+                }
+                figures.push(RegeneratedFigure { label: source.label.clone(), asset: Some(asset.clone()), placed, error: None });
+                updated.push(FigureSource {
+                    asset: Some(asset),
+                    regenerated_at: Some(chrono::Utc::now().timestamp_millis()),
+                    ..source.clone()
+                });
+            }
+            Err(e) => figures.push(RegeneratedFigure { label: source.label.clone(), asset: None, placed: false, error: Some(e) }),
+        }
+    }
+
+    for previous in sources.iter().filter_map(|s| s.asset.as_deref()) {
+        remove_replaced_asset(&assets_dir, previous, &content);
+    }
+    if !updated.is_empty() {
+        update_figure_sources(&document_uuid, |sources| {
+            for source in updated {
+                if let Some(existing) = sources.iter_mut().find(|s| s.label == source.label) {
+                    *existing = source;
+                }
+            }
+        })?;
+    }
+    Ok(FigureRegeneration { content, figures })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_figure_labels_and_references() {
+        assert_eq!(normalize_figure_label("fig:cars").unwrap(), "fig:cars");
+        assert_eq!(normalize_figure_label(" fig-cars ").unwrap(), "fig:cars");
+        assert_eq!(normalize_figure_label("cars_2").unwrap(), "fig:cars_2");
+        assert!(normalize_figure_label("fig:").is_err());
+        assert!(normalize_figure_label("../cars").is_err());
+
+        let content = "See @fig:cars.\n\n![Speed](assets/old.png){#fig:cars}\n\n![Iris](iris.png){#fig:cars-2 width=50%}\n";
+        let replaced = replace_figure_image(content, "fig:cars", "assets/figure-cars-1a2b3c4d.png").unwrap();
+        assert!(replaced.contains("![Speed](assets/figure-cars-1a2b3c4d.png){#fig:cars}"));
+        assert!(replaced.contains("![Iris](iris.png){#fig:cars-2 width=50%}"));
+        let replaced = replace_figure_image(&replaced, "fig:cars-2", "assets/figure-cars-2-00000000.png").unwrap();
+        assert!(replaced.contains("![Iris](assets/figure-cars-2-00000000.png){#fig:cars-2 width=50%}"));
+        assert!(replace_figure_image(content, "fig:sine", "assets/x.png").is_none());
+
+        let name = figure_asset_name("fig:cars", Path::new("/tmp/out/plot.PNG"), b"png");
+        assert!(name.starts_with("figure-cars-") && name.ends_with(".png"));
+        assert_ne!(name, figure_asset_name("fig:cars", Path::new("/tmp/out/plot.png"), b"other"));
+
+        assert!(interpreter(Path::new("plot.sh")).is_err());
+    }
+
+    #[test]
+    fn test_scripts_stay_in_document_folder() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let folder = dir.path().join("paper");
+        fs::create_dir_all(folder.join("scripts")).unwrap();
+        let document = folder.join("paper.kmd");
+        for script in ["scripts/cars.R", "../outside.py"] {
+            fs::write(folder.join(script), "").unwrap();
+        }
+
+        let stored = stored_script_path(&folder.join("scripts/cars.R"), Some(&document)).unwrap();
+        assert_eq!(stored, PathBuf::from("scripts/cars.R"));
+        assert_eq!(resolve_script(&stored, Some(&document)).unwrap(), folder.join("scripts/cars.R"));
+        assert!(resolve_script(&stored, None).is_err());
+        assert!(stored_script_path(&folder.join("scripts/../../outside.py"), Some(&document)).is_err());
+        assert!(stored_script_path(&folder.join("scripts/cars.R"), None).is_err());
+
+        // Paths in settings that leave the folder are never run
+        for script in ["/usr/local/bin/plot.py", "../outside.py", "scripts/../../outside.py"] {
+            assert!(resolve_script(Path::new(script), Some(&document)).is_err(), "{}", script);
+            let source = FigureSource { label: "fig:cars".to_string(), script: script.into(), asset: None, regenerated_at: None };
+            assert!(validate_figure_source(&source).is_err());
+        }
+        let source = FigureSource { label: "fig:cars".to_string(), script: stored, asset: None, regenerated_at: None };
+        assert!(validate_figure_source(&source).is_ok());
+    }
+}
@@ -0,0 +1,179 @@
+// src-tauri/src/file_lock.rs
+//! Advisory lock files for open KMD documents.
+//!
+//! Opening `notes.kmd` writes `.notes.kmd.lock` next to it, recording who
+//! holds the document. Other instances (or other machines on a shared drive)
+//! see the lock and can open the document read-only instead. Locks left
+//! behind by a crashed process on this machine are treated as stale.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+
+use crate::profile::load_saved_profile;
+
+/// Contents of a lock file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LockInfo {
+    pub pid: u32,
+    pub host: String,
+    /// Display name of the profile holding the lock
+    pub user: String,
+    /// RFC 3339 timestamp of when the document was opened
+    pub locked_at: String,
+}
+
+impl LockInfo {
+    /// Lock held by this process
+    pub fn current() -> LockInfo {
+        let user = load_saved_profile()
+            .ok()
+            .flatten()
+            .map(|p| p.name)
+            .filter(|n| !n.is_empty())
+            .unwrap_or_else(|| "Unknown user".to_string());
+        LockInfo {
+            pid: std::process::id(),
+            host: host_name(),
+            user,
+            locked_at: Utc::now().to_rfc3339(),
+        }
+    }
+
+    fn is_ours(&self) -> bool {
+        self.pid == std::process::id() && self.host == host_name()
+    }
+
+    /// A lock from this machine whose process is gone
+    fn is_stale(&self) -> bool {
+        self.host == host_name() && !process_alive(self.pid)
+    }
+}
+
+/// Name of this machine, as the system reports it
+pub(crate) fn host_name() -> String {
+    System::host_name()
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Whether a process of this machine is still running
+fn process_alive(pid: u32) -> bool {
+    let pid = Pid::from_u32(pid);
+    let mut system = System::new();
+    system.refresh_processes_specifics(ProcessesToUpdate::Some(&[pid]), true, ProcessRefreshKind::nothing());
+    system.process(pid).is_some()
+}
+
+/// Path of the lock file for a document
+pub fn lock_path(kmd_path: &Path) -> PathBuf {
+    let name = kmd_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    kmd_path.with_file_name(format!(".{}.lock", name))
+}
+
+/// Read the lock of a document, ignoring stale or unreadable lock files
+pub fn read_lock(kmd_path: &Path) -> Option<LockInfo> {
+    let content = fs::read_to_string(lock_path(kmd_path)).ok()?;
+    let lock: LockInfo = serde_json::from_str(&content).ok()?;
+    if lock.is_stale() {
+        return None;
+    }
+    Some(lock)
+}
+
+/// Take the lock of a document, failing if someone else holds it
+pub fn acquire_lock(kmd_path: &Path) -> Result<(), String> {
+    if let Some(lock) = read_lock(kmd_path) {
+        return Err(format!(
+            "Document is locked by {} on {} since {}",
+            lock.user, lock.host, lock.locked_at
+        ));
+    }
+    let json = serde_json::to_string_pretty(&LockInfo::current()).map_err(|e| e.to_string())?;
+    fs::write(lock_path(kmd_path), json).map_err(|e| format!("Failed to write lock file: {}", e))
+}
+
+/// Remove the lock of a document if this process holds it
+pub fn release_lock(kmd_path: &Path) {
+    let path = lock_path(kmd_path);
+    let ours = fs::read_to_string(&path)
+        .ok()
+        .and_then(|c| serde_json::from_str::<LockInfo>(&c).ok())
+        .is_some_and(|lock| lock.is_ours());
+    if ours {
+        fs::remove_file(path).ok();
+    }
+}
+
+/// Get the lock held on a document file, if any
+#[tauri::command]
+pub fn get_document_lock(path: String) -> Option<LockInfo> {
+    read_lock(Path::new(&path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_path() {
+        assert_eq!(lock_path(Path::new("/docs/paper.kmd")), PathBuf::from("/docs/.paper.kmd.lock"));
+    }
+
+    #[test]
+    fn test_acquire_and_release() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let kmd = dir.path().join("paper.kmd");
+
+        acquire_lock(&kmd).unwrap();
+        assert!(read_lock(&kmd).unwrap().is_ours());
+        // A second open in the same process is refused too
+        assert!(acquire_lock(&kmd).unwrap_err().contains("locked"));
+
+        release_lock(&kmd);
+        assert!(read_lock(&kmd).is_none());
+        acquire_lock(&kmd).unwrap();
+    }
+
+    #[test]
+    fn test_foreign_lock_is_kept() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let kmd = dir.path().join("paper.kmd");
+        let foreign = LockInfo {
+            pid: 1,
+            host: "other-machine".to_string(),
+            user: "Bob".to_string(),
+            locked_at: "2024-01-01T00:00:00Z".to_string(),
+        };
+        fs::write(lock_path(&kmd), serde_json::to_string(&foreign).unwrap()).unwrap();
+
+        assert_eq!(read_lock(&kmd), Some(foreign));
+        assert!(acquire_lock(&kmd).unwrap_err().contains("Bob"));
+        release_lock(&kmd);
+        assert!(lock_path(&kmd).exists());
+    }
+
+    #[test]
+    fn test_lock_of_exited_process_is_stale() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let kmd = dir.path().join("paper.kmd");
+        // The test binary lists its tests and exits
+        let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+            .arg("--list")
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+        let exited = LockInfo { pid: child.id(), ..LockInfo::current() };
+        child.wait().unwrap();
+        fs::write(lock_path(&kmd), serde_json::to_string(&exited).unwrap()).unwrap();
+
+        assert!(read_lock(&kmd).is_none());
+        acquire_lock(&kmd).unwrap();
+        assert!(read_lock(&kmd).unwrap().is_ours());
+    }
+}
@@ -0,0 +1,186 @@
+// src-tauri/src/file_progress.rs
+//! Progress and cancellation of opening and saving KMD files.
+//!
+//! Opening or saving a large KMD copies hundreds of megabytes. The copies go
+//! through `FileProgress`, which emits `FILE_PROGRESS` events with the bytes
+//! done and the current phase, and fails with `CANCELLED` once the job was
+//! cancelled with `cancel_file_job`. The caller then removes what it had
+//! written so far.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, State};
+
+use crate::error::KorppiError;
+use crate::events::{emit_event, FileProgressEvent, FILE_PROGRESS};
+
+/// Error of a job stopped by `cancel_file_job`
+pub const CANCELLED: &str = "Cancelled";
+
+/// Bytes copied between two progress events
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Open and save jobs that can still be cancelled
+#[derive(Default)]
+pub struct FileJobs {
+    jobs: HashMap<String, Arc<AtomicBool>>,
+}
+
+impl FileJobs {
+    /// Register a job; returns the flag it checks for cancellation
+    pub fn start(&mut self, job_id: &str) -> Arc<AtomicBool> {
+        self.jobs.entry(job_id.to_string()).or_default().clone()
+    }
+
+    pub fn finish(&mut self, job_id: &str) {
+        self.jobs.remove(job_id);
+    }
+
+    /// Ask a job to stop; false when no such job is running
+    pub fn cancel(&mut self, job_id: &str) -> bool {
+        match self.jobs.get(job_id) {
+            Some(flag) => {
+                flag.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Reports the progress of one open or save
+pub struct FileProgress {
+    app: Option<AppHandle>,
+    job_id: String,
+    operation: &'static str,
+    phase: &'static str,
+    bytes_done: u64,
+    bytes_total: u64,
+    cancel: Arc<AtomicBool>,
+}
+
+impl FileProgress {
+    pub fn new(app: AppHandle, job_id: &str, operation: &'static str, cancel: Arc<AtomicBool>) -> Self {
+        FileProgress {
+            app: Some(app),
+            job_id: job_id.to_string(),
+            operation,
+            phase: "starting",
+            bytes_done: 0,
+            bytes_total: 0,
+            cancel,
+        }
+    }
+
+    /// Progress nobody listens to and nobody cancels
+    pub fn silent() -> Self {
+        Self::silent_with(Arc::default())
+    }
+
+    /// Progress nobody listens to, stopped by a cancellation flag
+    pub fn silent_with(cancel: Arc<AtomicBool>) -> Self {
+        FileProgress {
+            app: None,
+            job_id: String::new(),
+            operation: "",
+            phase: "starting",
+            bytes_done: 0,
+            bytes_total: 0,
+            cancel,
+        }
+    }
+
+    pub fn set_total(&mut self, bytes_total: u64) {
+        self.bytes_total = bytes_total;
+    }
+
+    pub fn bytes_done(&self) -> u64 {
+        self.bytes_done
+    }
+
+    /// Enter a phase of the job, stopping if it was cancelled
+    pub fn phase(&mut self, phase: &'static str) -> Result<(), String> {
+        self.phase = phase;
+        self.check()?;
+        self.emit();
+        Ok(())
+    }
+
+    /// Copy everything from a reader to a writer, reporting every chunk
+    ///
+    /// I/O errors are kept as they are, so a full disk reaches the frontend
+    /// as such.
+    pub fn copy<R: Read + ?Sized, W: Write + ?Sized>(&mut self, reader: &mut R, writer: &mut W) -> Result<u64, KorppiError> {
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        let mut copied = 0u64;
+        loop {
+            self.check()?;
+            let read = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            writer.write_all(&buf[..read])?;
+            copied += read as u64;
+            self.bytes_done += read as u64;
+            self.emit();
+        }
+        Ok(copied)
+    }
+
+    /// Fail with `CANCELLED` once the job was cancelled
+    pub fn check(&self) -> Result<(), String> {
+        if self.cancel.load(Ordering::Relaxed) {
+            Err(CANCELLED.to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn emit(&self) {
+        if let Some(app) = &self.app {
+            emit_event(app, FILE_PROGRESS, FileProgressEvent {
+                job_id: self.job_id.clone(),
+                operation: self.operation.to_string(),
+                phase: self.phase.to_string(),
+                bytes_done: self.bytes_done,
+                bytes_total: self.bytes_total,
+            });
+        }
+    }
+}
+
+/// Stop a running open or save; returns false when it already finished
+#[tauri::command]
+pub fn cancel_file_job(jobs: State<'_, Mutex<FileJobs>>, job_id: String) -> Result<bool, KorppiError> {
+    let mut jobs = jobs.lock().map_err(|e| e.to_string())?;
+    Ok(jobs.cancel(&job_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_and_cancel() {
+        let mut jobs = FileJobs::default();
+        let flag = jobs.start("job");
+        let mut progress = FileProgress::silent_with(flag);
+
+        let data = vec![7u8; CHUNK_SIZE * 2 + 10];
+        let mut out = Vec::new();
+        assert_eq!(progress.copy(&mut data.as_slice(), &mut out).unwrap(), data.len() as u64);
+        assert_eq!(out, data);
+        assert_eq!(progress.bytes_done(), data.len() as u64);
+
+        assert!(jobs.cancel("job"));
+        assert_eq!(progress.phase("history").unwrap_err(), CANCELLED);
+        assert_eq!(progress.copy(&mut data.as_slice(), &mut Vec::new()).unwrap_err().to_string(), CANCELLED);
+
+        jobs.finish("job");
+        assert!(!jobs.cancel("job"));
+    }
+}
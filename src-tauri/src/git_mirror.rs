@@ -0,0 +1,641 @@
+// src-tauri/src/git_mirror.rs
+//! Git mirror of a document's history.
+//!
+//! When enabled for a document, every Save patch is also committed as a
+//! markdown snapshot to a local git repository, using the `git` executable.
+//! Commits carry `Korppi-Patch` and `Korppi-Author` trailers, so a document
+//! history can be rebuilt from the repository with the same patch UUIDs.
+//!
+//! Any markdown file kept in git can be imported the same way: each commit
+//! that touched it, following renames, becomes a Save patch by the commit
+//! author.
+
+use chrono::Utc;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use tauri::{State, Window};
+use uuid::Uuid;
+
+use crate::db_utils::ensure_schema;
+use crate::document_manager::{create_document_temp_dir, DocumentHandle, DocumentManager, DocumentState, ImportResult};
+use crate::history_rewrite::load_patches;
+use crate::kmd::{is_path_safe, AuthorRef, DocumentMeta, GitMirrorSettings};
+use crate::patch_log::{Patch, PatchInput};
+use crate::patch_store::PatchStore;
+
+const PATCH_TRAILER: &str = "Korppi-Patch";
+const AUTHOR_TRAILER: &str = "Korppi-Author";
+
+/// A commit of the mirror repository
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GitCommit {
+    pub hash: String,
+    pub author: String,
+    pub email: String,
+    /// Author date in milliseconds since the epoch
+    pub timestamp: i64,
+    pub message: String,
+    /// UUID of the mirrored patch, from the commit trailer
+    pub patch_uuid: Option<String>,
+    /// Profile ID of the patch author, from the commit trailer
+    pub author_id: Option<String>,
+}
+
+fn git(repo: &Path) -> Command {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(repo);
+    command
+}
+
+/// Run a git command and return its standard output
+fn run_git(command: &mut Command) -> Result<String, String> {
+    let output = command
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("git failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn check_file_name(file_name: &str) -> Result<(), String> {
+    if file_name.is_empty() || !is_path_safe(file_name) {
+        return Err(format!("Invalid mirror file name: {}", file_name));
+    }
+    Ok(())
+}
+
+/// Create the repository if it does not exist yet
+pub fn init_repo(repo: &Path) -> Result<(), String> {
+    fs::create_dir_all(repo).map_err(|e| format!("Failed to create repository directory: {}", e))?;
+    if !repo.join(".git").exists() {
+        run_git(git(repo).args(["init", "-q"]))?;
+    }
+    Ok(())
+}
+
+/// Commit a Save patch's snapshot; returns the commit hash, or `None` when
+/// the snapshot is identical to the committed file
+pub fn commit_patch(
+    settings: &GitMirrorSettings,
+    authors: &[AuthorRef],
+    patch: &Patch,
+) -> Result<Option<String>, String> {
+    let Some(snapshot) = patch.data.get("snapshot").and_then(|s| s.as_str()) else {
+        return Ok(None);
+    };
+    check_file_name(&settings.file_name)?;
+    let repo = settings.repo_path.as_path();
+    init_repo(repo)?;
+
+    let file_path = repo.join(&settings.file_name);
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    fs::write(&file_path, snapshot).map_err(|e| format!("Failed to write mirror file: {}", e))?;
+    run_git(git(repo).args(["add", "--"]).arg(&settings.file_name))?;
+
+    let unchanged = git(repo)
+        .args(["diff", "--cached", "--quiet"])
+        .status()
+        .map_err(|e| format!("Failed to run git: {}", e))?
+        .success();
+    if unchanged && has_head(repo) {
+        return Ok(None);
+    }
+
+    let author = authors.iter().find(|a| a.id == patch.author);
+    let name = author.map(|a| a.name.as_str()).filter(|n| !n.is_empty()).unwrap_or(&patch.author);
+    let email = author.and_then(|a| a.email.clone()).unwrap_or_default();
+    let subject = patch
+        .data
+        .get("message")
+        .and_then(|m| m.as_str())
+        .filter(|m| !m.trim().is_empty())
+        .unwrap_or("Save");
+    let mut message = format!("{}\n\n{}: {}", subject, AUTHOR_TRAILER, patch.author);
+    if let Some(uuid) = &patch.uuid {
+        message.push_str(&format!("\n{}: {}", PATCH_TRAILER, uuid));
+    }
+    let date = format!("@{} +0000", patch.timestamp / 1000);
+
+    run_git(
+        git(repo)
+            .args(["commit", "-q", "--allow-empty", "-m", &message])
+            .arg(format!("--author={} <{}>", name, email))
+            .arg(format!("--date={}", date))
+            .env("GIT_COMMITTER_NAME", name)
+            .env("GIT_COMMITTER_EMAIL", &email)
+            .env("GIT_COMMITTER_DATE", &date),
+    )?;
+    let hash = run_git(git(repo).args(["rev-parse", "HEAD"]))?;
+    Ok(Some(hash.trim().to_string()))
+}
+
+fn has_head(repo: &Path) -> bool {
+    git(repo).args(["rev-parse", "--verify", "-q", "HEAD"]).output().is_ok_and(|o| o.status.success())
+}
+
+/// `git log` format of a commit, read back by `parse_commit`
+fn log_format() -> String {
+    format!(
+        "%H%x1f%an%x1f%ae%x1f%at%x1f%s%x1f%(trailers:key={},valueonly,separator=)%x1f%(trailers:key={},valueonly,separator=)",
+        PATCH_TRAILER, AUTHOR_TRAILER
+    )
+}
+
+fn parse_commit(record: &str) -> Result<GitCommit, String> {
+    let trailer = |value: &str| Some(value.trim().to_string()).filter(|v| !v.is_empty());
+    let fields: Vec<&str> = record.split('\u{1f}').collect();
+    if fields.len() < 7 {
+        return Err(format!("Unexpected git log output: {}", record));
+    }
+    Ok(GitCommit {
+        hash: fields[0].to_string(),
+        author: fields[1].to_string(),
+        email: fields[2].to_string(),
+        timestamp: fields[3].trim().parse::<i64>().unwrap_or(0) * 1000,
+        message: fields[4].to_string(),
+        patch_uuid: trailer(fields[5]),
+        author_id: trailer(fields[6]),
+    })
+}
+
+/// Commits of the mirror repository, newest first
+pub fn mirror_log(repo: &Path) -> Result<Vec<GitCommit>, String> {
+    if !has_head(repo) {
+        return Ok(Vec::new());
+    }
+
+    let output = run_git(git(repo).arg("log").arg(format!("--format={}%x1e", log_format())))?;
+    output
+        .split('\u{1e}')
+        .map(|record| record.trim_start_matches('\n'))
+        .filter(|record| !record.is_empty())
+        .map(parse_commit)
+        .collect()
+}
+
+/// Commits that changed a file, newest first, with the file's path at each
+/// commit; renames are followed
+pub fn file_log(repo: &Path, file_path: &str) -> Result<Vec<(GitCommit, String)>, String> {
+    if !has_head(repo) {
+        return Ok(Vec::new());
+    }
+
+    let output = run_git(
+        git(repo)
+            .args(["log", "--follow", "--name-only"])
+            .arg(format!("--format=%x1e{}", log_format()))
+            .arg("--")
+            .arg(file_path),
+    )?;
+    let mut path = file_path.to_string();
+    output
+        .split('\u{1e}')
+        .filter(|record| !record.trim().is_empty())
+        .map(|record| {
+            let (header, names) = record.split_once('\n').unwrap_or((record, ""));
+            // Merges list no file; they keep the path of the newer commit
+            if let Some(name) = names.lines().map(str::trim).rfind(|l| !l.is_empty()) {
+                path = name.to_string();
+            }
+            Ok((parse_commit(header)?, path.clone()))
+        })
+        .collect()
+}
+
+/// Content of the mirror file at a commit
+fn file_at(repo: &Path, hash: &str, file_name: &str) -> Result<String, String> {
+    run_git(git(repo).arg("show").arg(format!("{}:{}", hash, file_name)))
+}
+
+/// Commit the Save patches of a history that the mirror does not have yet;
+/// returns the number of commits made
+pub fn backfill_mirror(settings: &GitMirrorSettings, authors: &[AuthorRef], conn: &Connection) -> Result<usize, String> {
+    init_repo(&settings.repo_path)?;
+    let mirrored: HashSet<String> = mirror_log(&settings.repo_path)?
+        .into_iter()
+        .filter_map(|c| c.patch_uuid)
+        .collect();
+
+    let mut committed = 0;
+    for patch in load_patches(conn)? {
+        if patch.kind != "Save" || patch.uuid.as_ref().is_some_and(|u| mirrored.contains(u)) {
+            continue;
+        }
+        if commit_patch(settings, authors, &patch)?.is_some() {
+            committed += 1;
+        }
+    }
+    Ok(committed)
+}
+
+/// Rebuild a history database from the mirror repository, oldest commit
+/// first; returns the content of the newest snapshot
+pub fn history_from_mirror(repo: &Path, file_name: &str, conn: &Connection) -> Result<String, String> {
+    check_file_name(file_name)?;
+    ensure_schema(conn)?;
+
+    let mut parent: Option<String> = None;
+    let mut content = String::new();
+    for commit in mirror_log(repo)?.into_iter().rev() {
+        let Ok(snapshot) = file_at(repo, &commit.hash, file_name) else {
+            continue;
+        };
+        let uuid = commit.patch_uuid.clone().unwrap_or_else(|| Uuid::new_v4().to_string());
+        let data = serde_json::json!({
+            "snapshot": snapshot,
+            "message": commit.message,
+            "git_commit": commit.hash,
+        });
+        let patch = PatchInput {
+            timestamp: commit.timestamp,
+            author: commit.author_id.clone().unwrap_or_else(|| commit.author.clone()),
+            kind: "Save".to_string(),
+            data,
+            uuid: Some(uuid.clone()),
+            parent_uuid: parent.clone(),
+        };
+        PatchStore::on(conn).insert_patch(&patch, None)?;
+        parent = Some(uuid);
+        content = snapshot;
+    }
+    Ok(content)
+}
+
+/// Path of a file relative to the repository root; `file_path` may be
+/// absolute or already relative
+fn repo_relative_path(repo: &Path, file_path: &str) -> Result<String, String> {
+    let path = Path::new(file_path);
+    if !path.is_absolute() {
+        return Ok(file_path.to_string());
+    }
+    let root = run_git(git(repo).args(["rev-parse", "--show-toplevel"]))?;
+    let root = fs::canonicalize(root.trim()).map_err(|e| e.to_string())?;
+    let path = fs::canonicalize(path).map_err(|e| format!("Failed to find {}: {}", file_path, e))?;
+    path.strip_prefix(&root)
+        .map(|p| p.to_string_lossy().replace('\\', "/"))
+        .map_err(|_| format!("{} is not in the repository {}", file_path, repo.display()))
+}
+
+/// Build a history database from the git log of a file, oldest commit
+/// first; returns the content of the newest snapshot and the commit authors
+///
+/// Authors are identified by email, or by name when a commit has none.
+pub fn history_from_git_log(repo: &Path, file_path: &str, conn: &Connection) -> Result<(String, Vec<AuthorRef>), String> {
+    ensure_schema(conn)?;
+    let file_path = repo_relative_path(repo, file_path)?;
+    let commits = file_log(repo, &file_path)?;
+    if commits.is_empty() {
+        return Err(format!("{} has no git history", file_path));
+    }
+
+    let mut authors: Vec<AuthorRef> = Vec::new();
+    let mut parent: Option<String> = None;
+    let mut content = String::new();
+    for (commit, path) in commits.into_iter().rev() {
+        // Commits that deleted the file have nothing to show
+        let Ok(snapshot) = file_at(repo, &commit.hash, &path) else {
+            continue;
+        };
+        let author_id = if commit.email.is_empty() { commit.author.clone() } else { commit.email.clone() };
+        if !authors.iter().any(|a| a.id == author_id) {
+            authors.push(AuthorRef {
+                id: author_id.clone(),
+                name: commit.author.clone(),
+                email: Some(commit.email.clone()).filter(|e| !e.is_empty()),
+                joined_at: None,
+                role: None,
+            });
+        }
+
+        let uuid = Uuid::new_v4().to_string();
+        let data = serde_json::json!({
+            "snapshot": snapshot,
+            "message": commit.message,
+            "authorName": commit.author,
+            "git_commit": commit.hash,
+        });
+        let patch = PatchInput {
+            timestamp: commit.timestamp,
+            author: author_id,
+            kind: "Save".to_string(),
+            data,
+            uuid: Some(uuid.clone()),
+            parent_uuid: parent.clone(),
+        };
+        PatchStore::on(conn).insert_patch(&patch, None)?;
+        parent = Some(uuid);
+        content = snapshot;
+    }
+    Ok((content, authors))
+}
+
+/// Mirror a freshly recorded Save patch if the document has a mirror
+///
+/// Mirroring failures are logged, never surfaced: the patch itself is safe
+/// in the history database.
+pub(crate) fn mirror_recorded_patch(doc: &DocumentState, patch: &Patch) {
+    if patch.kind != "Save" {
+        return;
+    }
+    if let Some(settings) = &doc.meta.settings.git_mirror {
+        if let Err(e) = commit_patch(settings, &doc.meta.authors, patch) {
+            log::warn!("Failed to mirror patch to {}: {}", settings.repo_path.display(), e);
+        }
+    }
+}
+
+/// Start mirroring a document's Save patches into a git repository
+///
+/// Existing Save patches are committed first; returns how many.
+#[tauri::command]
+pub fn enable_git_mirror(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    repo_path: String,
+    file_name: Option<String>,
+) -> Result<usize, String> {
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get_mut(&id)
+        .ok_or_else(|| format!("Document not found: {}", id))?;
+
+    let settings = GitMirrorSettings {
+        repo_path: repo_path.into(),
+        file_name: file_name.unwrap_or_else(|| "document.md".to_string()),
+    };
+    check_file_name(&settings.file_name)?;
+
+    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    ensure_schema(&conn)?;
+    let committed = backfill_mirror(&settings, &doc.meta.authors, &conn)?;
+
+    doc.meta.settings.git_mirror = Some(settings);
+    doc.handle.is_modified = true;
+    Ok(committed)
+}
+
+/// Stop mirroring a document; the repository is left as is
+#[tauri::command]
+pub fn disable_git_mirror(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+) -> Result<(), String> {
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get_mut(&id)
+        .ok_or_else(|| format!("Document not found: {}", id))?;
+    if doc.meta.settings.git_mirror.take().is_some() {
+        doc.handle.is_modified = true;
+    }
+    Ok(())
+}
+
+/// Get the commits of a document's mirror repository, newest first
+#[tauri::command]
+pub fn get_git_mirror_log(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+) -> Result<Vec<GitCommit>, String> {
+    let repo = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager.documents.get(&id)
+            .ok_or_else(|| format!("Document not found: {}", id))?;
+        doc.meta.settings.git_mirror
+            .as_ref()
+            .map(|m| m.repo_path.clone())
+            .ok_or_else(|| "Git mirroring is not enabled for this document".to_string())?
+    };
+    mirror_log(&repo)
+}
+
+/// Create a new document from a mirror repository
+///
+/// The history is rebuilt from the commits and the editor is seeded with
+/// the newest snapshot; saving the document writes the reconstructed KMD.
+#[tauri::command]
+pub fn import_git_mirror(
+    window: Window,
+    manager: State<'_, Mutex<DocumentManager>>,
+    repo_path: String,
+    file_name: Option<String>,
+) -> Result<ImportResult, String> {
+    let repo = Path::new(&repo_path);
+    let file_name = file_name.unwrap_or_else(|| "document.md".to_string());
+
+    let doc_id = Uuid::new_v4().to_string();
+    let temp_dir = create_document_temp_dir(&doc_id)?;
+    let history_path = temp_dir.join("history.sqlite");
+    let conn = Connection::open(&history_path).map_err(|e| e.to_string())?;
+    let content = history_from_mirror(repo, &file_name, &conn)?;
+
+    // Authors known from the commit trailers
+    let mut authors: HashMap<String, AuthorRef> = HashMap::new();
+    for commit in mirror_log(repo)? {
+        if let Some(author_id) = commit.author_id {
+            authors.entry(author_id.clone()).or_insert(AuthorRef {
+                id: author_id,
+                name: commit.author,
+                email: Some(commit.email).filter(|e| !e.is_empty()),
+                joined_at: None,
+                role: None,
+            });
+        }
+    }
+
+    let title = repo
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "Untitled Document".to_string());
+    let mut meta = DocumentMeta {
+        title,
+        authors: authors.into_values().collect(),
+        ..DocumentMeta::default()
+    };
+    meta.settings.git_mirror = Some(GitMirrorSettings {
+        repo_path: repo.to_path_buf(),
+        file_name,
+    });
+
+    open_imported_history(&window, &manager, doc_id, history_path, meta, content)
+}
+
+/// Create a new document from the git log of a markdown file
+///
+/// Each commit that changed the file becomes a Save patch with the commit's
+/// author and date, so the history shows the document's evolution before
+/// it moved to Korppi. `file_path` is relative to the repository or
+/// absolute.
+#[tauri::command]
+pub fn import_git_history(
+    window: Window,
+    manager: State<'_, Mutex<DocumentManager>>,
+    repo_path: String,
+    file_path: String,
+) -> Result<ImportResult, String> {
+    let repo = Path::new(&repo_path);
+
+    let doc_id = Uuid::new_v4().to_string();
+    let temp_dir = create_document_temp_dir(&doc_id)?;
+    let history_path = temp_dir.join("history.sqlite");
+    let conn = Connection::open(&history_path).map_err(|e| e.to_string())?;
+    let (content, authors) = history_from_git_log(repo, &file_path, &conn)?;
+
+    let title = Path::new(&file_path)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "Untitled Document".to_string());
+    let meta = DocumentMeta {
+        title,
+        authors,
+        ..DocumentMeta::default()
+    };
+
+    open_imported_history(&window, &manager, doc_id, history_path, meta, content)
+}
+
+/// Register a document rebuilt from git and make it active
+fn open_imported_history(
+    window: &Window,
+    manager: &State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    history_path: PathBuf,
+    meta: DocumentMeta,
+    content: String,
+) -> Result<ImportResult, String> {
+    let handle = DocumentHandle {
+        id: doc_id.clone(),
+        path: None,
+        title: meta.title.clone(),
+        is_modified: true,
+        opened_at: Utc::now(),
+        read_only: false,
+        external_merge: None,
+        recovered: None,
+        safe_mode: None,
+    };
+
+    let state = DocumentState {
+        handle: handle.clone(),
+        yjs_state: Vec::new(), // Will be populated when editor loads
+        yjs_updates: Vec::new(),
+        history_path,
+        meta,
+        sections: HashMap::new(),
+        disk_fingerprint: None,
+    };
+
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    manager.documents.insert(doc_id.clone(), state);
+    manager.set_active(window.label(), doc_id);
+
+    Ok(ImportResult {
+        handle,
+        content,
+        source_format: "git".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn save_patch(uuid: &str, timestamp: i64, snapshot: &str, message: &str) -> Patch {
+        Patch {
+            id: 0,
+            timestamp,
+            author: "a1".to_string(),
+            kind: "Save".to_string(),
+            data: serde_json::json!({ "snapshot": snapshot, "message": message }),
+            uuid: Some(uuid.to_string()),
+            parent_uuid: None,
+        }
+    }
+
+    #[test]
+    fn test_mirror_and_rebuild() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let settings = GitMirrorSettings {
+            repo_path: dir.path().join("mirror"),
+            file_name: "paper.md".to_string(),
+        };
+        let authors = vec![AuthorRef {
+            id: "a1".to_string(),
+            name: "Ada".to_string(),
+            email: Some("ada@example.org".to_string()),
+            joined_at: None,
+            role: None,
+        }];
+
+        assert!(commit_patch(&settings, &authors, &save_patch("p1", 1_700_000_000_000, "# Draft\n", "First")).unwrap().is_some());
+        assert!(commit_patch(&settings, &authors, &save_patch("p2", 1_700_000_060_000, "# Draft\n\nMore.\n", "")).unwrap().is_some());
+        // Unchanged snapshots make no commit
+        assert!(commit_patch(&settings, &authors, &save_patch("p3", 1_700_000_120_000, "# Draft\n\nMore.\n", "")).unwrap().is_none());
+
+        let log = mirror_log(&settings.repo_path).unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].message, "Save");
+        assert_eq!(log[0].patch_uuid.as_deref(), Some("p2"));
+        assert_eq!(log[1].message, "First");
+        assert_eq!(log[1].author, "Ada");
+        assert_eq!(log[1].author_id.as_deref(), Some("a1"));
+        assert_eq!(log[1].timestamp, 1_700_000_000_000);
+
+        let conn = Connection::open_in_memory().unwrap();
+        let content = history_from_mirror(&settings.repo_path, "paper.md", &conn).unwrap();
+        assert_eq!(content, "# Draft\n\nMore.\n");
+        let patches = load_patches(&conn).unwrap();
+        let uuids: Vec<_> = patches.iter().filter_map(|p| p.uuid.clone()).collect();
+        assert_eq!(uuids, vec!["p1", "p2"]);
+        assert_eq!(patches[1].parent_uuid.as_deref(), Some("p1"));
+        assert_eq!(patches[0].author, "a1");
+    }
+
+    #[test]
+    fn test_history_from_git_log_follows_renames() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let repo = dir.path();
+        init_repo(repo).unwrap();
+        let commit = |name: &str, email: &str, date: &str, message: &str| {
+            run_git(
+                git(repo)
+                    .args(["commit", "-q", "-m", message])
+                    .arg(format!("--author={} <{}>", name, email))
+                    .arg(format!("--date={}", date))
+                    .env("GIT_COMMITTER_NAME", name)
+                    .env("GIT_COMMITTER_EMAIL", email),
+            )
+            .unwrap();
+        };
+
+        fs::write(repo.join("draft.md"), "# Draft\n").unwrap();
+        fs::write(repo.join("other.md"), "Unrelated\n").unwrap();
+        run_git(git(repo).args(["add", "."])).unwrap();
+        commit("Ada", "ada@example.org", "@1700000000 +0000", "Start");
+        run_git(git(repo).args(["mv", "draft.md", "paper.md"])).unwrap();
+        commit("Ada", "ada@example.org", "@1700000060 +0000", "Rename");
+        fs::write(repo.join("paper.md"), "# Paper\n\nText.\n").unwrap();
+        run_git(git(repo).args(["add", "."])).unwrap();
+        commit("Bob", "bob@example.org", "@1700000120 +0000", "Retitle");
+
+        let conn = Connection::open_in_memory().unwrap();
+        let (content, authors) = history_from_git_log(repo, "paper.md", &conn).unwrap();
+        assert_eq!(content, "# Paper\n\nText.\n");
+        let ids: Vec<&str> = authors.iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, vec!["ada@example.org", "bob@example.org"]);
+
+        let patches = load_patches(&conn).unwrap();
+        assert_eq!(patches.len(), 3);
+        assert_eq!(patches[0].data["snapshot"], "# Draft\n");
+        assert_eq!(patches[0].timestamp, 1_700_000_000_000);
+        assert_eq!(patches[2].author, "bob@example.org");
+        assert_eq!(patches[2].parent_uuid, patches[1].uuid);
+
+        assert!(history_from_git_log(repo, "missing.md", &conn).is_err());
+    }
+}
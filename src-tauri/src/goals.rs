@@ -0,0 +1,253 @@
+// src-tauri/src/goals.rs
+//! Writing goals and daily progress.
+//!
+//! A document can have a goal of a number of words (and characters), with
+//! an optional deadline, stored in its settings. Progress is not logged
+//! separately: it is derived from the history, by diffing every Save and
+//! AutoSave snapshot against the one before it and adding up the words of
+//! the hunks per calendar day of the patch timestamps.
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, Offset};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::autosave::AUTOSAVE_KIND;
+use crate::contribution::count_words;
+use crate::db_utils::ensure_schema;
+use crate::document_manager::DocumentManager;
+use crate::error::KorppiError;
+use crate::history_rewrite::load_patches;
+use crate::hunk_calculator::calculate_hunks;
+use crate::kmd::WritingGoal;
+
+/// Changes made on one day
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct DailyProgress {
+    pub date: NaiveDate,
+    pub words_added: usize,
+    pub words_deleted: usize,
+    pub characters_added: usize,
+    /// Snapshots recorded that day
+    pub snapshots: usize,
+}
+
+/// A document's goal and how far along it is
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct GoalProgress {
+    pub doc_id: String,
+    pub goal: Option<WritingGoal>,
+    /// Words and characters of the latest snapshot
+    pub current_words: usize,
+    pub current_characters: usize,
+    /// Days with changes, oldest first
+    pub days: Vec<DailyProgress>,
+    /// Consecutive days, up to today, on which words were added; today
+    /// does not break the streak before anything was written
+    pub streak: u32,
+    pub remaining_words: usize,
+    /// Days until the deadline, today excluded; negative once it passed
+    pub days_left: Option<i64>,
+    /// Words to add per day, today included, to reach the goal by the deadline
+    pub words_per_day_needed: Option<usize>,
+    pub reached: bool,
+}
+
+fn count_characters(text: &str) -> usize {
+    text.chars().filter(|c| *c != '\n').count()
+}
+
+/// Per-day changes of a history and the text of its latest snapshot
+pub fn daily_progress(conn: &Connection, offset: FixedOffset) -> Result<(Vec<DailyProgress>, String), String> {
+    let mut days: BTreeMap<NaiveDate, DailyProgress> = BTreeMap::new();
+    let mut previous = String::new();
+    for patch in load_patches(conn)? {
+        if patch.kind != "Save" && patch.kind != AUTOSAVE_KIND {
+            continue;
+        }
+        let Some(snapshot) = patch.data.get("snapshot").and_then(|s| s.as_str()) else {
+            continue;
+        };
+        let Some(date) = DateTime::from_timestamp_millis(patch.timestamp).map(|t| t.with_timezone(&offset).date_naive())
+        else {
+            continue;
+        };
+        let day = days.entry(date).or_insert_with(|| DailyProgress { date, ..Default::default() });
+        day.snapshots += 1;
+        for hunk in calculate_hunks(&previous, snapshot) {
+            day.words_added += count_words(&hunk.modified_text);
+            day.words_deleted += count_words(&hunk.base_text);
+            day.characters_added += count_characters(&hunk.modified_text);
+        }
+        previous = snapshot.to_string();
+    }
+    Ok((days.into_values().collect(), previous))
+}
+
+/// Consecutive days with words added, ending today or, when nothing was
+/// added yet today, yesterday
+pub fn writing_streak(days: &[DailyProgress], today: NaiveDate) -> u32 {
+    let writing: Vec<NaiveDate> = days.iter().filter(|d| d.words_added > 0).map(|d| d.date).collect();
+    let mut day = if writing.contains(&today) { today } else { today.pred_opt().unwrap_or(today) };
+    let mut streak = 0;
+    while writing.contains(&day) {
+        streak += 1;
+        match day.pred_opt() {
+            Some(previous) => day = previous,
+            None => break,
+        }
+    }
+    streak
+}
+
+/// Progress of a history towards a goal, as of `today`
+pub fn goal_progress(
+    conn: &Connection,
+    goal: Option<WritingGoal>,
+    today: NaiveDate,
+    offset: FixedOffset,
+) -> Result<GoalProgress, String> {
+    let (days, text) = daily_progress(conn, offset)?;
+    let current_words = count_words(&text);
+    let current_characters = count_characters(&text);
+
+    let mut progress = GoalProgress {
+        streak: writing_streak(&days, today),
+        current_words,
+        current_characters,
+        days,
+        ..Default::default()
+    };
+    if let Some(goal) = &goal {
+        progress.remaining_words = (goal.target_words as usize).saturating_sub(current_words);
+        let characters_reached = goal.target_characters.is_none_or(|t| current_characters >= t as usize);
+        progress.reached = progress.remaining_words == 0 && characters_reached;
+        if let Some(deadline) = goal.deadline {
+            let days_left = (deadline - today).num_days();
+            progress.days_left = Some(days_left);
+            if days_left >= 0 {
+                progress.words_per_day_needed = Some(progress.remaining_words.div_ceil(days_left as usize + 1));
+            }
+        }
+    }
+    progress.goal = goal;
+    Ok(progress)
+}
+
+/// Set or clear (`target_words` of `None`) the writing goal of a document
+///
+/// The deadline is a date, "YYYY-MM-DD".
+#[tauri::command]
+pub fn set_writing_goal(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    target_words: Option<u32>,
+    deadline: Option<String>,
+    target_characters: Option<u32>,
+) -> Result<(), KorppiError> {
+    let goal = match target_words {
+        Some(target_words) => {
+            let deadline = deadline
+                .map(|d| {
+                    NaiveDate::parse_from_str(&d, "%Y-%m-%d")
+                        .map_err(|_| KorppiError::InvalidInput(format!("Invalid deadline: {}", d)))
+                })
+                .transpose()?;
+            Some(WritingGoal { target_words, target_characters, deadline })
+        }
+        None => None,
+    };
+
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager
+        .documents
+        .get_mut(&doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+    if doc.meta.settings.goal != goal {
+        doc.meta.settings.goal = goal;
+        doc.handle.is_modified = true;
+    }
+    Ok(())
+}
+
+/// The writing goal of a document with its per-day progress and streak,
+/// in the local time zone
+#[tauri::command]
+pub fn get_goal_progress(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+) -> Result<GoalProgress, KorppiError> {
+    let (history_path, goal) = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+        (doc.history_path.clone(), doc.meta.settings.goal.clone())
+    };
+    let now = Local::now();
+    let mut progress = if history_path.exists() {
+        let conn = Connection::open(&history_path)?;
+        ensure_schema(&conn)?;
+        goal_progress(&conn, goal, now.date_naive(), now.offset().fix())?
+    } else {
+        GoalProgress { goal, ..Default::default() }
+    };
+    progress.doc_id = doc_id;
+    Ok(progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+
+    const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+    #[test]
+    fn test_goal_progress_by_day() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+        // 2024-03-01 10:00 UTC
+        let start = 1_709_287_200_000;
+        let patches = [
+            (start, "Save", "One two three."),
+            (start + DAY_MS, "AutoSave", "One two three four five."),
+            (start + DAY_MS + 1000, "Suggestion", "Not counted at all here."),
+            (start + 2 * DAY_MS, "Save", "One two three four five six seven."),
+            (start + 2 * DAY_MS + 1000, "Save", "One two three four five six."),
+        ];
+        for (i, (timestamp, kind, snapshot)) in patches.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO patches (timestamp, author, kind, data, uuid) VALUES (?1, 'alice', ?2, ?3, ?4)",
+                params![timestamp, kind, serde_json::json!({ "snapshot": snapshot }).to_string(), format!("p{}", i)],
+            )
+            .unwrap();
+        }
+
+        let utc = FixedOffset::east_opt(0).unwrap();
+        let date = |d: u32| NaiveDate::from_ymd_opt(2024, 3, d).unwrap();
+        let goal = WritingGoal { target_words: 20, target_characters: None, deadline: Some(date(10)) };
+        let progress = goal_progress(&conn, Some(goal), date(4), utc).unwrap();
+
+        let days: Vec<(NaiveDate, usize, usize)> =
+            progress.days.iter().map(|d| (d.date, d.words_added, d.snapshots)).collect();
+        // Words of changed hunks count whole, as in the contribution report
+        assert_eq!(days, vec![(date(1), 3, 1), (date(2), 3, 1), (date(3), 4, 2)]);
+        assert!(progress.days[2].words_deleted > 0);
+        assert_eq!(progress.current_words, 6);
+        assert_eq!(progress.remaining_words, 14);
+        assert_eq!(progress.days_left, Some(6));
+        assert_eq!(progress.words_per_day_needed, Some(2));
+        assert!(!progress.reached);
+        // Nothing written yet on the 4th: the streak still counts up to the 3rd
+        assert_eq!(progress.streak, 3);
+        assert_eq!(writing_streak(&progress.days, date(5)), 0);
+
+        // Late in the evening in UTC-11 is the day before in UTC
+        let (days, _) = daily_progress(&conn, FixedOffset::west_opt(11 * 3600).unwrap()).unwrap();
+        assert_eq!(days[0].date, NaiveDate::from_ymd_opt(2024, 2, 29).unwrap());
+    }
+}
@@ -0,0 +1,322 @@
+// src-tauri/src/grammar.rs
+//! Grammar checking against a LanguageTool server.
+//!
+//! Off unless `AppSettings::languagetool_url` points at a server, usually a
+//! local one. The document text is checked paragraph by paragraph in the
+//! document's language; markdown syntax is sent as markup so LanguageTool
+//! skips it, and code blocks are not sent at all. Results are cached by a
+//! hash of the language and paragraph text, so only edited paragraphs are
+//! checked again.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::State;
+
+use crate::document_manager::{current_document_text, DocumentManager};
+use crate::error::KorppiError;
+use crate::settings::load_app_settings;
+
+/// Maximum number of replacements returned per issue
+const MAX_REPLACEMENTS: usize = 5;
+
+/// Cached paragraphs kept before the cache is cleared
+const MAX_CACHED_PARAGRAPHS: usize = 5000;
+
+/// A grammar or style issue in the document text
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GrammarIssue {
+    /// Starting character index (UTF-16, inclusive)
+    pub start: usize,
+    /// Ending character index (UTF-16, exclusive)
+    pub end: usize,
+    pub message: String,
+    pub short_message: Option<String>,
+    pub replacements: Vec<String>,
+    /// LanguageTool rule, e.g. "EN_A_VS_AN"
+    pub rule_id: String,
+    /// Rule category, e.g. "Grammar" or "Typography"
+    pub category: String,
+}
+
+/// Part of the document text to check (UTF-16 offsets)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GrammarRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Grammar check results by paragraph, shared between calls
+#[derive(Debug, Default)]
+pub struct GrammarChecker {
+    /// Issues with paragraph-relative offsets, keyed by language and text hash
+    cache: HashMap<String, Vec<GrammarIssue>>,
+}
+
+/// A paragraph of the document text
+#[derive(Debug, PartialEq)]
+struct Paragraph {
+    /// UTF-16 offset in the document text
+    start: usize,
+    text: String,
+}
+
+/// Split markdown into paragraphs separated by blank lines, leaving out
+/// fenced code blocks
+fn split_paragraphs(markdown: &str) -> Vec<Paragraph> {
+    let mut paragraphs = Vec::new();
+    let mut current: Option<Paragraph> = None;
+    let mut fence: Option<String> = None;
+    let mut offset = 0;
+
+    for line in markdown.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let trimmed = content.trim_start();
+        let line_len = line.encode_utf16().count();
+
+        if let Some(open) = &fence {
+            if trimmed.starts_with(open.as_str()) && trimmed.trim_start_matches(open.chars().next().unwrap_or('`')).trim().is_empty() {
+                fence = None;
+            }
+        } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            let marker = trimmed.chars().next().unwrap_or('`');
+            fence = Some(trimmed.chars().take_while(|c| *c == marker).collect());
+            paragraphs.extend(current.take());
+        } else if trimmed.is_empty() {
+            paragraphs.extend(current.take());
+        } else {
+            match &mut current {
+                Some(paragraph) => {
+                    paragraph.text.push('\n');
+                    paragraph.text.push_str(content);
+                }
+                None => {
+                    current = Some(Paragraph {
+                        start: offset,
+                        text: content.to_string(),
+                    })
+                }
+            }
+        }
+        offset += line_len;
+    }
+    paragraphs.extend(current);
+    paragraphs
+}
+
+/// LanguageTool annotation of a paragraph: markdown syntax as markup, the
+/// rest as text
+fn annotate(paragraph: &str) -> Value {
+    let markup = Regex::new(concat!(
+        r"(?m)^\s{0,3}(#{1,6}\s+|>\s?|[-*+]\s+(\[[ xX]\]\s+)?|\d+[.)]\s+)",
+        r"|`[^`\n]*`",
+        r"|!?\[|\]\([^)]*\)|\]\[[^\]]*\]|\]",
+        r"|\[@[^\]]*\]|@(fig|sec|tbl|eq):[\w-]+",
+        r"|\{[#.][^}]*\}",
+        r"|\*\*|__|~~|\*|_\b|\b_",
+        r"|</?[a-zA-Z][^>]*>",
+        r"|\|"
+    ))
+    .unwrap();
+
+    let mut annotation = Vec::new();
+    let mut last = 0;
+    for m in markup.find_iter(paragraph) {
+        if m.start() > last {
+            annotation.push(json!({ "text": &paragraph[last..m.start()] }));
+        }
+        // Inline code reads as a word, citations as a name
+        let markup = m.as_str();
+        if markup.starts_with('`') || markup.starts_with("[@") || markup.starts_with('@') {
+            annotation.push(json!({ "markup": markup, "interpretAs": "X" }));
+        } else if markup.starts_with('|') {
+            annotation.push(json!({ "markup": markup, "interpretAs": " " }));
+        } else {
+            annotation.push(json!({ "markup": markup }));
+        }
+        last = m.end();
+    }
+    if last < paragraph.len() {
+        annotation.push(json!({ "text": &paragraph[last..] }));
+    }
+    json!({ "annotation": annotation })
+}
+
+/// Read the matches of a LanguageTool response
+fn parse_matches(response: &Value) -> Vec<GrammarIssue> {
+    let Some(matches) = response.get("matches").and_then(Value::as_array) else {
+        return Vec::new();
+    };
+    matches
+        .iter()
+        .filter_map(|m| {
+            let start = m.get("offset")?.as_u64()? as usize;
+            let length = m.get("length")?.as_u64()? as usize;
+            let text = |key: &str| m.get(key).and_then(Value::as_str).filter(|s| !s.is_empty()).map(str::to_string);
+            Some(GrammarIssue {
+                start,
+                end: start + length,
+                message: text("message").unwrap_or_default(),
+                short_message: text("shortMessage"),
+                replacements: m
+                    .get("replacements")
+                    .and_then(Value::as_array)
+                    .map(|r| {
+                        r.iter()
+                            .filter_map(|r| r.get("value").and_then(Value::as_str).map(str::to_string))
+                            .take(MAX_REPLACEMENTS)
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                rule_id: m.pointer("/rule/id").and_then(Value::as_str).unwrap_or_default().to_string(),
+                category: m.pointer("/rule/category/name").and_then(Value::as_str).unwrap_or_default().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Check one paragraph on the server
+fn check_paragraph(url: &str, language: &str, paragraph: &str) -> Result<Vec<GrammarIssue>, KorppiError> {
+    let endpoint = format!("{}/v2/check", url.trim_end_matches('/'));
+    let data = annotate(paragraph).to_string();
+    let response = ureq::post(&endpoint)
+        .timeout(Duration::from_secs(20))
+        .send_form(&[("language", language), ("data", &data)])
+        .map_err(|e| match e {
+            ureq::Error::Status(status, response) => {
+                let body = response.into_string().unwrap_or_default();
+                KorppiError::Other(format!("LanguageTool answered with HTTP {}: {}", status, body.trim()))
+            }
+            e => KorppiError::GrammarUnavailable(format!("Could not reach LanguageTool at {}: {}", url, e)),
+        })?;
+    let body: Value = response.into_json()?;
+    Ok(parse_matches(&body))
+}
+
+fn cache_key(language: &str, paragraph: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(language.as_bytes());
+    hasher.update([0]);
+    hasher.update(paragraph.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Check the grammar of a document, or of the paragraphs overlapping a
+/// range of its text, in the document's language
+#[tauri::command]
+pub fn check_grammar(
+    manager: State<'_, Mutex<DocumentManager>>,
+    checker: State<'_, Mutex<GrammarChecker>>,
+    doc_id: String,
+    range: Option<GrammarRange>,
+) -> Result<Vec<GrammarIssue>, KorppiError> {
+    let url = load_app_settings()?.languagetool_url.ok_or_else(|| {
+        KorppiError::GrammarUnavailable("Grammar checking needs a LanguageTool server".to_string())
+    })?;
+    let (text, settings) = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.clone()))?;
+        (current_document_text(doc, false)?, doc.meta.settings.clone())
+    };
+    // Documents created before the setting existed carry an empty language
+    let language = if settings.language.is_empty() { "en-US" } else { settings.language.as_str() };
+
+    let mut issues = Vec::new();
+    for paragraph in split_paragraphs(&text) {
+        let end = paragraph.start + paragraph.text.encode_utf16().count();
+        if let Some(range) = range {
+            if end <= range.start || paragraph.start >= range.end {
+                continue;
+            }
+        }
+
+        let key = cache_key(language, &paragraph.text);
+        let cached = checker.lock().map_err(|e| e.to_string())?.cache.get(&key).cloned();
+        let found = match cached {
+            Some(found) => found,
+            None => {
+                let found = check_paragraph(&url, language, &paragraph.text)?;
+                let mut checker = checker.lock().map_err(|e| e.to_string())?;
+                if checker.cache.len() >= MAX_CACHED_PARAGRAPHS {
+                    checker.cache.clear();
+                }
+                checker.cache.insert(key, found.clone());
+                found
+            }
+        };
+
+        issues.extend(found.into_iter().map(|issue| GrammarIssue {
+            start: issue.start + paragraph.start,
+            end: issue.end + paragraph.start,
+            ..issue
+        }));
+    }
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_paragraphs() {
+        let text = "# Título\n\nFirst line\nsecond line.\n\n```r\nx <- 1\n\ny <- 2\n```\nAfter code.";
+        let paragraphs = split_paragraphs(text);
+
+        let texts: Vec<&str> = paragraphs.iter().map(|p| p.text.as_str()).collect();
+        assert_eq!(texts, vec!["# Título", "First line\nsecond line.", "After code."]);
+        assert_eq!(paragraphs[1].start, 10);
+        let after = text.find("After").unwrap();
+        assert_eq!(paragraphs[2].start, text[..after].encode_utf16().count());
+    }
+
+    #[test]
+    fn test_annotate_markdown() {
+        let data = annotate("## A **bold** [link](http://x.org) with `code` and @fig:plot");
+        let parts: Vec<(bool, &str)> = data["annotation"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| match p.get("text") {
+                Some(text) => (true, text.as_str().unwrap()),
+                None => (false, p["markup"].as_str().unwrap()),
+            })
+            .collect();
+
+        let text: String = parts.iter().filter(|(is_text, _)| *is_text).map(|(_, t)| *t).collect();
+        assert_eq!(text, "A bold link with  and ");
+        assert!(parts.contains(&(false, "](http://x.org)")));
+        assert!(parts.contains(&(false, "`code`")));
+        // Markup and text together are the paragraph, so offsets line up
+        let all: String = parts.iter().map(|(_, t)| *t).collect();
+        assert_eq!(all, "## A **bold** [link](http://x.org) with `code` and @fig:plot");
+    }
+
+    #[test]
+    fn test_parse_matches() {
+        let response = json!({
+            "matches": [{
+                "message": "Use \"an\" instead of \"a\".",
+                "shortMessage": "",
+                "offset": 8,
+                "length": 1,
+                "replacements": [{"value": "an"}],
+                "rule": {"id": "EN_A_VS_AN", "category": {"id": "MISC", "name": "Miscellaneous"}}
+            }]
+        });
+        let issues = parse_matches(&response);
+
+        assert_eq!(issues.len(), 1);
+        assert_eq!((issues[0].start, issues[0].end), (8, 9));
+        assert_eq!(issues[0].short_message, None);
+        assert_eq!(issues[0].replacements, vec!["an"]);
+        assert_eq!(issues[0].rule_id, "EN_A_VS_AN");
+    }
+}
@@ -0,0 +1,422 @@
+// src-tauri/src/history_export.rs
+//! The raw history of a document as data, for studying how it was written.
+//!
+//! Every patch is exported with its author, kind, parent and the size of
+//! its change: the hunks between its snapshot and the snapshot of its
+//! nearest ancestor that has one (or, for patches without a recorded
+//! parent, the previous snapshot). Reviews, review comments and document
+//! comments come along. Snapshot texts can run to megabytes each and are
+//! left out unless asked for.
+//!
+//! JSON goes to one file. CSV goes to a folder with one file per table,
+//! which spreadsheets and R or pandas read directly.
+
+use chrono::{TimeZone, Utc};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::comments::{init_comments_table, load_all_comments};
+use crate::contribution::{count_words, csv_field};
+use crate::db_utils::ensure_schema;
+use crate::document_manager::{author_display_name, DocumentManager};
+use crate::error::KorppiError;
+use crate::history_rewrite::load_patches;
+use crate::hunk_calculator::{calculate_hunks_with, DiffOptions};
+use crate::kmd::DocumentMeta;
+use crate::patch_store::PatchStore;
+use crate::review_comments::load_all_review_comments;
+
+/// Format of a history export
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryDataFormat {
+    Json,
+    Csv,
+}
+
+/// One patch with the size of its change
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PatchRecord {
+    pub id: i64,
+    pub uuid: Option<String>,
+    pub parent_uuid: Option<String>,
+    /// Milliseconds since the epoch
+    pub timestamp: i64,
+    pub author: String,
+    pub author_name: String,
+    pub kind: String,
+    /// Imported from a bundle no known key of the author signed
+    pub unverified: bool,
+    pub hunks: usize,
+    pub words_added: usize,
+    pub words_deleted: usize,
+    pub chars_added: usize,
+    pub chars_deleted: usize,
+    /// Patch data without the snapshot
+    pub data: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<String>,
+}
+
+/// A review decision on a patch
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReviewRecord {
+    pub patch_uuid: String,
+    pub reviewer_id: String,
+    pub reviewer_name: Option<String>,
+    pub decision: String,
+    pub reviewed_at: i64,
+}
+
+/// A comment on the text of the document
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CommentRecord {
+    pub id: i64,
+    pub parent_id: Option<i64>,
+    pub timestamp: i64,
+    pub author: String,
+    pub status: String,
+    pub selected_text: String,
+    pub content: String,
+}
+
+/// The history of a document as data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryData {
+    pub title: String,
+    pub document_uuid: String,
+    pub exported_at: String,
+    pub patches: Vec<PatchRecord>,
+    pub reviews: Vec<ReviewRecord>,
+    pub review_comments: Vec<crate::review_comments::ReviewComment>,
+    pub comments: Vec<CommentRecord>,
+}
+
+/// Collect the history of a document from its history database
+pub fn collect_history_data(
+    conn: &Connection,
+    meta: &DocumentMeta,
+    options: &DiffOptions,
+    include_snapshots: bool,
+) -> Result<HistoryData, String> {
+    let unverified = PatchStore::on(conn).unverified_uuids()?;
+    let patches = load_patches(conn)?;
+    let snapshot_of = |data: &serde_json::Value| data.get("snapshot").and_then(|s| s.as_str()).map(str::to_string);
+    let snapshots: HashMap<&str, String> = patches
+        .iter()
+        .filter_map(|p| Some((p.uuid.as_deref()?, snapshot_of(&p.data)?)))
+        .collect();
+    let parents: HashMap<&str, &str> = patches
+        .iter()
+        .filter_map(|p| Some((p.uuid.as_deref()?, p.parent_uuid.as_deref()?)))
+        .collect();
+
+    let mut records = Vec::with_capacity(patches.len());
+    let mut previous: Option<String> = None;
+    for patch in &patches {
+        let snapshot = snapshot_of(&patch.data);
+        let (mut hunks, mut words_added, mut words_deleted, mut chars_added, mut chars_deleted) = (0, 0, 0, 0, 0);
+        if let Some(snapshot) = &snapshot {
+            // The nearest ancestor with a snapshot; the parent chain is
+            // bounded by the number of patches in case it loops
+            let mut ancestor = patch.uuid.as_deref().and_then(|uuid| parents.get(uuid).copied());
+            let mut base = None;
+            for _ in 0..patches.len() {
+                let Some(uuid) = ancestor else {
+                    break;
+                };
+                if let Some(text) = snapshots.get(uuid) {
+                    base = Some(text.as_str());
+                    break;
+                }
+                ancestor = parents.get(uuid).copied();
+            }
+            let has_parent = patch.parent_uuid.is_some();
+            let base = base.unwrap_or(if has_parent { "" } else { previous.as_deref().unwrap_or("") });
+            for hunk in calculate_hunks_with(base, snapshot, options) {
+                hunks += 1;
+                words_added += count_words(&hunk.modified_text);
+                words_deleted += count_words(&hunk.base_text);
+                chars_added += hunk.modified_text.chars().count();
+                chars_deleted += hunk.base_text.chars().count();
+            }
+            previous = Some(snapshot.clone());
+        }
+
+        let mut data = patch.data.clone();
+        if let Some(fields) = data.as_object_mut() {
+            fields.remove("snapshot");
+        }
+        records.push(PatchRecord {
+            id: patch.id,
+            uuid: patch.uuid.clone(),
+            parent_uuid: patch.parent_uuid.clone(),
+            timestamp: patch.timestamp,
+            author: patch.author.clone(),
+            author_name: author_display_name(meta, &patch.author),
+            kind: patch.kind.clone(),
+            unverified: patch.uuid.as_ref().is_some_and(|uuid| unverified.contains(uuid)),
+            hunks,
+            words_added,
+            words_deleted,
+            chars_added,
+            chars_deleted,
+            data,
+            snapshot: snapshot.filter(|_| include_snapshots),
+        });
+    }
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT patch_uuid, reviewer_id, reviewer_name, decision, reviewed_at
+             FROM patch_reviews ORDER BY reviewed_at, patch_uuid, reviewer_id",
+        )
+        .map_err(|e| e.to_string())?;
+    let reviews = stmt
+        .query_map([], |row| {
+            Ok(ReviewRecord {
+                patch_uuid: row.get(0)?,
+                reviewer_id: row.get(1)?,
+                reviewer_name: row.get(2)?,
+                decision: row.get(3)?,
+                reviewed_at: row.get(4)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    init_comments_table(conn)?;
+    let comments = load_all_comments(conn)?
+        .into_iter()
+        .map(|c| CommentRecord {
+            id: c.id,
+            parent_id: c.parent_id,
+            timestamp: c.timestamp,
+            author: c.author,
+            status: c.status,
+            selected_text: c.selected_text,
+            content: c.content,
+        })
+        .collect();
+
+    Ok(HistoryData {
+        title: meta.title.clone(),
+        document_uuid: meta.uuid.clone(),
+        exported_at: Utc::now().to_rfc3339(),
+        patches: records,
+        reviews,
+        review_comments: load_all_review_comments(conn)?,
+        comments,
+    })
+}
+
+/// A timestamp in milliseconds as RFC 3339, for reading without converting
+fn time(timestamp: i64) -> String {
+    Utc.timestamp_millis_opt(timestamp).single().map(|t| t.to_rfc3339()).unwrap_or_default()
+}
+
+/// A CSV table: a header row and one row per record
+fn csv_table(columns: &[&str], rows: impl Iterator<Item = Vec<String>>) -> String {
+    let mut out = format!("{}\n", columns.join(","));
+    for row in rows {
+        let cells: Vec<String> = row.iter().map(|c| csv_field(c)).collect();
+        out.push_str(&format!("{}\n", cells.join(",")));
+    }
+    out
+}
+
+fn optional(value: &Option<impl ToString>) -> String {
+    value.as_ref().map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// The CSV files of an export, as (file name, content)
+pub fn render_csv(history: &HistoryData) -> Vec<(&'static str, String)> {
+    let with_snapshots = history.patches.iter().any(|p| p.snapshot.is_some());
+    let mut patch_columns = vec![
+        "id", "uuid", "parent_uuid", "timestamp", "time", "author", "author_name", "kind", "unverified",
+        "hunks", "words_added", "words_deleted", "chars_added", "chars_deleted", "data",
+    ];
+    if with_snapshots {
+        patch_columns.push("snapshot");
+    }
+    let patches = csv_table(
+        &patch_columns,
+        history.patches.iter().map(|p| {
+            let mut row = vec![
+                p.id.to_string(),
+                optional(&p.uuid),
+                optional(&p.parent_uuid),
+                p.timestamp.to_string(),
+                time(p.timestamp),
+                p.author.clone(),
+                p.author_name.clone(),
+                p.kind.clone(),
+                p.unverified.to_string(),
+                p.hunks.to_string(),
+                p.words_added.to_string(),
+                p.words_deleted.to_string(),
+                p.chars_added.to_string(),
+                p.chars_deleted.to_string(),
+                p.data.to_string(),
+            ];
+            if with_snapshots {
+                row.push(p.snapshot.clone().unwrap_or_default());
+            }
+            row
+        }),
+    );
+    let reviews = csv_table(
+        &["patch_uuid", "reviewer_id", "reviewer_name", "decision", "reviewed_at", "time"],
+        history.reviews.iter().map(|r| {
+            vec![
+                r.patch_uuid.clone(),
+                r.reviewer_id.clone(),
+                optional(&r.reviewer_name),
+                r.decision.clone(),
+                r.reviewed_at.to_string(),
+                time(r.reviewed_at),
+            ]
+        }),
+    );
+    let review_comments = csv_table(
+        &["uuid", "patch_uuid", "hunk_id", "author_id", "author_name", "content", "created_at", "time"],
+        history.review_comments.iter().map(|c| {
+            vec![
+                c.uuid.clone(),
+                c.patch_uuid.clone(),
+                optional(&c.hunk_id),
+                c.author_id.clone(),
+                optional(&c.author_name),
+                c.content.clone(),
+                c.created_at.to_string(),
+                time(c.created_at),
+            ]
+        }),
+    );
+    let comments = csv_table(
+        &["id", "parent_id", "timestamp", "time", "author", "status", "selected_text", "content"],
+        history.comments.iter().map(|c| {
+            vec![
+                c.id.to_string(),
+                optional(&c.parent_id),
+                c.timestamp.to_string(),
+                time(c.timestamp),
+                c.author.clone(),
+                c.status.clone(),
+                c.selected_text.clone(),
+                c.content.clone(),
+            ]
+        }),
+    );
+    vec![
+        ("patches.csv", patches),
+        ("reviews.csv", reviews),
+        ("review_comments.csv", review_comments),
+        ("comments.csv", comments),
+    ]
+}
+
+/// Write an export; returns the files written
+pub fn write_history_data(history: &HistoryData, format: HistoryDataFormat, path: &Path) -> Result<Vec<PathBuf>, String> {
+    match format {
+        HistoryDataFormat::Json => {
+            let content = serde_json::to_string_pretty(history).map_err(|e| e.to_string())?;
+            fs::write(path, content).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            Ok(vec![path.to_path_buf()])
+        }
+        HistoryDataFormat::Csv => {
+            fs::create_dir_all(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+            render_csv(history)
+                .into_iter()
+                .map(|(name, content)| {
+                    let file = path.join(name);
+                    fs::write(&file, content).map_err(|e| format!("Failed to write {}: {}", file.display(), e))?;
+                    Ok(file)
+                })
+                .collect()
+        }
+    }
+}
+
+/// Export the patches, reviews and comments of a document as data
+///
+/// `format` is "json", written to the file at `path`, or "csv", written as
+/// patches.csv, reviews.csv, review_comments.csv and comments.csv into the
+/// folder at `path`. Snapshot texts are included only with
+/// `include_snapshots`. Returns the paths of the files written.
+#[tauri::command]
+pub fn export_history_data(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    format: HistoryDataFormat,
+    path: String,
+    include_snapshots: Option<bool>,
+) -> Result<Vec<String>, KorppiError> {
+    let history = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+        let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+        ensure_schema(&conn)?;
+        collect_history_data(&conn, &doc.meta, &doc.meta.settings.diff, include_snapshots.unwrap_or(false))?
+    };
+    let written = write_history_data(&history, format, Path::new(&path))?;
+    Ok(written.iter().map(|p| p.to_string_lossy().to_string()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+
+    #[test]
+    fn test_history_data() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+        for (uuid, parent, author, text) in [
+            ("p1", None, "alice", "One two three"),
+            ("p2", Some("p1"), "bob", "One two, three four five"),
+            ("p3", Some("p1"), "alice", "One three"),
+        ] {
+            conn.execute(
+                "INSERT INTO patches (timestamp, author, kind, data, uuid, parent_uuid) VALUES (1000, ?1, 'Save', ?2, ?3, ?4)",
+                params![author, serde_json::json!({ "snapshot": text, "message": "Draft" }).to_string(), uuid, parent],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "INSERT INTO patch_reviews (patch_uuid, reviewer_id, decision, reviewer_name, reviewed_at) VALUES ('p2', 'alice', 'accepted', 'Alice', 5)",
+            [],
+        )
+        .unwrap();
+
+        let history = collect_history_data(&conn, &DocumentMeta::default(), &DiffOptions::default(), false).unwrap();
+        let stats: Vec<(usize, usize)> = history.patches.iter().map(|p| (p.words_added, p.words_deleted)).collect();
+        // p3 is diffed against its parent p1, not against p2
+        assert_eq!(stats[0], (3, 0));
+        assert!(stats[1].0 > 0);
+        assert_eq!(stats[2].1, 1);
+        assert!(history.patches.iter().all(|p| p.snapshot.is_none() && p.data.get("snapshot").is_none()));
+        assert_eq!(history.patches[0].data["message"], "Draft");
+        assert_eq!(history.reviews[0].reviewer_name.as_deref(), Some("Alice"));
+
+        let files = render_csv(&history);
+        let patches = &files[0].1;
+        assert!(patches.starts_with("id,uuid,parent_uuid,timestamp,time,author,"));
+        assert!(!patches.lines().next().unwrap().contains("snapshot"));
+        assert!(patches.contains("\"{\"\"message\"\":\"\"Draft\"\"}\""));
+        assert_eq!(files[1].1.lines().nth(1).unwrap(), "p2,alice,Alice,accepted,5,1970-01-01T00:00:00.005+00:00");
+
+        let with_snapshots = collect_history_data(&conn, &DocumentMeta::default(), &DiffOptions::default(), true).unwrap();
+        assert_eq!(with_snapshots.patches[2].snapshot.as_deref(), Some("One three"));
+        assert!(render_csv(&with_snapshots)[0].1.lines().next().unwrap().ends_with(",snapshot"));
+    }
+}
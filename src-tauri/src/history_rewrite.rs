@@ -0,0 +1,281 @@
+// src-tauri/src/history_rewrite.rs
+//! History rewrite tools.
+//!
+//! Squashing collapses a contiguous run of patches into a single Save patch
+//! carrying the final snapshot of the run. The squashed patch keeps the UUID
+//! and reviews of the last patch and the parent link of the first, so review
+//! state and patch chains outside the run stay intact. Pruning removes patches
+//! that were rejected and never accepted.
+
+use rusqlite::{params, Connection};
+use std::collections::HashSet;
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::db_utils::ensure_schema;
+use crate::document_manager::DocumentManager;
+use crate::patch_log::Patch;
+use crate::patch_store::PatchStore;
+use crate::suggestions::SUGGESTION_KIND;
+
+pub(crate) fn load_patches(conn: &Connection) -> Result<Vec<Patch>, String> {
+    PatchStore::on(conn).list()
+}
+
+/// Delete patches with their snapshots, reviews and review comments, pointing children at `new_parent`
+fn remove_patches(conn: &Connection, patches: &[&Patch], new_parent: Option<&str>) -> Result<(), String> {
+    for patch in patches {
+        PatchStore::on(conn).delete_snapshots(patch.id)?;
+        conn.execute("DELETE FROM patches WHERE id = ?1", params![patch.id])
+            .map_err(|e| e.to_string())?;
+        if let Some(uuid) = &patch.uuid {
+            conn.execute("DELETE FROM patch_reviews WHERE patch_uuid = ?1", params![uuid])
+                .map_err(|e| e.to_string())?;
+            conn.execute("DELETE FROM patch_review_comments WHERE patch_uuid = ?1", params![uuid])
+                .map_err(|e| e.to_string())?;
+            conn.execute(
+                "DELETE FROM orphaned_patches WHERE patch_uuid = ?1 OR rejected_uuid = ?1",
+                params![uuid],
+            )
+            .map_err(|e| e.to_string())?;
+            conn.execute(
+                "UPDATE patches SET parent_uuid = ?1 WHERE parent_uuid = ?2",
+                params![new_parent, uuid],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Replace the patches from `from_uuid` to `to_uuid` (inclusive) by one Save patch
+///
+/// Returns the UUID of the squashed patch, which is that of `to_uuid`.
+pub fn squash_patch_range(conn: &Connection, from_uuid: &str, to_uuid: &str, message: &str) -> Result<String, String> {
+    ensure_schema(conn)?;
+
+    let patches = load_patches(conn)?;
+    let position = |uuid: &str| {
+        patches
+            .iter()
+            .position(|p| p.uuid.as_deref() == Some(uuid))
+            .ok_or_else(|| format!("Patch not found: {}", uuid))
+    };
+    let start = position(from_uuid)?;
+    let end = position(to_uuid)?;
+    if start > end {
+        return Err("The first patch of the range must precede the last".to_string());
+    }
+
+    let run = &patches[start..=end];
+    if run.iter().any(|p| p.kind == SUGGESTION_KIND) {
+        return Err("Cannot squash a range containing suggestions".to_string());
+    }
+
+    let snapshot = run
+        .iter()
+        .rev()
+        .find_map(|p| p.data.get("snapshot").and_then(|s| s.as_str()))
+        .ok_or_else(|| "No snapshot in the patch range".to_string())?
+        .to_string();
+
+    let first = &run[0];
+    let last = &run[run.len() - 1];
+    let mut data = if last.data.is_object() { last.data.clone() } else { serde_json::json!({}) };
+    data["snapshot"] = serde_json::Value::String(snapshot.clone());
+    data["message"] = serde_json::Value::String(message.to_string());
+    data["squashed"] = serde_json::Value::Array(
+        run.iter()
+            .filter_map(|p| p.uuid.clone())
+            .map(serde_json::Value::String)
+            .collect(),
+    );
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+
+    // Reviews of the first patch carry over unless the reviewer also reviewed the last
+    if start != end {
+        conn.execute(
+            "INSERT OR IGNORE INTO patch_reviews (patch_uuid, reviewer_id, decision, reviewer_name, reviewed_at)
+             SELECT ?1, reviewer_id, decision, reviewer_name, reviewed_at FROM patch_reviews WHERE patch_uuid = ?2",
+            params![to_uuid, from_uuid],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    let interior: Vec<&Patch> = run[..run.len() - 1].iter().collect();
+    remove_patches(conn, &interior, Some(to_uuid))?;
+
+    conn.execute(
+        "UPDATE patches SET kind = 'Save', data = ?1, parent_uuid = ?2 WHERE id = ?3",
+        params![data.to_string(), first.parent_uuid, last.id],
+    )
+    .map_err(|e| e.to_string())?;
+    PatchStore::on(conn).delete_snapshots(last.id)?;
+    PatchStore::on(conn).add_snapshot(last.id, last.timestamp, snapshot.as_bytes())?;
+
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(to_uuid.to_string())
+}
+
+/// Delete patches rejected by at least one reviewer and accepted by none
+pub fn prune_rejected(conn: &Connection) -> Result<usize, String> {
+    ensure_schema(conn)?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT patch_uuid FROM patch_reviews GROUP BY patch_uuid
+             HAVING SUM(decision = 'rejected') > 0 AND SUM(decision = 'accepted') = 0",
+        )
+        .map_err(|e| e.to_string())?;
+    let rejected: HashSet<String> = stmt
+        .query_map([], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let patches = load_patches(conn)?;
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let mut pruned = 0;
+    for patch in &patches {
+        if patch.uuid.as_ref().is_some_and(|u| rejected.contains(u)) {
+            // Re-read the parent: an earlier pruned ancestor may have moved it
+            let parent: Option<String> = conn
+                .query_row("SELECT parent_uuid FROM patches WHERE id = ?1", params![patch.id], |row| row.get(0))
+                .map_err(|e| e.to_string())?;
+            remove_patches(conn, &[patch], parent.as_deref())?;
+            pruned += 1;
+        }
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(pruned)
+}
+
+/// Squash a contiguous run of patches into a single Save patch
+#[tauri::command]
+pub fn squash_patches(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    from_uuid: String,
+    to_uuid: String,
+    message: String,
+) -> Result<String, String> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get(&doc_id)
+        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+
+    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    squash_patch_range(&conn, &from_uuid, &to_uuid, &message)
+}
+
+/// Remove rejected patches from a document's history; returns how many were removed
+#[tauri::command]
+pub fn prune_rejected_patches(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+) -> Result<usize, String> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get(&doc_id)
+        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+
+    if !doc.history_path.exists() {
+        return Ok(0);
+    }
+
+    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    prune_rejected(&conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::patch_log::PatchInput;
+    use crate::patch_store::{insert_test_patch, test_patch};
+
+    fn insert_patch(conn: &Connection, uuid: &str, parent: Option<&str>, kind: &str, snapshot: &str) {
+        insert_test_patch(conn, PatchInput { kind: kind.to_string(), ..test_patch(uuid, parent, snapshot) });
+    }
+
+    fn review(conn: &Connection, uuid: &str, reviewer: &str, decision: &str) {
+        conn.execute(
+            "INSERT INTO patch_reviews (patch_uuid, reviewer_id, decision, reviewed_at) VALUES (?1, ?2, ?3, 1)",
+            params![uuid, reviewer, decision],
+        )
+        .unwrap();
+    }
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+        insert_patch(&conn, "p1", None, "Save", "a");
+        insert_patch(&conn, "p2", Some("p1"), "Save", "ab");
+        insert_patch(&conn, "p3", Some("p2"), "Save", "abc");
+        insert_patch(&conn, "p4", Some("p3"), "Save", "abcd");
+        insert_patch(&conn, "p5", Some("p4"), "Save", "abcde");
+        conn
+    }
+
+    #[test]
+    fn test_squash_patch_range() {
+        let conn = setup();
+        review(&conn, "p2", "bob", "accepted");
+        review(&conn, "p3", "bob", "rejected");
+        review(&conn, "p4", "carol", "accepted");
+
+        let uuid = squash_patch_range(&conn, "p2", "p4", "Tidy up").unwrap();
+        assert_eq!(uuid, "p4");
+
+        let patches = load_patches(&conn).unwrap();
+        let uuids: Vec<_> = patches.iter().filter_map(|p| p.uuid.clone()).collect();
+        assert_eq!(uuids, vec!["p1", "p4", "p5"]);
+
+        let squashed = &patches[1];
+        assert_eq!(squashed.kind, "Save");
+        assert_eq!(squashed.parent_uuid.as_deref(), Some("p1"));
+        assert_eq!(squashed.data["snapshot"], "abcd");
+        assert_eq!(squashed.data["message"], "Tidy up");
+        assert_eq!(patches[2].parent_uuid.as_deref(), Some("p4"));
+
+        let reviewers: Vec<String> = conn
+            .prepare("SELECT reviewer_id FROM patch_reviews WHERE patch_uuid = 'p4' ORDER BY reviewer_id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(reviewers, vec!["bob", "carol"]);
+
+        let snapshots: i64 = conn.query_row("SELECT COUNT(*) FROM snapshots", [], |row| row.get(0)).unwrap();
+        assert_eq!(snapshots, 3);
+    }
+
+    #[test]
+    fn test_squash_rejects_bad_ranges() {
+        let conn = setup();
+        assert!(squash_patch_range(&conn, "p4", "p2", "").is_err());
+        assert!(squash_patch_range(&conn, "p1", "missing", "").is_err());
+
+        insert_patch(&conn, "s1", None, SUGGESTION_KIND, "x");
+        insert_patch(&conn, "p6", Some("p5"), "Save", "abcdef");
+        assert!(squash_patch_range(&conn, "p5", "p6", "").is_err());
+    }
+
+    #[test]
+    fn test_prune_rejected() {
+        let conn = setup();
+        review(&conn, "p2", "bob", "rejected");
+        review(&conn, "p3", "bob", "rejected");
+        review(&conn, "p4", "bob", "rejected");
+        review(&conn, "p4", "carol", "accepted");
+
+        assert_eq!(prune_rejected(&conn).unwrap(), 2);
+
+        let patches = load_patches(&conn).unwrap();
+        let uuids: Vec<_> = patches.iter().filter_map(|p| p.uuid.clone()).collect();
+        assert_eq!(uuids, vec!["p1", "p4", "p5"]);
+        assert_eq!(patches[1].parent_uuid.as_deref(), Some("p1"));
+    }
+}
@@ -0,0 +1,327 @@
+// src-tauri/src/hooks.rs
+//! Shell commands and webhooks run on document events.
+//!
+//! A hook runs a command or POSTs to a URL when a document is saved, records
+//! a patch or finishes an export, for build scripts, chat notifications or
+//! rebuilding a site. Hooks are kept in the app settings, for every document
+//! or for one document by its UUID, and never in the .kmd file: opening a
+//! document someone sent must not run anything.
+//!
+//! Both kinds of hook get the same JSON payload, a command on its standard
+//! input and a webhook as the request body. Hooks run on a background
+//! thread; a failing hook is logged and never fails the save or export that
+//! triggered it.
+
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tauri::State;
+
+use crate::document_manager::DocumentManager;
+use crate::error::KorppiError;
+use crate::kmd::DocumentMeta;
+use crate::settings::{load_app_settings, save_app_settings};
+
+/// How long a webhook may take to answer
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Event a hook runs on
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookEvent {
+    Save,
+    PatchRecorded,
+    ExportFinished,
+}
+
+impl HookEvent {
+    /// Name as written in the settings
+    pub fn name(self) -> &'static str {
+        match self {
+            HookEvent::Save => "save",
+            HookEvent::PatchRecorded => "patch-recorded",
+            HookEvent::ExportFinished => "export-finished",
+        }
+    }
+}
+
+/// What a hook does
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum HookAction {
+    /// Run through the shell, in the folder of the document
+    Command { command: String },
+    /// POST the payload as JSON
+    Webhook { url: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Hook {
+    pub event: HookEvent,
+    #[serde(flatten)]
+    pub action: HookAction,
+    /// UUID of the document the hook belongs to; every document when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document: Option<String>,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// What a hook is told about the event
+#[derive(Debug, Clone, Serialize)]
+pub struct HookPayload {
+    pub event: HookEvent,
+    pub doc_id: String,
+    pub document_uuid: String,
+    pub title: String,
+    /// The .kmd file, once the document is saved
+    pub path: Option<PathBuf>,
+    /// Time the hook was fired (ms)
+    pub timestamp: i64,
+    /// Payload of the Tauri event: the saved `DocumentHandle`, the
+    /// `PatchRecordedEvent` or the finished `ExportRecord`
+    pub data: serde_json::Value,
+}
+
+impl HookPayload {
+    pub fn new<T: Serialize>(event: HookEvent, doc_id: &str, meta: &DocumentMeta, path: Option<&Path>, data: &T) -> Self {
+        Self {
+            event,
+            doc_id: doc_id.to_string(),
+            document_uuid: meta.uuid.clone(),
+            title: meta.title.clone(),
+            path: path.map(Path::to_path_buf),
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            data: serde_json::to_value(data).unwrap_or_default(),
+        }
+    }
+}
+
+pub fn validate_hook(hook: &Hook) -> Result<(), String> {
+    match &hook.action {
+        HookAction::Command { command } if command.trim().is_empty() => {
+            Err("Hook command must not be empty".to_string())
+        }
+        HookAction::Webhook { url } if !url.starts_with("http://") && !url.starts_with("https://") => {
+            Err(format!("Webhook URL must start with http:// or https://: {}", url))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Enabled hooks of an event, for every document or for this one
+pub fn hooks_for<'a>(hooks: &'a [Hook], event: HookEvent, document_uuid: &str) -> Vec<&'a Hook> {
+    hooks
+        .iter()
+        .filter(|hook| hook.enabled && hook.event == event)
+        .filter(|hook| hook.document.as_deref().is_none_or(|uuid| uuid == document_uuid))
+        .collect()
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.args(["/C", command]);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.args(["-c", command]);
+    shell
+}
+
+fn run_command(command: &str, payload: &HookPayload, body: &str) -> Result<String, String> {
+    let mut shell = shell(command);
+    shell
+        .env("KORPPI_EVENT", payload.event.name())
+        .env("KORPPI_DOCUMENT_UUID", &payload.document_uuid)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(path) = &payload.path {
+        shell.env("KORPPI_DOCUMENT", path);
+        if let Some(folder) = path.parent().filter(|p| p.is_dir()) {
+            shell.current_dir(folder);
+        }
+    }
+    let mut child = shell.spawn().map_err(|e| format!("Failed to run hook command: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that ignores its input may exit before reading it
+        if let Err(e) = stdin.write_all(body.as_bytes()) {
+            log::debug!("Hook command did not read the payload: {}", e);
+        }
+    }
+    let output = child.wait_with_output().map_err(|e| format!("Failed to run hook command: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Hook command failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn post_webhook(url: &str, body: &str) -> Result<String, String> {
+    match ureq::post(url)
+        .timeout(WEBHOOK_TIMEOUT)
+        .set("Content-Type", "application/json")
+        .send_string(body)
+    {
+        Ok(response) => Ok(format!("HTTP {}", response.status())),
+        Err(ureq::Error::Status(status, response)) => {
+            let body = response.into_string().unwrap_or_default();
+            Err(format!("Webhook answered with HTTP {}: {}", status, body.trim()))
+        }
+        Err(e) => Err(format!("Could not reach webhook {}: {}", url, e)),
+    }
+}
+
+/// Run one hook and wait for it; returns the command output or the HTTP
+/// status of the webhook
+pub fn run_hook(hook: &Hook, payload: &HookPayload) -> Result<String, String> {
+    let body = serde_json::to_string(payload).map_err(|e| e.to_string())?;
+    match &hook.action {
+        HookAction::Command { command } => run_command(command, payload, &body),
+        HookAction::Webhook { url } => post_webhook(url, &body),
+    }
+}
+
+/// Run the hooks of an event on a background thread
+pub fn fire_hooks(payload: HookPayload) {
+    let hooks = match load_app_settings() {
+        Ok(settings) => settings.hooks,
+        Err(e) => {
+            log::warn!("Cannot load hooks: {}", e);
+            return;
+        }
+    };
+    let hooks: Vec<Hook> = hooks_for(&hooks, payload.event, &payload.document_uuid).into_iter().cloned().collect();
+    if hooks.is_empty() {
+        return;
+    }
+    thread::spawn(move || {
+        for hook in &hooks {
+            if let Err(e) = run_hook(hook, &payload) {
+                log::warn!("Hook on {} of {} failed: {}", payload.event.name(), payload.doc_id, e);
+            }
+        }
+    });
+}
+
+fn document_meta(manager: &Mutex<DocumentManager>, id: &str) -> Result<(DocumentMeta, Option<PathBuf>), KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager
+        .documents
+        .get(id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
+    Ok((doc.meta.clone(), doc.handle.path.clone()))
+}
+
+/// Hooks that belong to one document, without the ones for every document
+#[tauri::command]
+pub fn get_document_hooks(manager: State<'_, Mutex<DocumentManager>>, id: String) -> Result<Vec<Hook>, KorppiError> {
+    let (meta, _) = document_meta(&manager, &id)?;
+    let settings = load_app_settings()?;
+    Ok(settings.hooks.into_iter().filter(|hook| hook.document.as_deref() == Some(meta.uuid.as_str())).collect())
+}
+
+/// Replace the hooks of one document; hooks for every document are set in
+/// the app settings
+#[tauri::command]
+pub fn set_document_hooks(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    hooks: Vec<Hook>,
+) -> Result<Vec<Hook>, KorppiError> {
+    let (meta, _) = document_meta(&manager, &id)?;
+    let hooks: Vec<Hook> = hooks.into_iter().map(|hook| Hook { document: Some(meta.uuid.clone()), ..hook }).collect();
+    for hook in &hooks {
+        validate_hook(hook).map_err(KorppiError::InvalidInput)?;
+    }
+    let mut settings = load_app_settings()?;
+    settings.hooks.retain(|hook| hook.document.as_deref() != Some(meta.uuid.as_str()));
+    settings.hooks.extend(hooks.iter().cloned());
+    save_app_settings(&settings)?;
+    Ok(hooks)
+}
+
+/// Run a hook now with a document's details, to try it out; the payload
+/// has no event data
+#[tauri::command]
+pub fn test_hook(manager: State<'_, Mutex<DocumentManager>>, id: String, hook: Hook) -> Result<String, KorppiError> {
+    validate_hook(&hook).map_err(KorppiError::InvalidInput)?;
+    let (meta, path) = document_meta(&manager, &id)?;
+    let payload = HookPayload::new(hook.event, &id, &meta, path.as_deref(), &serde_json::Value::Null);
+    run_hook(&hook, &payload).map_err(KorppiError::Other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command_hook(event: HookEvent, command: &str, document: Option<&str>) -> Hook {
+        Hook {
+            event,
+            action: HookAction::Command { command: command.to_string() },
+            document: document.map(str::to_string),
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_hooks_for_event_and_document() {
+        let mut disabled = command_hook(HookEvent::Save, "make", None);
+        disabled.enabled = false;
+        let hooks = vec![
+            command_hook(HookEvent::Save, "make", None),
+            command_hook(HookEvent::Save, "make site", Some("doc-a")),
+            command_hook(HookEvent::ExportFinished, "upload", None),
+            disabled,
+        ];
+        let commands = |event, uuid| -> Vec<HookAction> {
+            hooks_for(&hooks, event, uuid).into_iter().map(|h| h.action.clone()).collect()
+        };
+        assert_eq!(commands(HookEvent::Save, "doc-a").len(), 2);
+        assert_eq!(commands(HookEvent::Save, "doc-b"), vec![HookAction::Command { command: "make".to_string() }]);
+        assert!(commands(HookEvent::PatchRecorded, "doc-a").is_empty());
+
+        let json = serde_json::json!({ "event": "export-finished", "type": "webhook", "url": "https://example.org/hook" });
+        let hook: Hook = serde_json::from_value(json).unwrap();
+        assert_eq!(hook.action, HookAction::Webhook { url: "https://example.org/hook".to_string() });
+        assert!(hook.enabled);
+
+        assert!(validate_hook(&command_hook(HookEvent::Save, " ", None)).is_err());
+        let ftp = Hook { action: HookAction::Webhook { url: "ftp://example.org".to_string() }, ..hook };
+        assert!(validate_hook(&ftp).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_gets_payload() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let document = dir.path().join("paper.kmd");
+        std::fs::write(&document, b"").unwrap();
+        let meta = DocumentMeta { uuid: "doc-a".to_string(), title: "Paper".to_string(), ..DocumentMeta::default() };
+        let payload = HookPayload::new(HookEvent::Save, "1", &meta, Some(&document), &serde_json::json!({ "id": "1" }));
+
+        let hook = command_hook(HookEvent::Save, "cat > payload.json && echo \"$KORPPI_EVENT\"", None);
+        assert_eq!(run_hook(&hook, &payload).unwrap(), "save");
+        let written: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.path().join("payload.json")).unwrap()).unwrap();
+        assert_eq!(written["document_uuid"], "doc-a");
+        assert_eq!(written["data"]["id"], "1");
+
+        assert!(run_hook(&command_hook(HookEvent::Save, "exit 3", None), &payload).is_err());
+    }
+}
@@ -0,0 +1,948 @@
+// src-tauri/src/html_import.rs
+//! Import of HTML files, mostly Google Docs "Web page" exports.
+//!
+//! The HTML is converted with pandoc when it is available and with a small
+//! native converter otherwise. Both see the same prepared HTML: formatting
+//! Google Docs expresses through CSS classes becomes `<strong>`/`<em>`/`<s>`,
+//! redirect links are unwrapped and comments are cut out and replaced by
+//! markers, so they can be stored as Korppi comments on the text before the
+//! marker. `<ins>`/`<del>` edits become a suggestion against the original
+//! text. Images are copied into the document's `assets/` directory.
+
+use base64::Engine;
+use chrono::Utc;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use regex::{Captures, Regex};
+use rusqlite::{params, Connection};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use crate::comments::init_comments_table;
+use crate::db_utils::ensure_schema;
+use crate::document_manager::ASSETS_DIR;
+use crate::pandoc::pandoc_command;
+use crate::patch_log::PatchInput;
+use crate::patch_store::PatchStore;
+use crate::suggestions::SUGGESTION_KIND;
+
+/// Author of imported comments and suggestions; exports do not name them
+pub const IMPORT_AUTHOR: &str = "Imported";
+
+/// Pandoc markdown without the attribute and raw HTML syntax Google Docs
+/// markup would otherwise leave everywhere
+const PANDOC_MARKDOWN: &str =
+    "markdown-raw_html-native_divs-native_spans-bracketed_spans-fenced_divs-link_attributes-header_attributes";
+
+/// Elements whose content is never part of the document
+const SKIPPED_ELEMENTS: &[&str] = &["head", "title", "script", "style", "noscript", "template"];
+
+/// Elements that have no end tag in HTML
+const VOID_ELEMENTS: &[&str] = &["br", "img", "hr", "meta", "link", "input", "col", "wbr", "area", "base", "source"];
+
+/// Elements that start a new paragraph
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p", "div", "section", "article", "header", "footer", "main", "nav", "aside", "figure", "figcaption",
+    "dd", "dt", "address", "body",
+];
+
+/// Stands in for a hard line break until a block is written
+const HARD_BREAK: char = '\0';
+
+/// A comment thread found in the HTML
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedComment {
+    /// Text the comment was attached to, as it appears in the markdown
+    pub selected_text: String,
+    pub content: String,
+    pub replies: Vec<String>,
+}
+
+/// Converted HTML with the annotations that could be recovered
+#[derive(Debug, Clone)]
+pub struct HtmlImport {
+    pub markdown: String,
+    /// The text with `<ins>`/`<del>` edits applied, when there were any
+    pub suggested: Option<String>,
+    pub comments: Vec<ImportedComment>,
+}
+
+/// Convert an HTML file to markdown
+///
+/// Images are written to `temp_dir/assets/`. Pandoc is tried first when
+/// `use_pandoc` is set; the native converter is the fallback.
+pub fn import_html(file_path: &Path, temp_dir: &Path, use_pandoc: bool) -> Result<HtmlImport, String> {
+    let raw = fs::read(file_path).map_err(|e| format!("Failed to read HTML file: {}", e))?;
+    let html = String::from_utf8_lossy(&raw);
+    let source_dir = file_path.parent().unwrap_or_else(|| Path::new("."));
+
+    let html = prepare_html(&html);
+    let (html, bodies) = extract_comment_bodies(&html);
+    let html = mark_comment_references(&html);
+    let (original, suggested) = split_edits(&html);
+
+    let mut assets = AssetStore::new(source_dir, temp_dir);
+    let mut convert = |html: &str| -> Result<String, String> {
+        if use_pandoc {
+            if let Ok(markdown) = convert_with_pandoc(html, source_dir, temp_dir) {
+                return Ok(markdown);
+            }
+        }
+        html_to_markdown(html, &mut assets)
+    };
+
+    let markdown = convert(&original)?;
+    let suggested = match suggested {
+        Some(html) => Some(remove_comment_markers(&convert(&html)?)),
+        None => None,
+    };
+
+    let comments = bodies
+        .into_iter()
+        .map(|(id, mut paragraphs)| {
+            let content = paragraphs.remove(0);
+            ImportedComment {
+                selected_text: commented_text(&markdown, id),
+                content,
+                replies: paragraphs,
+            }
+        })
+        .collect();
+
+    Ok(HtmlImport {
+        markdown: remove_comment_markers(&markdown),
+        suggested: suggested.filter(|s| *s != remove_comment_markers(&markdown)),
+        comments,
+    })
+}
+
+/// Store the comments and suggestion of an import in a history database
+///
+/// Comments get empty anchors, so the editor finds them by their text.
+pub fn store_annotations(history_path: &Path, import: &HtmlImport) -> Result<(), String> {
+    if import.comments.is_empty() && import.suggested.is_none() {
+        return Ok(());
+    }
+
+    let conn = Connection::open(history_path).map_err(|e| e.to_string())?;
+    ensure_schema(&conn)?;
+    init_comments_table(&conn)?;
+    let timestamp = Utc::now().timestamp_millis();
+
+    let insert_comment = |selected_text: &str, content: &str, parent_id: Option<i64>| {
+        conn.execute(
+            "INSERT INTO comments (timestamp, author, author_color, start_anchor, end_anchor, selected_text, content, parent_id)
+             VALUES (?1, ?2, NULL, '', '', ?3, ?4, ?5)",
+            params![timestamp, IMPORT_AUTHOR, selected_text, content, parent_id],
+        )
+        .map(|_| conn.last_insert_rowid())
+        .map_err(|e| e.to_string())
+    };
+    for comment in &import.comments {
+        let id = insert_comment(&comment.selected_text, &comment.content, None)?;
+        for reply in &comment.replies {
+            insert_comment(&comment.selected_text, reply, Some(id))?;
+        }
+    }
+
+    if let Some(suggested) = &import.suggested {
+        let suggestion = PatchInput {
+            timestamp,
+            author: IMPORT_AUTHOR.to_string(),
+            kind: SUGGESTION_KIND.to_string(),
+            data: serde_json::json!({ "snapshot": suggested, "authorName": IMPORT_AUTHOR }),
+            uuid: None,
+            parent_uuid: None,
+        };
+        PatchStore::on(&conn).insert_patch(&suggestion, None)?;
+    }
+
+    Ok(())
+}
+
+/// Convert prepared HTML with pandoc, extracting images to `assets/`
+fn convert_with_pandoc(html: &str, source_dir: &Path, temp_dir: &Path) -> Result<String, String> {
+    let mut child = pandoc_command()
+        .current_dir(temp_dir)
+        .arg("-f")
+        .arg("html")
+        .arg("-t")
+        .arg(PANDOC_MARKDOWN)
+        .arg("--wrap=none")
+        .arg(format!("--extract-media={}", ASSETS_DIR))
+        .arg("--resource-path")
+        .arg(source_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run pandoc: {}", e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(html.as_bytes()).map_err(|e| format!("Failed to write to pandoc: {}", e))?;
+    }
+    let output = child.wait_with_output().map_err(|e| format!("Failed to run pandoc: {}", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("Pandoc conversion failed: {}", stderr));
+    }
+
+    String::from_utf8(output.stdout).map_err(|e| format!("Invalid UTF-8 in pandoc output: {}", e))
+}
+
+/// Formatting carried by a CSS rule or style attribute
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct CssFormat {
+    bold: bool,
+    italic: bool,
+    strike: bool,
+}
+
+impl CssFormat {
+    fn parse(declarations: &str) -> Self {
+        let declarations = declarations.to_lowercase().replace(char::is_whitespace, "");
+        CssFormat {
+            bold: declarations.contains("font-weight:700") || declarations.contains("font-weight:bold"),
+            italic: declarations.contains("font-style:italic"),
+            strike: declarations.contains("line-through"),
+        }
+    }
+
+    fn merge(self, other: CssFormat) -> Self {
+        CssFormat {
+            bold: self.bold || other.bold,
+            italic: self.italic || other.italic,
+            strike: self.strike || other.strike,
+        }
+    }
+}
+
+/// Value of an attribute in the attribute part of a tag
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let re = Regex::new(&format!(r#"(?i)\b{}\s*=\s*"([^"]*)""#, name)).ok()?;
+    re.captures(attributes).and_then(|c| c.get(1)).map(|m| m.as_str())
+}
+
+/// Turn CSS formatting into elements, unwrap redirect links and drop
+/// scripts and stylesheets
+fn prepare_html(html: &str) -> String {
+    let rule = Regex::new(r"\.([A-Za-z_][\w-]*)\s*\{([^}]*)\}").unwrap();
+    let styles = Regex::new(r"(?is)<style\b[^>]*>(.*?)</style>").unwrap();
+    let mut classes: HashMap<String, CssFormat> = HashMap::new();
+    for sheet in styles.captures_iter(html) {
+        for r in rule.captures_iter(&sheet[1]) {
+            let format = CssFormat::parse(&r[2]);
+            if format != CssFormat::default() {
+                let entry = classes.entry(r[1].to_string()).or_default();
+                *entry = entry.merge(format);
+            }
+        }
+    }
+
+    let html = styles.replace_all(html, "");
+    let html = Regex::new(r"(?is)<script\b[^>]*>.*?</script>").unwrap().replace_all(&html, "");
+
+    // Google Docs sends every link through https://www.google.com/url?q=...
+    let redirect = Regex::new(r#"href="https?://www\.google\.com/url\?q=([^"&]*)[^"]*""#).unwrap();
+    let html = redirect.replace_all(&html, |c: &Captures| {
+        let target = percent_decode(&decode_entities(&c[1]));
+        format!("href=\"{}\"", target.replace('&', "&amp;").replace('"', "&quot;"))
+    });
+
+    let span = Regex::new(r"(?is)<span\b([^>]*)>(.*?)</span>").unwrap();
+    span.replace_all(&html, |c: &Captures| {
+        let attributes = &c[1];
+        let mut format = attribute(attributes, "style").map(CssFormat::parse).unwrap_or_default();
+        for class in attribute(attributes, "class").unwrap_or("").split_whitespace() {
+            if let Some(class_format) = classes.get(class) {
+                format = format.merge(*class_format);
+            }
+        }
+        let (mut open, mut close) = (String::new(), String::new());
+        for (set, tag) in [(format.bold, "strong"), (format.italic, "em"), (format.strike, "s")] {
+            if set {
+                open.push_str(&format!("<{}>", tag));
+                close.insert_str(0, &format!("</{}>", tag));
+            }
+        }
+        format!("{}{}{}", open, &c[2], close)
+    })
+    .into_owned()
+}
+
+/// Marker left in the text where a comment was anchored
+fn comment_marker(id: u32) -> String {
+    format!("KORPPICOMMENT{}X", id)
+}
+
+fn remove_comment_markers(markdown: &str) -> String {
+    Regex::new(r"KORPPICOMMENT\d+X").unwrap().replace_all(markdown, "").into_owned()
+}
+
+/// Cut out the comment bodies Google Docs appends to the document
+///
+/// Returns the remaining HTML and the paragraphs of each comment (the
+/// comment first, then its replies) by comment number.
+fn extract_comment_bodies(html: &str) -> (String, Vec<(u32, Vec<String>)>) {
+    let body = Regex::new(r#"(?is)<div\b[^>]*>\s*<p\b[^>]*>\s*<a\b[^>]*\bid="cmnt(\d+)"[^>]*>.*?</a>(.*?)</div>"#).unwrap();
+    let paragraph_end = Regex::new(r"(?i)</p>").unwrap();
+
+    let mut comments = Vec::new();
+    for c in body.captures_iter(html) {
+        let Ok(id) = c[1].parse::<u32>() else {
+            continue;
+        };
+        let paragraphs: Vec<String> = paragraph_end
+            .split(&c[2])
+            .map(plain_text)
+            .filter(|p| !p.is_empty())
+            .collect();
+        if !paragraphs.is_empty() {
+            comments.push((id, paragraphs));
+        }
+    }
+    comments.sort_by_key(|(id, _)| *id);
+
+    (body.replace_all(html, "").into_owned(), comments)
+}
+
+/// Replace the `[a]` comment references with markers
+fn mark_comment_references(html: &str) -> String {
+    let reference = Regex::new(r##"(?is)(?:<sup\b[^>]*>\s*)?<a\b[^>]*href="#cmnt(\d+)"[^>]*>.*?</a>(?:\s*</sup>)?"##).unwrap();
+    reference
+        .replace_all(html, |c: &Captures| c[1].parse().map(comment_marker).unwrap_or_default())
+        .into_owned()
+}
+
+/// Split `<ins>`/`<del>` edits into the original and the edited HTML
+fn split_edits(html: &str) -> (String, Option<String>) {
+    let ins = Regex::new(r"(?is)<ins\b[^>]*>(.*?)</ins>").unwrap();
+    let del = Regex::new(r"(?is)<del\b[^>]*>(.*?)</del>").unwrap();
+    if !ins.is_match(html) && !del.is_match(html) {
+        return (html.to_string(), None);
+    }
+
+    let original = del.replace_all(&ins.replace_all(html, ""), "$1").into_owned();
+    let edited = ins.replace_all(&del.replace_all(html, ""), "$1").into_owned();
+    (original, Some(edited))
+}
+
+/// The text a comment marker follows: the rest of its sentence
+fn commented_text(markdown: &str, id: u32) -> String {
+    let marker = comment_marker(id);
+    let Some(position) = markdown.find(&marker) else {
+        return String::new();
+    };
+    let line_start = markdown[..position].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = remove_comment_markers(&markdown[line_start..position]);
+    let line = line.trim_start_matches(|c: char| c == '#' || c == '>' || c == '-' || c.is_whitespace());
+    let line = line.trim_end();
+
+    let sentence_start = [". ", "! ", "? "]
+        .iter()
+        .filter_map(|end| line.rfind(end).map(|i| i + end.len()))
+        .max()
+        .unwrap_or(0);
+    line[sentence_start..].trim().to_string()
+}
+
+/// Decode HTML character references
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let reference = Regex::new(r"&(#[xX][0-9a-fA-F]+|#[0-9]+|[A-Za-z][A-Za-z0-9]*);").unwrap();
+    reference
+        .replace_all(text, |c: &Captures| {
+            let name = &c[1];
+            let decoded = if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32).map(String::from)
+            } else if let Some(decimal) = name.strip_prefix('#') {
+                decimal.parse().ok().and_then(char::from_u32).map(String::from)
+            } else {
+                match name {
+                    "amp" => Some("&"),
+                    "lt" => Some("<"),
+                    "gt" => Some(">"),
+                    "quot" => Some("\""),
+                    "apos" => Some("'"),
+                    "nbsp" => Some("\u{a0}"),
+                    "ndash" => Some("\u{2013}"),
+                    "mdash" => Some("\u{2014}"),
+                    "hellip" => Some("\u{2026}"),
+                    "lsquo" => Some("\u{2018}"),
+                    "rsquo" => Some("\u{2019}"),
+                    "ldquo" => Some("\u{201c}"),
+                    "rdquo" => Some("\u{201d}"),
+                    "copy" => Some("\u{a9}"),
+                    _ => None,
+                }
+                .map(String::from)
+            };
+            decoded.unwrap_or_else(|| c[0].to_string())
+        })
+        .into_owned()
+}
+
+/// Decode %XX escapes in a URL or path
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Text of an HTML fragment with tags removed and whitespace collapsed
+fn plain_text(fragment: &str) -> String {
+    let text = Regex::new(r"<[^>]*>").unwrap().replace_all(fragment, " ");
+    decode_entities(&text).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Escape characters that markdown would read as markup
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Images copied into the document, by their `src`
+struct AssetStore<'a> {
+    source_dir: &'a Path,
+    assets_dir: PathBuf,
+    stored: HashMap<String, String>,
+}
+
+impl<'a> AssetStore<'a> {
+    fn new(source_dir: &'a Path, temp_dir: &Path) -> Self {
+        AssetStore {
+            source_dir,
+            assets_dir: temp_dir.join(ASSETS_DIR),
+            stored: HashMap::new(),
+        }
+    }
+
+    /// Copy an inline or local image into assets/ and return its markdown
+    /// path; remote and missing images keep their `src`
+    fn store(&mut self, src: &str) -> String {
+        if let Some(path) = self.stored.get(src) {
+            return path.clone();
+        }
+        let path = self.copy(src).unwrap_or_else(|| src.to_string());
+        self.stored.insert(src.to_string(), path.clone());
+        path
+    }
+
+    fn copy(&self, src: &str) -> Option<String> {
+        let (name, bytes) = if let Some(data) = src.strip_prefix("data:") {
+            let (header, payload) = data.split_once(',')?;
+            let mime = header.strip_suffix(";base64")?;
+            let extension = match mime {
+                "image/png" => "png",
+                "image/jpeg" | "image/jpg" => "jpg",
+                "image/gif" => "gif",
+                "image/svg+xml" => "svg",
+                "image/webp" => "webp",
+                _ => return None,
+            };
+            let payload: String = payload.chars().filter(|c| !c.is_whitespace()).collect();
+            let bytes = base64::engine::general_purpose::STANDARD.decode(payload).ok()?;
+            (format!("image{}.{}", self.stored.len() + 1, extension), bytes)
+        } else if src.contains("://") {
+            return None;
+        } else {
+            let path = self.source_dir.join(percent_decode(src));
+            let bytes = fs::read(&path).ok()?;
+            (path.file_name()?.to_string_lossy().to_string(), bytes)
+        };
+
+        let name = if self.assets_dir.join(&name).exists() {
+            format!("{}-{}", self.stored.len() + 1, name)
+        } else {
+            name
+        };
+        fs::create_dir_all(&self.assets_dir).ok()?;
+        fs::write(self.assets_dir.join(&name), bytes).ok()?;
+        Some(format!("{}/{}", ASSETS_DIR, name))
+    }
+}
+
+/// How an inline element is written once it ends
+enum InlineKind {
+    /// Emphasis delimiters, repeated in reverse after the content
+    Marks(&'static str),
+    Link(String),
+}
+
+struct OpenInline {
+    tag: String,
+    kind: InlineKind,
+    /// Where the content starts in the current text
+    start: usize,
+    /// Block the element was opened in
+    block: usize,
+}
+
+struct List {
+    ordered: bool,
+    next: usize,
+    depth: usize,
+}
+
+/// Writes markdown blocks while walking the HTML
+struct MarkdownWriter<'a, 'b> {
+    assets: &'a mut AssetStore<'b>,
+    blocks: Vec<(String, bool)>,
+    text: String,
+    block_count: usize,
+    inline: Vec<OpenInline>,
+    lists: Vec<List>,
+    /// Prefixes of the first and following lines of the next list item
+    item_marker: Option<(String, String)>,
+    /// Prefix of further blocks of the current list item
+    item_continuation: Option<String>,
+    quote_depth: usize,
+    heading: Option<usize>,
+    pre: Option<String>,
+    table: Option<Vec<Vec<String>>>,
+    row: Option<Vec<String>>,
+    skip_depth: usize,
+}
+
+impl<'a, 'b> MarkdownWriter<'a, 'b> {
+    fn new(assets: &'a mut AssetStore<'b>) -> Self {
+        MarkdownWriter {
+            assets,
+            blocks: Vec::new(),
+            text: String::new(),
+            block_count: 0,
+            inline: Vec::new(),
+            lists: Vec::new(),
+            item_marker: None,
+            item_continuation: None,
+            quote_depth: 0,
+            heading: None,
+            pre: None,
+            table: None,
+            row: None,
+            skip_depth: 0,
+        }
+    }
+
+    fn push_text(&mut self, raw: &str) {
+        if self.skip_depth > 0 {
+            return;
+        }
+        let text = decode_entities(raw);
+        if let Some(pre) = &mut self.pre {
+            pre.push_str(&text);
+            return;
+        }
+        for c in text.chars() {
+            if c.is_whitespace() {
+                if !self.text.is_empty() && !self.text.ends_with([' ', HARD_BREAK]) {
+                    self.text.push(' ');
+                }
+            } else if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']') {
+                self.text.push('\\');
+                self.text.push(c);
+            } else {
+                self.text.push(c);
+            }
+        }
+    }
+
+    /// Start a new inline context, forgetting open inline elements
+    fn reset_text(&mut self) -> String {
+        self.block_count += 1;
+        self.inline.clear();
+        std::mem::take(&mut self.text)
+    }
+
+    fn end_block(&mut self) {
+        if self.table.is_some() {
+            if !self.text.is_empty() && !self.text.ends_with(' ') {
+                self.text.push(' ');
+            }
+            return;
+        }
+        let text = self.reset_text();
+        let text = text.trim_matches(|c: char| c.is_whitespace() || c == HARD_BREAK);
+        if text.is_empty() {
+            return;
+        }
+        let text = text.replace(HARD_BREAK, "\\\n");
+        let text = match self.heading {
+            Some(level) => format!("{} {}", "#".repeat(level), text.replace("\\\n", " ")),
+            None => text,
+        };
+        self.push_block(&text);
+    }
+
+    fn push_block(&mut self, content: &str) {
+        let quote = "> ".repeat(self.quote_depth);
+        let (first, rest, list_item) = match self.item_marker.take() {
+            Some((first, rest)) => {
+                self.item_continuation = Some(rest.clone());
+                (first, rest, true)
+            }
+            None => {
+                let continuation = self.item_continuation.clone().unwrap_or_default();
+                (continuation.clone(), continuation, false)
+            }
+        };
+
+        let mut block = String::new();
+        for (i, line) in content.split('\n').enumerate() {
+            if i > 0 {
+                block.push('\n');
+            }
+            block.push_str(&quote);
+            block.push_str(if i == 0 { &first } else { &rest });
+            block.push_str(line);
+        }
+        self.blocks.push((block, list_item));
+    }
+
+    fn open_inline(&mut self, tag: &str, kind: InlineKind) {
+        self.inline.push(OpenInline {
+            tag: tag.to_string(),
+            kind,
+            start: self.text.len(),
+            block: self.block_count,
+        });
+    }
+
+    fn close_inline(&mut self, tag: &str) {
+        let Some(index) = self.inline.iter().rposition(|open| open.tag == tag) else {
+            return;
+        };
+        while self.inline.len() > index {
+            let open = self.inline.pop().expect("index is in bounds");
+            if open.block != self.block_count || open.start > self.text.len() {
+                continue;
+            }
+            let inner = self.text.split_off(open.start);
+            let content = inner.trim_matches(|c: char| c.is_whitespace() || c == HARD_BREAK);
+            if content.is_empty() {
+                self.text.push_str(&inner);
+                continue;
+            }
+            let leading = &inner[..inner.len() - inner.trim_start().len()];
+            let trailing = &inner[inner.trim_end().len()..];
+            let written = match &open.kind {
+                InlineKind::Marks("") => content.to_string(),
+                InlineKind::Marks(marks) => format!("{}{}{}", marks, content, marks.chars().rev().collect::<String>()),
+                InlineKind::Link(href) => format!("[{}]({})", content, href.replace(' ', "%20").replace(')', "%29")),
+            };
+            self.text.push_str(leading);
+            self.text.push_str(&written);
+            self.text.push_str(trailing);
+        }
+    }
+
+    fn start(&mut self, element: &BytesStart, has_end: bool) {
+        let name = String::from_utf8_lossy(element.local_name().as_ref()).to_lowercase();
+        if SKIPPED_ELEMENTS.contains(&name.as_str()) {
+            if has_end {
+                self.skip_depth += 1;
+            }
+            return;
+        }
+        if self.skip_depth > 0 {
+            return;
+        }
+
+        let attributes: HashMap<String, String> = element
+            .html_attributes()
+            .flatten()
+            .map(|a| {
+                (
+                    String::from_utf8_lossy(a.key.local_name().as_ref()).to_lowercase(),
+                    decode_entities(&String::from_utf8_lossy(&a.value)),
+                )
+            })
+            .collect();
+        let has_end = has_end && !VOID_ELEMENTS.contains(&name.as_str());
+
+        match name.as_str() {
+            n if BLOCK_ELEMENTS.contains(&n) => self.end_block(),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.end_block();
+                self.heading = name[1..].parse().ok();
+            }
+            "br" => match &mut self.pre {
+                Some(pre) => pre.push('\n'),
+                None if self.table.is_some() => self.push_text(" "),
+                None => self.text.push(HARD_BREAK),
+            },
+            "hr" => {
+                self.end_block();
+                self.push_block("---");
+            }
+            "pre" => {
+                self.end_block();
+                self.pre = Some(String::new());
+            }
+            "img" => {
+                let Some(src) = attributes.get("src").filter(|s| !s.is_empty()) else {
+                    return;
+                };
+                let path = self.assets.store(src);
+                let alt = escape_markdown(attributes.get("alt").map(String::as_str).unwrap_or(""));
+                self.text.push_str(&format!("![{}]({})", alt, path.replace(' ', "%20")));
+            }
+            "ul" | "ol" => {
+                self.end_block();
+                // Google Docs nests lists through a level in the class name
+                let level = attributes
+                    .get("class")
+                    .and_then(|class| Regex::new(r"lst-kix_\w+-(\d+)").unwrap().captures(class).and_then(|c| c[1].parse::<usize>().ok()));
+                let depth = match level {
+                    Some(level) => level + 1,
+                    None => self.lists.last().map(|l| l.depth + 1).unwrap_or(1),
+                };
+                let next = attributes.get("start").and_then(|s| s.parse().ok()).unwrap_or(1);
+                self.lists.push(List { ordered: name == "ol", next, depth });
+            }
+            "li" => {
+                self.end_block();
+                let (marker, depth) = match self.lists.last_mut() {
+                    Some(list) if list.ordered => {
+                        list.next += 1;
+                        (format!("{}. ", list.next - 1), list.depth)
+                    }
+                    Some(list) => ("- ".to_string(), list.depth),
+                    None => ("- ".to_string(), 1),
+                };
+                let indent = "    ".repeat(depth - 1);
+                self.item_marker = Some((format!("{}{}", indent, marker), format!("{}{}", indent, " ".repeat(marker.len()))));
+            }
+            "blockquote" => {
+                self.end_block();
+                self.quote_depth += 1;
+            }
+            "table" => {
+                self.end_block();
+                self.table = Some(Vec::new());
+            }
+            "tr" => {
+                self.finish_row();
+                self.row = Some(Vec::new());
+            }
+            "td" | "th" => {
+                self.reset_text();
+            }
+            _ if !has_end || self.pre.is_some() => {}
+            "b" | "strong" => {
+                let normal = attributes
+                    .get("style")
+                    .map(|s| s.replace(' ', "").contains("font-weight:normal") || s.replace(' ', "").contains("font-weight:400"))
+                    .unwrap_or(false);
+                let marks = if normal || self.heading.is_some() { "" } else { "**" };
+                self.open_inline(&name, InlineKind::Marks(marks));
+            }
+            "i" | "em" | "cite" | "dfn" => self.open_inline(&name, InlineKind::Marks("*")),
+            "s" | "strike" => self.open_inline(&name, InlineKind::Marks("~~")),
+            "code" | "kbd" | "samp" | "tt" => self.open_inline(&name, InlineKind::Marks("`")),
+            "a" => match attributes.get("href").filter(|h| !h.is_empty() && !h.starts_with('#')) {
+                Some(href) => self.open_inline(&name, InlineKind::Link(href.clone())),
+                None => self.open_inline(&name, InlineKind::Marks("")),
+            },
+            _ => self.open_inline(&name, InlineKind::Marks("")),
+        }
+    }
+
+    fn end(&mut self, name: &str) {
+        let name = name.to_lowercase();
+        if SKIPPED_ELEMENTS.contains(&name.as_str()) {
+            self.skip_depth = self.skip_depth.saturating_sub(1);
+            return;
+        }
+        if self.skip_depth > 0 {
+            return;
+        }
+
+        match name.as_str() {
+            n if BLOCK_ELEMENTS.contains(&n) => self.end_block(),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.end_block();
+                self.heading = None;
+            }
+            "pre" => {
+                if let Some(code) = self.pre.take() {
+                    self.push_block(&format!("```\n{}\n```", code.trim_matches('\n')));
+                }
+            }
+            "ul" | "ol" => {
+                self.end_block();
+                self.lists.pop();
+                self.item_marker = None;
+                self.item_continuation = None;
+            }
+            "li" => {
+                self.end_block();
+                self.item_continuation = None;
+            }
+            "blockquote" => {
+                self.end_block();
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+            }
+            "td" | "th" => {
+                let cell = self.reset_text();
+                let cell = cell.replace(HARD_BREAK, " ").trim().replace('|', "\\|");
+                if let Some(row) = &mut self.row {
+                    row.push(cell);
+                }
+            }
+            "tr" => self.finish_row(),
+            "table" => {
+                self.finish_row();
+                if let Some(rows) = self.table.take() {
+                    if let Some(table) = table_markdown(&rows) {
+                        self.push_block(&table);
+                    }
+                }
+            }
+            _ => self.close_inline(&name),
+        }
+    }
+
+    fn finish_row(&mut self) {
+        if let (Some(row), Some(table)) = (self.row.take(), &mut self.table) {
+            if !row.is_empty() {
+                table.push(row);
+            }
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.end_block();
+        let mut markdown = String::new();
+        let mut previous_item = false;
+        for (i, (block, list_item)) in self.blocks.iter().enumerate() {
+            if i > 0 {
+                markdown.push_str(if previous_item && *list_item { "\n" } else { "\n\n" });
+            }
+            markdown.push_str(block);
+            previous_item = *list_item;
+        }
+        markdown.push('\n');
+        markdown
+    }
+}
+
+/// Pipe table with the first row as header
+fn table_markdown(rows: &[Vec<String>]) -> Option<String> {
+    let width = rows.iter().map(Vec::len).max().filter(|w| *w > 0)?;
+    let line = |cells: &[String]| {
+        let mut line = String::from("|");
+        for i in 0..width {
+            line.push(' ');
+            line.push_str(cells.get(i).map(String::as_str).unwrap_or(""));
+            line.push_str(" |");
+        }
+        line
+    };
+
+    let mut lines = vec![line(&rows[0]), format!("|{}", " --- |".repeat(width))];
+    lines.extend(rows[1..].iter().map(|row| line(row)));
+    Some(lines.join("\n"))
+}
+
+/// Convert HTML to markdown without pandoc
+fn html_to_markdown(html: &str, assets: &mut AssetStore) -> Result<String, String> {
+    let mut reader = Reader::from_str(html);
+    reader.check_end_names(false);
+
+    let mut writer = MarkdownWriter::new(assets);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(ref e)) => writer.start(e, true),
+            Ok(Event::Empty(ref e)) => writer.start(e, false),
+            Ok(Event::End(ref e)) => writer.end(&String::from_utf8_lossy(e.local_name().as_ref())),
+            Ok(Event::Text(ref e)) => writer.push_text(&String::from_utf8_lossy(e.as_ref())),
+            Ok(Event::CData(ref e)) => writer.push_text(&String::from_utf8_lossy(e.as_ref())),
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(format!("Error parsing HTML: {}", e)),
+            _ => {}
+        }
+    }
+
+    Ok(writer.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOOGLE_DOCS_EXPORT: &str = r##"<html><head><meta content="text/html; charset=UTF-8" http-equiv="content-type"><style type="text/css">.c1{font-weight:700}.c2{font-style:italic}</style></head><body class="c5 doc-content"><h1 class="c3" id="h.abc"><span class="c1">Results</span></h1><p class="c4"><span>The effect was </span><span class="c1">large</span><span>. It held in every sample</span><sup><a href="#cmnt1" id="cmnt_ref1">[a]</a></sup><span>&nbsp;and </span><span class="c2">more</span><span>.</span></p><ul class="c6 lst-kix_x1-0 start"><li class="c7 li-bullet-0"><span>First</span></li></ul><ul class="c6 lst-kix_x1-1 start"><li class="c7 li-bullet-0"><span>Nested</span></li></ul><p><span>See </span><a href="https://www.google.com/url?q=https://example.org/a%3Fb%3D1&amp;sa=D&amp;ust=1"><span>the site</span></a></p><p><img alt="" src="data:image/png;base64,iVBORw0KGgo=" title=""></p><div class="c8"><p class="c9"><a href="#cmnt_ref1" id="cmnt1">[a]</a><span class="c10">Which samples?</span></p><p class="c9"><span class="c10">All four.</span></p></div></body></html>"##;
+
+    fn convert(html: &str) -> (HtmlImport, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.html");
+        fs::write(&path, html).unwrap();
+        let temp_dir = dir.path().join("doc");
+        fs::create_dir_all(&temp_dir).unwrap();
+        (import_html(&path, &temp_dir, false).unwrap(), dir)
+    }
+
+    #[test]
+    fn test_google_docs_export() {
+        let (import, dir) = convert(GOOGLE_DOCS_EXPORT);
+
+        assert_eq!(
+            import.markdown,
+            "# Results\n\n\
+             The effect was **large**. It held in every sample and *more*.\n\n\
+             - First\n    - Nested\n\n\
+             See [the site](https://example.org/a?b=1)\n\n\
+             ![](assets/image1.png)\n"
+        );
+        assert!(dir.path().join("doc/assets/image1.png").exists());
+        assert_eq!(import.suggested, None);
+        assert_eq!(
+            import.comments,
+            vec![ImportedComment {
+                selected_text: "It held in every sample".to_string(),
+                content: "Which samples?".to_string(),
+                replies: vec!["All four.".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_edits_become_suggestion() {
+        let html = "<p>One <del>two</del><ins>three</ins> four</p><ol><li>a</li><li>b<br>c</li></ol><table><tr><th>x</th><th>y</th></tr><tr><td><p>1</p></td><td>2</td></tr></table>";
+        let (import, dir) = convert(html);
+
+        assert_eq!(import.markdown, "One two four\n\n1. a\n2. b\\\n   c\n\n| x | y |\n| --- | --- |\n| 1 | 2 |\n");
+        assert!(import.suggested.as_deref().unwrap().starts_with("One three four\n"));
+
+        let history = dir.path().join("doc/history.sqlite");
+        store_annotations(&history, &import).unwrap();
+        let conn = Connection::open(&history).unwrap();
+        let kind: String = conn.query_row("SELECT kind FROM patches", [], |row| row.get(0)).unwrap();
+        assert_eq!(kind, SUGGESTION_KIND);
+    }
+}
@@ -1,11 +1,93 @@
 // src-tauri/src/hunk_calculator.rs
 // Calculates hunks (contiguous groups of changed lines) between documents
 // Uses the `similar` crate for efficient text diffing
+//
+// Performance budget: under 100 ms for a 1 MB document with scattered edits
+// (release build, `cargo bench --bench hunks`). Unchanged leading and
+// trailing lines are skipped before diffing, large texts use the patience
+// line diff (unique lines as anchors) with a deadline, and the
+// `imara-diff` feature swaps in imara-diff for that line pass.
 
 use serde::{Deserialize, Serialize};
-use similar::{DiffOp, TextDiff};
+use similar::{ChangeTag, DiffOp, TextDiff};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::State;
+use unicode_segmentation::UnicodeSegmentation;
 
+use crate::document_manager::DocumentManager;
+use crate::patch_log::Patch;
 
+/// Color used for hunks whose author has no known color
+pub const DEFAULT_HUNK_COLOR: &str = "#3498db";
+
+/// Default distance (bytes of unchanged text) below which hunks are merged
+pub const DEFAULT_COALESCE_DISTANCE: usize = 50;
+
+/// Share of changed text above which word diffs switch to `Prose`
+pub const DEFAULT_PROSE_THRESHOLD: f32 = 0.3;
+
+/// Changed sentences less similar than this are replaced whole instead of
+/// being diffed word by word
+const SENTENCE_SIMILARITY: f32 = 0.5;
+
+/// Texts (both sides, in bytes) above this size take the large-text path
+const LARGE_TEXT_BYTES: usize = 256 * 1024;
+
+/// Changed blocks above this size get a deadline on their word diff
+const LARGE_BLOCK_BYTES: usize = 64 * 1024;
+
+/// Time a diff of a large text or block may take before it settles for a
+/// coarser result
+const LARGE_DIFF_DEADLINE: Duration = Duration::from_millis(50);
+
+/// Unit compared by the second, fine-grained diff pass
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffGranularity {
+    Character,
+    #[default]
+    Word,
+    Sentence,
+    /// Sentences first, then words within changed sentences
+    Prose,
+}
+
+fn default_coalesce_distance() -> usize {
+    DEFAULT_COALESCE_DISTANCE
+}
+
+fn default_prose_threshold() -> Option<f32> {
+    Some(DEFAULT_PROSE_THRESHOLD)
+}
+
+/// How hunks are computed
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DiffOptions {
+    #[serde(default)]
+    pub granularity: DiffGranularity,
+    /// Hunks separated by less unchanged text than this (in bytes) are merged
+    #[serde(default = "default_coalesce_distance")]
+    pub coalesce_distance: usize,
+    /// Drop hunks that only change whitespace
+    #[serde(default)]
+    pub ignore_whitespace: bool,
+    /// Use `Prose` instead of `Word` when more than this share of the lines
+    /// changed (`None` never switches)
+    #[serde(default = "default_prose_threshold")]
+    pub prose_threshold: Option<f32>,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            granularity: DiffGranularity::default(),
+            coalesce_distance: DEFAULT_COALESCE_DISTANCE,
+            ignore_whitespace: false,
+            prose_threshold: default_prose_threshold(),
+        }
+    }
+}
 
 /// A hunk represents a contiguous block of changes (word level)
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -87,7 +169,34 @@ pub struct AuthoredHunk {
 /// 1. Identifies changed "blocks" using Line Diff.
 /// 2. Performs granular Word Diff within those blocks.
 pub fn calculate_hunks(base_text: &str, modified_text: &str) -> Vec<Hunk> {
-    let diff = TextDiff::from_lines(base_text, modified_text);
+    calculate_hunks_with(base_text, modified_text, &DiffOptions::default())
+}
+
+/// `calculate_hunks` with explicit granularity, coalescing and whitespace options
+pub fn calculate_hunks_with(base_text: &str, modified_text: &str, options: &DiffOptions) -> Vec<Hunk> {
+    // Only the lines between the unchanged start and end are diffed
+    let (prefix, suffix) = common_line_affixes(base_text, modified_text);
+    let base_middle = &base_text[prefix..base_text.len() - suffix];
+    let modified_middle = &modified_text[prefix..modified_text.len() - suffix];
+    let changes = line_changes(base_middle, modified_middle);
+
+    // Share of lines changed, counting the skipped ones as equal
+    let shared_lines = base_text[..prefix].split_inclusive('\n').count()
+        + base_text[base_text.len() - suffix..].split_inclusive('\n').count();
+    let changed_lines = changes.iter().filter(|(tag, _)| *tag != ChangeTag::Equal).count();
+    let total_lines = changes.len() + changes.iter().filter(|(tag, _)| *tag == ChangeTag::Equal).count() + 2 * shared_lines;
+    let changed_share = if total_lines == 0 { 0.0 } else { changed_lines as f32 / total_lines as f32 };
+
+    // Heavily edited prose gives confetti word hunks; keep sentences whole
+    let prose;
+    let options = if options.granularity == DiffGranularity::Word
+        && options.prose_threshold.is_some_and(|t| changed_share > t)
+    {
+        prose = DiffOptions { granularity: DiffGranularity::Prose, ..options.clone() };
+        &prose
+    } else {
+        options
+    };
     let mut all_hunks = Vec::new();
     
     // Global cursors to track absolute position in the Base document
@@ -103,9 +212,9 @@ pub fn calculate_hunks(base_text: &str, modified_text: &str) -> Vec<Hunk> {
     let mut block_start_utf16 = 0;
     let mut in_block = false;
     
-    for change in diff.iter_all_changes() {
-        match change.tag() {
-            similar::ChangeTag::Equal => {
+    for (tag, value) in changes {
+        match tag {
+            ChangeTag::Equal => {
                 // If we were in a block, flush it now
                 if in_block {
                     flush_block(
@@ -114,7 +223,7 @@ pub fn calculate_hunks(base_text: &str, modified_text: &str) -> Vec<Hunk> {
                         &pending_inserts, 
                         block_start_byte, 
                         block_start_utf16,
-                        base_text 
+                        options,
                     );
                     
                     // Reset buffers
@@ -124,27 +233,27 @@ pub fn calculate_hunks(base_text: &str, modified_text: &str) -> Vec<Hunk> {
                 }
                 
                 // Advance global cursors (Equal text consumes Base)
-                let len_bytes = change.value().len();
-                let len_utf16 = change.value().encode_utf16().count();
+                let len_bytes = value.len();
+                let len_utf16 = value.encode_utf16().count();
                 global_base_byte_cursor += len_bytes;
                 global_base_utf16_cursor += len_utf16;
             }
-            similar::ChangeTag::Delete => {
+            ChangeTag::Delete => {
                 if !in_block {
                     in_block = true;
                     block_start_byte = global_base_byte_cursor;
                     block_start_utf16 = global_base_utf16_cursor;
                 }
                 
-                pending_deletes.push_str(change.value());
+                pending_deletes.push_str(value);
                 
                 // Advance global cursors (Delete text consumes Base)
-                let len_bytes = change.value().len();
-                let len_utf16 = change.value().encode_utf16().count();
+                let len_bytes = value.len();
+                let len_utf16 = value.encode_utf16().count();
                 global_base_byte_cursor += len_bytes;
                 global_base_utf16_cursor += len_utf16;
             }
-            similar::ChangeTag::Insert => {
+            ChangeTag::Insert => {
                 if !in_block {
                     // Possible if pure insert (no previous delete)
                     in_block = true;
@@ -153,7 +262,7 @@ pub fn calculate_hunks(base_text: &str, modified_text: &str) -> Vec<Hunk> {
                     block_start_utf16 = global_base_utf16_cursor;
                 }
                 
-                pending_inserts.push_str(change.value());
+                pending_inserts.push_str(value);
                 // Insert does NOT consume Base cursors
             }
         }
@@ -167,13 +276,279 @@ pub fn calculate_hunks(base_text: &str, modified_text: &str) -> Vec<Hunk> {
             &pending_inserts, 
             block_start_byte, 
             block_start_utf16,
-            base_text
+            options,
         );
     }
+
+    // Back to coordinates of the whole base text
+    let prefix_utf16 = base_text[..prefix].encode_utf16().count();
+    for hunk in &mut all_hunks {
+        hunk.base_start += prefix_utf16;
+        hunk.base_end += prefix_utf16;
+        hunk.base_start_byte += prefix;
+        hunk.base_end_byte += prefix;
+    }
+    assign_display_lines(&mut all_hunks, base_text);
     
     all_hunks
 }
 
+/// Byte lengths of the longest common prefix and suffix made of whole lines
+fn common_line_affixes(base_text: &str, modified_text: &str) -> (usize, usize) {
+    let (base, modified) = (base_text.as_bytes(), modified_text.as_bytes());
+
+    let prefix = base.iter().zip(modified).take_while(|(a, b)| a == b).count();
+    // Back up to the start of a line
+    let prefix = base[..prefix].iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+
+    let max_suffix = (base.len() - prefix).min(modified.len() - prefix);
+    let suffix = base.iter().rev().zip(modified.iter().rev()).take(max_suffix).take_while(|(a, b)| a == b).count();
+    // Move forward to the start of a line; the newline is inside the common
+    // part, so both sides agree on it
+    let common = &base[base.len() - suffix..];
+    let suffix = if suffix == base.len() - prefix || suffix == modified.len() - prefix {
+        suffix
+    } else {
+        common.iter().position(|&b| b == b'\n').map_or(0, |i| suffix - i - 1)
+    };
+
+    (prefix, suffix)
+}
+
+/// Line diff as a stream of changed and unchanged lines
+fn line_changes<'a>(base_text: &'a str, modified_text: &'a str) -> Vec<(ChangeTag, &'a str)> {
+    if base_text.len() + modified_text.len() < LARGE_TEXT_BYTES {
+        return TextDiff::from_lines(base_text, modified_text)
+            .iter_all_changes()
+            .map(|c| (c.tag(), c.value()))
+            .collect();
+    }
+
+    #[cfg(feature = "imara-diff")]
+    {
+        imara_line_changes(base_text, modified_text)
+    }
+
+    #[cfg(not(feature = "imara-diff"))]
+    {
+        TextDiff::configure()
+            .algorithm(similar::Algorithm::Patience)
+            .deadline(Instant::now() + LARGE_DIFF_DEADLINE)
+            .diff_lines(base_text, modified_text)
+            .iter_all_changes()
+            .map(|c| (c.tag(), c.value()))
+            .collect()
+    }
+}
+
+/// Line diff of a large text with imara-diff's histogram algorithm
+#[cfg(feature = "imara-diff")]
+fn imara_line_changes<'a>(base_text: &'a str, modified_text: &'a str) -> Vec<(ChangeTag, &'a str)> {
+    use imara_diff::intern::InternedInput;
+    use imara_diff::sources::lines_with_terminator;
+    use std::ops::Range;
+
+    let base_lines: Vec<&str> = base_text.split_inclusive('\n').collect();
+    let modified_lines: Vec<&str> = modified_text.split_inclusive('\n').collect();
+    let input = InternedInput::new(lines_with_terminator(base_text), lines_with_terminator(modified_text));
+
+    let mut changes = Vec::with_capacity(base_lines.len());
+    let mut base_cursor = 0;
+    imara_diff::diff(imara_diff::Algorithm::Histogram, &input, |before: Range<u32>, after: Range<u32>| {
+        let (before, after) = (before.start as usize..before.end as usize, after.start as usize..after.end as usize);
+        changes.extend(base_lines[base_cursor..before.start].iter().map(|l| (ChangeTag::Equal, *l)));
+        changes.extend(base_lines[before.clone()].iter().map(|l| (ChangeTag::Delete, *l)));
+        changes.extend(modified_lines[after].iter().map(|l| (ChangeTag::Insert, *l)));
+        base_cursor = before.end;
+    });
+    changes.extend(base_lines[base_cursor..].iter().map(|l| (ChangeTag::Equal, *l)));
+
+    changes
+}
+
+/// Set `display_start_line` to the number of base lines before each hunk,
+/// counting newlines in one pass instead of once per hunk
+fn assign_display_lines(hunks: &mut [Hunk], base_text: &str) {
+    let bytes = base_text.as_bytes();
+    let (mut cursor, mut newlines) = (0, 0);
+    for hunk in hunks.iter_mut() {
+        let end = hunk.base_start_byte;
+        if end < cursor {
+            (cursor, newlines) = (0, 0);
+        }
+        newlines += bytes[cursor..end].iter().filter(|&&b| b == b'\n').count();
+        cursor = end;
+        // Same as `base_text[..end].lines().count()`
+        hunk.display_start_line = newlines + usize::from(end > 0 && bytes[end - 1] != b'\n');
+    }
+}
+
+/// Attach patch and author metadata to hunks computed for a stored patch
+pub fn author_hunks(hunks: Vec<Hunk>, patch: &Patch, author_name: &str, author_color: &str) -> Vec<AuthoredHunk> {
+    hunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, hunk)| AuthoredHunk {
+            hunk,
+            hunk_id: format!("{}-{}", patch.id, i),
+            patch_id: patch.id,
+            patch_uuid: patch.uuid.clone(),
+            author: patch.author.clone(),
+            author_name: author_name.to_string(),
+            author_color: author_color.to_string(),
+            timestamp: patch.timestamp,
+        })
+        .collect()
+}
+
+/// Apply a subset of hunks computed by `calculate_hunks` back onto the base text
+///
+/// Each hunk replaces its base byte range with its modified text, so applying
+/// every hunk of a diff reproduces the modified document.
+pub fn apply_hunks(base_text: &str, hunks: &[Hunk]) -> String {
+    let mut ordered: Vec<&Hunk> = hunks.iter().collect();
+    ordered.sort_by_key(|h| h.base_start_byte);
+
+    let mut result = String::with_capacity(base_text.len());
+    let mut cursor = 0;
+    for hunk in ordered {
+        // Skip hunks that overlap an already applied one
+        if hunk.base_start_byte < cursor || hunk.base_end_byte > base_text.len() {
+            continue;
+        }
+        result.push_str(&base_text[cursor..hunk.base_start_byte]);
+        result.push_str(&hunk.modified_text);
+        cursor = hunk.base_end_byte;
+    }
+    result.push_str(&base_text[cursor..]);
+
+    result
+}
+
+/// A region both sides of a three-way merge changed differently
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MergeCollision {
+    pub base: String,
+    pub local: String,
+    pub remote: String,
+}
+
+/// Result of `merge_three_way`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ThreeWayMerge {
+    /// Merged text; collisions are written as `<<<<<<< local`, `=======`,
+    /// `>>>>>>> remote` blocks for manual editing
+    pub merged: String,
+    pub collisions: Vec<MergeCollision>,
+}
+
+/// Hunks of both sides touching the same part of the base
+struct MergeCluster<'a> {
+    start: usize,
+    end: usize,
+    local: Vec<&'a Hunk>,
+    remote: Vec<&'a Hunk>,
+}
+
+impl<'a> MergeCluster<'a> {
+    fn new(hunk: &'a Hunk, is_local: bool) -> Self {
+        let mut cluster = Self { start: hunk.base_start_byte, end: hunk.base_end_byte, local: Vec::new(), remote: Vec::new() };
+        cluster.push(hunk, is_local);
+        cluster
+    }
+
+    fn push(&mut self, hunk: &'a Hunk, is_local: bool) {
+        self.end = self.end.max(hunk.base_end_byte);
+        if is_local {
+            self.local.push(hunk);
+        } else {
+            self.remote.push(hunk);
+        }
+    }
+
+    /// Whether a hunk (sorted after the cluster's hunks) touches the cluster
+    fn touches(&self, hunk: &Hunk) -> bool {
+        let (start, end) = (hunk.base_start_byte, hunk.base_end_byte);
+        if start == end && self.start == self.end {
+            start == self.start
+        } else if start == end {
+            self.start < start && start < self.end
+        } else if self.start == self.end {
+            false
+        } else {
+            start < self.end
+        }
+    }
+}
+
+/// Base text of a byte range with the given hunks applied
+fn region_text(base_text: &str, start: usize, end: usize, hunks: &[&Hunk]) -> String {
+    let mut result = String::new();
+    let mut cursor = start;
+    for hunk in hunks {
+        result.push_str(&base_text[cursor..hunk.base_start_byte]);
+        result.push_str(&hunk.modified_text);
+        cursor = hunk.base_end_byte;
+    }
+    result.push_str(&base_text[cursor..end]);
+    result
+}
+
+/// Merge two edited versions of a base text word by word
+///
+/// Changes made by only one side, or identically by both, are applied.
+/// Where both sides changed the same words differently, the region is kept
+/// as a collision.
+pub fn merge_three_way(base_text: &str, local_text: &str, remote_text: &str) -> ThreeWayMerge {
+    let local_hunks = calculate_hunks(base_text, local_text);
+    let remote_hunks = calculate_hunks(base_text, remote_text);
+
+    // (hunk, is_local), insertions before replacements at the same position
+    let mut all: Vec<(&Hunk, bool)> = local_hunks.iter().map(|h| (h, true))
+        .chain(remote_hunks.iter().map(|h| (h, false)))
+        .collect();
+    all.sort_by_key(|(h, _)| (h.base_start_byte, h.base_end_byte > h.base_start_byte, h.base_end_byte));
+
+    // Group hunks that touch the same part of the base
+    let mut clusters: Vec<MergeCluster> = Vec::new();
+    for (hunk, is_local) in all {
+        match clusters.last_mut() {
+            Some(cluster) if cluster.touches(hunk) => cluster.push(hunk, is_local),
+            _ => clusters.push(MergeCluster::new(hunk, is_local)),
+        }
+    }
+
+    let mut merged = String::with_capacity(base_text.len());
+    let mut collisions = Vec::new();
+    let mut cursor = 0;
+    for MergeCluster { start, end, local, remote } in clusters {
+        merged.push_str(&base_text[cursor..start]);
+        cursor = end;
+
+        let local_region = region_text(base_text, start, end, &local);
+        let remote_region = region_text(base_text, start, end, &remote);
+
+        if local.is_empty() || local_region == remote_region {
+            merged.push_str(&remote_region);
+        } else if remote.is_empty() {
+            merged.push_str(&local_region);
+        } else {
+            merged.push_str(&format!(
+                "<<<<<<< local\n{}\n=======\n{}\n>>>>>>> remote",
+                local_region, remote_region
+            ));
+            collisions.push(MergeCollision {
+                base: base_text[start..end].to_string(),
+                local: local_region,
+                remote: remote_region,
+            });
+        }
+    }
+    merged.push_str(&base_text[cursor..]);
+
+    ThreeWayMerge { merged, collisions }
+}
+
 /// Helper to run word diff on a specific block and map back to global coordinates
 fn flush_block(
     all_hunks: &mut Vec<Hunk>,
@@ -181,14 +556,14 @@ fn flush_block(
     local_mod: &str,
     block_start_byte: usize,
     block_start_utf16: usize,
-    full_base_text: &str,
+    options: &DiffOptions,
 ) {
     if local_base.is_empty() && local_mod.is_empty() {
         return;
     }
 
     // Run granular word diff on this block
-    let mut local_hunks = calculate_word_hunks_in_block(local_base, local_mod);
+    let mut local_hunks = calculate_word_hunks_in_block(local_base, local_mod, options);
     
     // Shift relative hunks to absolute coordinates
     for hunk in &mut local_hunks {
@@ -196,19 +571,168 @@ fn flush_block(
         hunk.base_end += block_start_utf16;
         hunk.base_start_byte += block_start_byte;
         hunk.base_end_byte += block_start_byte;
-        
-        // Recalculate line number based on absolute byte position
-        hunk.display_start_line = full_base_text[..hunk.base_start_byte].lines().count();
     }
     
     // Append to main list
     all_hunks.append(&mut local_hunks);
 }
 
+/// Split text into sentences (UAX #29), each keeping its trailing whitespace
+fn split_sentences(text: &str) -> Vec<&str> {
+    text.split_sentence_bounds().collect()
+}
+
+/// A hunk replacing all of `base_text` with `modified_text`
+fn replacement_hunk(base_text: &str, modified_text: &str) -> Hunk {
+    let hunk_type = match (base_text.is_empty(), modified_text.is_empty()) {
+        (true, _) => "add",
+        (_, true) => "delete",
+        _ => "modify",
+    };
+    let mut parts = Vec::new();
+    if !base_text.is_empty() {
+        parts.push(DiffPart { part_type: "delete".to_string(), text: base_text.to_string() });
+    }
+    if !modified_text.is_empty() {
+        parts.push(DiffPart { part_type: "add".to_string(), text: modified_text.to_string() });
+    }
+    Hunk {
+        hunk_type: hunk_type.to_string(),
+        base_start: 0,
+        base_end: base_text.encode_utf16().count(),
+        base_start_byte: 0,
+        base_end_byte: base_text.len(),
+        modified_length: modified_text.encode_utf16().count(),
+        base_text: base_text.to_string(),
+        modified_text: modified_text.to_string(),
+        display_start_line: 0,
+        parts,
+    }
+}
+
+/// Diff configuration for a block of `bytes` (both sides), with a deadline
+/// for large blocks
+fn block_diff_config(bytes: usize) -> similar::TextDiffConfig {
+    let mut config = TextDiff::configure();
+    if bytes > LARGE_BLOCK_BYTES {
+        config.deadline(Instant::now() + LARGE_DIFF_DEADLINE);
+    }
+    config
+}
+
+/// Share of words two texts have in common, ignoring whitespace
+fn word_similarity(a: &str, b: &str) -> f32 {
+    let config = block_diff_config(a.len() + b.len());
+    let a: Vec<&str> = a.split_whitespace().collect();
+    let b: Vec<&str> = b.split_whitespace().collect();
+    config.diff_slices(&a, &b).ratio()
+}
+
+/// Consecutive changed sentences of a prose diff
+#[derive(Default)]
+struct ChangedRun<'a> {
+    start_byte: usize,
+    start_utf16: usize,
+    deleted: Vec<&'a str>,
+    inserted: Vec<&'a str>,
+}
+
+/// Diff one changed unit of prose, word by word if both sides still
+/// resemble each other, whole otherwise
+fn prose_unit_hunks(deleted: &str, inserted: &str, word_options: &DiffOptions) -> Vec<Hunk> {
+    let mut hunks = if word_similarity(deleted, inserted) >= SENTENCE_SIMILARITY {
+        calculate_word_hunks_in_block(deleted, inserted, word_options)
+    } else {
+        vec![replacement_hunk(deleted, inserted)]
+    };
+    if word_options.ignore_whitespace {
+        hunks.retain(|h| !h.base_text.split_whitespace().eq(h.modified_text.split_whitespace()));
+    }
+    hunks
+}
+
+/// Sentence-aware diff of a block
+///
+/// Sentences are compared as atoms. A run of changed sentences is diffed
+/// sentence against sentence when both sides have as many, and as a whole
+/// otherwise; each part only descends to word level when it still
+/// resembles its counterpart. The hunks are then coalesced like word
+/// hunks. Offsets are relative to the block, like
+/// `calculate_word_hunks_in_block`.
+fn calculate_prose_hunks_in_block(base_text: &str, modified_text: &str, options: &DiffOptions) -> Vec<Hunk> {
+    let base_sentences = split_sentences(base_text);
+    let modified_sentences = split_sentences(modified_text);
+    let diff = block_diff_config(base_text.len() + modified_text.len()).diff_slices(&base_sentences, &modified_sentences);
+    let word_options = DiffOptions { granularity: DiffGranularity::Word, ..options.clone() };
+
+    let mut hunks = Vec::new();
+    let mut byte_cursor = 0;
+    let mut utf16_cursor = 0;
+    let mut run: Option<ChangedRun> = None;
+
+    let flush = |run: Option<ChangedRun>, hunks: &mut Vec<Hunk>| {
+        let Some(run) = run else {
+            return;
+        };
+        let units: Vec<(String, String)> = if run.deleted.len() == run.inserted.len() {
+            run.deleted.iter().zip(&run.inserted).map(|(d, i)| (d.to_string(), i.to_string())).collect()
+        } else {
+            vec![(run.deleted.concat(), run.inserted.concat())]
+        };
+
+        let (mut start_byte, mut start_utf16) = (run.start_byte, run.start_utf16);
+        for (deleted, inserted) in units {
+            for mut hunk in prose_unit_hunks(&deleted, &inserted, &word_options) {
+                hunk.base_start += start_utf16;
+                hunk.base_end += start_utf16;
+                hunk.base_start_byte += start_byte;
+                hunk.base_end_byte += start_byte;
+                hunks.push(hunk);
+            }
+            start_byte += deleted.len();
+            start_utf16 += deleted.encode_utf16().count();
+        }
+    };
+
+    for change in diff.iter_all_changes() {
+        let value = change.value();
+        let start = || ChangedRun { start_byte: byte_cursor, start_utf16: utf16_cursor, ..Default::default() };
+        match change.tag() {
+            similar::ChangeTag::Equal => {
+                flush(run.take(), &mut hunks);
+                byte_cursor += value.len();
+                utf16_cursor += value.encode_utf16().count();
+            }
+            similar::ChangeTag::Delete => {
+                run.get_or_insert_with(start).deleted.push(value);
+                byte_cursor += value.len();
+                utf16_cursor += value.encode_utf16().count();
+            }
+            similar::ChangeTag::Insert => {
+                run.get_or_insert_with(start).inserted.push(value);
+            }
+        }
+    }
+    flush(run.take(), &mut hunks);
+
+    coalesce_hunks(hunks, base_text, options.coalesce_distance)
+}
+
 /// The original logic: Word-Level Diff + Coalescing + Parts
 /// Now operating on a purely local pair of strings (0-indexed).
-fn calculate_word_hunks_in_block(base_text: &str, modified_text: &str) -> Vec<Hunk> {
-    let diff = TextDiff::from_words(base_text, modified_text);
+fn calculate_word_hunks_in_block(base_text: &str, modified_text: &str, options: &DiffOptions) -> Vec<Hunk> {
+    let config = block_diff_config(base_text.len() + modified_text.len());
+    let (base_sentences, modified_sentences);
+    let diff = match options.granularity {
+        DiffGranularity::Character => config.diff_chars(base_text, modified_text),
+        DiffGranularity::Word => config.diff_words(base_text, modified_text),
+        DiffGranularity::Prose => return calculate_prose_hunks_in_block(base_text, modified_text, options),
+        DiffGranularity::Sentence => {
+            base_sentences = split_sentences(base_text);
+            modified_sentences = split_sentences(modified_text);
+            config.diff_slices(&base_sentences, &modified_sentences)
+        }
+    };
     let mut hunks = Vec::new();
     
     // We need to track absolute character positions manually.
@@ -340,10 +864,17 @@ fn calculate_word_hunks_in_block(base_text: &str, modified_text: &str) -> Vec<Hu
     if let Some(h) = current_hunk {
         hunks.push(h);
     }
-    
-    // Phase 2: Coalesce micro-hunks
-    // We merge hunks separated by small gaps of "Equal" text to preserve semantic context.
-    
+
+    if options.ignore_whitespace {
+        hunks.retain(|h| !h.base_text.split_whitespace().eq(h.modified_text.split_whitespace()));
+    }
+
+    coalesce_hunks(hunks, base_text, options.coalesce_distance)
+}
+
+/// Phase 2: Coalesce micro-hunks
+/// We merge hunks separated by small gaps of "Equal" text to preserve semantic context.
+fn coalesce_hunks(hunks: Vec<Hunk>, base_text: &str, coalesce_distance: usize) -> Vec<Hunk> {
     if hunks.is_empty() {
         return Vec::new();
     }
@@ -351,14 +882,12 @@ fn calculate_word_hunks_in_block(base_text: &str, modified_text: &str) -> Vec<Hu
     let mut merged_hunks = Vec::new();
     let mut current = hunks[0].clone();
     
-    // Threshold in bytes (approx chars).
-    const COALESCE_THRESHOLD: usize = 50; 
-    
     for next in hunks.into_iter().skip(1) {
         // Calculate gap using BYTE positions to verify slicing distance
         let gap_len = next.base_start_byte - current.base_end_byte;
         
-        if gap_len < COALESCE_THRESHOLD {
+        // Threshold in bytes (approx chars).
+        if gap_len < coalesce_distance {
             // MERGE
             
             // 1. Get the gap text from the original base string using BYTE indices
@@ -567,17 +1096,32 @@ pub struct PatchInput {
 /// 
 /// This computes BASE vs PATCH_A, BASE vs PATCH_B, etc. and returns
 /// all hunks with author information attached.
+/// Diff options default to the settings of `doc_id`, when given.
 #[tauri::command]
 pub fn calculate_hunks_for_patches(
+    manager: State<'_, Mutex<DocumentManager>>,
     base_content: String,
     patches: Vec<PatchInput>,
-) -> Vec<AuthoredHunk> {
+    doc_id: Option<String>,
+    options: Option<DiffOptions>,
+) -> Result<Vec<AuthoredHunk>, String> {
+    let options = match (options, doc_id) {
+        (Some(options), _) => options,
+        (None, Some(doc_id)) => {
+            let manager = manager.lock().map_err(|e| e.to_string())?;
+            manager.documents.get(&doc_id)
+                .ok_or_else(|| format!("Document not found: {}", doc_id))?
+                .meta.settings.diff.clone()
+        }
+        (None, None) => DiffOptions::default(),
+    };
+
     let mut all_hunks = Vec::new();
     let mut hunk_counter = 0;
     
     for patch in patches {
         // Calculate hunks: BASE vs this PATCH
-        let hunks = calculate_hunks(&base_content, &patch.snapshot);
+        let hunks = calculate_hunks_with(&base_content, &patch.snapshot, &options);
         
         // Attach patch metadata to each hunk
         for hunk in hunks {
@@ -598,7 +1142,7 @@ pub fn calculate_hunks_for_patches(
     // Sort hunks by position in base document
     all_hunks.sort_by_key(|h| h.hunk.base_start);
     
-    all_hunks
+    Ok(all_hunks)
 }
 
 #[cfg(test)]
@@ -646,5 +1190,123 @@ mod tests_hybrid {
         assert_eq!(hunks[0].base_text, "changed\nC changed");
         assert_eq!(hunks[0].modified_text, "fixed\nC fixed");
     }
-}
 
+    #[test]
+    fn test_diff_options() {
+        let base = "The cat sat.  It was happy. The end.";
+        let modified = "The cat sat. It was glad. The end.";
+
+        // Characters: only the differing letters and the dropped space
+        let chars = DiffOptions { granularity: DiffGranularity::Character, coalesce_distance: 0, ..Default::default() };
+        let hunks = calculate_hunks_with(base, modified, &chars);
+        assert!(hunks.iter().all(|h| h.base_text.len() <= 4));
+
+        // Sentences are atoms
+        let sentences = DiffOptions { granularity: DiffGranularity::Sentence, ..Default::default() };
+        let hunks = calculate_hunks_with(base, modified, &sentences);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(hunks[0].base_text, "The cat sat.  It was happy. ");
+        assert_eq!(hunks[0].modified_text, "The cat sat. It was glad. ");
+
+        // Whitespace-only hunks are dropped, and nothing coalesces them back
+        let words = DiffOptions { ignore_whitespace: true, coalesce_distance: 0, ..Default::default() };
+        let hunks = calculate_hunks_with(base, modified, &words);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(hunks[0].base_text, "happy.");
+        assert_eq!(hunks[0].modified_text, "glad.");
+    }
+
+    #[test]
+    fn test_unchanged_lines_skipped() {
+        let base = "Intro 😊\nThe cat sat.\nOutro\n";
+        let modified = "Intro 😊\nThe dog sat.\nOutro\n";
+        assert_eq!(common_line_affixes(base, modified), ("Intro 😊\n".len(), "Outro\n".len()));
+
+        let hunks = calculate_hunks(base, modified);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(hunks[0].base_text, "cat");
+        let start = base.find("cat").unwrap();
+        assert_eq!(hunks[0].base_start_byte, start);
+        assert_eq!(hunks[0].base_start, base[..start].encode_utf16().count());
+        assert_eq!(hunks[0].display_start_line, base[..start].lines().count());
+
+        // Appending only diffs the new line
+        let appended = format!("{}More\n", base);
+        assert_eq!(common_line_affixes(base, &appended), (base.len(), 0));
+        let hunks = calculate_hunks(base, &appended);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(hunks[0].modified_text, "More\n");
+        assert_eq!(apply_hunks(base, &hunks), appended);
+
+        assert!(calculate_hunks(base, base).is_empty());
+    }
+
+    #[test]
+    fn test_prose_mode() {
+        let base = "The cat sat on the mat. It was a sunny day in the town. The end.";
+        let modified = "The cat sat on a mat. Rain fell on the old town all day. The end.";
+        let prose = DiffOptions { granularity: DiffGranularity::Prose, coalesce_distance: 0, ..Default::default() };
+        let hunks = calculate_hunks_with(base, modified, &prose);
+
+        // The lightly edited sentence is diffed word by word, the rewritten
+        // one is replaced whole
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[0].base_text, "the");
+        assert_eq!(hunks[0].modified_text, "a");
+        assert_eq!(hunks[1].base_text, "It was a sunny day in the town. ");
+        assert_eq!(hunks[1].modified_text, "Rain fell on the old town all day. ");
+        assert_eq!(hunks[1].base_start, base.find("It was").unwrap());
+        assert_eq!(apply_hunks(base, &hunks), modified);
+
+        // Word diffs switch to prose mode when most of the text changed
+        let words = DiffOptions { coalesce_distance: 0, ..Default::default() };
+        assert_eq!(calculate_hunks_with(base, modified, &words).len(), 2);
+        let never = DiffOptions { prose_threshold: None, ..words };
+        assert!(calculate_hunks_with(base, modified, &never).len() > 2);
+    }
+
+    #[test]
+    fn test_apply_all_hunks_reproduces_modified() {
+        let gap = "This is a very long sentence that serves as a gap between two changes to ensure they are not merged.";
+        let base = format!("Alice said: '{}' and Eve agreed.\n😊 done", gap);
+        let modified = format!("Bob said: '{}' and Mallory agreed.\n😊 finished", gap);
+
+        let hunks = calculate_hunks(&base, &modified);
+        assert!(hunks.len() >= 2);
+        assert_eq!(apply_hunks(&base, &hunks), modified);
+    }
+
+    #[test]
+    fn test_apply_subset_of_hunks() {
+        let gap = "This is a very long sentence that serves as a gap between two changes to ensure they are not merged.";
+        let base = format!("Alice said: '{}' and Eve agreed.", gap);
+        let modified = format!("Bob said: '{}' and Mallory agreed.", gap);
+
+        let hunks = calculate_hunks(&base, &modified);
+        assert_eq!(hunks.len(), 2);
+
+        let result = apply_hunks(&base, &hunks[1..]);
+        assert_eq!(result, format!("Alice said: '{}' and Mallory agreed.", gap));
+    }
+
+    #[test]
+    fn test_merge_three_way() {
+        let base = "The cat sat on the mat.";
+
+        // Edits to different words are both kept
+        let result = merge_three_way(base, "The dog sat on the mat.", "The cat sat on a rug.");
+        assert!(result.collisions.is_empty());
+        assert_eq!(result.merged, "The dog sat on a rug.");
+
+        // The same edit on both sides is applied once
+        let result = merge_three_way(base, "The dog sat on the mat.", "The dog sat on the mat.");
+        assert_eq!(result.merged, "The dog sat on the mat.");
+
+        // Different edits to the same word collide
+        let result = merge_three_way(base, "The dog sat on the mat.", "The bird sat on the mat.");
+        assert_eq!(result.collisions.len(), 1);
+        assert_eq!(result.collisions[0].local, "dog");
+        assert_eq!(result.collisions[0].remote, "bird");
+        assert!(result.merged.starts_with("The <<<<<<< local\ndog\n=======\nbird\n>>>>>>> remote"));
+    }
+}
@@ -0,0 +1,296 @@
+// src-tauri/src/import_conflicts.rs
+//! Conflict detection run after patches are imported.
+//!
+//! Importing a collaborator's document or a patch bundle adds their Save
+//! patches to the local history. For each imported author, the latest
+//! incoming Save snapshot is diffed against the last Save before it in the
+//! local history (the common base), and so is the latest local Save. Hunks
+//! of the two diffs that touch the same part of the base become `Conflict`
+//! rows in the conflict store, and the frontend is told via the
+//! `import-conflicts-detected` event.
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tauri::{AppHandle, Emitter};
+
+use crate::conflict_store;
+use crate::events::{emit_event, ConflictDetectedEvent, CONFLICT_DETECTED};
+use crate::history_rewrite::load_patches;
+use crate::hunk_calculator::{calculate_hunks, Hunk};
+use crate::models::{Conflict, ConflictStatus, ConflictType, TextSpan};
+use crate::patch_log::Patch;
+
+/// Event emitted after an import that produced conflicts
+pub const CONFLICTS_EVENT: &str = "import-conflicts-detected";
+
+/// Payload of `CONFLICTS_EVENT`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportConflictsEvent {
+    pub doc_id: String,
+    /// Number of conflicts found by this import
+    pub count: usize,
+}
+
+fn snapshot_text(patch: &Patch) -> Option<&str> {
+    if patch.kind != "Save" {
+        return None;
+    }
+    patch.data.get("snapshot").and_then(|s| s.as_str())
+}
+
+/// Walk up from an imported patch to the first ancestor that was already in
+/// the local history and has a snapshot
+fn common_base<'a>(
+    patch: &'a Patch,
+    by_uuid: &HashMap<&str, &'a Patch>,
+    imported: &HashSet<String>,
+) -> Option<&'a Patch> {
+    let mut current = patch;
+    let mut seen = HashSet::new();
+    while let Some(parent) = current.parent_uuid.as_deref().and_then(|uuid| by_uuid.get(uuid)) {
+        if !seen.insert(parent.id) {
+            return None;
+        }
+        let is_local = parent.uuid.as_ref().is_some_and(|uuid| !imported.contains(uuid));
+        if is_local && snapshot_text(parent).is_some() {
+            return Some(parent);
+        }
+        current = parent;
+    }
+    None
+}
+
+/// Whether two hunks change the same part of the base
+fn hunks_overlap(a: &Hunk, b: &Hunk) -> bool {
+    let a_insert = a.base_start == a.base_end;
+    let b_insert = b.base_start == b.base_end;
+    match (a_insert, b_insert) {
+        (true, true) => a.base_start == b.base_start,
+        (true, false) => b.base_start < a.base_start && a.base_start < b.base_end,
+        (false, true) => a.base_start < b.base_start && b.base_start < a.base_end,
+        (false, false) => a.base_start < b.base_end && b.base_start < a.base_end,
+    }
+}
+
+fn conflict_type(local: &Hunk, remote: &Hunk) -> ConflictType {
+    if local.hunk_type == "add" && remote.hunk_type == "add" {
+        ConflictType::ConcurrentInsert
+    } else if (local.hunk_type == "delete") != (remote.hunk_type == "delete") {
+        ConflictType::DeleteModify
+    } else {
+        ConflictType::OverlappingEdit
+    }
+}
+
+/// One side's version of the conflicting base region, so the sides can be
+/// merged against the base later
+fn span(hunk: &Hunk, patch: &Patch, base_text: &str, start_byte: usize, end_byte: usize) -> TextSpan {
+    TextSpan {
+        start: hunk.base_start,
+        end: hunk.base_end,
+        content: format!(
+            "{}{}{}",
+            &base_text[start_byte..hunk.base_start_byte],
+            hunk.modified_text,
+            &base_text[hunk.base_end_byte..end_byte]
+        ),
+        author: patch.author.clone(),
+        timestamp: patch.timestamp,
+    }
+}
+
+/// Find conflicts between imported patches and the local history
+///
+/// `imported` holds the UUIDs of the patches added by the import. Conflict
+/// IDs are derived from the patches and position, so importing the same
+/// changes again does not produce duplicates.
+pub fn detect_import_conflicts(patches: &[Patch], imported: &HashSet<String>, detected_at: i64) -> Vec<Conflict> {
+    let is_imported = |p: &Patch| p.uuid.as_ref().is_some_and(|uuid| imported.contains(uuid));
+    let by_uuid: HashMap<&str, &Patch> = patches
+        .iter()
+        .filter_map(|p| p.uuid.as_deref().map(|uuid| (uuid, p)))
+        .collect();
+
+    let Some(local) = patches
+        .iter()
+        .filter(|p| !is_imported(p) && p.uuid.is_some() && snapshot_text(p).is_some())
+        .max_by_key(|p| (p.timestamp, p.id))
+    else {
+        return Vec::new();
+    };
+
+    // Latest incoming Save per author
+    let mut remotes: HashMap<&str, &Patch> = HashMap::new();
+    for patch in patches.iter().filter(|p| is_imported(p) && snapshot_text(p).is_some()) {
+        let entry = remotes.entry(patch.author.as_str()).or_insert(patch);
+        if (patch.timestamp, patch.id) > (entry.timestamp, entry.id) {
+            *entry = patch;
+        }
+    }
+    let mut remotes: Vec<&Patch> = remotes.into_values().collect();
+    remotes.sort_by_key(|p| (p.timestamp, p.id));
+
+    let mut conflicts = Vec::new();
+    for remote in remotes {
+        let Some(base) = common_base(remote, &by_uuid, imported) else {
+            continue;
+        };
+        // No local edits since the base: the import applies cleanly
+        if base.id == local.id || local.timestamp < base.timestamp {
+            continue;
+        }
+
+        let base_text = snapshot_text(base).unwrap_or_default();
+        let local_hunks = calculate_hunks(base_text, snapshot_text(local).unwrap_or_default());
+        let remote_hunks = calculate_hunks(base_text, snapshot_text(remote).unwrap_or_default());
+
+        for local_hunk in &local_hunks {
+            for remote_hunk in remote_hunks.iter().filter(|h| hunks_overlap(local_hunk, h)) {
+                if local_hunk.modified_text == remote_hunk.modified_text
+                    && local_hunk.base_start == remote_hunk.base_start
+                    && local_hunk.base_end == remote_hunk.base_end
+                {
+                    // Both sides made the same change
+                    continue;
+                }
+                let start = local_hunk.base_start.min(remote_hunk.base_start);
+                let end = local_hunk.base_end.max(remote_hunk.base_end);
+                let start_byte = local_hunk.base_start_byte.min(remote_hunk.base_start_byte);
+                let end_byte = local_hunk.base_end_byte.max(remote_hunk.base_end_byte);
+                conflicts.push(Conflict {
+                    id: format!(
+                        "import-{}-{}-{}-{}",
+                        local.uuid.as_deref().unwrap_or_default(),
+                        remote.uuid.as_deref().unwrap_or_default(),
+                        local_hunk.base_start,
+                        remote_hunk.base_start
+                    ),
+                    conflict_type: conflict_type(local_hunk, remote_hunk),
+                    base_version: TextSpan {
+                        start,
+                        end,
+                        content: base_text[start_byte..end_byte].to_string(),
+                        author: base.author.clone(),
+                        timestamp: base.timestamp,
+                    },
+                    local_version: span(local_hunk, local, base_text, start_byte, end_byte),
+                    remote_version: span(remote_hunk, remote, base_text, start_byte, end_byte),
+                    status: ConflictStatus::Unresolved,
+                    detected_at,
+                });
+            }
+        }
+    }
+
+    conflicts
+}
+
+/// UUIDs of the patches in a history database, to tell imported patches
+/// apart afterwards
+pub fn patch_uuids(history_path: &Path) -> Result<HashSet<String>, String> {
+    let conn = Connection::open(history_path).map_err(|e| e.to_string())?;
+    Ok(load_patches(&conn)?.into_iter().filter_map(|p| p.uuid).collect())
+}
+
+/// Detect, store and announce conflicts after an import into a document's
+/// history
+///
+/// Returns the number of conflicts found.
+pub fn check_imported_patches(
+    app: &AppHandle,
+    doc_id: &str,
+    history_path: &Path,
+    imported: &HashSet<String>,
+) -> Result<usize, String> {
+    if imported.is_empty() {
+        return Ok(0);
+    }
+
+    let conn = Connection::open(history_path).map_err(|e| e.to_string())?;
+    let patches = load_patches(&conn)?;
+    let conflicts = detect_import_conflicts(&patches, imported, chrono::Utc::now().timestamp_millis());
+    if conflicts.is_empty() {
+        return Ok(0);
+    }
+
+    let store = conflict_store::init_db(app)?;
+    let mut new_ids = Vec::new();
+    for conflict in &conflicts {
+        if conflict_store::store_conflict(&store, conflict)? {
+            new_ids.push(conflict.id.clone());
+        }
+    }
+    if !new_ids.is_empty() {
+        emit_event(app, CONFLICT_DETECTED, ConflictDetectedEvent {
+            doc_id: Some(doc_id.to_string()),
+            conflict_ids: new_ids,
+        });
+    }
+
+    crate::session_log::log_event(app, "import_conflicts", Some(doc_id), serde_json::json!({
+        "imported": imported.len(),
+        "conflicts": conflicts.len(),
+    }));
+
+    app.emit(CONFLICTS_EVENT, ImportConflictsEvent {
+        doc_id: doc_id.to_string(),
+        count: conflicts.len(),
+    })
+    .map_err(|e| e.to_string())?;
+
+    Ok(conflicts.len())
+}
+
+/// Run `check_imported_patches`, reporting failures to the log instead of
+/// failing the import that already succeeded
+pub fn check_after_import(app: &AppHandle, doc_id: &str, history_path: &Path, imported: &HashSet<String>) {
+    if let Err(e) = check_imported_patches(app, doc_id, history_path, imported) {
+        log::warn!("Conflict detection after import into {} failed: {}", doc_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn save(id: i64, author: &str, uuid: &str, parent: Option<&str>, snapshot: &str) -> Patch {
+        Patch {
+            id,
+            timestamp: id * 1000,
+            author: author.to_string(),
+            kind: "Save".to_string(),
+            data: serde_json::json!({ "snapshot": snapshot }),
+            uuid: Some(uuid.to_string()),
+            parent_uuid: parent.map(|p| p.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_detect_import_conflicts() {
+        let patches = vec![
+            save(1, "alice", "base", None, "The cat sat on the mat."),
+            save(2, "alice", "local", Some("base"), "The dog sat on the mat."),
+            save(3, "bob", "remote", Some("base"), "The bird sat on the mat."),
+        ];
+        let imported: HashSet<String> = ["remote".to_string()].into();
+
+        let conflicts = detect_import_conflicts(&patches, &imported, 42);
+        assert_eq!(conflicts.len(), 1);
+        let conflict = &conflicts[0];
+        assert!(matches!(conflict.conflict_type, ConflictType::OverlappingEdit));
+        assert_eq!(conflict.base_version.content, "cat");
+        assert_eq!(conflict.local_version.content, "dog");
+        assert_eq!(conflict.local_version.author, "alice");
+        assert_eq!(conflict.remote_version.content, "bird");
+        assert_eq!(conflict.remote_version.author, "bob");
+        assert_eq!(conflict.detected_at, 42);
+
+        // Same input gives the same IDs, so re-imports are deduplicated
+        assert_eq!(detect_import_conflicts(&patches, &imported, 43)[0].id, conflict.id);
+
+        // Without local edits since the base, nothing conflicts
+        assert!(detect_import_conflicts(&[patches[0].clone(), patches[2].clone()], &imported, 42).is_empty());
+    }
+}
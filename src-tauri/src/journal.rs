@@ -0,0 +1,292 @@
+// src-tauri/src/journal.rs
+//! Write-ahead journal of the edits and patches of an open document.
+//!
+//! Edits live in memory until the document is saved, while patches go to
+//! the history database at once, so a crash in between loses the edits and
+//! can leave patches that describe text the file never had. Before an edit
+//! is added to the state or a patch to the history, it is appended to
+//! journal.jsonl next to the history database; a patch is journaled with
+//! the hash of the state it was recorded against. Saving starts the journal
+//! over.
+//!
+//! When a document is opened and the journal of an earlier session of the
+//! same, unchanged file is left in the temp directory, that session crashed.
+//! `reconcile` then replays its edits onto the state read from the file,
+//! records the patches whose state hash matches the replayed state, drops
+//! the ones whose edits were lost, and records an autosnapshot when edits
+//! came after the last patch. Other changes to the history of that session,
+//! such as reviews and comments, are not journaled and are not recovered.
+
+use base64::Engine;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use crate::autosave::record_autosnapshot;
+use crate::document_manager::DocumentState;
+use crate::kmd::YjsUpdate;
+use crate::patch_log::PatchInput;
+use crate::patch_store::PatchStore;
+use crate::yjs_store::document_text;
+
+/// Journal file, next to the history database
+pub const JOURNAL_FILE: &str = "journal.jsonl";
+
+/// The journal next to a document's history database
+pub(crate) fn journal_path(history_path: &Path) -> PathBuf {
+    history_path.with_file_name(JOURNAL_FILE)
+}
+
+/// One line of the journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JournalEntry {
+    /// First line: the file the session edits and its hash when last read or saved
+    Open { path: PathBuf, file_hash: String },
+    /// The Yjs state was replaced (base64)
+    State { data: String },
+    /// A Yjs update about to be added to the state (base64)
+    Update { author: String, seq: u32, data: String },
+    /// A patch about to be added to the history, with the hash of the state it describes
+    Patch { patch: PatchInput, state_hash: String },
+}
+
+impl JournalEntry {
+    pub fn state(data: &[u8]) -> Self {
+        JournalEntry::State { data: base64::engine::general_purpose::STANDARD.encode(data) }
+    }
+
+    pub fn update(update: &YjsUpdate) -> Self {
+        JournalEntry::Update {
+            author: update.author.clone(),
+            seq: update.seq,
+            data: base64::engine::general_purpose::STANDARD.encode(&update.data),
+        }
+    }
+}
+
+fn decode(data: &str) -> Result<Vec<u8>, String> {
+    base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| format!("Invalid journal data: {}", e))
+}
+
+/// Hash of a Yjs state and the updates on top of it
+///
+/// Updates are taken by author and sequence number, the order in which a
+/// KMD file stores them, so the hash survives a save and reopen.
+pub fn state_hash(yjs_state: &[u8], updates: &[YjsUpdate]) -> String {
+    let mut sorted: Vec<&YjsUpdate> = updates.iter().collect();
+    sorted.sort_by(|a, b| (&a.author, a.seq).cmp(&(&b.author, b.seq)));
+    let mut hasher = Sha256::new();
+    hasher.update(yjs_state);
+    for update in sorted {
+        hasher.update(&update.data);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Start the journal over for a file, followed by `entries`
+pub fn restart(history_path: &Path, path: &Path, file_hash: &str, entries: &[JournalEntry]) -> Result<(), String> {
+    let journal = journal_path(history_path);
+    let part = journal.with_extension("jsonl.part");
+    let mut file = fs::File::create(&part).map_err(|e| e.to_string())?;
+    let open = JournalEntry::Open { path: path.to_path_buf(), file_hash: file_hash.to_string() };
+    for entry in std::iter::once(&open).chain(entries) {
+        let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        writeln!(file, "{}", line).map_err(|e| e.to_string())?;
+    }
+    file.sync_all().map_err(|e| e.to_string())?;
+    fs::rename(&part, &journal).map_err(|e| e.to_string())
+}
+
+/// Append an entry and flush it to disk
+///
+/// Documents without a journal, such as ones never saved, are skipped.
+pub fn append(history_path: &Path, entry: &JournalEntry) -> Result<(), String> {
+    let journal = journal_path(history_path);
+    if !journal.exists() {
+        return Ok(());
+    }
+    let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(&journal)
+        .map_err(|e| format!("Cannot write journal: {}", e))?;
+    writeln!(file, "{}", line).map_err(|e| format!("Cannot write journal: {}", e))?;
+    file.sync_data().map_err(|e| format!("Cannot write journal: {}", e))
+}
+
+/// Journal the current Yjs state of a document after it was replaced
+pub(crate) fn journal_state(doc: &DocumentState) -> Result<(), String> {
+    append(&doc.history_path, &JournalEntry::state(&doc.yjs_state))
+}
+
+/// Entries of a journal, up to the first line that cannot be read
+///
+/// A crash while appending leaves a partial last line, which is ignored.
+pub fn read_journal(journal: &Path) -> Result<Vec<JournalEntry>, String> {
+    let file = fs::File::open(journal).map_err(|e| e.to_string())?;
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let Ok(line) = line else { break };
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(_) => break,
+        }
+    }
+    Ok(entries)
+}
+
+/// Journal left by a crashed session of the file at `path`
+///
+/// Looks through the temp directories of documents that are not open for a
+/// journal that edits the same file while it still has `file_hash`, and
+/// returns the most recently written one.
+pub fn find_orphaned_journal(base: &Path, path: &Path, file_hash: &str, open_ids: &HashSet<String>) -> Option<PathBuf> {
+    let entries = fs::read_dir(base).ok()?;
+    entries
+        .flatten()
+        .filter(|entry| !open_ids.contains(&entry.file_name().to_string_lossy().to_string()))
+        .map(|entry| entry.path().join(JOURNAL_FILE))
+        .filter(|journal| {
+            let Ok(file) = fs::File::open(journal) else {
+                return false;
+            };
+            let mut first = String::new();
+            if BufReader::new(file).read_line(&mut first).is_err() {
+                return false;
+            }
+            matches!(
+                serde_json::from_str(&first),
+                Ok(JournalEntry::Open { path: p, file_hash: h }) if p == path && h == file_hash
+            )
+        })
+        .max_by_key(|journal| fs::metadata(journal).and_then(|m| m.modified()).ok())
+}
+
+/// What was recovered from the journal of a crashed session
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct RecoveryReport {
+    /// Yjs updates replayed onto the state
+    pub updates: usize,
+    /// Patches added back to the history
+    pub patches: usize,
+    /// UUIDs of patches whose edits were lost, left out of the history
+    pub dropped_patches: Vec<String>,
+    /// An autosnapshot was recorded for edits made after the last patch
+    pub autosnapshot: bool,
+}
+
+/// Replay a crashed session's journal onto the state and history read from the file
+pub fn reconcile(
+    entries: Vec<JournalEntry>,
+    yjs_state: &mut Vec<u8>,
+    yjs_updates: &mut Vec<YjsUpdate>,
+    conn: &Connection,
+    author: &str,
+    now: i64,
+) -> Result<RecoveryReport, String> {
+    let mut report = RecoveryReport::default();
+    let mut unpatched = 0u32;
+    for entry in entries {
+        match entry {
+            JournalEntry::Open { .. } => {}
+            JournalEntry::State { data } => {
+                *yjs_state = decode(&data)?;
+                unpatched += 1;
+            }
+            JournalEntry::Update { author, seq, data } => {
+                yjs_updates.push(YjsUpdate { author, seq, data: decode(&data)? });
+                report.updates += 1;
+                unpatched += 1;
+            }
+            JournalEntry::Patch { patch, state_hash: hash } => {
+                if hash != state_hash(yjs_state, yjs_updates) {
+                    report.dropped_patches.push(patch.uuid.clone().unwrap_or_default());
+                    continue;
+                }
+                if PatchStore::on(conn).insert_patch(&patch, None)?.is_some() {
+                    report.patches += 1;
+                }
+                unpatched = 0;
+            }
+        }
+    }
+
+    if unpatched > 0 {
+        let mut parts: Vec<&[u8]> = vec![yjs_state];
+        parts.extend(yjs_updates.iter().map(|u| u.data.as_slice()));
+        let text = document_text(&parts, false)?;
+        report.autosnapshot = record_autosnapshot(conn, author, now, &text, unpatched)?.is_some();
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db_utils::ensure_schema;
+
+    fn patch(uuid: &str) -> PatchInput {
+        PatchInput {
+            timestamp: 1,
+            author: "alice".to_string(),
+            kind: "Edit".to_string(),
+            data: serde_json::json!({}),
+            uuid: Some(uuid.to_string()),
+            parent_uuid: None,
+        }
+    }
+
+    #[test]
+    fn test_reconcile_replays_and_drops() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let history_path = dir.path().join("session").join("history.sqlite");
+        fs::create_dir_all(history_path.parent().unwrap()).unwrap();
+        let kmd = dir.path().join("draft.kmd");
+
+        // Without a journal nothing is written
+        append(&history_path, &JournalEntry::state(b"ignored")).unwrap();
+        assert!(!journal_path(&history_path).exists());
+
+        let base = b"base".to_vec();
+        let first = YjsUpdate { author: "alice".to_string(), seq: 1, data: b"one".to_vec() };
+        let lost = YjsUpdate { author: "alice".to_string(), seq: 2, data: b"two".to_vec() };
+        restart(&history_path, &kmd, "h1", &[]).unwrap();
+        append(&history_path, &JournalEntry::update(&first)).unwrap();
+        let matching = state_hash(&base, std::slice::from_ref(&first));
+        append(&history_path, &JournalEntry::Patch { patch: patch("p1"), state_hash: matching }).unwrap();
+        // The update of the second patch never reached the journal
+        let missing = state_hash(&base, &[first.clone(), lost]);
+        append(&history_path, &JournalEntry::Patch { patch: patch("p2"), state_hash: missing }).unwrap();
+        // A torn last line is ignored
+        let mut file = OpenOptions::new().append(true).open(journal_path(&history_path)).unwrap();
+        write!(file, "{{\"op\":\"upd").unwrap();
+
+        let open_ids = HashSet::new();
+        let journal = find_orphaned_journal(dir.path(), &kmd, "h1", &open_ids).unwrap();
+        assert!(find_orphaned_journal(dir.path(), &kmd, "changed", &open_ids).is_none());
+        let open_ids: HashSet<String> = HashSet::from(["session".to_string()]);
+        assert!(find_orphaned_journal(dir.path(), &kmd, "h1", &open_ids).is_none());
+
+        let entries = read_journal(&journal).unwrap();
+        assert_eq!(entries.len(), 4);
+
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+        let (mut state, mut updates) = (base.clone(), Vec::new());
+        let report = reconcile(entries, &mut state, &mut updates, &conn, "alice", 2).unwrap();
+        assert_eq!(updates, vec![first]);
+        assert_eq!(report.updates, 1);
+        assert_eq!(report.patches, 1);
+        assert_eq!(report.dropped_patches, vec!["p2".to_string()]);
+        assert!(!report.autosnapshot);
+        assert!(PatchStore::on(&conn).patch_exists_by_uuid("p1").unwrap());
+        assert!(!PatchStore::on(&conn).patch_exists_by_uuid("p2").unwrap());
+    }
+}
@@ -3,30 +3,57 @@
 //!
 //! A KMD file is a ZIP archive containing:
 //! - format.json: Format version and compatibility info
-//! - state.yjs: Yjs CRDT document state (binary)
+//! - state.yjs: Yjs CRDT document state (binary), a checkpoint since v0.2
+//! - updates/<author>/<seq>.yjs: append-only Yjs updates per author (v0.2)
 //! - history.sqlite: Semantic patch history
 //! - meta.json: Document metadata
 //! - authors/: Author profile cache
 
 use std::fs::{self, File};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-use chrono::Utc;
+use chrono::{NaiveDate, Utc};
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
-use tauri::{AppHandle, Manager};
+use tauri::{AppHandle, Manager, State};
 use uuid::Uuid;
 use zip::write::FileOptions;
 use zip::ZipWriter;
 
+use crate::comments::{load_all_comments, Comment};
+use crate::document_metadata::with_metadata_frontmatter;
+use crate::docx_styles::{apply_heading_styles, apply_line_numbers, validate_heading_styles};
+use crate::hunk_calculator::DiffOptions;
+use crate::outline::{build_outline, OutlineNode};
+use crate::document_manager::{document_roster_dir, get_document_history_path, DocumentManager};
+use crate::error::KorppiError;
+use crate::pandoc::{is_pandoc_available, pandoc_command};
+use crate::profile::{kmd_author_profile, load_saved_profile};
+use crate::source_format::SourceMetadata;
+use crate::typography::apply_typography;
+use crate::workspaces::resolve_document_references_for_export;
+use crate::yjs_store::anchor_blocks;
+
 use docx_rs::*;
 use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
 use regex::Regex;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-pub const KMD_VERSION: &str = "0.1.0";
+pub const KMD_VERSION: &str = "0.4.0";
+/// Files with a state.yjs checkpoint stay readable by v0.1 readers
 pub const MIN_READER_VERSION: &str = "0.1.0";
+/// Required reader version for files that only carry update chunks
+pub const CHUNKED_MIN_READER_VERSION: &str = "0.2.0";
+/// Directory holding the append-only Yjs updates
+pub const UPDATES_DIR: &str = "updates/";
+/// Required reader version for multi-section project files
+pub const PROJECT_MIN_READER_VERSION: &str = "0.3.0";
+/// Directory holding the state and history of each project section
+pub const SECTIONS_DIR: &str = "sections/";
+/// Required reader version for files with zstd-compressed entries
+pub const ZSTD_MIN_READER_VERSION: &str = "0.4.0";
 pub const APP_NAME: &str = "korppi";
 pub const APP_VERSION: &str = "0.1.0";
 
@@ -45,6 +72,61 @@ pub struct CreatedBy {
     pub version: String,
 }
 
+/// Compression of the state.yjs and history.sqlite entries of a KMD file
+///
+/// Deflate can be read by every version. Zstd compresses large histories
+/// faster and smaller, but needs a v0.4 reader. The other entries are always
+/// deflated, so older readers can still read format.json and refuse the file.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KmdCompression {
+    #[default]
+    Deflate,
+    Zstd,
+}
+
+impl KmdCompression {
+    /// Name recorded in format.json
+    pub fn name(self) -> &'static str {
+        match self {
+            KmdCompression::Deflate => "deflate",
+            KmdCompression::Zstd => "zstd",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "deflate" => Some(KmdCompression::Deflate),
+            "zstd" => Some(KmdCompression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Options to write an archive entry with
+    pub fn entry_options(self, entry_name: &str) -> FileOptions {
+        let large = entry_name.ends_with("state.yjs") || entry_name.ends_with("history.sqlite");
+        let method = match self {
+            KmdCompression::Zstd if large => zip::CompressionMethod::Zstd,
+            _ => zip::CompressionMethod::Deflated,
+        };
+        FileOptions::default().compression_method(method).unix_permissions(0o644)
+    }
+}
+
+impl FormatInfo {
+    /// Record the compression of the large entries, raising the required
+    /// reader version for zstd
+    pub fn with_compression(mut self, compression: KmdCompression) -> Self {
+        self.compression = compression.name().to_string();
+        if compression == KmdCompression::Zstd
+            && parse_version(&self.min_reader_version) < parse_version(ZSTD_MIN_READER_VERSION)
+        {
+            self.min_reader_version = ZSTD_MIN_READER_VERSION.to_string();
+        }
+        self
+    }
+}
+
 impl Default for FormatInfo {
     fn default() -> Self {
         Self {
@@ -59,6 +141,57 @@ impl Default for FormatInfo {
     }
 }
 
+/// How the Yjs document is stored in a KMD archive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KmdLayout {
+    /// v0.1: a single state.yjs blob
+    Monolithic,
+    /// v0.2: optional state.yjs checkpoint plus updates/ chunks
+    Chunked,
+}
+
+/// A single Yjs update stored under updates/
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct YjsUpdate {
+    /// Author (profile ID) or client that produced the update
+    pub author: String,
+    /// Per-author sequence number, starting at 1
+    pub seq: u32,
+    pub data: Vec<u8>,
+}
+
+impl YjsUpdate {
+    /// Archive entry name, e.g. updates/alice/000001.yjs
+    pub fn entry_name(&self) -> String {
+        format!("{}{}/{:06}.yjs", UPDATES_DIR, self.author, self.seq)
+    }
+
+    /// Parse author and sequence number from an archive entry name
+    pub fn parse_entry_name(name: &str) -> Option<(String, u32)> {
+        let rest = name.strip_prefix(UPDATES_DIR)?;
+        let (author, file) = rest.split_once('/')?;
+        let seq = file.strip_suffix(".yjs")?.parse().ok()?;
+        if author.is_empty() || !is_path_safe(author) {
+            return None;
+        }
+        Some((author.to_string(), seq))
+    }
+}
+
+/// A child section of a multi-file project
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SectionRef {
+    pub id: String,
+    pub title: String,
+}
+
+impl SectionRef {
+    /// Archive entry name of a file belonging to this section, e.g. sections/<id>/state.yjs
+    pub fn entry_name(&self, file: &str) -> String {
+        format!("{}{}/{}", SECTIONS_DIR, self.id, file)
+    }
+}
+
 /// Document metadata stored in meta.json
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DocumentMeta {
@@ -71,6 +204,19 @@ pub struct DocumentMeta {
     pub settings: DocumentSettings,
     #[serde(default)]
     pub sync_state: SyncState,
+    /// Ordered sections of a project; empty for single-buffer documents
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sections: Vec<SectionRef>,
+    /// Frontmatter and chunk headers of an imported Quarto or R Markdown file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceMetadata>,
+    #[serde(rename = "abstract", default, skip_serializing_if = "Option::is_none")]
+    pub abstract_text: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    /// Other metadata by name, such as the journal a paper is written for
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom_fields: BTreeMap<String, String>,
 }
 
 impl Default for DocumentMeta {
@@ -84,6 +230,11 @@ impl Default for DocumentMeta {
             authors: Vec::new(),
             settings: DocumentSettings::default(),
             sync_state: SyncState::default(),
+            sections: Vec::new(),
+            source: None,
+            abstract_text: None,
+            keywords: Vec::new(),
+            custom_fields: BTreeMap::new(),
         }
     }
 }
@@ -113,15 +264,193 @@ pub struct AuthorProfile {
     pub avatar_base64: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub public_key: Option<String>,
+    /// Key the author signs patch bundles with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_key: Option<String>,
 }
 
 /// Document settings
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DocumentSettings {
     #[serde(default = "default_language")]
     pub language: String,
     #[serde(default = "default_true")]
     pub spell_check: bool,
+    /// Smart quotes, dashes and locale spacing on export
+    #[serde(default = "default_true")]
+    pub smart_typography: bool,
+    #[serde(default)]
+    pub crossref: CrossRefSettings,
+    /// Collect notes at the end of the document instead of the page foot.
+    /// Applies to the built-in DOCX writer; pandoc always writes footnotes.
+    #[serde(default)]
+    pub endnotes: bool,
+    #[serde(default)]
+    pub autosave: AutoSaveSettings,
+    /// Reference document whose styles pandoc uses for DOCX export
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference_doc: Option<PathBuf>,
+    /// Mirror Save patches as commits into a git repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_mirror: Option<GitMirrorSettings>,
+    /// Refuse to accept a patch while unresolved comments sit on its changes
+    #[serde(default)]
+    pub block_acceptance_on_comments: bool,
+    /// Default granularity, coalescing and whitespace handling of diffs
+    #[serde(default)]
+    pub diff: DiffOptions,
+    /// Compression of the document state and history when saving
+    #[serde(default)]
+    pub compression: KmdCompression,
+    /// Backups of this document, instead of the ones set in the app settings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<BackupSettings>,
+    /// Writing goal tracked against the history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub goal: Option<WritingGoal>,
+    /// Start exports with a table of contents
+    #[serde(default)]
+    pub toc: bool,
+    /// Deepest heading level listed in the table of contents
+    #[serde(default = "default_toc_depth")]
+    pub toc_depth: u8,
+    /// Number headings in exports ("1", "1.1", "1.1.1")
+    #[serde(default)]
+    pub number_headings: bool,
+    /// DOCX style names of headings by level, e.g. 1 -> "Title 1"; other
+    /// levels keep Word's "Heading N"
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub heading_styles: BTreeMap<u8, String>,
+    /// Number the lines of DOCX and PDF exports, for review copies
+    #[serde(default)]
+    pub line_numbers: bool,
+}
+
+impl Default for DocumentSettings {
+    fn default() -> Self {
+        Self {
+            language: default_language(),
+            spell_check: true,
+            smart_typography: true,
+            crossref: CrossRefSettings::default(),
+            endnotes: false,
+            autosave: AutoSaveSettings::default(),
+            reference_doc: None,
+            git_mirror: None,
+            block_acceptance_on_comments: false,
+            diff: DiffOptions::default(),
+            compression: KmdCompression::default(),
+            backup: None,
+            goal: None,
+            toc: false,
+            toc_depth: default_toc_depth(),
+            number_headings: false,
+            heading_styles: BTreeMap::new(),
+            line_numbers: false,
+        }
+    }
+}
+
+fn default_toc_depth() -> u8 {
+    3
+}
+
+/// Policy for automatic snapshots taken independently of manual saves
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AutoSaveSettings {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Snapshot after this many minutes of activity (0 disables the timer)
+    #[serde(default = "default_autosave_interval")]
+    pub interval_minutes: u32,
+    /// Snapshot after this many changes (0 disables the counter)
+    #[serde(default = "default_autosave_changes")]
+    pub change_threshold: u32,
+}
+
+impl Default for AutoSaveSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_minutes: default_autosave_interval(),
+            change_threshold: default_autosave_changes(),
+        }
+    }
+}
+
+fn default_autosave_interval() -> u32 {
+    10
+}
+
+fn default_autosave_changes() -> u32 {
+    500
+}
+
+/// Local git repository that receives a commit per Save patch
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GitMirrorSettings {
+    pub repo_path: PathBuf,
+    /// Markdown file inside the repository holding the snapshot
+    #[serde(default = "default_mirror_file")]
+    pub file_name: String,
+}
+
+fn default_mirror_file() -> String {
+    "document.md".to_string()
+}
+
+/// Copies of the saved file kept in another directory
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BackupSettings {
+    pub directory: PathBuf,
+    /// Hours between two backups of a document
+    #[serde(default = "default_backup_interval")]
+    pub interval_hours: u32,
+    /// Number of days whose newest backup is kept
+    #[serde(default = "default_keep_daily")]
+    pub keep_daily: u32,
+    /// Number of weeks whose newest backup is kept
+    #[serde(default = "default_keep_weekly")]
+    pub keep_weekly: u32,
+}
+
+fn default_backup_interval() -> u32 {
+    24
+}
+
+fn default_keep_daily() -> u32 {
+    7
+}
+
+fn default_keep_weekly() -> u32 {
+    4
+}
+
+/// Length a document should reach, optionally by a date
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WritingGoal {
+    pub target_words: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_characters: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<NaiveDate>,
+}
+
+/// Cross-reference numbering style
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CrossRefSettings {
+    /// Number figures, tables and equations per chapter ("Figure 2.3")
+    #[serde(default)]
+    pub chapter_numbering: bool,
+    /// Prefix overrides; defaults follow the document language
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub figure_prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub table_prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub section_prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equation_prefix: Option<String>,
 }
 
 fn default_language() -> String {
@@ -261,7 +590,7 @@ pub fn is_path_safe(path: &str) -> bool {
 
 /// Export the current document as a KMD file
 #[tauri::command]
-pub fn export_kmd(app: AppHandle, path: String) -> Result<DocumentMeta, String> {
+pub fn export_kmd(app: AppHandle, path: String) -> Result<DocumentMeta, KorppiError> {
     let yjs_path = get_yjs_path(&app)?;
     let history_path = get_history_path(&app)?;
 
@@ -280,67 +609,52 @@ pub fn export_kmd(app: AppHandle, path: String) -> Result<DocumentMeta, String>
     }
 
     // Create the ZIP archive
-    let file = File::create(&path).map_err(|e| format!("Failed to create file: {}", e))?;
+    let file = File::create(&path)?;
     let mut zip = ZipWriter::new(file);
-    let options = FileOptions::default()
-        .compression_method(zip::CompressionMethod::Deflated)
-        .unix_permissions(0o644);
+    let compression = meta.settings.compression;
+    let options = compression.entry_options("meta.json");
 
     // Write format.json
-    let format_info = FormatInfo::default();
-    let format_json = serde_json::to_string_pretty(&format_info).map_err(|e| e.to_string())?;
-    zip.start_file("format.json", options)
-        .map_err(|e| e.to_string())?;
-    zip.write_all(format_json.as_bytes())
-        .map_err(|e| e.to_string())?;
+    let format_info = FormatInfo::default().with_compression(compression);
+    let format_json = serde_json::to_string_pretty(&format_info)?;
+    zip.start_file("format.json", options)?;
+    zip.write_all(format_json.as_bytes())?;
 
     // Write state.yjs (if exists)
     if yjs_path.exists() {
-        let yjs_data = fs::read(&yjs_path).map_err(|e| e.to_string())?;
-        zip.start_file("state.yjs", options)
-            .map_err(|e| e.to_string())?;
-        zip.write_all(&yjs_data).map_err(|e| e.to_string())?;
+        let yjs_data = fs::read(&yjs_path)?;
+        zip.start_file("state.yjs", compression.entry_options("state.yjs"))?;
+        zip.write_all(&yjs_data)?;
     }
 
     // Write history.sqlite (if exists)
     if history_path.exists() {
-        let history_data = fs::read(&history_path).map_err(|e| e.to_string())?;
-        zip.start_file("history.sqlite", options)
-            .map_err(|e| e.to_string())?;
-        zip.write_all(&history_data).map_err(|e| e.to_string())?;
+        let history_data = fs::read(&history_path)?;
+        zip.start_file("history.sqlite", compression.entry_options("history.sqlite"))?;
+        zip.write_all(&history_data)?;
     }
 
     // Write meta.json
-    let meta_json = serde_json::to_string_pretty(&meta).map_err(|e| e.to_string())?;
-    zip.start_file("meta.json", options)
-        .map_err(|e| e.to_string())?;
-    zip.write_all(meta_json.as_bytes())
-        .map_err(|e| e.to_string())?;
+    let meta_json = serde_json::to_string_pretty(&meta)?;
+    zip.start_file("meta.json", options)?;
+    zip.write_all(meta_json.as_bytes())?;
 
     // Write authors directory
-    zip.add_directory("authors/", options)
-        .map_err(|e| e.to_string())?;
+    zip.add_directory("authors/", options)?;
 
     // Write author profiles
+    let local_profile = load_saved_profile().unwrap_or_default();
+    let roster_dir = document_roster_dir(&history_path);
     for author in &meta.authors {
-        let profile = AuthorProfile {
-            id: author.id.clone(),
-            name: author.name.clone(),
-            email: author.email.clone(),
-            color: "#3498db".to_string(),
-            avatar_base64: None,
-            public_key: None,
-        };
-        let profile_json = serde_json::to_string_pretty(&profile).map_err(|e| e.to_string())?;
+        let profile = kmd_author_profile(author, &roster_dir, local_profile.as_ref());
+        let profile_json = serde_json::to_string_pretty(&profile)?;
         let author_file = format!("authors/{}.json", author.id);
-        zip.start_file(&author_file, options)
-            .map_err(|e| e.to_string())?;
-        zip.write_all(profile_json.as_bytes())
-            .map_err(|e| e.to_string())?;
+        zip.start_file(&author_file, options)?;
+        zip.write_all(profile_json.as_bytes())?;
     }
 
     // Finalize the archive
-    zip.finish().map_err(|e| e.to_string())?;
+    zip.finish()?;
 
     // Save updated metadata
     save_meta(&app, &meta)?;
@@ -348,26 +662,52 @@ pub fn export_kmd(app: AppHandle, path: String) -> Result<DocumentMeta, String>
     Ok(meta)
 }
 
-/// Check if the KMD version is compatible
-pub fn check_version_compatibility(format_info: &FormatInfo) -> Result<(), String> {
-    // Simple version check: parse as semver-like (handles 0.1.0, 1.0, 2.0.0-beta.1, etc.)
-    // Extract major.minor.patch numbers, treating missing parts as 0
-    fn parse_version(v: &str) -> (u32, u32, u32) {
-        let parts: Vec<u32> = v
-            .split('.')
-            .take(3)
-            .map(|s| {
-                // Handle prerelease suffixes like "0-beta" by taking only the numeric part
-                s.split('-').next().unwrap_or("0").parse().unwrap_or(0)
-            })
-            .collect();
-        (
-            *parts.first().unwrap_or(&0),
-            *parts.get(1).unwrap_or(&0),
-            *parts.get(2).unwrap_or(&0),
-        )
+/// Parse a semver-like version (handles 0.1.0, 1.0, 2.0.0-beta.1, etc.)
+/// Extracts major.minor.patch numbers, treating missing parts as 0
+fn parse_version(v: &str) -> (u32, u32, u32) {
+    let parts: Vec<u32> = v
+        .split('.')
+        .take(3)
+        .map(|s| {
+            // Handle prerelease suffixes like "0-beta" by taking only the numeric part
+            s.split('-').next().unwrap_or("0").parse().unwrap_or(0)
+        })
+        .collect();
+    (
+        *parts.first().unwrap_or(&0),
+        *parts.get(1).unwrap_or(&0),
+        *parts.get(2).unwrap_or(&0),
+    )
+}
+
+/// Storage layout of a KMD file, based on the version that wrote it
+pub fn kmd_layout(format_info: &FormatInfo) -> KmdLayout {
+    if parse_version(&format_info.kmd_version) >= (0, 2, 0) {
+        KmdLayout::Chunked
+    } else {
+        KmdLayout::Monolithic
+    }
+}
+
+/// Format info for a file being written. Files whose state.yjs checkpoint
+/// does not hold the whole document (it has updates on top) cannot be read
+/// by v0.1 readers, projects with sections need a v0.3 reader and
+/// zstd-compressed files a v0.4 reader.
+pub fn format_info_for(checkpoint_only: bool, has_sections: bool, compression: KmdCompression) -> FormatInfo {
+    let mut format_info = FormatInfo::default();
+    if has_sections {
+        format_info.min_reader_version = PROJECT_MIN_READER_VERSION.to_string();
+    } else if !checkpoint_only {
+        format_info.min_reader_version = CHUNKED_MIN_READER_VERSION.to_string();
     }
+    format_info.with_compression(compression)
+}
 
+/// Check if the KMD version is compatible
+///
+/// v0.1 files are read as a single state.yjs and written back as v0.2 with
+/// that state as the checkpoint, so older readers can still open them.
+pub fn check_version_compatibility(format_info: &FormatInfo) -> Result<(), String> {
     let min_version = parse_version(&format_info.min_reader_version);
     let our_version = parse_version(KMD_VERSION);
 
@@ -399,6 +739,13 @@ pub fn check_version_compatibility(format_info: &FormatInfo) -> Result<(), Strin
         ));
     }
 
+    if KmdCompression::from_name(&format_info.compression).is_none() {
+        return Err(format!(
+            "KMD file uses {} compression, which this version of Korppi cannot read",
+            format_info.compression
+        ));
+    }
+
     Ok(())
 }
 
@@ -407,45 +754,560 @@ pub fn check_version_compatibility(format_info: &FormatInfo) -> Result<(), Strin
 
 /// Get current document metadata
 #[tauri::command]
-pub fn get_document_meta(app: AppHandle) -> Result<DocumentMeta, String> {
-    load_or_create_meta(&app)
+pub fn get_document_meta(app: AppHandle) -> Result<DocumentMeta, KorppiError> {
+    Ok(load_or_create_meta(&app)?)
 }
 
 /// Update document title
 #[tauri::command]
-pub fn set_document_title(app: AppHandle, title: String) -> Result<(), String> {
+pub fn set_document_title(app: AppHandle, title: String) -> Result<(), KorppiError> {
     let mut meta = load_or_create_meta(&app)?;
     meta.title = title;
     meta.modified_at = Utc::now().to_rfc3339();
-    save_meta(&app, &meta)
+    Ok(save_meta(&app, &meta)?)
 }
 
 /// Write text content to a file (for markdown export)
 #[tauri::command]
-pub fn write_text_file(path: String, content: String) -> Result<(), String> {
-    fs::write(&path, content).map_err(|e| format!("Failed to write file: {}", e))
+pub fn write_text_file(path: String, content: String) -> Result<(), KorppiError> {
+    fs::write(&path, content)?;
+    Ok(())
 }
 
 /// Export markdown content to a file
+/// Optionally adds comments and review decisions of an open document,
+/// with comments placed at the text their anchors point to,
+/// and applies the typography rules of the document language.
+/// References to sections of other documents are resolved first.
+/// The metadata of the document `doc_id` goes into YAML frontmatter.
 #[tauri::command]
-pub fn export_markdown(path: String, content: String) -> Result<(), String> {
-    write_text_file(path, content)
+pub fn export_markdown(
+    manager: State<'_, Mutex<DocumentManager>>,
+    path: String,
+    content: String,
+    annotations: Option<ExportAnnotations>,
+    settings: Option<DocumentSettings>,
+    doc_id: Option<String>,
+) -> Result<(), KorppiError> {
+    let meta = document_meta(&manager, doc_id.as_deref())?;
+    let content = match annotations {
+        Some(options) => {
+            let yjs_parts: Vec<Vec<u8>> = {
+                let manager = manager.lock().map_err(|e| e.to_string())?;
+                match manager.documents.get(&options.doc_id) {
+                    Some(doc) => std::iter::once(doc.yjs_state.clone())
+                        .chain(doc.yjs_updates.iter().map(|u| u.data.clone()))
+                        .collect(),
+                    None => Vec::new(),
+                }
+            };
+            let yjs_parts: Vec<&[u8]> = yjs_parts.iter().map(Vec::as_slice).collect();
+            annotate_markdown_for_export(&content, &options, &yjs_parts)?
+        }
+        None => content,
+    };
+    write_markdown_export(path, content, settings, meta.as_ref())
 }
 
-/// Cross-reference registries for figures, sections, and tables
+/// Metadata of an open document to export with it
+fn document_meta(
+    manager: &Mutex<DocumentManager>,
+    doc_id: Option<&str>,
+) -> Result<Option<DocumentMeta>, KorppiError> {
+    let Some(doc_id) = doc_id else {
+        return Ok(None);
+    };
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager
+        .documents
+        .get(doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+    Ok(Some(doc.meta.clone()))
+}
+
+/// Resolve references, apply typography, add the metadata frontmatter and
+/// write exported markdown
+pub fn write_markdown_export(
+    path: String,
+    content: String,
+    settings: Option<DocumentSettings>,
+    meta: Option<&DocumentMeta>,
+) -> Result<(), KorppiError> {
+    let content = resolve_document_references_for_export(&content);
+    let content = match settings {
+        Some(settings) if settings.smart_typography => apply_typography(&content, &settings.language),
+        _ => content,
+    };
+    write_text_file(path, with_metadata_frontmatter(content, meta))
+}
+
+/// Where exported comments are placed
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentPlacement {
+    /// Footnotes anchored after the commented text
+    #[default]
+    Footnotes,
+    /// A "Comments" section at the end of the document
+    Appendix,
+    /// CriticMarkup highlights with the thread as a comment: `{==text==}{>>comment<<}`
+    CriticMarkup,
+    /// HTML comments after the commented text, hidden when rendered
+    Html,
+}
+
+/// Review context to include when exporting a document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportAnnotations {
+    /// Open document whose comments and reviews are exported
+    pub doc_id: String,
+    #[serde(default = "default_true")]
+    pub include_comments: bool,
+    #[serde(default = "default_true")]
+    pub include_reviews: bool,
+    #[serde(default)]
+    pub comment_placement: CommentPlacement,
+}
+
+/// A review decision joined with the patch it applies to
+#[derive(Debug, Clone)]
+struct ReviewSummaryRow {
+    patch_uuid: String,
+    patch_author: Option<String>,
+    reviewer: String,
+    decision: String,
+    reviewed_at: i64,
+}
+
+/// Format a millisecond timestamp for human-readable export output
+fn format_export_timestamp(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(timestamp)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+/// Load review decisions with their patch authors from a history database
+fn load_review_summary(conn: &Connection) -> Result<Vec<ReviewSummaryRow>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT r.patch_uuid, p.author, COALESCE(r.reviewer_name, r.reviewer_id), r.decision, r.reviewed_at
+             FROM patch_reviews r
+             LEFT JOIN patches p ON p.uuid = r.patch_uuid
+             ORDER BY p.timestamp ASC, r.reviewed_at ASC",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(ReviewSummaryRow {
+                patch_uuid: row.get(0)?,
+                patch_author: row.get(1)?,
+                reviewer: row.get(2)?,
+                decision: row.get(3)?,
+                reviewed_at: row.get(4)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(rows)
+}
+
+/// Load comments and reviews of an open document and render them into the markdown
+///
+/// `yjs_parts` is the document's Yjs state and updates, used to find the
+/// block each comment is anchored in; empty when it is not available.
+fn annotate_markdown_for_export(
+    content: &str,
+    options: &ExportAnnotations,
+    yjs_parts: &[&[u8]],
+) -> Result<String, String> {
+    let history_path = get_document_history_path(&options.doc_id)?;
+    if !history_path.exists() {
+        return Ok(content.to_string());
+    }
+
+    let conn = Connection::open(&history_path).map_err(|e| e.to_string())?;
+    crate::db_utils::ensure_schema(&conn)?;
+    crate::comments::init_comments_table(&conn)?;
+
+    let comments = if options.include_comments {
+        load_all_comments(&conn)?
+    } else {
+        Vec::new()
+    };
+    let reviews = if options.include_reviews {
+        load_review_summary(&conn)?
+    } else {
+        Vec::new()
+    };
+
+    let blocks = if options.comment_placement != CommentPlacement::Appendix && !yjs_parts.is_empty() {
+        comment_anchor_blocks(&comments, yjs_parts)
+    } else {
+        HashMap::new()
+    };
+
+    Ok(render_export_annotations(content, &comments, &reviews, options.comment_placement, &blocks))
+}
+
+/// Markdown of the block each comment thread's start anchor points into, by thread id
+fn comment_anchor_blocks(comments: &[Comment], yjs_parts: &[&[u8]]) -> HashMap<i64, String> {
+    let threads: Vec<&Comment> = comments
+        .iter()
+        .filter(|c| c.parent_id.is_none() && !c.start_anchor.is_empty())
+        .collect();
+    let anchors: Vec<&str> = threads.iter().map(|c| c.start_anchor.as_str()).collect();
+    match anchor_blocks(yjs_parts, &anchors) {
+        Ok(blocks) => threads
+            .iter()
+            .zip(blocks)
+            .filter_map(|(thread, block)| Some((thread.id, block?)))
+            .collect(),
+        Err(e) => {
+            log::warn!("Cannot resolve comment anchors for export: {}", e);
+            HashMap::new()
+        }
+    }
+}
+
+/// Byte range of a thread's selected text in the content
+///
+/// The text is looked for inside the block the thread's anchor resolves to,
+/// so a phrase that appears several times is matched where it was commented,
+/// and anywhere in the content otherwise.
+fn locate_thread(content: &str, thread: &Comment, block: Option<&str>) -> Option<(usize, usize)> {
+    let selected = thread.selected_text.as_str();
+    if selected.is_empty() {
+        return None;
+    }
+    let in_block = block.filter(|b| !b.is_empty()).and_then(|block| {
+        let block_start = content.find(block)?;
+        let start = block.find(selected)?;
+        Some(block_start + start)
+    });
+    let start = in_block.or_else(|| content.find(selected))?;
+    Some((start, start + selected.len()))
+}
+
+/// Render a comment and its replies as a single line of markdown
+fn render_comment_line(comment: &Comment) -> String {
+    let status = if comment.status == "resolved" { " [resolved]" } else { "" };
+    format!(
+        "**{}** ({}){}: {}",
+        comment.author,
+        format_export_timestamp(comment.timestamp),
+        status,
+        comment.content.replace('\n', " ")
+    )
+}
+
+/// Render a thread and its replies as one line of plain text for inline comments
+fn render_inline_thread(thread: &Comment, replies: &[&Comment]) -> String {
+    std::iter::once(thread)
+        .chain(replies.iter().copied())
+        .map(|comment| {
+            let status = if comment.status == "resolved" { " [resolved]" } else { "" };
+            format!(
+                "{} ({}){}: {}",
+                comment.author,
+                format_export_timestamp(comment.timestamp),
+                status,
+                comment.content.replace('\n', " ")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// Text that cannot end an HTML comment early
+fn escape_html_comment(text: &str) -> String {
+    let mut text = text.to_string();
+    while text.contains("--") {
+        text = text.replace("--", "- -");
+    }
+    text
+}
+
+/// Append comment threads and a review decision summary to markdown content
+///
+/// Inline placements anchor each thread at its selected text, found in the
+/// block its anchor resolves to (`blocks`, by thread id) or else at its first
+/// occurrence; threads whose text no longer appears fall back to the appendix.
+/// Footnote markers go after the text, CriticMarkup highlights the text and
+/// adds the thread as a comment, and HTML comments go after the text.
+/// Highlights cannot overlap, so a thread overlapping an earlier highlight
+/// only gets the comment.
+fn render_export_annotations(
+    content: &str,
+    comments: &[Comment],
+    reviews: &[ReviewSummaryRow],
+    placement: CommentPlacement,
+    blocks: &HashMap<i64, String>,
+) -> String {
+    let threads: Vec<&Comment> = comments
+        .iter()
+        .filter(|c| c.parent_id.is_none() && c.status != "deleted")
+        .collect();
+    let replies_of = |id: i64| -> Vec<&Comment> {
+        comments
+            .iter()
+            .filter(|c| c.parent_id == Some(id) && c.status != "deleted")
+            .collect()
+    };
+
+    let mut result = content.to_string();
+    let mut footnotes = Vec::new();
+    let mut appendix = Vec::new();
+
+    // Find anchors first, then insert markers back to front so offsets stay valid
+    let mut anchored: Vec<(usize, usize, &Comment)> = Vec::new();
+    for thread in &threads {
+        let range = if placement == CommentPlacement::Appendix {
+            None
+        } else {
+            locate_thread(content, thread, blocks.get(&thread.id).map(String::as_str))
+        };
+        match range {
+            Some((start, end)) => anchored.push((start, end, thread)),
+            None => appendix.push(*thread),
+        }
+    }
+    anchored.sort_by_key(|(start, end, c)| (*start, *end, c.id));
+
+    // (offset, insertion); insertions at the same offset keep their order
+    let mut insertions: Vec<(usize, String)> = Vec::new();
+    let mut highlighted_until = 0;
+    for (start, end, thread) in &anchored {
+        let replies = replies_of(thread.id);
+        match placement {
+            CommentPlacement::Footnotes | CommentPlacement::Appendix => {
+                let label = format!("comment-{}", thread.id);
+                insertions.push((*end, format!("[^{}]", label)));
+
+                let mut note = format!("[^{}]: {}", label, render_comment_line(thread));
+                for reply in &replies {
+                    note.push_str(&format!("\n\n    {}", render_comment_line(reply)));
+                }
+                footnotes.push((thread.id, note));
+            }
+            CommentPlacement::CriticMarkup => {
+                let comment = format!("{{>>{}<<}}", render_inline_thread(thread, &replies).replace("<<}", "<< }"));
+                let text = &content[*start..*end];
+                if *start >= highlighted_until && !text.contains("==}") && !text.contains('\n') {
+                    insertions.push((*start, "{==".to_string()));
+                    insertions.push((*end, format!("==}}{}", comment)));
+                    highlighted_until = *end;
+                } else {
+                    // Inside an earlier highlight, the comment follows it
+                    insertions.push(((*end).max(highlighted_until), comment));
+                }
+            }
+            CommentPlacement::Html => {
+                let comment = escape_html_comment(&render_inline_thread(thread, &replies));
+                insertions.push((*end, format!("<!-- {} -->", comment)));
+            }
+        }
+    }
+    insertions.sort_by_key(|(offset, _)| *offset);
+    for (offset, text) in insertions.into_iter().rev() {
+        result.insert_str(offset, &text);
+    }
+    footnotes.sort_by_key(|(id, _)| *id);
+
+    if !footnotes.is_empty() {
+        result = result.trim_end().to_string();
+        result.push_str("\n\n");
+        result.push_str(
+            &footnotes
+                .into_iter()
+                .map(|(_, note)| note)
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+        );
+        result.push('\n');
+    }
+
+    if !appendix.is_empty() {
+        result = result.trim_end().to_string();
+        result.push_str("\n\n## Comments\n\n");
+        for thread in appendix {
+            if thread.selected_text.is_empty() {
+                result.push_str(&format!("- {}\n", render_comment_line(thread)));
+            } else {
+                result.push_str(&format!(
+                    "- On \"{}\": {}\n",
+                    thread.selected_text.replace('\n', " "),
+                    render_comment_line(thread)
+                ));
+            }
+            for reply in replies_of(thread.id) {
+                result.push_str(&format!("    - {}\n", render_comment_line(reply)));
+            }
+        }
+    }
+
+    if !reviews.is_empty() {
+        result = result.trim_end().to_string();
+        result.push_str("\n\n## Review Decisions\n\n");
+        result.push_str("| Patch | Author | Reviewer | Decision | Reviewed |\n");
+        result.push_str("|-------|--------|----------|----------|----------|\n");
+        for review in reviews {
+            let short_uuid: String = review.patch_uuid.chars().take(8).collect();
+            result.push_str(&format!(
+                "| {} | {} | {} | {} | {} |\n",
+                short_uuid,
+                review.patch_author.as_deref().unwrap_or("unknown"),
+                review.reviewer,
+                review.decision,
+                format_export_timestamp(review.reviewed_at)
+            ));
+        }
+    }
+
+    result
+}
+
+/// Resolved prefixes and numbering style for cross-references
+#[derive(Debug, Clone)]
+struct CrossRefFormat {
+    chapter_numbering: bool,
+    /// Sections are referred to by their heading number ("Section 2.1")
+    heading_numbers: bool,
+    figure: String,
+    table: String,
+    section: String,
+    equation: String,
+}
+
+impl Default for CrossRefFormat {
+    fn default() -> Self {
+        CrossRefFormat::for_language("en")
+    }
+}
+
+impl CrossRefFormat {
+    /// Localized default prefixes for a language tag
+    fn for_language(language: &str) -> Self {
+        let base = language.split(['-', '_']).next().unwrap_or("").to_lowercase();
+        let (figure, table, section, equation) = match base.as_str() {
+            "de" => ("Abbildung", "Tabelle", "Abschnitt", "Gleichung"),
+            "fr" => ("Figure", "Tableau", "Section", "Équation"),
+            "es" => ("Figura", "Tabla", "Sección", "Ecuación"),
+            "it" => ("Figura", "Tabella", "Sezione", "Equazione"),
+            "pt" => ("Figura", "Tabela", "Seção", "Equação"),
+            "fi" => ("Kuva", "Taulukko", "Luku", "Yhtälö"),
+            "sv" => ("Figur", "Tabell", "Avsnitt", "Ekvation"),
+            "nl" => ("Figuur", "Tabel", "Sectie", "Vergelijking"),
+            _ => ("Figure", "Table", "Section", "Equation"),
+        };
+        CrossRefFormat {
+            chapter_numbering: false,
+            heading_numbers: false,
+            figure: figure.to_string(),
+            table: table.to_string(),
+            section: section.to_string(),
+            equation: equation.to_string(),
+        }
+    }
+
+    fn from_settings(settings: Option<&DocumentSettings>) -> Self {
+        let Some(settings) = settings else {
+            return CrossRefFormat::default();
+        };
+        let defaults = CrossRefFormat::for_language(&settings.language);
+        let crossref = &settings.crossref;
+        CrossRefFormat {
+            chapter_numbering: crossref.chapter_numbering,
+            heading_numbers: settings.number_headings,
+            figure: crossref.figure_prefix.clone().unwrap_or(defaults.figure),
+            table: crossref.table_prefix.clone().unwrap_or(defaults.table),
+            section: crossref.section_prefix.clone().unwrap_or(defaults.section),
+            equation: crossref.equation_prefix.clone().unwrap_or(defaults.equation),
+        }
+    }
+}
+
+/// Cross-reference registries for figures, sections, tables and equations
 #[derive(Debug, Clone, Default)]
 struct CrossRefRegistry {
     figures: HashMap<String, u32>,
     sections: HashMap<String, u32>,
+    /// Heading numbers of labelled sections, when headings are numbered
+    section_numbers: HashMap<String, String>,
     tables: HashMap<String, u32>,
+    equations: HashMap<String, u32>,
+    /// Chapter (level-1 heading count) each figure, table and equation appears in
+    chapters: HashMap<String, u32>,
+    format: CrossRefFormat,
+}
+
+impl CrossRefRegistry {
+    /// Displayed number for a label, e.g. "3" or "2.3" with chapter numbering
+    fn display_number(&self, label: &str) -> Option<String> {
+        let num = if label.starts_with("fig:") {
+            self.figures.get(label)
+        } else if label.starts_with("tbl:") {
+            self.tables.get(label)
+        } else if label.starts_with("eq:") {
+            self.equations.get(label)
+        } else {
+            // Sections are numbered globally, or as their headings are
+            return self.section_numbers.get(label).cloned().or_else(|| self.sections.get(label).map(|n| n.to_string()));
+        }?;
+
+        match self.chapters.get(label) {
+            Some(&chapter) if self.format.chapter_numbering && chapter > 0 => {
+                Some(format!("{}.{}", chapter, num))
+            }
+            _ => Some(num.to_string()),
+        }
+    }
+}
+
+/// Number labels found by a regex, restarting per chapter when chapter numbering is on
+fn number_labels(
+    re: &Regex,
+    group: usize,
+    text: &str,
+    chapter_starts: &[usize],
+    registry: &mut CrossRefRegistry,
+    select: fn(&mut CrossRefRegistry) -> &mut HashMap<String, u32>,
+) {
+    let mut counters: HashMap<u32, u32> = HashMap::new();
+    for caps in re.captures_iter(text) {
+        if let Some(label_match) = caps.get(group) {
+            let label = label_match.as_str().to_string();
+            if select(registry).contains_key(&label) {
+                continue;
+            }
+            let chapter = chapter_starts.iter().filter(|&&start| start <= label_match.start()).count() as u32;
+            let key = if registry.format.chapter_numbering { chapter } else { 0 };
+            let counter = counters.entry(key).or_insert(0);
+            *counter += 1;
+            let number = *counter;
+            registry.chapters.insert(label.clone(), chapter);
+            select(registry).insert(label, number);
+        }
+    }
+}
+
+/// Number of the next heading at a level ("2.1"), counting it in `counters`
+fn next_heading_number(counters: &mut [u32; 6], level: usize) -> String {
+    let level = level.clamp(1, 6);
+    counters[level - 1] += 1;
+    counters[level..].iter_mut().for_each(|c| *c = 0);
+    counters[..level].iter().map(|n| n.to_string()).collect::<Vec<_>>().join(".")
 }
 
 /// Build registries for all cross-reference types by scanning the markdown
-fn build_crossref_registry(markdown: &str) -> CrossRefRegistry {
-    let mut registry = CrossRefRegistry::default();
-    let mut fig_counter = 0u32;
+fn build_crossref_registry(markdown: &str, settings: Option<&DocumentSettings>) -> CrossRefRegistry {
+    let mut registry = CrossRefRegistry {
+        format: CrossRefFormat::from_settings(settings),
+        ..CrossRefRegistry::default()
+    };
     let mut sec_counter = 0u32;
-    let mut tbl_counter = 0u32;
 
     // Remove fenced code blocks and inline code before scanning to avoid matching examples
     let code_block_re = Regex::new(r"(?s)```.*?```").unwrap();
@@ -454,17 +1316,13 @@ fn build_crossref_registry(markdown: &str) -> CrossRefRegistry {
     let inline_code_re = Regex::new(r"`[^`]+`").unwrap();
     let markdown_no_code = inline_code_re.replace_all(&markdown_no_code, "");
 
+    // Chapters start at level-1 headings
+    let chapter_re = Regex::new(r"(?m)^#\s+").unwrap();
+    let chapter_starts: Vec<usize> = chapter_re.find_iter(&markdown_no_code).map(|m| m.start()).collect();
+
     // Match figure syntax: ![caption](url){#fig:label}
     let figure_re = Regex::new(r"!\[([^\]]*)\]\(([^)]+)\)\{#(fig:[^}]+)\}").unwrap();
-    for caps in figure_re.captures_iter(&markdown_no_code) {
-        if let Some(label_match) = caps.get(3) {
-            let label = label_match.as_str().to_string();
-            if !registry.figures.contains_key(&label) {
-                fig_counter += 1;
-                registry.figures.insert(label, fig_counter);
-            }
-        }
-    }
+    number_labels(&figure_re, 3, &markdown_no_code, &chapter_starts, &mut registry, |r| &mut r.figures);
 
     // Match section syntax: # Heading {#sec:label}
     let section_re = Regex::new(r"(?m)^#{1,6}\s+.*\{#(sec:[^}]+)\}").unwrap();
@@ -478,51 +1336,74 @@ fn build_crossref_registry(markdown: &str) -> CrossRefRegistry {
         }
     }
 
-    // Match table syntax: {#tbl:label}
-    let table_re = Regex::new(r"\{#(tbl:[^}]+)\}").unwrap();
-    for caps in table_re.captures_iter(&markdown_no_code) {
-        if let Some(label_match) = caps.get(1) {
-            let label = label_match.as_str().to_string();
-            if !registry.tables.contains_key(&label) {
-                tbl_counter += 1;
-                registry.tables.insert(label, tbl_counter);
+    if registry.format.heading_numbers {
+        let heading_re = Regex::new(r"(?m)^(#{1,6})[ \t]+(.*)$").unwrap();
+        let label_re = Regex::new(r"\{#(sec:[^}]+)\}").unwrap();
+        let mut counters = [0u32; 6];
+        for caps in heading_re.captures_iter(&markdown_no_code) {
+            let number = next_heading_number(&mut counters, caps[1].len());
+            if let Some(label) = label_re.captures(&caps[2]) {
+                registry.section_numbers.entry(label[1].to_string()).or_insert(number);
             }
         }
     }
 
+    // Match table syntax: {#tbl:label}
+    let table_re = Regex::new(r"\{#(tbl:[^}]+)\}").unwrap();
+    number_labels(&table_re, 1, &markdown_no_code, &chapter_starts, &mut registry, |r| &mut r.tables);
+
+    // Match equation syntax: $$ ... $$ {#eq:label}
+    let equation_re = Regex::new(r"\$\$\s*\{#(eq:[^}]+)\}").unwrap();
+    number_labels(&equation_re, 1, &markdown_no_code, &chapter_starts, &mut registry, |r| &mut r.equations);
+
     registry
 }
 
 /// Get reference text for a label
 fn get_reference_text(label: &str, registry: &CrossRefRegistry) -> String {
-    if label.starts_with("fig:") {
-        if let Some(&num) = registry.figures.get(label) {
-            return format!("Figure {}", num);
-        }
+    let prefix = if label.starts_with("fig:") {
+        &registry.format.figure
     } else if label.starts_with("sec:") {
-        if let Some(&num) = registry.sections.get(label) {
-            return format!("Section {}", num);
-        }
+        &registry.format.section
     } else if label.starts_with("tbl:") {
-        if let Some(&num) = registry.tables.get(label) {
-            return format!("Table {}", num);
-        }
+        &registry.format.table
+    } else if label.starts_with("eq:") {
+        &registry.format.equation
+    } else {
+        return format!("[{}]", label);
+    };
+
+    match registry.display_number(label) {
+        Some(number) => format!("{} {}", prefix, number),
+        None => format!("[{}]", label),
     }
-    format!("[{}]", label)
+}
+
+/// Reference text of a section label in a document, e.g. "Section 2"
+pub(crate) fn section_reference_text(markdown: &str, settings: Option<&DocumentSettings>, label: &str) -> Option<String> {
+    let registry = build_crossref_registry(markdown, settings);
+    registry.sections.contains_key(label).then(|| get_reference_text(label, &registry))
 }
 
 /// Pre-process markdown to handle cross-references
 /// - Replaces @fig:label with "Figure N"
 /// - Replaces @sec:label with "Section N"
 /// - Replaces @tbl:label with "Table N"
+/// - Replaces @eq:label with "Equation N" and numbers the labelled equation
 /// - Removes {#sec:label} from headings
 /// - Removes {#tbl:label} from after tables
+/// - Prefixes and numbering follow the document's crossref settings
 /// - Converts ![caption](url){#fig:label} to standard ![caption](url)
-fn preprocess_markdown_for_docx(markdown: &str, registry: &CrossRefRegistry) -> String {
+/// - Applies locale typography unless disabled in the document settings
+fn preprocess_markdown_for_docx(
+    markdown: &str,
+    registry: &CrossRefRegistry,
+    settings: Option<&DocumentSettings>,
+) -> String {
     let mut result = markdown.to_string();
 
-    // Replace all cross-references: @fig:label, @sec:label, @tbl:label
-    let ref_re = Regex::new(r"@((?:fig|sec|tbl):[a-zA-Z0-9_-]+)").unwrap();
+    // Replace all cross-references: @fig:label, @sec:label, @tbl:label, @eq:label
+    let ref_re = Regex::new(r"@((?:fig|sec|tbl|eq):[a-zA-Z0-9_-]+)").unwrap();
     result = ref_re
         .replace_all(&result, |caps: &regex::Captures| {
             let label = caps.get(1).map(|m| m.as_str()).unwrap_or("");
@@ -543,6 +1424,22 @@ fn preprocess_markdown_for_docx(markdown: &str, registry: &CrossRefRegistry) ->
     let tbl_label_re = Regex::new(r"\s*\{#tbl:[^}]+\}").unwrap();
     result = tbl_label_re.replace_all(&result, "").to_string();
 
+    // Number labelled display equations: $$ ... $$ {#eq:label} -> $$ ... \qquad (N)$$
+    let eq_label_re = Regex::new(r"\$\$\s*\{#(eq:[^}]+)\}").unwrap();
+    result = eq_label_re
+        .replace_all(&result, |caps: &regex::Captures| {
+            let label = caps.get(1).map(|m| m.as_str()).unwrap_or("");
+            match registry.display_number(label) {
+                Some(number) => format!(" \\qquad ({})$$", number),
+                None => "$$".to_string(),
+            }
+        })
+        .to_string();
+
+    if let Some(settings) = settings.filter(|s| s.smart_typography) {
+        result = apply_typography(&result, &settings.language);
+    }
+
     result
 }
 
@@ -563,22 +1460,212 @@ fn extract_figure_from_parsed_text(text: &str) -> Option<(String, String)> {
     }
 }
 
+/// Collect the text of footnote definitions, one entry per paragraph, keyed by label
+fn collect_footnote_definitions(markdown: &str) -> HashMap<String, Vec<String>> {
+    let mut definitions: HashMap<String, Vec<String>> = HashMap::new();
+    let mut current: Option<String> = None;
+
+    let parser = Parser::new_ext(markdown, Options::ENABLE_FOOTNOTES | Options::ENABLE_STRIKETHROUGH);
+    for event in parser {
+        match event {
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                current = Some(label.to_string());
+                definitions.entry(label.to_string()).or_default();
+            }
+            Event::End(TagEnd::FootnoteDefinition) => current = None,
+            Event::Start(Tag::Paragraph) => {
+                if let Some(label) = &current {
+                    definitions.entry(label.clone()).or_default().push(String::new());
+                }
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(label) = &current {
+                    let paragraphs = definitions.entry(label.clone()).or_default();
+                    match paragraphs.last_mut() {
+                        Some(last) => last.push_str(&text),
+                        None => paragraphs.push(text.to_string()),
+                    }
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some(last) = current.as_ref().and_then(|l| definitions.get_mut(l)).and_then(|p| p.last_mut()) {
+                    last.push(' ');
+                }
+            }
+            _ => {}
+        }
+    }
+
+    definitions
+}
+
+/// Abstract numbering definitions used for lists in the DOCX fallback
+const BULLET_ABSTRACT_NUM_ID: usize = 10;
+const ORDERED_ABSTRACT_NUM_ID: usize = 11;
+/// Numbering instance shared by all bullet lists
+const BULLET_NUM_ID: usize = 10;
+/// Ordered lists each get their own instance so numbering restarts
+const FIRST_ORDERED_NUM_ID: usize = 11;
+/// Word supports nine list levels
+const MAX_LIST_LEVEL: usize = 8;
+
+/// Build a nine-level abstract numbering for bullet or ordered lists
+fn list_abstract_numbering(ordered: bool) -> AbstractNumbering {
+    let id = if ordered { ORDERED_ABSTRACT_NUM_ID } else { BULLET_ABSTRACT_NUM_ID };
+    let mut numbering = AbstractNumbering::new(id);
+    for level in 0..=MAX_LIST_LEVEL {
+        let (format, text) = if ordered {
+            // 1. a. i. repeating
+            let format = ["decimal", "lowerLetter", "lowerRoman"][level % 3];
+            (format, format!("%{}.", level + 1))
+        } else {
+            ("bullet", ["•", "◦", "▪"][level % 3].to_string())
+        };
+        numbering = numbering.add_level(
+            Level::new(level, Start::new(1), NumberFormat::new(format), LevelText::new(text), LevelJc::new("left"))
+                .indent(Some(720 * (level as i32 + 1)), Some(SpecialIndentType::Hanging(360)), None, None),
+        );
+    }
+    numbering
+}
+
+/// A list being written: its numbering instance and whether the current item
+/// still needs its marker
+#[derive(Debug, Clone)]
+struct ListContext {
+    num_id: usize,
+    item_marker_pending: bool,
+}
+
+/// Attach list numbering to a paragraph inside a (possibly nested) list.
+/// Only the first paragraph of an item carries the marker; later ones are indented.
+fn apply_list_numbering(para: Paragraph, list_stack: &mut [ListContext]) -> Paragraph {
+    let level = list_stack.len().saturating_sub(1).min(MAX_LIST_LEVEL);
+    match list_stack.last_mut() {
+        Some(list) if list.item_marker_pending => {
+            list.item_marker_pending = false;
+            para.numbering(NumberingId::new(list.num_id), IndentLevel::new(level))
+        }
+        Some(_) => para.indent(Some(720 * (level as i32 + 1)), None, None, None),
+        None => para,
+    }
+}
+
+/// Word bookmark name for a heading id: letters, digits and underscores,
+/// starting with a letter and at most 40 characters
+fn bookmark_name(id: &str) -> String {
+    let mut name: String = id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name.insert_str(0, "bm_");
+    }
+    name.truncate(40);
+    name
+}
+
+/// Turn section labels into heading ids and @sec: references into internal links.
+/// Must run before preprocess_markdown_for_docx, which strips {#sec:label}.
+fn link_section_references(markdown: &str, registry: &CrossRefRegistry) -> String {
+    let heading_re = Regex::new(r"(?m)^(#{1,6}\s+.*)\{#(sec:[^}]+)\}").unwrap();
+    let result = heading_re.replace_all(markdown, |caps: &regex::Captures| {
+        format!("{}{{#{}}}", &caps[1], bookmark_name(&caps[2]))
+    });
+
+    // The reference itself stays in the link text and is resolved by preprocessing
+    let ref_re = Regex::new(r"@(sec:[a-zA-Z0-9_-]+)").unwrap();
+    ref_re
+        .replace_all(&result, |caps: &regex::Captures| {
+            let label = &caps[1];
+            if registry.sections.contains_key(label) {
+                format!("[@{}](#{})", label, bookmark_name(label))
+            } else {
+                caps[0].to_string()
+            }
+        })
+        .to_string()
+}
+
+/// A static table of contents as a markdown list, for the built-in DOCX
+/// writer
+///
+/// Lists the headings down to `depth`, with their numbers when headings are
+/// numbered. Labelled sections carry their cross-reference number and link
+/// to their heading's bookmark.
+fn table_of_contents_markdown(markdown: &str, registry: &CrossRefRegistry, depth: u8) -> String {
+    struct Toc<'a> {
+        registry: &'a CrossRefRegistry,
+        depth: u8,
+        counters: [u32; 6],
+        entries: String,
+    }
+
+    fn add_entries(nodes: &[OutlineNode], indent: usize, toc: &mut Toc) {
+        let (registry, depth) = (toc.registry, toc.depth);
+        for node in nodes.iter().filter(|n| n.level <= depth) {
+            let title = node.title.replace('[', "\\[").replace(']', "\\]");
+            let label = node.label.as_deref().filter(|l| registry.sections.contains_key(*l));
+            let number = if registry.format.heading_numbers {
+                Some(next_heading_number(&mut toc.counters, node.level as usize))
+            } else {
+                label.and_then(|l| registry.display_number(l))
+            };
+            let text = match number {
+                Some(number) => format!("{} {}", number, title),
+                None => title,
+            };
+            let entry = match label {
+                Some(label) => format!("[{}](#{})", text, bookmark_name(label)),
+                None => text,
+            };
+            toc.entries.push_str(&format!("{}- {}\n", "  ".repeat(indent), entry));
+            add_entries(&node.children, indent + 1, toc);
+        }
+    }
+
+    let mut toc = Toc { registry, depth, counters: [0; 6], entries: String::new() };
+    add_entries(&build_outline(markdown), 0, &mut toc);
+    let entries = toc.entries;
+    if entries.is_empty() {
+        return String::new();
+    }
+    format!("**Contents**\n\n{}\n", entries)
+}
+
+/// Collect the bookmark names of all headings with an id
+fn collect_heading_bookmarks(markdown: &str) -> HashSet<String> {
+    Parser::new_ext(markdown, Options::ENABLE_HEADING_ATTRIBUTES)
+        .filter_map(|event| match event {
+            Event::Start(Tag::Heading { id: Some(id), .. }) => Some(bookmark_name(&id)),
+            _ => None,
+        })
+        .collect()
+}
+
 /// Convert markdown to DOCX format
-fn markdown_to_docx(markdown: &str) -> Result<Docx, String> {
+fn markdown_to_docx(markdown: &str, settings: Option<&DocumentSettings>) -> Result<Docx, String> {
     // Build cross-reference registry for all types (figures, sections, tables)
-    let crossref_registry = build_crossref_registry(markdown);
+    let crossref_registry = build_crossref_registry(markdown, settings);
 
-    // Pre-process markdown to resolve cross-references
-    let processed_markdown = preprocess_markdown_for_docx(markdown, &crossref_registry);
+    // Pre-process markdown to resolve cross-references, linking section references
+    let mut linked_markdown = link_section_references(markdown, &crossref_registry);
+    if let Some(settings) = settings.filter(|s| s.toc) {
+        let toc = table_of_contents_markdown(markdown, &crossref_registry, settings.toc_depth);
+        linked_markdown.insert_str(0, &toc);
+    }
+    let processed_markdown = preprocess_markdown_for_docx(&linked_markdown, &crossref_registry, settings);
 
-    let mut docx = Docx::new();
+    let mut docx = Docx::new()
+        .add_abstract_numbering(list_abstract_numbering(false))
+        .add_abstract_numbering(list_abstract_numbering(true))
+        .add_numbering(Numbering::new(BULLET_NUM_ID, BULLET_ABSTRACT_NUM_ID));
 
     let mut current_paragraph = Paragraph::new();
     let mut current_text = String::new();
     let mut in_paragraph = false;
-    let mut list_items: Vec<Paragraph> = Vec::new();
-    let mut in_list = false;
-    let mut is_ordered_list = false;
+    let mut list_stack: Vec<ListContext> = Vec::new();
+    let mut next_ordered_num_id = FIRST_ORDERED_NUM_ID;
 
     // Stack to track formatting
     let mut bold_depth: i32 = 0;
@@ -588,6 +1675,20 @@ fn markdown_to_docx(markdown: &str) -> Result<Docx, String> {
     let mut code_text = String::new();
     let mut paragraph_style: Option<String> = None;
 
+    // Footnote definitions are rendered at their references, not in the body
+    let footnote_definitions = collect_footnote_definitions(&processed_markdown);
+    let use_endnotes = settings.is_some_and(|s| s.endnotes);
+    let mut endnote_order: Vec<String> = Vec::new();
+    let mut in_footnote_definition = false;
+
+    // Headings with an id become bookmarks that internal links can target
+    let heading_bookmarks = collect_heading_bookmarks(&processed_markdown);
+    let number_headings = settings.is_some_and(|s| s.number_headings);
+    let mut heading_counters = [0u32; 6];
+    let mut next_bookmark_id = 0usize;
+    let mut heading_bookmark: Option<usize> = None;
+    let mut link_dest: Option<String> = None;
+
     // Helper function to flush current text with formatting
     let flush_text = |para: Paragraph,
                       text: &str,
@@ -611,16 +1712,26 @@ fn markdown_to_docx(markdown: &str) -> Result<Docx, String> {
         para.add_run(run)
     };
 
-    // Enable GFM extensions (strikethrough)
+    // Enable GFM extensions (strikethrough, footnotes)
     let mut options = Options::empty();
     options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
     let parser = Parser::new_ext(&processed_markdown, options);
 
     for event in parser {
+        // Skip definition bodies; they were collected up front
+        if in_footnote_definition {
+            if let Event::End(TagEnd::FootnoteDefinition) = event {
+                in_footnote_definition = false;
+            }
+            continue;
+        }
+
         match event {
             Event::Start(tag) => {
                 match tag {
-                    Tag::Heading { level, .. } => {
+                    Tag::Heading { level, id, .. } => {
                         // Flush any existing paragraph
                         if in_paragraph && !current_text.is_empty() {
                             current_paragraph = flush_text(
@@ -647,6 +1758,14 @@ fn markdown_to_docx(markdown: &str) -> Result<Docx, String> {
                         };
                         paragraph_style = Some(format!("Heading{}", heading_level));
                         current_paragraph = Paragraph::new();
+                        if number_headings {
+                            current_text = format!("{} ", next_heading_number(&mut heading_counters, heading_level));
+                        }
+                        if let Some(id) = id {
+                            current_paragraph = current_paragraph.add_bookmark_start(next_bookmark_id, bookmark_name(&id));
+                            heading_bookmark = Some(next_bookmark_id);
+                            next_bookmark_id += 1;
+                        }
                         in_paragraph = true;
                     }
                     Tag::Paragraph => {
@@ -696,11 +1815,46 @@ fn markdown_to_docx(markdown: &str) -> Result<Docx, String> {
                         strikethrough_depth += 1;
                     }
                     Tag::List(start_num) => {
-                        in_list = true;
-                        is_ordered_list = start_num.is_some();
+                        // A nested list ends the text of the enclosing item
+                        if in_paragraph && !list_stack.is_empty() {
+                            if !current_text.is_empty() {
+                                current_paragraph = flush_text(
+                                    current_paragraph,
+                                    &current_text,
+                                    bold_depth > 0,
+                                    italic_depth > 0,
+                                    strikethrough_depth > 0,
+                                );
+                                current_text.clear();
+                            }
+                            docx = docx.add_paragraph(apply_list_numbering(current_paragraph, &mut list_stack));
+                            current_paragraph = Paragraph::new();
+                            in_paragraph = false;
+                        }
+
+                        let num_id = match start_num {
+                            Some(start) => {
+                                let num_id = next_ordered_num_id;
+                                next_ordered_num_id += 1;
+                                let level = list_stack.len().min(MAX_LIST_LEVEL);
+                                docx = docx.add_numbering(
+                                    Numbering::new(num_id, ORDERED_ABSTRACT_NUM_ID)
+                                        .add_override(LevelOverride::new(level).start(start as usize)),
+                                );
+                                num_id
+                            }
+                            None => BULLET_NUM_ID,
+                        };
+                        list_stack.push(ListContext {
+                            num_id,
+                            item_marker_pending: false,
+                        });
                     }
                     Tag::Item => {
                         // Start a new list item
+                        if let Some(list) = list_stack.last_mut() {
+                            list.item_marker_pending = true;
+                        }
                         current_paragraph = Paragraph::new();
                         in_paragraph = true;
                     }
@@ -717,6 +1871,23 @@ fn markdown_to_docx(markdown: &str) -> Result<Docx, String> {
                     Tag::Image { .. } => {
                         // Images are handled at the Event::End
                     }
+                    Tag::FootnoteDefinition(_) => {
+                        in_footnote_definition = true;
+                    }
+                    Tag::Link { dest_url, .. } => {
+                        // Flush text before the link so only the link text is collected
+                        if !current_text.is_empty() {
+                            current_paragraph = flush_text(
+                                current_paragraph,
+                                &current_text,
+                                bold_depth > 0,
+                                italic_depth > 0,
+                                strikethrough_depth > 0,
+                            );
+                            current_text.clear();
+                        }
+                        link_dest = Some(dest_url.to_string());
+                    }
                     _ => {}
                 }
             }
@@ -730,8 +1901,8 @@ fn markdown_to_docx(markdown: &str) -> Result<Docx, String> {
                                 extract_figure_from_parsed_text(&full_text)
                             {
                                 // This is a figure - output it as such
-                                let fig_num =
-                                    crossref_registry.figures.get(&label).copied().unwrap_or(0);
+                                let fig_num = crossref_registry.display_number(&label);
+                                let fig_prefix = &crossref_registry.format.figure;
 
                                 // Create centered paragraph for the figure placeholder
                                 let figure_para = Paragraph::new()
@@ -740,10 +1911,9 @@ fn markdown_to_docx(markdown: &str) -> Result<Docx, String> {
                                 docx = docx.add_paragraph(figure_para);
 
                                 // Create caption paragraph
-                                let caption_text = if fig_num > 0 {
-                                    format!("Figure {}: {}", fig_num, caption)
-                                } else {
-                                    format!("Figure: {}", caption)
+                                let caption_text = match fig_num {
+                                    Some(num) => format!("{} {}: {}", fig_prefix, num, caption),
+                                    None => format!("{}: {}", fig_prefix, caption),
                                 };
                                 let caption_para = Paragraph::new()
                                     .add_run(Run::new().add_text(caption_text).italic())
@@ -755,6 +1925,7 @@ fn markdown_to_docx(markdown: &str) -> Result<Docx, String> {
                                 current_paragraph = Paragraph::new();
                                 in_paragraph = false;
                                 paragraph_style = None;
+                                heading_bookmark = None;
                             } else {
                                 // Regular paragraph
                                 if !current_text.is_empty() {
@@ -768,16 +1939,17 @@ fn markdown_to_docx(markdown: &str) -> Result<Docx, String> {
                                     current_text.clear();
                                 }
 
+                                if let Some(bookmark_id) = heading_bookmark.take() {
+                                    current_paragraph = current_paragraph.add_bookmark_end(bookmark_id);
+                                }
+
                                 // Apply style if any
                                 if let Some(ref style) = paragraph_style {
                                     current_paragraph = current_paragraph.style(style);
                                 }
 
-                                if in_list {
-                                    list_items.push(current_paragraph);
-                                } else {
-                                    docx = docx.add_paragraph(current_paragraph);
-                                }
+                                current_paragraph = apply_list_numbering(current_paragraph, &mut list_stack);
+                                docx = docx.add_paragraph(current_paragraph);
 
                                 current_paragraph = Paragraph::new();
                                 in_paragraph = false;
@@ -828,20 +2000,23 @@ fn markdown_to_docx(markdown: &str) -> Result<Docx, String> {
                         strikethrough_depth = strikethrough_depth.saturating_sub(1);
                     }
                     TagEnd::List(_) => {
-                        // Add all collected list items
-                        for item in list_items.drain(..) {
-                            let indented_item = if is_ordered_list {
-                                item.numbering(NumberingId::new(2), IndentLevel::new(0))
-                            } else {
-                                item.numbering(NumberingId::new(1), IndentLevel::new(0))
-                            };
-                            docx = docx.add_paragraph(indented_item);
-                        }
-                        in_list = false;
-                        is_ordered_list = false;
+                        list_stack.pop();
                     }
-                    TagEnd::Item => {
-                        // Item end is handled by paragraph end
+                    TagEnd::Item if in_paragraph => {
+                        // Tight list items have no paragraph of their own
+                        if !current_text.is_empty() {
+                            current_paragraph = flush_text(
+                                current_paragraph,
+                                &current_text,
+                                bold_depth > 0,
+                                italic_depth > 0,
+                                strikethrough_depth > 0,
+                            );
+                            current_text.clear();
+                        }
+                        docx = docx.add_paragraph(apply_list_numbering(current_paragraph, &mut list_stack));
+                        current_paragraph = Paragraph::new();
+                        in_paragraph = false;
                     }
                     TagEnd::CodeBlock => {
                         if in_code_block {
@@ -881,6 +2056,41 @@ fn markdown_to_docx(markdown: &str) -> Result<Docx, String> {
                     TagEnd::Image => {
                         // Image was already processed through the text events
                     }
+                    TagEnd::Link => {
+                        let dest = link_dest.take().unwrap_or_default();
+                        // Fall back to the URL when the link has no text
+                        let display = if current_text.trim().is_empty() {
+                            dest.clone()
+                        } else {
+                            std::mem::take(&mut current_text)
+                        };
+                        current_text.clear();
+
+                        let target = match dest.strip_prefix('#') {
+                            Some(anchor) => {
+                                let name = bookmark_name(anchor);
+                                heading_bookmarks.contains(&name).then_some((name, HyperlinkType::Anchor))
+                            }
+                            None if !dest.is_empty() => Some((dest, HyperlinkType::External)),
+                            None => None,
+                        };
+
+                        match target {
+                            Some((target, link_type)) => {
+                                let mut run = Run::new().add_text(display).color("0563C1").underline("single");
+                                if bold_depth > 0 {
+                                    run = run.bold();
+                                }
+                                if italic_depth > 0 {
+                                    run = run.italic();
+                                }
+                                current_paragraph =
+                                    current_paragraph.add_hyperlink(Hyperlink::new(target, link_type).add_run(run));
+                            }
+                            // Missing target: keep the text without a link
+                            None => current_text.push_str(&display),
+                        }
+                    }
                     _ => {}
                 }
             }
@@ -916,6 +2126,48 @@ fn markdown_to_docx(markdown: &str) -> Result<Docx, String> {
                     current_text.push(' ');
                 }
             }
+            Event::FootnoteReference(label) => {
+                // Flush current text so the reference lands in place
+                if !current_text.is_empty() {
+                    current_paragraph = flush_text(
+                        current_paragraph,
+                        &current_text,
+                        bold_depth > 0,
+                        italic_depth > 0,
+                        strikethrough_depth > 0,
+                    );
+                    current_text.clear();
+                }
+
+                let label = label.to_string();
+                match footnote_definitions.get(&label) {
+                    Some(_) if use_endnotes => {
+                        let number = match endnote_order.iter().position(|l| *l == label) {
+                            Some(index) => index + 1,
+                            None => {
+                                endnote_order.push(label);
+                                endnote_order.len()
+                            }
+                        };
+                        current_paragraph = current_paragraph.add_run(
+                            Run::new()
+                                .add_text(number.to_string())
+                                .vert_align(VertAlignType::SuperScript),
+                        );
+                    }
+                    Some(paragraphs) => {
+                        let mut footnote = Footnote::new();
+                        for text in paragraphs {
+                            footnote = footnote.add_content(Paragraph::new().add_run(Run::new().add_text(text)));
+                        }
+                        current_paragraph = current_paragraph.add_run(Run::new().add_footnote_reference(footnote));
+                    }
+                    None => {
+                        // Keep the marker visible when the definition is missing
+                        current_text.push_str(&format!("[^{}]", label));
+                    }
+                }
+            }
             _ => {}
         }
     }
@@ -937,27 +2189,45 @@ fn markdown_to_docx(markdown: &str) -> Result<Docx, String> {
         docx = docx.add_paragraph(current_paragraph);
     }
 
-    Ok(docx)
-}
+    // Endnotes go in a closing "Notes" section
+    if !endnote_order.is_empty() {
+        docx = docx.add_paragraph(
+            Paragraph::new()
+                .add_run(Run::new().add_text("Notes"))
+                .style("Heading1"),
+        );
+        for (index, label) in endnote_order.iter().enumerate() {
+            let paragraphs = footnote_definitions.get(label).cloned().unwrap_or_default();
+            for (i, text) in paragraphs.iter().enumerate() {
+                let text = if i == 0 {
+                    format!("{}. {}", index + 1, text)
+                } else {
+                    text.clone()
+                };
+                docx = docx.add_paragraph(Paragraph::new().add_run(Run::new().add_text(text)));
+            }
+        }
+    }
 
-/// Check if pandoc is available on the system
-fn is_pandoc_available() -> bool {
-    use std::process::Command;
-    Command::new("pandoc")
-        .arg("--version")
-        .output()
-        .map(|o| o.status.success())
-        .unwrap_or(false)
+    Ok(docx)
 }
 
 /// Export markdown to DOCX using pandoc
-fn export_with_pandoc(path: &str, content: &str) -> Result<(), String> {
-    use std::process::{Command, Stdio};
+/// Convert markdown with pandoc to `to` ("docx", "html", ...); `None` lets
+/// pandoc pick the format from the output extension, as needed for PDF
+fn export_with_pandoc(
+    path: &str,
+    content: &str,
+    to: Option<&str>,
+    settings: Option<&DocumentSettings>,
+    meta: Option<&DocumentMeta>,
+) -> Result<(), KorppiError> {
+    use std::process::Stdio;
     use std::io::Write;
     
     // Preprocess the markdown to convert custom syntax to standard markdown
-    let crossref_registry = build_crossref_registry(content);
-    let mut processed_content = preprocess_markdown_for_docx(content, &crossref_registry);
+    let crossref_registry = build_crossref_registry(content, settings);
+    let mut processed_content = preprocess_markdown_for_docx(content, &crossref_registry, settings);
     
     // Convert Tauri asset:// URLs back to absolute paths for pandoc
     // asset://localhost/%2Fpath%2Fto%2Ffile -> /path/to/file
@@ -982,17 +2252,49 @@ fn export_with_pandoc(path: &str, content: &str) -> Result<(), String> {
         }
         decoded
     }).to_string();
+    // pandoc reads the metadata from frontmatter into the document properties
+    processed_content = with_metadata_frontmatter(processed_content, meta);
     
-    let mut child = Command::new("pandoc")
+    let mut command = pandoc_command();
+    command
         .arg("-f")
         .arg("markdown")
-        .arg("-t")
-        .arg("docx")
         .arg("-o")
-        .arg(path)
+        .arg(path);
+    match to {
+        Some("html") => {
+            command.arg("-t").arg("html").arg("--standalone");
+        }
+        Some(format) => {
+            command.arg("-t").arg(format);
+        }
+        None => {}
+    }
+    if let Some(reference_doc) = settings.and_then(|s| s.reference_doc.as_ref()).filter(|p| p.exists()) {
+        command.arg(format!("--reference-doc={}", reference_doc.display()));
+    }
+    if let Some(settings) = settings.filter(|s| s.toc) {
+        command.arg("--toc").arg(format!("--toc-depth={}", settings.toc_depth));
+    }
+    if settings.is_some_and(|s| s.number_headings) {
+        command.arg("--number-sections");
+    }
+    // PDF line numbers come from the LaTeX lineno package; the header file
+    // must outlive pandoc
+    let mut line_numbers_header = None;
+    if to.is_none() && settings.is_some_and(|s| s.line_numbers) {
+        let mut header = tempfile::NamedTempFile::new().map_err(|e| e.to_string())?;
+        header
+            .write_all(b"\\usepackage{lineno}\n\\linenumbers\n")
+            .map_err(|e| format!("Failed to write LaTeX header: {}", e))?;
+        command.arg("--include-in-header").arg(header.path());
+        line_numbers_header = Some(header);
+    }
+    
+    let mut child = command
         .stdin(Stdio::piped())
         .spawn()
-        .map_err(|e| format!("Failed to start pandoc: {}", e))?;
+        .map_err(|e| KorppiError::PandocUnavailable(format!("Failed to start pandoc: {}", e)))?;
     
     if let Some(mut stdin) = child.stdin.take() {
         stdin.write_all(processed_content.as_bytes())
@@ -1002,8 +2304,9 @@ fn export_with_pandoc(path: &str, content: &str) -> Result<(), String> {
     let status = child.wait()
         .map_err(|e| format!("Failed to wait for pandoc: {}", e))?;
     
+    drop(line_numbers_header);
     if !status.success() {
-        return Err("Pandoc conversion failed".to_string());
+        return Err("Pandoc conversion failed".into());
     }
     
     Ok(())
@@ -1011,24 +2314,90 @@ fn export_with_pandoc(path: &str, content: &str) -> Result<(), String> {
 
 /// Export markdown content as a DOCX file
 /// Uses pandoc if available for better quality output, falls back to docx_rs library
+/// Optionally includes comments and review decisions of an open document
+/// and applies the typography rules of the document language.
+/// References to sections of other documents are resolved first.
+/// The metadata of the document `doc_id` becomes the document properties.
 #[tauri::command]
-pub fn export_docx(path: String, content: String) -> Result<(), String> {
+pub fn export_docx(
+    manager: State<'_, Mutex<DocumentManager>>,
+    path: String,
+    content: String,
+    annotations: Option<ExportAnnotations>,
+    settings: Option<DocumentSettings>,
+    doc_id: Option<String>,
+) -> Result<(), KorppiError> {
+    let meta = document_meta(&manager, doc_id.as_deref())?;
+    write_docx_export(path, content, annotations, settings, meta.as_ref())
+}
+
+/// Write markdown content as a DOCX file
+///
+/// The metadata only reaches the document properties through pandoc.
+pub fn write_docx_export(
+    path: String,
+    content: String,
+    annotations: Option<ExportAnnotations>,
+    settings: Option<DocumentSettings>,
+    meta: Option<&DocumentMeta>,
+) -> Result<(), KorppiError> {
+    let pandoc_available = is_pandoc_available();
+
+    let content = match annotations {
+        Some(options) => annotate_markdown_for_export(&content, &options, &[])?,
+        None => content,
+    };
+    let content = resolve_document_references_for_export(&content);
+    let heading_styles = settings.as_ref().map(|s| s.heading_styles.clone()).unwrap_or_default();
+    validate_heading_styles(&heading_styles).map_err(KorppiError::InvalidInput)?;
+
     // Try pandoc first for better quality output
-    if is_pandoc_available() {
-        return export_with_pandoc(&path, &content);
-    }
-    
-    // Fallback to Rust docx_rs library
-    let docx = markdown_to_docx(&content)?;
+    if pandoc_available {
+        export_with_pandoc(&path, &content, Some("docx"), settings.as_ref(), meta)?;
+    } else {
+        // Fallback to Rust docx_rs library
+        let docx = markdown_to_docx(&content, settings.as_ref())?;
 
-    let file = File::create(&path).map_err(|e| format!("Failed to create file: {}", e))?;
-    docx.build()
-        .pack(file)
-        .map_err(|e| format!("Failed to write DOCX: {}", e))?;
+        let file = File::create(&path)?;
+        docx.build().pack(file)?;
+    }
 
+    apply_heading_styles(Path::new(&path), &heading_styles)?;
+    if settings.is_some_and(|s| s.line_numbers) {
+        apply_line_numbers(Path::new(&path))?;
+    }
     Ok(())
 }
 
+/// Export markdown content as a standalone HTML file (requires pandoc)
+pub fn export_html(
+    path: &str,
+    content: &str,
+    settings: Option<&DocumentSettings>,
+    meta: Option<&DocumentMeta>,
+) -> Result<(), KorppiError> {
+    if !is_pandoc_available() {
+        return Err(KorppiError::PandocUnavailable("HTML export requires pandoc".to_string()));
+    }
+    export_with_pandoc(path, &resolve_document_references_for_export(content), Some("html"), settings, meta)
+}
+
+/// Export markdown content as a PDF file (requires pandoc and a PDF engine)
+pub fn export_pdf(
+    path: &str,
+    content: &str,
+    settings: Option<&DocumentSettings>,
+    meta: Option<&DocumentMeta>,
+) -> Result<(), KorppiError> {
+    if !is_pandoc_available() {
+        return Err(KorppiError::PandocUnavailable("PDF export requires pandoc".to_string()));
+    }
+    if !path.to_lowercase().ends_with(".pdf") {
+        return Err(KorppiError::InvalidInput("PDF output path must end in .pdf".to_string()));
+    }
+    export_with_pandoc(path, &resolve_document_references_for_export(content), None, settings, meta)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1108,6 +2477,68 @@ mod tests {
         assert!(check_version_compatibility(&format).is_err());
     }
 
+    #[test]
+    fn test_kmd_layout_and_update_entries() {
+        let mut format = FormatInfo::default();
+        assert_eq!(kmd_layout(&format), KmdLayout::Chunked);
+        format.kmd_version = "0.1.0".to_string();
+        assert_eq!(kmd_layout(&format), KmdLayout::Monolithic);
+
+        let update = YjsUpdate {
+            author: "alice".to_string(),
+            seq: 12,
+            data: vec![1, 2, 3],
+        };
+        assert_eq!(update.entry_name(), "updates/alice/000012.yjs");
+        assert_eq!(
+            YjsUpdate::parse_entry_name(&update.entry_name()),
+            Some(("alice".to_string(), 12))
+        );
+        assert_eq!(YjsUpdate::parse_entry_name("updates/../000001.yjs"), None);
+        assert_eq!(YjsUpdate::parse_entry_name("updates/alice/state.bin"), None);
+    }
+
+    #[test]
+    fn test_zstd_compression() {
+        let format = format_info_for(true, true, KmdCompression::Zstd);
+        assert_eq!(format.compression, "zstd");
+        assert_eq!(format.min_reader_version, ZSTD_MIN_READER_VERSION);
+        assert!(check_version_compatibility(&format).is_ok());
+
+        let unknown = FormatInfo { compression: "brotli".to_string(), ..FormatInfo::default() };
+        assert!(check_version_compatibility(&unknown).unwrap_err().contains("brotli"));
+
+        // Only the large entries change method
+        let zstd = KmdCompression::Zstd;
+        let settings: DocumentSettings = serde_json::from_str(r#"{"compression": "zstd"}"#).unwrap();
+        assert_eq!(settings.compression, zstd);
+        assert_eq!(DocumentSettings::default().compression, KmdCompression::Deflate);
+        for (entry, zstd_entry) in [("state.yjs", true), ("sections/s1/history.sqlite", true), ("meta.json", false)] {
+            let file = tempfile::tempfile().unwrap();
+            let mut zip = ZipWriter::new(file);
+            zip.start_file(entry, zstd.entry_options(entry)).unwrap();
+            zip.write_all(b"data").unwrap();
+            let mut archive = zip::ZipArchive::new(zip.finish().unwrap()).unwrap();
+            let method = archive.by_index(0).unwrap().compression();
+            assert_eq!(method == zip::CompressionMethod::Zstd, zstd_entry, "{}", entry);
+        }
+    }
+
+    #[test]
+    fn test_format_info_for_projects() {
+        let deflate = KmdCompression::Deflate;
+        assert_eq!(format_info_for(true, false, deflate).min_reader_version, MIN_READER_VERSION);
+        assert_eq!(format_info_for(false, false, deflate).min_reader_version, CHUNKED_MIN_READER_VERSION);
+        assert_eq!(format_info_for(true, true, deflate).min_reader_version, PROJECT_MIN_READER_VERSION);
+
+        let section = SectionRef { id: "s1".to_string(), title: "Intro".to_string() };
+        assert_eq!(section.entry_name("state.yjs"), "sections/s1/state.yjs");
+
+        // Single-buffer documents keep the v0.2 meta.json layout
+        let json = serde_json::to_string(&DocumentMeta::default()).unwrap();
+        assert!(!json.contains("sections"));
+    }
+
     #[test]
     fn test_path_safety() {
         assert!(is_path_safe("format.json"));
@@ -1141,15 +2572,23 @@ mod tests {
             }],
             settings: DocumentSettings::default(),
             sync_state: SyncState::default(),
+            sections: Vec::new(),
+            source: None,
+            abstract_text: Some("We measured things.".to_string()),
+            keywords: vec!["measurement".to_string()],
+            custom_fields: BTreeMap::from([("journal".to_string(), "Nature".to_string())]),
         };
 
         let json = serde_json::to_string_pretty(&meta).unwrap();
+        assert!(json.contains("\"abstract\": \"We measured things.\""));
         let parsed: DocumentMeta = serde_json::from_str(&json).unwrap();
 
         assert_eq!(parsed.uuid, meta.uuid);
         assert_eq!(parsed.title, meta.title);
         assert_eq!(parsed.authors.len(), 1);
         assert_eq!(parsed.authors[0].name, "Test Author");
+        assert_eq!(parsed.keywords, meta.keywords);
+        assert_eq!(parsed.custom_fields, meta.custom_fields);
     }
 
     #[test]
@@ -1161,6 +2600,7 @@ mod tests {
             color: "#FF6B6B".to_string(),
             avatar_base64: None,
             public_key: None,
+            signing_key: None,
         };
 
         let json = serde_json::to_string_pretty(&profile).unwrap();
@@ -1173,28 +2613,28 @@ mod tests {
     #[test]
     fn test_markdown_to_docx_basic() {
         let markdown = "# Heading 1\n\nThis is a paragraph with **bold** and *italic* text.";
-        let result = markdown_to_docx(markdown);
+        let result = markdown_to_docx(markdown, None);
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_markdown_to_docx_lists() {
         let markdown = "# Lists\n\n- Item 1\n- Item 2\n\n1. First\n2. Second";
-        let result = markdown_to_docx(markdown);
+        let result = markdown_to_docx(markdown, None);
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_markdown_to_docx_code() {
         let markdown = "# Code\n\nInline `code` and:\n\n```\ncode block\n```";
-        let result = markdown_to_docx(markdown);
+        let result = markdown_to_docx(markdown, None);
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_markdown_to_docx_blockquote() {
         let markdown = "> This is a quote\n> with multiple lines";
-        let result = markdown_to_docx(markdown);
+        let result = markdown_to_docx(markdown, None);
         assert!(result.is_ok());
     }
 
@@ -1208,7 +2648,7 @@ mod tests {
         let path_str = file_path.to_str().unwrap().to_string();
 
         let markdown = "# Test Document\n\nThis is a test.";
-        let result = export_docx(path_str.clone(), markdown.to_string());
+        let result = write_docx_export(path_str.clone(), markdown.to_string(), None, None, None);
 
         assert!(result.is_ok());
         assert!(file_path.exists());
@@ -1240,7 +2680,7 @@ Some text here.
 See @fig:sales for the sales data.
 "#;
 
-        let registry = build_crossref_registry(markdown);
+        let registry = build_crossref_registry(markdown, None);
         assert_eq!(registry.figures.len(), 2);
         assert_eq!(registry.figures.get("fig:sales"), Some(&1));
         assert_eq!(registry.figures.get("fig:revenue"), Some(&2));
@@ -1259,7 +2699,7 @@ See @fig:sales for the sales data.
         registry.sections.insert("sec:intro".to_string(), 2);
         registry.tables.insert("tbl:data".to_string(), 3);
 
-        let result = preprocess_markdown_for_docx(markdown, &registry);
+        let result = preprocess_markdown_for_docx(markdown, &registry, None);
 
         assert!(result.contains("Figure 1"));
         assert!(result.contains("Section 2"));
@@ -1269,12 +2709,113 @@ See @fig:sales for the sales data.
         assert!(!result.contains("@tbl:data"));
     }
 
+    #[test]
+    fn test_preprocess_applies_typography() {
+        let markdown = "See \"@fig:plot\" -- it's here.\n\n![Plot](plot.png){#fig:plot}";
+        let registry = build_crossref_registry(markdown, None);
+        let mut settings = DocumentSettings::default();
+
+        let result = preprocess_markdown_for_docx(markdown, &registry, Some(&settings));
+        assert!(result.starts_with("See “Figure 1” – it’s here."));
+
+        settings.smart_typography = false;
+        let result = preprocess_markdown_for_docx(markdown, &registry, Some(&settings));
+        assert!(result.starts_with("See \"Figure 1\" -- it's here."));
+    }
+
+    #[test]
+    fn test_chapter_numbering_and_custom_prefixes() {
+        let markdown = "# One\n\n![A](a.png){#fig:a}\n\n![B](b.png){#fig:b}\n\n# Two\n\n![C](c.png){#fig:c}\n\nSee @fig:b and @fig:c.";
+        let mut settings = DocumentSettings {
+            smart_typography: false,
+            ..DocumentSettings::default()
+        };
+        settings.crossref.chapter_numbering = true;
+        settings.crossref.figure_prefix = Some("Fig.".to_string());
+
+        let registry = build_crossref_registry(markdown, Some(&settings));
+        assert_eq!(registry.figures.get("fig:c"), Some(&1));
+        assert_eq!(get_reference_text("fig:a", &registry), "Fig. 1.1");
+        assert_eq!(get_reference_text("fig:b", &registry), "Fig. 1.2");
+        assert_eq!(get_reference_text("fig:c", &registry), "Fig. 2.1");
+
+        let result = preprocess_markdown_for_docx(markdown, &registry, Some(&settings));
+        assert!(result.contains("See Fig. 1.2 and Fig. 2.1."));
+
+        // Without chapter numbering figures are numbered globally
+        let registry = build_crossref_registry(markdown, None);
+        assert_eq!(get_reference_text("fig:c", &registry), "Figure 3");
+    }
+
+    #[test]
+    fn test_table_of_contents() {
+        let markdown = "# Introduction\n\n## Aims {#sec:aims}\n\n### Detail\n\n# Methods {#sec:methods}\n\nSee @sec:aims.";
+        let registry = build_crossref_registry(markdown, None);
+        let toc = table_of_contents_markdown(markdown, &registry, 2);
+        assert_eq!(
+            toc,
+            format!(
+                "**Contents**\n\n- Introduction\n  - [1 Aims](#{})\n- [2 Methods](#{})\n\n",
+                bookmark_name("sec:aims"),
+                bookmark_name("sec:methods")
+            )
+        );
+        assert!(table_of_contents_markdown("No headings.", &registry, 3).is_empty());
+
+        let settings = DocumentSettings { toc: true, ..DocumentSettings::default() };
+        let docx = markdown_to_docx(markdown, Some(&settings)).expect("DOCX generation failed");
+        let text = extract_text_content(&docx_to_bytes(docx).unwrap()).unwrap();
+        assert!(text.contains("Contents"));
+        assert!(text.contains("1 Aims"));
+    }
+
+    #[test]
+    fn test_numbered_headings() {
+        let markdown = "# Introduction\n\n## Aims {#sec:aims}\n\n# Methods\n\n## Data\n\n### Sources {#sec:sources}\n\nSee @sec:sources.";
+        let settings = DocumentSettings { number_headings: true, smart_typography: false, ..DocumentSettings::default() };
+        let registry = build_crossref_registry(markdown, Some(&settings));
+        assert_eq!(get_reference_text("sec:aims", &registry), "Section 1.1");
+        assert_eq!(get_reference_text("sec:sources", &registry), "Section 2.1.1");
+        assert!(table_of_contents_markdown(markdown, &registry, 2).contains("- 2 Methods\n  - 2.1 Data\n"));
+
+        let docx = markdown_to_docx(markdown, Some(&settings)).expect("DOCX generation failed");
+        let text = extract_text_content(&docx_to_bytes(docx).unwrap()).unwrap();
+        assert!(text.contains("2.1.1 Sources"));
+    }
+
+    #[test]
+    fn test_localized_crossref_prefixes() {
+        let markdown = "![A](a.png){#fig:a}\n\n| A |\n|---|\n\n{#tbl:t}";
+        let settings = DocumentSettings {
+            language: "de-DE".to_string(),
+            ..DocumentSettings::default()
+        };
+
+        let registry = build_crossref_registry(markdown, Some(&settings));
+        assert_eq!(get_reference_text("fig:a", &registry), "Abbildung 1");
+        assert_eq!(get_reference_text("tbl:t", &registry), "Tabelle 1");
+    }
+
+    #[test]
+    fn test_equation_labels() {
+        let markdown = "$$ E = mc^2 $$ {#eq:energy}\n\n$$\na^2 + b^2 = c^2\n$$ {#eq:pythagoras}\n\nBy @eq:pythagoras and @eq:energy.";
+        let registry = build_crossref_registry(markdown, None);
+        assert_eq!(registry.equations.get("eq:energy"), Some(&1));
+        assert_eq!(registry.equations.get("eq:pythagoras"), Some(&2));
+
+        let result = preprocess_markdown_for_docx(markdown, &registry, None);
+        assert!(result.contains("$$ E = mc^2  \\qquad (1)$$"));
+        assert!(result.contains("a^2 + b^2 = c^2\n \\qquad (2)$$"));
+        assert!(result.contains("By Equation 2 and Equation 1."));
+        assert!(!result.contains("{#eq:"));
+    }
+
     #[test]
     fn test_preprocess_unresolved_reference() {
         let markdown = "See @fig:missing and @sec:unknown for details.";
         let registry = CrossRefRegistry::default();
 
-        let result = preprocess_markdown_for_docx(markdown, &registry);
+        let result = preprocess_markdown_for_docx(markdown, &registry, None);
 
         assert!(result.contains("[fig:missing]"));
         assert!(result.contains("[sec:unknown]"));
@@ -1285,7 +2826,7 @@ See @fig:sales for the sales data.
         let markdown = "# Introduction {#sec:intro}\n\nSome text.";
         let registry = CrossRefRegistry::default();
 
-        let result = preprocess_markdown_for_docx(markdown, &registry);
+        let result = preprocess_markdown_for_docx(markdown, &registry, None);
 
         assert!(!result.contains("{#sec:intro}"));
         assert!(result.contains("# Introduction"));
@@ -1296,7 +2837,7 @@ See @fig:sales for the sales data.
         let markdown = "| A | B |\n|---|---|\n| 1 | 2 |\n\n{#tbl:data}";
         let registry = CrossRefRegistry::default();
 
-        let result = preprocess_markdown_for_docx(markdown, &registry);
+        let result = preprocess_markdown_for_docx(markdown, &registry, None);
 
         assert!(!result.contains("{#tbl:data}"));
     }
@@ -1330,7 +2871,7 @@ See @fig:sales for the sales data.
 As shown in @fig:sales, sales are increasing.
 "#;
 
-        let result = markdown_to_docx(markdown);
+        let result = markdown_to_docx(markdown, None);
         assert!(result.is_ok());
     }
 
@@ -1346,7 +2887,7 @@ As shown in @fig:sales, sales are increasing.
 Compare @fig:first with @fig:second to see the trend.
 "#;
 
-        let result = markdown_to_docx(markdown);
+        let result = markdown_to_docx(markdown, None);
         assert!(result.is_ok());
     }
 
@@ -1362,7 +2903,7 @@ This is the introduction.
 As described in @sec:intro, we use certain methods.
 "#;
 
-        let result = markdown_to_docx(markdown);
+        let result = markdown_to_docx(markdown, None);
         assert!(result.is_ok());
     }
 
@@ -1381,7 +2922,7 @@ As described in @sec:intro, we use certain methods.
 See @tbl:data for the complete dataset.
 "#;
 
-        let result = markdown_to_docx(markdown);
+        let result = markdown_to_docx(markdown, None);
         assert!(result.is_ok());
     }
 
@@ -1401,10 +2942,97 @@ See @tbl:data for the complete dataset.
 In @sec:intro, we present @fig:main which summarizes the data in @tbl:summary.
 "#;
 
-        let result = markdown_to_docx(markdown);
+        let result = markdown_to_docx(markdown, None);
         assert!(result.is_ok());
     }
 
+    fn test_comment(id: i64, parent_id: Option<i64>, selected: &str, content: &str) -> Comment {
+        Comment {
+            id,
+            timestamp: 1_700_000_000_000 + id,
+            author: "Alice".to_string(),
+            author_color: None,
+            start_anchor: String::new(),
+            end_anchor: String::new(),
+            selected_text: selected.to_string(),
+            content: content.to_string(),
+            status: "unresolved".to_string(),
+            parent_id,
+            orphaned: false,
+        }
+    }
+
+    #[test]
+    fn test_render_comments_as_footnotes() {
+        let markdown = "The quick brown fox jumps.";
+        let comments = vec![
+            test_comment(1, None, "brown fox", "Which fox?"),
+            test_comment(2, Some(1), "brown fox", "The red one."),
+            test_comment(3, None, "missing text", "Orphaned"),
+        ];
+
+        let result = render_export_annotations(markdown, &comments, &[], CommentPlacement::Footnotes, &HashMap::new());
+
+        assert!(result.starts_with("The quick brown fox[^comment-1] jumps."));
+        assert!(result.contains("[^comment-1]: **Alice**"));
+        assert!(result.contains("Which fox?"));
+        assert!(result.contains("    **Alice**"));
+        assert!(result.contains("The red one."));
+        // Unanchored thread falls back to the appendix
+        assert!(result.contains("## Comments"));
+        assert!(result.contains("Orphaned"));
+    }
+
+    #[test]
+    fn test_render_comments_appendix_and_reviews() {
+        let markdown = "Some text.";
+        let mut deleted = test_comment(2, None, "text", "Gone");
+        deleted.status = "deleted".to_string();
+        let comments = vec![test_comment(1, None, "Some", "Keep"), deleted];
+        let reviews = vec![ReviewSummaryRow {
+            patch_uuid: "0123456789abcdef".to_string(),
+            patch_author: Some("bob".to_string()),
+            reviewer: "Alice".to_string(),
+            decision: "accepted".to_string(),
+            reviewed_at: 1_700_000_000_000,
+        }];
+
+        let result = render_export_annotations(markdown, &comments, &reviews, CommentPlacement::Appendix, &HashMap::new());
+
+        assert!(result.starts_with("Some text."));
+        assert!(!result.contains("[^comment-"));
+        assert!(result.contains("- On \"Some\": **Alice**"));
+        assert!(!result.contains("Gone"));
+        assert!(result.contains("## Review Decisions"));
+        assert!(result.contains("| 01234567 | bob | Alice | accepted |"));
+    }
+
+    #[test]
+    fn test_render_comments_inline() {
+        let markdown = "A fox here.\n\nThe brown fox jumps.";
+        let comments = vec![
+            test_comment(1, None, "fox", "This one"),
+            test_comment(2, Some(1), "fox", "Agreed -->"),
+            test_comment(3, None, "brown fox jumps", "Overlaps <<}"),
+        ];
+        // The first thread's anchor resolves to the second paragraph, where it
+        // overlaps the third thread
+        let blocks = HashMap::from([(1, "The brown fox jumps.".to_string())]);
+
+        let result = render_export_annotations(markdown, &comments, &[], CommentPlacement::CriticMarkup, &blocks);
+        assert!(result.starts_with("A fox here.\n\nThe {==brown fox jumps==}{>>Alice ("));
+        assert!(result.contains(": Overlaps << }<<}{>>Alice ("));
+        assert!(result.contains(": This one | Alice ("));
+        assert!(result.ends_with("Agreed --><<}."));
+        assert_eq!(result.matches("{==").count(), 1);
+        assert!(!result.contains("## Comments"));
+
+        let result = render_export_annotations(markdown, &comments, &[], CommentPlacement::Html, &HashMap::new());
+        assert!(result.starts_with("A fox<!-- Alice ("));
+        assert!(result.contains("Agreed - -> -->"));
+        assert!(result.ends_with("jumps<!-- Alice (2023-11-14 22:13): Overlaps <<} -->."));
+    }
+
     /// Helper function to convert Docx to bytes
     fn docx_to_bytes(docx: Docx) -> Result<Vec<u8>, String> {
         use std::io::Cursor;
@@ -1489,8 +3117,8 @@ See @sec:intro, @fig:test, and @tbl:test for details.
 "#;
 
         // Generate DOCX twice
-        let docx1 = markdown_to_docx(markdown).expect("First DOCX generation failed");
-        let docx2 = markdown_to_docx(markdown).expect("Second DOCX generation failed");
+        let docx1 = markdown_to_docx(markdown, None).expect("First DOCX generation failed");
+        let docx2 = markdown_to_docx(markdown, None).expect("Second DOCX generation failed");
 
         // Convert to bytes
         let bytes1 = docx_to_bytes(docx1).expect("Failed to pack first DOCX");
@@ -1510,7 +3138,7 @@ See @sec:intro, @fig:test, and @tbl:test for details.
     fn test_docx_structure_valid() {
         // Test that the generated DOCX has valid structure
         let markdown = "# Hello World\n\nThis is a test.";
-        let docx = markdown_to_docx(markdown).expect("DOCX generation failed");
+        let docx = markdown_to_docx(markdown, None).expect("DOCX generation failed");
         let bytes = docx_to_bytes(docx).expect("Failed to pack DOCX");
 
         // Verify document.xml can be extracted
@@ -1535,6 +3163,82 @@ See @sec:intro, @fig:test, and @tbl:test for details.
         );
     }
 
+    #[test]
+    fn test_markdown_to_docx_footnotes() {
+        let markdown = "Claim one.[^a] Claim two.[^b]\n\n[^a]: First source.\n[^b]: Second source.";
+
+        // Footnote bodies are not written inline
+        let docx = markdown_to_docx(markdown, None).expect("DOCX generation failed");
+        let text = extract_text_content(&docx_to_bytes(docx).unwrap()).unwrap();
+        assert!(text.contains("Claim one."));
+        assert!(!text.contains("First source."));
+        assert!(!text.contains("[^a]"));
+
+        let settings = DocumentSettings {
+            endnotes: true,
+            ..DocumentSettings::default()
+        };
+        let docx = markdown_to_docx(markdown, Some(&settings)).expect("DOCX generation failed");
+        let text = extract_text_content(&docx_to_bytes(docx).unwrap()).unwrap();
+        assert!(text.contains("Notes"));
+        assert!(text.contains("1. First source."));
+        assert!(text.contains("2. Second source."));
+    }
+
+    #[test]
+    fn test_markdown_to_docx_nested_lists() {
+        let markdown = "- Fruit\n  1. Apple\n  2. Pear\n- Vegetables\n\n3. Third\n4. Fourth";
+        let docx = markdown_to_docx(markdown, None).expect("DOCX generation failed");
+        let xml = extract_document_xml(&docx_to_bytes(docx).unwrap()).unwrap();
+
+        // Every item is its own paragraph
+        let para_re = Regex::new(r"<w:p>.*?</w:p>|<w:p .*?</w:p>").unwrap();
+        let paragraphs: Vec<&str> = para_re.find_iter(&xml).map(|m| m.as_str()).collect();
+        let find = |needle: &str| *paragraphs.iter().find(|p| p.contains(needle)).unwrap();
+        assert_eq!(paragraphs.len(), 6);
+
+        assert!(find("Fruit").contains(r#"w:ilvl w:val="0""#));
+        assert!(find("Apple").contains(r#"w:ilvl w:val="1""#));
+        assert!(find("Pear").contains(r#"w:ilvl w:val="1""#));
+        assert!(find("Vegetables").contains(r#"w:ilvl w:val="0""#));
+        assert!(!find("Fruit").contains("Apple"));
+
+        // Ordered lists get their own numbering instance
+        let num_id = |p: &str| {
+            let re = Regex::new(r#"w:numId w:val="(\d+)""#).unwrap();
+            re.captures(p).unwrap()[1].to_string()
+        };
+        assert_eq!(num_id(find("Fruit")), num_id(find("Vegetables")));
+        assert_ne!(num_id(find("Apple")), num_id(find("Fruit")));
+        assert_ne!(num_id(find("Apple")), num_id(find("Third")));
+    }
+
+    #[test]
+    fn test_markdown_to_docx_links() {
+        let markdown = "# Introduction {#sec:intro}\n\nSee @sec:intro, [the site](https://example.org), <https://korppi.org> and [nowhere](#missing).";
+        let docx = markdown_to_docx(markdown, None).expect("DOCX generation failed");
+        let bytes = docx_to_bytes(docx).unwrap();
+        let xml = extract_document_xml(&bytes).unwrap();
+
+        assert!(xml.contains(r#"w:name="sec_intro""#));
+        assert!(xml.contains(r#"w:anchor="sec_intro""#));
+        assert_eq!(xml.matches("<w:hyperlink").count(), 3);
+
+        let text = extract_text_content(&bytes).unwrap();
+        assert!(text.contains("Section 1"));
+        assert!(text.contains("the site"));
+        assert!(text.contains("https://korppi.org"));
+        assert!(text.contains("nowhere"));
+        assert!(!text.contains("{#"));
+    }
+
+    #[test]
+    fn test_bookmark_name() {
+        assert_eq!(bookmark_name("sec:intro"), "sec_intro");
+        assert_eq!(bookmark_name("1-start"), "bm_1_start");
+        assert_eq!(bookmark_name(&"a".repeat(50)).len(), 40);
+    }
+
     #[test]
     fn test_reference_document_export() {
         // Test exporting the reference document to verify cross-references work end-to-end
@@ -1561,7 +3265,7 @@ See @sec:intro, @fig:test, and @tbl:test for details.
             fs::read_to_string(&ref_doc_path).expect("Failed to read reference document");
 
         // Generate DOCX
-        let docx = markdown_to_docx(&markdown);
+        let docx = markdown_to_docx(&markdown, None);
         assert!(
             docx.is_ok(),
             "Failed to generate DOCX from reference document: {:?}",
@@ -1612,8 +3316,8 @@ See @sec:intro, @fig:test, and @tbl:test for details.
             fs::read_to_string(&ref_doc_path).expect("Failed to read reference document");
 
         // Generate DOCX multiple times and verify consistency
-        let docx1 = markdown_to_docx(&markdown).expect("First generation failed");
-        let docx2 = markdown_to_docx(&markdown).expect("Second generation failed");
+        let docx1 = markdown_to_docx(&markdown, None).expect("First generation failed");
+        let docx2 = markdown_to_docx(&markdown, None).expect("Second generation failed");
 
         let bytes1 = docx_to_bytes(docx1).expect("Failed to pack first DOCX");
         let bytes2 = docx_to_bytes(docx2).expect("Failed to pack second DOCX");
@@ -9,45 +9,234 @@ pub mod kmd;
 pub mod document_manager;
 pub mod comments;
 pub mod db_utils;
+pub mod patch_store;
 pub mod hunk_calculator;
+pub mod suggestions;
+pub mod attribution;
+pub mod settings;
+pub mod pandoc;
+pub mod spellcheck;
+pub mod typography;
+pub mod autosave;
+pub mod history_rewrite;
+pub mod templates;
+pub mod sections;
+pub mod file_lock;
+pub mod session_log;
+pub mod cli;
+pub mod patch_bundle;
+pub mod bundle_crypto;
+pub mod git_mirror;
+pub mod channels;
+pub mod import_conflicts;
+pub mod contribution;
+pub mod outline;
+pub mod section_locks;
+pub mod error;
+pub mod events;
+pub mod dropped_files;
+pub mod html_import;
+pub mod source_format;
+pub mod quarto;
+pub mod zotero;
+pub mod doi;
+pub mod grammar;
+pub mod readability;
+pub mod review_comments;
+pub mod patch_graph;
+pub mod change_summary;
+pub mod workspaces;
+pub mod authors;
+pub mod presence;
+pub mod live_session;
+pub mod live_discovery;
+pub mod trash;
+pub mod file_progress;
+pub mod exports;
+pub mod backups;
+pub mod journal;
+pub mod patch_schema;
+pub mod document_size;
+pub mod criticmarkup;
+pub mod goals;
+pub mod review_queue;
+pub mod deep_links;
+pub mod document_metadata;
+pub mod safe_open;
+pub mod workspace_archive;
+pub mod collation;
+pub mod profile_storage;
+pub mod history_export;
+pub mod hooks;
+pub mod figure_sources;
+pub mod docx_styles;
 
 use std::sync::Mutex;
+use tauri::Manager;
 use patch_log::{
     list_patches, record_patch, get_patch, save_snapshot, get_snapshot_for_patch,
     restore_to_patch, import_patches_from_document, record_patch_review,
     get_patch_reviews, get_patches_needing_review,
 };
 use yjs_store::{load_doc, store_update};
-use conflict_commands::{detect_conflicts, get_conflicts, resolve_conflict, get_conflict_count};
-use profile::{get_profile, save_profile, get_profile_path, export_profile, import_profile};
+use conflict_commands::{detect_conflicts, get_conflicts, resolve_conflict, get_conflict_count, reopen_conflict, list_resolution_history, auto_merge_conflict};
+use profile::{
+    get_profile, save_profile, get_profile_path, export_profile, import_profile, set_profile_avatar, clear_profile_avatar,
+    set_profile_color,
+};
 use kmd::{export_kmd, export_markdown, export_docx, get_document_meta, set_document_title, write_text_file};
 use document_manager::{
-    new_document, open_document, save_document, close_document,
+    new_document, open_document, save_document, export_kmd_clean, close_document,
     get_open_documents, get_recent_documents, clear_recent_documents,
+    pin_recent_document, unpin_recent_document, get_recent_settings, update_recent_settings,
     set_active_document, get_active_document, get_document_state,
+    get_document_updates, append_document_update, merge_document_state, get_document_text,
     update_document_state, mark_document_modified, update_document_title,
-    record_document_patch, list_document_patches, get_initial_file,
-    save_document_snapshot, restore_document_to_patch,
-    record_document_patch_review, get_document_patch_reviews,
+    record_document_patch, list_document_patches, get_document_patch, get_document_patch_summary, get_initial_file,
+    save_document_snapshot, verify_snapshots, restore_document_to_patch, export_at_patch,
+    record_document_patch_review, get_document_patch_reviews, get_patch_blocking_comments, set_comment_blocking_policy, set_document_compression,
     get_document_patches_needing_review, check_parent_patch_status,
     delete_document_reviews_after,
-    import_document, check_pandoc_available, open_url,
+    import_document, check_pandoc_available, open_url, open_in_new_window, list_document_assets,
+    get_patch_diff, get_diff_between, cleanup_workspace,
     DocumentManager,
 };
 use comments::{
     add_comment, list_comments, add_reply, resolve_comment, delete_comment, mark_comment_deleted, restore_comment,
+    list_comment_events, rebase_comment_anchors, update_comment_anchors,
 };
 use hunk_calculator::calculate_hunks_for_patches;
+use suggestions::{list_suggestions, accept_suggestion, reject_suggestion};
+use attribution::compute_attribution;
+use contribution::{generate_contribution_report, export_contribution_report};
+use outline::get_document_outline;
+use section_locks::{lock_section, unlock_section, list_section_locks};
+use settings::{get_app_settings, update_app_settings};
+use pandoc::diagnose_pandoc;
+use spellcheck::{check_spelling, add_to_dictionary, SpellChecker};
+use autosave::{record_document_activity, compact_document_history, AutoSaveTracker};
+use history_rewrite::{squash_patches, prune_rejected_patches};
+use templates::{list_templates, new_document_from_template};
+use file_lock::get_document_lock;
+use session_log::{log_session_event, export_diagnostics};
+use patch_bundle::{export_patch_bundle, apply_patch_bundle, dry_run_import_bundle};
+use dropped_files::handle_dropped_files;
+use source_format::{export_quarto, export_rmarkdown};
+use quarto::{check_quarto_available, render_quarto_figures};
+use zotero::{search_zotero, insert_citation};
+use doi::{fetch_doi_metadata, insert_doi_citation};
+use grammar::{check_grammar, GrammarChecker};
+use readability::analyze_readability;
+use review_comments::{add_review_comment, list_review_comments};
+use patch_graph::{get_patch_descendants, get_patch_graph};
+use change_summary::export_change_summary;
+use workspaces::{
+    add_workspace_document, check_document_references, create_workspace, delete_workspace, get_workspace_activity,
+    list_workspaces, open_workspace, remove_workspace_document, search_workspace,
+};
+use authors::{merge_authors, rename_author};
+use presence::{get_active_collaborators, heartbeat_presence};
+use live_session::{
+    get_live_session, host_live_session, join_live_session, leave_live_session, send_live_awareness,
+    send_live_update, LiveSessions,
+};
+use live_discovery::discover_live_sessions;
+use trash::{
+    delete_document, list_trashed_documents, purge_trashed_documents, restore_trashed_document, set_trash_size_limit,
+};
+use file_progress::{cancel_file_job, FileJobs};
+use exports::{list_exports, queue_export, regenerate_export, ExportQueue};
+use backups::{list_backups, restore_from_backup, run_backup_now, set_document_backup};
+use patch_schema::lint_history;
+use document_size::get_document_size_breakdown;
+use criticmarkup::export_diff_criticmarkup;
+use goals::{get_goal_progress, set_writing_goal};
+use review_queue::get_review_queue;
+use deep_links::{generate_position_link, get_initial_position_link, resolve_position_link};
+use document_metadata::{get_document_metadata, set_document_metadata};
+use safe_open::check_document_trust;
+use workspace_archive::{export_workspace_archive, import_workspace_archive};
+use profile_storage::{profile_storage_get, profile_storage_set, profile_storage_delete};
+use history_export::export_history_data;
+use hooks::{get_document_hooks, set_document_hooks, test_hook};
+use figure_sources::{attach_figure_source, detach_figure_source, list_figure_sources, regenerate_figures};
+use git_mirror::{enable_git_mirror, disable_git_mirror, get_git_mirror_log, import_git_mirror, import_git_history};
+use channels::{
+    stage_patch_bundle, list_patch_channels, list_channel_patches, cherry_pick_channel_patch, drop_patch_channel,
+};
+use sections::{
+    list_sections, add_section, reorder_sections, remove_section, get_section_state, update_section_state,
+    get_project_markdown, export_project_docx,
+};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     #[cfg(debug_assertions)]
     env_logger::init();
 
-    tauri::Builder::default()
+    // A file passed on the command line (or by a file association) opens in
+    // the main window, as does the document of a korppi:// link
+    let mut documents = DocumentManager::default();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let cwd = std::env::current_dir().unwrap_or_default();
+    if let Some(link) = deep_links::link_argument(&documents, &args) {
+        if let Some(path) = &link.path {
+            documents.pending_opens.insert(document_manager::MAIN_WINDOW.to_string(), path.into());
+        }
+        documents.pending_links.insert(document_manager::MAIN_WINDOW.to_string(), link);
+    } else if let Some(path) = cli::file_argument(&args, &cwd) {
+        documents.pending_opens.insert(document_manager::MAIN_WINDOW.to_string(), path);
+    }
+
+    let builder = tauri::Builder::default();
+    // Later launches hand their file to this instance and exit
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    let builder = builder.plugin(tauri_plugin_single_instance::init(|app, args, cwd| {
+        document_manager::forward_open_request(app, &args, &cwd);
+    }));
+
+    // korppi:// links; Windows and Linux pass them as arguments, which the
+    // single-instance plugin forwards, macOS sends them as events
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    let builder = builder.plugin(tauri_plugin_deep_link::init()).setup(|app| {
+        use tauri_plugin_deep_link::DeepLinkExt;
+        // Remove temp directories leaked by sessions that were killed before
+        // closing; only here, once a second launch has handed off and exited,
+        // so the directories of the running instance are never touched
+        document_manager::run_temp_janitor();
+        #[cfg(any(windows, target_os = "linux"))]
+        if let Err(e) = app.deep_link().register_all() {
+            log::warn!("Failed to register the korppi:// scheme: {}", e);
+        }
+        #[cfg(target_os = "macos")]
+        {
+            let handle = app.handle().clone();
+            app.deep_link().on_open_url(move |event| {
+                let urls: Vec<String> = event.urls().iter().map(|url| url.to_string()).collect();
+                deep_links::forward_position_link(&handle, &urls);
+            });
+        }
+        Ok(())
+    });
+
+    builder
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
-        .manage(Mutex::new(DocumentManager::default()))
+        .manage(Mutex::new(documents))
+        .manage(Mutex::new(SpellChecker::default()))
+        .manage(Mutex::new(GrammarChecker::default()))
+        .manage(Mutex::new(AutoSaveTracker::default()))
+        .manage(Mutex::new(LiveSessions::default()))
+        .manage(Mutex::new(FileJobs::default()))
+        .manage(Mutex::new(ExportQueue::default()))
+        .on_window_event(|window, event| {
+            // Documents stay open; only the window's active document is forgotten
+            if let tauri::WindowEvent::Destroyed = event {
+                if let Ok(mut manager) = window.state::<Mutex<DocumentManager>>().lock() {
+                    manager.window_closed(window.label());
+                }
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             load_doc,
             store_update,
@@ -61,9 +250,15 @@ pub fn run() {
             get_conflicts,
             resolve_conflict,
             get_conflict_count,
+            reopen_conflict,
+            list_resolution_history,
+            auto_merge_conflict,
             get_profile,
             save_profile,
             get_profile_path,
+            set_profile_avatar,
+            clear_profile_avatar,
+            set_profile_color,
             export_profile,
             import_profile,
             export_kmd,
@@ -76,29 +271,113 @@ pub fn run() {
             new_document,
             open_document,
             save_document,
+            export_kmd_clean,
             close_document,
             get_open_documents,
             get_recent_documents,
             clear_recent_documents,
+            pin_recent_document,
+            unpin_recent_document,
+            get_recent_settings,
+            update_recent_settings,
             set_active_document,
             get_active_document,
             get_document_state,
+            get_document_updates,
+            append_document_update,
+            merge_document_state,
+            get_document_text,
             update_document_state,
             mark_document_modified,
             update_document_title,
             record_document_patch,
             list_document_patches,
+            get_document_patch,
+            get_document_patch_summary,
             get_initial_file,
+            open_in_new_window,
             restore_document_to_patch,
+            export_at_patch,
             save_document_snapshot,
+            verify_snapshots,
             record_document_patch_review,
             get_document_patch_reviews,
+            add_review_comment,
+            list_review_comments,
+            get_patch_descendants,
+            get_patch_graph,
+            export_change_summary,
+            list_workspaces,
+            create_workspace,
+            delete_workspace,
+            add_workspace_document,
+            remove_workspace_document,
+            open_workspace,
+            search_workspace,
+            get_workspace_activity,
+            check_document_references,
+            merge_authors,
+            rename_author,
+            heartbeat_presence,
+            get_active_collaborators,
+            host_live_session,
+            join_live_session,
+            leave_live_session,
+            get_live_session,
+            send_live_update,
+            send_live_awareness,
+            discover_live_sessions,
+            delete_document,
+            list_trashed_documents,
+            restore_trashed_document,
+            purge_trashed_documents,
+            set_trash_size_limit,
+            cancel_file_job,
+            queue_export,
+            list_exports,
+            regenerate_export,
+            set_document_backup,
+            run_backup_now,
+            list_backups,
+            restore_from_backup,
+            lint_history,
+            get_document_size_breakdown,
+            export_diff_criticmarkup,
+            set_writing_goal,
+            get_goal_progress,
+            get_review_queue,
+            generate_position_link,
+            resolve_position_link,
+            get_initial_position_link,
+            get_document_metadata,
+            set_document_metadata,
+            check_document_trust,
+            export_workspace_archive,
+            import_workspace_archive,
+            profile_storage_get,
+            profile_storage_set,
+            profile_storage_delete,
+            export_history_data,
+            get_document_hooks,
+            set_document_hooks,
+            test_hook,
+            attach_figure_source,
+            detach_figure_source,
+            list_figure_sources,
+            regenerate_figures,
+            get_patch_blocking_comments,
+            set_comment_blocking_policy,
+            set_document_compression,
             get_document_patches_needing_review,
             check_parent_patch_status,
             delete_document_reviews_after,
             import_document,
             check_pandoc_available,
+            diagnose_pandoc,
             open_url,
+            get_patch_diff,
+            get_diff_between,
+            cleanup_workspace,
             import_patches_from_document,
             record_patch_review,
             get_patch_reviews,
@@ -111,8 +390,71 @@ pub fn run() {
             delete_comment,
             mark_comment_deleted,
             restore_comment,
+            list_comment_events,
+            rebase_comment_anchors,
+            update_comment_anchors,
             // Hunk calculator
             calculate_hunks_for_patches,
+            // Suggested edits
+            list_suggestions,
+            accept_suggestion,
+            reject_suggestion,
+            // Attribution
+            compute_attribution,
+            generate_contribution_report,
+            export_contribution_report,
+            get_document_outline,
+            lock_section,
+            unlock_section,
+            list_section_locks,
+            // Settings
+            get_app_settings,
+            update_app_settings,
+            // Spell checking
+            check_spelling,
+            add_to_dictionary,
+            record_document_activity,
+            compact_document_history,
+            squash_patches,
+            prune_rejected_patches,
+            list_templates,
+            new_document_from_template,
+            list_sections,
+            add_section,
+            reorder_sections,
+            remove_section,
+            get_section_state,
+            update_section_state,
+            get_project_markdown,
+            export_project_docx,
+            get_document_lock,
+            log_session_event,
+            export_diagnostics,
+            export_patch_bundle,
+            apply_patch_bundle,
+            dry_run_import_bundle,
+            handle_dropped_files,
+            export_quarto,
+            export_rmarkdown,
+            check_quarto_available,
+            render_quarto_figures,
+            list_document_assets,
+            search_zotero,
+            insert_citation,
+            fetch_doi_metadata,
+            insert_doi_citation,
+            check_grammar,
+            analyze_readability,
+            enable_git_mirror,
+            disable_git_mirror,
+            get_git_mirror_log,
+            import_git_mirror,
+            import_git_history,
+            stage_patch_bundle,
+            list_patch_channels,
+            list_channel_patches,
+            cherry_pick_channel_patch,
+            drop_patch_channel,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,187 @@
+// src-tauri/src/live_discovery.rs
+//! Finding live sessions on the local network with mDNS.
+//!
+//! A hosted session is advertised as a `_korppi-live._tcp` service whose
+//! TXT record carries the document title and the host's name, so people in
+//! the same room can pick a session from a list instead of typing an IP
+//! address. Networks that block multicast still allow joining by URL. The
+//! session token is never advertised.
+
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::State;
+use uuid::Uuid;
+
+use crate::error::KorppiError;
+use crate::file_lock::host_name;
+use crate::live_session::LiveSessions;
+
+/// mDNS service type of live sessions
+pub const LIVE_SERVICE_TYPE: &str = "_korppi-live._tcp.local.";
+
+/// How long discovery listens for answers when the caller does not say
+const DEFAULT_DISCOVERY_MS: u64 = 2000;
+
+/// A live session found on the network
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DiscoveredSession {
+    pub title: String,
+    /// Name of the person hosting
+    pub author: String,
+    /// Machine hosting the session
+    pub host: String,
+    /// Address of the host; `join_live_session` needs it with the session
+    /// token, `/?token=...`, which only the host can give
+    pub url: String,
+}
+
+/// An mDNS advertisement, withdrawn when dropped
+pub struct LiveAdvertisement {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+impl LiveAdvertisement {
+    /// Full mDNS name of the advertised service
+    pub fn fullname(&self) -> &str {
+        &self.fullname
+    }
+
+    /// Announce a session hosted on this machine
+    pub fn start(ip: &str, port: u16, title: &str, author: &str) -> Result<LiveAdvertisement, String> {
+        let daemon = ServiceDaemon::new().map_err(|e| format!("Failed to start mDNS: {}", e))?;
+        let instance = format!("korppi-{}", &Uuid::new_v4().simple().to_string()[..12]);
+        let host = format!("{}.local.", dns_label(&host_name()));
+        let properties = [("title", title), ("author", author)];
+        let info = ServiceInfo::new(LIVE_SERVICE_TYPE, &instance, &host, ip, port, &properties[..])
+            .map_err(|e| format!("Invalid mDNS service: {}", e))?;
+        let fullname = info.get_fullname().to_string();
+        daemon
+            .register(info)
+            .map_err(|e| format!("Failed to advertise live session: {}", e))?;
+        Ok(LiveAdvertisement { daemon, fullname })
+    }
+}
+
+impl Drop for LiveAdvertisement {
+    fn drop(&mut self) {
+        self.daemon.unregister(&self.fullname).ok();
+        self.daemon.shutdown().ok();
+    }
+}
+
+/// A host name reduced to what a DNS label allows
+fn dns_label(name: &str) -> String {
+    let label: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '-' })
+        .take(63)
+        .collect();
+    let label = label.trim_matches('-');
+    if label.is_empty() {
+        "korppi".to_string()
+    } else {
+        label.to_string()
+    }
+}
+
+/// Session described by a resolved service, or None without an IPv4 address
+fn discovered_session(info: &ServiceInfo) -> Option<DiscoveredSession> {
+    let ip = info
+        .get_addresses()
+        .iter()
+        .filter(|ip| matches!(ip, IpAddr::V4(_)))
+        .min()?
+        .to_string();
+    let property = |key: &str| info.get_property_val_str(key).unwrap_or_default().to_string();
+    Some(DiscoveredSession {
+        title: property("title"),
+        author: property("author"),
+        host: info.get_hostname().trim_end_matches('.').trim_end_matches(".local").to_string(),
+        url: format!("ws://{}:{}", ip, info.get_port()),
+    })
+}
+
+/// Live sessions advertised on the local network, by title
+///
+/// Listens for `timeout_ms` (two seconds by default). Sessions hosted by
+/// this instance are left out.
+#[tauri::command]
+pub async fn discover_live_sessions(
+    sessions: State<'_, Mutex<LiveSessions>>,
+    timeout_ms: Option<u64>,
+) -> Result<Vec<DiscoveredSession>, KorppiError> {
+    let own = sessions.lock().map_err(|e| e.to_string())?.advertised_names();
+
+    let daemon = ServiceDaemon::new().map_err(|e| format!("Failed to start mDNS: {}", e))?;
+    let receiver = daemon
+        .browse(LIVE_SERVICE_TYPE)
+        .map_err(|e| format!("Failed to browse for live sessions: {}", e))?;
+
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms.unwrap_or(DEFAULT_DISCOVERY_MS));
+    let mut found: BTreeMap<String, DiscoveredSession> = BTreeMap::new();
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        match receiver.recv_timeout(remaining) {
+            Ok(ServiceEvent::ServiceResolved(info)) => {
+                if own.iter().any(|name| name == info.get_fullname()) {
+                    continue;
+                }
+                if let Some(session) = discovered_session(&info) {
+                    found.insert(info.get_fullname().to_string(), session);
+                }
+            }
+            Ok(ServiceEvent::ServiceRemoved(_, fullname)) => {
+                found.remove(&fullname);
+            }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    daemon.shutdown().ok();
+
+    let mut sessions: Vec<DiscoveredSession> = found.into_values().collect();
+    sessions.sort_by(|a, b| a.title.cmp(&b.title).then(a.url.cmp(&b.url)));
+    Ok(sessions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dns_label() {
+        assert_eq!(dns_label("alice-laptop"), "alice-laptop");
+        assert_eq!(dns_label("Alice's MacBook.lan"), "Alice-s-MacBook-lan");
+        assert_eq!(dns_label("..."), "korppi");
+    }
+
+    #[test]
+    fn test_discovered_session() {
+        let properties = [("title", "Field notes"), ("author", "Alice")];
+        let info = ServiceInfo::new(
+            LIVE_SERVICE_TYPE,
+            "korppi-abc",
+            "alice-laptop.local.",
+            "192.168.1.20,fe80::1",
+            47820,
+            &properties[..],
+        )
+        .unwrap();
+        assert_eq!(
+            discovered_session(&info),
+            Some(DiscoveredSession {
+                title: "Field notes".to_string(),
+                author: "Alice".to_string(),
+                host: "alice-laptop".to_string(),
+                url: "ws://192.168.1.20:47820".to_string(),
+            })
+        );
+
+        let ipv6_only = ServiceInfo::new(LIVE_SERVICE_TYPE, "korppi-def", "bob.local.", "fe80::1", 47820, &properties[..]).unwrap();
+        assert_eq!(discovered_session(&ipv6_only), None);
+    }
+}
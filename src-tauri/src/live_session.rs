@@ -0,0 +1,672 @@
+// src-tauri/src/live_session.rs
+//! Live collaboration on a local network.
+//!
+//! One instance hosts a document with `host_live_session`, which starts a
+//! WebSocket server; others connect to it with `join_live_session`. Peers
+//! exchange binary frames: Yjs updates, which are merged into the open
+//! document, and awareness data (cursors, names), which is only relayed.
+//! The host forwards every frame it receives to its other peers.
+//!
+//! A session is only open to those who know its URL: the host makes up a
+//! random token for each session and refuses connections that do not carry
+//! it. The token is not advertised, so a session found on the network still
+//! needs the code from the host. Updates are never merged into a document
+//! open read-only.
+//!
+//! Live editing does not bypass the history: each instance keeps recording
+//! its own Save patches when it saves, and the merged document is saved like
+//! any other edit.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tungstenite::http::StatusCode;
+use tungstenite::{Message, WebSocket};
+use uuid::Uuid;
+
+use crate::document_manager::DocumentManager;
+use crate::error::KorppiError;
+use crate::file_lock::LockInfo;
+use crate::live_discovery::LiveAdvertisement;
+use crate::events::{emit_event, LiveAwarenessEvent, LivePeerEvent, LiveUpdateEvent, LIVE_AWARENESS, LIVE_PEER_LEFT, LIVE_UPDATE};
+use crate::yjs_store::merge_states;
+
+/// Port used when the host does not pick one
+pub const DEFAULT_LIVE_PORT: u16 = 47820;
+
+/// How long a peer thread blocks on reads before sending queued frames
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Peer ID under which a client knows its host
+const HOST_PEER: &str = "host";
+
+const FRAME_UPDATE: u8 = 0;
+const FRAME_AWARENESS: u8 = 1;
+
+/// A message between peers
+#[derive(Debug, Clone, PartialEq)]
+pub enum LiveFrame {
+    /// Encoded Yjs update, or a full state when a peer connects
+    Update(Vec<u8>),
+    /// Opaque awareness data of the sending peer
+    Awareness(Vec<u8>),
+}
+
+impl LiveFrame {
+    /// Wire format: a tag byte followed by the payload
+    pub fn encode(&self) -> Vec<u8> {
+        let (tag, payload) = match self {
+            LiveFrame::Update(data) => (FRAME_UPDATE, data),
+            LiveFrame::Awareness(data) => (FRAME_AWARENESS, data),
+        };
+        let mut bytes = Vec::with_capacity(payload.len() + 1);
+        bytes.push(tag);
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    /// Parse a frame, or None for an unknown tag
+    pub fn decode(bytes: &[u8]) -> Option<LiveFrame> {
+        let (tag, payload) = bytes.split_first()?;
+        match *tag {
+            FRAME_UPDATE => Some(LiveFrame::Update(payload.to_vec())),
+            FRAME_AWARENESS => Some(LiveFrame::Awareness(payload.to_vec())),
+            _ => None,
+        }
+    }
+}
+
+/// What a session does with the document it shares
+pub trait LiveHandler: Send + Sync + 'static {
+    /// Full state sent to a peer when it connects
+    fn snapshot(&self) -> Option<Vec<u8>>;
+    /// A frame arrived from a peer
+    fn received(&self, peer: &str, frame: &LiveFrame);
+    /// A peer disconnected; for a client, the host is gone
+    fn peer_left(&self, peer: &str);
+}
+
+/// Whether this instance hosts the session or joined one
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LiveRole {
+    Host,
+    Client,
+}
+
+type PeerSenders = Arc<Mutex<HashMap<String, Sender<LiveFrame>>>>;
+
+/// A running live session; stopped when dropped
+pub struct LiveSession {
+    pub role: LiveRole,
+    /// Address peers connect to, with the session token, e.g.
+    /// ws://192.168.1.20:47820/?token=...
+    pub url: String,
+    /// Port the host listens on
+    port: u16,
+    advertisement: Option<LiveAdvertisement>,
+    peers: PeerSenders,
+    stop: Arc<AtomicBool>,
+}
+
+impl LiveSession {
+    /// Start a WebSocket server on a port (0 picks a free one), for peers
+    /// with the session's token
+    pub fn host(port: u16, handler: Arc<dyn LiveHandler>) -> Result<LiveSession, String> {
+        let token = Uuid::new_v4().simple().to_string();
+        let listener = TcpListener::bind(("0.0.0.0", port))
+            .map_err(|e| format!("Failed to listen on port {}: {}", port, e))?;
+        let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+
+        let peers: PeerSenders = Arc::default();
+        let stop = Arc::new(AtomicBool::new(false));
+        {
+            let peers = peers.clone();
+            let stop = stop.clone();
+            let token: Arc<str> = token.as_str().into();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            let peers = peers.clone();
+                            let stop = stop.clone();
+                            let handler = handler.clone();
+                            let token = token.clone();
+                            thread::spawn(move || accept_peer(stream, &token, peers, stop, handler));
+                        }
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                        Err(e) => {
+                            log::warn!("Live session stopped accepting peers: {}", e);
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+
+        Ok(LiveSession {
+            role: LiveRole::Host,
+            url: format!("ws://{}:{}/?token={}", local_ip(), port, token),
+            port,
+            advertisement: None,
+            peers,
+            stop,
+        })
+    }
+
+    /// Connect to a session hosted at a ws:// URL, which carries its token
+    pub fn join(url: &str, handler: Arc<dyn LiveHandler>) -> Result<LiveSession, String> {
+        let address = url
+            .strip_prefix("ws://")
+            .ok_or_else(|| format!("Not a ws:// URL: {}", url))?
+            .split(['/', '?'])
+            .next()
+            .unwrap_or_default();
+        let port = address.rsplit_once(':').and_then(|(_, p)| p.parse().ok()).unwrap_or(80);
+        let stream = TcpStream::connect(address).map_err(|e| format!("Failed to connect to {}: {}", url, e))?;
+        let (socket, _) = tungstenite::client(url, stream).map_err(|e| format!("WebSocket handshake failed: {}", e))?;
+
+        let peers: PeerSenders = Arc::default();
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = channel();
+        peers.lock().map_err(|e| e.to_string())?.insert(HOST_PEER.to_string(), sender);
+        {
+            let peers = peers.clone();
+            let stop = stop.clone();
+            thread::spawn(move || run_peer(socket, HOST_PEER.to_string(), receiver, peers, stop, handler, false));
+        }
+
+        Ok(LiveSession {
+            role: LiveRole::Client,
+            url: url.to_string(),
+            port,
+            advertisement: None,
+            peers,
+            stop,
+        })
+    }
+
+    /// Announce a hosted session on the local network with mDNS
+    pub fn advertise(&mut self, title: &str, author: &str) -> Result<(), String> {
+        if self.role != LiveRole::Host {
+            return Err("Only the host advertises a live session".to_string());
+        }
+        self.advertisement = Some(LiveAdvertisement::start(&local_ip(), self.port, title, author)?);
+        Ok(())
+    }
+
+    /// Send a frame to every peer
+    pub fn broadcast(&self, frame: &LiveFrame) {
+        if let Ok(peers) = self.peers.lock() {
+            for sender in peers.values() {
+                sender.send(frame.clone()).ok();
+            }
+        }
+    }
+
+    /// Number of connected peers
+    pub fn peer_count(&self) -> usize {
+        self.peers.lock().map(|p| p.len()).unwrap_or(0)
+    }
+
+    /// Disconnect all peers and stop accepting new ones
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for LiveSession {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Whether a handshake request carries the session token in its query
+fn has_token(request: &Request, token: &str) -> bool {
+    request
+        .uri()
+        .query()
+        .is_some_and(|query| query.split('&').any(|pair| pair.strip_prefix("token=") == Some(token)))
+}
+
+/// Complete the handshake of an incoming connection and serve it; peers
+/// without the token are turned away before they get the snapshot
+#[allow(clippy::result_large_err)]
+fn accept_peer(stream: TcpStream, token: &str, peers: PeerSenders, stop: Arc<AtomicBool>, handler: Arc<dyn LiveHandler>) {
+    if let Err(e) = stream.set_nonblocking(false) {
+        log::warn!("Failed to configure live session peer: {}", e);
+        return;
+    }
+    let authorize = |request: &Request, response: Response| {
+        if has_token(request, token) {
+            return Ok(response);
+        }
+        let mut denied = ErrorResponse::new(Some("Invalid live session token".to_string()));
+        *denied.status_mut() = StatusCode::UNAUTHORIZED;
+        Err(denied)
+    };
+    let socket = match tungstenite::accept_hdr(stream, authorize) {
+        Ok(socket) => socket,
+        Err(e) => {
+            log::warn!("Live session handshake failed: {}", e);
+            return;
+        }
+    };
+    let peer = Uuid::new_v4().to_string();
+    let (sender, receiver) = channel();
+    match peers.lock() {
+        Ok(mut peers) => peers.insert(peer.clone(), sender),
+        Err(_) => return,
+    };
+    run_peer(socket, peer, receiver, peers, stop, handler, true);
+}
+
+/// Exchange frames with one peer until either side closes
+///
+/// Reads time out every `POLL_INTERVAL` so frames queued for the peer are
+/// sent promptly. A host relays what it receives to its other peers.
+fn run_peer(
+    mut socket: WebSocket<TcpStream>,
+    peer: String,
+    outgoing: Receiver<LiveFrame>,
+    peers: PeerSenders,
+    stop: Arc<AtomicBool>,
+    handler: Arc<dyn LiveHandler>,
+    relay: bool,
+) {
+    if let Err(e) = socket.get_ref().set_read_timeout(Some(POLL_INTERVAL)) {
+        log::warn!("Failed to configure live session peer: {}", e);
+    }
+    // Both sides start by sending everything they have; merging is idempotent
+    if let Some(state) = handler.snapshot() {
+        if socket.send(Message::Binary(LiveFrame::Update(state).encode())).is_err() {
+            stop_peer(&peer, &peers, &handler);
+            return;
+        }
+    }
+
+    while !stop.load(Ordering::Relaxed) {
+        match socket.read() {
+            Ok(Message::Binary(bytes)) => {
+                if let Some(frame) = LiveFrame::decode(&bytes) {
+                    handler.received(&peer, &frame);
+                    if relay {
+                        if let Ok(peers) = peers.lock() {
+                            for (id, sender) in peers.iter().filter(|(id, _)| **id != peer) {
+                                if sender.send(frame.clone()).is_err() {
+                                    log::debug!("Live session peer {} is gone", id);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(Message::Close(_)) => break,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e)) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => break,
+        }
+        let mut failed = false;
+        while let Ok(frame) = outgoing.try_recv() {
+            if socket.send(Message::Binary(frame.encode())).is_err() {
+                failed = true;
+                break;
+            }
+        }
+        if failed {
+            break;
+        }
+    }
+
+    socket.close(None).ok();
+    socket.flush().ok();
+    stop_peer(&peer, &peers, &handler);
+}
+
+fn stop_peer(peer: &str, peers: &PeerSenders, handler: &Arc<dyn LiveHandler>) {
+    if let Ok(mut peers) = peers.lock() {
+        peers.remove(peer);
+    }
+    handler.peer_left(peer);
+}
+
+/// Address of this machine on the local network, for the session URL
+///
+/// Connecting a UDP socket sends nothing; it only picks the interface that
+/// would route outside traffic.
+fn local_ip() -> String {
+    UdpSocket::bind("0.0.0.0:0")
+        .and_then(|socket| {
+            socket.connect("192.0.2.1:80")?;
+            socket.local_addr()
+        })
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|_| "127.0.0.1".to_string())
+}
+
+/// Shares an open document with the peers of a session
+struct DocumentLiveHandler {
+    app: AppHandle,
+    doc_id: String,
+}
+
+impl LiveHandler for DocumentLiveHandler {
+    fn snapshot(&self) -> Option<Vec<u8>> {
+        let manager = self.app.state::<Mutex<DocumentManager>>();
+        let manager = manager.lock().ok()?;
+        let doc = manager.documents.get(&self.doc_id)?;
+        let mut parts: Vec<&[u8]> = vec![&doc.yjs_state];
+        parts.extend(doc.yjs_updates.iter().map(|u| u.data.as_slice()));
+        merge_states(&parts).ok()
+    }
+
+    fn received(&self, peer: &str, frame: &LiveFrame) {
+        match frame {
+            LiveFrame::Update(update) => {
+                let merged = {
+                    let manager = self.app.state::<Mutex<DocumentManager>>();
+                    let Ok(mut manager) = manager.lock() else {
+                        return;
+                    };
+                    let Some(doc) = manager.documents.get_mut(&self.doc_id) else {
+                        return;
+                    };
+                    if doc.handle.read_only {
+                        log::warn!("Ignoring live update from {}: document is open read-only", peer);
+                        return;
+                    }
+                    match merge_states(&[&doc.yjs_state, update]) {
+                        Ok(state) => {
+                            doc.yjs_state = state;
+                            if let Err(e) = crate::journal::journal_state(doc) {
+                                log::warn!("Cannot journal live update from {}: {}", peer, e);
+                            }
+                            doc.handle.is_modified = true;
+                            true
+                        }
+                        Err(e) => {
+                            log::warn!("Ignoring live update from {}: {}", peer, e);
+                            false
+                        }
+                    }
+                };
+                if merged {
+                    emit_event(&self.app, LIVE_UPDATE, LiveUpdateEvent {
+                        doc_id: self.doc_id.clone(),
+                        peer: peer.to_string(),
+                        update: update.clone(),
+                    });
+                }
+            }
+            LiveFrame::Awareness(data) => {
+                emit_event(&self.app, LIVE_AWARENESS, LiveAwarenessEvent {
+                    doc_id: self.doc_id.clone(),
+                    peer: peer.to_string(),
+                    data: data.clone(),
+                });
+            }
+        }
+    }
+
+    fn peer_left(&self, peer: &str) {
+        emit_event(&self.app, LIVE_PEER_LEFT, LivePeerEvent {
+            doc_id: self.doc_id.clone(),
+            peer: peer.to_string(),
+        });
+    }
+}
+
+/// Live sessions, keyed by document ID
+#[derive(Default)]
+pub struct LiveSessions {
+    sessions: HashMap<String, LiveSession>,
+}
+
+impl LiveSessions {
+    /// mDNS names of the sessions this instance advertises
+    pub fn advertised_names(&self) -> Vec<String> {
+        self.sessions
+            .values()
+            .filter_map(|s| s.advertisement.as_ref())
+            .map(|a| a.fullname().to_string())
+            .collect()
+    }
+}
+
+/// State of a document's live session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiveSessionInfo {
+    pub doc_id: String,
+    pub role: LiveRole,
+    pub url: String,
+    pub peers: usize,
+}
+
+impl LiveSessionInfo {
+    fn new(doc_id: &str, session: &LiveSession) -> LiveSessionInfo {
+        LiveSessionInfo {
+            doc_id: doc_id.to_string(),
+            role: session.role,
+            url: session.url.clone(),
+            peers: session.peer_count(),
+        }
+    }
+}
+
+fn ensure_document_open(manager: &State<'_, Mutex<DocumentManager>>, doc_id: &str) -> Result<(), KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    if !manager.documents.contains_key(doc_id) {
+        return Err(KorppiError::DocumentNotFound(doc_id.to_string()));
+    }
+    Ok(())
+}
+
+/// Share an open document on the local network
+///
+/// Replaces any session the document already had. The session is advertised
+/// with mDNS; peers find it with `discover_live_sessions` or join the
+/// returned URL.
+#[tauri::command]
+pub fn host_live_session(
+    app: AppHandle,
+    manager: State<'_, Mutex<DocumentManager>>,
+    sessions: State<'_, Mutex<LiveSessions>>,
+    doc_id: String,
+    port: Option<u16>,
+) -> Result<LiveSessionInfo, KorppiError> {
+    let title = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.clone()))?;
+        doc.handle.title.clone()
+    };
+    let handler = Arc::new(DocumentLiveHandler { app, doc_id: doc_id.clone() });
+    let mut session = LiveSession::host(port.unwrap_or(DEFAULT_LIVE_PORT), handler)?;
+    // Joining by URL still works on networks without multicast
+    let author = LockInfo::current().user;
+    if let Err(e) = session.advertise(&title, &author) {
+        log::warn!("Live session is not discoverable: {}", e);
+    }
+    let info = LiveSessionInfo::new(&doc_id, &session);
+    sessions.lock().map_err(|e| e.to_string())?.sessions.insert(doc_id, session);
+    Ok(info)
+}
+
+/// Join a session hosted by another instance, syncing it into an open document
+///
+/// Join from a new empty document to get a copy of the host's; joining from
+/// an existing copy merges both.
+#[tauri::command]
+pub fn join_live_session(
+    app: AppHandle,
+    manager: State<'_, Mutex<DocumentManager>>,
+    sessions: State<'_, Mutex<LiveSessions>>,
+    doc_id: String,
+    url: String,
+) -> Result<LiveSessionInfo, KorppiError> {
+    ensure_document_open(&manager, &doc_id)?;
+    let handler = Arc::new(DocumentLiveHandler { app, doc_id: doc_id.clone() });
+    let session = LiveSession::join(url.trim(), handler)?;
+    let info = LiveSessionInfo::new(&doc_id, &session);
+    sessions.lock().map_err(|e| e.to_string())?.sessions.insert(doc_id, session);
+    Ok(info)
+}
+
+/// Leave or stop the live session of a document
+#[tauri::command]
+pub fn leave_live_session(
+    sessions: State<'_, Mutex<LiveSessions>>,
+    doc_id: String,
+) -> Result<(), KorppiError> {
+    sessions.lock().map_err(|e| e.to_string())?.sessions.remove(&doc_id);
+    Ok(())
+}
+
+/// The live session of a document, if any
+#[tauri::command]
+pub fn get_live_session(
+    sessions: State<'_, Mutex<LiveSessions>>,
+    doc_id: String,
+) -> Result<Option<LiveSessionInfo>, KorppiError> {
+    let sessions = sessions.lock().map_err(|e| e.to_string())?;
+    Ok(sessions.sessions.get(&doc_id).map(|s| LiveSessionInfo::new(&doc_id, s)))
+}
+
+/// Send a local Yjs update to the peers of a document's session
+#[tauri::command]
+pub fn send_live_update(
+    sessions: State<'_, Mutex<LiveSessions>>,
+    doc_id: String,
+    update: Vec<u8>,
+) -> Result<(), KorppiError> {
+    let sessions = sessions.lock().map_err(|e| e.to_string())?;
+    if let Some(session) = sessions.sessions.get(&doc_id) {
+        session.broadcast(&LiveFrame::Update(update));
+    }
+    Ok(())
+}
+
+/// Send this user's awareness data (cursor, name) to the peers of a session
+#[tauri::command]
+pub fn send_live_awareness(
+    sessions: State<'_, Mutex<LiveSessions>>,
+    doc_id: String,
+    data: Vec<u8>,
+) -> Result<(), KorppiError> {
+    let sessions = sessions.lock().map_err(|e| e.to_string())?;
+    if let Some(session) = sessions.sessions.get(&doc_id) {
+        session.broadcast(&LiveFrame::Awareness(data));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    /// Records what arrives, and offers a fixed snapshot
+    struct Recorder {
+        snapshot: Vec<u8>,
+        frames: Mutex<Vec<(String, LiveFrame)>>,
+    }
+
+    impl Recorder {
+        fn new(snapshot: &[u8]) -> Arc<Recorder> {
+            Arc::new(Recorder {
+                snapshot: snapshot.to_vec(),
+                frames: Mutex::new(Vec::new()),
+            })
+        }
+
+        /// Wait until a frame arrives that matches
+        fn wait_for(&self, expected: &LiveFrame) -> String {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while Instant::now() < deadline {
+                if let Some((peer, _)) = self.frames.lock().unwrap().iter().find(|(_, f)| f == expected) {
+                    return peer.clone();
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            panic!("{:?} never arrived", expected);
+        }
+    }
+
+    impl LiveHandler for Recorder {
+        fn snapshot(&self) -> Option<Vec<u8>> {
+            Some(self.snapshot.clone())
+        }
+
+        fn received(&self, peer: &str, frame: &LiveFrame) {
+            self.frames.lock().unwrap().push((peer.to_string(), frame.clone()));
+        }
+
+        fn peer_left(&self, _peer: &str) {}
+    }
+
+    #[test]
+    fn test_frame_roundtrip() {
+        for frame in [LiveFrame::Update(vec![1, 2, 3]), LiveFrame::Awareness(Vec::new())] {
+            assert_eq!(LiveFrame::decode(&frame.encode()), Some(frame));
+        }
+        assert_eq!(LiveFrame::decode(&[]), None);
+        assert_eq!(LiveFrame::decode(&[9, 1]), None);
+    }
+
+    #[test]
+    fn test_host_relays_between_peers() {
+        let host_handler = Recorder::new(b"host state");
+        let host = LiveSession::host(0, host_handler.clone()).unwrap();
+        let (_, token) = host.url.split_once("/?").unwrap();
+        let url = format!("ws://127.0.0.1:{}/?{}", host.port, token);
+
+        let alice = Recorder::new(b"alice state");
+        let alice_session = LiveSession::join(&url, alice.clone()).unwrap();
+        let bob = Recorder::new(b"bob state");
+        let bob_session = LiveSession::join(&url, bob.clone()).unwrap();
+
+        // Snapshots are exchanged on connect
+        alice.wait_for(&LiveFrame::Update(b"host state".to_vec()));
+        host_handler.wait_for(&LiveFrame::Update(b"bob state".to_vec()));
+
+        let edit = LiveFrame::Update(b"alice edit".to_vec());
+        alice_session.broadcast(&edit);
+        host_handler.wait_for(&edit);
+        assert_eq!(bob.wait_for(&edit), HOST_PEER);
+
+        let cursor = LiveFrame::Awareness(b"bob cursor".to_vec());
+        bob_session.broadcast(&cursor);
+        alice.wait_for(&cursor);
+        assert_eq!(host.peer_count(), 2);
+    }
+
+    #[test]
+    fn test_host_rejects_peers_without_token() {
+        let host_handler = Recorder::new(b"host state");
+        let host = LiveSession::host(0, host_handler.clone()).unwrap();
+        assert!(host.url.contains("/?token="));
+
+        for url in [
+            format!("ws://127.0.0.1:{}", host.port),
+            format!("ws://127.0.0.1:{}/?token=guess", host.port),
+        ] {
+            let intruder = Recorder::new(b"intruder state");
+            assert!(LiveSession::join(&url, intruder.clone()).is_err());
+            assert!(intruder.frames.lock().unwrap().is_empty());
+        }
+        thread::sleep(POLL_INTERVAL * 2);
+        assert_eq!(host.peer_count(), 0);
+        assert!(host_handler.frames.lock().unwrap().is_empty());
+    }
+}
@@ -48,4 +48,21 @@ pub struct ResolutionInput {
     pub conflict_id: String,
     pub resolution: ConflictStatus,
     pub merged_content: Option<String>, // For manual merge
+    /// Save patch in which the resolution was applied to the document
+    #[serde(default)]
+    pub patch_uuid: Option<String>,
+}
+
+/// One entry of a conflict's resolution history
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConflictResolution {
+    pub id: i64,
+    pub conflict_id: String,
+    pub strategy: ConflictStatus,
+    pub author: String,
+    pub merged_content: Option<String>,
+    pub patch_uuid: Option<String>,
+    pub resolved_at: i64,
+    /// Set when the conflict was reopened after this resolution
+    pub reopened_at: Option<i64>,
 }
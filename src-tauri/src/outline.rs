@@ -0,0 +1,191 @@
+// src-tauri/src/outline.rs
+//! Document outline derived from the markdown headings.
+//!
+//! Parses the current text with pulldown-cmark into a heading tree so the
+//! frontend does not re-parse markdown for navigation. Offsets are UTF-16
+//! like the editor's; `{#sec:label}` heading attributes become labels. Each
+//! node also carries the word count of its section, subsections included.
+
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::document_manager::{current_document_text, DocumentManager};
+
+/// A heading and the section it opens
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OutlineNode {
+    /// Heading level, 1-6
+    pub level: u8,
+    pub title: String,
+    /// Section label from a `{#sec:label}` attribute
+    pub label: Option<String>,
+    /// Start of the heading line (UTF-16)
+    pub start: usize,
+    /// End of the heading line (UTF-16)
+    pub heading_end: usize,
+    /// End of the section: the next heading of the same or a higher level,
+    /// or the end of the text (UTF-16, exclusive)
+    pub end: usize,
+    /// Words of body text in the section, subsections included
+    pub words: usize,
+    pub children: Vec<OutlineNode>,
+}
+
+fn level_number(level: HeadingLevel) -> u8 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+/// Byte offsets to UTF-16 offsets
+fn utf16_offset(text: &str, byte: usize) -> usize {
+    text[..byte].encode_utf16().count()
+}
+
+/// Headings in document order, without children
+///
+/// Offsets are still bytes here; `build_outline` converts them.
+fn flat_headings(markdown: &str) -> Vec<OutlineNode> {
+    let mut headings = Vec::new();
+    // (byte offset, word count) of body text outside headings
+    let mut body_words: Vec<(usize, usize)> = Vec::new();
+    let mut current: Option<OutlineNode> = None;
+
+    for (event, range) in Parser::new_ext(markdown, Options::ENABLE_HEADING_ATTRIBUTES).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading { level, id, .. }) => {
+                current = Some(OutlineNode {
+                    level: level_number(level),
+                    title: String::new(),
+                    label: id.map(|id| id.to_string()),
+                    start: range.start,
+                    heading_end: range.end,
+                    end: markdown.len(),
+                    words: 0,
+                    children: Vec::new(),
+                });
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some(mut heading) = current.take() {
+                    heading.title = heading.title.trim().to_string();
+                    headings.push(heading);
+                }
+            }
+            Event::Text(text) | Event::Code(text) => match current.as_mut() {
+                Some(heading) => heading.title.push_str(&text),
+                None => body_words.push((range.start, text.split_whitespace().count())),
+            },
+            _ => {}
+        }
+    }
+
+    // A section runs until the next heading at the same or a higher level
+    for i in 0..headings.len() {
+        let level = headings[i].level;
+        if let Some(next) = headings[i + 1..].iter().find(|h| h.level <= level) {
+            headings[i].end = next.start;
+        }
+        let (from, to) = (headings[i].heading_end, headings[i].end);
+        headings[i].words = body_words
+            .iter()
+            .filter(|(offset, _)| from <= *offset && *offset < to)
+            .map(|(_, words)| words)
+            .sum();
+    }
+
+    headings
+}
+
+/// Parse markdown into a heading tree with UTF-16 offsets
+pub fn build_outline(markdown: &str) -> Vec<OutlineNode> {
+    let mut roots: Vec<OutlineNode> = Vec::new();
+    // Path of open headings from a root to the most recent one
+    let mut stack: Vec<OutlineNode> = Vec::new();
+
+    fn close(stack: &mut Vec<OutlineNode>, roots: &mut Vec<OutlineNode>) {
+        if let Some(node) = stack.pop() {
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(node),
+                None => roots.push(node),
+            }
+        }
+    }
+
+    for mut heading in flat_headings(markdown) {
+        heading.start = utf16_offset(markdown, heading.start);
+        heading.heading_end = utf16_offset(markdown, heading.heading_end);
+        heading.end = utf16_offset(markdown, heading.end);
+
+        while stack.last().is_some_and(|open| open.level >= heading.level) {
+            close(&mut stack, &mut roots);
+        }
+        stack.push(heading);
+    }
+    while !stack.is_empty() {
+        close(&mut stack, &mut roots);
+    }
+
+    roots
+}
+
+/// Find the section with a `{#sec:label}` in an outline
+pub fn find_section<'a>(outline: &'a [OutlineNode], label: &str) -> Option<&'a OutlineNode> {
+    outline.iter().find_map(|node| {
+        if node.label.as_deref() == Some(label) {
+            Some(node)
+        } else {
+            find_section(&node.children, label)
+        }
+    })
+}
+
+/// Get the heading tree of a document's current text
+#[tauri::command]
+pub fn get_document_outline(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+) -> Result<Vec<OutlineNode>, String> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get(&doc_id)
+        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+
+    Ok(build_outline(&current_document_text(doc, false)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_outline() {
+        let markdown = "# Intro {#sec:intro}\n\nSome text here.\n\n## Détails\n\nMore `code` words.\n\n# Methods {#sec:methods}\n\nDone.\n";
+        let outline = build_outline(markdown);
+
+        assert_eq!(outline.len(), 2);
+        let intro = &outline[0];
+        assert_eq!(intro.title, "Intro");
+        assert_eq!(intro.label.as_deref(), Some("sec:intro"));
+        assert_eq!(intro.start, 0);
+        assert_eq!(intro.words, 6);
+        assert_eq!(intro.children.len(), 1);
+        assert_eq!(intro.children[0].title, "Détails");
+        assert_eq!(intro.children[0].level, 2);
+        assert_eq!(intro.children[0].words, 3);
+
+        let methods = &outline[1];
+        let methods_start = markdown.find("# Methods").unwrap();
+        assert_eq!(methods.start, markdown[..methods_start].encode_utf16().count());
+        assert_eq!(intro.end, methods.start);
+        assert_eq!(methods.end, markdown.encode_utf16().count());
+
+        assert_eq!(find_section(&outline, "sec:methods").map(|n| n.words), Some(1));
+        assert!(find_section(&outline, "sec:missing").is_none());
+    }
+}
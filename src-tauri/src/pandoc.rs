@@ -0,0 +1,109 @@
+// src-tauri/src/pandoc.rs
+//! Locating and invoking pandoc.
+//!
+//! The executable defaults to `pandoc` on PATH but can be overridden in the
+//! application settings, which also carry extra arguments appended to every
+//! conversion. This covers flatpak/snap and portable installs.
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::settings::load_app_settings;
+
+/// Capability information reported by diagnose_pandoc
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PandocDiagnostics {
+    pub available: bool,
+    /// Executable that was invoked
+    pub executable: PathBuf,
+    /// True when the executable comes from the settings rather than PATH
+    pub configured: bool,
+    pub version: Option<String>,
+    pub extra_args: Vec<String>,
+    pub input_formats: Vec<String>,
+    pub output_formats: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// Resolve the pandoc executable and extra arguments from the settings
+fn pandoc_config() -> (PathBuf, bool, Vec<String>) {
+    let settings = load_app_settings().unwrap_or_default();
+    match settings.pandoc_path {
+        Some(path) => (path, true, settings.pandoc_args),
+        None => (PathBuf::from("pandoc"), false, settings.pandoc_args),
+    }
+}
+
+/// A pandoc command without any arguments
+fn base_command() -> Command {
+    let (executable, _, _) = pandoc_config();
+    Command::new(executable)
+}
+
+/// A pandoc command for a conversion, with the configured extra arguments
+pub fn pandoc_command() -> Command {
+    let (executable, _, extra_args) = pandoc_config();
+    let mut command = Command::new(executable);
+    command.args(extra_args);
+    command
+}
+
+/// Check if pandoc is available
+pub fn is_pandoc_available() -> bool {
+    base_command()
+        .arg("--version")
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+/// Run pandoc with a listing flag and return one entry per line
+fn list_formats(flag: &str) -> Vec<String> {
+    base_command()
+        .arg(flag)
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| {
+            String::from_utf8_lossy(&o.stdout)
+                .lines()
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Report the configured pandoc executable, its version and supported formats
+#[tauri::command]
+pub fn diagnose_pandoc() -> PandocDiagnostics {
+    let (executable, configured, extra_args) = pandoc_config();
+    let mut diagnostics = PandocDiagnostics {
+        executable: executable.clone(),
+        configured,
+        extra_args,
+        ..PandocDiagnostics::default()
+    };
+
+    match Command::new(&executable).arg("--version").output() {
+        Ok(output) if output.status.success() => {
+            diagnostics.available = true;
+            // First line looks like "pandoc 3.1.11"
+            diagnostics.version = String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .next()
+                .map(|l| l.trim_start_matches("pandoc").trim().to_string());
+            diagnostics.input_formats = list_formats("--list-input-formats");
+            diagnostics.output_formats = list_formats("--list-output-formats");
+        }
+        Ok(output) => {
+            diagnostics.error = Some(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        Err(e) => {
+            diagnostics.error = Some(format!("Failed to run {}: {}", executable.display(), e));
+        }
+    }
+
+    diagnostics
+}
@@ -0,0 +1,1026 @@
+// src-tauri/src/patch_bundle.rs
+//! Patch bundles: the changes of a document since a given patch.
+//!
+//! A `.kmd-patch` file is a ZIP holding `bundle.json` (the document UUID and
+//! the patch the bundle starts after), `history.sqlite` with the newer
+//! patches, their snapshots, reviews and review comments, and `state.yjs`,
+//! the sender's full Yjs state. Applying a bundle imports the patches
+//! missing by UUID and merges the state, so applying the same bundle twice
+//! is harmless.
+//!
+//! A bundle can also carry a chosen set of patches (or one patch and its
+//! ancestors) instead of everything since a patch. Such a partial bundle
+//! leaves out `state.yjs`, since the sender's state holds the edits of the
+//! patches left out too; the receiver gets the patches as history to review.
+//! The manifest lists the parents the selection depends on but leaves out.
+//!
+//! A bundle encrypted for a collaborator keeps `bundle.json` readable but
+//! replaces the other two files with `payload.enc`, an encrypted ZIP of
+//! them that only the recipient's key opens (see `bundle_crypto`).
+//!
+//! Bundles written by a saved profile are signed: the manifest names the
+//! sender and carries a signature of the other entries. A bundle whose
+//! signature does not match, or whose sender signs with a key other than
+//! the one their author profile in the document has, is refused. Imported
+//! patches by anyone but a sender whose key is on record are flagged as
+//! unverified, since nothing shows their author wrote them.
+//!
+//! The path-based functions work on KMD files directly and back the
+//! `korppi patch` command line; the commands work on open documents.
+
+use chrono::Utc;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Seek, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use tauri::{AppHandle, State};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use x25519_dalek::StaticSecret;
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use crate::authors::apply_author_aliases;
+use crate::bundle_crypto::{
+    decrypt_with, encrypt_for, local_identity, public_key_of, sign_with, signing_key_of, verify_signature, BundleEncryption,
+    BundleSignature, Recipient,
+};
+use crate::db_utils::ensure_schema;
+use crate::error::KorppiError;
+use crate::document_manager::{
+    bundle_to_kmd, cleanup_document_temp_dir, document_roster_dir, extract_kmd_to_temp, DocumentManager,
+};
+use crate::file_lock::{acquire_lock, release_lock};
+use crate::history_rewrite::load_patches;
+use crate::hunk_calculator::{calculate_hunks_with, DiffOptions, Hunk};
+use crate::import_conflicts::patch_uuids;
+use crate::kmd::AuthorProfile;
+use crate::patch_log::{Patch, PatchInput};
+use crate::patch_store::PatchStore;
+use crate::profile::load_saved_profile;
+use crate::review_comments::copy_review_comments;
+use crate::safe_open::{check_archive, harden_history};
+use crate::section_locks::{copy_locks, lock_warnings, SectionLockWarning};
+use crate::yjs_store::{document_text, merge_states};
+
+/// Current version of the bundle layout; version 2 added encryption
+pub const BUNDLE_VERSION: u32 = 2;
+
+/// Unencrypted bundles keep the first layout, which older releases read
+const PLAIN_BUNDLE_VERSION: u32 = 1;
+
+/// Encrypted history and state of an encrypted bundle
+const PAYLOAD_ENTRY: &str = "payload.enc";
+
+/// File extension of patch bundles
+pub const BUNDLE_EXTENSION: &str = "kmd-patch";
+
+/// Contents of bundle.json
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BundleManifest {
+    pub version: u32,
+    /// UUID of the document the patches belong to
+    pub document_uuid: String,
+    /// Last patch the receiver is expected to have; `None` bundles the whole history
+    pub since: Option<String>,
+    pub patch_count: usize,
+    /// UUIDs of the patches of a partial bundle
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<String>,
+    /// Parents of bundled patches that the bundle leaves out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_parents: Vec<String>,
+    /// RFC 3339 timestamp
+    pub created_at: String,
+    /// Set when the bundle is encrypted for one collaborator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<BundleEncryption>,
+    /// Set when the bundle is signed by its sender
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<BundleSignature>,
+}
+
+/// Outcome of applying a bundle
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BundleApplyResult {
+    pub imported_patches: usize,
+    /// Patches the document already had
+    pub skipped_patches: usize,
+    /// The bundle's Yjs state brought edits the document did not have
+    pub state_changed: bool,
+    /// Incoming patches that change sections locked by someone else
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lock_warnings: Vec<SectionLockWarning>,
+    /// Author ID of the sender, when the bundle is signed with their key on record
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signed_by: Option<String>,
+    /// Imported patches flagged as unverified
+    #[serde(default)]
+    pub unverified_patches: usize,
+    /// UUIDs of the imported patches
+    #[serde(skip)]
+    pub imported_uuids: HashSet<String>,
+}
+
+/// The profile that signs bundles, with its secret key
+pub struct BundleSigner {
+    pub id: String,
+    pub secret: StaticSecret,
+}
+
+/// Signer for bundles written on this computer; `None` until a profile is saved
+pub fn local_signer() -> Result<Option<BundleSigner>, String> {
+    load_saved_profile()?
+        .map(|profile| Ok(BundleSigner { id: profile.id, secret: local_identity()? }))
+        .transpose()
+}
+
+/// Which patches a bundle carries
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchSelection {
+    /// Every patch recorded after this one; the whole history for None
+    Since(Option<String>),
+    /// Exactly these patches, by UUID
+    Only(Vec<String>),
+    /// A patch and all its ancestors, e.g. the work on one chapter
+    Branch(String),
+}
+
+impl PatchSelection {
+    /// Selection from the optional arguments of a command; at most one of
+    /// them may be given
+    pub fn from_options(since: Option<String>, patches: Option<Vec<String>>, branch: Option<String>) -> Result<Self, String> {
+        match (since, patches, branch) {
+            (since, None, None) => Ok(Self::Since(since)),
+            (None, Some(patches), None) if patches.is_empty() => Err("No patches selected".to_string()),
+            (None, Some(patches), None) => Ok(Self::Only(patches)),
+            (None, None, Some(tip)) => Ok(Self::Branch(tip)),
+            _ => Err("Choose patches by one of since, patch list or branch".to_string()),
+        }
+    }
+
+    /// Whether the selection can leave out patches recorded after its first one
+    pub fn is_partial(&self) -> bool {
+        !matches!(self, Self::Since(_))
+    }
+}
+
+/// Patches of a selection, in recording order
+pub fn select_patches(conn: &Connection, selection: &PatchSelection) -> Result<Vec<Patch>, String> {
+    let wanted: HashSet<String> = match selection {
+        PatchSelection::Since(since) => return patches_since(conn, since.as_deref()),
+        PatchSelection::Only(uuids) => uuids.iter().cloned().collect(),
+        PatchSelection::Branch(tip) => {
+            let patches = load_patches(conn)?;
+            let parents: HashMap<&str, Option<&str>> = patches
+                .iter()
+                .filter_map(|p| Some((p.uuid.as_deref()?, p.parent_uuid.as_deref())))
+                .collect();
+            let mut chain = HashSet::new();
+            let mut next = Some(tip.as_str());
+            while let Some(uuid) = next.filter(|u| !chain.contains(*u)) {
+                let parent = *parents.get(uuid).ok_or_else(|| format!("Patch not found: {}", uuid))?;
+                chain.insert(uuid.to_string());
+                next = parent;
+            }
+            chain
+        }
+    };
+
+    let patches: Vec<Patch> = load_patches(conn)?
+        .into_iter()
+        .filter(|p| p.uuid.as_ref().is_some_and(|u| wanted.contains(u)))
+        .collect();
+    if let Some(missing) = wanted.iter().find(|u| !patches.iter().any(|p| p.uuid.as_ref() == Some(*u))) {
+        return Err(format!("Patch not found: {}", missing));
+    }
+    Ok(patches)
+}
+
+/// Parents the patches point at that are not among them, in first-use order
+pub fn missing_parents(patches: &[Patch]) -> Vec<String> {
+    let included: HashSet<&str> = patches.iter().filter_map(|p| p.uuid.as_deref()).collect();
+    let mut missing: Vec<String> = Vec::new();
+    for parent in patches.iter().filter_map(|p| p.parent_uuid.as_deref()) {
+        if !included.contains(parent) && !missing.iter().any(|m| m == parent) {
+            missing.push(parent.to_string());
+        }
+    }
+    missing
+}
+
+/// Patches recorded after `since`, in recording order
+pub fn patches_since(conn: &Connection, since: Option<&str>) -> Result<Vec<Patch>, String> {
+    let patches = load_patches(conn)?;
+    let Some(since) = since else {
+        return Ok(patches);
+    };
+    let position = patches
+        .iter()
+        .position(|p| p.uuid.as_deref() == Some(since))
+        .ok_or_else(|| format!("Patch not found: {}", since))?;
+    Ok(patches[position + 1..].to_vec())
+}
+
+/// Copy patches with their snapshots and reviews, skipping patches the
+/// target already has, and rewrite author IDs the target merged away.
+/// Returns (imported, skipped).
+pub(crate) fn copy_patches(source: &Connection, target: &Connection, patches: &[Patch]) -> Result<(usize, usize), String> {
+    let mut imported = 0;
+    let mut skipped = 0;
+    let source_store = PatchStore::on(source);
+    let target_store = PatchStore::on(target);
+
+    for patch in patches {
+        let Some(uuid) = &patch.uuid else {
+            continue;
+        };
+        let input = PatchInput {
+            timestamp: patch.timestamp,
+            author: patch.author.clone(),
+            kind: patch.kind.clone(),
+            data: patch.data.clone(),
+            uuid: Some(uuid.clone()),
+            parent_uuid: patch.parent_uuid.clone(),
+        };
+        let snapshot = source_store.snapshot(patch.id)?.map(|(_, state)| state);
+        match target_store.insert_patch(&input, snapshot.as_deref())? {
+            Some(_) => imported += 1,
+            None => skipped += 1,
+        }
+
+        // Reviews travel with their patch; the most recent decision wins
+        let mut stmt = source
+            .prepare("SELECT reviewer_id, decision, reviewer_name, reviewed_at FROM patch_reviews WHERE patch_uuid = ?1")
+            .map_err(|e| e.to_string())?;
+        let reviews = stmt
+            .query_map(params![uuid], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, i64>(3)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        for (reviewer_id, decision, reviewer_name, reviewed_at) in reviews {
+            target
+                .execute(
+                    "INSERT INTO patch_reviews (patch_uuid, reviewer_id, decision, reviewer_name, reviewed_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5)
+                     ON CONFLICT(patch_uuid, reviewer_id) DO UPDATE SET
+                        decision = excluded.decision,
+                        reviewer_name = excluded.reviewer_name,
+                        reviewed_at = excluded.reviewed_at
+                     WHERE excluded.reviewed_at > patch_reviews.reviewed_at",
+                    params![uuid, reviewer_id, decision, reviewer_name, reviewed_at],
+                )
+                .map_err(|e| e.to_string())?;
+        }
+        copy_review_comments(source, target, uuid)?;
+    }
+    apply_author_aliases(target)?;
+
+    Ok((imported, skipped))
+}
+
+/// Write named entries to a ZIP archive
+fn write_entries<W: Write + Seek>(writer: W, entries: &[(&str, &[u8])]) -> Result<W, String> {
+    let mut zip = ZipWriter::new(writer);
+    let options = FileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .unix_permissions(0o644);
+    for (name, data) in entries {
+        zip.start_file(*name, options).map_err(|e| e.to_string())?;
+        zip.write_all(data).map_err(|e| e.to_string())?;
+    }
+    zip.finish().map_err(|e| e.to_string())
+}
+
+/// What a bundle signature covers: the document, the sender and the hash
+/// of each entry besides the manifest
+fn signed_message(document_uuid: &str, signer_id: &str, entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut message = format!("korppi bundle\n{}\n{}\n", document_uuid, signer_id);
+    for (name, data) in entries {
+        message.push_str(&format!("{} {:x}\n", name, Sha256::digest(data)));
+    }
+    message.into_bytes()
+}
+
+/// Check the signature of a bundle, if it has one, against its entries
+fn check_signature(manifest: &BundleManifest, entries: &[(&str, &[u8])]) -> Result<(), String> {
+    let Some(signature) = &manifest.signature else {
+        return Ok(());
+    };
+    let message = signed_message(&manifest.document_uuid, &signature.signer_id, entries);
+    verify_signature(&signature.signer_key, &message, &signature.signature)
+        .map_err(|_| format!("Patch bundle signed by {} was altered after signing", signature.signer_id))
+}
+
+/// Write a bundle of the selected patches from a history database,
+/// encrypted if a recipient is given and signed if a signer is
+pub fn create_bundle(
+    history_path: &Path,
+    document_uuid: &str,
+    selection: &PatchSelection,
+    yjs_state: &[u8],
+    recipient: Option<&Recipient>,
+    signer: Option<&BundleSigner>,
+    dest: &Path,
+) -> Result<BundleManifest, String> {
+    let source = Connection::open(history_path).map_err(|e| e.to_string())?;
+    ensure_schema(&source)?;
+    let patches = select_patches(&source, selection)?;
+
+    let temp_dir = tempfile::TempDir::new().map_err(|e| e.to_string())?;
+    let bundle_history = temp_dir.path().join("history.sqlite");
+    {
+        let target = Connection::open(&bundle_history).map_err(|e| e.to_string())?;
+        ensure_schema(&target)?;
+        copy_patches(&source, &target, &patches)?;
+        copy_locks(&source, &target)?;
+    }
+
+    let partial = selection.is_partial();
+    let mut manifest = BundleManifest {
+        version: PLAIN_BUNDLE_VERSION,
+        document_uuid: document_uuid.to_string(),
+        since: match selection {
+            PatchSelection::Since(since) => since.clone(),
+            _ => None,
+        },
+        patch_count: patches.len(),
+        patches: if partial { patches.iter().filter_map(|p| p.uuid.clone()).collect() } else { Vec::new() },
+        missing_parents: if partial { missing_parents(&patches) } else { Vec::new() },
+        created_at: Utc::now().to_rfc3339(),
+        encryption: None,
+        signature: None,
+    };
+
+    let history = fs::read(&bundle_history).map_err(|e| e.to_string())?;
+    let mut content: Vec<(&str, &[u8])> = vec![("history.sqlite", &history)];
+    if !partial {
+        content.push(("state.yjs", yjs_state));
+    }
+    let payload = match recipient {
+        Some(recipient) => {
+            let inner = write_entries(Cursor::new(Vec::new()), &content)?.into_inner();
+            let (encryption, ciphertext) = encrypt_for(recipient, &inner, document_uuid.as_bytes())?;
+            manifest.version = BUNDLE_VERSION;
+            manifest.encryption = Some(encryption);
+            Some(ciphertext)
+        }
+        None => None,
+    };
+
+    let content = match &payload {
+        Some(ciphertext) => vec![(PAYLOAD_ENTRY, ciphertext.as_slice())],
+        None => content,
+    };
+    if let Some(signer) = signer {
+        manifest.signature = Some(BundleSignature {
+            signer_id: signer.id.clone(),
+            signer_key: signing_key_of(&signer.secret),
+            signature: sign_with(&signer.secret, &signed_message(document_uuid, &signer.id, &content)),
+        });
+    }
+
+    let manifest_json = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    let mut entries: Vec<(&str, &[u8])> = vec![("bundle.json", manifest_json.as_bytes())];
+    entries.extend(content);
+    let file = File::create(dest).map_err(|e| format!("Failed to create bundle: {}", e))?;
+    write_entries(file, &entries)?;
+
+    Ok(manifest)
+}
+
+/// A bundle unpacked to a temporary directory
+pub struct OpenedBundle {
+    pub manifest: BundleManifest,
+    /// Yjs state of the sender
+    pub yjs_state: Vec<u8>,
+    /// Connection to the bundled history; valid while `_dir` lives
+    pub history: Connection,
+    _dir: tempfile::TempDir,
+}
+
+fn open_archive(bundle_path: &Path) -> Result<ZipArchive<File>, String> {
+    let file = File::open(bundle_path).map_err(|e| format!("Failed to open bundle: {}", e))?;
+    ZipArchive::new(file).map_err(|e| format!("Invalid patch bundle: {}", e))
+}
+
+fn archive_manifest(archive: &mut ZipArchive<File>) -> Result<BundleManifest, String> {
+    let manifest: BundleManifest = {
+        let mut entry = archive.by_name("bundle.json").map_err(|_| "Missing bundle.json in patch bundle")?;
+        let mut content = String::new();
+        entry.read_to_string(&mut content).map_err(|e| e.to_string())?;
+        serde_json::from_str(&content).map_err(|e| format!("Invalid bundle.json: {}", e))?
+    };
+    if manifest.version > BUNDLE_VERSION {
+        return Err(format!(
+            "Patch bundle version {} is newer than supported version {}",
+            manifest.version, BUNDLE_VERSION
+        ));
+    }
+    Ok(manifest)
+}
+
+/// Read the manifest of a bundle without unpacking it
+pub fn read_bundle_manifest(bundle_path: &Path) -> Result<BundleManifest, String> {
+    archive_manifest(&mut open_archive(bundle_path)?)
+}
+
+/// Read an archive entry, or None if it is missing
+fn read_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<Option<Vec<u8>>, String> {
+    let Ok(mut entry) = archive.by_name(name) else {
+        return Ok(None);
+    };
+    let mut data = Vec::new();
+    entry.read_to_end(&mut data).map_err(|e| e.to_string())?;
+    Ok(Some(data))
+}
+
+/// Unpack a bundle and check that it belongs to the document
+///
+/// Encrypted bundles are decrypted with this profile's key.
+pub fn open_bundle(bundle_path: &Path, document_uuid: &str) -> Result<OpenedBundle, String> {
+    open_bundle_as(bundle_path, document_uuid, local_identity)
+}
+
+/// Unpack a bundle, getting the secret key only if it is encrypted
+fn open_bundle_as(
+    bundle_path: &Path,
+    document_uuid: &str,
+    identity: impl FnOnce() -> Result<StaticSecret, String>,
+) -> Result<OpenedBundle, String> {
+    let mut archive = open_archive(bundle_path)?;
+    check_archive(&mut archive)?;
+    let manifest = archive_manifest(&mut archive)?;
+    if manifest.document_uuid != document_uuid {
+        return Err("Patch bundle belongs to a different document".to_string());
+    }
+
+    let (history_data, yjs_state) = match &manifest.encryption {
+        Some(encryption) => {
+            let ciphertext = read_entry(&mut archive, PAYLOAD_ENTRY)?.ok_or("Missing payload.enc in patch bundle")?;
+            check_signature(&manifest, &[(PAYLOAD_ENTRY, &ciphertext)])?;
+            let inner = decrypt_with(&identity()?, encryption, &ciphertext, document_uuid.as_bytes())?;
+            let mut inner = ZipArchive::new(Cursor::new(inner)).map_err(|e| format!("Invalid patch bundle: {}", e))?;
+            check_archive(&mut inner)?;
+            (read_entry(&mut inner, "history.sqlite")?, read_entry(&mut inner, "state.yjs")?)
+        }
+        None => {
+            let history = read_entry(&mut archive, "history.sqlite")?;
+            let state = read_entry(&mut archive, "state.yjs")?;
+            let signed: Vec<(&str, &[u8])> = [("history.sqlite", &history), ("state.yjs", &state)]
+                .into_iter()
+                .filter_map(|(name, data)| Some((name, data.as_deref()?)))
+                .collect();
+            check_signature(&manifest, &signed)?;
+            (history, state)
+        }
+    };
+
+    let temp_dir = tempfile::TempDir::new().map_err(|e| e.to_string())?;
+    let bundle_history = temp_dir.path().join("history.sqlite");
+    fs::write(&bundle_history, history_data.ok_or("Missing history.sqlite in patch bundle")?)
+        .map_err(|e| e.to_string())?;
+    let yjs_state = yjs_state.unwrap_or_default();
+    // Bundles always come from someone else
+    for stripped in harden_history(&bundle_history, "history.sqlite")? {
+        log::warn!("Left {} out of patch bundle {}", stripped, bundle_path.display());
+    }
+    let history = Connection::open(&bundle_history).map_err(|e| e.to_string())?;
+    ensure_schema(&history)?;
+
+    Ok(OpenedBundle {
+        manifest,
+        yjs_state,
+        history,
+        _dir: temp_dir,
+    })
+}
+
+/// Apply a bundle to a history database and Yjs state
+///
+/// Returns the result and the merged Yjs state.
+pub fn apply_bundle(
+    history_path: &Path,
+    document_uuid: &str,
+    yjs_state: &[u8],
+    bundle_path: &Path,
+) -> Result<(BundleApplyResult, Vec<u8>), String> {
+    let bundle = open_bundle(bundle_path, document_uuid)?;
+    let signed_by = verified_signer(history_path, &bundle.manifest)?;
+    let source = &bundle.history;
+    let incoming = &bundle.yjs_state;
+    let target = Connection::open(history_path).map_err(|e| e.to_string())?;
+    ensure_schema(&target)?;
+    let patches = load_patches(source)?;
+    let known = patch_uuids(history_path)?;
+    let tx = target.unchecked_transaction().map_err(|e| e.to_string())?;
+    let (imported_patches, skipped_patches) = copy_patches(source, &target, &patches)?;
+    copy_locks(source, &target)?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    let imported_uuids: HashSet<String> = patch_uuids(history_path)?.difference(&known).cloned().collect();
+    let unverified_patches = PatchStore::on(&target).mark_unverified(&imported_uuids, signed_by.as_deref())?;
+    let lock_warnings = lock_warnings(&target, &imported_uuids)?;
+
+    let merged = merge_states(&[yjs_state, incoming])?;
+    let state_changed = merged != merge_states(&[yjs_state])?;
+
+    Ok((
+        BundleApplyResult {
+            imported_patches,
+            skipped_patches,
+            state_changed,
+            lock_warnings,
+            signed_by,
+            unverified_patches,
+            imported_uuids,
+        },
+        merged,
+    ))
+}
+
+/// What applying a bundle would do to a document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleDryRun {
+    #[serde(flatten)]
+    pub result: BundleApplyResult,
+    /// Markdown of the document once the bundle is applied
+    pub text: String,
+    /// Changes from the current text to `text`
+    pub hunks: Vec<Hunk>,
+}
+
+/// Apply a bundle to a copy of a history database and to the Yjs state in
+/// memory, leaving the document as it is
+///
+/// `updates` are the document's unsaved Yjs updates, which stay on top of
+/// the merged state.
+pub fn dry_run_bundle(
+    history_path: &Path,
+    document_uuid: &str,
+    yjs_state: &[u8],
+    updates: &[&[u8]],
+    bundle_path: &Path,
+    options: &DiffOptions,
+) -> Result<BundleDryRun, String> {
+    let temp_dir = tempfile::TempDir::new().map_err(|e| e.to_string())?;
+    let history_copy = temp_dir.path().join("history.sqlite");
+    if history_path.exists() {
+        fs::copy(history_path, &history_copy).map_err(|e| e.to_string())?;
+    }
+    let (result, merged) = apply_bundle(&history_copy, document_uuid, yjs_state, bundle_path)?;
+
+    let mut current: Vec<&[u8]> = vec![yjs_state];
+    current.extend_from_slice(updates);
+    let current_text = document_text(&current, false)?;
+    current[0] = &merged;
+    let text = document_text(&current, false)?;
+    let hunks = calculate_hunks_with(&current_text, &text, options);
+    Ok(BundleDryRun { result, text, hunks })
+}
+
+/// Whether an author ID is this computer's saved profile
+fn is_local_author(author_id: &str) -> bool {
+    load_saved_profile().ok().flatten().is_some_and(|p| p.id == author_id)
+}
+
+/// Profile of a document author, from the author profiles of the document
+fn roster_profile(history_path: &Path, author_id: &str) -> Option<AuthorProfile> {
+    (!author_id.contains(['/', '\\']))
+        .then(|| fs::read_to_string(document_roster_dir(history_path).join(format!("{}.json", author_id))).ok())
+        .flatten()
+        .and_then(|content| serde_json::from_str(&content).ok())
+}
+
+/// The author a bundle's signature vouches for
+///
+/// `None` when the bundle is unsigned or no signing key of its sender is on
+/// record; a signature by another key than the one on record is refused.
+pub(crate) fn verified_signer(history_path: &Path, manifest: &BundleManifest) -> Result<Option<String>, String> {
+    let Some(signature) = &manifest.signature else {
+        return Ok(None);
+    };
+    let known_key = if is_local_author(&signature.signer_id) {
+        Some(signing_key_of(&local_identity()?))
+    } else {
+        roster_profile(history_path, &signature.signer_id).and_then(|profile| profile.signing_key)
+    };
+    match known_key {
+        Some(key) if key == signature.signer_key => Ok(Some(signature.signer_id.clone())),
+        Some(_) => Err(format!(
+            "Patch bundle claims to come from {} but is signed with another key",
+            signature.signer_id
+        )),
+        None => Ok(None),
+    }
+}
+
+/// The encryption key of a document author, from the author profiles of
+/// the document (or this profile's own key)
+pub fn find_recipient(history_path: &Path, author_id: &str) -> Result<Recipient, String> {
+    if is_local_author(author_id) {
+        return Ok(Recipient {
+            id: author_id.to_string(),
+            public_key: public_key_of(&local_identity()?),
+        });
+    }
+    let public_key = roster_profile(history_path, author_id)
+        .and_then(|profile| profile.public_key)
+        .ok_or_else(|| {
+            format!(
+                "No encryption key is known for {}; they need to save this document once with a current version",
+                author_id
+            )
+        })?;
+    Ok(Recipient {
+        id: author_id.to_string(),
+        public_key,
+    })
+}
+
+/// Write a bundle of the selected changes in a KMD file, encrypted for
+/// an author of the document if one is given
+pub fn export_bundle_from_kmd(
+    kmd_path: &Path,
+    selection: &PatchSelection,
+    recipient: Option<&str>,
+    dest: &Path,
+) -> Result<BundleManifest, String> {
+    let doc_id = format!("bundle-{}", Uuid::new_v4());
+    let result = extract_kmd_to_temp(&kmd_path.to_path_buf(), &doc_id).and_then(|extracted| {
+        let recipient = recipient.map(|id| find_recipient(&extracted.history_path, id)).transpose()?;
+        let signer = local_signer()?;
+        create_bundle(
+            &extracted.history_path,
+            &extracted.meta.uuid,
+            selection,
+            &extracted.yjs_state,
+            recipient.as_ref(),
+            signer.as_ref(),
+            dest,
+        )
+    });
+    cleanup_document_temp_dir(&doc_id).ok();
+    result
+}
+
+/// Apply a bundle to a KMD file in place
+///
+/// Fails if the document is open elsewhere, as that copy would overwrite
+/// the result on its next save.
+pub fn apply_bundle_to_kmd(kmd_path: &Path, bundle_path: &Path) -> Result<BundleApplyResult, String> {
+    acquire_lock(kmd_path)?;
+    let doc_id = format!("bundle-{}", Uuid::new_v4());
+    let result = extract_kmd_to_temp(&kmd_path.to_path_buf(), &doc_id).and_then(|mut extracted| {
+        let (result, merged) = apply_bundle(&extracted.history_path, &extracted.meta.uuid, &extracted.yjs_state, bundle_path)?;
+        if result.imported_patches > 0 || result.state_changed {
+            extracted.meta.modified_at = Utc::now().to_rfc3339();
+            bundle_to_kmd(
+                &kmd_path.to_path_buf(),
+                &merged,
+                &[],
+                &extracted.history_path,
+                &extracted.meta,
+                &extracted.sections,
+            )?;
+        }
+        Ok(result)
+    });
+    cleanup_document_temp_dir(&doc_id).ok();
+    release_lock(kmd_path);
+    result
+}
+
+/// Export the patches of an open document recorded after `since` as a bundle
+///
+/// `patches` (UUIDs) or `branch` (a patch UUID, taken with its ancestors)
+/// export only those patches instead; the returned manifest lists the
+/// parents they depend on but leave out.
+///
+/// With a recipient (an author ID), the bundle is encrypted so only that
+/// collaborator can apply it. Once a profile is saved, bundles are signed
+/// with its key.
+#[tauri::command]
+pub fn export_patch_bundle(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    since: Option<String>,
+    patches: Option<Vec<String>>,
+    branch: Option<String>,
+    path: String,
+    recipient: Option<String>,
+) -> Result<BundleManifest, KorppiError> {
+    let selection = PatchSelection::from_options(since, patches, branch).map_err(KorppiError::InvalidInput)?;
+    let (history_path, document_uuid, yjs_state) = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager.documents.get(&id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
+        let mut parts: Vec<&[u8]> = vec![&doc.yjs_state];
+        parts.extend(doc.yjs_updates.iter().map(|u| u.data.as_slice()));
+        (doc.history_path.clone(), doc.meta.uuid.clone(), merge_states(&parts)?)
+    };
+    let recipient = recipient
+        .filter(|r| !r.is_empty())
+        .map(|r| find_recipient(&history_path, &r).map_err(KorppiError::InvalidInput))
+        .transpose()?;
+    let signer = local_signer()?;
+    Ok(create_bundle(
+        &history_path,
+        &document_uuid,
+        &selection,
+        &yjs_state,
+        recipient.as_ref(),
+        signer.as_ref(),
+        Path::new(&path),
+    )?)
+}
+
+/// Apply a patch bundle to an open document
+#[tauri::command]
+pub fn apply_patch_bundle(
+    app: AppHandle,
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    path: String,
+) -> Result<BundleApplyResult, KorppiError> {
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get_mut(&id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
+    if doc.handle.read_only {
+        return Err("Document is open read-only".into());
+    }
+
+    let (result, merged) = apply_bundle(&doc.history_path, &doc.meta.uuid, &doc.yjs_state, Path::new(&path))?;
+    if result.imported_patches > 0 || result.state_changed {
+        doc.yjs_state = merged;
+        crate::journal::journal_state(doc)?;
+        doc.handle.is_modified = true;
+    }
+    crate::import_conflicts::check_after_import(&app, &id, &doc.history_path, &result.imported_uuids);
+    crate::section_locks::emit_lock_warnings(&app, &id, &result.lock_warnings);
+    Ok(result)
+}
+
+/// Preview applying a patch bundle to an open document: the merged text,
+/// its changes and the patches that would be imported
+///
+/// Nothing is written to the history or the document.
+#[tauri::command]
+pub fn dry_run_import_bundle(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    path: String,
+) -> Result<BundleDryRun, KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get(&doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+    let updates: Vec<&[u8]> = doc.yjs_updates.iter().map(|u| u.data.as_slice()).collect();
+    Ok(dry_run_bundle(
+        &doc.history_path,
+        &doc.meta.uuid,
+        &doc.yjs_state,
+        &updates,
+        Path::new(&path),
+        &doc.meta.settings.diff,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::patch_store::{insert_test_patch, test_patch};
+
+    fn history(dir: &Path, name: &str, uuids: &[&str]) -> std::path::PathBuf {
+        let path = dir.join(name);
+        let conn = Connection::open(&path).unwrap();
+        ensure_schema(&conn).unwrap();
+        let mut parent = None;
+        for uuid in uuids {
+            insert_test_patch(&conn, test_patch(uuid, parent, uuid));
+            parent = Some(*uuid);
+        }
+        path
+    }
+
+    fn uuids(path: &Path) -> Vec<String> {
+        let conn = Connection::open(path).unwrap();
+        load_patches(&conn).unwrap().into_iter().filter_map(|p| p.uuid).collect()
+    }
+
+    #[test]
+    fn test_bundle_roundtrip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let sender = history(dir.path(), "sender.sqlite", &["p1", "p2", "p3"]);
+        let receiver = history(dir.path(), "receiver.sqlite", &["p1"]);
+        {
+            let conn = Connection::open(&sender).unwrap();
+            conn.execute(
+                "INSERT INTO patch_reviews (patch_uuid, reviewer_id, decision, reviewed_at) VALUES ('p3', 'bob', 'accepted', 5)",
+                [],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO patch_review_comments (uuid, patch_uuid, author_id, content, created_at) VALUES ('c1', 'p3', 'bob', 'Clearer', 5)",
+                [],
+            )
+            .unwrap();
+        }
+
+        let bundle = dir.path().join("changes.kmd-patch");
+        let manifest = create_bundle(&sender, "doc-1", &PatchSelection::Since(Some("p1".to_string())), &[], None, None, &bundle).unwrap();
+        assert_eq!(manifest.patch_count, 2);
+        assert_eq!(manifest.since.as_deref(), Some("p1"));
+
+        let (result, _) = apply_bundle(&receiver, "doc-1", &[], &bundle).unwrap();
+        assert_eq!(result.imported_patches, 2);
+        assert_eq!(result.skipped_patches, 0);
+        assert_eq!(uuids(&receiver), vec!["p1", "p2", "p3"]);
+
+        let conn = Connection::open(&receiver).unwrap();
+        let decision: String = conn
+            .query_row("SELECT decision FROM patch_reviews WHERE patch_uuid = 'p3'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(decision, "accepted");
+        let comment: String = conn
+            .query_row("SELECT content FROM patch_review_comments WHERE patch_uuid = 'p3'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(comment, "Clearer");
+        drop(conn);
+
+        // Applying again changes nothing
+        let (result, _) = apply_bundle(&receiver, "doc-1", &[], &bundle).unwrap();
+        assert_eq!(result.imported_patches, 0);
+        assert_eq!(result.skipped_patches, 2);
+        assert_eq!(uuids(&receiver).len(), 3);
+    }
+
+    #[test]
+    fn test_dry_run_leaves_history_alone() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let sender = history(dir.path(), "sender.sqlite", &["p1", "p2"]);
+        let receiver = history(dir.path(), "receiver.sqlite", &["p1"]);
+        let bundle = dir.path().join("changes.kmd-patch");
+        create_bundle(&sender, "doc-1", &PatchSelection::Since(None), &[], None, None, &bundle).unwrap();
+
+        let preview = dry_run_bundle(&receiver, "doc-1", &[], &[], &bundle, &DiffOptions::default()).unwrap();
+        assert_eq!(preview.result.imported_patches, 1);
+        assert_eq!(preview.result.skipped_patches, 1);
+        assert!(!preview.result.state_changed);
+        assert!(preview.hunks.is_empty());
+        assert_eq!(uuids(&receiver), vec!["p1"]);
+
+        assert!(dry_run_bundle(&receiver, "doc-2", &[], &[], &bundle, &DiffOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_selective_bundle() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let sender = history(dir.path(), "sender.sqlite", &["p1", "p2", "p3"]);
+        {
+            // A side branch off p1
+            let conn = Connection::open(&sender).unwrap();
+            insert_test_patch(&conn, test_patch("b1", Some("p1"), "b1"));
+        }
+        let conn = Connection::open(&sender).unwrap();
+        let uuids_of = |patches: Vec<Patch>| patches.into_iter().filter_map(|p| p.uuid).collect::<Vec<_>>();
+        let branch = select_patches(&conn, &PatchSelection::Branch("p3".to_string())).unwrap();
+        assert_eq!(uuids_of(branch), vec!["p1", "p2", "p3"]);
+        let only = select_patches(&conn, &PatchSelection::Only(vec!["b1".to_string(), "p3".to_string()])).unwrap();
+        assert_eq!(missing_parents(&only), vec!["p2", "p1"]);
+        assert!(select_patches(&conn, &PatchSelection::Only(vec!["nope".to_string()])).is_err());
+        assert!(select_patches(&conn, &PatchSelection::Branch("nope".to_string())).is_err());
+        drop(conn);
+
+        // A partial bundle carries the patches but not the sender's state
+        let bundle = dir.path().join("chapter.kmd-patch");
+        let selection = PatchSelection::Only(vec!["p3".to_string()]);
+        let manifest = create_bundle(&sender, "doc-1", &selection, b"state", None, None, &bundle).unwrap();
+        assert_eq!(manifest.patches, vec!["p3"]);
+        assert_eq!(manifest.missing_parents, vec!["p2"]);
+        assert_eq!(read_bundle_manifest(&bundle).unwrap(), manifest);
+        assert!(open_bundle(&bundle, "doc-1").unwrap().yjs_state.is_empty());
+
+        let receiver = history(dir.path(), "receiver.sqlite", &["p1", "p2"]);
+        let (result, _) = apply_bundle(&receiver, "doc-1", &[], &bundle).unwrap();
+        assert_eq!(result.imported_patches, 1);
+        assert!(!result.state_changed);
+        assert_eq!(uuids(&receiver), vec!["p1", "p2", "p3"]);
+
+        assert!(PatchSelection::from_options(Some("p1".to_string()), None, Some("p3".to_string())).is_err());
+        assert!(PatchSelection::from_options(None, Some(Vec::new()), None).is_err());
+    }
+
+    #[test]
+    fn test_bundle_checks() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let sender = history(dir.path(), "sender.sqlite", &["p1"]);
+        let bundle = dir.path().join("changes.kmd-patch");
+
+        assert!(create_bundle(&sender, "doc-1", &PatchSelection::Since(Some("missing".to_string())), &[], None, None, &bundle).is_err());
+
+        create_bundle(&sender, "doc-1", &PatchSelection::Since(None), &[], None, None, &bundle).unwrap();
+        let receiver = history(dir.path(), "receiver.sqlite", &[]);
+        let err = apply_bundle(&receiver, "doc-2", &[], &bundle).unwrap_err();
+        assert!(err.contains("different document"));
+    }
+
+    #[test]
+    fn test_encrypted_bundle() {
+        use aes_gcm::aead::OsRng;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let sender = history(dir.path(), "sender.sqlite", &["p1", "p2"]);
+        let bundle = dir.path().join("changes.kmd-patch");
+        let bob = StaticSecret::random_from_rng(OsRng);
+        let recipient = Recipient {
+            id: "bob".to_string(),
+            public_key: public_key_of(&bob),
+        };
+
+        let manifest = create_bundle(&sender, "doc-1", &PatchSelection::Since(None), b"state", Some(&recipient), None, &bundle).unwrap();
+        assert_eq!(manifest.version, BUNDLE_VERSION);
+        assert_eq!(read_bundle_manifest(&bundle).unwrap().encryption, manifest.encryption);
+        let mut archive = open_archive(&bundle).unwrap();
+        assert!(archive.by_name("history.sqlite").is_err());
+
+        let opened = open_bundle_as(&bundle, "doc-1", || Ok(bob.clone())).unwrap();
+        assert_eq!(opened.yjs_state, b"state");
+        assert_eq!(load_patches(&opened.history).unwrap().len(), 2);
+
+        let carol = StaticSecret::random_from_rng(OsRng);
+        let err = open_bundle_as(&bundle, "doc-1", || Ok(carol)).err().unwrap();
+        assert!(err.contains("another collaborator"));
+    }
+
+    #[test]
+    fn test_signed_bundle() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let sender = history(dir.path(), "sender.sqlite", &["p1", "p2", "p3"]);
+        Connection::open(&sender)
+            .unwrap()
+            .execute("UPDATE patches SET author = 'mallory' WHERE uuid = 'p3'", [])
+            .unwrap();
+        let alice = BundleSigner { id: "alice".to_string(), secret: StaticSecret::from([7u8; 32]) };
+        let bundle = dir.path().join("changes.kmd-patch");
+        let manifest = create_bundle(&sender, "doc-1", &PatchSelection::Since(None), &[], None, Some(&alice), &bundle).unwrap();
+        assert_eq!(manifest.signature.as_ref().unwrap().signer_id, "alice");
+
+        // A receiver with the history of one document and the author
+        // profile of alice with the given signing key
+        let receiver = |name: &str, key: Option<String>| {
+            let doc_dir = dir.path().join(name);
+            fs::create_dir_all(&doc_dir).unwrap();
+            let history_path = history(&doc_dir, "history.sqlite", &["p1"]);
+            let profile = AuthorProfile {
+                id: "alice".to_string(),
+                name: "Alice".to_string(),
+                email: None,
+                color: "#3498db".to_string(),
+                avatar_base64: None,
+                public_key: None,
+                signing_key: key,
+            };
+            let roster = document_roster_dir(&history_path);
+            fs::create_dir_all(&roster).unwrap();
+            fs::write(roster.join("alice.json"), serde_json::to_string(&profile).unwrap()).unwrap();
+            history_path
+        };
+
+        // Only patches by the signer count as verified
+        let known = receiver("known", Some(signing_key_of(&alice.secret)));
+        let (result, _) = apply_bundle(&known, "doc-1", &[], &bundle).unwrap();
+        assert_eq!(result.signed_by.as_deref(), Some("alice"));
+        assert_eq!((result.imported_patches, result.unverified_patches), (2, 1));
+        let store = PatchStore::open(&known).unwrap();
+        assert_eq!(store.unverified_uuids().unwrap(), HashSet::from(["p3".to_string()]));
+
+        // Without the signer's key on record nothing is verified
+        let (result, _) = apply_bundle(&receiver("unknown", None), "doc-1", &[], &bundle).unwrap();
+        assert_eq!((result.signed_by, result.unverified_patches), (None, 2));
+
+        // A bundle signed as alice by someone else is refused
+        let impostor = receiver("impostor", Some(signing_key_of(&StaticSecret::from([8u8; 32]))));
+        assert!(apply_bundle(&impostor, "doc-1", &[], &bundle).unwrap_err().contains("another key"));
+        assert_eq!(uuids(&impostor), vec!["p1"]);
+
+        // So is a signed bundle whose history was swapped
+        let other = history(dir.path(), "other.sqlite", &["p1", "p4"]);
+        let mut archive = open_archive(&bundle).unwrap();
+        let manifest_json = read_entry(&mut archive, "bundle.json").unwrap().unwrap();
+        let forged = dir.path().join("forged.kmd-patch");
+        let entries: [(&str, &[u8]); 2] = [("bundle.json", &manifest_json), ("history.sqlite", &fs::read(&other).unwrap())];
+        write_entries(File::create(&forged).unwrap(), &entries).unwrap();
+        assert!(apply_bundle(&known, "doc-1", &[], &forged).unwrap_err().contains("altered"));
+    }
+}
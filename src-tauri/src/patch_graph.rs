@@ -0,0 +1,408 @@
+// src-tauri/src/patch_graph.rs
+//! The patch dependency graph: each patch builds on its `parent_uuid`.
+//!
+//! Rejecting a patch can orphan everything built on it. Orphans are kept in
+//! the `orphaned_patches` table of the history database, one row per
+//! orphaned patch and rejected ancestor, so accepting the ancestor again
+//! releases exactly the patches it orphaned.
+//!
+//! `get_patch_graph` lays the graph out like a commit graph: one row per
+//! patch in recording order, and a lane per line of development, with
+//! edges for parent links and for runs of edits by the same author.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::db_utils::ensure_schema;
+use crate::document_manager::{author_display_name, DocumentManager};
+use crate::error::KorppiError;
+use crate::history_rewrite::load_patches;
+use crate::kmd::DocumentMeta;
+use crate::patch_log::Patch;
+
+/// Longest pause (ms) between two patches of an author in one session
+const SESSION_GAP_MS: i64 = 30 * 60 * 1000;
+
+/// Combined review decision on a patch
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewStatus {
+    Pending,
+    Accepted,
+    Rejected,
+    /// Accepted by some reviewers and rejected by others
+    Contested,
+}
+
+/// A patch in the graph
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchGraphNode {
+    pub uuid: String,
+    pub id: i64,
+    pub kind: String,
+    pub author: String,
+    pub author_name: String,
+    pub timestamp: i64,
+    pub status: ReviewStatus,
+    /// Rejected ancestor that orphaned this patch, if any
+    pub orphaned_by: Option<String>,
+    /// Position in recording order, from 0
+    pub row: usize,
+    /// Column of the line of development, from 0
+    pub lane: usize,
+    /// Editing session, numbered in order of first patch
+    pub session: usize,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PatchGraphEdgeKind {
+    /// `to` builds on `from`
+    Parent,
+    /// `to` follows `from` in the same editing session
+    Session,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PatchGraphEdge {
+    pub from: String,
+    pub to: String,
+    pub kind: PatchGraphEdgeKind,
+}
+
+/// The patch graph of a document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchGraph {
+    /// Nodes by row
+    pub nodes: Vec<PatchGraphNode>,
+    pub edges: Vec<PatchGraphEdge>,
+    pub lanes: usize,
+    pub sessions: usize,
+}
+
+/// Child patch UUIDs of each patch UUID, in recording order
+pub(crate) fn patch_children(conn: &Connection) -> Result<HashMap<String, Vec<String>>, String> {
+    let mut stmt = conn
+        .prepare("SELECT uuid, parent_uuid FROM patches WHERE uuid IS NOT NULL AND parent_uuid IS NOT NULL ORDER BY id")
+        .map_err(|e| e.to_string())?;
+    let edges = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut children: HashMap<String, Vec<String>> = HashMap::new();
+    for (uuid, parent) in edges {
+        children.entry(parent).or_default().push(uuid);
+    }
+    Ok(children)
+}
+
+/// UUIDs of all transitive children of a patch, nearest first
+pub(crate) fn patch_descendants(conn: &Connection, patch_uuid: &str) -> Result<Vec<String>, String> {
+    let children = patch_children(conn)?;
+    let mut seen = HashSet::from([patch_uuid.to_string()]);
+    let mut queue = VecDeque::from([patch_uuid.to_string()]);
+    let mut descendants = Vec::new();
+    while let Some(uuid) = queue.pop_front() {
+        for child in children.get(&uuid).into_iter().flatten() {
+            // Guard against parent cycles in damaged histories
+            if seen.insert(child.clone()) {
+                descendants.push(child.clone());
+                queue.push_back(child.clone());
+            }
+        }
+    }
+    Ok(descendants)
+}
+
+/// Mark the descendants of a rejected patch as orphaned
+///
+/// Returns the UUIDs of the descendants.
+pub(crate) fn orphan_descendants(conn: &Connection, rejected_uuid: &str, reviewer_id: &str) -> Result<Vec<String>, String> {
+    let descendants = patch_descendants(conn, rejected_uuid)?;
+    let orphaned_at = chrono::Utc::now().timestamp_millis();
+    for uuid in &descendants {
+        conn.execute(
+            "INSERT OR REPLACE INTO orphaned_patches (patch_uuid, rejected_uuid, reviewer_id, orphaned_at) VALUES (?1, ?2, ?3, ?4)",
+            params![uuid, rejected_uuid, reviewer_id, orphaned_at],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(descendants)
+}
+
+/// Release the patches a reviewer orphaned by rejecting a patch
+pub(crate) fn release_orphans(conn: &Connection, rejected_uuid: &str, reviewer_id: &str) -> Result<usize, String> {
+    conn.execute(
+        "DELETE FROM orphaned_patches WHERE rejected_uuid = ?1 AND reviewer_id = ?2",
+        params![rejected_uuid, reviewer_id],
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// The rejected ancestor that first orphaned each orphaned patch, by patch UUID
+pub(crate) fn orphaned_patches(conn: &Connection) -> Result<HashMap<String, String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT patch_uuid, rejected_uuid FROM orphaned_patches ORDER BY orphaned_at DESC")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(rows.into_iter().collect())
+}
+
+/// Combined review status of each reviewed patch, by patch UUID
+fn review_statuses(conn: &Connection) -> Result<HashMap<String, ReviewStatus>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT patch_uuid, SUM(decision = 'accepted'), SUM(decision = 'rejected')
+             FROM patch_reviews GROUP BY patch_uuid",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(rows
+        .into_iter()
+        .map(|(uuid, accepted, rejected)| {
+            let status = match (accepted > 0, rejected > 0) {
+                (true, true) => ReviewStatus::Contested,
+                (true, false) => ReviewStatus::Accepted,
+                (false, true) => ReviewStatus::Rejected,
+                (false, false) => ReviewStatus::Pending,
+            };
+            (uuid, status)
+        })
+        .collect())
+}
+
+/// Lay out patches in recording order
+///
+/// A patch continues its parent's lane if it is the parent's first child,
+/// and opens the leftmost free lane otherwise. Patches without a known
+/// parent, as in histories recorded before parents were, stay on lane 0.
+fn build_graph(
+    patches: &[Patch],
+    meta: &DocumentMeta,
+    statuses: &HashMap<String, ReviewStatus>,
+    orphans: &HashMap<String, String>,
+) -> PatchGraph {
+    let patches: Vec<(&Patch, &String)> = patches.iter().filter_map(|p| p.uuid.as_ref().map(|u| (p, u))).collect();
+    let known: HashSet<&String> = patches.iter().map(|(_, uuid)| *uuid).collect();
+
+    let mut nodes: Vec<PatchGraphNode> = Vec::new();
+    let mut edges = Vec::new();
+    // Patch UUID at the tip of each lane
+    let mut tips: Vec<Option<String>> = Vec::new();
+    // Last patch and session number of each author
+    let mut last_by_author: HashMap<&str, (usize, usize)> = HashMap::new();
+    let mut sessions = 0;
+
+    for (row, (patch, uuid)) in patches.iter().enumerate() {
+        let parent = patch.parent_uuid.as_ref().filter(|p| known.contains(p));
+        let lane = match parent {
+            Some(parent) => match tips.iter().position(|tip| tip.as_ref() == Some(parent)) {
+                Some(lane) => lane,
+                None => tips.iter().position(Option::is_none).unwrap_or(tips.len()),
+            },
+            None => 0,
+        };
+        if lane == tips.len() {
+            tips.push(None);
+        }
+        tips[lane] = Some((*uuid).clone());
+
+        if let Some(parent) = parent {
+            edges.push(PatchGraphEdge {
+                from: parent.clone(),
+                to: (*uuid).clone(),
+                kind: PatchGraphEdgeKind::Parent,
+            });
+        }
+
+        let session = match last_by_author.get(patch.author.as_str()) {
+            Some(&(last, session)) if patch.timestamp - nodes[last].timestamp <= SESSION_GAP_MS => {
+                if parent != Some(&nodes[last].uuid) {
+                    edges.push(PatchGraphEdge {
+                        from: nodes[last].uuid.clone(),
+                        to: (*uuid).clone(),
+                        kind: PatchGraphEdgeKind::Session,
+                    });
+                }
+                session
+            }
+            _ => {
+                sessions += 1;
+                sessions - 1
+            }
+        };
+        last_by_author.insert(patch.author.as_str(), (row, session));
+
+        let author_name = patch
+            .data
+            .get("authorName")
+            .and_then(|n| n.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| author_display_name(meta, &patch.author));
+        nodes.push(PatchGraphNode {
+            uuid: (*uuid).clone(),
+            id: patch.id,
+            kind: patch.kind.clone(),
+            author: patch.author.clone(),
+            author_name,
+            timestamp: patch.timestamp,
+            status: statuses.get(*uuid).copied().unwrap_or(ReviewStatus::Pending),
+            orphaned_by: orphans.get(*uuid).cloned(),
+            row,
+            lane,
+            session,
+        });
+    }
+
+    PatchGraph {
+        nodes,
+        edges,
+        lanes: tips.len(),
+        sessions,
+    }
+}
+
+/// Get the patch graph of a document for display as a DAG
+#[tauri::command]
+pub fn get_patch_graph(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+) -> Result<PatchGraph, KorppiError> {
+    let (history_path, meta) = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.clone()))?;
+        (doc.history_path.clone(), doc.meta.clone())
+    };
+
+    let conn = Connection::open(&history_path)?;
+    ensure_schema(&conn)?;
+    let patches = load_patches(&conn)?;
+    Ok(build_graph(&patches, &meta, &review_statuses(&conn)?, &orphaned_patches(&conn)?))
+}
+
+/// Get the UUIDs of all patches built on a patch, directly or transitively
+#[tauri::command]
+pub fn get_patch_descendants(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    patch_uuid: String,
+) -> Result<Vec<String>, KorppiError> {
+    let history_path = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.clone()))?;
+        doc.history_path.clone()
+    };
+
+    let conn = Connection::open(&history_path)?;
+    ensure_schema(&conn)?;
+    Ok(patch_descendants(&conn, &patch_uuid)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(edges: &[(&str, Option<&str>)]) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+        for (uuid, parent) in edges {
+            conn.execute(
+                "INSERT INTO patches (timestamp, author, kind, data, uuid, parent_uuid) VALUES (1, 'alice', 'Save', '{}', ?1, ?2)",
+                params![uuid, parent],
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    #[test]
+    fn test_patch_descendants() {
+        // p1 <- p2 <- p3, p2 <- p4 <- p5, p1 <- p6
+        let conn = history(&[
+            ("p1", None),
+            ("p2", Some("p1")),
+            ("p3", Some("p2")),
+            ("p4", Some("p2")),
+            ("p5", Some("p4")),
+            ("p6", Some("p1")),
+        ]);
+
+        assert_eq!(patch_descendants(&conn, "p2").unwrap(), vec!["p3", "p4", "p5"]);
+        assert_eq!(patch_descendants(&conn, "p1").unwrap(), vec!["p2", "p6", "p3", "p4", "p5"]);
+        assert!(patch_descendants(&conn, "p5").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_orphan_and_release() {
+        let conn = history(&[("p1", None), ("p2", Some("p1")), ("p3", Some("p2"))]);
+
+        orphan_descendants(&conn, "p2", "bob").unwrap();
+        orphan_descendants(&conn, "p1", "carol").unwrap();
+        let orphans = orphaned_patches(&conn).unwrap();
+        assert_eq!(orphans.len(), 2);
+        assert!(orphans.contains_key("p2") && orphans.contains_key("p3"));
+
+        // Releasing p1 leaves p3 orphaned by bob's rejection of p2
+        assert_eq!(release_orphans(&conn, "p1", "carol").unwrap(), 2);
+        let orphans = orphaned_patches(&conn).unwrap();
+        assert_eq!(orphans.get("p3").map(String::as_str), Some("p2"));
+        assert!(!orphans.contains_key("p2"));
+    }
+
+    #[test]
+    fn test_build_graph_layout() {
+        let conn = history(&[
+            ("p1", None),
+            ("p2", Some("p1")),
+            ("p3", Some("p1")),
+            ("p4", Some("p2")),
+        ]);
+        // p3 by bob a minute after p1, p4 by alice much later
+        conn.execute("UPDATE patches SET author = 'bob', timestamp = 60000 WHERE uuid = 'p3'", []).unwrap();
+        conn.execute("UPDATE patches SET timestamp = 7200000 WHERE uuid = 'p4'", []).unwrap();
+        conn.execute(
+            "INSERT INTO patch_reviews (patch_uuid, reviewer_id, decision, reviewed_at) VALUES ('p3', 'alice', 'rejected', 1), ('p3', 'carol', 'accepted', 1)",
+            [],
+        )
+        .unwrap();
+
+        let graph = build_graph(
+            &load_patches(&conn).unwrap(),
+            &DocumentMeta::default(),
+            &review_statuses(&conn).unwrap(),
+            &HashMap::new(),
+        );
+
+        let lanes: Vec<usize> = graph.nodes.iter().map(|n| n.lane).collect();
+        assert_eq!(lanes, vec![0, 0, 1, 0]);
+        assert_eq!(graph.lanes, 2);
+        let sessions: Vec<usize> = graph.nodes.iter().map(|n| n.session).collect();
+        assert_eq!(sessions, vec![0, 0, 1, 2]);
+        assert_eq!(graph.nodes[2].status, ReviewStatus::Contested);
+        assert_eq!(graph.nodes[0].status, ReviewStatus::Pending);
+
+        let parents = graph.edges.iter().filter(|e| e.kind == PatchGraphEdgeKind::Parent).count();
+        assert_eq!(parents, 3);
+        // p1 and p2 are one session, but already linked by their parent edge
+        assert!(graph.edges.iter().all(|e| e.kind == PatchGraphEdgeKind::Parent));
+    }
+}
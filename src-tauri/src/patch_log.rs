@@ -9,8 +9,13 @@ use tauri::{AppHandle, Manager};
 use uuid::Uuid;
 use zip::ZipArchive;
 
-use crate::comments::{Comment, init_comments_table};
+use crate::comments::{Comment, CommentEvent, init_comments_table};
 use crate::db_utils::ensure_schema;
+use crate::error::KorppiError;
+use crate::events::{emit_event, PatchRecordedEvent, PATCH_RECORDED};
+use crate::patch_store::PatchStore;
+use crate::safe_open::{check_archive, harden_history};
+use crate::review_comments::ReviewComment;
 
 /// Generate a deterministic patch UID from content
 /// Uses SHA256 hash of author + timestamp + snapshot content
@@ -55,7 +60,7 @@ fn get_conn(app: &AppHandle) -> Result<Connection, String> {
     Ok(conn)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PatchInput {
     pub timestamp: i64,
     pub author: String,
@@ -85,73 +90,48 @@ pub struct PatchReview {
     pub decision: String, // "accepted" or "rejected"
     pub reviewer_name: Option<String>,
     pub reviewed_at: i64,
+    /// The reviewer's comments on the patch, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub comments: Vec<ReviewComment>,
 }
 
 #[tauri::command]
-pub fn record_patch(app: AppHandle, patch: PatchInput, parent_uuid: Option<String>) -> Result<String, String> {
+pub fn record_patch(app: AppHandle, patch: PatchInput, parent_uuid: Option<String>) -> Result<String, KorppiError> {
     let conn = get_conn(&app)?;
-    let data_str =
-        serde_json::to_string(&patch.data).map_err(|e| e.to_string())?;
-
-    // Use provided UUID or generate new one
-    let patch_uuid = patch.uuid.clone().unwrap_or_else(|| Uuid::new_v4().to_string());
 
     // Use provided parent_uuid (from struct) or argument fallback
-    let actual_parent = patch.parent_uuid.or(parent_uuid);
+    let mut patch = patch;
+    patch.parent_uuid = patch.parent_uuid.or(parent_uuid);
 
-    conn.execute(
-        "INSERT INTO patches (timestamp, author, kind, data, uuid, parent_uuid)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        params![patch.timestamp, patch.author, patch.kind, data_str, patch_uuid, actual_parent],
-    )
-    .map_err(|e| e.to_string())?;
+    let Some(stored) = PatchStore::on(&conn).insert_patch(&patch, None)? else {
+        // Already recorded
+        return Ok(patch.uuid.unwrap_or_default());
+    };
+    let patch_uuid = stored.uuid.clone().unwrap_or_default();
+
+    emit_event(&app, PATCH_RECORDED, PatchRecordedEvent {
+        doc_id: None,
+        patch_id: stored.id,
+        uuid: stored.uuid,
+        author: stored.author,
+        kind: stored.kind,
+        timestamp: stored.timestamp,
+    });
 
     Ok(patch_uuid)
 }
 
 #[tauri::command]
-pub fn list_patches(app: AppHandle) -> Result<Vec<Patch>, String> {
+pub fn list_patches(app: AppHandle) -> Result<Vec<Patch>, KorppiError> {
     let conn = get_conn(&app)?;
-    let mut stmt = conn
-        .prepare(
-            "SELECT id, timestamp, author, kind, data, uuid, parent_uuid
-             FROM patches
-             ORDER BY id ASC",
-        )
-        .map_err(|e| e.to_string())?;
-
-    let rows = stmt
-        .query_map([], |row| {
-            let data_str: String = row.get(4)?;
-            let data: serde_json::Value =
-                serde_json::from_str(&data_str).unwrap_or(serde_json::Value::Null);
-
-            Ok(Patch {
-                id: row.get(0)?,
-                timestamp: row.get(1)?,
-                author: row.get(2)?,
-                kind: row.get(3)?,
-                data,
-                uuid: row.get(5).ok(),
-                parent_uuid: row.get(6).ok(),
-            })
-        })
-        .map_err(|e| e.to_string())?;
-
-    let mut patches = Vec::new();
-    for row in rows {
-        patches.push(row.map_err(|e| e.to_string())?);
-    }
-
-    Ok(patches)
+    Ok(PatchStore::on(&conn).list()?)
 }
 
 #[tauri::command]
-pub fn get_patch(app: AppHandle, id: i64) -> Result<Patch, String> {
+pub fn get_patch(app: AppHandle, id: i64) -> Result<Patch, KorppiError> {
     let conn = get_conn(&app)?;
     let mut stmt = conn
-        .prepare("SELECT id, timestamp, author, kind, data, uuid, parent_uuid FROM patches WHERE id = ?1")
-        .map_err(|e| e.to_string())?;
+        .prepare("SELECT id, timestamp, author, kind, data, uuid, parent_uuid FROM patches WHERE id = ?1")?;
 
     let patch = stmt
         .query_row([id], |row| {
@@ -168,8 +148,7 @@ pub fn get_patch(app: AppHandle, id: i64) -> Result<Patch, String> {
                 uuid: row.get(5).ok(),
                 parent_uuid: row.get(6).ok(),
             })
-        })
-        .map_err(|e| e.to_string())?;
+        })?;
 
     Ok(patch)
 }
@@ -187,13 +166,13 @@ const MAX_SNAPSHOT_SIZE: usize = 100 * 1024 * 1024;
 
 /// Save a Yjs state snapshot at a specific patch ID
 #[tauri::command]
-pub fn save_snapshot(app: AppHandle, patch_id: i64, state: Vec<u8>) -> Result<(), String> {
+pub fn save_snapshot(app: AppHandle, patch_id: i64, state: Vec<u8>) -> Result<(), KorppiError> {
     // Validate input
     if state.is_empty() {
-        return Err("Snapshot state cannot be empty".to_string());
+        return Err(KorppiError::InvalidInput("Snapshot state cannot be empty".to_string()));
     }
     if state.len() > MAX_SNAPSHOT_SIZE {
-        return Err(format!("Snapshot size exceeds maximum allowed ({} bytes)", MAX_SNAPSHOT_SIZE));
+        return Err(KorppiError::InvalidInput(format!("Snapshot size exceeds maximum allowed ({} bytes)", MAX_SNAPSHOT_SIZE)));
     }
 
     let conn = get_conn(&app)?;
@@ -202,28 +181,23 @@ pub fn save_snapshot(app: AppHandle, patch_id: i64, state: Vec<u8>) -> Result<()
         .map_err(|e| e.to_string())?
         .as_millis() as i64;
 
-    conn.execute(
-        "INSERT INTO snapshots (timestamp, patch_id, state) VALUES (?1, ?2, ?3)",
-        params![timestamp, patch_id, state],
-    )
-    .map_err(|e| e.to_string())?;
+    PatchStore::on(&conn).add_snapshot(patch_id, timestamp, &state)?;
 
     Ok(())
 }
 
 /// Get the nearest snapshot before or at a given patch ID
 #[tauri::command]
-pub fn get_snapshot_for_patch(app: AppHandle, patch_id: i64) -> Result<Option<Snapshot>, String> {
+pub fn get_snapshot_for_patch(app: AppHandle, patch_id: i64) -> Result<Option<Snapshot>, KorppiError> {
     let conn = get_conn(&app)?;
 
     let mut stmt = conn
         .prepare(
-            "SELECT id, timestamp, patch_id, state FROM snapshots
+            "SELECT id, timestamp, patch_id, state FROM snapshot_states
              WHERE patch_id <= ?1
              ORDER BY patch_id DESC
              LIMIT 1",
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
 
     let snapshot = stmt
         .query_row([patch_id], |row| {
@@ -234,8 +208,7 @@ pub fn get_snapshot_for_patch(app: AppHandle, patch_id: i64) -> Result<Option<Sn
                 state: row.get(3)?,
             })
         })
-        .optional()
-        .map_err(|e| e.to_string())?;
+        .optional()?;
 
     Ok(snapshot)
 }
@@ -245,14 +218,15 @@ pub fn get_snapshot_for_patch(app: AppHandle, patch_id: i64) -> Result<Option<Sn
 pub fn import_patches_from_document(
     source_path: String,
     target_doc_id: String,
-    _app: AppHandle,
-) -> Result<Vec<Patch>, String> {
+    app: AppHandle,
+) -> Result<Vec<Patch>, KorppiError> {
     // Open the source KMD file
     let source_file = std::fs::File::open(&source_path)
         .map_err(|e| format!("Failed to open source file: {}", e))?;
     
     let mut archive = ZipArchive::new(source_file)
         .map_err(|e| format!("Failed to read KMD archive:{}", e))?;
+    check_archive(&mut archive)?;
     
     // Extract history.sqlite from the archive
     let mut history_file = archive
@@ -273,142 +247,79 @@ pub fn import_patches_from_document(
     drop(history_file);
     drop(archive);
     
+    // The source document comes from someone else, like a patch bundle
+    let stripped = harden_history(&temp_db_path, "history.sqlite").inspect_err(|_| {
+        std::fs::remove_file(&temp_db_path).ok();
+    })?;
+    for stripped in stripped {
+        log::warn!("Left {} out of the patches imported from {}", stripped, source_path);
+    }
+    
     // Open the extracted database
     let source_conn = Connection::open(&temp_db_path)
         .map_err(|e| format!("Failed to open source history: {}", e))?;
-    
-    // Get all Save patches from source (only explicit saves, not intermediate edits)
-    let source_patches: Vec<(i64, i64, String, String, String, Option<String>, Option<String>)> = {
-        // First try with uuid and parent_uuid columns
-        let query = "SELECT id, timestamp, author, kind, data, uuid, parent_uuid FROM patches WHERE kind = 'Save' ORDER BY timestamp ASC";
-        let query_fallback = "SELECT id, timestamp, author, kind, data, NULL as uuid, NULL as parent_uuid FROM patches WHERE kind = 'Save' ORDER BY timestamp ASC";
-
-        let mut stmt = source_conn
-            .prepare(query)
-            .or_else(|_| source_conn.prepare(query_fallback))
-            .map_err(|e| e.to_string())?;
-        
-        let rows = stmt
-            .query_map([], |row| {
-                Ok((
-                    row.get(0)?,
-                    row.get(1)?,
-                    row.get(2)?,
-                    row.get(3)?,
-                    row.get(4)?,
-                    row.get(5).ok(),
-                    row.get(6).ok(),
-                ))
-            })
-            .map_err(|e| e.to_string())?;
-        
-        // Collect before stmt is dropped
-        rows.collect::<Result<Vec<_>, _>>()
-            .map_err(|e| e.to_string())?
-    };
-    
-    // Get snapshots for those patches
-    let mut snapshot_map: HashMap<i64, Vec<u8>> = HashMap::new();
-    for (patch_id, _, _, _, _, _, _) in &source_patches {
-        let state: Option<Vec<u8>> = source_conn
-            .query_row(
-                "SELECT state FROM snapshots WHERE patch_id = ?1",
-                [patch_id],
-                |row| row.get(0)
-            )
-            .optional()
-            .map_err(|e| e.to_string())?;
-        
-        if let Some(state) = state {
-            snapshot_map.insert(*patch_id, state);
-        }
-    }
+    // The extracted copy can be from any version; upgrade it before reading
+    ensure_schema(&source_conn)?;
     
     // Get target document's history database path
     let temp_base = std::env::temp_dir().join("korppi-documents");
     let target_history_path = temp_base.join(&target_doc_id).join("history.sqlite");
     
     if !target_history_path.exists() {
-        return Err(format!("Target document history not found at {:?}", target_history_path));
-    }
-    
-    let target_conn = Connection::open(&target_history_path)
-        .map_err(|e| e.to_string())?;
-    
-    // Use shared schema definition
-    ensure_schema(&target_conn)?;
-    
-    // Import patches into target, deduplicating by UUID
-    let mut imported_patches = Vec::new();
-    
-    for (source_patch_id, timestamp, author, kind, data_str, source_uuid, parent_uuid) in source_patches {
-        // Parse data
-        let data: serde_json::Value = serde_json::from_str(&data_str)
-            .unwrap_or(serde_json::Value::Null);
-        
-        // Use existing UUID or generate a new one
-        let patch_uuid = source_uuid.unwrap_or_else(|| Uuid::new_v4().to_string());
-        
-        // Check if this patch already exists by UUID
-        let exists: bool = target_conn
-            .query_row(
-                "SELECT 1 FROM patches WHERE uuid = ?1",
-                params![&patch_uuid],
-                |_| Ok(true)
-            )
-            .optional()
-            .map_err(|e| e.to_string())?
-            .unwrap_or(false);
-        
-        if exists {
-            // Patch already exists, skip insert but import reviews below
-            continue;
-        }
-        
-        // Insert new patch
-        target_conn
-            .execute(
-                "INSERT INTO patches (timestamp, author, kind, data, uuid, parent_uuid) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-                params![timestamp, &author, &kind, &data_str, &patch_uuid, parent_uuid],
-            )
-            .map_err(|e| e.to_string())?;
-        
-        let new_patch_id = target_conn.last_insert_rowid();
-        
-        // Insert snapshot if available
-        if let Some(state) = snapshot_map.get(&source_patch_id) {
-            target_conn
-                .execute(
-                    "INSERT INTO snapshots (timestamp, patch_id, state) VALUES (?1, ?2, ?3)",
-                    params![timestamp, new_patch_id, state],
-                )
-                .map_err(|e| e.to_string())?;
-        }
-        
-        imported_patches.push(Patch {
-            id: new_patch_id,
-            timestamp,
-            author,
-            kind,
-            data,
-            uuid: Some(patch_uuid),
-            parent_uuid,
-        });
+        return Err(KorppiError::DocumentNotFound(format!("{:?}", target_history_path)));
     }
     
-    // Import reviews from source to target
-    import_reviews(&source_conn, &target_conn)?;
-
-    // Import comments
-    import_comments(&source_conn, &target_conn)?;
+    let target = PatchStore::open(&target_history_path)?;
+    let imported_patches = import_history(&source_conn, &target)?;
 
     // Clean up
     drop(source_conn);
     std::fs::remove_file(&temp_db_path).ok();
+    drop(target);
+
+    let imported_uuids = imported_patches.iter().filter_map(|p| p.uuid.clone()).collect();
+    crate::import_conflicts::check_after_import(&app, &target_doc_id, &target_history_path, &imported_uuids);
+    crate::section_locks::check_after_import(&app, &target_doc_id, &target_history_path, &imported_uuids);
+
+    crate::session_log::log_event(&app, "import_patches", Some(&target_doc_id), serde_json::json!({
+        "source": source_path,
+        "imported": imported_patches.len(),
+    }));
 
     Ok(imported_patches)
 }
 
+/// Import the Save patches of another copy of a history, with its reviews
+/// and comments; returns the patches that were new
+pub(crate) fn import_history(source_conn: &Connection, target: &PatchStore) -> Result<Vec<Patch>, String> {
+    // Only explicit saves, not intermediate edits
+    let source = PatchStore::on(source_conn);
+    let mut source_patches: Vec<Patch> = source.list()?.into_iter().filter(|p| p.kind == "Save").collect();
+    source_patches.sort_by_key(|p| p.timestamp);
+
+    // Import patches into target, deduplicating by UUID
+    let mut imported_patches = Vec::new();
+    for patch in source_patches {
+        let snapshot = source.snapshot(patch.id)?.map(|(_, state)| state);
+        let input = PatchInput {
+            timestamp: patch.timestamp,
+            author: patch.author,
+            kind: patch.kind,
+            data: patch.data,
+            uuid: patch.uuid,
+            parent_uuid: patch.parent_uuid,
+        };
+        // Already present patches are skipped; their reviews are imported below
+        if let Some(stored) = target.insert_patch(&input, snapshot.as_deref())? {
+            imported_patches.push(stored);
+        }
+    }
+
+    import_reviews(source_conn, target.conn())?;
+    import_comments(source_conn, target.conn())?;
+    Ok(imported_patches)
+}
+
 fn import_reviews(source_conn: &Connection, target_conn: &Connection) -> Result<(), String> {
     // Check if patch_reviews table exists in source
     let table_exists: bool = source_conn
@@ -436,6 +347,7 @@ fn import_reviews(source_conn: &Connection, target_conn: &Connection) -> Result<
                 decision: row.get(2)?,
                 reviewer_name: row.get(3)?,
                 reviewed_at: row.get(4)?,
+                comments: Vec::new(),
             })
         })
         .map_err(|e| e.to_string())?
@@ -490,6 +402,7 @@ fn import_comments(source_conn: &Connection, target_conn: &Connection) -> Result
                 content: row.get(7)?,
                 status: row.get(8)?,
                 parent_id: row.get(9)?,
+                orphaned: false,
             })
         })
         .map_err(|e| e.to_string())?
@@ -544,6 +457,76 @@ fn import_comments(source_conn: &Connection, target_conn: &Connection) -> Result
         }
     }
 
+    // Import the comment audit trail
+    import_comment_events(source_conn, target_conn, &id_map)?;
+
+    Ok(())
+}
+
+fn import_comment_events(
+    source_conn: &Connection,
+    target_conn: &Connection,
+    id_map: &HashMap<i64, i64>,
+) -> Result<(), String> {
+    // Older documents have no audit trail
+    let table_exists: bool = source_conn
+        .query_row(
+            "SELECT count(*) FROM sqlite_master WHERE type='table' AND name='comment_events'",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+
+    if !table_exists {
+        return Ok(());
+    }
+
+    let mut stmt = source_conn
+        .prepare("SELECT id, comment_id, event, actor, timestamp FROM comment_events ORDER BY id ASC")
+        .map_err(|e| e.to_string())?;
+
+    let source_events = stmt
+        .query_map([], |row| {
+            Ok(CommentEvent {
+                id: row.get(0)?,
+                comment_id: row.get(1)?,
+                event: row.get(2)?,
+                actor: row.get(3)?,
+                timestamp: row.get(4)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    for event in source_events {
+        let Some(&target_comment_id) = id_map.get(&event.comment_id) else {
+            continue;
+        };
+
+        // Skip events already present (e.g. from a previous import)
+        let exists: bool = target_conn
+            .query_row(
+                "SELECT 1 FROM comment_events WHERE comment_id = ?1 AND event = ?2 AND timestamp = ?3 AND actor IS ?4",
+                params![target_comment_id, event.event, event.timestamp, event.actor],
+                |_| Ok(true),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?
+            .unwrap_or(false);
+
+        if exists {
+            continue;
+        }
+
+        target_conn
+            .execute(
+                "INSERT INTO comment_events (comment_id, event, actor, timestamp) VALUES (?1, ?2, ?3, ?4)",
+                params![target_comment_id, event.event, event.actor, event.timestamp],
+            )
+            .map_err(|e| e.to_string())?;
+    }
+
     Ok(())
 }
 
@@ -555,12 +538,12 @@ pub fn record_patch_review(
     reviewer_id: String,
     decision: String,
     reviewer_name: Option<String>,
-) -> Result<(), String> {
+) -> Result<(), KorppiError> {
     let conn = get_conn(&app)?;
 
     // Validate decision
     if decision != "accepted" && decision != "rejected" {
-        return Err(format!("Invalid decision: {}. Must be 'accepted' or 'rejected'", decision));
+        return Err(KorppiError::InvalidInput(format!("Invalid decision: {}. Must be 'accepted' or 'rejected'", decision)));
     }
 
     let reviewed_at = std::time::SystemTime::now()
@@ -571,8 +554,7 @@ pub fn record_patch_review(
     conn.execute(
         "INSERT OR REPLACE INTO patch_reviews (patch_uuid, reviewer_id, decision, reviewer_name, reviewed_at) VALUES (?1, ?2, ?3, ?4, ?5)",
         params![patch_uuid, reviewer_id, decision, reviewer_name, reviewed_at],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     Ok(())
 }
@@ -582,12 +564,11 @@ pub fn record_patch_review(
 pub fn get_patch_reviews(
     app: AppHandle,
     patch_uuid: String,
-) -> Result<Vec<PatchReview>, String> {
+) -> Result<Vec<PatchReview>, KorppiError> {
     let conn = get_conn(&app)?;
 
     let mut stmt = conn
-        .prepare("SELECT patch_uuid, reviewer_id, decision, reviewer_name, reviewed_at FROM patch_reviews WHERE patch_uuid = ?1 ORDER BY reviewed_at DESC")
-        .map_err(|e| e.to_string())?;
+        .prepare("SELECT patch_uuid, reviewer_id, decision, reviewer_name, reviewed_at FROM patch_reviews WHERE patch_uuid = ?1 ORDER BY reviewed_at DESC")?;
 
     let reviews = stmt
         .query_map([patch_uuid], |row| {
@@ -597,11 +578,10 @@ pub fn get_patch_reviews(
                 decision: row.get(2)?,
                 reviewer_name: row.get(3)?,
                 reviewed_at: row.get(4)?,
+                comments: Vec::new(),
             })
-        })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
 
     Ok(reviews)
 }
@@ -611,7 +591,7 @@ pub fn get_patch_reviews(
 pub fn get_patches_needing_review(
     app: AppHandle,
     reviewer_id: String,
-) -> Result<Vec<Patch>, String> {
+) -> Result<Vec<Patch>, KorppiError> {
     let conn = get_conn(&app)?;
 
     // Query patches where author != reviewer_id and no review exists from reviewer_id
@@ -627,8 +607,7 @@ pub fn get_patches_needing_review(
                  AND pr.reviewer_id = ?1
              )
              ORDER BY p.timestamp ASC"
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
 
     let patches = stmt
         .query_map([reviewer_id], |row| {
@@ -645,10 +624,8 @@ pub fn get_patches_needing_review(
                 uuid: row.get(5).ok(),
                 parent_uuid: row.get(6).ok(),
             })
-        })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
 
     Ok(patches)
 }
@@ -663,18 +640,16 @@ pub struct RestoreResult {
 /// Restore to a specific patch - returns the snapshot content (text) for that patch
 /// This uses the text snapshot stored in the patch data if available
 #[tauri::command]
-pub fn restore_to_patch(app: AppHandle, patch_id: i64) -> Result<RestoreResult, String> {
+pub fn restore_to_patch(app: AppHandle, patch_id: i64) -> Result<RestoreResult, KorppiError> {
     let conn = get_conn(&app)?;
 
     // First, try to get the patch to extract the snapshot field from data
     let mut stmt = conn
-        .prepare("SELECT data FROM patches WHERE id = ?1")
-        .map_err(|e| e.to_string())?;
+        .prepare("SELECT data FROM patches WHERE id = ?1")?;
 
     let data_str: Option<String> = stmt
         .query_row([patch_id], |row| row.get(0))
-        .optional()
-        .map_err(|e| e.to_string())?;
+        .optional()?;
 
     if let Some(data_str) = data_str {
         // Parse the JSON data and extract the snapshot field if present
@@ -0,0 +1,289 @@
+// src-tauri/src/patch_schema.rs
+//! Payloads of patches, by kind.
+//!
+//! `patch.data` is stored as free-form JSON and comes from the editor, from
+//! other people's KMD files and from patch bundles. Each known kind has a
+//! payload type here, and a patch is checked against it before it goes into
+//! a history, so a corrupted or crafted bundle cannot store a snapshot that
+//! is not text or an author colour that is not a colour. Fields a payload
+//! does not name are kept as they are, so that newer versions can add some.
+//! Patches of other kinds need an object for data and the same author fields.
+//!
+//! `lint_history` reports the rows of a history that do not pass.
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::autosave::AUTOSAVE_KIND;
+use crate::db_utils::ensure_schema;
+use crate::document_manager::DocumentManager;
+use crate::error::KorppiError;
+use crate::suggestions::SUGGESTION_KIND;
+
+/// Kind of the patches the editor groups its edit steps into
+pub const SEMANTIC_GROUP_KIND: &str = "semantic_group";
+
+/// Longest accepted patch kind
+const MAX_KIND_LEN: usize = 64;
+
+/// Author name and colour shown for a patch
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct AuthorFields {
+    #[serde(rename = "authorName", default, skip_serializing_if = "Option::is_none")]
+    pub author_name: Option<String>,
+    #[serde(rename = "authorColor", default, skip_serializing_if = "Option::is_none")]
+    pub author_color: Option<String>,
+}
+
+/// A manual save, a merge or an accepted suggestion
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SavePayload {
+    pub snapshot: String,
+    #[serde(flatten)]
+    pub author: AuthorFields,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Commit the patch was imported from or mirrored to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_commit: Option<String>,
+    /// Suggestion whose hunks this save accepted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accepted_suggestion: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accepted_hunks: Option<Vec<String>>,
+    /// Patches combined by the merge wizard
+    #[serde(rename = "sourcePatches", default, skip_serializing_if = "Option::is_none")]
+    pub source_patches: Option<Vec<i64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(rename = "isMergePatch", default, skip_serializing_if = "Option::is_none")]
+    pub is_merge_patch: Option<bool>,
+}
+
+/// A snapshot taken by autosave
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AutoSavePayload {
+    pub snapshot: String,
+    #[serde(flatten)]
+    pub author: AuthorFields,
+    /// Edits since the previous snapshot
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changes: Option<u32>,
+}
+
+/// Text proposed by a reviewer, not yet part of the document
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SuggestionPayload {
+    pub snapshot: String,
+    #[serde(flatten)]
+    pub author: AuthorFields,
+}
+
+/// One editor step inside a semantic group
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EditStep {
+    pub kind: String,
+}
+
+/// Editor steps grouped into one patch
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SemanticGroupPayload {
+    pub patches: Vec<EditStep>,
+    #[serde(default)]
+    pub snapshot: String,
+    #[serde(flatten)]
+    pub author: AuthorFields,
+}
+
+/// Payload of a patch of a known kind
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(tag = "kind", content = "data")]
+pub enum PatchPayload {
+    Save(SavePayload),
+    AutoSave(AutoSavePayload),
+    Suggestion(SuggestionPayload),
+    #[serde(rename = "semantic_group")]
+    SemanticGroup(SemanticGroupPayload),
+}
+
+impl PatchPayload {
+    pub fn author(&self) -> &AuthorFields {
+        match self {
+            PatchPayload::Save(p) => &p.author,
+            PatchPayload::AutoSave(p) => &p.author,
+            PatchPayload::Suggestion(p) => &p.author,
+            PatchPayload::SemanticGroup(p) => &p.author,
+        }
+    }
+}
+
+fn is_known_kind(kind: &str) -> bool {
+    matches!(kind, "Save" | AUTOSAVE_KIND | SUGGESTION_KIND | SEMANTIC_GROUP_KIND)
+}
+
+/// `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`
+pub(crate) fn is_hex_color(color: &str) -> bool {
+    color
+        .strip_prefix('#')
+        .is_some_and(|hex| matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Parse the data of a patch; `None` for kinds without a payload type
+pub fn parse_payload(kind: &str, data: &serde_json::Value) -> Result<Option<PatchPayload>, String> {
+    if kind.is_empty()
+        || kind.len() > MAX_KIND_LEN
+        || !kind.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(format!("Invalid patch kind: {:?}", kind));
+    }
+    if !data.is_object() {
+        return Err(format!("{} patch data must be an object", kind));
+    }
+
+    let (payload, author) = if is_known_kind(kind) {
+        let tagged = serde_json::json!({ "kind": kind, "data": data });
+        let payload: PatchPayload =
+            serde_json::from_value(tagged).map_err(|e| format!("Invalid {} patch data: {}", kind, e))?;
+        let author = payload.author().clone();
+        (Some(payload), author)
+    } else {
+        let author: AuthorFields =
+            serde_json::from_value(data.clone()).map_err(|e| format!("Invalid {} patch data: {}", kind, e))?;
+        (None, author)
+    };
+
+    if let Some(color) = author.author_color.as_deref().filter(|c| !is_hex_color(c)) {
+        return Err(format!("Invalid author colour in {} patch: {:?}", kind, color));
+    }
+    Ok(payload)
+}
+
+/// Check the data of a patch against the payload of its kind
+pub fn validate_patch_data(kind: &str, data: &serde_json::Value) -> Result<(), String> {
+    parse_payload(kind, data).map(|_| ())
+}
+
+/// A history row that does not pass
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct HistoryIssue {
+    pub patch_id: i64,
+    pub uuid: Option<String>,
+    pub kind: String,
+    pub problem: String,
+}
+
+/// Every patch of a history whose data is not valid JSON, does not fit its
+/// kind, or that has no UUID
+pub fn lint_patches(conn: &Connection) -> Result<Vec<HistoryIssue>, String> {
+    let mut stmt = conn
+        .prepare("SELECT id, uuid, kind, data FROM patches ORDER BY id")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut issues = Vec::new();
+    for (patch_id, uuid, kind, data) in rows {
+        let problem = match serde_json::from_str::<serde_json::Value>(&data) {
+            Err(e) => Some(format!("Data is not valid JSON: {}", e)),
+            Ok(data) => validate_patch_data(&kind, &data).err(),
+        };
+        let problem = problem.or_else(|| uuid.is_none().then(|| "Patch has no UUID".to_string()));
+        if let Some(problem) = problem {
+            issues.push(HistoryIssue { patch_id, uuid, kind, problem });
+        }
+    }
+    Ok(issues)
+}
+
+/// Report the patches of a document whose data is malformed
+#[tauri::command]
+pub fn lint_history(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+) -> Result<Vec<HistoryIssue>, KorppiError> {
+    let history_path = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(id.to_string()))?;
+        doc.history_path.clone()
+    };
+    if !history_path.exists() {
+        return Ok(Vec::new());
+    }
+    let conn = Connection::open(&history_path)?;
+    ensure_schema(&conn)?;
+    Ok(lint_patches(&conn)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+    use serde_json::json;
+
+    #[test]
+    fn test_payloads_by_kind() {
+        let save = parse_payload("Save", &json!({
+            "snapshot": "# Draft",
+            "authorName": "Alice",
+            "authorColor": "#3498db",
+            "sourcePatches": [1, 2],
+            "futureField": { "kept": true },
+        }))
+        .unwrap();
+        let Some(PatchPayload::Save(save)) = save else {
+            panic!("expected a Save payload");
+        };
+        assert_eq!(save.snapshot, "# Draft");
+        assert_eq!(save.author.author_name.as_deref(), Some("Alice"));
+        assert_eq!(save.source_patches, Some(vec![1, 2]));
+
+        let group = json!({ "patches": [{ "kind": "insert_text", "text": "a" }], "snapshot": "a" });
+        assert!(matches!(parse_payload("semantic_group", &group), Ok(Some(PatchPayload::SemanticGroup(_)))));
+        assert_eq!(parse_payload("Edit", &json!({})), Ok(None));
+
+        assert!(validate_patch_data("Save", &json!({ "snapshot": 42 })).is_err());
+        assert!(validate_patch_data("AutoSave", &json!({})).is_err());
+        assert!(validate_patch_data("Suggestion", &json!("text")).is_err());
+        assert!(validate_patch_data("Edit", &json!({ "authorColor": "red;background:url(x)" })).is_err());
+        assert!(validate_patch_data("Save\"><script>", &json!({ "snapshot": "" })).is_err());
+    }
+
+    #[test]
+    fn test_lint_patches() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+        let insert = |kind: &str, data: &str, uuid: Option<&str>| {
+            conn.execute(
+                "INSERT INTO patches (timestamp, author, kind, data, uuid) VALUES (1, 'alice', ?1, ?2, ?3)",
+                params![kind, data, uuid],
+            )
+            .unwrap();
+        };
+        insert("Save", r#"{"snapshot":"ok"}"#, Some("p1"));
+        insert("Save", "{not json", Some("p2"));
+        insert("AutoSave", r#"{"changes":3}"#, Some("p3"));
+        insert("Save", r#"{"snapshot":"ok"}"#, None);
+
+        let issues = lint_patches(&conn).unwrap();
+        let flagged: Vec<(i64, &str)> = issues.iter().map(|i| (i.patch_id, i.kind.as_str())).collect();
+        assert_eq!(flagged, vec![(2, "Save"), (3, "AutoSave"), (4, "Save")]);
+        assert!(issues[0].problem.contains("JSON"));
+        assert!(issues[1].problem.contains("snapshot"));
+        assert_eq!(issues[2].problem, "Patch has no UUID");
+    }
+}
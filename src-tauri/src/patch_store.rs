@@ -0,0 +1,638 @@
+// src-tauri/src/patch_store.rs
+//! Reading and writing the patches of a history database.
+//!
+//! Every place that adds patches goes through `PatchStore::insert_patch`,
+//! so they agree on the rules:
+//!
+//! - a patch whose data does not fit its kind is refused (see `patch_schema`);
+//! - a patch without a UUID gets a fresh one;
+//! - a patch whose UUID is already in the history is skipped, never stored twice;
+//! - a snapshot given with the patch is stored with it, and a Save patch
+//!   without one gets the text of its `snapshot` field.
+//!
+//! Snapshots are stored by hash: a state already held by an earlier snapshot
+//! is not stored again, the new row refers to the earlier one instead.
+
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::db_utils::{ensure_schema, snapshot_hash};
+use crate::document_manager::row_to_patch;
+use crate::patch_graph::ReviewStatus;
+use crate::patch_log::{Patch, PatchInput};
+use crate::patch_schema::validate_patch_data;
+
+/// Outcome of checking the stored snapshots against their hashes
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SnapshotReport {
+    /// Snapshots that carry a hash
+    pub checked: usize,
+    /// Patch IDs of snapshots whose state no longer matches its hash
+    pub hash_mismatches: Vec<i64>,
+    /// Patch IDs of snapshots referring to a state that is gone
+    pub missing_states: Vec<i64>,
+}
+
+impl SnapshotReport {
+    pub fn is_ok(&self) -> bool {
+        self.hash_mismatches.is_empty() && self.missing_states.is_empty()
+    }
+}
+
+/// A page of patches, counted from the newest
+#[derive(Debug, Clone, Default)]
+pub struct PatchPage {
+    /// Most patches to return; all when absent
+    pub limit: Option<usize>,
+    /// Newest patches to skip
+    pub offset: usize,
+    /// Only patches recorded before this time (ms)
+    pub before_timestamp: Option<i64>,
+}
+
+/// What to order a filtered listing by
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PatchSortKey {
+    /// Recording time, then recording order
+    #[default]
+    Timestamp,
+    /// Author name, in the order of the document language
+    Author,
+    Kind,
+}
+
+/// Which patches to list, and in what order
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct PatchFilter {
+    /// Author IDs; any author when empty
+    pub authors: Vec<String>,
+    /// Patch kinds; any kind when empty
+    pub kinds: Vec<String>,
+    /// Only patches recorded at or after this time (ms)
+    pub since: Option<i64>,
+    /// Only patches recorded before this time (ms)
+    pub until: Option<i64>,
+    /// Combined review decision, as in the patch graph
+    pub review_status: Option<ReviewStatus>,
+    /// Text the snapshot of the patch contains, ignoring case
+    pub query: Option<String>,
+    pub sort_by: PatchSortKey,
+    pub descending: bool,
+}
+
+/// SQL condition on the reviews of patch `p` for a review status
+fn review_condition(status: ReviewStatus) -> String {
+    let decided = |decision: &str| {
+        format!("EXISTS (SELECT 1 FROM patch_reviews r WHERE r.patch_uuid = p.uuid AND r.decision = '{}')", decision)
+    };
+    let (accepted, rejected) = (decided("accepted"), decided("rejected"));
+    match status {
+        ReviewStatus::Pending => format!("NOT {} AND NOT {}", accepted, rejected),
+        ReviewStatus::Accepted => format!("{} AND NOT {}", accepted, rejected),
+        ReviewStatus::Rejected => format!("{} AND NOT {}", rejected, accepted),
+        ReviewStatus::Contested => format!("{} AND {}", accepted, rejected),
+    }
+}
+
+/// Counts of a history, for showing its size without listing it
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PatchSummary {
+    pub total: usize,
+    pub by_kind: BTreeMap<String, usize>,
+    pub by_author: BTreeMap<String, usize>,
+    pub first_timestamp: Option<i64>,
+    pub last_timestamp: Option<i64>,
+    /// UUID of the newest patch, the parent of the next one
+    pub latest_uuid: Option<String>,
+}
+
+/// Patches and snapshots of one history database
+///
+/// Owns its connection when opened from a path, or borrows one that is
+/// already open (e.g. inside a transaction).
+pub struct PatchStore<C = Connection> {
+    conn: C,
+}
+
+impl PatchStore<Connection> {
+    /// Open the history database at a path, upgrading its schema
+    pub fn open(history_path: &Path) -> Result<Self, String> {
+        let conn = Connection::open(history_path).map_err(|e| e.to_string())?;
+        ensure_schema(&conn)?;
+        Ok(PatchStore { conn })
+    }
+}
+
+impl<'c> PatchStore<&'c Connection> {
+    /// Use a connection whose schema is already in place
+    pub fn on(conn: &'c Connection) -> Self {
+        PatchStore { conn }
+    }
+}
+
+impl<C: Borrow<Connection>> PatchStore<C> {
+    pub fn conn(&self) -> &Connection {
+        self.conn.borrow()
+    }
+
+    /// Store a patch; returns it with its ID and UUID, or None when a patch
+    /// with its UUID is already in the history
+    pub fn insert_patch(&self, patch: &PatchInput, snapshot: Option<&[u8]>) -> Result<Option<Patch>, String> {
+        let uuid = patch.uuid.clone().unwrap_or_else(|| Uuid::new_v4().to_string());
+        if self.patch_exists_by_uuid(&uuid)? {
+            return Ok(None);
+        }
+        validate_patch_data(&patch.kind, &patch.data).map_err(|e| format!("Patch {}: {}", uuid, e))?;
+
+        let data = serde_json::to_string(&patch.data).map_err(|e| e.to_string())?;
+        self.conn()
+            .execute(
+                "INSERT INTO patches (timestamp, author, kind, data, uuid, parent_uuid) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![patch.timestamp, patch.author, patch.kind, data, uuid, patch.parent_uuid],
+            )
+            .map_err(|e| format!("Failed to add patch to history: {}", e))?;
+        let id = self.conn().last_insert_rowid();
+
+        let text_snapshot = (patch.kind == "Save")
+            .then(|| patch.data.get("snapshot").and_then(|s| s.as_str()))
+            .flatten()
+            .map(str::as_bytes);
+        if let Some(state) = snapshot.or(text_snapshot) {
+            self.add_snapshot(id, patch.timestamp, state)?;
+        }
+
+        Ok(Some(Patch {
+            id,
+            timestamp: patch.timestamp,
+            author: patch.author.clone(),
+            kind: patch.kind.clone(),
+            data: patch.data.clone(),
+            uuid: Some(uuid),
+            parent_uuid: patch.parent_uuid.clone(),
+        }))
+    }
+
+    pub fn patch_exists_by_uuid(&self, uuid: &str) -> Result<bool, String> {
+        self.conn()
+            .prepare_cached("SELECT 1 FROM patches WHERE uuid = ?1")
+            .and_then(|mut stmt| stmt.exists(params![uuid]))
+            .map_err(|e| e.to_string())
+    }
+
+    /// Every patch, oldest first
+    pub fn list(&self) -> Result<Vec<Patch>, String> {
+        let mut stmt = self
+            .conn()
+            .prepare("SELECT id, timestamp, author, kind, data, uuid, parent_uuid FROM patches ORDER BY id ASC")
+            .map_err(|e| e.to_string())?;
+        let patches = stmt
+            .query_map([], row_to_patch)
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        Ok(patches)
+    }
+
+    /// One page of patches, oldest first within the page
+    pub fn page(&self, page: &PatchPage) -> Result<Vec<Patch>, String> {
+        let limit = page.limit.map(|l| l as i64).unwrap_or(-1);
+        let mut stmt = self
+            .conn()
+            .prepare(
+                "SELECT id, timestamp, author, kind, data, uuid, parent_uuid FROM patches
+                 WHERE ?1 IS NULL OR timestamp < ?1
+                 ORDER BY id DESC LIMIT ?2 OFFSET ?3",
+            )
+            .map_err(|e| e.to_string())?;
+        let mut patches = stmt
+            .query_map(params![page.before_timestamp, limit, page.offset as i64], row_to_patch)
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        patches.reverse();
+        Ok(patches)
+    }
+
+    /// Patches matching a filter, oldest first
+    ///
+    /// Everything but the text query is matched in SQL; snapshots are then
+    /// searched one row at a time, so only matching patches are kept.
+    pub fn filtered(&self, filter: &PatchFilter) -> Result<Vec<Patch>, String> {
+        let mut conditions: Vec<String> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+        let mut any_of = |column: &str, options: &[String], values: &mut Vec<Value>| {
+            if !options.is_empty() {
+                conditions.push(format!("p.{} IN ({})", column, vec!["?"; options.len()].join(", ")));
+                values.extend(options.iter().cloned().map(Value::Text));
+            }
+        };
+        any_of("author", &filter.authors, &mut values);
+        any_of("kind", &filter.kinds, &mut values);
+        if let Some(since) = filter.since {
+            conditions.push("p.timestamp >= ?".to_string());
+            values.push(Value::Integer(since));
+        }
+        if let Some(until) = filter.until {
+            conditions.push("p.timestamp < ?".to_string());
+            values.push(Value::Integer(until));
+        }
+        if let Some(status) = filter.review_status {
+            conditions.push(review_condition(status));
+        }
+        let query = filter.query.as_deref().map(str::trim).filter(|q| !q.is_empty()).map(str::to_lowercase);
+        if query.is_some() {
+            conditions.push("json_extract(p.data, '$.snapshot') IS NOT NULL".to_string());
+        }
+
+        let sql = format!(
+            "SELECT p.id, p.timestamp, p.author, p.kind, p.data, p.uuid, p.parent_uuid FROM patches p{} ORDER BY p.id ASC",
+            if conditions.is_empty() { String::new() } else { format!(" WHERE {}", conditions.join(" AND ")) }
+        );
+        let mut stmt = self.conn().prepare(&sql).map_err(|e| e.to_string())?;
+        let rows = stmt.query_map(params_from_iter(values), row_to_patch).map_err(|e| e.to_string())?;
+        let mut patches = Vec::new();
+        for patch in rows {
+            let patch = patch.map_err(|e| e.to_string())?;
+            let matches = query.as_ref().is_none_or(|query| {
+                patch.data.get("snapshot").and_then(|s| s.as_str()).is_some_and(|s| s.to_lowercase().contains(query))
+            });
+            if matches {
+                patches.push(patch);
+            }
+        }
+        Ok(patches)
+    }
+
+    pub fn get(&self, patch_id: i64) -> Result<Option<Patch>, String> {
+        self.conn()
+            .query_row(
+                "SELECT id, timestamp, author, kind, data, uuid, parent_uuid FROM patches WHERE id = ?1",
+                params![patch_id],
+                row_to_patch,
+            )
+            .optional()
+            .map_err(|e| e.to_string())
+    }
+
+    /// Flag patches as unverified, except those authored by
+    /// `verified_author` (or an alias of theirs); returns how many were flagged
+    pub fn mark_unverified(&self, uuids: &HashSet<String>, verified_author: Option<&str>) -> Result<usize, String> {
+        let mut stmt = self
+            .conn()
+            .prepare(
+                "UPDATE patches SET unverified = 1 WHERE uuid = ?1 AND (?2 IS NULL OR (
+                    author != ?2 AND author NOT IN (SELECT author_id FROM author_aliases WHERE alias_id = ?2)
+                 ))",
+            )
+            .map_err(|e| e.to_string())?;
+        let mut flagged = 0;
+        for uuid in uuids {
+            flagged += stmt.execute(params![uuid, verified_author]).map_err(|e| e.to_string())?;
+        }
+        Ok(flagged)
+    }
+
+    /// UUIDs of the patches flagged as unverified
+    pub fn unverified_uuids(&self) -> Result<HashSet<String>, String> {
+        let mut stmt = self
+            .conn()
+            .prepare("SELECT uuid FROM patches WHERE unverified = 1 AND uuid IS NOT NULL")
+            .map_err(|e| e.to_string())?;
+        let uuids = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<HashSet<String>, _>>()
+            .map_err(|e| e.to_string())?;
+        Ok(uuids)
+    }
+
+    pub fn summary(&self) -> Result<PatchSummary, String> {
+        let mut summary = PatchSummary::default();
+        let mut stmt = self
+            .conn()
+            .prepare("SELECT kind, author, COUNT(*), MIN(timestamp), MAX(timestamp) FROM patches GROUP BY kind, author")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)? as usize,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let (kind, author, count, first, last) = row.map_err(|e| e.to_string())?;
+            summary.total += count;
+            *summary.by_kind.entry(kind).or_default() += count;
+            *summary.by_author.entry(author).or_default() += count;
+            summary.first_timestamp = Some(summary.first_timestamp.map_or(first, |t| t.min(first)));
+            summary.last_timestamp = Some(summary.last_timestamp.map_or(last, |t| t.max(last)));
+        }
+        summary.latest_uuid = self
+            .conn()
+            .query_row("SELECT uuid FROM patches ORDER BY id DESC LIMIT 1", [], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?
+            .flatten();
+        Ok(summary)
+    }
+
+    /// Store a snapshot taken at a patch, sharing the state with an earlier
+    /// snapshot of the same hash
+    pub fn add_snapshot(&self, patch_id: i64, timestamp: i64, state: &[u8]) -> Result<(), String> {
+        let hash = snapshot_hash(state);
+        let holder: Option<i64> = self
+            .conn()
+            .query_row(
+                "SELECT id FROM snapshots WHERE hash = ?1 AND same_as IS NULL ORDER BY id LIMIT 1",
+                params![hash],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let stored: &[u8] = if holder.is_some() { &[] } else { state };
+        self.conn()
+            .execute(
+                "INSERT INTO snapshots (timestamp, patch_id, state, hash, same_as) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![timestamp, patch_id, stored, hash, holder],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Delete the snapshots of a patch
+    ///
+    /// A snapshot other rows refer to hands its state to the first of them.
+    pub fn delete_snapshots(&self, patch_id: i64) -> Result<(), String> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare("SELECT id, state FROM snapshots WHERE patch_id = ?1 AND same_as IS NULL")
+            .map_err(|e| e.to_string())?;
+        let holders: Vec<(i64, Vec<u8>)> = stmt
+            .query_map(params![patch_id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+
+        for (id, state) in holders {
+            let heir: Option<i64> = conn
+                .query_row(
+                    "SELECT id FROM snapshots WHERE same_as = ?1 AND patch_id != ?2 ORDER BY id LIMIT 1",
+                    params![id, patch_id],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(|e| e.to_string())?;
+            if let Some(heir) = heir {
+                conn.execute("UPDATE snapshots SET state = ?1, same_as = NULL WHERE id = ?2", params![state, heir])
+                    .map_err(|e| e.to_string())?;
+                conn.execute("UPDATE snapshots SET same_as = ?1 WHERE same_as = ?2", params![heir, id])
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+
+        conn.execute("DELETE FROM snapshots WHERE patch_id = ?1", params![patch_id])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Check every hashed snapshot against its hash
+    pub fn verify_snapshots(&self) -> Result<SnapshotReport, String> {
+        let mut stmt = self
+            .conn()
+            .prepare(
+                "SELECT s.patch_id, s.hash, s.same_as, o.id, o.same_as, COALESCE(o.state, s.state)
+                 FROM snapshots s LEFT JOIN snapshots o ON o.id = s.same_as
+                 WHERE s.hash IS NOT NULL ORDER BY s.id",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<i64>>(2)?,
+                    row.get::<_, Option<i64>>(3)?,
+                    row.get::<_, Option<i64>>(4)?,
+                    row.get::<_, Vec<u8>>(5)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+
+        let mut report = SnapshotReport::default();
+        for row in rows {
+            let (patch_id, hash, same_as, holder, holder_same_as, state) = row.map_err(|e| e.to_string())?;
+            report.checked += 1;
+            // A reference must land on a row that holds the state itself
+            if same_as.is_some() && (holder.is_none() || holder_same_as.is_some()) {
+                report.missing_states.push(patch_id);
+            } else if snapshot_hash(&state) != hash {
+                report.hash_mismatches.push(patch_id);
+            }
+        }
+        Ok(report)
+    }
+
+    /// Snapshot stored with a patch, with its timestamp
+    pub fn snapshot(&self, patch_id: i64) -> Result<Option<(i64, Vec<u8>)>, String> {
+        self.conn()
+            .query_row(
+                "SELECT timestamp, state FROM snapshot_states WHERE patch_id = ?1",
+                params![patch_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Save patch by alice whose text is `snapshot`, for building test histories
+#[cfg(test)]
+pub(crate) fn test_patch(uuid: &str, parent: Option<&str>, snapshot: &str) -> PatchInput {
+    PatchInput {
+        timestamp: 1,
+        author: "alice".to_string(),
+        kind: "Save".to_string(),
+        data: serde_json::json!({ "snapshot": snapshot }),
+        uuid: Some(uuid.to_string()),
+        parent_uuid: parent.map(str::to_string),
+    }
+}
+
+/// Store a test patch with its text as snapshot, whatever its kind
+#[cfg(test)]
+pub(crate) fn insert_test_patch(conn: &Connection, patch: PatchInput) {
+    let snapshot = patch.data["snapshot"].as_str().unwrap_or_default().to_string();
+    PatchStore::on(conn)
+        .insert_patch(&patch, Some(snapshot.as_bytes()))
+        .unwrap()
+        .expect("test patch UUID already in the history");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(kind: &str, uuid: Option<&str>, data: serde_json::Value) -> PatchInput {
+        PatchInput {
+            timestamp: 1,
+            author: "alice".to_string(),
+            kind: kind.to_string(),
+            data,
+            uuid: uuid.map(str::to_string),
+            parent_uuid: None,
+        }
+    }
+
+    #[test]
+    fn test_insert_rules() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = PatchStore::open(&dir.path().join("history.sqlite")).unwrap();
+
+        // Save patches keep their text as snapshot
+        let save = store
+            .insert_patch(&input("Save", Some("p1"), serde_json::json!({ "snapshot": "Hello" })), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(save.uuid.as_deref(), Some("p1"));
+        assert_eq!(store.snapshot(save.id).unwrap(), Some((1, b"Hello".to_vec())));
+
+        // Same UUID again: skipped
+        assert!(store.insert_patch(&input("Save", Some("p1"), serde_json::json!({})), None).unwrap().is_none());
+
+        // Other kinds get a UUID, and a snapshot only when given one
+        let edit = store.insert_patch(&input("edit", None, serde_json::json!({ "snapshot": "x" })), None).unwrap().unwrap();
+        assert!(edit.uuid.is_some_and(|u| Uuid::parse_str(&u).is_ok()));
+        assert_eq!(store.snapshot(edit.id).unwrap(), None);
+        let autosave = store.insert_patch(&input("Autosave", None, serde_json::json!({})), Some(b"state")).unwrap().unwrap();
+        assert_eq!(store.snapshot(autosave.id).unwrap(), Some((1, b"state".to_vec())));
+
+        // Data that does not fit the kind is refused
+        let err = store.insert_patch(&input("Save", Some("p2"), serde_json::json!({ "snapshot": 1 })), None).unwrap_err();
+        assert!(err.starts_with("Patch p2:"));
+
+        let listed: Vec<i64> = store.list().unwrap().iter().map(|p| p.id).collect();
+        assert_eq!(listed, vec![save.id, edit.id, autosave.id]);
+        assert!(PatchStore::on(store.conn()).patch_exists_by_uuid("p1").unwrap());
+    }
+
+    #[test]
+    fn test_pages_and_summary() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = PatchStore::open(&dir.path().join("history.sqlite")).unwrap();
+        assert_eq!(store.summary().unwrap(), PatchSummary::default());
+        for (timestamp, kind) in [(10, "edit"), (20, "Save"), (30, "edit"), (40, "edit")] {
+            let uuid = format!("p{}", timestamp);
+            let data = serde_json::json!({ "snapshot": uuid });
+            store.insert_patch(&PatchInput { timestamp, ..input(kind, Some(&uuid), data) }, None).unwrap();
+        }
+
+        let times = |page: PatchPage| -> Vec<i64> { store.page(&page).unwrap().iter().map(|p| p.timestamp).collect() };
+        assert_eq!(times(PatchPage::default()), vec![10, 20, 30, 40]);
+        assert_eq!(times(PatchPage { limit: Some(2), ..Default::default() }), vec![30, 40]);
+        assert_eq!(times(PatchPage { limit: Some(2), offset: 1, ..Default::default() }), vec![20, 30]);
+        assert_eq!(times(PatchPage { limit: Some(2), before_timestamp: Some(30), ..Default::default() }), vec![10, 20]);
+
+        let summary = store.summary().unwrap();
+        assert_eq!(summary.total, 4);
+        assert_eq!(summary.by_kind["edit"], 3);
+        assert_eq!(summary.by_author["alice"], 4);
+        assert_eq!((summary.first_timestamp, summary.last_timestamp), (Some(10), Some(40)));
+        assert_eq!(summary.latest_uuid.as_deref(), Some("p40"));
+        assert_eq!(store.get(2).unwrap().map(|p| p.timestamp), Some(20));
+        assert!(store.get(99).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_filters() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = PatchStore::open(&dir.path().join("history.sqlite")).unwrap();
+        let patches = [
+            (10, "alice", "Save", "The Raven"),
+            (20, "bob", "Save", "The RAVEN, revised"),
+            (30, "alice", "edit", ""),
+            (40, "bob", "Save", "Ä crow"),
+        ];
+        for (timestamp, author, kind, snapshot) in patches {
+            let data = if snapshot.is_empty() { serde_json::json!({}) } else { serde_json::json!({ "snapshot": snapshot }) };
+            let patch = PatchInput {
+                timestamp,
+                author: author.to_string(),
+                ..input(kind, Some(&format!("p{}", timestamp)), data)
+            };
+            store.insert_patch(&patch, None).unwrap();
+        }
+        store
+            .conn()
+            .execute_batch(
+                "INSERT INTO patch_reviews (patch_uuid, reviewer_id, decision, reviewed_at) VALUES
+                 ('p10', 'rita', 'accepted', 1), ('p20', 'rita', 'accepted', 1), ('p20', 'sam', 'rejected', 1)",
+            )
+            .unwrap();
+
+        let times = |filter: PatchFilter| -> Vec<i64> { store.filtered(&filter).unwrap().iter().map(|p| p.timestamp).collect() };
+        assert_eq!(times(PatchFilter::default()), vec![10, 20, 30, 40]);
+        assert_eq!(times(PatchFilter { authors: vec!["bob".to_string()], ..Default::default() }), vec![20, 40]);
+        assert_eq!(times(PatchFilter { kinds: vec!["edit".to_string()], ..Default::default() }), vec![30]);
+        assert_eq!(times(PatchFilter { since: Some(20), until: Some(40), ..Default::default() }), vec![20, 30]);
+        assert_eq!(times(PatchFilter { review_status: Some(ReviewStatus::Pending), ..Default::default() }), vec![30, 40]);
+        assert_eq!(times(PatchFilter { review_status: Some(ReviewStatus::Accepted), ..Default::default() }), vec![10]);
+        assert_eq!(times(PatchFilter { review_status: Some(ReviewStatus::Contested), ..Default::default() }), vec![20]);
+        assert_eq!(times(PatchFilter { query: Some("raven".to_string()), ..Default::default() }), vec![10, 20]);
+        assert_eq!(times(PatchFilter { query: Some("ä".to_string()), ..Default::default() }), vec![40]);
+        let combined = PatchFilter {
+            authors: vec!["bob".to_string()],
+            query: Some("Raven".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(times(combined), vec![20]);
+    }
+
+    #[test]
+    fn test_shared_snapshots() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = PatchStore::open(&dir.path().join("history.sqlite")).unwrap();
+        let stored_len = |patch_id: i64| -> i64 {
+            store
+                .conn()
+                .query_row("SELECT LENGTH(state) FROM snapshots WHERE patch_id = ?1", [patch_id], |row| row.get(0))
+                .unwrap()
+        };
+
+        for timestamp in 1..=4 {
+            store.insert_patch(&PatchInput { timestamp, ..input("edit", None, serde_json::json!({})) }, None).unwrap();
+        }
+        store.add_snapshot(1, 1, b"same").unwrap();
+        store.add_snapshot(2, 2, b"other").unwrap();
+        store.add_snapshot(3, 3, b"same").unwrap();
+        store.add_snapshot(4, 4, b"same").unwrap();
+        assert_eq!(stored_len(3), 0);
+        assert_eq!(store.snapshot(3).unwrap(), Some((3, b"same".to_vec())));
+        assert!(store.verify_snapshots().unwrap().is_ok());
+
+        // Deleting the holder hands the state on
+        store.delete_snapshots(1).unwrap();
+        assert_eq!(stored_len(3), 4);
+        assert_eq!(store.snapshot(4).unwrap(), Some((4, b"same".to_vec())));
+        let report = store.verify_snapshots().unwrap();
+        assert_eq!(report.checked, 3);
+        assert!(report.is_ok());
+
+        // Damage is reported per patch
+        store.conn().execute("UPDATE snapshots SET state = x'00' WHERE patch_id = 2", []).unwrap();
+        store.conn().execute("DELETE FROM snapshots WHERE patch_id = 3", []).unwrap();
+        let report = store.verify_snapshots().unwrap();
+        assert_eq!(report.hash_mismatches, vec![2]);
+        assert_eq!(report.missing_states, vec![4]);
+    }
+}
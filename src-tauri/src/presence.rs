@@ -0,0 +1,186 @@
+// src-tauri/src/presence.rs
+//! Presence files for documents on shared or synced folders.
+//!
+//! While `notes.kmd` is open, each collaborator's instance keeps a sidecar
+//! `.{document uuid}.{author id}.presence.json` next to it and refreshes its
+//! heartbeat periodically. Reading the other sidecars of the same document
+//! tells who else has it open, even when they only opened it read-only.
+//! Sidecars whose heartbeat is too old are ignored, so a crash or a lost
+//! connection only leaves a harmless file behind.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::document_manager::DocumentManager;
+use crate::error::KorppiError;
+use crate::file_lock::host_name;
+use crate::profile::{load_saved_profile, DEFAULT_AUTHOR_COLOR};
+
+/// Heartbeats older than this mean the collaborator is gone
+pub const PRESENCE_TIMEOUT_MS: i64 = 2 * 60 * 1000;
+
+const PRESENCE_SUFFIX: &str = ".presence.json";
+
+/// Contents of a presence file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PresenceInfo {
+    pub author_id: String,
+    pub author_name: String,
+    pub color: String,
+    pub host: String,
+    /// Milliseconds since the epoch of the last heartbeat
+    pub heartbeat: i64,
+}
+
+impl PresenceInfo {
+    /// Presence of the local profile, as of now
+    pub fn current() -> PresenceInfo {
+        let profile = load_saved_profile().ok().flatten();
+        PresenceInfo {
+            author_id: profile.as_ref().map(|p| p.id.clone()).unwrap_or_else(|| "local".to_string()),
+            author_name: profile
+                .as_ref()
+                .map(|p| p.name.clone())
+                .filter(|n| !n.is_empty())
+                .unwrap_or_else(|| "Unknown user".to_string()),
+            color: profile
+                .map(|p| p.color)
+                .unwrap_or_else(|| DEFAULT_AUTHOR_COLOR.to_string()),
+            host: host_name(),
+            heartbeat: Utc::now().timestamp_millis(),
+        }
+    }
+}
+
+/// Path of an author's presence file for a document
+pub fn presence_path(kmd_path: &Path, doc_uuid: &str, author_id: &str) -> PathBuf {
+    kmd_path.with_file_name(format!(".{}.{}{}", doc_uuid, author_id, PRESENCE_SUFFIX))
+}
+
+/// Write or refresh a presence file
+pub fn write_presence(kmd_path: &Path, doc_uuid: &str, info: &PresenceInfo) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(info).map_err(|e| e.to_string())?;
+    fs::write(presence_path(kmd_path, doc_uuid, &info.author_id), json)
+        .map_err(|e| format!("Failed to write presence file: {}", e))
+}
+
+/// Remove an author's presence file, if any
+pub fn remove_presence(kmd_path: &Path, doc_uuid: &str, author_id: &str) {
+    fs::remove_file(presence_path(kmd_path, doc_uuid, author_id)).ok();
+}
+
+/// Other authors with a live presence file for a document, by name
+///
+/// Unreadable files and heartbeats older than `PRESENCE_TIMEOUT_MS` before
+/// `now` are skipped.
+pub fn read_collaborators(kmd_path: &Path, doc_uuid: &str, own_author_id: &str, now: i64) -> Vec<PresenceInfo> {
+    let Some(dir) = kmd_path.parent() else {
+        return Vec::new();
+    };
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let prefix = format!(".{}.", doc_uuid);
+    let mut collaborators: Vec<PresenceInfo> = entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            name.starts_with(&prefix) && name.ends_with(PRESENCE_SUFFIX)
+        })
+        .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+        .filter_map(|content| serde_json::from_str::<PresenceInfo>(&content).ok())
+        .filter(|info| info.author_id != own_author_id && now - info.heartbeat <= PRESENCE_TIMEOUT_MS)
+        .collect();
+    collaborators.sort_by(|a, b| a.author_name.cmp(&b.author_name).then(a.author_id.cmp(&b.author_id)));
+    collaborators
+}
+
+/// File and UUID of an open document, or None if it was never saved
+fn document_location(manager: &State<'_, Mutex<DocumentManager>>, doc_id: &str) -> Result<Option<(PathBuf, String)>, KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager
+        .documents
+        .get(doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+    Ok(doc.handle.path.clone().map(|path| (path, doc.meta.uuid.clone())))
+}
+
+/// Refresh this user's presence file for an open document
+///
+/// Called periodically by the frontend; does nothing for unsaved documents.
+#[tauri::command]
+pub fn heartbeat_presence(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+) -> Result<(), KorppiError> {
+    if let Some((path, uuid)) = document_location(&manager, &doc_id)? {
+        write_presence(&path, &uuid, &PresenceInfo::current())?;
+    }
+    Ok(())
+}
+
+/// Other people who currently have a document open
+#[tauri::command]
+pub fn get_active_collaborators(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+) -> Result<Vec<PresenceInfo>, KorppiError> {
+    let Some((path, uuid)) = document_location(&manager, &doc_id)? else {
+        return Ok(Vec::new());
+    };
+    let own = PresenceInfo::current();
+    Ok(read_collaborators(&path, &uuid, &own.author_id, own.heartbeat))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn presence(author_id: &str, name: &str, heartbeat: i64) -> PresenceInfo {
+        PresenceInfo {
+            author_id: author_id.to_string(),
+            author_name: name.to_string(),
+            color: "#e74c3c".to_string(),
+            host: "laptop".to_string(),
+            heartbeat,
+        }
+    }
+
+    #[test]
+    fn test_presence_path() {
+        assert_eq!(
+            presence_path(Path::new("/shared/paper.kmd"), "d1", "alice"),
+            PathBuf::from("/shared/.d1.alice.presence.json")
+        );
+    }
+
+    #[test]
+    fn test_read_collaborators() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let kmd = dir.path().join("paper.kmd");
+        let now = 1_700_000_000_000;
+
+        write_presence(&kmd, "d1", &presence("me", "Me", now)).unwrap();
+        write_presence(&kmd, "d1", &presence("carol", "Carol", now - 1000)).unwrap();
+        write_presence(&kmd, "d1", &presence("bob", "Bob", now - 30_000)).unwrap();
+        write_presence(&kmd, "d1", &presence("dave", "Dave", now - PRESENCE_TIMEOUT_MS - 1)).unwrap();
+        // Another document in the same folder
+        write_presence(&kmd, "d2", &presence("erin", "Erin", now)).unwrap();
+        fs::write(presence_path(&kmd, "d1", "broken"), "{").unwrap();
+
+        let names: Vec<String> = read_collaborators(&kmd, "d1", "me", now)
+            .into_iter()
+            .map(|p| p.author_name)
+            .collect();
+        assert_eq!(names, vec!["Bob", "Carol"]);
+
+        remove_presence(&kmd, "d1", "bob");
+        assert_eq!(read_collaborators(&kmd, "d1", "me", now), vec![presence("carol", "Carol", now - 1000)]);
+    }
+}
@@ -1,10 +1,24 @@
 // src-tauri/src/profile.rs
+use base64::Engine;
+use image::imageops::FilterType;
+use image::ImageFormat;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
 use std::fs;
 use tauri::AppHandle;
 use uuid::Uuid;
 
+use crate::bundle_crypto::{local_identity, public_key_of, signing_key_of};
+use crate::error::KorppiError;
+use crate::kmd::{AuthorProfile, AuthorRef};
+
+/// Default author color for new profiles
+pub(crate) const DEFAULT_AUTHOR_COLOR: &str = "#3498db";
+
+/// Width and height of avatars, in pixels
+const AVATAR_SIZE: u32 = 128;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserProfile {
     pub id: String,             // UUID
@@ -12,6 +26,18 @@ pub struct UserProfile {
     pub email: Option<String>,
     pub avatar_path: Option<PathBuf>,
     pub color: String,          // Hex color e.g., "#3498db"
+    /// Square PNG avatar, base64-encoded; written to the KMD files of
+    /// documents the user authors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar_base64: Option<String>,
+    /// Public key collaborators encrypt patch bundles with, base64; set
+    /// whenever the profile is saved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    /// Public key patch bundles from this user are signed with, base64;
+    /// set whenever the profile is saved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_key: Option<String>,
 }
 
 impl Default for UserProfile {
@@ -21,50 +47,77 @@ impl Default for UserProfile {
             name: String::new(),
             email: None,
             avatar_path: None,
-            color: "#3498db".to_string(),
+            color: DEFAULT_AUTHOR_COLOR.to_string(),
+            avatar_base64: None,
+            public_key: None,
+            signing_key: None,
         }
     }
 }
 
 /// Get the config directory path for the application
-fn get_config_dir() -> Result<PathBuf, String> {
+pub(crate) fn get_config_dir() -> Result<PathBuf, String> {
     dirs::config_dir()
         .map(|p| p.join("korppi"))
         .ok_or_else(|| "Could not determine config directory".to_string())
 }
 
 /// Get the profile file path
-fn get_profile_file_path() -> Result<PathBuf, String> {
+pub(crate) fn get_profile_file_path() -> Result<PathBuf, String> {
     get_config_dir().map(|p| p.join("profile.toml"))
 }
 
-/// Load profile from disk, return default if not exists
-#[tauri::command]
-pub fn get_profile(_app: AppHandle) -> Result<UserProfile, String> {
+/// Load the saved profile from disk, if any (for use by other modules)
+pub fn load_saved_profile() -> Result<Option<UserProfile>, String> {
     let path = get_profile_file_path()?;
     
     if !path.exists() {
-        return Ok(UserProfile::default());
+        return Ok(None);
     }
     
     let content = fs::read_to_string(&path)
         .map_err(|e| format!("Failed to read profile: {}", e))?;
     
     toml::from_str(&content)
+        .map(Some)
         .map_err(|e| format!("Failed to parse profile: {}", e))
 }
 
+/// Load profile from disk, return default if not exists
+///
+/// Profiles saved before encryption or signing keys existed get them now.
+#[tauri::command]
+pub fn get_profile(_app: AppHandle) -> Result<UserProfile, String> {
+    match load_saved_profile()? {
+        Some(profile) if profile.public_key.is_none() || profile.signing_key.is_none() => {
+            write_profile(&profile)?;
+            Ok(load_saved_profile()?.unwrap_or(profile))
+        }
+        profile => Ok(profile.unwrap_or_default()),
+    }
+}
+
 /// Save profile to disk
 #[tauri::command]
 pub fn save_profile(_app: AppHandle, profile: UserProfile) -> Result<(), String> {
+    write_profile(&profile)
+}
+
+pub(crate) fn write_profile(profile: &UserProfile) -> Result<(), String> {
     let config_dir = get_config_dir()?;
+    let identity = local_identity()?;
+    let profile = &UserProfile {
+        public_key: Some(public_key_of(&identity)),
+        signing_key: Some(signing_key_of(&identity)),
+        ..profile.clone()
+    };
     let path = config_dir.join("profile.toml");
     
     // Ensure config directory exists
     fs::create_dir_all(&config_dir)
         .map_err(|e| format!("Failed to create config directory: {}", e))?;
     
-    let content = toml::to_string_pretty(&profile)
+    let content = toml::to_string_pretty(profile)
         .map_err(|e| format!("Failed to serialize profile: {}", e))?;
     
     fs::write(&path, content)
@@ -73,6 +126,97 @@ pub fn save_profile(_app: AppHandle, profile: UserProfile) -> Result<(), String>
     Ok(())
 }
 
+/// Change the saved profile, creating it if needed
+fn update_saved_profile(change: impl FnOnce(&mut UserProfile)) -> Result<UserProfile, String> {
+    let mut profile = load_saved_profile()?.unwrap_or_default();
+    change(&mut profile);
+    write_profile(&profile)?;
+    Ok(profile)
+}
+
+/// Crop an image to its central square, scale it down to the avatar size
+/// and encode it as base64 PNG
+fn encode_avatar(image: &[u8]) -> Result<String, String> {
+    let image = image::load_from_memory(image).map_err(|e| format!("Unsupported image: {}", e))?;
+    let side = image.width().min(image.height());
+    let square = image.crop_imm((image.width() - side) / 2, (image.height() - side) / 2, side, side);
+    let size = side.min(AVATAR_SIZE);
+    let avatar = square.resize_exact(size, size, FilterType::Lanczos3);
+
+    let mut png = Vec::new();
+    avatar
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode avatar: {}", e))?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(png))
+}
+
+/// A `#rgb` or `#rrggbb` color, lowercased
+fn normalize_color(color: &str) -> Option<String> {
+    let hex = color.trim().strip_prefix('#')?;
+    ((hex.len() == 3 || hex.len() == 6) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| format!("#{}", hex.to_ascii_lowercase()))
+}
+
+/// Set the profile avatar from image data (PNG, JPEG, GIF or WebP)
+#[tauri::command]
+pub fn set_profile_avatar(image: Vec<u8>) -> Result<UserProfile, KorppiError> {
+    let avatar = encode_avatar(&image).map_err(KorppiError::InvalidInput)?;
+    Ok(update_saved_profile(|profile| profile.avatar_base64 = Some(avatar))?)
+}
+
+/// Remove the profile avatar
+#[tauri::command]
+pub fn clear_profile_avatar() -> Result<UserProfile, KorppiError> {
+    Ok(update_saved_profile(|profile| {
+        profile.avatar_base64 = None;
+        profile.avatar_path = None;
+    })?)
+}
+
+/// Set the profile highlight color
+#[tauri::command]
+pub fn set_profile_color(color: String) -> Result<UserProfile, KorppiError> {
+    let color = normalize_color(&color)
+        .ok_or_else(|| KorppiError::InvalidInput(format!("Not a hex color: {}", color)))?;
+    Ok(update_saved_profile(|profile| profile.color = color)?)
+}
+
+/// Profile of a document author to write into its KMD file
+///
+/// Starts from the profile in the KMD file the document was read from, if
+/// any, so collaborators keep their color and avatar. The local user's own
+/// entry takes their current color and avatar.
+pub(crate) fn kmd_author_profile(author: &AuthorRef, roster_dir: &Path, local: Option<&UserProfile>) -> AuthorProfile {
+    let stored: Option<AuthorProfile> = (!author.id.contains(['/', '\\']))
+        .then(|| fs::read_to_string(roster_dir.join(format!("{}.json", author.id))).ok())
+        .flatten()
+        .and_then(|content| serde_json::from_str(&content).ok());
+
+    let mut profile = AuthorProfile {
+        id: author.id.clone(),
+        name: author.name.clone(),
+        email: author.email.clone(),
+        color: DEFAULT_AUTHOR_COLOR.to_string(),
+        avatar_base64: None,
+        public_key: None,
+        signing_key: None,
+    };
+    if let Some(stored) = stored {
+        profile.email = profile.email.or(stored.email);
+        profile.color = stored.color;
+        profile.avatar_base64 = stored.avatar_base64;
+        profile.public_key = stored.public_key;
+        profile.signing_key = stored.signing_key;
+    }
+    if let Some(local) = local.filter(|p| p.id == author.id) {
+        profile.color = local.color.clone();
+        profile.avatar_base64 = local.avatar_base64.clone();
+        profile.public_key = local.public_key.clone().or(profile.public_key);
+        profile.signing_key = local.signing_key.clone().or(profile.signing_key);
+    }
+    profile
+}
+
 /// Return the config directory path (for avatar storage)
 #[tauri::command]
 pub fn get_profile_path(_app: AppHandle) -> Result<PathBuf, String> {
@@ -151,6 +295,9 @@ mod tests {
             email: Some("test@example.com".to_string()),
             avatar_path: Some(PathBuf::from("/path/to/avatar.png")),
             color: "#ff5500".to_string(),
+            avatar_base64: None,
+            public_key: None,
+            signing_key: None,
         };
 
         let toml_str = toml::to_string_pretty(&profile).unwrap();
@@ -173,6 +320,9 @@ mod tests {
             email: Some("test@example.com".to_string()),
             avatar_path: None,
             color: "#aabbcc".to_string(),
+            avatar_base64: Some("iVBORw0KGgo=".to_string()),
+            public_key: Some("a2V5".to_string()),
+            signing_key: Some("c2lnbg==".to_string()),
         };
 
         // Write to file
@@ -187,6 +337,9 @@ mod tests {
         assert_eq!(loaded.name, profile.name);
         assert_eq!(loaded.email, profile.email);
         assert_eq!(loaded.color, profile.color);
+        assert_eq!(loaded.avatar_base64, profile.avatar_base64);
+        assert_eq!(loaded.public_key, profile.public_key);
+        assert_eq!(loaded.signing_key, profile.signing_key);
     }
 
     #[test]
@@ -201,4 +354,65 @@ mod tests {
         assert!(profile.avatar_path.is_none());
         assert_eq!(profile.color, "#123456");
     }
+
+    #[test]
+    fn test_encode_avatar() {
+        let photo = image::RgbImage::from_pixel(300, 200, image::Rgb([200, 40, 40]));
+        let mut data = Vec::new();
+        photo.write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg).unwrap();
+
+        let avatar = base64::engine::general_purpose::STANDARD.decode(encode_avatar(&data).unwrap()).unwrap();
+        let avatar = image::load_from_memory_with_format(&avatar, ImageFormat::Png).unwrap();
+        assert_eq!((avatar.width(), avatar.height()), (AVATAR_SIZE, AVATAR_SIZE));
+
+        assert!(encode_avatar(b"not an image").is_err());
+        assert_eq!(normalize_color(" #A1B2C3"), Some("#a1b2c3".to_string()));
+        assert_eq!(normalize_color("#fff"), Some("#fff".to_string()));
+        assert_eq!(normalize_color("a1b2c3"), None);
+        assert_eq!(normalize_color("#12345g"), None);
+    }
+
+    #[test]
+    fn test_kmd_author_profile() {
+        let roster = TempDir::new().unwrap();
+        let stored = AuthorProfile {
+            id: "bob".to_string(),
+            name: "Robert".to_string(),
+            email: Some("bob@example.org".to_string()),
+            color: "#00aa00".to_string(),
+            avatar_base64: Some("Ym9i".to_string()),
+            public_key: Some("Ym9iLWtleQ==".to_string()),
+            signing_key: Some("Ym9iLXNpZ24=".to_string()),
+        };
+        std::fs::write(roster.path().join("bob.json"), serde_json::to_string(&stored).unwrap()).unwrap();
+        let author = |id: &str, name: &str| AuthorRef {
+            id: id.to_string(),
+            name: name.to_string(),
+            email: None,
+            joined_at: None,
+            role: None,
+        };
+        let local = UserProfile {
+            id: "ann".to_string(),
+            color: "#ff0000".to_string(),
+            avatar_base64: Some("YW5u".to_string()),
+            public_key: Some("YW5uLWtleQ==".to_string()),
+            ..UserProfile::default()
+        };
+
+        // Collaborators keep the profile their KMD file had, under their current name
+        let bob = kmd_author_profile(&author("bob", "Bob"), roster.path(), Some(&local));
+        assert_eq!((bob.name.as_str(), bob.color.as_str()), ("Bob", "#00aa00"));
+        assert_eq!(bob.email.as_deref(), Some("bob@example.org"));
+        assert_eq!(bob.avatar_base64.as_deref(), Some("Ym9i"));
+        assert_eq!(bob.public_key.as_deref(), Some("Ym9iLWtleQ=="));
+        assert_eq!(bob.signing_key.as_deref(), Some("Ym9iLXNpZ24="));
+
+        let ann = kmd_author_profile(&author("ann", "Ann"), roster.path(), Some(&local));
+        assert_eq!((ann.color.as_str(), ann.avatar_base64.as_deref()), ("#ff0000", Some("YW5u")));
+        assert_eq!(ann.public_key.as_deref(), Some("YW5uLWtleQ=="));
+
+        let carol = kmd_author_profile(&author("carol", "Carol"), roster.path(), None);
+        assert_eq!(carol.color, DEFAULT_AUTHOR_COLOR);
+    }
 }
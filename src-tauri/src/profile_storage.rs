@@ -0,0 +1,201 @@
+// src-tauri/src/profile_storage.rs
+//! Small JSON values the frontend keeps per profile: panel layouts, the
+//! last review filter and the like.
+//!
+//! Values are grouped by namespace, one per frontend module, and stored in
+//! `storage/<profile id>.json` in the config directory, so switching
+//! profiles switches the stored state with them. Values and the file as a
+//! whole are size-limited; this is not a place for document content.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::KorppiError;
+use crate::profile::{get_config_dir, load_saved_profile};
+
+/// Longest namespace or key
+const MAX_NAME_LEN: usize = 64;
+
+/// Largest value, as JSON
+const MAX_VALUE_BYTES: usize = 64 * 1024;
+
+/// Largest storage file of one profile
+const MAX_STORAGE_BYTES: usize = 1024 * 1024;
+
+/// File name used before a profile is saved
+const NO_PROFILE: &str = "default";
+
+/// Stored values by namespace and key
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct ProfileStorage(BTreeMap<String, BTreeMap<String, serde_json::Value>>);
+
+impl ProfileStorage {
+    pub fn get(&self, namespace: &str, key: &str) -> Option<&serde_json::Value> {
+        self.0.get(namespace)?.get(key)
+    }
+
+    /// Store a value, unless it or the storage as a whole gets too large
+    pub fn set(&mut self, namespace: &str, key: &str, value: serde_json::Value) -> Result<(), String> {
+        let size = serde_json::to_vec(&value).map_err(|e| e.to_string())?.len();
+        if size > MAX_VALUE_BYTES {
+            return Err(format!("Value of {}/{} is larger than {} KB", namespace, key, MAX_VALUE_BYTES / 1024));
+        }
+        let previous = self.0.entry(namespace.to_string()).or_default().insert(key.to_string(), value);
+        if self.size()? > MAX_STORAGE_BYTES {
+            self.restore(namespace, key, previous);
+            return Err(format!("Profile storage is full ({} KB)", MAX_STORAGE_BYTES / 1024));
+        }
+        Ok(())
+    }
+
+    /// Remove a key, or the whole namespace without one; whether anything
+    /// was removed
+    pub fn delete(&mut self, namespace: &str, key: Option<&str>) -> bool {
+        match key {
+            None => self.0.remove(namespace).is_some(),
+            Some(key) => {
+                let Some(values) = self.0.get_mut(namespace) else {
+                    return false;
+                };
+                let removed = values.remove(key).is_some();
+                if values.is_empty() {
+                    self.0.remove(namespace);
+                }
+                removed
+            }
+        }
+    }
+
+    fn restore(&mut self, namespace: &str, key: &str, previous: Option<serde_json::Value>) {
+        match previous {
+            Some(value) => {
+                self.0.entry(namespace.to_string()).or_default().insert(key.to_string(), value);
+            }
+            None => {
+                self.delete(namespace, Some(key));
+            }
+        }
+    }
+
+    fn size(&self) -> Result<usize, String> {
+        Ok(serde_json::to_vec(self).map_err(|e| e.to_string())?.len())
+    }
+}
+
+/// A namespace or key: letters, digits, `.`, `_` and `-`
+fn validate_name(kind: &str, name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid storage {}: {:?}", kind, name))
+    }
+}
+
+/// Storage file of the saved profile
+fn storage_path() -> Result<PathBuf, String> {
+    let id = load_saved_profile()?
+        .map(|profile| profile.id)
+        .filter(|id| validate_name("profile ID", id).is_ok())
+        .unwrap_or_else(|| NO_PROFILE.to_string());
+    get_config_dir().map(|dir| dir.join("storage").join(format!("{}.json", id)))
+}
+
+/// Load stored values from a file, empty if it does not exist
+pub fn load_storage_from(path: &Path) -> Result<ProfileStorage, String> {
+    if !path.exists() {
+        return Ok(ProfileStorage::default());
+    }
+    let content = fs::read_to_string(path).map_err(|e| format!("Failed to read profile storage: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Failed to parse profile storage: {}", e))
+}
+
+/// Save stored values to a file
+pub fn save_storage_to(path: &Path, storage: &ProfileStorage) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create storage directory: {}", e))?;
+    }
+    let content = serde_json::to_string(storage).map_err(|e| format!("Failed to serialize profile storage: {}", e))?;
+    fs::write(path, content).map_err(|e| format!("Failed to write profile storage: {}", e))
+}
+
+/// Get a value stored for the current profile, or null
+#[tauri::command]
+pub fn profile_storage_get(namespace: String, key: String) -> Result<Option<serde_json::Value>, KorppiError> {
+    validate_name("namespace", &namespace).map_err(KorppiError::InvalidInput)?;
+    validate_name("key", &key).map_err(KorppiError::InvalidInput)?;
+    Ok(load_storage_from(&storage_path()?)?.get(&namespace, &key).cloned())
+}
+
+/// Store a JSON value for the current profile
+#[tauri::command]
+pub fn profile_storage_set(namespace: String, key: String, value: serde_json::Value) -> Result<(), KorppiError> {
+    validate_name("namespace", &namespace).map_err(KorppiError::InvalidInput)?;
+    validate_name("key", &key).map_err(KorppiError::InvalidInput)?;
+    let path = storage_path()?;
+    let mut storage = load_storage_from(&path)?;
+    storage.set(&namespace, &key, value).map_err(KorppiError::InvalidInput)?;
+    Ok(save_storage_to(&path, &storage)?)
+}
+
+/// Delete a stored value, or every value of a namespace without a key
+#[tauri::command]
+pub fn profile_storage_delete(namespace: String, key: Option<String>) -> Result<bool, KorppiError> {
+    validate_name("namespace", &namespace).map_err(KorppiError::InvalidInput)?;
+    if let Some(key) = &key {
+        validate_name("key", key).map_err(KorppiError::InvalidInput)?;
+    }
+    let path = storage_path()?;
+    let mut storage = load_storage_from(&path)?;
+    let removed = storage.delete(&namespace, key.as_deref());
+    if removed {
+        save_storage_to(&path, &storage)?;
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_storage_roundtrip_and_limits() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("storage").join("p1.json");
+        assert_eq!(load_storage_from(&path).unwrap(), ProfileStorage::default());
+
+        let mut storage = ProfileStorage::default();
+        storage.set("timeline", "filter", json!({ "authors": ["alice"] })).unwrap();
+        storage.set("layout", "sidebar", json!(280)).unwrap();
+        save_storage_to(&path, &storage).unwrap();
+        let mut loaded = load_storage_from(&path).unwrap();
+        assert_eq!(loaded.get("timeline", "filter"), Some(&json!({ "authors": ["alice"] })));
+
+        assert!(loaded.delete("layout", Some("sidebar")));
+        assert!(!loaded.delete("layout", Some("sidebar")));
+        assert!(loaded.get("layout", "sidebar").is_none());
+
+        let large = json!("x".repeat(MAX_VALUE_BYTES));
+        assert!(loaded.set("timeline", "big", large).is_err());
+        // Fill up the storage; the value that does not fit is not kept
+        let value = json!("x".repeat(MAX_VALUE_BYTES / 2));
+        let mut full = loaded.clone();
+        let mut count = 0;
+        while loaded.set("fill", &format!("k{}", count), value.clone()).is_ok() {
+            full = loaded.clone();
+            count += 1;
+        }
+        assert_eq!(count, MAX_STORAGE_BYTES / (MAX_VALUE_BYTES / 2) - 1);
+        assert_eq!(loaded, full);
+
+        assert!(validate_name("key", "panel.width_2").is_ok());
+        assert!(validate_name("key", "../x").is_err());
+        assert!(validate_name("namespace", "").is_err());
+    }
+}
@@ -0,0 +1,392 @@
+// src-tauri/src/quarto.rs
+//! Rendering imported Quarto and R Markdown documents to preview figures.
+//!
+//! `render_quarto_figures` writes the document back out as its source
+//! format, runs `quarto render` on it to markdown and stores the figures it
+//! produced as document assets. Each figure is embedded after the chunk that
+//! made it, with a `{#fig:label}` when the chunk has a `fig-` label, so
+//! computational results show in the editor and export like any figure.
+//!
+//! The executable defaults to `quarto` on PATH but can be set in the
+//! application settings, like pandoc's.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::document_manager::{document_assets_dir, DocumentManager, ASSETS_DIR};
+use crate::error::KorppiError;
+use crate::settings::load_app_settings;
+use crate::source_format::{
+    chunk_label, match_chunks, remove_rendered_figures, restore_source, CodeChunk, SourceFormat,
+    RENDERED_FIGURE_PREFIX,
+};
+
+/// Name of the markdown file quarto renders to
+const RENDERED_FILE: &str = "rendered.md";
+
+pub(crate) const FIGURE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "svg", "webp"];
+
+/// A figure embedded by render_quarto_figures
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RenderedFigure {
+    /// Cross-reference label ("fig:cars"), if the chunk has a `fig-` label
+    pub label: Option<String>,
+    pub caption: String,
+    /// Path the figure is embedded with ("assets/render-fig-cars-1.png")
+    pub asset: String,
+}
+
+/// Result of render_quarto_figures
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuartoRender {
+    /// Editor content with the figures embedded after their chunks
+    pub content: String,
+    pub figures: Vec<RenderedFigure>,
+    /// Figures of chunks without a label, which cannot be placed
+    pub unplaced: usize,
+}
+
+/// A figure found in rendered markdown
+#[derive(Debug, Clone, PartialEq)]
+struct OutputFigure {
+    /// Label in Quarto's form ("fig-cars"), from the image or its div
+    label: Option<String>,
+    caption: String,
+    path: PathBuf,
+}
+
+/// The quarto executable, from the settings or PATH
+fn quarto_executable() -> PathBuf {
+    load_app_settings()
+        .unwrap_or_default()
+        .quarto_path
+        .unwrap_or_else(|| PathBuf::from("quarto"))
+}
+
+/// Check if quarto is available
+pub fn is_quarto_available() -> bool {
+    Command::new(quarto_executable())
+        .arg("--version")
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+/// Tauri command to check if quarto is available
+#[tauri::command]
+pub fn check_quarto_available() -> bool {
+    is_quarto_available()
+}
+
+/// Render a source file next to it as markdown, executing its chunks in
+/// `execute_dir` when given
+fn render_to_markdown(source_path: &Path, execute_dir: Option<&Path>) -> Result<String, KorppiError> {
+    let dir = source_path.parent().unwrap_or(Path::new("."));
+    let mut command = Command::new(quarto_executable());
+    command
+        .arg("render")
+        .arg(source_path.file_name().unwrap_or_default())
+        .args(["--to", "markdown", "--output", RENDERED_FILE])
+        .current_dir(dir);
+    if let Some(execute_dir) = execute_dir {
+        command.arg("--execute-dir").arg(execute_dir);
+    }
+
+    let output = command
+        .output()
+        .map_err(|e| KorppiError::QuartoUnavailable(format!("Failed to start quarto: {}", e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let tail: Vec<&str> = stderr.trim().lines().rev().take(10).collect();
+        let tail: Vec<&str> = tail.into_iter().rev().collect();
+        return Err(format!("Quarto render failed:\n{}", tail.join("\n")).into());
+    }
+    Ok(fs::read_to_string(dir.join(RENDERED_FILE))?)
+}
+
+/// Find the images of rendered markdown that are files under `dir`
+///
+/// Figures are labelled by their attributes (`{#fig-cars}`) or by the
+/// `::: {#fig-cars}` div around them, whose last paragraph is the caption.
+fn parse_rendered_figures(markdown: &str, dir: &Path) -> Vec<OutputFigure> {
+    let image_re = Regex::new(r"!\[([^\]]*)\]\(([^)\s]+)[^)]*\)(\{[^}]*\})?").unwrap();
+    let label_re = Regex::new(r"#(fig-[\w-]+)").unwrap();
+
+    let mut figures: Vec<OutputFigure> = Vec::new();
+    // Open divs: label, index of their first figure and last paragraph line
+    let mut divs: Vec<(Option<String>, usize, Option<String>)> = Vec::new();
+    let mut in_code = false;
+
+    for line in markdown.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code || trimmed.is_empty() {
+            continue;
+        }
+
+        if trimmed.starts_with(":::") {
+            let attrs = trimmed.trim_start_matches(':').trim();
+            if !attrs.is_empty() {
+                let label = label_re.captures(attrs).map(|c| c[1].to_string());
+                divs.push((label, figures.len(), None));
+            } else if let Some((Some(_), first, Some(caption))) = divs.pop() {
+                for figure in figures[first..].iter_mut().filter(|f| f.caption.is_empty()) {
+                    figure.caption = caption.clone();
+                }
+            }
+            continue;
+        }
+
+        let mut has_image = false;
+        for caps in image_re.captures_iter(trimmed) {
+            has_image = true;
+            let path = dir.join(&caps[2]);
+            let is_figure = path
+                .extension()
+                .map(|e| FIGURE_EXTENSIONS.contains(&e.to_string_lossy().to_lowercase().as_str()))
+                .unwrap_or(false);
+            if !is_figure || !path.is_file() {
+                continue;
+            }
+            let label = caps
+                .get(3)
+                .and_then(|attrs| label_re.captures(attrs.as_str()))
+                .map(|c| c[1].to_string())
+                .or_else(|| divs.iter().rev().find_map(|(label, _, _)| label.clone()));
+            figures.push(OutputFigure {
+                label,
+                caption: caps[1].to_string(),
+                path,
+            });
+        }
+        if !has_image {
+            if let Some(div) = divs.last_mut() {
+                div.2 = Some(trimmed.to_string());
+            }
+        }
+    }
+    figures
+}
+
+/// The chunk label a figure belongs to: its own label, or the chunk its
+/// file is named after (knitr writes `fig-cars-1.png` for chunk `fig-cars`)
+fn figure_chunk<'a>(figure: &OutputFigure, labels: &'a [String]) -> Option<&'a String> {
+    if let Some(label) = &figure.label {
+        if let Some(chunk) = labels.iter().find(|l| *l == label) {
+            return Some(chunk);
+        }
+    }
+    let stem = figure.path.file_stem()?.to_string_lossy().to_string();
+    labels
+        .iter()
+        .filter(|l| stem == **l || stem.starts_with(&format!("{}-", l)))
+        .max_by_key(|l| l.len())
+}
+
+/// Embed figures after the chunks that produced them
+///
+/// Previously embedded figures are dropped first. Returns the new content,
+/// each embedded figure with the file to store it from, and the number of
+/// figures that matched no chunk.
+fn place_figures(
+    content: &str,
+    chunks: &[CodeChunk],
+    figures: &[OutputFigure],
+) -> (String, Vec<(PathBuf, RenderedFigure)>, usize) {
+    let lines = remove_rendered_figures(content);
+    let line_refs: Vec<&str> = lines.iter().map(String::as_str).collect();
+    let labelled: Vec<(usize, String)> = match_chunks(&line_refs, chunks)
+        .into_iter()
+        .filter_map(|(block, header)| {
+            let header = header?;
+            chunk_label(&block, &header).map(|label| (block.close, label))
+        })
+        .collect();
+    let labels: Vec<String> = labelled.iter().map(|(_, l)| l.clone()).collect();
+
+    let mut placed: Vec<(PathBuf, RenderedFigure)> = Vec::new();
+    let mut after_line: HashMap<usize, Vec<String>> = HashMap::new();
+    let mut unplaced = 0;
+    for figure in figures {
+        let Some(chunk) = figure_chunk(figure, &labels) else {
+            unplaced += 1;
+            continue;
+        };
+        let close = labelled.iter().find(|(_, l)| l == chunk).map(|(c, _)| *c).unwrap_or(0);
+
+        let file_name = figure.path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let mut name = format!("{}{}", RENDERED_FIGURE_PREFIX, file_name);
+        if placed.iter().any(|(_, f)| f.asset.ends_with(&format!("/{}", name))) {
+            name = format!("{}{}-{}", RENDERED_FIGURE_PREFIX, placed.len(), file_name);
+        }
+        let label = figure
+            .label
+            .as_deref()
+            .unwrap_or(chunk)
+            .strip_prefix("fig-")
+            .map(|l| format!("fig:{}", l));
+        let rendered = RenderedFigure {
+            label,
+            caption: figure.caption.replace(['[', ']'], ""),
+            asset: format!("{}/{}", ASSETS_DIR, name),
+        };
+
+        let mut image = format!("![{}]({})", rendered.caption, rendered.asset);
+        if let Some(label) = &rendered.label {
+            image.push_str(&format!("{{#{}}}", label));
+        }
+        let embedded = after_line.entry(close).or_default();
+        embedded.push(String::new());
+        embedded.push(image);
+        placed.push((figure.path.clone(), rendered));
+    }
+
+    let mut output: Vec<String> = Vec::with_capacity(lines.len());
+    for (i, line) in lines.into_iter().enumerate() {
+        output.push(line);
+        if let Some(embedded) = after_line.remove(&i) {
+            output.extend(embedded);
+        }
+    }
+    (output.join("\n"), placed, unplaced)
+}
+
+/// Run the chunks of an imported Quarto or R Markdown document and embed
+/// the figures they produce
+///
+/// `content` is the current editor content; the returned content replaces
+/// it. Figures of the previous render are replaced.
+#[tauri::command]
+pub async fn render_quarto_figures(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    content: String,
+) -> Result<QuartoRender, KorppiError> {
+    let (source, title, history_path) = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.clone()))?;
+        (doc.meta.source.clone(), doc.meta.title.clone(), doc.history_path.clone())
+    };
+    let source = source.ok_or_else(|| {
+        KorppiError::InvalidInput("Only documents imported from Quarto or R Markdown can be rendered".to_string())
+    })?;
+    if !is_quarto_available() {
+        return Err(KorppiError::QuartoUnavailable("Rendering figures requires Quarto".to_string()));
+    }
+
+    let (format, extension) = if source.format == "rmarkdown" {
+        (SourceFormat::RMarkdown, "Rmd")
+    } else {
+        (SourceFormat::Quarto, "qmd")
+    };
+    let work_dir = tempfile::tempdir()?;
+    let source_path = work_dir.path().join(format!("document.{}", extension));
+    fs::write(&source_path, restore_source(&content, Some(&source), format, &title))?;
+
+    let execute_dir = source.path.as_deref().and_then(Path::parent).filter(|d| d.is_dir());
+    let rendered = render_to_markdown(&source_path, execute_dir)?;
+    let figures = parse_rendered_figures(&rendered, work_dir.path());
+    let (content, placed, unplaced) = place_figures(&content, &source.chunks, &figures);
+
+    let assets_dir = document_assets_dir(&history_path);
+    fs::create_dir_all(&assets_dir)?;
+    for entry in fs::read_dir(&assets_dir)? {
+        let path = entry?.path();
+        if path.file_name().is_some_and(|n| n.to_string_lossy().starts_with(RENDERED_FIGURE_PREFIX)) {
+            fs::remove_file(path)?;
+        }
+    }
+    for (path, figure) in &placed {
+        let name = &figure.asset[ASSETS_DIR.len() + 1..];
+        fs::copy(path, assets_dir.join(name))?;
+    }
+
+    Ok(QuartoRender {
+        content,
+        figures: placed.into_iter().map(|(_, figure)| figure).collect(),
+        unplaced,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_format::split_source;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_rendered_figures() {
+        let dir = TempDir::new().unwrap();
+        let figure_dir = dir.path().join("document_files/figure-markdown");
+        fs::create_dir_all(&figure_dir).unwrap();
+        for name in ["fig-cars-1.png", "fig-iris-1.png", "unnamed-chunk-1-1.png"] {
+            fs::write(figure_dir.join(name), b"png").unwrap();
+        }
+
+        let rendered = "\
+::: {.cell}
+![Speed and distance](document_files/figure-markdown/fig-cars-1.png){#fig-cars}
+:::
+
+::: {#fig-iris .cell}
+::: {.cell-output-display}
+![](document_files/figure-markdown/fig-iris-1.png)
+:::
+
+Petal length
+:::
+
+![](document_files/figure-markdown/unnamed-chunk-1-1.png)
+
+![Logo](https://example.com/logo.png)";
+        let figures = parse_rendered_figures(rendered, dir.path());
+
+        assert_eq!(figures.len(), 3);
+        assert_eq!(figures[0].label.as_deref(), Some("fig-cars"));
+        assert_eq!(figures[0].caption, "Speed and distance");
+        assert_eq!(figures[1].label.as_deref(), Some("fig-iris"));
+        assert_eq!(figures[1].caption, "Petal length");
+        assert_eq!(figures[2].label, None);
+    }
+
+    #[test]
+    fn test_place_figures() {
+        let qmd = "---\ntitle: \"Cars\"\n---\n\nSee @fig-cars.\n\n```{r fig-cars, echo=FALSE}\nplot(cars)\n```\n\n```{python}\n#| label: fig-sine\nplt.plot(x)\n```\n\n```{r}\nsummary(cars)\n```\n";
+        let (body, source) = split_source(qmd, "quarto");
+        assert!(body.contains("See @fig:cars."));
+
+        let figure = |label: Option<&str>, name: &str| OutputFigure {
+            label: label.map(str::to_string),
+            caption: "A plot".to_string(),
+            path: PathBuf::from(format!("/render/{}", name)),
+        };
+        let figures = vec![
+            figure(None, "fig-cars-1.png"),
+            figure(Some("fig-sine"), "cell-2-output-1.png"),
+            figure(None, "unnamed-chunk-1-1.png"),
+        ];
+        let (content, placed, unplaced) = place_figures(&body, &source.chunks, &figures);
+
+        assert_eq!(unplaced, 1);
+        assert_eq!(placed.len(), 2);
+        assert!(content.contains("plot(cars)\n```\n\n![A plot](assets/render-fig-cars-1.png){#fig:cars}\n\n```python"));
+        assert!(content.contains("![A plot](assets/render-cell-2-output-1.png){#fig:sine}"));
+
+        // Rendering again replaces the figures, exporting leaves them out
+        let (again, _, _) = place_figures(&content, &source.chunks, &figures);
+        assert_eq!(again, content);
+        assert_eq!(restore_source(&content, Some(&source), SourceFormat::Quarto, "Cars"), qmd);
+    }
+}
@@ -0,0 +1,438 @@
+// src-tauri/src/readability.rs
+//! Readability and style metrics for writing feedback.
+//!
+//! Paragraphs come from pulldown-cmark, so headings, code and tables are
+//! left out; sentences are split on terminal punctuation, skipping common
+//! abbreviations. Flesch scores and passive voice use English heuristics
+//! (syllable counting, "to be" + participle) and are only reported for
+//! English documents. Statistics are given for the whole text and for each
+//! section of the outline, and problems come back as UTF-16 ranges the
+//! editor can underline.
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::document_manager::{current_document_text, DocumentManager};
+use crate::error::KorppiError;
+use crate::outline::{build_outline, OutlineNode};
+
+/// Sentences longer than this many words are flagged
+const LONG_SENTENCE_WORDS: usize = 30;
+
+/// Paragraphs longer than this many words are flagged
+const LONG_PARAGRAPH_WORDS: usize = 150;
+
+/// Words ending in a period that do not end a sentence
+const ABBREVIATIONS: &[&str] = &[
+    "e.g", "i.e", "al", "etc", "vs", "cf", "fig", "figs", "eq", "eqs", "tab", "sec", "ch", "dr", "mr", "mrs", "ms",
+    "prof", "no", "vol", "pp", "approx", "ca", "resp",
+];
+
+/// Irregular past participles for passive voice detection
+const IRREGULAR_PARTICIPLES: &[&str] = &[
+    "arisen", "begun", "bought", "brought", "built", "caught", "chosen", "done", "drawn", "driven", "eaten",
+    "fallen", "felt", "found", "forgotten", "given", "gone", "grown", "heard", "held", "hidden", "kept", "known",
+    "laid", "led", "left", "lost", "made", "meant", "met", "paid", "proven", "put", "read", "run", "said", "seen",
+    "sent", "set", "shown", "sold", "spent", "spoken", "stolen", "struck", "taken", "taught", "thought", "thrown",
+    "told", "understood", "won", "worn", "written",
+];
+
+/// Aggregate metrics of some text
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ReadabilityStats {
+    pub words: usize,
+    pub sentences: usize,
+    pub paragraphs: usize,
+    /// Words per sentence
+    pub average_sentence_length: f64,
+    /// 0-100, higher is easier (English only)
+    pub flesch_reading_ease: Option<f64>,
+    /// US school grade (English only)
+    pub flesch_kincaid_grade: Option<f64>,
+    /// Share of sentences in the passive voice, 0-1 (English only)
+    pub passive_ratio: Option<f64>,
+    pub long_sentences: usize,
+    pub long_paragraphs: usize,
+}
+
+/// Metrics of one section of the outline, subsections included
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SectionReadability {
+    pub title: String,
+    pub level: u8,
+    pub label: Option<String>,
+    /// Section range (UTF-16), as in the outline
+    pub start: usize,
+    pub end: usize,
+    pub stats: ReadabilityStats,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StyleIssueKind {
+    LongSentence,
+    LongParagraph,
+    PassiveVoice,
+}
+
+/// A problem to underline in the editor
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StyleIssue {
+    /// Starting character index (UTF-16, inclusive)
+    pub start: usize,
+    /// Ending character index (UTF-16, exclusive)
+    pub end: usize,
+    pub kind: StyleIssueKind,
+    pub message: String,
+}
+
+/// Result of analyze_readability
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReadabilityReport {
+    pub summary: ReadabilityStats,
+    pub sections: Vec<SectionReadability>,
+    pub issues: Vec<StyleIssue>,
+}
+
+/// A sentence with byte offsets into the markdown
+#[derive(Debug)]
+struct Sentence {
+    start: usize,
+    end: usize,
+    words: usize,
+    syllables: usize,
+    /// Byte ranges of passive constructions
+    passives: Vec<(usize, usize)>,
+}
+
+/// A paragraph with byte offsets into the markdown
+#[derive(Debug)]
+struct Paragraph {
+    start: usize,
+    end: usize,
+    sentences: Vec<Sentence>,
+}
+
+impl Paragraph {
+    fn words(&self) -> usize {
+        self.sentences.iter().map(|s| s.words).sum()
+    }
+}
+
+/// Byte offsets to UTF-16 offsets
+fn utf16_offset(text: &str, byte: usize) -> usize {
+    text[..byte].encode_utf16().count()
+}
+
+/// English syllable estimate: vowel groups, less a silent final e
+fn count_syllables(word: &str) -> usize {
+    let word: String = word.chars().filter(|c| c.is_alphabetic()).flat_map(char::to_lowercase).collect();
+    if word.chars().count() <= 3 {
+        return 1;
+    }
+    let is_vowel = |c: char| "aeiouy".contains(c);
+    let mut count = 0;
+    let mut previous_vowel = false;
+    for c in word.chars() {
+        let vowel = is_vowel(c);
+        if vowel && !previous_vowel {
+            count += 1;
+        }
+        previous_vowel = vowel;
+    }
+    if word.ends_with('e') && !word.ends_with("le") && !word.ends_with("ee") {
+        count -= 1;
+    }
+    count.max(1)
+}
+
+/// Words of a sentence, without link targets, attributes and citations
+fn sentence_words(source: &str) -> Vec<String> {
+    let markup = Regex::new(r"\]\([^)]*\)|\{[^}]*\}|\[@[^\]]*\]|<[^>]+>").unwrap();
+    markup
+        .replace_all(source, " ")
+        .split_whitespace()
+        .filter(|w| w.chars().any(char::is_alphanumeric))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Byte ranges where a sentence ends within a paragraph's source
+fn sentence_ranges(source: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let chars: Vec<(usize, char)> = source.char_indices().collect();
+
+    let mut i = 0;
+    while i < chars.len() {
+        let (index, c) = chars[i];
+        if matches!(c, '.' | '!' | '?') {
+            // Include repeated punctuation and closing quotes or brackets
+            let mut j = i + 1;
+            while j < chars.len() && matches!(chars[j].1, '.' | '!' | '?' | '"' | '\'' | '”' | '’' | ')' | ']' | '*' | '_') {
+                j += 1;
+            }
+            let at_break = j == chars.len() || chars[j].1.is_whitespace();
+            let word_before = source[start..index]
+                .rsplit(|ch: char| ch.is_whitespace() || ch == '(')
+                .next()
+                .unwrap_or("")
+                .to_lowercase();
+            let abbreviation = c == '.'
+                && (ABBREVIATIONS.contains(&word_before.as_str())
+                    || (word_before.chars().count() == 1 && word_before.chars().all(char::is_alphabetic)));
+            if at_break && !abbreviation {
+                let end = chars.get(j).map(|(b, _)| *b).unwrap_or(source.len());
+                ranges.push((start, end));
+                start = end;
+            }
+            i = j;
+            continue;
+        }
+        i += 1;
+    }
+    if !source[start..].trim().is_empty() {
+        ranges.push((start, source.len()));
+    }
+
+    // Drop the whitespace between sentences
+    ranges
+        .into_iter()
+        .filter_map(|(s, e)| {
+            let text = &source[s..e];
+            let lead = text.len() - text.trim_start().len();
+            let trail = text.len() - text.trim_end().len();
+            (s + lead < e - trail).then_some((s + lead, e - trail))
+        })
+        .collect()
+}
+
+/// Paragraphs of body text with their sentences
+fn analyze_paragraphs(markdown: &str, english: bool) -> Vec<Paragraph> {
+    let passive = Regex::new(&format!(
+        r"(?i)\b(am|is|are|was|were|be|been|being)\s+(\w+ly\s+)?(\w+ed|{})\b",
+        IRREGULAR_PARTICIPLES.join("|")
+    ))
+    .unwrap();
+
+    let mut paragraphs = Vec::new();
+    let options = Options::ENABLE_TABLES | Options::ENABLE_HEADING_ATTRIBUTES | Options::ENABLE_STRIKETHROUGH;
+    for (event, range) in Parser::new_ext(markdown, options).into_offset_iter() {
+        if !matches!(event, Event::Start(Tag::Paragraph)) {
+            continue;
+        }
+        let source = &markdown[range.clone()];
+        let sentences = sentence_ranges(source)
+            .into_iter()
+            .filter_map(|(s, e)| {
+                let words = sentence_words(&source[s..e]);
+                if words.is_empty() {
+                    return None;
+                }
+                let passives = if english {
+                    passive
+                        .find_iter(&source[s..e])
+                        .map(|m| (range.start + s + m.start(), range.start + s + m.end()))
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+                Some(Sentence {
+                    start: range.start + s,
+                    end: range.start + e,
+                    words: words.len(),
+                    syllables: words.iter().map(|w| count_syllables(w)).sum(),
+                    passives,
+                })
+            })
+            .collect::<Vec<_>>();
+        if !sentences.is_empty() {
+            paragraphs.push(Paragraph {
+                start: range.start,
+                end: range.end,
+                sentences,
+            });
+        }
+    }
+    paragraphs
+}
+
+fn compute_stats<'a>(paragraphs: impl Iterator<Item = &'a Paragraph>, english: bool) -> ReadabilityStats {
+    let mut stats = ReadabilityStats::default();
+    let mut syllables = 0;
+    let mut passive = 0;
+    for paragraph in paragraphs {
+        stats.paragraphs += 1;
+        if paragraph.words() > LONG_PARAGRAPH_WORDS {
+            stats.long_paragraphs += 1;
+        }
+        for sentence in &paragraph.sentences {
+            stats.sentences += 1;
+            stats.words += sentence.words;
+            syllables += sentence.syllables;
+            if sentence.words > LONG_SENTENCE_WORDS {
+                stats.long_sentences += 1;
+            }
+            if !sentence.passives.is_empty() {
+                passive += 1;
+            }
+        }
+    }
+    if stats.sentences == 0 || stats.words == 0 {
+        return stats;
+    }
+
+    let words_per_sentence = stats.words as f64 / stats.sentences as f64;
+    let syllables_per_word = syllables as f64 / stats.words as f64;
+    let round = |x: f64| (x * 10.0).round() / 10.0;
+    stats.average_sentence_length = round(words_per_sentence);
+    if english {
+        stats.flesch_reading_ease = Some(round(206.835 - 1.015 * words_per_sentence - 84.6 * syllables_per_word));
+        stats.flesch_kincaid_grade = Some(round(0.39 * words_per_sentence + 11.8 * syllables_per_word - 15.59));
+        stats.passive_ratio = Some((passive as f64 / stats.sentences as f64 * 100.0).round() / 100.0);
+    }
+    stats
+}
+
+/// Sections of an outline in document order
+fn flatten_outline<'a>(nodes: &'a [OutlineNode], out: &mut Vec<&'a OutlineNode>) {
+    for node in nodes {
+        out.push(node);
+        flatten_outline(&node.children, out);
+    }
+}
+
+/// Analyze markdown written in `language`
+pub fn readability_report(markdown: &str, language: &str) -> ReadabilityReport {
+    let english = language.is_empty() || language.to_lowercase().starts_with("en");
+    let paragraphs = analyze_paragraphs(markdown, english);
+
+    let mut issues = Vec::new();
+    for paragraph in &paragraphs {
+        let words = paragraph.words();
+        if words > LONG_PARAGRAPH_WORDS {
+            issues.push(StyleIssue {
+                start: utf16_offset(markdown, paragraph.start),
+                end: utf16_offset(markdown, paragraph.end),
+                kind: StyleIssueKind::LongParagraph,
+                message: format!("Long paragraph ({} words); consider splitting it", words),
+            });
+        }
+        for sentence in &paragraph.sentences {
+            if sentence.words > LONG_SENTENCE_WORDS {
+                issues.push(StyleIssue {
+                    start: utf16_offset(markdown, sentence.start),
+                    end: utf16_offset(markdown, sentence.end),
+                    kind: StyleIssueKind::LongSentence,
+                    message: format!("Long sentence ({} words)", sentence.words),
+                });
+            }
+            for (start, end) in &sentence.passives {
+                issues.push(StyleIssue {
+                    start: utf16_offset(markdown, *start),
+                    end: utf16_offset(markdown, *end),
+                    kind: StyleIssueKind::PassiveVoice,
+                    message: "Passive voice".to_string(),
+                });
+            }
+        }
+    }
+    issues.sort_by_key(|issue| (issue.start, issue.end));
+
+    let outline = build_outline(markdown);
+    let mut nodes = Vec::new();
+    flatten_outline(&outline, &mut nodes);
+    let sections = nodes
+        .into_iter()
+        .map(|node| {
+            let in_section = paragraphs.iter().filter(|p| {
+                let start = utf16_offset(markdown, p.start);
+                node.heading_end <= start && start < node.end
+            });
+            SectionReadability {
+                title: node.title.clone(),
+                level: node.level,
+                label: node.label.clone(),
+                start: node.start,
+                end: node.end,
+                stats: compute_stats(in_section, english),
+            }
+        })
+        .collect();
+
+    ReadabilityReport {
+        summary: compute_stats(paragraphs.iter(), english),
+        sections,
+        issues,
+    }
+}
+
+/// Compute readability metrics of a document's current text, overall and
+/// per section, with ranges of long sentences, long paragraphs and
+/// passive constructions
+#[tauri::command]
+pub fn analyze_readability(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+) -> Result<ReadabilityReport, KorppiError> {
+    let (text, language) = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.clone()))?;
+        (current_document_text(doc, false)?, doc.meta.settings.language.clone())
+    };
+    Ok(readability_report(&text, &language))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sentences_and_syllables() {
+        let source = "Results are shown in Fig. 2, e.g. for J. Doe. Is this \"clear?\" Yes!";
+        let sentences: Vec<&str> = sentence_ranges(source).into_iter().map(|(s, e)| &source[s..e]).collect();
+        assert_eq!(sentences, vec!["Results are shown in Fig. 2, e.g. for J. Doe.", "Is this \"clear?\"", "Yes!"]);
+
+        assert_eq!(count_syllables("the"), 1);
+        assert_eq!(count_syllables("readability"), 5);
+        assert_eq!(count_syllables("make"), 1);
+        assert_eq!(count_syllables("table"), 2);
+    }
+
+    #[test]
+    fn test_readability_report() {
+        let long = vec!["word"; 35].join(" ");
+        let markdown = format!(
+            "# Methods\n\nThe samples were collected daily. We measured them.\n\n```r\nx <- 1\n```\n\n## Results\n\n{}.\n\n# Discussion\n\nIt works.\n",
+            long
+        );
+        let report = readability_report(&markdown, "en-GB");
+
+        assert_eq!(report.summary.sentences, 4);
+        assert_eq!(report.summary.paragraphs, 3);
+        assert_eq!(report.summary.long_sentences, 1);
+        assert_eq!(report.summary.passive_ratio, Some(0.25));
+        assert!(report.summary.flesch_reading_ease.is_some());
+
+        let titles: Vec<&str> = report.sections.iter().map(|s| s.title.as_str()).collect();
+        assert_eq!(titles, vec!["Methods", "Results", "Discussion"]);
+        // Methods includes its Results subsection
+        assert_eq!(report.sections[0].stats.sentences, 3);
+        assert_eq!(report.sections[2].stats.sentences, 1);
+
+        let passive = report.issues.iter().find(|i| i.kind == StyleIssueKind::PassiveVoice).unwrap();
+        assert_eq!(&markdown[passive.start..passive.end], "were collected");
+        assert!(report.issues.iter().any(|i| i.kind == StyleIssueKind::LongSentence));
+
+        let german = readability_report(&markdown, "de-DE");
+        assert_eq!(german.summary.flesch_reading_ease, None);
+        assert_eq!(german.summary.passive_ratio, None);
+        assert_eq!(german.summary.average_sentence_length, report.summary.average_sentence_length);
+    }
+}
@@ -0,0 +1,234 @@
+// src-tauri/src/review_comments.rs
+//! Review comments: discussion on a patch, such as why it was rejected.
+//!
+//! Unlike document comments they are not anchored in the text but attached
+//! to a patch by UUID, optionally to one of its hunks. They live in the
+//! `patch_review_comments` table of the history database and travel with
+//! their patch in bundles.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::State;
+use uuid::Uuid;
+
+use crate::db_utils::ensure_schema;
+use crate::document_manager::DocumentManager;
+use crate::error::KorppiError;
+
+/// A comment on a patch
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReviewComment {
+    pub uuid: String,
+    pub patch_uuid: String,
+    /// Hunk the comment is about, e.g. "42-0"; None for the whole patch
+    pub hunk_id: Option<String>,
+    pub author_id: String,
+    pub author_name: Option<String>,
+    pub content: String,
+    pub created_at: i64,
+}
+
+fn row_to_comment(row: &rusqlite::Row) -> rusqlite::Result<ReviewComment> {
+    Ok(ReviewComment {
+        uuid: row.get(0)?,
+        patch_uuid: row.get(1)?,
+        hunk_id: row.get(2)?,
+        author_id: row.get(3)?,
+        author_name: row.get(4)?,
+        content: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+/// Comments on a patch, oldest first
+pub(crate) fn load_review_comments(conn: &Connection, patch_uuid: &str) -> Result<Vec<ReviewComment>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT uuid, patch_uuid, hunk_id, author_id, author_name, content, created_at
+             FROM patch_review_comments WHERE patch_uuid = ?1 ORDER BY created_at, uuid",
+        )
+        .map_err(|e| e.to_string())?;
+    let comments = stmt
+        .query_map(params![patch_uuid], row_to_comment)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(comments)
+}
+
+/// Every comment on every patch, oldest first
+pub(crate) fn load_all_review_comments(conn: &Connection) -> Result<Vec<ReviewComment>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT uuid, patch_uuid, hunk_id, author_id, author_name, content, created_at
+             FROM patch_review_comments ORDER BY created_at, uuid",
+        )
+        .map_err(|e| e.to_string())?;
+    let comments = stmt
+        .query_map([], row_to_comment)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(comments)
+}
+
+/// Copy the comments on a patch to another history database
+///
+/// Comments are never edited, so those already in the target are kept.
+pub(crate) fn copy_review_comments(source: &Connection, target: &Connection, patch_uuid: &str) -> Result<(), String> {
+    for comment in load_review_comments(source, patch_uuid)? {
+        target
+            .execute(
+                "INSERT OR IGNORE INTO patch_review_comments (uuid, patch_uuid, hunk_id, author_id, author_name, content, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    comment.uuid,
+                    comment.patch_uuid,
+                    comment.hunk_id,
+                    comment.author_id,
+                    comment.author_name,
+                    comment.content,
+                    comment.created_at
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Comment on a patch in a document, or on one of its hunks
+#[tauri::command]
+pub fn add_review_comment(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    patch_uuid: String,
+    text: String,
+    hunk_id: Option<String>,
+    author_id: String,
+    author_name: Option<String>,
+) -> Result<ReviewComment, KorppiError> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(KorppiError::InvalidInput("Review comment is empty".to_string()));
+    }
+    let history_path = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.clone()))?;
+        doc.history_path.clone()
+    };
+
+    let conn = Connection::open(&history_path)?;
+    ensure_schema(&conn)?;
+    let known: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM patches WHERE uuid = ?1",
+        params![patch_uuid],
+        |row| row.get(0),
+    )?;
+    if !known {
+        return Err(KorppiError::PatchNotFound(patch_uuid));
+    }
+
+    let comment = ReviewComment {
+        uuid: Uuid::new_v4().to_string(),
+        patch_uuid,
+        hunk_id: hunk_id.filter(|h| !h.is_empty()),
+        author_id,
+        author_name,
+        content: text.to_string(),
+        created_at: chrono::Utc::now().timestamp_millis(),
+    };
+    conn.execute(
+        "INSERT INTO patch_review_comments (uuid, patch_uuid, hunk_id, author_id, author_name, content, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            comment.uuid,
+            comment.patch_uuid,
+            comment.hunk_id,
+            comment.author_id,
+            comment.author_name,
+            comment.content,
+            comment.created_at
+        ],
+    )?;
+    Ok(comment)
+}
+
+/// List the comments on a patch in a document, oldest first
+#[tauri::command]
+pub fn list_review_comments(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    patch_uuid: String,
+) -> Result<Vec<ReviewComment>, KorppiError> {
+    let history_path = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.clone()))?;
+        doc.history_path.clone()
+    };
+
+    let conn = Connection::open(&history_path)?;
+    ensure_schema(&conn)?;
+    Ok(load_review_comments(&conn, &patch_uuid)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comment(uuid: &str, created_at: i64) -> ReviewComment {
+        ReviewComment {
+            uuid: uuid.to_string(),
+            patch_uuid: "p1".to_string(),
+            hunk_id: Some("7-0".to_string()),
+            author_id: "bob".to_string(),
+            author_name: Some("Bob".to_string()),
+            content: "Changes the meaning of the claim".to_string(),
+            created_at,
+        }
+    }
+
+    fn insert(conn: &Connection, comment: &ReviewComment) {
+        conn.execute(
+            "INSERT INTO patch_review_comments (uuid, patch_uuid, hunk_id, author_id, author_name, content, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                comment.uuid,
+                comment.patch_uuid,
+                comment.hunk_id,
+                comment.author_id,
+                comment.author_name,
+                comment.content,
+                comment.created_at
+            ],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_copy_review_comments() {
+        let source = Connection::open_in_memory().unwrap();
+        let target = Connection::open_in_memory().unwrap();
+        ensure_schema(&source).unwrap();
+        ensure_schema(&target).unwrap();
+
+        insert(&source, &comment("c2", 20));
+        insert(&source, &comment("c1", 10));
+        insert(&target, &comment("c1", 10));
+
+        copy_review_comments(&source, &target, "p1").unwrap();
+        copy_review_comments(&source, &target, "p1").unwrap();
+
+        let copied = load_review_comments(&target, "p1").unwrap();
+        let uuids: Vec<&str> = copied.iter().map(|c| c.uuid.as_str()).collect();
+        assert_eq!(uuids, vec!["c1", "c2"]);
+        assert_eq!(copied[1], comment("c2", 20));
+        assert!(load_review_comments(&target, "p2").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,236 @@
+// src-tauri/src/review_queue.rs
+//! Review queue: what a reviewer has not seen yet.
+//!
+//! Every reviewer has a baseline, the newest patch they accepted, kept in
+//! `review_baselines` as they review. `get_review_queue` lists the patches
+//! they have not reviewed, oldest first, each with its hunks against the
+//! baseline rather than against the patch before it, so the changes a
+//! reviewer has not seen add up and are read in document order. Patches
+//! older than the baseline that were skipped are diffed against the text
+//! before them.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::db_utils::ensure_schema;
+use crate::document_manager::{author_display_name, row_to_patch, snapshot_at_patch, DocumentManager};
+use crate::error::KorppiError;
+use crate::hunk_calculator::{author_hunks, calculate_hunks_with, AuthoredHunk, DEFAULT_HUNK_COLOR};
+use crate::kmd::DocumentMeta;
+use crate::patch_log::Patch;
+
+/// An unreviewed patch with the changes to show for it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewQueueItem {
+    pub patch: Patch,
+    /// Against the baseline, or the text before the patch when it is older
+    pub hunks: Vec<AuthoredHunk>,
+    /// The hunks include changes of earlier patches the reviewer has not seen
+    pub cumulative: bool,
+}
+
+/// Unreviewed patches of a reviewer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewQueue {
+    pub reviewer_id: String,
+    /// Last patch the reviewer accepted; `None` before the first one
+    pub baseline_uuid: Option<String>,
+    pub items: Vec<ReviewQueueItem>,
+}
+
+/// Newest patch a reviewer accepted, from the reviews themselves
+fn newest_accepted(conn: &Connection, reviewer_id: &str) -> Result<Option<(i64, String)>, String> {
+    conn.query_row(
+        "SELECT p.id, p.uuid FROM patch_reviews r JOIN patches p ON p.uuid = r.patch_uuid
+         WHERE r.reviewer_id = ?1 AND r.decision = 'accepted'
+         ORDER BY p.id DESC LIMIT 1",
+        params![reviewer_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+/// Recompute a reviewer's baseline from their accepted reviews, after
+/// reviews were changed or deleted
+pub(crate) fn reset_review_baseline(conn: &Connection, reviewer_id: &str, now: i64) -> Result<(), String> {
+    match newest_accepted(conn, reviewer_id)? {
+        Some((_, uuid)) => conn.execute(
+            "INSERT OR REPLACE INTO review_baselines (reviewer_id, patch_uuid, updated_at) VALUES (?1, ?2, ?3)",
+            params![reviewer_id, uuid, now],
+        ),
+        None => conn.execute("DELETE FROM review_baselines WHERE reviewer_id = ?1", params![reviewer_id]),
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Move a reviewer's baseline to a patch they accepted, unless it is
+/// already at a newer one
+pub(crate) fn advance_review_baseline(conn: &Connection, reviewer_id: &str, patch_uuid: &str, now: i64) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO review_baselines (reviewer_id, patch_uuid, updated_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT (reviewer_id) DO UPDATE SET patch_uuid = excluded.patch_uuid, updated_at = excluded.updated_at
+         WHERE (SELECT id FROM patches WHERE uuid = excluded.patch_uuid)
+             > COALESCE((SELECT id FROM patches WHERE uuid = review_baselines.patch_uuid), 0)",
+        params![reviewer_id, patch_uuid, now],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// A reviewer's baseline patch (ID and UUID)
+///
+/// A baseline whose patch was removed by a history rewrite is recomputed.
+pub fn review_baseline(conn: &Connection, reviewer_id: &str) -> Result<Option<(i64, String)>, String> {
+    let stored: Option<(Option<i64>, String)> = conn
+        .query_row(
+            "SELECT p.id, b.patch_uuid FROM review_baselines b LEFT JOIN patches p ON p.uuid = b.patch_uuid
+             WHERE b.reviewer_id = ?1",
+            params![reviewer_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    match stored {
+        Some((Some(id), uuid)) => Ok(Some((id, uuid))),
+        Some((None, _)) => {
+            reset_review_baseline(conn, reviewer_id, chrono::Utc::now().timestamp_millis())?;
+            newest_accepted(conn, reviewer_id)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Patches by others that a reviewer has not reviewed, oldest first
+fn unreviewed_patches(conn: &Connection, reviewer_id: &str) -> Result<Vec<Patch>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT p.id, p.timestamp, p.author, p.kind, p.data, p.uuid, p.parent_uuid
+             FROM patches p
+             WHERE p.author != ?1
+             AND p.uuid IS NOT NULL
+             AND NOT EXISTS (
+                 SELECT 1 FROM patch_reviews pr WHERE pr.patch_uuid = p.uuid AND pr.reviewer_id = ?1
+             )
+             ORDER BY p.id ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let patches = stmt
+        .query_map(params![reviewer_id], row_to_patch)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(patches)
+}
+
+/// Build the review queue of a reviewer from a history database
+pub fn review_queue(conn: &Connection, meta: &DocumentMeta, reviewer_id: &str) -> Result<ReviewQueue, String> {
+    let baseline = review_baseline(conn, reviewer_id)?;
+    let baseline_text = match &baseline {
+        Some((id, _)) => snapshot_at_patch(conn, *id)?,
+        None => String::new(),
+    };
+
+    let mut items = Vec::new();
+    for patch in unreviewed_patches(conn, reviewer_id)? {
+        let newer = baseline.as_ref().is_none_or(|(id, _)| patch.id > *id);
+        let base = if newer { baseline_text.clone() } else { snapshot_at_patch(conn, patch.id - 1)? };
+        let text = snapshot_at_patch(conn, patch.id)?;
+        let author_name = author_display_name(meta, &patch.author);
+        let hunks = author_hunks(
+            calculate_hunks_with(&base, &text, &meta.settings.diff),
+            &patch,
+            &author_name,
+            DEFAULT_HUNK_COLOR,
+        );
+        items.push(ReviewQueueItem { patch, hunks, cumulative: newer });
+    }
+
+    Ok(ReviewQueue {
+        reviewer_id: reviewer_id.to_string(),
+        baseline_uuid: baseline.map(|(_, uuid)| uuid),
+        items,
+    })
+}
+
+/// Unreviewed patches of a reviewer with their changes since the last
+/// patch the reviewer accepted
+#[tauri::command]
+pub fn get_review_queue(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    reviewer_id: String,
+) -> Result<ReviewQueue, KorppiError> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager
+        .documents
+        .get(&doc_id)
+        .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+
+    let conn = Connection::open(&doc.history_path)?;
+    ensure_schema(&conn)?;
+    Ok(review_queue(&conn, &doc.meta, &reviewer_id)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert_save(conn: &Connection, uuid: &str, author: &str, snapshot: &str) {
+        conn.execute(
+            "INSERT INTO patches (timestamp, author, kind, data, uuid) VALUES (1, ?1, 'Save', ?2, ?3)",
+            params![author, serde_json::json!({ "snapshot": snapshot }).to_string(), uuid],
+        )
+        .unwrap();
+    }
+
+    fn review(conn: &Connection, uuid: &str, decision: &str) {
+        conn.execute(
+            "INSERT OR REPLACE INTO patch_reviews (patch_uuid, reviewer_id, decision, reviewed_at) VALUES (?1, 'rita', ?2, 1)",
+            params![uuid, decision],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_queue_is_cumulative_from_baseline() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+        let meta = DocumentMeta::default();
+        insert_save(&conn, "p1", "alice", "Alpha.\n\nBeta.\n\nGamma.");
+        insert_save(&conn, "p2", "alice", "Alpha one.\n\nBeta.\n\nGamma.");
+        insert_save(&conn, "p3", "bob", "Alpha one.\n\nBeta two.\n\nGamma.");
+        insert_save(&conn, "p4", "alice", "Alpha one.\n\nBeta two.\n\nGamma three.");
+
+        review(&conn, "p2", "accepted");
+        advance_review_baseline(&conn, "rita", "p2", 1).unwrap();
+        // Accepting an older patch later does not move the baseline back
+        review(&conn, "p1", "accepted");
+        advance_review_baseline(&conn, "rita", "p1", 2).unwrap();
+
+        let queue = review_queue(&conn, &meta, "rita").unwrap();
+        assert_eq!(queue.baseline_uuid.as_deref(), Some("p2"));
+        let uuids: Vec<&str> = queue.items.iter().filter_map(|i| i.patch.uuid.as_deref()).collect();
+        assert_eq!(uuids, vec!["p3", "p4"]);
+        // p4 shows p3's change too, as rita has not seen it
+        let added: Vec<&str> = queue.items[1].hunks.iter().map(|h| h.hunk.modified_text.as_str()).collect();
+        assert_eq!(added.len(), 2);
+        assert!(added[0].contains("two") && added[1].contains("three"));
+        assert!(queue.items.iter().all(|i| i.cumulative));
+
+        // Rejecting the baseline falls back to the newest other accepted patch
+        review(&conn, "p2", "rejected");
+        reset_review_baseline(&conn, "rita", 3).unwrap();
+        let queue = review_queue(&conn, &meta, "rita").unwrap();
+        assert_eq!(queue.baseline_uuid.as_deref(), Some("p1"));
+        assert_eq!(queue.items.len(), 2);
+
+        // A baseline whose patch is gone is recomputed
+        conn.execute("DELETE FROM patch_reviews", []).unwrap();
+        conn.execute("UPDATE review_baselines SET patch_uuid = 'gone'", []).unwrap();
+        assert_eq!(review_baseline(&conn, "rita").unwrap(), None);
+    }
+}
@@ -0,0 +1,417 @@
+// src-tauri/src/safe_open.rs
+//! Safe mode for KMD files and patch bundles from someone else.
+//!
+//! A KMD file is a ZIP archive with a SQLite database in it, and both can
+//! be crafted: entry names that climb out of the extraction directory,
+//! entries that inflate to gigabytes, and triggers or views in the history
+//! that run SQL whenever Korppi writes to it. A file that is not among the
+//! recent documents opens in safe mode, which
+//!
+//! - checks every entry name with `is_path_safe` and every entry size
+//!   against a limit for its kind, and refuses archives that compress too well;
+//! - opens the history with SQLite's defensive settings (no schema writes,
+//!   no untrusted functions, a page limit), read-only to inspect it;
+//! - drops the triggers and views Korppi does not create, scripts in SVG
+//!   and HTML assets and author profiles with colors or avatars that are
+//!   not what Korppi writes.
+//!
+//! `check_document_trust` reports all of this before the file is opened,
+//! for the trust prompt. Reading an unknown file without opening it, as
+//! workspace search and activity do, goes through safe mode as well. Patch
+//! bundles and documents whose patches are imported always get the archive
+//! and history checks, as they always come from someone else.
+
+use base64::Engine;
+use regex::Regex;
+use rusqlite::config::DbConfig;
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+use crate::db_utils::create_snapshot_states_view;
+use crate::document_manager::load_recent_documents;
+use crate::error::KorppiError;
+use crate::kmd::{is_path_safe, AuthorProfile, DocumentMeta, SECTIONS_DIR, UPDATES_DIR};
+use crate::patch_schema::{is_hex_color, lint_patches};
+use crate::profile::DEFAULT_AUTHOR_COLOR;
+
+/// Most entries an archive may have
+const MAX_ENTRIES: usize = 100_000;
+/// Largest JSON entry: format, metadata, manifest and author profiles
+const MAX_JSON_SIZE: u64 = 8 << 20;
+/// Largest Yjs state or update entry
+const MAX_STATE_SIZE: u64 = 256 << 20;
+/// Largest history database
+const MAX_HISTORY_SIZE: u64 = 1 << 30;
+/// Largest asset
+const MAX_ASSET_SIZE: u64 = 100 << 20;
+/// Largest size of all entries together
+const MAX_TOTAL_SIZE: u64 = 4 << 30;
+/// Highest ratio of size to compressed size, for entries above 1 MiB
+const MAX_COMPRESSION_RATIO: u64 = 1000;
+
+/// Views the history schema creates itself
+const SCHEMA_VIEWS: [&str; 1] = ["snapshot_states"];
+
+/// Asset kinds a browser runs scripts in
+const SCRIPT_ASSET_EXTENSIONS: [&str; 5] = ["html", "htm", "xhtml", "js", "mjs"];
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// What opening a file would mean, for the trust prompt
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct TrustReport {
+    pub path: String,
+    pub title: String,
+    pub authors: Vec<String>,
+    /// The file is among the recent documents and opens normally
+    pub trusted: bool,
+    /// Why the file cannot be opened, even in safe mode
+    pub problems: Vec<String>,
+    /// What safe mode leaves out
+    pub stripped: Vec<String>,
+    /// Entries Korppi does not read and patches with malformed data
+    pub warnings: Vec<String>,
+}
+
+/// Largest size of an archive entry, or `None` for entries Korppi does not read
+fn entry_limit(name: &str) -> Option<u64> {
+    match name {
+        "format.json" | "meta.json" | "bundle.json" => Some(MAX_JSON_SIZE),
+        "state.yjs" => Some(MAX_STATE_SIZE),
+        "history.sqlite" => Some(MAX_HISTORY_SIZE),
+        "payload.enc" => Some(MAX_HISTORY_SIZE + MAX_STATE_SIZE),
+        _ if name.starts_with("authors/") && name.ends_with(".json") => Some(MAX_JSON_SIZE),
+        _ if name.starts_with("assets/") => Some(MAX_ASSET_SIZE),
+        _ if name.starts_with(UPDATES_DIR) => Some(MAX_STATE_SIZE),
+        _ if name.starts_with(SECTIONS_DIR) && name.ends_with("/state.yjs") => Some(MAX_STATE_SIZE),
+        _ if name.starts_with(SECTIONS_DIR) && name.ends_with("/history.sqlite") => Some(MAX_HISTORY_SIZE),
+        _ => None,
+    }
+}
+
+/// Check the entries of an archive from someone else: names that stay in
+/// the directory they are extracted to, sizes within the limit of their
+/// kind and nothing that inflates suspiciously
+///
+/// Returns the entries Korppi does not read, which are left in the archive.
+pub fn check_archive<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Vec<String>, String> {
+    if archive.len() > MAX_ENTRIES {
+        return Err(format!("Archive has {} entries, more than {}", archive.len(), MAX_ENTRIES));
+    }
+    let mut unknown = Vec::new();
+    let mut total: u64 = 0;
+    for i in 0..archive.len() {
+        let entry = archive.by_index_raw(i).map_err(|e| e.to_string())?;
+        let name = entry.name().to_string();
+        if !is_path_safe(&name) || name.contains('\\') || name.contains('\0') {
+            return Err(format!("Unsafe entry name in archive: {:?}", name));
+        }
+        if entry.is_dir() {
+            continue;
+        }
+        let size = entry.size();
+        match entry_limit(&name) {
+            Some(limit) if size > limit => {
+                return Err(format!("Archive entry {} is {} bytes, more than {}", name, size, limit));
+            }
+            Some(_) => {}
+            None => unknown.push(name.clone()),
+        }
+        if size > 1 << 20 && size / entry.compressed_size().max(1) > MAX_COMPRESSION_RATIO {
+            return Err(format!("Archive entry {} is compressed suspiciously well", name));
+        }
+        total = total.saturating_add(size);
+    }
+    if total > MAX_TOTAL_SIZE {
+        return Err(format!("Archive is {} bytes uncompressed, more than {}", total, MAX_TOTAL_SIZE));
+    }
+    Ok(unknown)
+}
+
+/// Open a history database from someone else with SQLite's defensive
+/// settings
+///
+/// The connection cannot write the schema directly, lets triggers and
+/// views call no function that is not known to be harmless and cannot
+/// grow the file past the history size limit. An inspecting connection
+/// (`writable` false) is read-only and `query_only` as well.
+pub fn open_untrusted_history(path: &Path, writable: bool) -> Result<Connection, String> {
+    let flags = if writable { OpenFlags::SQLITE_OPEN_READ_WRITE } else { OpenFlags::SQLITE_OPEN_READ_ONLY };
+    let invalid = |e: rusqlite::Error| format!("Invalid history database: {}", e);
+    let conn = Connection::open_with_flags(path, flags | OpenFlags::SQLITE_OPEN_NO_MUTEX).map_err(invalid)?;
+    conn.set_db_config(DbConfig::SQLITE_DBCONFIG_DEFENSIVE, true).map_err(invalid)?;
+    conn.pragma_update(None, "trusted_schema", false).map_err(invalid)?;
+    if !writable {
+        conn.pragma_update(None, "query_only", true).map_err(invalid)?;
+    }
+
+    let page_size: u64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0)).map_err(invalid)?;
+    let page_count: u64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0)).map_err(invalid)?;
+    let max_pages = MAX_HISTORY_SIZE / page_size.max(512);
+    if page_count > max_pages {
+        return Err(format!("History database has {} pages, more than {}", page_count, max_pages));
+    }
+    if writable {
+        conn.query_row(&format!("PRAGMA max_page_count = {}", max_pages), [], |_| Ok(()))
+            .map_err(invalid)?;
+    }
+    let check: String = conn.query_row("PRAGMA quick_check(1)", [], |row| row.get(0)).map_err(invalid)?;
+    if check != "ok" {
+        return Err(format!("History database is damaged: {}", check));
+    }
+    Ok(conn)
+}
+
+/// Triggers and views of a history, as (type, name); the schema's own
+/// views are included
+fn scriptable_objects(conn: &Connection) -> Result<Vec<(String, String)>, String> {
+    let mut stmt = conn
+        .prepare("SELECT type, name FROM sqlite_master WHERE type IN ('trigger', 'view') ORDER BY name")
+        .map_err(|e| e.to_string())?;
+    let objects = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(objects)
+}
+
+fn is_foreign(kind: &str, name: &str) -> bool {
+    kind == "trigger" || !SCHEMA_VIEWS.contains(&name)
+}
+
+/// What safe mode would drop from a history and the patches it would keep
+/// although their data is malformed, without changing the file
+pub fn inspect_history(path: &Path, entry: &str) -> Result<(Vec<String>, Vec<String>), String> {
+    let conn = open_untrusted_history(path, false)?;
+    let stripped = scriptable_objects(&conn)?
+        .into_iter()
+        .filter(|(kind, name)| is_foreign(kind, name))
+        .map(|(kind, name)| format!("{} {} in {}", kind, name, entry))
+        .collect();
+    let has_patches: bool = conn
+        .query_row("SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'patches')", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let mut warnings = Vec::new();
+    if has_patches {
+        let issues = lint_patches(&conn).unwrap_or_default();
+        if !issues.is_empty() {
+            warnings.push(format!("{} patch(es) in {} have malformed data", issues.len(), entry));
+        }
+    }
+    Ok((stripped, warnings))
+}
+
+/// Drop every trigger and view of a history from someone else, then
+/// recreate the schema's own views; returns what was foreign
+pub fn harden_history(path: &Path, entry: &str) -> Result<Vec<String>, String> {
+    let conn = open_untrusted_history(path, true)?;
+    let objects = scriptable_objects(&conn)?;
+    let mut stripped = Vec::new();
+    for (kind, name) in &objects {
+        let statement = if kind == "trigger" { "DROP TRIGGER" } else { "DROP VIEW" };
+        conn.execute_batch(&format!("{} IF EXISTS \"{}\"", statement, name.replace('"', "\"\"")))
+            .map_err(|e| e.to_string())?;
+        if is_foreign(kind, name) {
+            stripped.push(format!("{} {} in {}", kind, name, entry));
+        }
+    }
+    if objects.iter().any(|(kind, name)| !is_foreign(kind, name)) {
+        create_snapshot_states_view(&conn)?;
+    }
+    Ok(stripped)
+}
+
+/// Whether an asset can run scripts when it is opened: HTML and
+/// JavaScript files, and SVG images with scripts or event handlers
+pub fn is_scriptable_asset(name: &str, data: &[u8]) -> bool {
+    let extension = Path::new(name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    if SCRIPT_ASSET_EXTENSIONS.contains(&extension.as_str()) {
+        return true;
+    }
+    if extension != "svg" {
+        return false;
+    }
+    let text = String::from_utf8_lossy(data).to_ascii_lowercase();
+    let handler = Regex::new(r"\son[a-z]+\s*=").unwrap();
+    text.contains("<script") || text.contains("javascript:") || text.contains("<foreignobject") || handler.is_match(&text)
+}
+
+/// An author profile from someone else as Korppi would write it: a hex
+/// color and a PNG avatar, or none
+///
+/// Returns the profile to keep, or `None` when it is not one, and what was
+/// left out of it.
+pub fn sanitize_author_profile(entry: &str, data: &[u8]) -> (Option<Vec<u8>>, Vec<String>) {
+    let Ok(mut profile) = serde_json::from_slice::<AuthorProfile>(data) else {
+        return (None, vec![format!("{}, which is not an author profile", entry)]);
+    };
+    let mut stripped = Vec::new();
+    if !is_hex_color(&profile.color) {
+        profile.color = DEFAULT_AUTHOR_COLOR.to_string();
+        stripped.push(format!("color of {}", entry));
+    }
+    let is_png = |avatar: &str| {
+        base64::engine::general_purpose::STANDARD
+            .decode(avatar)
+            .is_ok_and(|image| image.starts_with(PNG_SIGNATURE))
+    };
+    if profile.avatar_base64.as_deref().is_some_and(|avatar| !is_png(avatar)) {
+        profile.avatar_base64 = None;
+        stripped.push(format!("avatar of {}", entry));
+    }
+    (serde_json::to_vec_pretty(&profile).ok(), stripped)
+}
+
+/// Whether a file was opened on this computer before
+pub fn is_trusted(path: &Path) -> bool {
+    let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+    let path = canonical(path);
+    load_recent_documents()
+        .unwrap_or_default()
+        .iter()
+        .any(|recent| canonical(&recent.path) == path)
+}
+
+fn read_archive_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Option<Vec<u8>> {
+    let mut entry = archive.by_name(name).ok()?;
+    let mut data = Vec::new();
+    entry.read_to_end(&mut data).ok()?;
+    Some(data)
+}
+
+/// Look through a KMD file for what safe mode would refuse or leave out
+pub fn inspect_document(path: &Path) -> Result<TrustReport, String> {
+    let mut report = TrustReport {
+        path: path.to_string_lossy().to_string(),
+        trusted: is_trusted(path),
+        ..Default::default()
+    };
+    let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| format!("Invalid ZIP archive: {}", e))?;
+    match check_archive(&mut archive) {
+        Ok(unknown) => report.warnings.extend(unknown.into_iter().map(|name| format!("Unknown entry {}", name))),
+        Err(problem) => {
+            report.problems.push(problem);
+            return Ok(report);
+        }
+    }
+
+    match read_archive_entry(&mut archive, "meta.json").map(|data| serde_json::from_slice::<DocumentMeta>(&data)) {
+        Some(Ok(meta)) => {
+            report.title = meta.title;
+            report.authors = meta.authors.into_iter().map(|a| a.name).collect();
+        }
+        _ => report.problems.push("Missing or invalid meta.json".to_string()),
+    }
+
+    let temp_dir = tempfile::TempDir::new().map_err(|e| e.to_string())?;
+    let names: Vec<String> = archive.file_names().map(|n| n.to_string()).collect();
+    for name in names {
+        if name == "history.sqlite" || (name.starts_with(SECTIONS_DIR) && name.ends_with("/history.sqlite")) {
+            let Some(data) = read_archive_entry(&mut archive, &name) else { continue };
+            let history: PathBuf = temp_dir.path().join("history.sqlite");
+            std::fs::write(&history, data).map_err(|e| e.to_string())?;
+            match inspect_history(&history, &name) {
+                Ok((stripped, warnings)) => {
+                    report.stripped.extend(stripped);
+                    report.warnings.extend(warnings);
+                }
+                Err(problem) => report.problems.push(problem),
+            }
+            std::fs::remove_file(&history).ok();
+        } else if name.starts_with("assets/") {
+            if read_archive_entry(&mut archive, &name).is_some_and(|data| is_scriptable_asset(&name, &data)) {
+                report.stripped.push(name);
+            }
+        } else if name.starts_with("authors/") && name.ends_with(".json") {
+            if let Some(data) = read_archive_entry(&mut archive, &name) {
+                report.stripped.extend(sanitize_author_profile(&name, &data).1);
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Whether a KMD file opens normally and, if not, what safe mode would
+/// refuse or leave out of it
+#[tauri::command]
+pub fn check_document_trust(path: String) -> Result<TrustReport, KorppiError> {
+    let path = PathBuf::from(path);
+    if !path.exists() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("File not found: {:?}", path)).into());
+    }
+    Ok(inspect_document(&path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db_utils::ensure_schema;
+    use std::io::{Cursor, Write};
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    fn archive_of(entries: &[(&str, &[u8])]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        ZipArchive::new(Cursor::new(zip.finish().unwrap().into_inner())).unwrap()
+    }
+
+    #[test]
+    fn test_safe_mode_checks_and_strips() {
+        let unknown = check_archive(&mut archive_of(&[("meta.json", b"{}"), ("notes.txt", b"x")])).unwrap();
+        assert_eq!(unknown, vec!["notes.txt"]);
+        assert!(check_archive(&mut archive_of(&[("../evil.json", b"{}")])).is_err());
+        assert!(check_archive(&mut archive_of(&[("assets\\..\\x.png", b"")])).is_err());
+        assert!(check_archive(&mut archive_of(&[("meta.json", &vec![b' '; 9 << 20])])).is_err());
+        let bomb = vec![0u8; 20 << 20];
+        assert!(check_archive(&mut archive_of(&[("assets/zeros.bin", &bomb)])).is_err());
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("history.sqlite");
+        {
+            let conn = Connection::open(&path).unwrap();
+            ensure_schema(&conn).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE stolen (data TEXT);
+                 CREATE TRIGGER leak AFTER INSERT ON patches BEGIN INSERT INTO stolen VALUES (new.data); END;
+                 INSERT INTO patches (timestamp, author, kind, data, uuid) VALUES (1, 'a', 'Save', 'not json', 'p1');",
+            )
+            .unwrap();
+        }
+        let (stripped, warnings) = inspect_history(&path, "history.sqlite").unwrap();
+        assert_eq!(stripped, vec!["trigger leak in history.sqlite"]);
+        assert_eq!(warnings.len(), 1);
+        // Inspecting changes nothing
+        assert!(open_untrusted_history(&path, false).unwrap().execute("DELETE FROM patches", []).is_err());
+
+        assert_eq!(harden_history(&path, "history.sqlite").unwrap(), stripped);
+        let conn = Connection::open(&path).unwrap();
+        let objects = scriptable_objects(&conn).unwrap();
+        assert_eq!(objects, vec![("view".to_string(), "snapshot_states".to_string())]);
+
+        assert!(is_scriptable_asset("plot.svg", b"<svg onload=\"alert(1)\"></svg>"));
+        assert!(is_scriptable_asset("page.HTML", b""));
+        assert!(!is_scriptable_asset("plot.svg", b"<svg><path d=\"M0 0\"/></svg>"));
+        assert!(!is_scriptable_asset("photo.png", b"<script>"));
+
+        let profile = br#"{"id":"a","name":"Eve","color":"red;background:url(x)","avatar_base64":"PHN2Zz4="}"#;
+        let (kept, stripped) = sanitize_author_profile("authors/a.json", profile);
+        let kept: AuthorProfile = serde_json::from_slice(&kept.unwrap()).unwrap();
+        assert_eq!(kept.color, DEFAULT_AUTHOR_COLOR);
+        assert!(kept.avatar_base64.is_none());
+        assert_eq!(stripped.len(), 2);
+        assert!(sanitize_author_profile("authors/b.json", b"[]").0.is_none());
+    }
+}
@@ -0,0 +1,386 @@
+// src-tauri/src/section_locks.rs
+//! Advisory section locks.
+//!
+//! Collaborators splitting work ("you take section 3, I take section 4")
+//! can lock a section by its `{#sec:label}`. Locks live in the
+//! `section_locks` table of the history database, so they travel inside the
+//! KMD file and in patch bundles. Releasing a lock keeps its row with
+//! `released_at` set, so the release propagates like the lock did; the most
+//! recently updated row wins when two copies meet.
+//!
+//! Locks never stop anyone from editing. Imports report incoming patches
+//! that change a section someone else holds.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, State};
+
+use crate::db_utils::ensure_schema;
+use crate::document_manager::{current_document_text, DocumentManager};
+use crate::history_rewrite::load_patches;
+use crate::hunk_calculator::calculate_hunks;
+use crate::outline::{build_outline, find_section, OutlineNode};
+use crate::profile::load_saved_profile;
+
+/// Event emitted when imported patches touch sections locked by others
+pub const LOCK_WARNINGS_EVENT: &str = "section-lock-warnings";
+
+/// A lock on a section
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SectionLock {
+    /// Section label, e.g. "sec:methods"
+    pub label: String,
+    pub holder_id: String,
+    pub holder_name: String,
+    pub locked_at: i64,
+    /// Set once the lock is released
+    pub released_at: Option<i64>,
+}
+
+/// An imported patch that changed a section locked by someone else
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SectionLockWarning {
+    pub label: String,
+    pub holder_name: String,
+    pub patch_uuid: String,
+    pub author: String,
+}
+
+pub fn ensure_lock_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS section_locks (
+            label       TEXT PRIMARY KEY,
+            holder_id   TEXT    NOT NULL,
+            holder_name TEXT    NOT NULL,
+            locked_at   INTEGER NOT NULL,
+            released_at INTEGER,
+            updated_at  INTEGER NOT NULL
+        );
+        "#,
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn row_to_lock(row: &rusqlite::Row) -> rusqlite::Result<SectionLock> {
+    Ok(SectionLock {
+        label: row.get(0)?,
+        holder_id: row.get(1)?,
+        holder_name: row.get(2)?,
+        locked_at: row.get(3)?,
+        released_at: row.get(4)?,
+    })
+}
+
+/// Locks currently held, by label
+pub fn active_locks(conn: &Connection) -> Result<Vec<SectionLock>, String> {
+    ensure_lock_schema(conn)?;
+    let mut stmt = conn
+        .prepare("SELECT label, holder_id, holder_name, locked_at, released_at FROM section_locks WHERE released_at IS NULL ORDER BY label")
+        .map_err(|e| e.to_string())?;
+    let locks = stmt
+        .query_map([], row_to_lock)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(locks)
+}
+
+/// Lock a section for a holder; fails if someone else holds it
+pub fn lock(conn: &Connection, label: &str, holder_id: &str, holder_name: &str, now: i64) -> Result<SectionLock, String> {
+    ensure_lock_schema(conn)?;
+    let existing = conn
+        .query_row(
+            "SELECT label, holder_id, holder_name, locked_at, released_at FROM section_locks WHERE label = ?1 AND released_at IS NULL",
+            params![label],
+            row_to_lock,
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    if let Some(existing) = existing {
+        if existing.holder_id != holder_id {
+            return Err(format!("Section {} is locked by {}", label, existing.holder_name));
+        }
+        return Ok(existing);
+    }
+
+    conn.execute(
+        "INSERT OR REPLACE INTO section_locks (label, holder_id, holder_name, locked_at, released_at, updated_at) VALUES (?1, ?2, ?3, ?4, NULL, ?4)",
+        params![label, holder_id, holder_name, now],
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok(SectionLock {
+        label: label.to_string(),
+        holder_id: holder_id.to_string(),
+        holder_name: holder_name.to_string(),
+        locked_at: now,
+        released_at: None,
+    })
+}
+
+/// Release a lock; only its holder may, unless `force` is set
+pub fn unlock(conn: &Connection, label: &str, holder_id: &str, force: bool, now: i64) -> Result<(), String> {
+    ensure_lock_schema(conn)?;
+    let holder: Option<(String, String)> = conn
+        .query_row(
+            "SELECT holder_id, holder_name FROM section_locks WHERE label = ?1 AND released_at IS NULL",
+            params![label],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    match holder {
+        None => Err(format!("Section {} is not locked", label)),
+        Some((id, name)) if id != holder_id && !force => {
+            Err(format!("Section {} is locked by {}", label, name))
+        }
+        Some(_) => {
+            conn.execute(
+                "UPDATE section_locks SET released_at = ?1, updated_at = ?1 WHERE label = ?2",
+                params![now, label],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(())
+        }
+    }
+}
+
+/// Copy lock rows between history databases, keeping the newer row
+pub fn copy_locks(source: &Connection, target: &Connection) -> Result<(), String> {
+    ensure_lock_schema(source)?;
+    ensure_lock_schema(target)?;
+    let mut stmt = source
+        .prepare("SELECT label, holder_id, holder_name, locked_at, released_at, updated_at FROM section_locks")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| Ok((row_to_lock(row)?, row.get::<_, i64>(5)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    for (lock, updated_at) in rows {
+        target
+            .execute(
+                "INSERT INTO section_locks (label, holder_id, holder_name, locked_at, released_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT(label) DO UPDATE SET
+                    holder_id = excluded.holder_id,
+                    holder_name = excluded.holder_name,
+                    locked_at = excluded.locked_at,
+                    released_at = excluded.released_at,
+                    updated_at = excluded.updated_at
+                 WHERE excluded.updated_at > section_locks.updated_at",
+                params![lock.label, lock.holder_id, lock.holder_name, lock.locked_at, lock.released_at, updated_at],
+            )
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Labelled sections of an outline, nested ones included
+fn labelled_sections(outline: &[OutlineNode]) -> Vec<&OutlineNode> {
+    outline
+        .iter()
+        .flat_map(|node| {
+            let mut nodes = if node.label.is_some() { vec![node] } else { Vec::new() };
+            nodes.extend(labelled_sections(&node.children));
+            nodes
+        })
+        .collect()
+}
+
+/// Imported Save patches that change sections locked by someone else
+///
+/// Each patch is diffed against its parent's snapshot, and its hunks are
+/// matched against the sections of that parent text.
+pub fn lock_warnings(conn: &Connection, imported: &HashSet<String>) -> Result<Vec<SectionLockWarning>, String> {
+    let locks = active_locks(conn)?;
+    if locks.is_empty() || imported.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let patches = load_patches(conn)?;
+    let snapshots: HashMap<&str, &str> = patches
+        .iter()
+        .filter(|p| p.kind == "Save")
+        .filter_map(|p| Some((p.uuid.as_deref()?, p.data.get("snapshot")?.as_str()?)))
+        .collect();
+
+    let mut warnings = Vec::new();
+    for patch in &patches {
+        let Some(uuid) = patch.uuid.as_deref().filter(|uuid| imported.contains(*uuid)) else {
+            continue;
+        };
+        let (Some(snapshot), Some(base)) = (
+            snapshots.get(uuid),
+            patch.parent_uuid.as_deref().and_then(|parent| snapshots.get(parent)),
+        ) else {
+            continue;
+        };
+
+        let hunks = calculate_hunks(base, snapshot);
+        let outline = build_outline(base);
+        for section in labelled_sections(&outline) {
+            let label = section.label.as_deref().unwrap_or_default();
+            let Some(lock) = locks.iter().find(|l| l.label == label && l.holder_id != patch.author) else {
+                continue;
+            };
+            let touched = hunks.iter().any(|h| {
+                h.base_start < section.end && (h.base_end > section.start || h.base_start >= section.start)
+            });
+            if touched {
+                warnings.push(SectionLockWarning {
+                    label: label.to_string(),
+                    holder_name: lock.holder_name.clone(),
+                    patch_uuid: uuid.to_string(),
+                    author: patch.author.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(warnings)
+}
+
+/// Report lock warnings to the frontend
+pub fn emit_lock_warnings(app: &AppHandle, doc_id: &str, warnings: &[SectionLockWarning]) {
+    if warnings.is_empty() {
+        return;
+    }
+    crate::session_log::log_event(app, "section_lock_warnings", Some(doc_id), serde_json::json!({
+        "warnings": warnings.len(),
+    }));
+    if let Err(e) = app.emit(LOCK_WARNINGS_EVENT, warnings) {
+        log::warn!("Failed to emit section lock warnings: {}", e);
+    }
+}
+
+/// Check an import into a history database for patches touching locked
+/// sections, reporting failures to the log
+pub fn check_after_import(app: &AppHandle, doc_id: &str, history_path: &Path, imported: &HashSet<String>) {
+    let warnings = Connection::open(history_path)
+        .map_err(|e| e.to_string())
+        .and_then(|conn| lock_warnings(&conn, imported));
+    match warnings {
+        Ok(warnings) => emit_lock_warnings(app, doc_id, &warnings),
+        Err(e) => log::warn!("Section lock check after import into {} failed: {}", doc_id, e),
+    }
+}
+
+/// ID and name of the local user
+fn current_user() -> (String, String) {
+    load_saved_profile()
+        .ok()
+        .flatten()
+        .map(|p| (p.id, p.name))
+        .unwrap_or_else(|| ("unknown".to_string(), "Unknown user".to_string()))
+}
+
+/// Lock a section of a document for the local user
+#[tauri::command]
+pub fn lock_section(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    label: String,
+) -> Result<SectionLock, String> {
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get_mut(&doc_id)
+        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+
+    let outline = build_outline(&current_document_text(doc, false)?);
+    if find_section(&outline, &label).is_none() {
+        return Err(format!("No section labelled {}", label));
+    }
+
+    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    ensure_schema(&conn)?;
+    let (holder_id, holder_name) = current_user();
+    let section_lock = lock(&conn, &label, &holder_id, &holder_name, chrono::Utc::now().timestamp_millis())?;
+    doc.handle.is_modified = true;
+    Ok(section_lock)
+}
+
+/// Release a section lock; `force` releases a lock held by someone else
+#[tauri::command]
+pub fn unlock_section(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    label: String,
+    force: Option<bool>,
+) -> Result<(), String> {
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get_mut(&doc_id)
+        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+
+    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    ensure_schema(&conn)?;
+    let (holder_id, _) = current_user();
+    unlock(&conn, &label, &holder_id, force.unwrap_or(false), chrono::Utc::now().timestamp_millis())?;
+    doc.handle.is_modified = true;
+    Ok(())
+}
+
+/// List the section locks currently held in a document
+#[tauri::command]
+pub fn list_section_locks(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+) -> Result<Vec<SectionLock>, String> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = manager.documents.get(&doc_id)
+        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+
+    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    active_locks(&conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert_save(conn: &Connection, uuid: &str, parent: Option<&str>, author: &str, snapshot: &str) {
+        conn.execute(
+            "INSERT INTO patches (timestamp, author, kind, data, uuid, parent_uuid) VALUES (1, ?1, 'Save', ?2, ?3, ?4)",
+            params![author, serde_json::json!({ "snapshot": snapshot }).to_string(), uuid, parent],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_locks_and_import_warnings() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+
+        lock(&conn, "sec:methods", "alice", "Alice", 1).unwrap();
+        assert!(lock(&conn, "sec:methods", "bob", "Bob", 2).unwrap_err().contains("Alice"));
+        assert!(unlock(&conn, "sec:methods", "bob", false, 2).is_err());
+        assert_eq!(active_locks(&conn).unwrap().len(), 1);
+
+        let base = "# Intro {#sec:intro}\n\nHello.\n\n# Methods {#sec:methods}\n\nWe measured.\n";
+        insert_save(&conn, "p1", None, "alice", base);
+        insert_save(&conn, "p2", Some("p1"), "bob", &base.replace("We measured.", "We counted."));
+        insert_save(&conn, "p3", Some("p1"), "bob", &base.replace("Hello.", "Hi."));
+
+        let imported: HashSet<String> = ["p2".to_string(), "p3".to_string()].into();
+        let warnings = lock_warnings(&conn, &imported).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].label, "sec:methods");
+        assert_eq!(warnings[0].patch_uuid, "p2");
+        assert_eq!(warnings[0].holder_name, "Alice");
+
+        // Releases travel to other copies like locks do
+        let other = Connection::open_in_memory().unwrap();
+        copy_locks(&conn, &other).unwrap();
+        assert_eq!(active_locks(&other).unwrap().len(), 1);
+        unlock(&conn, "sec:methods", "alice", false, 3).unwrap();
+        copy_locks(&conn, &other).unwrap();
+        assert!(active_locks(&other).unwrap().is_empty());
+        assert!(lock_warnings(&conn, &imported).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+// src-tauri/src/sections.rs
+//! Multi-file projects.
+//!
+//! A project is a document whose text lives in ordered child sections, each
+//! with its own Yjs state and history database. The order and titles are kept
+//! in `DocumentMeta::sections`; the section data in `DocumentState::sections`.
+//! Exports concatenate the sections so cross-references are numbered
+//! continuously across the whole project.
+
+use std::fs;
+use std::sync::Mutex;
+use tauri::State;
+use uuid::Uuid;
+
+use crate::document_manager::{create_document_temp_dir, DocumentManager, DocumentState, SectionState};
+use crate::kmd::{write_docx_export, SectionRef};
+use crate::yjs_store::document_text;
+
+/// Reorder sections to match a list of section IDs
+///
+/// The list must name every section exactly once.
+pub fn reorder(sections: &[SectionRef], order: &[String]) -> Result<Vec<SectionRef>, String> {
+    if order.len() != sections.len() {
+        return Err("Section order must list every section exactly once".to_string());
+    }
+    order
+        .iter()
+        .enumerate()
+        .map(|(i, id)| {
+            if order[..i].contains(id) {
+                return Err(format!("Duplicate section in order: {}", id));
+            }
+            sections
+                .iter()
+                .find(|s| &s.id == id)
+                .cloned()
+                .ok_or_else(|| format!("Section not found: {}", id))
+        })
+        .collect()
+}
+
+/// Join section texts into one markdown document
+pub fn join_sections(texts: &[String]) -> String {
+    texts
+        .iter()
+        .map(|t| t.trim_matches('\n'))
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Markdown of a whole project, sections in order; single-buffer documents
+/// return their own text
+pub(crate) fn project_markdown(doc: &DocumentState) -> Result<String, String> {
+    if doc.meta.sections.is_empty() {
+        return crate::document_manager::current_document_text(doc, false);
+    }
+
+    let mut texts = Vec::new();
+    for section in &doc.meta.sections {
+        let state = doc
+            .sections
+            .get(&section.id)
+            .map(|s| s.yjs_state.as_slice())
+            .unwrap_or_default();
+        if !state.is_empty() {
+            texts.push(document_text(&[state], false)?);
+        }
+    }
+    Ok(join_sections(&texts))
+}
+
+fn get_doc<'a>(manager: &'a mut DocumentManager, id: &str) -> Result<&'a mut DocumentState, String> {
+    manager.documents.get_mut(id)
+        .ok_or_else(|| format!("Document not found: {}", id))
+}
+
+/// List the sections of a project in order
+#[tauri::command]
+pub fn list_sections(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+) -> Result<Vec<SectionRef>, String> {
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    Ok(get_doc(&mut manager, &id)?.meta.sections.clone())
+}
+
+/// Add an empty section, at the end unless a position is given
+#[tauri::command]
+pub fn add_section(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    title: String,
+    position: Option<usize>,
+) -> Result<SectionRef, String> {
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = get_doc(&mut manager, &id)?;
+
+    let section = SectionRef {
+        id: Uuid::new_v4().to_string(),
+        title,
+    };
+    let section_dir = create_document_temp_dir(&id)?.join("sections").join(&section.id);
+    fs::create_dir_all(&section_dir).map_err(|e| e.to_string())?;
+
+    doc.sections.insert(section.id.clone(), SectionState {
+        yjs_state: Vec::new(),
+        history_path: section_dir.join("history.sqlite"),
+    });
+    let position = position.unwrap_or(doc.meta.sections.len()).min(doc.meta.sections.len());
+    doc.meta.sections.insert(position, section.clone());
+    doc.handle.is_modified = true;
+
+    Ok(section)
+}
+
+/// Reorder the sections of a project
+#[tauri::command]
+pub fn reorder_sections(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    order: Vec<String>,
+) -> Result<Vec<SectionRef>, String> {
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = get_doc(&mut manager, &id)?;
+
+    doc.meta.sections = reorder(&doc.meta.sections, &order)?;
+    doc.handle.is_modified = true;
+
+    Ok(doc.meta.sections.clone())
+}
+
+/// Remove a section together with its state and history
+#[tauri::command]
+pub fn remove_section(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    section_id: String,
+) -> Result<(), String> {
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = get_doc(&mut manager, &id)?;
+
+    let index = doc.meta.sections.iter().position(|s| s.id == section_id)
+        .ok_or_else(|| format!("Section not found: {}", section_id))?;
+    doc.meta.sections.remove(index);
+    if let Some(section) = doc.sections.remove(&section_id) {
+        if let Some(dir) = section.history_path.parent() {
+            fs::remove_dir_all(dir).ok();
+        }
+    }
+    doc.handle.is_modified = true;
+
+    Ok(())
+}
+
+/// Get the Yjs state of a section
+#[tauri::command]
+pub fn get_section_state(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    section_id: String,
+) -> Result<Vec<u8>, String> {
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = get_doc(&mut manager, &id)?;
+    doc.sections.get(&section_id)
+        .map(|s| s.yjs_state.clone())
+        .ok_or_else(|| format!("Section not found: {}", section_id))
+}
+
+/// Update the Yjs state of a section
+#[tauri::command]
+pub fn update_section_state(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    section_id: String,
+    state: Vec<u8>,
+) -> Result<(), String> {
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    let doc = get_doc(&mut manager, &id)?;
+    if doc.handle.read_only {
+        return Err("Document is open read-only".to_string());
+    }
+    let section = doc.sections.get_mut(&section_id)
+        .ok_or_else(|| format!("Section not found: {}", section_id))?;
+    section.yjs_state = state;
+    doc.handle.is_modified = true;
+    Ok(())
+}
+
+/// Get the markdown of a whole project, sections concatenated in order
+#[tauri::command]
+pub fn get_project_markdown(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+) -> Result<String, String> {
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    project_markdown(get_doc(&mut manager, &id)?)
+}
+
+/// Export a whole project as DOCX with continuous cross-reference numbering
+#[tauri::command]
+pub fn export_project_docx(
+    manager: State<'_, Mutex<DocumentManager>>,
+    id: String,
+    path: String,
+) -> Result<(), String> {
+    let (content, meta) = {
+        let mut manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = get_doc(&mut manager, &id)?;
+        (project_markdown(doc)?, doc.meta.clone())
+    };
+    Ok(write_docx_export(path, content, None, Some(meta.settings.clone()), Some(&meta))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(id: &str) -> SectionRef {
+        SectionRef { id: id.to_string(), title: id.to_uppercase() }
+    }
+
+    #[test]
+    fn test_reorder_sections() {
+        let sections = vec![section("a"), section("b"), section("c")];
+        let order = vec!["c".to_string(), "a".to_string(), "b".to_string()];
+        let reordered = reorder(&sections, &order).unwrap();
+        let ids: Vec<&str> = reordered.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["c", "a", "b"]);
+
+        assert!(reorder(&sections, &["a".to_string(), "b".to_string()]).is_err());
+        assert!(reorder(&sections, &["a".to_string(), "a".to_string(), "b".to_string()]).is_err());
+        assert!(reorder(&sections, &["a".to_string(), "b".to_string(), "x".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_join_sections() {
+        let texts = vec![
+            "# One\n\n![A](a.png){#fig:a}\n".to_string(),
+            String::new(),
+            "\n# Two\n\n![B](b.png){#fig:b}\n\nSee @fig:a and @fig:b.".to_string(),
+        ];
+        let markdown = join_sections(&texts);
+        assert!(markdown.starts_with("# One"));
+        assert!(markdown.contains("{#fig:a}\n\n# Two"));
+        assert!(markdown.ends_with("See @fig:a and @fig:b."));
+    }
+}
@@ -0,0 +1,254 @@
+// src-tauri/src/session_log.rs
+//! Opt-in session log for debugging sync problems.
+//!
+//! When `session_log` is enabled in the settings, opens, saves, imports and
+//! conflict checks append one JSON line each to `logs/session.jsonl` in the
+//! app data directory. The file rotates at `MAX_LOG_BYTES`, keeping
+//! `MAX_LOG_FILES` older files. `export_diagnostics` zips the logs together
+//! with environment information for bug reports.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::settings::load_app_settings;
+
+/// Size at which the current log file is rotated
+pub const MAX_LOG_BYTES: u64 = 1024 * 1024;
+
+/// Number of rotated log files kept besides the current one
+pub const MAX_LOG_FILES: usize = 5;
+
+const LOG_FILE: &str = "session.jsonl";
+
+/// One line of the session log
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SessionEvent {
+    /// RFC 3339 timestamp
+    pub timestamp: String,
+    /// Random ID of the app run that wrote the event
+    pub session_id: String,
+    /// Event kind, e.g. "open", "save", "import_patches"
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doc_id: Option<String>,
+    /// Event-specific fields (paths, patch counts, errors)
+    #[serde(default)]
+    pub details: serde_json::Value,
+}
+
+/// Environment information included in a diagnostics bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvironmentInfo {
+    pub app_version: String,
+    pub os: String,
+    pub arch: String,
+    pub kmd_version: String,
+    pub pandoc: crate::pandoc::PandocDiagnostics,
+    pub settings: crate::settings::AppSettings,
+}
+
+/// ID of this app run, shared by all events it logs
+fn session_id() -> &'static str {
+    static SESSION_ID: OnceLock<String> = OnceLock::new();
+    SESSION_ID.get_or_init(|| Uuid::new_v4().to_string())
+}
+
+/// Directory holding the session logs
+fn get_log_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map(|p| p.join("logs"))
+        .map_err(|e| format!("Failed to get app data dir: {}", e))
+}
+
+/// Path of a log file; index 0 is the current file
+fn log_file(dir: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        dir.join(LOG_FILE)
+    } else {
+        dir.join(format!("session.{}.jsonl", index))
+    }
+}
+
+/// Shift the log files up by one, dropping the oldest
+fn rotate_logs(dir: &Path) -> Result<(), String> {
+    fs::remove_file(log_file(dir, MAX_LOG_FILES)).ok();
+    for index in (0..MAX_LOG_FILES).rev() {
+        let from = log_file(dir, index);
+        if from.exists() {
+            fs::rename(&from, log_file(dir, index + 1)).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Append an event to the log in a directory, rotating first if the current
+/// file is full
+pub fn append_event(dir: &Path, event: &SessionEvent) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create log directory: {}", e))?;
+
+    let current = log_file(dir, 0);
+    if fs::metadata(&current).is_ok_and(|m| m.len() >= MAX_LOG_BYTES) {
+        rotate_logs(dir)?;
+    }
+
+    let line = serde_json::to_string(event).map_err(|e| e.to_string())?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&current)
+        .map_err(|e| format!("Failed to open session log: {}", e))?;
+    writeln!(file, "{}", line).map_err(|e| format!("Failed to write session log: {}", e))
+}
+
+/// Log files in a directory, oldest first
+pub fn log_files(dir: &Path) -> Vec<PathBuf> {
+    (0..=MAX_LOG_FILES)
+        .rev()
+        .map(|index| log_file(dir, index))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Record an event if the session log is enabled
+///
+/// Logging never fails the operation being logged; errors are only reported
+/// to the debug log.
+pub fn log_event(app: &AppHandle, kind: &str, doc_id: Option<&str>, details: serde_json::Value) {
+    if !load_app_settings().is_ok_and(|s| s.session_log) {
+        return;
+    }
+
+    let event = SessionEvent {
+        timestamp: Utc::now().to_rfc3339(),
+        session_id: session_id().to_string(),
+        kind: kind.to_string(),
+        doc_id: doc_id.map(|s| s.to_string()),
+        details,
+    };
+    if let Err(e) = get_log_dir(app).and_then(|dir| append_event(&dir, &event)) {
+        log::warn!("Failed to record session event {}: {}", kind, e);
+    }
+}
+
+/// Record an event from the frontend (e.g. editor-side sync steps)
+#[tauri::command]
+pub fn log_session_event(
+    app: AppHandle,
+    kind: String,
+    doc_id: Option<String>,
+    details: Option<serde_json::Value>,
+) {
+    log_event(&app, &kind, doc_id.as_deref(), details.unwrap_or(serde_json::Value::Null));
+}
+
+/// Write a ZIP with the session logs and environment information
+pub fn write_diagnostics(dest: &Path, log_dir: &Path, environment: &EnvironmentInfo) -> Result<(), String> {
+    let file = File::create(dest).map_err(|e| format!("Failed to create diagnostics file: {}", e))?;
+    let mut zip = ZipWriter::new(file);
+    let options = FileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .unix_permissions(0o644);
+
+    let environment_json = serde_json::to_string_pretty(environment).map_err(|e| e.to_string())?;
+    zip.start_file("environment.json", options)
+        .map_err(|e| e.to_string())?;
+    zip.write_all(environment_json.as_bytes())
+        .map_err(|e| e.to_string())?;
+
+    for path in log_files(log_dir) {
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let content = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        zip.start_file(format!("logs/{}", name), options)
+            .map_err(|e| e.to_string())?;
+        zip.write_all(&content)
+            .map_err(|e| e.to_string())?;
+    }
+
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Bundle the session logs and environment information into a ZIP for a
+/// bug report
+#[tauri::command]
+pub fn export_diagnostics(app: AppHandle, path: String) -> Result<(), String> {
+    let environment = EnvironmentInfo {
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        kmd_version: crate::kmd::KMD_VERSION.to_string(),
+        pandoc: crate::pandoc::diagnose_pandoc(),
+        settings: load_app_settings().unwrap_or_default(),
+    };
+    write_diagnostics(Path::new(&path), &get_log_dir(&app)?, &environment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn event(kind: &str) -> SessionEvent {
+        SessionEvent {
+            timestamp: "2024-01-01T00:00:00Z".to_string(),
+            session_id: "s1".to_string(),
+            kind: kind.to_string(),
+            doc_id: Some("doc".to_string()),
+            details: serde_json::json!({ "patches": 3 }),
+        }
+    }
+
+    #[test]
+    fn test_append_and_rotate() {
+        let dir = tempfile::TempDir::new().unwrap();
+        append_event(dir.path(), &event("open")).unwrap();
+        append_event(dir.path(), &event("save")).unwrap();
+
+        let content = fs::read_to_string(log_file(dir.path(), 0)).unwrap();
+        let events: Vec<SessionEvent> = content.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(events, vec![event("open"), event("save")]);
+
+        // A full log file is moved aside before the next write
+        for _ in 0..MAX_LOG_FILES + 2 {
+            fs::write(log_file(dir.path(), 0), vec![b'x'; MAX_LOG_BYTES as usize]).unwrap();
+            append_event(dir.path(), &event("import")).unwrap();
+        }
+        let files = log_files(dir.path());
+        assert_eq!(files.len(), MAX_LOG_FILES + 1);
+        assert_eq!(files.last(), Some(&log_file(dir.path(), 0)));
+        assert!(fs::read_to_string(log_file(dir.path(), 0)).unwrap().contains("\"import\""));
+    }
+
+    #[test]
+    fn test_write_diagnostics() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let log_dir = dir.path().join("logs");
+        append_event(&log_dir, &event("open")).unwrap();
+
+        let environment = EnvironmentInfo {
+            app_version: "1.0.0".to_string(),
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            kmd_version: crate::kmd::KMD_VERSION.to_string(),
+            pandoc: Default::default(),
+            settings: Default::default(),
+        };
+        let dest = dir.path().join("diagnostics.zip");
+        write_diagnostics(&dest, &log_dir, &environment).unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&dest).unwrap()).unwrap();
+        let mut content = String::new();
+        archive.by_name("logs/session.jsonl").unwrap().read_to_string(&mut content).unwrap();
+        assert!(content.contains("\"open\""));
+        assert!(archive.by_name("environment.json").is_ok());
+    }
+}
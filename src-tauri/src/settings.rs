@@ -0,0 +1,275 @@
+// src-tauri/src/settings.rs
+//! Application-wide preferences.
+//!
+//! Stored as settings.json in the config directory. The file carries a
+//! version number; older layouts are upgraded on load and missing fields
+//! fall back to their defaults.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backups::validate_backup_settings;
+use crate::figure_sources::{validate_figure_source, FigureSource};
+use crate::hooks::{validate_hook, Hook};
+use crate::kmd::BackupSettings;
+use crate::profile::get_config_dir;
+
+/// Current version of the settings file layout
+pub const SETTINGS_VERSION: u32 = 1;
+
+/// Default export format offered by the export dialog
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Kmd,
+    Markdown,
+    Docx,
+}
+
+/// Persistent application preferences
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct AppSettings {
+    pub version: u32,
+    /// Profile ID used as author when none is selected
+    pub default_author: Option<String>,
+    /// Autosave interval in seconds (0 disables autosave)
+    pub autosave_interval_secs: u64,
+    pub default_export_format: ExportFormat,
+    /// Explicit pandoc executable instead of looking it up on PATH
+    pub pandoc_path: Option<PathBuf>,
+    /// Extra arguments passed to every pandoc conversion
+    pub pandoc_args: Vec<String>,
+    /// Explicit quarto executable instead of looking it up on PATH
+    pub quarto_path: Option<PathBuf>,
+    /// LanguageTool server for grammar checking, e.g. "http://localhost:8081";
+    /// grammar checking is off when unset
+    pub languagetool_url: Option<String>,
+    pub telemetry_opt_out: bool,
+    /// Record opens, saves and imports to a local log for bug reports
+    pub session_log: bool,
+    /// Backups of saved documents, unless a document sets its own
+    pub backup: Option<BackupSettings>,
+    /// Sizes above which a document is reported as getting too large
+    pub size_limits: SizeLimits,
+    /// Commands and webhooks run on document events
+    pub hooks: Vec<Hook>,
+    /// Scripts that draw figures, by document UUID
+    pub figure_sources: BTreeMap<String, Vec<FigureSource>>,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            default_author: None,
+            autosave_interval_secs: 60,
+            default_export_format: ExportFormat::default(),
+            pandoc_path: None,
+            pandoc_args: Vec::new(),
+            quarto_path: None,
+            languagetool_url: None,
+            telemetry_opt_out: false,
+            session_log: false,
+            backup: None,
+            size_limits: SizeLimits::default(),
+            hooks: Vec::new(),
+            figure_sources: BTreeMap::new(),
+        }
+    }
+}
+
+/// Soft limits on the size of a document, in MB; 0 turns a limit off
+///
+/// Going over one only warns: the document still saves.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SizeLimits {
+    /// Whole document, kept under what mail servers usually accept
+    pub total_mb: u32,
+    /// Patches, snapshots and comments
+    pub history_mb: u32,
+    /// Images and other assets
+    pub assets_mb: u32,
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        Self {
+            total_mb: 20,
+            history_mb: 10,
+            assets_mb: 15,
+        }
+    }
+}
+
+/// Get the settings file path
+fn get_settings_path() -> Result<PathBuf, String> {
+    get_config_dir().map(|p| p.join("settings.json"))
+}
+
+/// Upgrade a settings document from an older version
+fn migrate_settings(value: serde_json::Value) -> Result<AppSettings, String> {
+    let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+    if version > SETTINGS_VERSION as u64 {
+        return Err(format!(
+            "Settings file version {} is newer than supported version {}",
+            version, SETTINGS_VERSION
+        ));
+    }
+
+    // Per-version upgrades go here; fields added since an older version
+    // are filled in from the defaults by serde
+
+    let mut settings: AppSettings =
+        serde_json::from_value(value).map_err(|e| format!("Failed to parse settings: {}", e))?;
+    settings.version = SETTINGS_VERSION;
+    Ok(settings)
+}
+
+fn validate_settings(settings: &AppSettings) -> Result<(), String> {
+    if settings.autosave_interval_secs != 0 && settings.autosave_interval_secs < 5 {
+        return Err("Autosave interval must be 0 (disabled) or at least 5 seconds".to_string());
+    }
+    if let Some(path) = &settings.pandoc_path {
+        if path.as_os_str().is_empty() {
+            return Err("Pandoc path must not be empty".to_string());
+        }
+    }
+    if let Some(path) = &settings.quarto_path {
+        if path.as_os_str().is_empty() {
+            return Err("Quarto path must not be empty".to_string());
+        }
+    }
+    if let Some(url) = &settings.languagetool_url {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err("LanguageTool URL must start with http:// or https://".to_string());
+        }
+    }
+    if let Some(backup) = &settings.backup {
+        validate_backup_settings(backup)?;
+    }
+    for hook in &settings.hooks {
+        validate_hook(hook)?;
+    }
+    for source in settings.figure_sources.values().flatten() {
+        validate_figure_source(source)?;
+    }
+    Ok(())
+}
+
+/// Load settings from a file, returning defaults if it does not exist
+pub fn load_settings_from(path: &Path) -> Result<AppSettings, String> {
+    if !path.exists() {
+        return Ok(AppSettings::default());
+    }
+
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read settings: {}", e))?;
+    let value: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse settings: {}", e))?;
+
+    migrate_settings(value)
+}
+
+/// Save settings to a file
+pub fn save_settings_to(path: &Path, settings: &AppSettings) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create config directory: {}", e))?;
+    }
+
+    let content = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+
+    fs::write(path, content)
+        .map_err(|e| format!("Failed to write settings: {}", e))
+}
+
+/// Load the application settings (for use by other modules)
+pub fn load_app_settings() -> Result<AppSettings, String> {
+    load_settings_from(&get_settings_path()?)
+}
+
+/// Validate and save the application settings (for use by other modules)
+pub fn save_app_settings(settings: &AppSettings) -> Result<(), String> {
+    validate_settings(settings)?;
+    save_settings_to(&get_settings_path()?, settings)
+}
+
+/// Get the application settings
+#[tauri::command]
+pub fn get_app_settings() -> Result<AppSettings, String> {
+    load_app_settings()
+}
+
+/// Validate and persist the application settings
+#[tauri::command]
+pub fn update_app_settings(settings: AppSettings) -> Result<AppSettings, String> {
+    validate_settings(&settings)?;
+
+    let settings = AppSettings {
+        version: SETTINGS_VERSION,
+        ..settings
+    };
+    save_settings_to(&get_settings_path()?, &settings)?;
+
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_settings_roundtrip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("settings.json");
+
+        assert_eq!(load_settings_from(&path).unwrap(), AppSettings::default());
+
+        let settings = AppSettings {
+            default_export_format: ExportFormat::Docx,
+            pandoc_path: Some(PathBuf::from("/opt/pandoc/bin/pandoc")),
+            pandoc_args: vec!["--reference-doc=/home/user/reference.docx".to_string()],
+            quarto_path: Some(PathBuf::from("/opt/quarto/bin/quarto")),
+            languagetool_url: Some("http://localhost:8081".to_string()),
+            telemetry_opt_out: true,
+            ..AppSettings::default()
+        };
+        save_settings_to(&path, &settings).unwrap();
+
+        assert_eq!(load_settings_from(&path).unwrap(), settings);
+    }
+
+    #[test]
+    fn test_migrate_unversioned_settings() {
+        let legacy = serde_json::json!({
+            "default_export_format": "markdown"
+        });
+
+        let settings = migrate_settings(legacy).unwrap();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.autosave_interval_secs, 60);
+        assert_eq!(settings.default_export_format, ExportFormat::Markdown);
+        assert!(!settings.telemetry_opt_out);
+        assert!(!settings.session_log);
+
+        let future = serde_json::json!({ "version": SETTINGS_VERSION + 1 });
+        assert!(migrate_settings(future).is_err());
+    }
+
+    #[test]
+    fn test_validate_autosave_interval() {
+        let settings = AppSettings {
+            autosave_interval_secs: 2,
+            ..AppSettings::default()
+        };
+        assert!(validate_settings(&settings).is_err());
+        assert!(validate_settings(&AppSettings::default()).is_ok());
+    }
+}
@@ -0,0 +1,392 @@
+// src-tauri/src/source_format.rs
+//! Quarto and R Markdown round-trips.
+//!
+//! Importing a `.qmd` or `.Rmd` file keeps its YAML frontmatter and the
+//! headers of its executable code chunks (```` ```{r setup, echo=FALSE} ````)
+//! in `DocumentMeta::source`; the editor only sees the body, with chunks as
+//! plain ```` ```r ```` fences. `export_quarto` and `export_rmarkdown` put
+//! both back. Chunks are found again by their code, or by their order among
+//! the chunks of the same language once their code was edited.
+//!
+//! Cross-references use Korppi's `@fig:label` form in the editor and
+//! Quarto's `@fig-label` form in the file. Figures embedded by
+//! `render_quarto_figures` are left out of exports, since rendering the file
+//! produces them again.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::document_manager::DocumentManager;
+use crate::error::KorppiError;
+
+/// Original layout of an imported Quarto or R Markdown file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SourceMetadata {
+    /// "quarto" or "rmarkdown"
+    pub format: String,
+    /// YAML between the `---` delimiters, without them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frontmatter: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<CodeChunk>,
+    /// The imported file, whose directory chunks are executed in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+}
+
+/// File name prefix of the figures `render_quarto_figures` stores as assets
+pub const RENDERED_FIGURE_PREFIX: &str = "render-";
+
+/// An executable code chunk of the original file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CodeChunk {
+    /// Engine, used as the fence language in the editor (r, python, ...)
+    pub language: String,
+    /// Everything between the braces, e.g. "r setup, include=FALSE"
+    pub header: String,
+    /// SHA-256 of the chunk code at import
+    pub code_hash: String,
+}
+
+/// Target of a source export
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SourceFormat {
+    Quarto,
+    RMarkdown,
+}
+
+impl SourceFormat {
+    fn default_frontmatter(self, title: &str) -> String {
+        let title = format!("title: \"{}\"", title.replace('\\', "\\\\").replace('"', "\\\""));
+        match self {
+            SourceFormat::Quarto => format!("{}\nformat: html", title),
+            SourceFormat::RMarkdown => format!("{}\noutput: html_document", title),
+        }
+    }
+}
+
+fn code_hash(code: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(code.trim_end().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Split YAML frontmatter off markdown content
+///
+/// Returns the YAML without its delimiters, if there is any, and the rest.
+pub fn split_yaml_frontmatter(content: &str) -> (Option<String>, String) {
+    let lines: Vec<&str> = content.lines().collect();
+
+    // Check if the document starts with YAML frontmatter delimiter
+    if lines.is_empty() || lines[0].trim() != "---" {
+        return (None, content.to_string());
+    }
+
+    // Find the closing delimiter
+    for (i, line) in lines.iter().enumerate().skip(1) {
+        if line.trim() == "---" || line.trim() == "..." {
+            return (Some(lines[1..i].join("\n")), lines[(i + 1)..].join("\n"));
+        }
+    }
+
+    // No closing delimiter found, return original content
+    (None, content.to_string())
+}
+
+/// A fence opening line: indentation, fence, info string
+fn parse_fence(line: &str) -> Option<(&str, &str, &str)> {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+    let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let fence_len = trimmed.chars().take_while(|c| *c == marker).count();
+    if fence_len < 3 || indent.len() > 3 {
+        return None;
+    }
+    Some((indent, &trimmed[..fence_len], trimmed[fence_len..].trim()))
+}
+
+fn closes_fence(line: &str, fence: &str) -> bool {
+    let trimmed = line.trim();
+    let marker = fence.chars().next().unwrap_or('`');
+    trimmed.len() >= fence.len() && trimmed.chars().all(|c| c == marker)
+}
+
+/// A fenced code block of the body
+pub(crate) struct FencedBlock {
+    /// Line index of the opening fence
+    open: usize,
+    /// Line index of the closing fence (or the last line)
+    pub(crate) close: usize,
+    indent: String,
+    fence: String,
+    info: String,
+    code: String,
+}
+
+pub(crate) fn fenced_blocks(lines: &[&str]) -> Vec<FencedBlock> {
+    let mut blocks = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let Some((indent, fence, info)) = parse_fence(lines[i]) else {
+            i += 1;
+            continue;
+        };
+        let end = (i + 1..lines.len()).find(|&j| closes_fence(lines[j], fence)).unwrap_or(lines.len());
+        blocks.push(FencedBlock {
+            open: i,
+            close: end.min(lines.len() - 1),
+            indent: indent.to_string(),
+            fence: fence.to_string(),
+            info: info.to_string(),
+            code: lines[i + 1..end].join("\n"),
+        });
+        i = end + 1;
+    }
+    blocks
+}
+
+/// Separate an imported Quarto or R Markdown file into the body shown in
+/// the editor and the metadata needed to write it back
+pub fn split_source(content: &str, format: &str) -> (String, SourceMetadata) {
+    let (frontmatter, body) = split_yaml_frontmatter(content);
+    let mut lines: Vec<String> = body.lines().map(str::to_string).collect();
+    let line_refs: Vec<&str> = body.lines().collect();
+
+    let mut chunks = Vec::new();
+    for block in fenced_blocks(&line_refs) {
+        let Some(header) = block.info.strip_prefix('{').and_then(|h| h.strip_suffix('}')) else {
+            continue;
+        };
+        let header = header.trim();
+        let language: String = header
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '-' || *c == '.')
+            .collect();
+        if language.is_empty() {
+            continue;
+        }
+        lines[block.open] = format!("{}{}{}", block.indent, block.fence, language);
+        chunks.push(CodeChunk {
+            language,
+            header: header.to_string(),
+            code_hash: code_hash(&block.code),
+        });
+    }
+    convert_crossrefs(&mut lines, false);
+
+    let source = SourceMetadata {
+        format: format.to_string(),
+        frontmatter,
+        chunks,
+        path: None,
+    };
+    (lines.join("\n"), source)
+}
+
+/// Switch cross-references outside code blocks between `@fig:label` and
+/// Quarto's `@fig-label` (likewise for sections and tables)
+fn convert_crossrefs(lines: &mut [String], to_quarto: bool) {
+    let line_refs: Vec<&str> = lines.iter().map(String::as_str).collect();
+    let mut in_code = vec![false; lines.len()];
+    for block in fenced_blocks(&line_refs) {
+        in_code[block.open..=block.close].iter_mut().for_each(|c| *c = true);
+    }
+
+    let (pattern, replacement) = if to_quarto {
+        (r"(@|\{#)(fig|sec|tbl):", "$1$2-")
+    } else {
+        (r"(@|\{#)(fig|sec|tbl)-", "$1$2:")
+    };
+    let re = Regex::new(pattern).unwrap();
+    for (line, in_code) in lines.iter_mut().zip(in_code) {
+        if !in_code && re.is_match(line) {
+            *line = re.replace_all(line, replacement).into_owned();
+        }
+    }
+}
+
+/// Whether a line is a figure embedded by `render_quarto_figures`
+pub(crate) fn is_rendered_figure(line: &str) -> bool {
+    let re = Regex::new(&format!(r"^!\[[^\]]*\]\(assets/{}[^)]*\)(\{{[^}}]*\}})?$", RENDERED_FIGURE_PREFIX)).unwrap();
+    re.is_match(line.trim())
+}
+
+/// Drop embedded rendered figures, with the blank line before each
+pub(crate) fn remove_rendered_figures(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in content.lines() {
+        if is_rendered_figure(line) {
+            if lines.last().is_some_and(|l| l.trim().is_empty()) {
+                lines.pop();
+            }
+            continue;
+        }
+        lines.push(line.to_string());
+    }
+    lines
+}
+
+/// Pair the code blocks of editor content with the chunks they came from
+///
+/// Returns each block with a language and the header to write for it:
+/// unchanged chunks are matched first, then edited ones in order. New
+/// blocks in a language the original file executed get a bare header.
+pub(crate) fn match_chunks(lines: &[&str], chunks: &[CodeChunk]) -> Vec<(FencedBlock, Option<String>)> {
+    let blocks: Vec<FencedBlock> = fenced_blocks(lines)
+        .into_iter()
+        .filter(|b| !b.info.is_empty() && !b.info.starts_with('{'))
+        .collect();
+
+    let mut used = vec![false; chunks.len()];
+    let mut headers: Vec<Option<String>> = vec![None; blocks.len()];
+    for (b, block) in blocks.iter().enumerate() {
+        let hash = code_hash(&block.code);
+        if let Some(c) = (0..chunks.len()).find(|&c| !used[c] && chunks[c].language == block.info && chunks[c].code_hash == hash) {
+            used[c] = true;
+            headers[b] = Some(chunks[c].header.clone());
+        }
+    }
+    for (b, block) in blocks.iter().enumerate() {
+        if headers[b].is_some() {
+            continue;
+        }
+        if let Some(c) = (0..chunks.len()).find(|&c| !used[c] && chunks[c].language == block.info) {
+            used[c] = true;
+            headers[b] = Some(chunks[c].header.clone());
+        } else if chunks.iter().any(|c| c.language == block.info) {
+            headers[b] = Some(block.info.clone());
+        }
+    }
+
+    blocks.into_iter().zip(headers).collect()
+}
+
+/// Label of a chunk, from its header (`r fig-cars, echo=FALSE`,
+/// `label="fig-cars"`) or a `#| label:` option in its code
+pub(crate) fn chunk_label(block: &FencedBlock, header: &str) -> Option<String> {
+    let rest = header.split_once([' ', ',']).map(|(_, rest)| rest).unwrap_or("");
+    let options: Vec<&str> = rest.split(',').map(str::trim).collect();
+    let from_header = options.iter().enumerate().find_map(|(i, option)| match option.split_once('=') {
+        Some((key, value)) if key.trim() == "label" => Some(value.trim().trim_matches(['"', '\'']).to_string()),
+        None if i == 0 && !option.is_empty() => Some(option.to_string()),
+        _ => None,
+    });
+    from_header.or_else(|| {
+        block.code.lines().find_map(|line| {
+            let option = line.trim().strip_prefix("#|")?.trim();
+            let value = option.strip_prefix("label:")?;
+            Some(value.trim().trim_matches(['"', '\'']).to_string())
+        })
+    })
+    .filter(|label| !label.is_empty())
+}
+
+/// Rebuild a Quarto or R Markdown file from the editor content
+///
+/// Code blocks get back the header of the chunk they came from. New code
+/// blocks in a language the original file executed become chunks too.
+pub fn restore_source(content: &str, source: Option<&SourceMetadata>, format: SourceFormat, title: &str) -> String {
+    let frontmatter = source
+        .and_then(|s| s.frontmatter.clone())
+        .unwrap_or_else(|| format.default_frontmatter(title));
+    let chunks: &[CodeChunk] = source.map(|s| s.chunks.as_slice()).unwrap_or(&[]);
+
+    let mut lines = remove_rendered_figures(content);
+    let line_refs: Vec<&str> = lines.iter().map(String::as_str).collect();
+    let headers: Vec<(usize, String)> = match_chunks(&line_refs, chunks)
+        .into_iter()
+        .filter_map(|(block, header)| header.map(|h| (block.open, format!("{}{}{{{}}}", block.indent, block.fence, h))))
+        .collect();
+    for (open, line) in headers {
+        lines[open] = line;
+    }
+    convert_crossrefs(&mut lines, true);
+
+    format!("---\n{}\n---\n\n{}\n", frontmatter, lines.join("\n").trim_start_matches('\n'))
+}
+
+fn export_source(
+    manager: &Mutex<DocumentManager>,
+    doc_id: &str,
+    path: &str,
+    content: &str,
+    format: SourceFormat,
+) -> Result<(), KorppiError> {
+    let output = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.to_string()))?;
+        restore_source(content, doc.meta.source.as_ref(), format, &doc.meta.title)
+    };
+    fs::write(path, output)?;
+    Ok(())
+}
+
+/// Export a document as a Quarto file, restoring the frontmatter and
+/// chunk headers of the file it was imported from
+#[tauri::command]
+pub fn export_quarto(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    path: String,
+    content: String,
+) -> Result<(), KorppiError> {
+    export_source(&manager, &doc_id, &path, &content, SourceFormat::Quarto)
+}
+
+/// Export a document as an R Markdown file, restoring the frontmatter and
+/// chunk headers of the file it was imported from
+#[tauri::command]
+pub fn export_rmarkdown(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    path: String,
+    content: String,
+) -> Result<(), KorppiError> {
+    export_source(&manager, &doc_id, &path, &content, SourceFormat::RMarkdown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QMD: &str = "---\ntitle: \"Analysis\"\nformat: pdf\n---\n\n# Data\n\n```{r setup, include=FALSE}\nlibrary(dplyr)\n```\n\nText with `r nrow(df)` rows.\n\n```{python}\n#| echo: false\nprint(1)\n```\n\n```bash\nls\n```";
+
+    #[test]
+    fn test_split_source() {
+        let (body, source) = split_source(QMD, "quarto");
+
+        assert!(body.starts_with("\n# Data\n\n```r\nlibrary(dplyr)\n```"));
+        assert!(body.contains("```python\n#| echo: false"));
+        assert!(body.contains("```bash\nls"));
+        assert_eq!(source.frontmatter.as_deref(), Some("title: \"Analysis\"\nformat: pdf"));
+        let headers: Vec<&str> = source.chunks.iter().map(|c| c.header.as_str()).collect();
+        assert_eq!(headers, vec!["r setup, include=FALSE", "python"]);
+    }
+
+    #[test]
+    fn test_restore_source_round_trip() {
+        let (body, source) = split_source(QMD, "quarto");
+        assert_eq!(restore_source(&body, Some(&source), SourceFormat::Quarto, "Ignored"), format!("{}\n", QMD));
+
+        // An edited chunk keeps its header, a new R block becomes a chunk
+        let edited = body.replace("library(dplyr)", "library(tidyr)") + "\n\n```r\nsummary(df)\n```";
+        let restored = restore_source(&edited, Some(&source), SourceFormat::Quarto, "Ignored");
+        assert!(restored.contains("```{r setup, include=FALSE}\nlibrary(tidyr)"));
+        assert!(restored.contains("```{r}\nsummary(df)"));
+        assert!(restored.contains("```bash\nls"));
+    }
+
+    #[test]
+    fn test_restore_source_without_metadata() {
+        let restored = restore_source("Hello\n", None, SourceFormat::RMarkdown, "My \"Notes\"");
+        assert_eq!(restored, "---\ntitle: \"My \\\"Notes\\\"\"\noutput: html_document\n---\n\nHello\n");
+    }
+}
@@ -0,0 +1,404 @@
+// src-tauri/src/spellcheck.rs
+//! Spell checking driven by DocumentSettings.
+//!
+//! Reads Hunspell dictionaries (.dic/.aff pairs) from the korppi config
+//! directory (`dictionaries/`, for downloaded dictionaries) and the usual
+//! system locations. Prefix and suffix rules are expanded when a dictionary
+//! is loaded. Words added by the user go into a custom list kept per profile.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tauri::State;
+
+use crate::document_manager::DocumentManager;
+use crate::profile::{get_config_dir, load_saved_profile};
+
+/// Maximum number of suggestions returned per word
+const MAX_SUGGESTIONS: usize = 5;
+
+/// A misspelled word in the checked text
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SpellingIssue {
+    /// Starting character index (UTF-16, inclusive)
+    pub start: usize,
+    /// Ending character index (UTF-16, exclusive)
+    pub end: usize,
+    pub word: String,
+    pub suggestions: Vec<String>,
+}
+
+/// An affix rule from a .aff file
+#[derive(Debug, Clone)]
+struct AffixRule {
+    strip: String,
+    add: String,
+    condition: Option<Regex>,
+}
+
+/// A loaded Hunspell dictionary with all affixed forms expanded
+#[derive(Debug, Default)]
+pub struct Dictionary {
+    words: HashSet<String>,
+    /// Characters tried when generating suggestions (TRY in the .aff file)
+    try_chars: Vec<char>,
+}
+
+/// Decode dictionary bytes according to the SET directive
+fn decode(bytes: &[u8], latin1: bool) -> String {
+    if latin1 {
+        bytes.iter().map(|&b| b as char).collect()
+    } else {
+        String::from_utf8_lossy(bytes).to_string()
+    }
+}
+
+impl Dictionary {
+    /// Build a dictionary from .aff and .dic contents
+    pub fn parse(aff: &str, dic: &str) -> Dictionary {
+        let mut prefixes: HashMap<String, Vec<AffixRule>> = HashMap::new();
+        let mut suffixes: HashMap<String, Vec<AffixRule>> = HashMap::new();
+        let mut try_chars = Vec::new();
+
+        for line in aff.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["TRY", chars, ..] => try_chars = chars.chars().collect(),
+                [kind @ ("PFX" | "SFX"), flag, strip, add, rest @ ..] if !rest.is_empty() => {
+                    let condition = rest[0];
+                    let pattern = if *kind == "SFX" {
+                        format!("{}$", condition)
+                    } else {
+                        format!("^{}", condition)
+                    };
+                    let rule = AffixRule {
+                        strip: if *strip == "0" { String::new() } else { strip.to_string() },
+                        // Continuation flags after "/" are not supported
+                        add: match add.split('/').next() {
+                            Some("0") | None => String::new(),
+                            Some(a) => a.to_string(),
+                        },
+                        condition: if condition == "." { None } else { Regex::new(&pattern).ok() },
+                    };
+                    let table = if *kind == "SFX" { &mut suffixes } else { &mut prefixes };
+                    table.entry(flag.to_string()).or_default().push(rule);
+                }
+                _ => {}
+            }
+        }
+
+        let mut words = HashSet::new();
+        // The first line of a .dic file is the approximate word count
+        for line in dic.lines().skip(1) {
+            let entry = line.split_whitespace().next().unwrap_or("");
+            if entry.is_empty() {
+                continue;
+            }
+            let (stem, flags) = entry.split_once('/').unwrap_or((entry, ""));
+            words.insert(stem.to_string());
+
+            for flag in flags.chars() {
+                let flag = flag.to_string();
+                for rule in suffixes.get(&flag).into_iter().flatten() {
+                    if rule.condition.as_ref().is_none_or(|c| c.is_match(stem)) {
+                        if let Some(base) = stem.strip_suffix(rule.strip.as_str()) {
+                            words.insert(format!("{}{}", base, rule.add));
+                        }
+                    }
+                }
+                for rule in prefixes.get(&flag).into_iter().flatten() {
+                    if rule.condition.as_ref().is_none_or(|c| c.is_match(stem)) {
+                        if let Some(base) = stem.strip_prefix(rule.strip.as_str()) {
+                            words.insert(format!("{}{}", rule.add, base));
+                        }
+                    }
+                }
+            }
+        }
+
+        if try_chars.is_empty() {
+            try_chars = ('a'..='z').collect();
+        }
+
+        Dictionary { words, try_chars }
+    }
+
+    /// Check a word, accepting a capitalized form of a known lowercase word
+    fn contains(&self, word: &str, custom: &HashSet<String>) -> bool {
+        if self.words.contains(word) || custom.contains(word) {
+            return true;
+        }
+        let lower = word.to_lowercase();
+        self.words.contains(&lower) || custom.contains(&lower)
+    }
+
+    /// Known words one edit away from a misspelling
+    fn suggest(&self, word: &str, custom: &HashSet<String>) -> Vec<String> {
+        let chars: Vec<char> = word.chars().collect();
+        let mut candidates = Vec::new();
+
+        for i in 0..chars.len() {
+            // Deletion
+            let mut c = chars.clone();
+            c.remove(i);
+            candidates.push(c.iter().collect::<String>());
+            // Transposition
+            if i + 1 < chars.len() {
+                let mut c = chars.clone();
+                c.swap(i, i + 1);
+                candidates.push(c.iter().collect());
+            }
+        }
+        for i in 0..=chars.len() {
+            for &t in &self.try_chars {
+                // Insertion
+                let mut c = chars.clone();
+                c.insert(i, t);
+                candidates.push(c.iter().collect());
+                // Replacement
+                if i < chars.len() && chars[i] != t {
+                    let mut c = chars.clone();
+                    c[i] = t;
+                    candidates.push(c.iter().collect());
+                }
+            }
+        }
+
+        let mut seen = HashSet::new();
+        candidates
+            .into_iter()
+            .filter(|c| !c.is_empty() && self.contains(c, custom) && seen.insert(c.clone()))
+            .take(MAX_SUGGESTIONS)
+            .collect()
+    }
+}
+
+/// Cache of loaded dictionaries, keyed by language
+#[derive(Default)]
+pub struct SpellChecker {
+    dictionaries: HashMap<String, Arc<Dictionary>>,
+}
+
+impl SpellChecker {
+    fn dictionary(&mut self, language: &str) -> Result<Arc<Dictionary>, String> {
+        if let Some(dict) = self.dictionaries.get(language) {
+            return Ok(dict.clone());
+        }
+        let dict = Arc::new(load_dictionary(language)?);
+        self.dictionaries.insert(language.to_string(), dict.clone());
+        Ok(dict)
+    }
+}
+
+/// Directories searched for Hunspell dictionaries, in priority order
+fn dictionary_dirs() -> Vec<PathBuf> {
+    let mut search = Vec::new();
+    if let Ok(config) = get_config_dir() {
+        search.push(config.join("dictionaries"));
+    }
+    search.push(PathBuf::from("/usr/share/hunspell"));
+    search.push(PathBuf::from("/usr/share/myspell"));
+    search.push(PathBuf::from("/usr/share/myspell/dicts"));
+    search.push(PathBuf::from("/Library/Spelling"));
+    if let Some(home) = dirs::home_dir() {
+        search.push(home.join("Library/Spelling"));
+    }
+    search
+}
+
+/// Load the Hunspell dictionary for a language tag such as "en-US"
+fn load_dictionary(language: &str) -> Result<Dictionary, String> {
+    let name = language.replace('-', "_");
+    let base_lang = name.split('_').next().unwrap_or(&name).to_string();
+
+    for dir in dictionary_dirs() {
+        for candidate in [&name, &base_lang] {
+            let dic_path = dir.join(format!("{}.dic", candidate));
+            let aff_path = dir.join(format!("{}.aff", candidate));
+            if !dic_path.exists() || !aff_path.exists() {
+                continue;
+            }
+
+            let aff_bytes = fs::read(&aff_path)
+                .map_err(|e| format!("Failed to read {}: {}", aff_path.display(), e))?;
+            let dic_bytes = fs::read(&dic_path)
+                .map_err(|e| format!("Failed to read {}: {}", dic_path.display(), e))?;
+
+            let latin1 = String::from_utf8_lossy(&aff_bytes)
+                .lines()
+                .any(|l| l.trim().eq_ignore_ascii_case("SET ISO8859-1"));
+
+            return Ok(Dictionary::parse(
+                &decode(&aff_bytes, latin1),
+                &decode(&dic_bytes, latin1),
+            ));
+        }
+    }
+
+    Err(format!("No dictionary found for language: {}", language))
+}
+
+/// Path of the custom word list for the current profile
+fn custom_dictionary_path() -> Result<PathBuf, String> {
+    let profile_id = load_saved_profile()?
+        .map(|p| p.id)
+        .unwrap_or_else(|| "default".to_string());
+    Ok(get_config_dir()?
+        .join("dictionaries")
+        .join(format!("custom-{}.txt", profile_id)))
+}
+
+fn load_custom_words() -> Result<HashSet<String>, String> {
+    let path = custom_dictionary_path()?;
+    if !path.exists() {
+        return Ok(HashSet::new());
+    }
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read custom dictionary: {}", e))?;
+    Ok(content
+        .lines()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect())
+}
+
+/// Split text into words with their UTF-16 ranges
+///
+/// Apostrophes inside a word are kept ("don't"); words containing digits
+/// are skipped.
+fn tokenize(text: &str) -> Vec<(usize, usize, String)> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let chars: Vec<char> = text.chars().collect();
+
+    for (i, &c) in chars.iter().enumerate() {
+        let inner_apostrophe = (c == '\'' || c == '’')
+            && !current.is_empty()
+            && chars.get(i + 1).is_some_and(|n| n.is_alphabetic());
+        if c.is_alphanumeric() || inner_apostrophe {
+            if current.is_empty() {
+                start = offset;
+            }
+            current.push(c);
+        } else if !current.is_empty() {
+            tokens.push((start, offset, std::mem::take(&mut current)));
+        }
+        offset += c.len_utf16();
+    }
+    if !current.is_empty() {
+        tokens.push((start, offset, current));
+    }
+
+    tokens.retain(|(_, _, w)| !w.chars().any(|c| c.is_numeric()));
+    tokens
+}
+
+/// Find misspelled words in text
+pub fn find_misspellings(dict: &Dictionary, custom: &HashSet<String>, text: &str) -> Vec<SpellingIssue> {
+    tokenize(text)
+        .into_iter()
+        .filter(|(_, _, word)| !dict.contains(&word.replace('’', "'"), custom))
+        .map(|(start, end, word)| SpellingIssue {
+            start,
+            end,
+            suggestions: dict.suggest(&word, custom),
+            word,
+        })
+        .collect()
+}
+
+/// Check spelling of text using the document's language setting
+#[tauri::command]
+pub fn check_spelling(
+    manager: State<'_, Mutex<DocumentManager>>,
+    checker: State<'_, Mutex<SpellChecker>>,
+    doc_id: String,
+    text: String,
+) -> Result<Vec<SpellingIssue>, String> {
+    let settings = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+        doc.meta.settings.clone()
+    };
+
+    if !settings.spell_check {
+        return Ok(Vec::new());
+    }
+
+    // Documents created before the setting existed carry an empty language
+    let language = if settings.language.is_empty() { "en-US" } else { settings.language.as_str() };
+    let dict = checker
+        .lock()
+        .map_err(|e| e.to_string())?
+        .dictionary(language)?;
+    let custom = load_custom_words()?;
+
+    Ok(find_misspellings(&dict, &custom, &text))
+}
+
+/// Add a word to the current profile's custom dictionary
+#[tauri::command]
+pub fn add_to_dictionary(word: String) -> Result<(), String> {
+    let word = word.trim().to_string();
+    if word.is_empty() || word.chars().any(|c| c.is_whitespace()) {
+        return Err("Dictionary entries must be a single word".to_string());
+    }
+
+    let mut words: Vec<String> = load_custom_words()?.into_iter().collect();
+    if words.contains(&word) {
+        return Ok(());
+    }
+    words.push(word);
+    words.sort();
+
+    let path = custom_dictionary_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create dictionary directory: {}", e))?;
+    }
+    fs::write(&path, words.join("\n") + "\n")
+        .map_err(|e| format!("Failed to write custom dictionary: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AFF: &str = "SET UTF-8\nTRY esianrtolcdugmphbyfvkwz\n\nSFX S Y 2\nSFX S y ies [^aeiou]y\nSFX S 0 s [^y]\n\nPFX U Y 1\nPFX U 0 un .\n";
+    const DIC: &str = "5\ncat/S\nstory/S\nhappy/U\ndon't\nlike\n";
+
+    #[test]
+    fn test_affix_expansion() {
+        let dict = Dictionary::parse(AFF, DIC);
+        let custom = HashSet::new();
+
+        assert!(dict.contains("cats", &custom));
+        assert!(dict.contains("stories", &custom));
+        assert!(!dict.contains("storys", &custom));
+        assert!(dict.contains("unhappy", &custom));
+        assert!(dict.contains("Cat", &custom));
+    }
+
+    #[test]
+    fn test_find_misspellings_with_suggestions() {
+        let dict = Dictionary::parse(AFF, DIC);
+        let mut custom = HashSet::new();
+        custom.insert("Korppi".to_string());
+
+        let issues = find_misspellings(&dict, &custom, "😊 Korppi cats don't like storeis 42");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].word, "storeis");
+        // The emoji counts as two UTF-16 units
+        assert_eq!(issues[0].start, 26);
+        assert_eq!(issues[0].end, 33);
+        assert!(issues[0].suggestions.contains(&"stories".to_string()));
+    }
+}
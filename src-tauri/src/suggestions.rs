@@ -0,0 +1,578 @@
+// src-tauri/src/suggestions.rs
+//! Suggested-edits mode.
+//!
+//! A suggestion is a patch of kind "Suggestion" whose snapshot holds the
+//! proposed text. Its hunks are computed against the current working text
+//! and can be accepted or discarded individually. Accepting records a new
+//! Save patch whose data links back to the original suggestion.
+//!
+//! Decisions are recorded per hunk, keyed by the hunk's base and suggested
+//! text since hunk IDs are positional and shift as hunks get applied. A
+//! suggestion is closed once none of its hunks are left undecided.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::State;
+use uuid::Uuid;
+
+use crate::db_utils::ensure_schema;
+use crate::document_manager::{author_display_name, row_to_patch, DocumentManager};
+use crate::hunk_calculator::{apply_hunks, author_hunks, calculate_hunks, AuthoredHunk, DEFAULT_HUNK_COLOR};
+use crate::kmd::DocumentMeta;
+use crate::patch_log::{Patch, PatchInput};
+use crate::patch_store::PatchStore;
+
+/// Patch kind used for suggested edits
+pub const SUGGESTION_KIND: &str = "Suggestion";
+
+/// A pending or decided suggestion with its hunks against the working text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Suggestion {
+    pub patch: Patch,
+    pub hunks: Vec<AuthoredHunk>,
+    /// "pending", "accepted" or "rejected"
+    pub status: String,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<i64>,
+}
+
+/// Result of accepting a suggestion
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcceptedSuggestion {
+    /// Working text with the accepted hunks applied
+    pub content: String,
+    /// UUID of the Save patch recording the acceptance
+    pub patch_uuid: String,
+    pub accepted_hunks: Vec<String>,
+}
+
+/// Initialize the suggestion decisions table
+pub fn init_suggestions_table(conn: &Connection) -> Result<(), String> {
+    ensure_schema(conn)?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS suggestion_decisions (
+            suggestion_uuid TEXT PRIMARY KEY,
+            decision TEXT NOT NULL CHECK (decision IN ('accepted', 'rejected')),
+            decided_by TEXT,
+            decided_at INTEGER NOT NULL,
+            result_patch_uuid TEXT,
+            accepted_hunks TEXT
+        )",
+        [],
+    )
+    .map_err(|e| format!("Failed to create suggestion_decisions table: {}", e))?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS suggestion_hunk_decisions (
+            suggestion_uuid TEXT NOT NULL,
+            base_text TEXT NOT NULL,
+            modified_text TEXT NOT NULL,
+            decision TEXT NOT NULL CHECK (decision IN ('accepted', 'rejected')),
+            decided_by TEXT,
+            decided_at INTEGER NOT NULL,
+            result_patch_uuid TEXT,
+            PRIMARY KEY (suggestion_uuid, base_text, modified_text)
+        )",
+        [],
+    )
+    .map_err(|e| format!("Failed to create suggestion_hunk_decisions table: {}", e))?;
+
+    Ok(())
+}
+
+fn now_millis() -> Result<i64, String> {
+    Ok(std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_millis() as i64)
+}
+
+fn load_suggestion_patch(conn: &Connection, patch_uuid: &str) -> Result<Patch, String> {
+    conn.query_row(
+        "SELECT id, timestamp, author, kind, data, uuid, parent_uuid FROM patches WHERE uuid = ?1 AND kind = ?2",
+        params![patch_uuid, SUGGESTION_KIND],
+        row_to_patch,
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Suggestion not found: {}", patch_uuid))
+}
+
+fn suggestion_snapshot(patch: &Patch) -> Result<&str, String> {
+    patch
+        .data
+        .get("snapshot")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| format!("Suggestion {} has no snapshot", patch.id))
+}
+
+fn ensure_undecided(conn: &Connection, patch_uuid: &str) -> Result<(), String> {
+    let decision: Option<String> = conn
+        .query_row(
+            "SELECT decision FROM suggestion_decisions WHERE suggestion_uuid = ?1",
+            params![patch_uuid],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    match decision {
+        Some(decision) => Err(format!("Suggestion {} was already {}", patch_uuid, decision)),
+        None => Ok(()),
+    }
+}
+
+/// Compute the hunks a suggestion would apply to the working text
+pub fn suggestion_hunks(patch: &Patch, base_content: &str, meta: &DocumentMeta) -> Result<Vec<AuthoredHunk>, String> {
+    let snapshot = suggestion_snapshot(patch)?;
+    let author_name = author_display_name(meta, &patch.author);
+
+    Ok(author_hunks(
+        calculate_hunks(base_content, snapshot),
+        patch,
+        &author_name,
+        DEFAULT_HUNK_COLOR,
+    ))
+}
+
+/// Compute the hunks of a suggestion that are still undecided
+///
+/// Accepted hunks drop out of the diff on their own once applied; rejected
+/// ones are filtered out here.
+pub fn pending_hunks(
+    conn: &Connection,
+    patch: &Patch,
+    base_content: &str,
+    meta: &DocumentMeta,
+) -> Result<Vec<AuthoredHunk>, String> {
+    let hunks = suggestion_hunks(patch, base_content, meta)?;
+    let Some(suggestion_uuid) = patch.uuid.as_deref() else {
+        return Ok(hunks);
+    };
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT base_text, modified_text FROM suggestion_hunk_decisions
+             WHERE suggestion_uuid = ?1 AND decision = 'rejected'",
+        )
+        .map_err(|e| e.to_string())?;
+    let rejected = stmt
+        .query_map(params![suggestion_uuid], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(hunks
+        .into_iter()
+        .filter(|h| {
+            !rejected
+                .iter()
+                .any(|(base, modified)| *base == h.hunk.base_text && *modified == h.hunk.modified_text)
+        })
+        .collect())
+}
+
+fn suggestion_uuid(suggestion: &Patch) -> Result<String, String> {
+    suggestion
+        .uuid
+        .clone()
+        .ok_or_else(|| format!("Suggestion {} has no UUID", suggestion.id))
+}
+
+/// Pick the hunks named by `hunk_ids`, or all of them when none are given
+fn select_hunks<'a>(hunks: &'a [AuthoredHunk], hunk_ids: Option<&[String]>) -> Result<Vec<&'a AuthoredHunk>, String> {
+    match hunk_ids {
+        Some(ids) => {
+            for id in ids {
+                if !hunks.iter().any(|h| &h.hunk_id == id) {
+                    return Err(format!("Hunk not found in suggestion: {}", id));
+                }
+            }
+            Ok(hunks.iter().filter(|h| ids.contains(&h.hunk_id)).collect())
+        }
+        None => Ok(hunks.iter().collect()),
+    }
+}
+
+fn record_hunk_decisions(
+    conn: &Connection,
+    suggestion_uuid: &str,
+    hunks: &[&AuthoredHunk],
+    decision: &str,
+    actor: &str,
+    timestamp: i64,
+    result_patch_uuid: Option<&str>,
+) -> Result<(), String> {
+    for hunk in hunks {
+        conn.execute(
+            "INSERT OR REPLACE INTO suggestion_hunk_decisions
+                (suggestion_uuid, base_text, modified_text, decision, decided_by, decided_at, result_patch_uuid)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                suggestion_uuid,
+                hunk.hunk.base_text,
+                hunk.hunk.modified_text,
+                decision,
+                actor,
+                timestamp,
+                result_patch_uuid
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Close a suggestion whose hunks have all been decided
+///
+/// It counts as accepted if any of its hunks were accepted.
+fn close_suggestion(
+    conn: &Connection,
+    suggestion_uuid: &str,
+    actor: &str,
+    timestamp: i64,
+    result_patch_uuid: Option<&str>,
+    accepted_hunks: Option<&str>,
+) -> Result<(), String> {
+    let any_accepted: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM suggestion_hunk_decisions WHERE suggestion_uuid = ?1 AND decision = 'accepted')",
+            params![suggestion_uuid],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    let decision = if any_accepted { "accepted" } else { "rejected" };
+
+    conn.execute(
+        "INSERT INTO suggestion_decisions (suggestion_uuid, decision, decided_by, decided_at, result_patch_uuid, accepted_hunks)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![suggestion_uuid, decision, actor, timestamp, result_patch_uuid, accepted_hunks],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Apply the selected hunks of a suggestion and record provenance
+///
+/// `hunks` are the suggestion's pending hunks. The suggestion is closed
+/// once no hunks are left undecided. Returns the new text and the UUID of
+/// the Save patch that was recorded.
+pub fn apply_suggestion(
+    conn: &Connection,
+    suggestion: &Patch,
+    hunks: &[AuthoredHunk],
+    base_content: &str,
+    hunk_ids: Option<&[String]>,
+    actor: &str,
+) -> Result<AcceptedSuggestion, String> {
+    let suggestion_uuid = suggestion_uuid(suggestion)?;
+    let selected = select_hunks(hunks, hunk_ids)?;
+
+    let plain: Vec<_> = selected.iter().map(|h| h.hunk.clone()).collect();
+    let content = apply_hunks(base_content, &plain);
+    let accepted_hunks: Vec<String> = selected.iter().map(|h| h.hunk_id.clone()).collect();
+
+    // Chain the new Save onto the latest one in the main history
+    let parent_uuid: Option<String> = conn
+        .query_row(
+            "SELECT uuid FROM patches WHERE kind = 'Save' ORDER BY id DESC LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .flatten();
+
+    let timestamp = now_millis()?;
+    let patch_uuid = Uuid::new_v4().to_string();
+    let data = serde_json::json!({
+        "snapshot": content,
+        "accepted_suggestion": suggestion_uuid,
+        "accepted_hunks": accepted_hunks,
+    });
+
+    // The Save, its snapshot and the decisions land together or not at all
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+
+    PatchStore::on(&tx).insert_patch(
+        &PatchInput {
+            timestamp,
+            author: actor.to_string(),
+            kind: "Save".to_string(),
+            data,
+            uuid: Some(patch_uuid.clone()),
+            parent_uuid,
+        },
+        None,
+    )?;
+
+    record_hunk_decisions(&tx, &suggestion_uuid, &selected, "accepted", actor, timestamp, Some(&patch_uuid))?;
+    if selected.len() == hunks.len() {
+        let hunks_str = serde_json::to_string(&accepted_hunks).map_err(|e| e.to_string())?;
+        close_suggestion(&tx, &suggestion_uuid, actor, timestamp, Some(&patch_uuid), Some(&hunks_str))?;
+    }
+
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(AcceptedSuggestion {
+        content,
+        patch_uuid,
+        accepted_hunks,
+    })
+}
+
+/// Discard the selected hunks of a suggestion
+///
+/// `hunks` are the suggestion's pending hunks. The suggestion is closed
+/// once no hunks are left undecided.
+pub fn discard_hunks(
+    conn: &Connection,
+    suggestion: &Patch,
+    hunks: &[AuthoredHunk],
+    hunk_ids: Option<&[String]>,
+    actor: &str,
+) -> Result<(), String> {
+    let suggestion_uuid = suggestion_uuid(suggestion)?;
+    let selected = select_hunks(hunks, hunk_ids)?;
+    let timestamp = now_millis()?;
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    record_hunk_decisions(&tx, &suggestion_uuid, &selected, "rejected", actor, timestamp, None)?;
+    if selected.len() == hunks.len() {
+        close_suggestion(&tx, &suggestion_uuid, actor, timestamp, None, None)?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// List suggestions for a document with their hunks against the working text
+#[tauri::command]
+pub fn list_suggestions(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    base_content: String,
+    include_decided: Option<bool>,
+) -> Result<Vec<Suggestion>, String> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+
+    let doc = manager
+        .documents
+        .get(&doc_id)
+        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+
+    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    init_suggestions_table(&conn)?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT p.id, p.timestamp, p.author, p.kind, p.data, p.uuid, p.parent_uuid,
+                    d.decision, d.decided_by, d.decided_at
+             FROM patches p
+             LEFT JOIN suggestion_decisions d ON d.suggestion_uuid = p.uuid
+             WHERE p.kind = ?1
+             ORDER BY p.id ASC",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let rows = stmt
+        .query_map(params![SUGGESTION_KIND], |row| {
+            Ok((
+                row_to_patch(row)?,
+                row.get::<_, Option<String>>(7)?,
+                row.get::<_, Option<String>>(8)?,
+                row.get::<_, Option<i64>>(9)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let include_decided = include_decided.unwrap_or(false);
+    let mut suggestions = Vec::new();
+    for (patch, decision, decided_by, decided_at) in rows {
+        if decision.is_some() && !include_decided {
+            continue;
+        }
+        // Decided suggestions no longer apply to the working text
+        let hunks = if decision.is_none() {
+            pending_hunks(&conn, &patch, &base_content, &doc.meta)?
+        } else {
+            Vec::new()
+        };
+        suggestions.push(Suggestion {
+            patch,
+            hunks,
+            status: decision.unwrap_or_else(|| "pending".to_string()),
+            decided_by,
+            decided_at,
+        });
+    }
+
+    Ok(suggestions)
+}
+
+/// Accept a suggestion, applying all or only the selected hunks
+#[tauri::command]
+pub fn accept_suggestion(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    patch_uuid: String,
+    base_content: String,
+    hunk_ids: Option<Vec<String>>,
+    actor: String,
+) -> Result<AcceptedSuggestion, String> {
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+
+    let doc = manager
+        .documents
+        .get_mut(&doc_id)
+        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+
+    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    init_suggestions_table(&conn)?;
+
+    ensure_undecided(&conn, &patch_uuid)?;
+    let suggestion = load_suggestion_patch(&conn, &patch_uuid)?;
+    let hunks = pending_hunks(&conn, &suggestion, &base_content, &doc.meta)?;
+
+    let accepted = apply_suggestion(
+        &conn,
+        &suggestion,
+        &hunks,
+        &base_content,
+        hunk_ids.as_deref(),
+        &actor,
+    )?;
+
+    doc.handle.is_modified = true;
+
+    Ok(accepted)
+}
+
+/// Reject a suggestion, discarding all or only the selected hunks
+#[tauri::command]
+pub fn reject_suggestion(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    patch_uuid: String,
+    base_content: String,
+    hunk_ids: Option<Vec<String>>,
+    actor: String,
+) -> Result<(), String> {
+    let manager = manager.lock().map_err(|e| e.to_string())?;
+
+    let doc = manager
+        .documents
+        .get(&doc_id)
+        .ok_or_else(|| format!("Document not found: {}", doc_id))?;
+
+    let conn = Connection::open(&doc.history_path).map_err(|e| e.to_string())?;
+    init_suggestions_table(&conn)?;
+
+    ensure_undecided(&conn, &patch_uuid)?;
+    let suggestion = load_suggestion_patch(&conn, &patch_uuid)?;
+    let hunks = pending_hunks(&conn, &suggestion, &base_content, &doc.meta)?;
+
+    discard_hunks(&conn, &suggestion, &hunks, hunk_ids.as_deref(), &actor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> (Connection, Patch) {
+        let conn = Connection::open_in_memory().unwrap();
+        init_suggestions_table(&conn).unwrap();
+
+        conn.execute(
+            "INSERT INTO patches (timestamp, author, kind, data, uuid) VALUES (1, 'alice', 'Save', ?1, 'save-1')",
+            params![serde_json::json!({"snapshot": "The cat sat.\n\nIt was red."}).to_string()],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO patches (timestamp, author, kind, data, uuid, parent_uuid) VALUES (2, 'bob', ?1, ?2, 'sugg-1', 'save-1')",
+            params![
+                SUGGESTION_KIND,
+                serde_json::json!({"snapshot": "The dog sat.\n\nIt was blue."}).to_string()
+            ],
+        )
+        .unwrap();
+
+        let patch = load_suggestion_patch(&conn, "sugg-1").unwrap();
+        (conn, patch)
+    }
+
+    #[test]
+    fn test_accept_selected_hunks_records_provenance() {
+        let (conn, suggestion) = setup();
+        let base = "The cat sat.\n\nIt was red.";
+
+        let hunks = suggestion_hunks(&suggestion, base, &DocumentMeta::default()).unwrap();
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[0].author_name, "bob");
+
+        let ids = vec![hunks[1].hunk_id.clone()];
+        let accepted = apply_suggestion(&conn, &suggestion, &hunks, base, Some(&ids), "alice").unwrap();
+        assert_eq!(accepted.content, "The cat sat.\n\nIt was blue.");
+
+        let (data, parent): (String, Option<String>) = conn
+            .query_row(
+                "SELECT data, parent_uuid FROM patches WHERE uuid = ?1",
+                params![accepted.patch_uuid],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        let data: serde_json::Value = serde_json::from_str(&data).unwrap();
+        assert_eq!(data["accepted_suggestion"], "sugg-1");
+        assert_eq!(parent.as_deref(), Some("save-1"));
+
+        // One hunk is still undecided
+        assert!(ensure_undecided(&conn, "sugg-1").is_ok());
+        let hunks = pending_hunks(&conn, &suggestion, &accepted.content, &DocumentMeta::default()).unwrap();
+        assert_eq!(hunks.len(), 1);
+    }
+
+    #[test]
+    fn test_suggestion_closes_when_every_hunk_is_decided() {
+        let (conn, suggestion) = setup();
+        let meta = DocumentMeta::default();
+        let base = "The cat sat.\n\nIt was red.";
+
+        let hunks = pending_hunks(&conn, &suggestion, base, &meta).unwrap();
+        let ids = vec![hunks[0].hunk_id.clone()];
+        discard_hunks(&conn, &suggestion, &hunks, Some(&ids), "alice").unwrap();
+        assert!(ensure_undecided(&conn, "sugg-1").is_ok());
+
+        // The rejected hunk stays out of the pending list
+        let hunks = pending_hunks(&conn, &suggestion, base, &meta).unwrap();
+        assert_eq!(hunks.len(), 1);
+        let accepted = apply_suggestion(&conn, &suggestion, &hunks, base, None, "alice").unwrap();
+        assert_eq!(accepted.content, "The cat sat.\n\nIt was blue.");
+
+        let decision: String = conn
+            .query_row(
+                "SELECT decision FROM suggestion_decisions WHERE suggestion_uuid = 'sugg-1'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(decision, "accepted");
+        assert!(pending_hunks(&conn, &suggestion, &accepted.content, &meta).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_unknown_hunk_is_rejected() {
+        let (conn, suggestion) = setup();
+        let base = "The cat sat.\n\nIt was red.";
+
+        let hunks = suggestion_hunks(&suggestion, base, &DocumentMeta::default()).unwrap();
+        let ids = vec!["missing".to_string()];
+        assert!(apply_suggestion(&conn, &suggestion, &hunks, base, Some(&ids), "alice").is_err());
+        assert!(ensure_undecided(&conn, "sugg-1").is_ok());
+    }
+}
@@ -0,0 +1,299 @@
+// src-tauri/src/templates.rs
+//! Document templates.
+//!
+//! Built-in templates ship with the app; user templates live in the
+//! `templates/` directory of the korppi config directory. A user template is
+//! either a `.kmd` file, whose metadata and text are reused, or a `.md` file
+//! with an optional `<name>.json` sidecar holding a name, description,
+//! settings and authors. A `<name>.docx` next to the template becomes the
+//! reference document for DOCX export.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{State, Window};
+use uuid::Uuid;
+use zip::ZipArchive;
+
+use crate::document_manager::{
+    create_document_temp_dir, read_kmd_yjs_state, DocumentHandle, DocumentManager, DocumentState, ImportResult,
+};
+use crate::kmd::{is_path_safe, AuthorRef, DocumentMeta, DocumentSettings};
+use crate::profile::get_config_dir;
+use crate::yjs_store::document_text;
+
+/// A template offered when creating a document
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TemplateInfo {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub builtin: bool,
+}
+
+/// Metadata applied to documents created from a template
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TemplateMeta {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub settings: DocumentSettings,
+    #[serde(default)]
+    pub authors: Vec<AuthorRef>,
+}
+
+/// A loaded template
+#[derive(Debug, Clone)]
+pub struct Template {
+    pub info: TemplateInfo,
+    pub content: String,
+    pub meta: TemplateMeta,
+}
+
+const ARTICLE: &str = "# Title\n\n## Abstract\n\nSummarize the article.\n\n## Introduction {#sec:intro}\n\n## Methods {#sec:methods}\n\n## Results {#sec:results}\n\n## Discussion\n\n## References\n";
+const THESIS_CHAPTER: &str = "# Chapter title {#sec:chapter}\n\n## Introduction\n\nOutline the chapter and how it follows from the previous one.\n\n## Background\n\n## Contribution\n\n## Summary\n";
+const MEETING_NOTES: &str = "# Meeting notes\n\n**Date:** \n\n**Attendees:** \n\n## Agenda\n\n1. \n\n## Discussion\n\n## Decisions\n\n## Action items\n\n- [ ] \n";
+
+/// Templates that ship with the app
+pub fn builtin_templates() -> Vec<Template> {
+    let template = |id: &str, name: &str, description: &str, content: &str, settings: DocumentSettings| Template {
+        info: TemplateInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            description: description.to_string(),
+            builtin: true,
+        },
+        content: content.to_string(),
+        meta: TemplateMeta { settings, ..TemplateMeta::default() },
+    };
+
+    let mut thesis = DocumentSettings::default();
+    thesis.crossref.chapter_numbering = true;
+    let mut notes = DocumentSettings::default();
+    notes.autosave.interval_minutes = 2;
+
+    vec![
+        template("article", "Article", "Research article with standard sections", ARTICLE, DocumentSettings::default()),
+        template("thesis-chapter", "Thesis chapter", "Chapter with per-chapter figure and table numbering", THESIS_CHAPTER, thesis),
+        template("meeting-notes", "Meeting notes", "Agenda, decisions and action items", MEETING_NOTES, notes),
+    ]
+}
+
+/// Directory holding user templates
+fn get_templates_dir() -> Result<PathBuf, String> {
+    get_config_dir().map(|p| p.join("templates"))
+}
+
+/// Read meta.json from a KMD file
+pub(crate) fn read_kmd_meta(path: &Path) -> Result<DocumentMeta, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| format!("Invalid ZIP archive: {}", e))?;
+    let mut meta_file = archive
+        .by_name("meta.json")
+        .map_err(|_| "Missing meta.json in KMD file")?;
+    let mut content = String::new();
+    meta_file.read_to_string(&mut content).map_err(|e| e.to_string())?;
+    serde_json::from_str(&content).map_err(|e| format!("Invalid meta.json: {}", e))
+}
+
+/// Load a user template file (.md or .kmd) from a directory
+fn load_user_template(dir: &Path, file_name: &str) -> Result<Template, String> {
+    if !is_path_safe(file_name) || file_name.contains(['/', '\\']) {
+        return Err(format!("Invalid template name: {}", file_name));
+    }
+    let path = dir.join(file_name);
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| file_name.to_string());
+
+    let (content, mut meta) = match path.extension().and_then(|e| e.to_str()) {
+        Some("md") => {
+            let content = fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read template: {}", e))?;
+            let sidecar = dir.join(format!("{}.json", stem));
+            let meta = if sidecar.exists() {
+                let json = fs::read_to_string(&sidecar)
+                    .map_err(|e| format!("Failed to read template metadata: {}", e))?;
+                serde_json::from_str(&json).map_err(|e| format!("Invalid template metadata: {}", e))?
+            } else {
+                TemplateMeta::default()
+            };
+            (content, meta)
+        }
+        Some("kmd") => {
+            let doc_meta = read_kmd_meta(&path)?;
+            let state = read_kmd_yjs_state(&path)?;
+            let content = if state.is_empty() { String::new() } else { document_text(&[&state], false)? };
+            let meta = TemplateMeta {
+                name: Some(doc_meta.title),
+                description: None,
+                settings: doc_meta.settings,
+                authors: doc_meta.authors,
+            };
+            (content, meta)
+        }
+        _ => return Err(format!("Unsupported template format: {}", file_name)),
+    };
+
+    let reference_doc = dir.join(format!("{}.docx", stem));
+    if reference_doc.exists() {
+        meta.settings.reference_doc = Some(reference_doc);
+    }
+
+    Ok(Template {
+        info: TemplateInfo {
+            id: file_name.to_string(),
+            name: meta.name.clone().unwrap_or(stem),
+            description: meta.description.clone().unwrap_or_default(),
+            builtin: false,
+        },
+        content,
+        meta,
+    })
+}
+
+/// List the user templates in a directory, skipping unreadable ones
+pub fn list_user_templates(dir: &Path) -> Vec<Template> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|name| name.ends_with(".md") || name.ends_with(".kmd"))
+        .collect();
+    names.sort();
+
+    names
+        .iter()
+        .filter_map(|name| match load_user_template(dir, name) {
+            Ok(template) => Some(template),
+            Err(e) => {
+                log::warn!("Skipping template {}: {}", name, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Find a template by ID, built-in templates first
+fn find_template(id: &str) -> Result<Template, String> {
+    if let Some(template) = builtin_templates().into_iter().find(|t| t.info.id == id) {
+        return Ok(template);
+    }
+    load_user_template(&get_templates_dir()?, id)
+}
+
+/// List built-in and user templates
+#[tauri::command]
+pub fn list_templates() -> Result<Vec<TemplateInfo>, String> {
+    let mut templates: Vec<TemplateInfo> = builtin_templates().into_iter().map(|t| t.info).collect();
+    templates.extend(list_user_templates(&get_templates_dir()?).into_iter().map(|t| t.info));
+    Ok(templates)
+}
+
+/// Create a new document seeded with a template's content and metadata
+#[tauri::command]
+pub fn new_document_from_template(
+    window: Window,
+    manager: State<'_, Mutex<DocumentManager>>,
+    template_id: String,
+) -> Result<ImportResult, String> {
+    let template = find_template(&template_id)?;
+
+    let doc_id = Uuid::new_v4().to_string();
+    let temp_dir = create_document_temp_dir(&doc_id)?;
+
+    let handle = DocumentHandle {
+        id: doc_id.clone(),
+        path: None,
+        title: "Untitled Document".to_string(),
+        is_modified: true,
+        opened_at: Utc::now(),
+        read_only: false,
+        external_merge: None,
+        recovered: None,
+        safe_mode: None,
+    };
+
+    let meta = DocumentMeta {
+        authors: template.meta.authors,
+        settings: template.meta.settings,
+        ..DocumentMeta::default()
+    };
+
+    let state = DocumentState {
+        handle: handle.clone(),
+        yjs_state: Vec::new(), // Will be populated when editor loads
+        yjs_updates: Vec::new(),
+        history_path: temp_dir.join("history.sqlite"),
+        meta,
+        sections: HashMap::new(),
+        disk_fingerprint: None,
+    };
+
+    let mut manager = manager.lock().map_err(|e| e.to_string())?;
+    manager.documents.insert(doc_id.clone(), state);
+    manager.set_active(window.label(), doc_id);
+
+    Ok(ImportResult {
+        handle,
+        content: template.content,
+        source_format: "template".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_templates() {
+        let templates = builtin_templates();
+        let ids: Vec<&str> = templates.iter().map(|t| t.info.id.as_str()).collect();
+        assert_eq!(ids, vec!["article", "thesis-chapter", "meeting-notes"]);
+
+        let thesis = &templates[1];
+        assert!(thesis.meta.settings.crossref.chapter_numbering);
+        assert!(thesis.content.starts_with("# Chapter title"));
+    }
+
+    #[test]
+    fn test_user_templates_with_sidecar_and_reference_doc() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("report.md"), "# Report\n").unwrap();
+        fs::write(
+            dir.path().join("report.json"),
+            r#"{"name": "Quarterly report", "settings": {"language": "de-DE"}, "authors": [{"id": "a1", "name": "Ada"}]}"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("report.docx"), b"styles").unwrap();
+        fs::write(dir.path().join("plain.md"), "Plain\n").unwrap();
+        fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+
+        let templates = list_user_templates(dir.path());
+        assert_eq!(templates.len(), 2);
+
+        let plain = &templates[0];
+        assert_eq!(plain.info.name, "plain");
+        assert!(plain.meta.settings.reference_doc.is_none());
+
+        let report = &templates[1];
+        assert_eq!(report.info.id, "report.md");
+        assert_eq!(report.info.name, "Quarterly report");
+        assert!(!report.info.builtin);
+        assert_eq!(report.content, "# Report\n");
+        assert_eq!(report.meta.settings.language, "de-DE");
+        assert_eq!(report.meta.authors[0].name, "Ada");
+        assert_eq!(report.meta.settings.reference_doc, Some(dir.path().join("report.docx")));
+
+        assert!(load_user_template(dir.path(), "../report.md").is_err());
+    }
+}
@@ -0,0 +1,378 @@
+// src-tauri/src/trash.rs
+//! Document trash: deleted KMD files can be restored.
+//!
+//! `delete_document` moves the file into `trash/` in the config directory
+//! under a fresh ID and records where it came from in `trash/trash.json`.
+//! Restoring puts it back at its original path (or next to it, if that path
+//! is taken again). When the trash grows past its size limit, the oldest
+//! documents are purged for good.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::State;
+use uuid::Uuid;
+
+use crate::document_manager::{add_to_recent, remove_from_recent, DocumentManager};
+use crate::error::KorppiError;
+use crate::file_lock::{lock_path, read_lock};
+use crate::profile::get_config_dir;
+use crate::templates::read_kmd_meta;
+
+/// Current schema version of trash.json
+const TRASH_SCHEMA_VERSION: u32 = 1;
+
+/// Default size limit of the trash
+const DEFAULT_TRASH_LIMIT_BYTES: u64 = 500 * 1024 * 1024;
+
+/// A document in the trash
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TrashedDocument {
+    pub id: String,
+    pub title: String,
+    /// Where the file was when it was deleted
+    pub original_path: PathBuf,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub deleted_at: DateTime<Utc>,
+    /// File size in bytes
+    pub size: u64,
+}
+
+fn default_limit() -> u64 {
+    DEFAULT_TRASH_LIMIT_BYTES
+}
+
+/// On-disk layout of trash.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TrashFile {
+    version: u32,
+    /// Oldest documents are purged beyond this total size
+    #[serde(default = "default_limit")]
+    max_size_bytes: u64,
+    #[serde(default)]
+    documents: Vec<TrashedDocument>,
+}
+
+impl Default for TrashFile {
+    fn default() -> Self {
+        Self {
+            version: TRASH_SCHEMA_VERSION,
+            max_size_bytes: DEFAULT_TRASH_LIMIT_BYTES,
+            documents: Vec::new(),
+        }
+    }
+}
+
+/// Trash contents and settings, as shown to the user
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TrashListing {
+    /// Newest first
+    pub documents: Vec<TrashedDocument>,
+    pub total_size: u64,
+    pub max_size_bytes: u64,
+}
+
+fn get_trash_dir() -> Result<PathBuf, String> {
+    get_config_dir().map(|p| p.join("trash"))
+}
+
+/// File of a trashed document
+fn trashed_path(trash_dir: &Path, id: &str) -> PathBuf {
+    trash_dir.join(format!("{}.kmd", id))
+}
+
+/// Load trash.json
+fn load_trash_file(trash_dir: &Path) -> Result<TrashFile, String> {
+    let path = trash_dir.join("trash.json");
+    if !path.exists() {
+        return Ok(TrashFile::default());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let mut file: TrashFile = serde_json::from_str(&content).map_err(|e| format!("Invalid trash.json: {}", e))?;
+    file.version = TRASH_SCHEMA_VERSION;
+    Ok(file)
+}
+
+/// Save trash.json
+fn save_trash_file(trash_dir: &Path, file: &TrashFile) -> Result<(), String> {
+    fs::create_dir_all(trash_dir).map_err(|e| e.to_string())?;
+    let content = serde_json::to_string_pretty(file).map_err(|e| e.to_string())?;
+    fs::write(trash_dir.join("trash.json"), content).map_err(|e| e.to_string())
+}
+
+/// Move a file, copying when the trash is on another file system
+fn move_file(from: &Path, to: &Path) -> Result<(), String> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to).map_err(|e| format!("Failed to move {}: {}", from.display(), e))?;
+    fs::remove_file(from).map_err(|e| format!("Failed to remove {}: {}", from.display(), e))
+}
+
+/// A free path for a restored file: the original, or "name (restored N).kmd"
+fn restore_target(original: &Path) -> PathBuf {
+    if !original.exists() {
+        return original.to_path_buf();
+    }
+    let stem = original.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let extension = original.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_else(|| "kmd".to_string());
+    (1..)
+        .map(|n| {
+            let suffix = if n == 1 { String::new() } else { format!(" {}", n) };
+            original.with_file_name(format!("{} (restored{}).{}", stem, suffix, extension))
+        })
+        .find(|p| !p.exists())
+        .expect("some restore name is free")
+}
+
+/// Purge the oldest documents until the trash fits its limit, never the
+/// one with ID `keep`
+///
+/// A document is not purged by its own trashing, even when it is larger than
+/// the whole trash. Returns the purged documents.
+fn enforce_limit(trash_dir: &Path, file: &mut TrashFile, keep: Option<&str>) -> Vec<TrashedDocument> {
+    file.documents.sort_by_key(|d| d.deleted_at);
+    let mut total: u64 = file.documents.iter().map(|d| d.size).sum();
+    let mut purged = Vec::new();
+    while total > file.max_size_bytes {
+        let Some(index) = file.documents.iter().position(|d| Some(d.id.as_str()) != keep) else {
+            break;
+        };
+        let oldest = file.documents.remove(index);
+        fs::remove_file(trashed_path(trash_dir, &oldest.id)).ok();
+        total -= oldest.size;
+        purged.push(oldest);
+    }
+    purged
+}
+
+/// Move a KMD file into the trash
+pub fn move_to_trash(trash_dir: &Path, path: &Path) -> Result<TrashedDocument, String> {
+    let metadata = fs::metadata(path).map_err(|e| format!("Cannot delete {}: {}", path.display(), e))?;
+    let title = read_kmd_meta(path)
+        .ok()
+        .map(|meta| meta.title)
+        .filter(|t| !t.is_empty() && t != "Untitled Document")
+        .or_else(|| path.file_stem().map(|s| s.to_string_lossy().to_string()))
+        .unwrap_or_default();
+
+    let mut file = load_trash_file(trash_dir)?;
+    fs::create_dir_all(trash_dir).map_err(|e| e.to_string())?;
+    let document = TrashedDocument {
+        id: Uuid::new_v4().to_string(),
+        title,
+        original_path: path.to_path_buf(),
+        deleted_at: Utc::now(),
+        size: metadata.len(),
+    };
+    move_file(path, &trashed_path(trash_dir, &document.id))?;
+    // A stale lock would keep the restored file read-only
+    fs::remove_file(lock_path(path)).ok();
+
+    file.documents.push(document.clone());
+    for purged in enforce_limit(trash_dir, &mut file, Some(&document.id)) {
+        log::info!("Purged {} from the trash to stay under its size limit", purged.original_path.display());
+    }
+    save_trash_file(trash_dir, &file)?;
+    Ok(document)
+}
+
+/// Put a trashed document back; returns where it was restored to
+pub fn restore_from_trash(trash_dir: &Path, id: &str) -> Result<PathBuf, String> {
+    let mut file = load_trash_file(trash_dir)?;
+    let index = file
+        .documents
+        .iter()
+        .position(|d| d.id == id)
+        .ok_or_else(|| format!("Not in the trash: {}", id))?;
+    let document = &file.documents[index];
+
+    let target = restore_target(&document.original_path);
+    if let Some(parent) = target.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to recreate {}: {}", parent.display(), e))?;
+    }
+    move_file(&trashed_path(trash_dir, id), &target)?;
+
+    file.documents.remove(index);
+    save_trash_file(trash_dir, &file)?;
+    Ok(target)
+}
+
+/// Delete trashed documents for good; all of them when `id` is None
+pub fn purge_from_trash(trash_dir: &Path, id: Option<&str>) -> Result<usize, String> {
+    let mut file = load_trash_file(trash_dir)?;
+    let (purged, kept): (Vec<_>, Vec<_>) = file
+        .documents
+        .into_iter()
+        .partition(|d| id.is_none_or(|id| d.id == id));
+    if let (Some(id), true) = (id, purged.is_empty()) {
+        return Err(format!("Not in the trash: {}", id));
+    }
+    for document in &purged {
+        fs::remove_file(trashed_path(trash_dir, &document.id)).ok();
+    }
+    file.documents = kept;
+    save_trash_file(trash_dir, &file)?;
+    Ok(purged.len())
+}
+
+/// List the trash, newest first
+fn trash_listing(trash_dir: &Path) -> Result<TrashListing, String> {
+    let file = load_trash_file(trash_dir)?;
+    let mut documents = file.documents;
+    // Ties keep their trashing order, so reversing puts the newest first
+    documents.sort_by_key(|d| d.deleted_at);
+    documents.reverse();
+    Ok(TrashListing {
+        total_size: documents.iter().map(|d| d.size).sum(),
+        documents,
+        max_size_bytes: file.max_size_bytes,
+    })
+}
+
+/// Move a document file to the trash and drop it from the recent list
+///
+/// The document must not be open here or locked by someone else.
+#[tauri::command]
+pub fn delete_document(
+    manager: State<'_, Mutex<DocumentManager>>,
+    path: PathBuf,
+) -> Result<TrashedDocument, KorppiError> {
+    {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        if manager.document_at(&path).is_some() {
+            return Err(KorppiError::InvalidInput("Close the document before deleting it".to_string()));
+        }
+    }
+    if let Some(lock) = read_lock(&path) {
+        return Err(KorppiError::InvalidInput(format!(
+            "Document is open by {} on {}",
+            lock.user, lock.host
+        )));
+    }
+    let document = move_to_trash(&get_trash_dir()?, &path)?;
+    remove_from_recent(&path)?;
+    Ok(document)
+}
+
+/// List the documents in the trash, newest first
+#[tauri::command]
+pub fn list_trashed_documents() -> Result<TrashListing, KorppiError> {
+    Ok(trash_listing(&get_trash_dir()?)?)
+}
+
+/// Restore a trashed document; returns its path
+#[tauri::command]
+pub fn restore_trashed_document(trash_id: String) -> Result<PathBuf, KorppiError> {
+    let trash_dir = get_trash_dir()?;
+    let title = load_trash_file(&trash_dir)?
+        .documents
+        .into_iter()
+        .find(|d| d.id == trash_id)
+        .map(|d| d.title)
+        .unwrap_or_default();
+    let path = restore_from_trash(&trash_dir, &trash_id)?;
+    add_to_recent(path.clone(), title)?;
+    Ok(path)
+}
+
+/// Delete a trashed document for good, or every one if no ID is given;
+/// returns how many were purged
+#[tauri::command]
+pub fn purge_trashed_documents(trash_id: Option<String>) -> Result<usize, KorppiError> {
+    Ok(purge_from_trash(&get_trash_dir()?, trash_id.as_deref())?)
+}
+
+/// Change the size limit of the trash, purging the oldest documents beyond it
+#[tauri::command]
+pub fn set_trash_size_limit(max_size_bytes: u64) -> Result<TrashListing, KorppiError> {
+    let trash_dir = get_trash_dir()?;
+    let mut file = load_trash_file(&trash_dir)?;
+    file.max_size_bytes = max_size_bytes;
+    enforce_limit(&trash_dir, &mut file, None);
+    save_trash_file(&trash_dir, &file)?;
+    Ok(trash_listing(&trash_dir)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_file(path: &Path, size: usize) {
+        fs::write(path, vec![b'x'; size]).unwrap();
+    }
+
+    #[test]
+    fn test_trash_and_restore() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let trash = dir.path().join("trash");
+        let kmd = dir.path().join("docs").join("paper.kmd");
+        fs::create_dir_all(kmd.parent().unwrap()).unwrap();
+        write_file(&kmd, 10);
+
+        let trashed = move_to_trash(&trash, &kmd).unwrap();
+        assert!(!kmd.exists());
+        assert_eq!(trashed.title, "paper");
+        assert_eq!(trashed.size, 10);
+        let listing = trash_listing(&trash).unwrap();
+        assert_eq!(listing.documents.len(), 1);
+        assert_eq!(listing.documents[0].id, trashed.id);
+        assert_eq!(listing.documents[0].original_path, kmd);
+
+        // A new file took the old name
+        write_file(&kmd, 3);
+        let restored = restore_from_trash(&trash, &trashed.id).unwrap();
+        assert_eq!(restored, dir.path().join("docs").join("paper (restored).kmd"));
+        assert_eq!(fs::metadata(&restored).unwrap().len(), 10);
+        assert!(trash_listing(&trash).unwrap().documents.is_empty());
+        assert!(restore_from_trash(&trash, &trashed.id).is_err());
+    }
+
+    #[test]
+    fn test_size_limit_and_purge() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let trash = dir.path().join("trash");
+        save_trash_file(&trash, &TrashFile { max_size_bytes: 25, ..TrashFile::default() }).unwrap();
+
+        let mut trashed = Vec::new();
+        for name in ["a", "b", "c"] {
+            let path = dir.path().join(format!("{}.kmd", name));
+            write_file(&path, 10);
+            trashed.push(move_to_trash(&trash, &path).unwrap());
+        }
+        // The oldest went to stay under 25 bytes
+        let listing = trash_listing(&trash).unwrap();
+        let titles: Vec<&str> = listing.documents.iter().map(|d| d.title.as_str()).collect();
+        assert_eq!(titles, vec!["c", "b"]);
+        assert_eq!(listing.total_size, 20);
+        assert!(!trashed_path(&trash, &trashed[0].id).exists());
+
+        assert_eq!(purge_from_trash(&trash, Some(&trashed[1].id)).unwrap(), 1);
+        assert!(purge_from_trash(&trash, Some(&trashed[1].id)).is_err());
+        assert_eq!(purge_from_trash(&trash, None).unwrap(), 1);
+        assert!(!trashed_path(&trash, &trashed[2].id).exists());
+    }
+
+    #[test]
+    fn test_document_larger_than_trash() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let trash = dir.path().join("trash");
+        save_trash_file(&trash, &TrashFile { max_size_bytes: 25, ..TrashFile::default() }).unwrap();
+
+        let small = dir.path().join("small.kmd");
+        write_file(&small, 10);
+        let small = move_to_trash(&trash, &small).unwrap();
+        let large = dir.path().join("large.kmd");
+        write_file(&large, 40);
+        let large = move_to_trash(&trash, &large).unwrap();
+
+        // The older document makes room, the new one stays restorable
+        let listing = trash_listing(&trash).unwrap();
+        let ids: Vec<&str> = listing.documents.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec![large.id.as_str()]);
+        assert!(!trashed_path(&trash, &small.id).exists());
+        assert_eq!(restore_from_trash(&trash, &large.id).unwrap(), dir.path().join("large.kmd"));
+    }
+}
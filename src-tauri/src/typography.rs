@@ -0,0 +1,197 @@
+// src-tauri/src/typography.rs
+//! Locale-aware typography pass applied on export.
+//!
+//! Converts straight quotes to the quotation marks of the document language,
+//! `---`/`--` to em/en dashes and `...` to an ellipsis, and inserts the
+//! non-breaking spaces French requires before high punctuation. Code, math,
+//! link targets, attributes, HTML and front matter are left untouched.
+
+use regex::Regex;
+
+/// Quotation marks for a language: (double open, double close, single open, single close)
+fn quote_marks(language: &str) -> (&'static str, &'static str, &'static str, &'static str) {
+    let base = language.split(['-', '_']).next().unwrap_or("").to_lowercase();
+    match base.as_str() {
+        "de" | "cs" | "sk" => ("„", "“", "‚", "‘"),
+        // Guillemets carry a no-break space on the inside in French
+        "fr" => ("«\u{00A0}", "\u{00A0}»", "“", "”"),
+        "es" | "it" | "pt" | "ru" | "uk" => ("«", "»", "“", "”"),
+        "fi" | "sv" => ("”", "”", "’", "’"),
+        "pl" | "hu" | "ro" => ("„", "”", "‚", "’"),
+        _ => ("“", "”", "‘", "’"),
+    }
+}
+
+fn is_french(language: &str) -> bool {
+    language.to_lowercase().starts_with("fr")
+}
+
+/// Is a quote at this position opening (preceded by nothing, space or an opening bracket)?
+fn is_opening(prev: Option<char>) -> bool {
+    match prev {
+        None => true,
+        Some(c) => c.is_whitespace() || "([{—–-/".contains(c),
+    }
+}
+
+/// Apply typography rules to a span of prose
+fn transform_prose(text: &str, language: &str) -> String {
+    let (dq_open, dq_close, sq_open, sq_close) = quote_marks(language);
+
+    let text = text.replace("---", "—").replace("--", "–").replace("...", "…");
+
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = String::with_capacity(text.len());
+    let mut prev: Option<char> = None;
+    for (i, &c) in chars.iter().enumerate() {
+        let next = chars.get(i + 1).copied();
+        match c {
+            '"' => {
+                if is_opening(prev) {
+                    result.push_str(dq_open);
+                } else {
+                    result.push_str(dq_close);
+                }
+            }
+            '\'' => {
+                let inside_word = prev.is_some_and(|p| p.is_alphanumeric())
+                    && next.is_some_and(|n| n.is_alphanumeric());
+                if inside_word {
+                    // Apostrophe
+                    result.push('’');
+                } else if is_opening(prev) && next.is_some_and(|n| !n.is_whitespace()) {
+                    result.push_str(sq_open);
+                } else {
+                    result.push_str(sq_close);
+                }
+            }
+            _ => result.push(c),
+        }
+        prev = Some(c);
+    }
+
+    if is_french(language) {
+        // Narrow no-break space before ; ! ? and no-break space before :
+        let high_re = Regex::new(r"([\p{L}\p{N}»)])[ \u{00A0}]?([;!?]+)(\s|$|[*_)\]»])").unwrap();
+        result = high_re.replace_all(&result, "$1\u{202F}$2$3").to_string();
+        let colon_re = Regex::new(r"(\p{L}|»|\))[ ]?:(\s|$)").unwrap();
+        result = colon_re.replace_all(&result, "$1\u{00A0}:$2").to_string();
+    }
+
+    result
+}
+
+/// Apply typography to a single line, skipping protected spans
+fn transform_line(line: &str, language: &str, protected: &Regex) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut last = 0;
+    for m in protected.find_iter(line) {
+        result.push_str(&transform_prose(&line[last..m.start()], language));
+        result.push_str(m.as_str());
+        last = m.end();
+    }
+    result.push_str(&transform_prose(&line[last..], language));
+    result
+}
+
+/// Apply locale-aware typography to a markdown document
+pub fn apply_typography(markdown: &str, language: &str) -> String {
+    // Inline code, math, link targets, attributes, HTML tags, autolinks and bare URLs
+    let protected = Regex::new(
+        r"`[^`]*`|\$[^$]+\$|\]\([^)]*\)|\]\[[^\]]*\]|\{[^}]*\}|<[^>]+>|https?://\S+|^\[[^\]]+\]:.*$",
+    )
+    .unwrap();
+    // Thematic breaks and table separator rows must keep their hyphens
+    let rule_re = Regex::new(r"^\s*([-*_]\s*){3,}$|^\s*\|?\s*:?-+:?\s*(\|\s*:?-+:?\s*)*\|?\s*$").unwrap();
+
+    let mut result = Vec::new();
+    let mut in_fence: Option<String> = None;
+    let mut in_front_matter = false;
+
+    for (i, line) in markdown.split('\n').enumerate() {
+        let trimmed = line.trim_start();
+
+        if i == 0 && line.trim_end() == "---" {
+            in_front_matter = true;
+            result.push(line.to_string());
+            continue;
+        }
+        if in_front_matter {
+            if matches!(line.trim_end(), "---" | "...") {
+                in_front_matter = false;
+            }
+            result.push(line.to_string());
+            continue;
+        }
+
+        if let Some(fence) = &in_fence {
+            if trimmed.starts_with(fence.as_str()) {
+                in_fence = None;
+            }
+            result.push(line.to_string());
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = Some(trimmed[..3].to_string());
+            result.push(line.to_string());
+            continue;
+        }
+
+        // Indented code blocks and display math
+        if line.starts_with("    ") || line.starts_with('\t') || trimmed.starts_with("$$") || rule_re.is_match(line) {
+            result.push(line.to_string());
+            continue;
+        }
+
+        result.push(transform_line(line, language, &protected));
+    }
+
+    result.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_english_quotes_and_dashes() {
+        let input = "She said \"it's fine\" --- and 'maybe' later...";
+        assert_eq!(
+            apply_typography(input, "en-US"),
+            "She said “it’s fine” — and ‘maybe’ later…"
+        );
+    }
+
+    #[test]
+    fn test_german_quotes() {
+        assert_eq!(apply_typography("Er sagte \"Hallo\".", "de-DE"), "Er sagte „Hallo“.");
+    }
+
+    #[test]
+    fn test_french_spacing() {
+        let output = apply_typography("Il a dit \"bonjour\" : quoi? Oui!", "fr-FR");
+        assert_eq!(
+            output,
+            "Il a dit «\u{00A0}bonjour\u{00A0}»\u{00A0}: quoi\u{202F}? Oui\u{202F}!"
+        );
+    }
+
+    #[test]
+    fn test_protected_regions_untouched() {
+        let input = "---\ntitle: \"A -- B\"\n---\n\nUse `a -- b` and [\"link\"](http://x.org/a--b){#fig:x}\n\n```\nlet s = \"x\";\n```\n\n| a | b |\n|---|---|\n\n***";
+        let output = apply_typography(input, "en");
+
+        assert!(output.contains("title: \"A -- B\""));
+        assert!(output.contains("`a -- b`"));
+        assert!(output.contains("[“link”](http://x.org/a--b){#fig:x}"));
+        assert!(output.contains("let s = \"x\";"));
+        assert!(output.contains("|---|---|"));
+        assert!(output.ends_with("***"));
+    }
+
+    #[test]
+    fn test_french_image_not_broken() {
+        let output = apply_typography("Voir ![figure](a.png) ici", "fr");
+        assert_eq!(output, "Voir ![figure](a.png) ici");
+    }
+}
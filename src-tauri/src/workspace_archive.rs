@@ -0,0 +1,352 @@
+// src-tauri/src/workspace_archive.rs
+//! Workspace archives: a whole workspace in one file, to move it to
+//! another computer.
+//!
+//! A `.kmd-workspace` file is a ZIP holding `manifest.json` (the workspace
+//! name and its documents), the KMD files of the chosen members under
+//! `documents/`, and the profile of this computer, `profile.toml`, and
+//! optionally its secret key, `identity.key`. The KMD files carry their
+//! Yjs state, history and author profiles, so collaboration picks up where
+//! it left off; the secret key is needed to open patch bundles
+//! collaborators encrypted for this profile.
+//!
+//! Importing writes the documents to a folder, creates the workspace with
+//! their new paths and restores the profile and key, unless the computer
+//! has a profile or key of its own already.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::State;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::bundle_crypto::{read_identity, restore_identity};
+use crate::document_manager::DocumentManager;
+use crate::error::KorppiError;
+use crate::kmd::is_path_safe;
+use crate::profile::{get_profile_file_path, load_saved_profile, write_profile, UserProfile};
+use crate::templates::read_kmd_meta;
+use crate::workspaces::{create_workspace, load_workspace, Workspace};
+
+/// Current version of the archive layout
+pub const WORKSPACE_ARCHIVE_VERSION: u32 = 1;
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const DOCUMENTS_DIR: &str = "documents/";
+const PROFILE_ENTRY: &str = "profile.toml";
+const IDENTITY_ENTRY: &str = "identity.key";
+
+/// A document in a workspace archive
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ArchivedDocument {
+    /// Entry of the KMD file, under `documents/`
+    pub entry: String,
+    /// File name to restore it as
+    pub file_name: String,
+    pub uuid: Option<String>,
+    pub title: String,
+    /// Where the file was on the computer it was exported from
+    pub original_path: PathBuf,
+}
+
+/// Contents of manifest.json
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WorkspaceArchiveManifest {
+    pub version: u32,
+    pub workspace_name: String,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub exported_at: DateTime<Utc>,
+    pub documents: Vec<ArchivedDocument>,
+    pub has_profile: bool,
+    pub has_identity: bool,
+}
+
+/// Outcome of exporting a workspace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceArchiveExport {
+    pub manifest: WorkspaceArchiveManifest,
+    /// Open documents with unsaved changes, archived as last saved
+    pub warnings: Vec<String>,
+}
+
+/// Outcome of importing a workspace archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceArchiveImport {
+    pub workspace: Workspace,
+    /// Restored KMD files, in the order of the workspace
+    pub documents: Vec<PathBuf>,
+    pub profile_restored: bool,
+    pub identity_restored: bool,
+    /// Files renamed to keep existing ones, and a profile or key left alone
+    pub warnings: Vec<String>,
+}
+
+/// Files unpacked from a workspace archive
+pub struct UnpackedWorkspace {
+    pub manifest: WorkspaceArchiveManifest,
+    pub documents: Vec<PathBuf>,
+    pub profile: Option<String>,
+    pub identity: Option<String>,
+    pub warnings: Vec<String>,
+}
+
+/// A path in a folder for a file name, numbered when the name is taken
+fn free_path(dir: &Path, file_name: &str) -> PathBuf {
+    let candidate = dir.join(file_name);
+    if !candidate.exists() {
+        return candidate;
+    }
+    let name = Path::new(file_name);
+    let stem = name.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let extension = name.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    (2..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+        .find(|path| !path.exists())
+        .expect("some numbered name is free")
+}
+
+/// Write KMD files, a profile and a secret key to a workspace archive
+pub fn write_workspace_archive(
+    path: &Path,
+    workspace_name: &str,
+    documents: &[PathBuf],
+    profile: Option<&str>,
+    identity: Option<&str>,
+) -> Result<WorkspaceArchiveManifest, String> {
+    let mut manifest = WorkspaceArchiveManifest {
+        version: WORKSPACE_ARCHIVE_VERSION,
+        workspace_name: workspace_name.to_string(),
+        exported_at: Utc::now(),
+        documents: Vec::with_capacity(documents.len()),
+        has_profile: profile.is_some(),
+        has_identity: identity.is_some(),
+    };
+
+    let file = File::create(path).map_err(|e| format!("Failed to create archive: {}", e))?;
+    let mut zip = ZipWriter::new(file);
+    // KMD files are compressed already
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (i, document) in documents.iter().enumerate() {
+        let file_name = document
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .ok_or_else(|| format!("Not a file: {}", document.display()))?;
+        let entry = format!("{}{:03}-{}", DOCUMENTS_DIR, i + 1, file_name);
+        let meta = read_kmd_meta(document).ok();
+        zip.start_file(&entry, stored).map_err(|e| e.to_string())?;
+        let mut source = File::open(document).map_err(|e| format!("Failed to read {}: {}", document.display(), e))?;
+        std::io::copy(&mut source, &mut zip).map_err(|e| e.to_string())?;
+        manifest.documents.push(ArchivedDocument {
+            entry,
+            title: meta
+                .as_ref()
+                .map(|m| m.title.clone())
+                .filter(|t| !t.is_empty())
+                .unwrap_or_else(|| file_name.trim_end_matches(".kmd").to_string()),
+            uuid: meta.map(|m| m.uuid),
+            file_name,
+            original_path: document.clone(),
+        });
+    }
+    if let Some(profile) = profile {
+        zip.start_file(PROFILE_ENTRY, options).map_err(|e| e.to_string())?;
+        zip.write_all(profile.as_bytes()).map_err(|e| e.to_string())?;
+    }
+    if let Some(identity) = identity {
+        zip.start_file(IDENTITY_ENTRY, options).map_err(|e| e.to_string())?;
+        zip.write_all(identity.as_bytes()).map_err(|e| e.to_string())?;
+    }
+    zip.start_file(MANIFEST_ENTRY, options).map_err(|e| e.to_string())?;
+    let content = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    zip.write_all(content.as_bytes()).map_err(|e| e.to_string())?;
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(manifest)
+}
+
+fn read_text<R: Read + std::io::Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<Option<String>, String> {
+    let Ok(mut entry) = archive.by_name(name) else {
+        return Ok(None);
+    };
+    let mut content = String::new();
+    entry.read_to_string(&mut content).map_err(|e| format!("Invalid {}: {}", name, e))?;
+    Ok(Some(content))
+}
+
+/// Unpack the KMD files of a workspace archive into a folder, keeping
+/// the files already there
+pub fn unpack_workspace_archive(path: &Path, target_dir: &Path) -> Result<UnpackedWorkspace, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open archive: {}", e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| format!("Invalid workspace archive: {}", e))?;
+    let manifest: WorkspaceArchiveManifest = {
+        let content = read_text(&mut archive, MANIFEST_ENTRY)?.ok_or("Missing manifest.json in workspace archive")?;
+        serde_json::from_str(&content).map_err(|e| format!("Invalid manifest.json: {}", e))?
+    };
+    if manifest.version > WORKSPACE_ARCHIVE_VERSION {
+        return Err(format!(
+            "Workspace archive version {} is newer than supported version {}",
+            manifest.version, WORKSPACE_ARCHIVE_VERSION
+        ));
+    }
+
+    fs::create_dir_all(target_dir).map_err(|e| format!("Failed to create {}: {}", target_dir.display(), e))?;
+    let mut documents = Vec::with_capacity(manifest.documents.len());
+    let mut warnings = Vec::new();
+    for document in &manifest.documents {
+        let valid_name = !document.file_name.is_empty()
+            && is_path_safe(&document.file_name)
+            && !document.file_name.contains(['/', '\\']);
+        if !valid_name || !document.entry.starts_with(DOCUMENTS_DIR) || !is_path_safe(&document.entry) {
+            return Err(format!("Invalid document in workspace archive: {}", document.entry));
+        }
+        let mut entry = archive
+            .by_name(&document.entry)
+            .map_err(|_| format!("Missing {} in workspace archive", document.entry))?;
+        let target = free_path(target_dir, &document.file_name);
+        if target.file_name().is_some_and(|n| n.to_string_lossy() != document.file_name) {
+            warnings.push(format!("{} exists; restored as {}", document.file_name, target.display()));
+        }
+        let mut out = File::create(&target).map_err(|e| format!("Failed to write {}: {}", target.display(), e))?;
+        std::io::copy(&mut entry, &mut out).map_err(|e| e.to_string())?;
+        documents.push(target);
+    }
+
+    Ok(UnpackedWorkspace {
+        profile: read_text(&mut archive, PROFILE_ENTRY)?,
+        identity: read_text(&mut archive, IDENTITY_ENTRY)?,
+        manifest,
+        documents,
+        warnings,
+    })
+}
+
+/// Put a workspace, or some of its documents, in one archive with this
+/// computer's profile and, if asked, its secret key
+///
+/// Documents are archived as last saved.
+#[tauri::command]
+pub fn export_workspace_archive(
+    manager: State<'_, Mutex<DocumentManager>>,
+    workspace_id: String,
+    path: String,
+    documents: Option<Vec<PathBuf>>,
+    include_identity: Option<bool>,
+) -> Result<WorkspaceArchiveExport, KorppiError> {
+    let workspace = load_workspace(&workspace_id)?;
+    let documents = match documents {
+        Some(selected) => {
+            if let Some(outsider) = selected.iter().find(|p| !workspace.documents.contains(p)) {
+                return Err(KorppiError::InvalidInput(format!("Not in the workspace: {}", outsider.display())));
+            }
+            selected
+        }
+        None => workspace.documents.clone(),
+    };
+    if let Some(missing) = documents.iter().find(|p| !p.exists()) {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("File not found: {:?}", missing)).into());
+    }
+
+    let warnings = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        documents
+            .iter()
+            .filter_map(|path| manager.document_at(path).and_then(|id| manager.documents.get(id)))
+            .filter(|doc| doc.handle.is_modified)
+            .map(|doc| format!("{} has unsaved changes; the archive has the last saved version", doc.handle.title))
+            .collect()
+    };
+    let profile_path = get_profile_file_path()?;
+    let profile = profile_path.exists().then(|| fs::read_to_string(&profile_path)).transpose()?;
+    let identity = if include_identity.unwrap_or(false) { read_identity()? } else { None };
+
+    let manifest =
+        write_workspace_archive(Path::new(&path), &workspace.name, &documents, profile.as_deref(), identity.as_deref())?;
+    Ok(WorkspaceArchiveExport { manifest, warnings })
+}
+
+/// Restore a workspace archive: its documents go to `target_dir`, and the
+/// profile and secret key are restored unless this computer has its own
+#[tauri::command]
+pub fn import_workspace_archive(path: String, target_dir: String) -> Result<WorkspaceArchiveImport, KorppiError> {
+    let unpacked = unpack_workspace_archive(Path::new(&path), Path::new(&target_dir))?;
+    let mut warnings = unpacked.warnings;
+
+    // The key first: the restored profile's public key derives from it
+    let identity_restored = match &unpacked.identity {
+        Some(key) => {
+            let restored = restore_identity(key)?;
+            if !restored {
+                warnings.push("This computer has an encryption key of its own; it was kept".to_string());
+            }
+            restored
+        }
+        None => false,
+    };
+    let mut profile_restored = false;
+    if let Some(content) = &unpacked.profile {
+        let archived: UserProfile = toml::from_str(content).map_err(|e| format!("Invalid profile.toml: {}", e))?;
+        match load_saved_profile()? {
+            Some(local) if local.id != archived.id => {
+                warnings.push(format!("This computer has the profile of {}; it was kept", local.name));
+            }
+            Some(_) => {}
+            None => {
+                write_profile(&archived)?;
+                profile_restored = true;
+            }
+        }
+    }
+
+    let workspace = create_workspace(unpacked.manifest.workspace_name.clone(), Some(unpacked.documents.clone()))?;
+    Ok(WorkspaceArchiveImport {
+        workspace,
+        documents: unpacked.documents,
+        profile_restored,
+        identity_restored,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workspace_archive_round_trip() {
+        let source = tempfile::TempDir::new().unwrap();
+        let paper = source.path().join("paper.kmd");
+        let letter = source.path().join("letter.kmd");
+        fs::write(&paper, b"paper bytes").unwrap();
+        fs::write(&letter, b"letter bytes").unwrap();
+
+        let archive = source.path().join("thesis.kmd-workspace");
+        let manifest = write_workspace_archive(
+            &archive,
+            "Thesis",
+            &[paper.clone(), letter.clone()],
+            Some("id = \"a\"\nname = \"Alice\"\ncolor = \"#3498db\"\n"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(manifest.documents.len(), 2);
+        assert_eq!(manifest.documents[0].title, "paper");
+        assert!(manifest.has_profile && !manifest.has_identity);
+
+        let target = tempfile::TempDir::new().unwrap();
+        fs::write(target.path().join("letter.kmd"), b"kept").unwrap();
+        let unpacked = unpack_workspace_archive(&archive, target.path()).unwrap();
+        assert_eq!(unpacked.manifest.documents, manifest.documents);
+        assert_eq!(unpacked.documents[0], target.path().join("paper.kmd"));
+        // An existing file is kept and the archived one numbered
+        assert_eq!(unpacked.documents[1], target.path().join("letter (2).kmd"));
+        assert_eq!(fs::read(target.path().join("letter.kmd")).unwrap(), b"kept");
+        assert_eq!(fs::read(&unpacked.documents[1]).unwrap(), b"letter bytes");
+        assert_eq!(unpacked.warnings.len(), 1);
+        assert!(unpacked.profile.unwrap().contains("Alice"));
+        assert!(unpacked.identity.is_none());
+    }
+}
@@ -0,0 +1,837 @@
+// src-tauri/src/workspaces.rs
+//! Workspaces: named groups of related documents, such as a paper, its
+//! supplementary materials and the cover letter.
+//!
+//! Workspaces are kept in `workspace.json` in the config directory and list
+//! their documents by path. Members can be opened together, searched
+//! together and followed through their recent saves. Open members are read
+//! from their editor state, closed ones from their KMD file.
+//!
+//! Workspace members also make up the registry of documents that others can
+//! reference by UUID: `@doc:<uuid>/sec:<label>` cites a section of another
+//! document, and `!@doc:<uuid>/sec:<label>` on a line of its own copies the
+//! section in. Both are resolved on export; references that do not resolve
+//! are reported by `check_document_references`.
+
+use chrono::{DateTime, Utc};
+use regex::{Regex, RegexBuilder};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, State, Window};
+use uuid::Uuid;
+
+use crate::db_utils::ensure_schema;
+use crate::document_metadata::metadata_matches;
+use crate::document_manager::{
+    author_display_name, cleanup_document_temp_dir, current_document_text, extract_kmd_to_temp, open_document,
+    read_kmd_markdown, row_to_patch, DocumentHandle, DocumentManager,
+};
+use crate::error::KorppiError;
+use crate::file_progress::FileJobs;
+use crate::kmd::{section_reference_text, DocumentMeta, DocumentSettings};
+use crate::outline::{build_outline, find_section};
+use crate::profile::get_config_dir;
+use crate::templates::read_kmd_meta;
+
+/// Current schema version of workspace.json
+const WORKSPACE_SCHEMA_VERSION: u32 = 1;
+
+/// Matches returned per document by a workspace search
+const MAX_MATCHES_PER_DOCUMENT: usize = 50;
+
+/// Characters of context on each side of a search match
+const SNIPPET_CONTEXT: usize = 40;
+
+/// Saves returned by default by `get_workspace_activity`
+const DEFAULT_ACTIVITY_LIMIT: usize = 50;
+
+/// A cross-document reference, e.g. `@doc:<uuid>/sec:methods`
+const DOCUMENT_REFERENCE: &str = r"@doc:([0-9A-Za-z-]+)/(sec:[A-Za-z0-9_-]+)";
+
+/// A named group of documents
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Workspace {
+    pub id: String,
+    pub name: String,
+    /// Member KMD files, in the order they were added
+    pub documents: Vec<PathBuf>,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// On-disk layout of workspace.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WorkspaceFile {
+    version: u32,
+    #[serde(default)]
+    workspaces: Vec<Workspace>,
+}
+
+impl Default for WorkspaceFile {
+    fn default() -> Self {
+        Self {
+            version: WORKSPACE_SCHEMA_VERSION,
+            workspaces: Vec::new(),
+        }
+    }
+}
+
+impl WorkspaceFile {
+    fn workspace_mut(&mut self, workspace_id: &str) -> Result<&mut Workspace, String> {
+        self.workspaces
+            .iter_mut()
+            .find(|w| w.id == workspace_id)
+            .ok_or_else(|| format!("Workspace not found: {}", workspace_id))
+    }
+}
+
+impl Workspace {
+    /// Add a document unless it is already a member
+    fn add_document(&mut self, path: PathBuf) -> bool {
+        if self.documents.contains(&path) {
+            return false;
+        }
+        self.documents.push(path);
+        true
+    }
+
+    /// Remove a document, returning whether it was a member
+    fn remove_document(&mut self, path: &Path) -> bool {
+        let before = self.documents.len();
+        self.documents.retain(|p| p != path);
+        self.documents.len() != before
+    }
+}
+
+/// What happened to one member of an opened workspace
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum WorkspaceMemberOpen {
+    /// Opened now, or already open
+    Opened { path: PathBuf, handle: DocumentHandle },
+    /// The file is missing or could not be opened
+    Failed { path: PathBuf, error: KorppiError },
+}
+
+/// A search match in a member document
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WorkspaceSearchMatch {
+    pub path: PathBuf,
+    pub title: String,
+    /// Document ID if the document is open
+    pub doc_id: Option<String>,
+    /// Line of the match, from 1
+    pub line: usize,
+    /// Character index of the match in the markdown (UTF-16)
+    pub offset: usize,
+    /// The matched line around the match
+    pub snippet: String,
+    /// Match position in the snippet (UTF-16, end exclusive)
+    pub snippet_start: usize,
+    pub snippet_end: usize,
+    /// Metadata field the match is in ("abstract", "keywords" or a custom
+    /// field), `None` in the text; for a field, the snippet is its value
+    /// and the positions are 0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+}
+
+/// A save in a member document
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WorkspaceActivity {
+    pub path: PathBuf,
+    pub title: String,
+    pub doc_id: Option<String>,
+    pub patch_uuid: Option<String>,
+    pub timestamp: i64,
+    pub author: String,
+    pub author_name: String,
+}
+
+/// A match in a text, before it is tied to a document
+#[derive(Debug, Clone, PartialEq)]
+struct TextMatch {
+    line: usize,
+    offset: usize,
+    snippet: String,
+    snippet_start: usize,
+    snippet_end: usize,
+}
+
+/// Why a cross-document reference does not resolve
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentReferenceProblem {
+    /// No workspace document has the UUID
+    UnknownDocument,
+    /// The document has no section with the label
+    UnknownSection,
+    /// The section copies in, directly or not, the document it is copied into
+    Cycle,
+}
+
+/// A cross-document reference that does not resolve
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DocumentReferenceWarning {
+    /// The reference as written, without the leading `!`
+    pub reference: String,
+    pub problem: DocumentReferenceProblem,
+    /// Title of the referenced document, if it was found
+    pub document_title: Option<String>,
+}
+
+/// A referenced document, read once per export
+#[derive(Debug, Clone)]
+pub(crate) struct ReferencedDocument {
+    pub title: String,
+    pub content: String,
+    pub settings: DocumentSettings,
+}
+
+/// Documents that can be referenced, by UUID
+///
+/// Built from the members of all workspaces; their text is only read when
+/// a reference needs it.
+#[derive(Debug, Default)]
+pub(crate) struct DocumentRegistry {
+    paths: HashMap<String, PathBuf>,
+    loaded: HashMap<String, Option<ReferencedDocument>>,
+}
+
+impl DocumentRegistry {
+    /// Registry of the members of all workspaces
+    ///
+    /// Members that cannot be read are left out.
+    fn from_workspaces() -> Result<Self, String> {
+        let mut registry = Self::default();
+        for workspace in load_workspace_file()?.workspaces {
+            for path in workspace.documents {
+                match read_kmd_meta(&path) {
+                    Ok(meta) => {
+                        registry.paths.entry(meta.uuid).or_insert(path);
+                    }
+                    Err(e) => log::warn!("Leaving {} out of the document registry: {}", path.display(), e),
+                }
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Use a document's text instead of reading it from its file
+    fn insert(&mut self, uuid: String, document: ReferencedDocument) {
+        self.loaded.insert(uuid, Some(document));
+    }
+
+    fn document(&mut self, uuid: &str) -> Option<&ReferencedDocument> {
+        if !self.loaded.contains_key(uuid) {
+            let document = self.paths.get(uuid).and_then(|path| match read_kmd_markdown(path) {
+                Ok((content, meta)) => Some(ReferencedDocument {
+                    title: document_title(path, &meta),
+                    content,
+                    settings: meta.settings,
+                }),
+                Err(e) => {
+                    log::warn!("Could not read referenced document {}: {}", path.display(), e);
+                    None
+                }
+            });
+            self.loaded.insert(uuid.to_string(), document);
+        }
+        self.loaded.get(uuid).and_then(Option::as_ref)
+    }
+}
+
+/// Byte offset of a UTF-16 offset
+fn byte_offset(text: &str, utf16: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in text.char_indices() {
+        if units >= utf16 {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+/// Text of a labelled section, heading included, without section labels
+/// so they do not clash with those of the including document
+fn section_text(document: &ReferencedDocument, label: &str) -> Option<String> {
+    let outline = build_outline(&document.content);
+    let section = find_section(&outline, label)?;
+    let start = byte_offset(&document.content, section.start);
+    let end = byte_offset(&document.content, section.end);
+    let labels = Regex::new(r"[ \t]*\{#sec:[^}]+\}").unwrap();
+    Some(labels.replace_all(document.content[start..end].trim_end(), "").to_string())
+}
+
+/// Resolve the cross-document references in markdown
+///
+/// Copied sections are resolved in turn; `including` holds the UUIDs of
+/// the documents being copied from, to stop cycles. References that do not
+/// resolve are written as `[doc:<uuid>/sec:<label>]`.
+fn resolve_references(
+    content: &str,
+    registry: &mut DocumentRegistry,
+    including: &mut Vec<String>,
+    warnings: &mut Vec<DocumentReferenceWarning>,
+) -> String {
+    let transclusion = Regex::new(&format!(r"(?m)^!{}[ \t]*$", DOCUMENT_REFERENCE)).unwrap();
+    let citation = Regex::new(DOCUMENT_REFERENCE).unwrap();
+
+    let mut warn = |reference: &str, problem, document_title: Option<String>| {
+        warnings.push(DocumentReferenceWarning {
+            reference: reference.to_string(),
+            problem,
+            document_title,
+        });
+        format!("[{}]", &reference[1..])
+    };
+
+    let mut copied = Vec::new();
+    let content = transclusion.replace_all(content, |caps: &regex::Captures| {
+        let reference = caps[0][1..].trim_end();
+        let (uuid, label) = (&caps[1], &caps[2]);
+        let Some(document) = registry.document(uuid) else {
+            return warn(reference, DocumentReferenceProblem::UnknownDocument, None);
+        };
+        let title = document.title.clone();
+        if including.iter().any(|u| u == uuid) {
+            return warn(reference, DocumentReferenceProblem::Cycle, Some(title));
+        }
+        match section_text(document, label) {
+            Some(text) => {
+                copied.push((uuid.to_string(), text));
+                // Placeholder, replaced once the copied text is resolved
+                format!("\u{0}{}\u{0}", copied.len() - 1)
+            }
+            None => warn(reference, DocumentReferenceProblem::UnknownSection, Some(title)),
+        }
+    });
+
+    let content = citation.replace_all(&content, |caps: &regex::Captures| {
+        let reference = &caps[0];
+        let Some(document) = registry.document(&caps[1]) else {
+            return warn(reference, DocumentReferenceProblem::UnknownDocument, None);
+        };
+        match section_reference_text(&document.content, Some(&document.settings), &caps[2]) {
+            Some(text) => format!("{} of *{}*", text, document.title),
+            None => {
+                let title = document.title.clone();
+                warn(reference, DocumentReferenceProblem::UnknownSection, Some(title))
+            }
+        }
+    });
+
+    let mut result = content.to_string();
+    for (index, (uuid, text)) in copied.into_iter().enumerate() {
+        including.push(uuid);
+        let text = resolve_references(&text, registry, including, warnings);
+        including.pop();
+        result = result.replacen(&format!("\u{0}{}\u{0}", index), &text, 1);
+    }
+    result
+}
+
+/// Resolve the cross-document references in markdown for export, logging
+/// the ones that do not resolve
+pub(crate) fn resolve_document_references_for_export(content: &str) -> String {
+    if !content.contains("@doc:") {
+        return content.to_string();
+    }
+    let mut registry = match DocumentRegistry::from_workspaces() {
+        Ok(registry) => registry,
+        Err(e) => {
+            log::warn!("Could not load the document registry: {}", e);
+            DocumentRegistry::default()
+        }
+    };
+    let mut warnings = Vec::new();
+    let resolved = resolve_references(content, &mut registry, &mut Vec::new(), &mut warnings);
+    for warning in warnings {
+        log::warn!("Unresolved document reference {}: {:?}", warning.reference, warning.problem);
+    }
+    resolved
+}
+
+/// Load workspace.json
+fn load_workspace_file() -> Result<WorkspaceFile, String> {
+    let path = get_config_dir()?.join("workspace.json");
+    if !path.exists() {
+        return Ok(WorkspaceFile::default());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let mut file: WorkspaceFile = serde_json::from_str(&content).map_err(|e| format!("Invalid workspace.json: {}", e))?;
+    file.version = WORKSPACE_SCHEMA_VERSION;
+    Ok(file)
+}
+
+/// Save workspace.json
+fn save_workspace_file(file: &WorkspaceFile) -> Result<(), String> {
+    let config_dir = get_config_dir()?;
+    fs::create_dir_all(&config_dir).map_err(|e| e.to_string())?;
+    let content = serde_json::to_string_pretty(file).map_err(|e| e.to_string())?;
+    fs::write(config_dir.join("workspace.json"), content).map_err(|e| e.to_string())
+}
+
+pub(crate) fn load_workspace(workspace_id: &str) -> Result<Workspace, String> {
+    load_workspace_file()?
+        .workspaces
+        .into_iter()
+        .find(|w| w.id == workspace_id)
+        .ok_or_else(|| format!("Workspace not found: {}", workspace_id))
+}
+
+/// Title of a document, falling back to its file name
+fn document_title(path: &Path, meta: &DocumentMeta) -> String {
+    if meta.title.is_empty() || meta.title == "Untitled Document" {
+        path.file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| meta.title.clone())
+    } else {
+        meta.title.clone()
+    }
+}
+
+/// Find a query in a text line by line
+fn search_text(text: &str, query: &str, case_sensitive: bool) -> Vec<TextMatch> {
+    let Ok(pattern) = RegexBuilder::new(&regex::escape(query)).case_insensitive(!case_sensitive).build() else {
+        return Vec::new();
+    };
+    let utf16_len = |s: &str| s.encode_utf16().count();
+
+    let mut matches = Vec::new();
+    let mut line_offset = 0;
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        for m in pattern.find_iter(line) {
+            if matches.len() >= MAX_MATCHES_PER_DOCUMENT {
+                return matches;
+            }
+            // Cut the line SNIPPET_CONTEXT characters either side of the match
+            let before = &line[..m.start()];
+            let from = before.char_indices().rev().nth(SNIPPET_CONTEXT - 1).map(|(i, _)| i).unwrap_or(0);
+            let after = &line[m.end()..];
+            let to = m.end() + after.char_indices().nth(SNIPPET_CONTEXT).map(|(i, _)| i).unwrap_or(after.len());
+
+            let prefix = if from > 0 { "…" } else { "" };
+            let head = format!("{}{}", prefix, before[from..].trim_start());
+            let snippet = format!("{}{}", head, line[m.start()..to].trim_end());
+            let snippet_start = utf16_len(&head);
+            matches.push(TextMatch {
+                line: index + 1,
+                offset: line_offset + utf16_len(before),
+                snippet: if to < line.trim_end().len() { format!("{}…", snippet) } else { snippet },
+                snippet_start,
+                snippet_end: snippet_start + utf16_len(m.as_str()),
+            });
+        }
+        line_offset += utf16_len(line);
+    }
+    matches
+}
+
+/// The newest saves in a history database, newest first
+fn recent_saves(history_path: &Path, limit: usize) -> Result<Vec<crate::patch_log::Patch>, String> {
+    if !history_path.exists() {
+        return Ok(Vec::new());
+    }
+    let conn = Connection::open(history_path).map_err(|e| e.to_string())?;
+    ensure_schema(&conn)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, timestamp, author, kind, data, uuid, parent_uuid FROM patches
+             WHERE kind = 'Save' ORDER BY timestamp DESC, id DESC LIMIT ?1",
+        )
+        .map_err(|e| e.to_string())?;
+    let patches = stmt
+        .query_map([limit as i64], row_to_patch)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(patches)
+}
+
+/// List all workspaces
+#[tauri::command]
+pub fn list_workspaces() -> Result<Vec<Workspace>, KorppiError> {
+    Ok(load_workspace_file()?.workspaces)
+}
+
+/// Create a workspace, optionally with its first documents
+#[tauri::command]
+pub fn create_workspace(name: String, documents: Option<Vec<PathBuf>>) -> Result<Workspace, KorppiError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(KorppiError::InvalidInput("Workspace name is empty".to_string()));
+    }
+    let mut workspace = Workspace {
+        id: Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        documents: Vec::new(),
+        created_at: Utc::now(),
+    };
+    for path in documents.unwrap_or_default() {
+        workspace.add_document(path);
+    }
+
+    let mut file = load_workspace_file()?;
+    file.workspaces.push(workspace.clone());
+    save_workspace_file(&file)?;
+    Ok(workspace)
+}
+
+/// Delete a workspace; its documents are left alone
+#[tauri::command]
+pub fn delete_workspace(workspace_id: String) -> Result<(), KorppiError> {
+    let mut file = load_workspace_file()?;
+    file.workspace_mut(&workspace_id)?;
+    file.workspaces.retain(|w| w.id != workspace_id);
+    Ok(save_workspace_file(&file)?)
+}
+
+/// Add a KMD file to a workspace
+#[tauri::command]
+pub fn add_workspace_document(workspace_id: String, path: PathBuf) -> Result<Workspace, KorppiError> {
+    if path.extension().and_then(|e| e.to_str()) != Some("kmd") {
+        return Err(KorppiError::InvalidInput(format!("Not a Korppi document: {}", path.display())));
+    }
+    if !path.exists() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("File not found: {:?}", path)).into());
+    }
+
+    let mut file = load_workspace_file()?;
+    let workspace = file.workspace_mut(&workspace_id)?;
+    workspace.add_document(path);
+    let workspace = workspace.clone();
+    save_workspace_file(&file)?;
+    Ok(workspace)
+}
+
+/// Remove a document from a workspace; the file itself is left alone
+#[tauri::command]
+pub fn remove_workspace_document(workspace_id: String, path: PathBuf) -> Result<Workspace, KorppiError> {
+    let mut file = load_workspace_file()?;
+    let workspace = file.workspace_mut(&workspace_id)?;
+    if !workspace.remove_document(&path) {
+        return Err(KorppiError::InvalidInput(format!("Not in workspace: {}", path.display())));
+    }
+    let workspace = workspace.clone();
+    save_workspace_file(&file)?;
+    Ok(workspace)
+}
+
+/// Open every document of a workspace in the calling window
+///
+/// Documents that are already open are not opened again, and one missing
+/// file does not stop the others.
+#[tauri::command]
+pub async fn open_workspace(
+    app: AppHandle,
+    window: Window,
+    manager: State<'_, Mutex<DocumentManager>>,
+    jobs: State<'_, Mutex<FileJobs>>,
+    workspace_id: String,
+) -> Result<Vec<WorkspaceMemberOpen>, KorppiError> {
+    let workspace = load_workspace(&workspace_id)?;
+    let mut results = Vec::with_capacity(workspace.documents.len());
+
+    for path in workspace.documents {
+        let open = {
+            let manager = manager.lock().map_err(|e| e.to_string())?;
+            manager
+                .document_at(&path)
+                .and_then(|id| manager.documents.get(id))
+                .map(|doc| doc.handle.clone())
+        };
+        let outcome = match open {
+            Some(handle) => Ok(handle),
+            None => open_document(
+                app.clone(),
+                window.clone(),
+                manager.clone(),
+                jobs.clone(),
+                Some(path.to_string_lossy().to_string()),
+                None,
+                None,
+                None,
+            )
+            .await,
+        };
+        results.push(match outcome {
+            Ok(handle) => WorkspaceMemberOpen::Opened { path, handle },
+            Err(error) => WorkspaceMemberOpen::Failed { path, error },
+        });
+    }
+
+    Ok(results)
+}
+
+/// Search the markdown and the metadata of every document in a workspace
+///
+/// Matching ignores case unless `case_sensitive` is set. Metadata matches
+/// come first for each document. Documents that cannot be read are skipped.
+#[tauri::command]
+pub fn search_workspace(
+    manager: State<'_, Mutex<DocumentManager>>,
+    workspace_id: String,
+    query: String,
+    case_sensitive: Option<bool>,
+) -> Result<Vec<WorkspaceSearchMatch>, KorppiError> {
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let workspace = load_workspace(&workspace_id)?;
+
+    let mut results = Vec::new();
+    for path in workspace.documents {
+        let open = {
+            let manager = manager.lock().map_err(|e| e.to_string())?;
+            match manager.document_at(&path).and_then(|id| manager.documents.get(id).map(|doc| (id, doc))) {
+                Some((id, doc)) => Some((
+                    id.clone(),
+                    doc.handle.title.clone(),
+                    current_document_text(doc, false)?,
+                    doc.meta.clone(),
+                )),
+                None => None,
+            }
+        };
+        let (doc_id, title, text, meta) = match open {
+            Some((id, title, text, meta)) => (Some(id), title, text, meta),
+            None => match read_kmd_markdown(&path) {
+                Ok((text, meta)) => (None, document_title(&path, &meta), text, meta),
+                Err(e) => {
+                    log::warn!("Skipping {} in workspace search: {}", path.display(), e);
+                    continue;
+                }
+            },
+        };
+
+        let case_sensitive = case_sensitive.unwrap_or(false);
+        results.extend(metadata_matches(&meta, &query, case_sensitive).into_iter().map(|(field, value)| {
+            WorkspaceSearchMatch {
+                path: path.clone(),
+                title: title.clone(),
+                doc_id: doc_id.clone(),
+                line: 0,
+                offset: 0,
+                snippet: value,
+                snippet_start: 0,
+                snippet_end: 0,
+                field: Some(field),
+            }
+        }));
+        results.extend(search_text(&text, &query, case_sensitive).into_iter().map(|m| {
+            WorkspaceSearchMatch {
+                path: path.clone(),
+                title: title.clone(),
+                doc_id: doc_id.clone(),
+                line: m.line,
+                offset: m.offset,
+                snippet: m.snippet,
+                snippet_start: m.snippet_start,
+                snippet_end: m.snippet_end,
+                field: None,
+            }
+        }));
+    }
+    Ok(results)
+}
+
+/// Get the latest saves across the documents of a workspace, newest first
+#[tauri::command]
+pub fn get_workspace_activity(
+    manager: State<'_, Mutex<DocumentManager>>,
+    workspace_id: String,
+    limit: Option<usize>,
+) -> Result<Vec<WorkspaceActivity>, KorppiError> {
+    let limit = limit.unwrap_or(DEFAULT_ACTIVITY_LIMIT);
+    let workspace = load_workspace(&workspace_id)?;
+
+    let mut activity = Vec::new();
+    for path in workspace.documents {
+        let open = {
+            let manager = manager.lock().map_err(|e| e.to_string())?;
+            manager.document_at(&path).and_then(|id| manager.documents.get(id).map(|doc| (id, doc))).map(|(id, doc)| {
+                (id.clone(), doc.handle.title.clone(), doc.history_path.clone(), doc.meta.clone())
+            })
+        };
+        let member = match open {
+            Some((id, title, history_path, meta)) => {
+                recent_saves(&history_path, limit).map(|saves| (Some(id), title, meta, saves))
+            }
+            None => {
+                let read_id = format!("workspace-{}", Uuid::new_v4());
+                let result = extract_kmd_to_temp(&path, &read_id).and_then(|extracted| {
+                    let saves = recent_saves(&extracted.history_path, limit)?;
+                    Ok((None, document_title(&path, &extracted.meta), extracted.meta, saves))
+                });
+                cleanup_document_temp_dir(&read_id).ok();
+                result
+            }
+        };
+        let (doc_id, title, meta, saves) = match member {
+            Ok(member) => member,
+            Err(e) => {
+                log::warn!("Skipping {} in workspace activity: {}", path.display(), e);
+                continue;
+            }
+        };
+
+        activity.extend(saves.into_iter().map(|patch| WorkspaceActivity {
+            path: path.clone(),
+            title: title.clone(),
+            doc_id: doc_id.clone(),
+            patch_uuid: patch.uuid,
+            timestamp: patch.timestamp,
+            author_name: patch
+                .data
+                .get("authorName")
+                .and_then(|n| n.as_str())
+                .map(str::to_string)
+                .unwrap_or_else(|| author_display_name(&meta, &patch.author)),
+            author: patch.author,
+        }));
+    }
+
+    activity.sort_by_key(|a| std::cmp::Reverse(a.timestamp));
+    activity.truncate(limit);
+    Ok(activity)
+}
+
+/// Check the cross-document references of a document
+///
+/// Open documents are checked against their current text, others against
+/// their saved file.
+#[tauri::command]
+pub fn check_document_references(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+) -> Result<Vec<DocumentReferenceWarning>, KorppiError> {
+    let mut registry = DocumentRegistry::from_workspaces()?;
+    let content = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.clone()))?;
+        for open in manager.documents.values() {
+            registry.insert(
+                open.meta.uuid.clone(),
+                ReferencedDocument {
+                    title: open.handle.title.clone(),
+                    content: current_document_text(open, false)?,
+                    settings: open.meta.settings.clone(),
+                },
+            );
+        }
+        current_document_text(doc, false)?
+    };
+
+    let mut warnings = Vec::new();
+    resolve_references(&content, &mut registry, &mut Vec::new(), &mut warnings);
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workspace_membership() {
+        let mut workspace = Workspace {
+            id: "w1".to_string(),
+            name: "Paper".to_string(),
+            documents: Vec::new(),
+            created_at: DateTime::from_timestamp_millis(1_700_000_000_000).unwrap(),
+        };
+        assert!(workspace.add_document(PathBuf::from("/a/paper.kmd")));
+        assert!(workspace.add_document(PathBuf::from("/a/supplement.kmd")));
+        assert!(!workspace.add_document(PathBuf::from("/a/paper.kmd")));
+        assert_eq!(workspace.documents.len(), 2);
+
+        assert!(workspace.remove_document(Path::new("/a/paper.kmd")));
+        assert!(!workspace.remove_document(Path::new("/a/paper.kmd")));
+        assert_eq!(workspace.documents, vec![PathBuf::from("/a/supplement.kmd")]);
+
+        let file = WorkspaceFile {
+            version: WORKSPACE_SCHEMA_VERSION,
+            workspaces: vec![workspace.clone()],
+        };
+        let parsed: WorkspaceFile = serde_json::from_str(&serde_json::to_string(&file).unwrap()).unwrap();
+        assert_eq!(parsed.workspaces, vec![workspace]);
+    }
+
+    #[test]
+    fn test_search_text() {
+        let text = "# Méthodes\n\nWe sampled the río twice.\nThe Río Grande was sampled once.";
+        let matches = search_text(text, "río", false);
+
+        assert_eq!(matches.len(), 2);
+        assert_eq!((matches[0].line, matches[1].line), (3, 4));
+        let second = text.find("Río").unwrap();
+        assert_eq!(matches[1].offset, text[..second].encode_utf16().count());
+        assert_eq!(matches[0].snippet, "We sampled the río twice.");
+        let highlighted: String = matches[0]
+            .snippet
+            .chars()
+            .skip(matches[0].snippet_start)
+            .take(matches[0].snippet_end - matches[0].snippet_start)
+            .collect();
+        assert_eq!(highlighted, "río");
+
+        assert_eq!(search_text(text, "río", true).len(), 1);
+
+        let long = format!("{} needle {}", "a".repeat(60), "b".repeat(60));
+        let matches = search_text(&long, "needle", false);
+        assert!(matches[0].snippet.starts_with('…') && matches[0].snippet.ends_with('…'));
+        assert_eq!(matches[0].snippet.chars().count(), 1 + SNIPPET_CONTEXT + "needle".len() + SNIPPET_CONTEXT + 1);
+    }
+
+    fn referenced(title: &str, content: &str) -> ReferencedDocument {
+        ReferencedDocument {
+            title: title.to_string(),
+            content: content.to_string(),
+            settings: DocumentSettings::default(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_references() {
+        let mut registry = DocumentRegistry::default();
+        registry.insert(
+            "a1".to_string(),
+            referenced(
+                "Supplement",
+                "# Data {#sec:data}\n\nTwo sites.\n\n# Methods {#sec:methods}\n\nSee @doc:b2/sec:letter.\n\n!@doc:b2/sec:letter\n\n# End\n",
+            ),
+        );
+        registry.insert("b2".to_string(), referenced("Letter", "# Letter {#sec:letter}\n\nDear editor.\n\n!@doc:a1/sec:data\n"));
+
+        let content = "As in @doc:a1/sec:methods and @doc:a1/sec:nope.\n\n!@doc:a1/sec:methods\n\n!@doc:c3/sec:x\n";
+        let mut warnings = Vec::new();
+        let resolved = resolve_references(content, &mut registry, &mut Vec::new(), &mut warnings);
+
+        assert!(resolved.starts_with("As in Section 2 of *Supplement* and [doc:a1/sec:nope]."));
+        // The copied section with its citation and copy resolved, without
+        // labels; the copy of the copy would bring back the Supplement
+        assert!(resolved.contains("# Methods\n\nSee Section 1 of *Letter*.\n\n# Letter\n\nDear editor.\n\n[doc:a1/sec:data]"));
+        assert!(!resolved.contains("# End"));
+        assert!(resolved.trim_end().ends_with("[doc:c3/sec:x]"));
+
+        let problems: Vec<DocumentReferenceProblem> = warnings.iter().map(|w| w.problem).collect();
+        assert_eq!(
+            problems,
+            vec![
+                DocumentReferenceProblem::UnknownDocument,
+                DocumentReferenceProblem::UnknownSection,
+                DocumentReferenceProblem::Cycle,
+            ]
+        );
+        assert_eq!(warnings[1].document_title.as_deref(), Some("Supplement"));
+        assert_eq!(warnings[2].reference, "@doc:a1/sec:data");
+    }
+}
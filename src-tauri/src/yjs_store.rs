@@ -2,10 +2,429 @@
 use std::fs;
 use std::path::PathBuf;
 
+use std::collections::HashMap;
+
 use tauri::{AppHandle, Manager};
+use serde::Deserialize;
+use yrs::branch::BranchPtr;
+use yrs::types::text::YChange;
+use yrs::types::TypePtr;
+use yrs::updates::decoder::Decode;
+use yrs::{
+    Any, Assoc, Doc, IndexScope, Out, ReadTxn, StateVector, StickyIndex, Transact, Update, Xml, XmlFragment,
+    XmlOut, ID,
+};
 
 const FILENAME: &str = "document.yjs";
 
+/// Name of the XML fragment y-prosemirror binds the editor to
+pub const PROSEMIRROR_FRAGMENT: &str = "prosemirror";
+
+/// Node attributes needed to render markdown
+const NODE_ATTRIBUTES: &[&str] = &["level", "language", "order", "src", "alt", "title", "checked"];
+
+/// Marks from innermost to outermost when wrapping text
+const MARK_ORDER: &[&str] = &["inlineCode", "strike_through", "emphasis", "strong", "link"];
+
+/// Load encoded Yjs states and updates into a fresh document
+pub fn load_yjs_doc(updates: &[&[u8]]) -> Result<Doc, String> {
+    let doc = Doc::new();
+    {
+        let mut txn = doc.transact_mut();
+        for data in updates.iter().filter(|u| !u.is_empty()) {
+            let update = Update::decode_v1(data).map_err(|e| format!("Invalid Yjs update: {}", e))?;
+            txn.apply_update(update)
+                .map_err(|e| format!("Failed to apply Yjs update: {}", e))?;
+        }
+    }
+    Ok(doc)
+}
+
+/// Merge Yjs states and updates into a single encoded state.
+/// Order does not matter and duplicates are harmless.
+pub fn merge_states(updates: &[&[u8]]) -> Result<Vec<u8>, String> {
+    if updates.iter().all(|u| u.is_empty()) {
+        return Ok(Vec::new());
+    }
+    let doc = load_yjs_doc(updates)?;
+    let txn = doc.transact();
+    Ok(txn.encode_state_as_update_v1(&StateVector::default()))
+}
+
+/// State vector (latest clock per client) of an encoded state
+pub fn state_vector(state: &[u8]) -> Result<StateVector, String> {
+    let doc = load_yjs_doc(&[state])?;
+    let txn = doc.transact();
+    Ok(txn.state_vector())
+}
+
+/// True when both states contain changes the other has not seen,
+/// i.e. they were edited concurrently and merging interleaves edits
+pub fn has_diverged(a: &[u8], b: &[u8]) -> Result<bool, String> {
+    let sv_a = state_vector(a)?;
+    let sv_b = state_vector(b)?;
+    let a_ahead = sv_a.iter().any(|(client, clock)| *clock > sv_b.get(client));
+    let b_ahead = sv_b.iter().any(|(client, clock)| *clock > sv_a.get(client));
+    Ok(a_ahead && b_ahead)
+}
+
+/// A run of text with its ProseMirror marks (name, link target)
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextRun {
+    pub text: String,
+    pub marks: Vec<(String, Option<String>)>,
+}
+
+/// A ProseMirror node read from the Yjs XML tree
+#[derive(Debug, Clone, PartialEq)]
+pub enum PmNode {
+    Element {
+        tag: String,
+        attrs: HashMap<String, String>,
+        children: Vec<PmNode>,
+    },
+    Text(Vec<TextRun>),
+}
+
+fn read_children<T: ReadTxn, F: XmlFragment>(txn: &T, node: &F) -> Vec<PmNode> {
+    (0..node.len(txn))
+        .filter_map(|i| node.get(txn, i))
+        .map(|child| read_node(txn, child))
+        .collect()
+}
+
+fn read_node<T: ReadTxn>(txn: &T, node: XmlOut) -> PmNode {
+    match node {
+        XmlOut::Element(element) => {
+            let attrs = NODE_ATTRIBUTES
+                .iter()
+                .filter_map(|name| {
+                    element
+                        .get_attribute(txn, name)
+                        .map(|value| (name.to_string(), value.to_string(txn)))
+                })
+                .collect();
+            PmNode::Element {
+                tag: element.tag().to_string(),
+                attrs,
+                children: read_children(txn, &element),
+            }
+        }
+        XmlOut::Fragment(fragment) => PmNode::Element {
+            tag: String::new(),
+            attrs: HashMap::new(),
+            children: read_children(txn, &fragment),
+        },
+        XmlOut::Text(text) => PmNode::Text(
+            text.diff(txn, YChange::identity)
+                .into_iter()
+                .filter_map(|chunk| {
+                    let text = match chunk.insert {
+                        Out::Any(Any::String(s)) => s.to_string(),
+                        _ => return None,
+                    };
+                    // y-prosemirror stores each mark as an attribute holding the mark attrs
+                    let marks = chunk
+                        .attributes
+                        .map(|attrs| {
+                            attrs
+                                .iter()
+                                .map(|(name, value)| {
+                                    let href = match value {
+                                        Any::Map(map) => map.get("href").map(|h| match h {
+                                            Any::String(s) => s.to_string(),
+                                            _ => String::new(),
+                                        }),
+                                        _ => None,
+                                    };
+                                    (name.to_string(), href)
+                                })
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    Some(TextRun { text, marks })
+                })
+                .collect(),
+        ),
+    }
+}
+
+fn render_run(run: &TextRun, plain: bool) -> String {
+    if plain {
+        return run.text.clone();
+    }
+    let mut text = run.text.clone();
+    for mark in MARK_ORDER {
+        let Some((_, href)) = run.marks.iter().find(|(name, _)| name == mark) else {
+            continue;
+        };
+        text = match *mark {
+            "inlineCode" => format!("`{}`", text),
+            "strike_through" => format!("~~{}~~", text),
+            "emphasis" => format!("*{}*", text),
+            "strong" => format!("**{}**", text),
+            _ => format!("[{}]({})", text, href.clone().unwrap_or_default()),
+        };
+    }
+    text
+}
+
+/// Render inline content (text runs, images, hard breaks)
+fn render_inline(nodes: &[PmNode], plain: bool) -> String {
+    let mut result = String::new();
+    for node in nodes {
+        match node {
+            PmNode::Text(runs) => {
+                for run in runs {
+                    result.push_str(&render_run(run, plain));
+                }
+            }
+            PmNode::Element { tag, attrs, children } => match tag.as_str() {
+                "hardbreak" | "hard_break" => result.push_str(if plain { "\n" } else { "  \n" }),
+                "image" => {
+                    let alt = attrs.get("alt").cloned().unwrap_or_default();
+                    if plain {
+                        result.push_str(&alt);
+                    } else {
+                        let src = attrs.get("src").cloned().unwrap_or_default();
+                        result.push_str(&format!("![{}]({})", alt, src));
+                    }
+                }
+                _ => result.push_str(&render_inline(children, plain)),
+            },
+        }
+    }
+    result
+}
+
+/// Prefix the first line with `first` and the remaining lines with `rest`
+fn indent_block(text: &str, first: &str, rest: &str) -> String {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let prefix = if i == 0 { first } else { rest };
+            if line.is_empty() {
+                prefix.trim_end().to_string()
+            } else {
+                format!("{}{}", prefix, line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn render_table(rows: &[PmNode], plain: bool) -> String {
+    let cells_of = |row: &PmNode| -> Vec<String> {
+        match row {
+            PmNode::Element { children, .. } => children
+                .iter()
+                .map(|cell| match cell {
+                    PmNode::Element { children, .. } => render_blocks(children, plain).replace('\n', " "),
+                    PmNode::Text(_) => render_inline(std::slice::from_ref(cell), plain),
+                })
+                .collect(),
+            PmNode::Text(_) => Vec::new(),
+        }
+    };
+
+    let mut lines = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        let cells = cells_of(row);
+        if plain {
+            lines.push(cells.join("\t"));
+            continue;
+        }
+        lines.push(format!("| {} |", cells.join(" | ")));
+        if i == 0 {
+            lines.push(format!("|{}|", vec!["---"; cells.len()].join("|")));
+        }
+    }
+    lines.join("\n")
+}
+
+fn render_block(node: &PmNode, plain: bool) -> String {
+    let PmNode::Element { tag, attrs, children } = node else {
+        return render_inline(std::slice::from_ref(node), plain);
+    };
+
+    match tag.as_str() {
+        "paragraph" => render_inline(children, plain),
+        "heading" => {
+            let text = render_inline(children, plain);
+            if plain {
+                return text;
+            }
+            let level = attrs
+                .get("level")
+                .and_then(|l| l.parse::<f64>().ok())
+                .map(|l| l as usize)
+                .unwrap_or(1)
+                .clamp(1, 6);
+            format!("{} {}", "#".repeat(level), text)
+        }
+        "blockquote" => {
+            let text = render_blocks(children, plain);
+            if plain {
+                text
+            } else {
+                indent_block(&text, "> ", "> ")
+            }
+        }
+        "code_block" => {
+            let code = render_inline(children, true);
+            if plain {
+                code
+            } else {
+                let language = attrs.get("language").cloned().unwrap_or_default();
+                format!("```{}\n{}\n```", language, code)
+            }
+        }
+        "hr" => if plain { String::new() } else { "---".to_string() },
+        "bullet_list" | "ordered_list" => {
+            let ordered = tag == "ordered_list";
+            let start = attrs
+                .get("order")
+                .and_then(|o| o.parse::<f64>().ok())
+                .map(|o| o as usize)
+                .unwrap_or(1);
+            children
+                .iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    let content = match item {
+                        PmNode::Element { children, .. } => render_blocks_tight(children, plain),
+                        PmNode::Text(_) => render_inline(std::slice::from_ref(item), plain),
+                    };
+                    let marker = if plain {
+                        String::new()
+                    } else if ordered {
+                        format!("{}. ", start + i)
+                    } else {
+                        "- ".to_string()
+                    };
+                    indent_block(&content, &marker, &" ".repeat(marker.len()))
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        "table" => render_table(children, plain),
+        _ => render_blocks(children, plain),
+    }
+}
+
+/// Blocks separated by blank lines
+fn render_blocks(nodes: &[PmNode], plain: bool) -> String {
+    nodes
+        .iter()
+        .map(|node| render_block(node, plain))
+        .filter(|block| !block.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Blocks of a list item, on consecutive lines
+fn render_blocks_tight(nodes: &[PmNode], plain: bool) -> String {
+    nodes
+        .iter()
+        .map(|node| render_block(node, plain))
+        .filter(|block| !block.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Render ProseMirror nodes as markdown, or as plain text without markup
+pub fn render_nodes(nodes: &[PmNode], plain: bool) -> String {
+    render_blocks(nodes, plain)
+}
+
+/// Decode the editor document from Yjs states/updates and render it as
+/// markdown (or plain text)
+pub fn document_text(updates: &[&[u8]], plain: bool) -> Result<String, String> {
+    let doc = load_yjs_doc(updates)?;
+    let fragment = doc.get_or_insert_xml_fragment(PROSEMIRROR_FRAGMENT);
+    let txn = doc.transact();
+    let nodes = read_children(&txn, &fragment);
+    Ok(render_nodes(&nodes, plain))
+}
+
+/// Yjs item or type ID as written by `Y.relativePositionToJSON`
+#[derive(Debug, Deserialize)]
+struct AnchorId {
+    client: u64,
+    clock: u32,
+}
+
+/// A comment anchor: the JSON of a Yjs relative position
+#[derive(Debug, Deserialize)]
+struct AnchorJson {
+    #[serde(rename = "type", default)]
+    parent: Option<AnchorId>,
+    #[serde(default)]
+    tname: Option<String>,
+    #[serde(default)]
+    item: Option<AnchorId>,
+    #[serde(default)]
+    assoc: i32,
+}
+
+pub(crate) fn parse_anchor(anchor: &str) -> Option<StickyIndex> {
+    let json: AnchorJson = serde_json::from_str(anchor).ok()?;
+    let scope = match (json.item, json.tname, json.parent) {
+        (Some(item), _, _) => IndexScope::Relative(ID::new(item.client, item.clock)),
+        (None, Some(name), _) => IndexScope::Root(name.into()),
+        (None, None, Some(parent)) => IndexScope::Nested(ID::new(parent.client, parent.clock)),
+        (None, None, None) => return None,
+    };
+    let assoc = if json.assoc < 0 { Assoc::Before } else { Assoc::After };
+    Some(StickyIndex::new(scope, assoc))
+}
+
+fn branch_of(node: &XmlOut) -> BranchPtr {
+    match node {
+        XmlOut::Element(element) => BranchPtr::from(element.as_ref()),
+        XmlOut::Fragment(fragment) => BranchPtr::from(fragment.as_ref()),
+        XmlOut::Text(text) => BranchPtr::from(text.as_ref()),
+    }
+}
+
+/// Index of the top-level block a comment anchor points into
+fn anchor_block_index<T: ReadTxn, F: XmlFragment>(txn: &T, fragment: &F, root: BranchPtr, anchor: &str) -> Option<u32> {
+    let offset = parse_anchor(anchor)?.get_offset(txn)?;
+    if offset.branch == root {
+        return Some(offset.index);
+    }
+    // Walk up from the type holding the position to its top-level ancestor
+    let mut branch = offset.branch;
+    loop {
+        let item = branch.item?;
+        match &item.parent {
+            TypePtr::Branch(parent) if *parent == root => break,
+            TypePtr::Branch(parent) => branch = *parent,
+            _ => return None,
+        }
+    }
+    (0..fragment.len(txn)).find(|&i| fragment.get(txn, i).is_some_and(|child| branch_of(&child) == branch))
+}
+
+/// Markdown of the top-level block each comment anchor points into
+///
+/// Anchors are the JSON the editor stores for a comment's start. `None` for
+/// anchors that cannot be read or whose text was deleted.
+pub fn anchor_blocks(updates: &[&[u8]], anchors: &[&str]) -> Result<Vec<Option<String>>, String> {
+    let doc = load_yjs_doc(updates)?;
+    let fragment = doc.get_or_insert_xml_fragment(PROSEMIRROR_FRAGMENT);
+    let root = BranchPtr::from(fragment.as_ref());
+    let txn = doc.transact();
+    Ok(anchors
+        .iter()
+        .map(|anchor| {
+            let index = anchor_block_index(&txn, &fragment, root, anchor)?;
+            let block = read_node(&txn, fragment.get(&txn, index)?);
+            Some(render_block(&block, false)).filter(|text| !text.is_empty())
+        })
+        .collect())
+}
+
 fn doc_path(app: &AppHandle) -> Result<PathBuf, String> {
     let mut path = app.path().app_data_dir()
         .map_err(|e| format!("Failed to get app data dir: {}", e))?;
@@ -77,6 +496,124 @@ mod tests {
         assert!(result.unwrap().is_empty());
     }
 
+    /// Encoded state of a document with `text` inserted by `client` on top of `base`
+    fn edit(base: &[u8], client: u64, index: u32, text: &str) -> Vec<u8> {
+        use yrs::Text;
+        let doc = Doc::with_client_id(client);
+        let content = doc.get_or_insert_text("content");
+        {
+            let mut txn = doc.transact_mut();
+            if !base.is_empty() {
+                txn.apply_update(Update::decode_v1(base).unwrap()).unwrap();
+            }
+            content.insert(&mut txn, index, text);
+        }
+        let txn = doc.transact();
+        txn.encode_state_as_update_v1(&StateVector::default())
+    }
+
+    fn text_of(state: &[u8]) -> String {
+        use yrs::GetString;
+        let doc = load_yjs_doc(&[state]).unwrap();
+        let content = doc.get_or_insert_text("content");
+        let txn = doc.transact();
+        content.get_string(&txn)
+    }
+
+    #[test]
+    fn test_merge_concurrent_states() {
+        let base = edit(&[], 1, 0, "Hello");
+        let ours = edit(&base, 2, 5, " world");
+        let theirs = edit(&base, 3, 0, "Oh, ");
+
+        let merged = merge_states(&[&ours, &theirs]).unwrap();
+        let text = text_of(&merged);
+        assert!(text.contains("Oh, "));
+        assert!(text.contains(" world"));
+
+        // Merging again changes nothing
+        let again = merge_states(&[&merged, &ours, &theirs]).unwrap();
+        assert_eq!(text_of(&again), text);
+        assert!(merge_states(&[&[], &[]]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_has_diverged() {
+        let base = edit(&[], 1, 0, "Hello");
+        let ours = edit(&base, 2, 5, "!");
+        let theirs = edit(&base, 3, 5, "?");
+
+        assert!(has_diverged(&ours, &theirs).unwrap());
+        assert!(!has_diverged(&base, &ours).unwrap());
+        assert!(!has_diverged(&ours, &ours).unwrap());
+    }
+
+    fn element(tag: &str, attrs: &[(&str, &str)], children: Vec<PmNode>) -> PmNode {
+        PmNode::Element {
+            tag: tag.to_string(),
+            attrs: attrs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            children,
+        }
+    }
+
+    fn text(runs: &[(&str, &[&str])]) -> PmNode {
+        PmNode::Text(
+            runs.iter()
+                .map(|(text, marks)| TextRun {
+                    text: text.to_string(),
+                    marks: marks.iter().map(|m| (m.to_string(), None)).collect(),
+                })
+                .collect(),
+        )
+    }
+
+    fn sample_document() -> Vec<PmNode> {
+        vec![
+            element("heading", &[("level", "2")], vec![text(&[("Title", &[])])]),
+            element(
+                "paragraph",
+                &[],
+                vec![text(&[("Some ", &[]), ("bold", &["strong"]), (" and ", &[]), ("code", &["inlineCode"])])],
+            ),
+            element(
+                "bullet_list",
+                &[],
+                vec![
+                    element("list_item", &[], vec![element("paragraph", &[], vec![text(&[("One", &[])])])]),
+                    element(
+                        "list_item",
+                        &[],
+                        vec![
+                            element("paragraph", &[], vec![text(&[("Two", &[])])]),
+                            element(
+                                "ordered_list",
+                                &[("order", "3")],
+                                vec![element("list_item", &[], vec![element("paragraph", &[], vec![text(&[("Nested", &[])])])])],
+                            ),
+                        ],
+                    ),
+                ],
+            ),
+            element("code_block", &[("language", "rust")], vec![text(&[("let x = 1;", &[])])]),
+            element("blockquote", &[], vec![element("paragraph", &[], vec![text(&[("Quoted", &["emphasis"])])])]),
+        ]
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let markdown = render_nodes(&sample_document(), false);
+        assert_eq!(
+            markdown,
+            "## Title\n\nSome **bold** and `code`\n\n- One\n- Two\n  3. Nested\n\n```rust\nlet x = 1;\n```\n\n> *Quoted*"
+        );
+    }
+
+    #[test]
+    fn test_render_plain_text() {
+        let plain = render_nodes(&sample_document(), true);
+        assert_eq!(plain, "Title\n\nSome bold and code\n\nOne\nTwo\nNested\n\nlet x = 1;\n\nQuoted");
+    }
+
     #[test]
     fn test_roundtrip() {
         let temp_dir = TempDir::new().unwrap();
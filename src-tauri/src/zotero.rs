@@ -0,0 +1,264 @@
+// src-tauri/src/zotero.rs
+//! Citations from a running Zotero with the Better BibTeX extension.
+//!
+//! Zotero serves a local HTTP endpoint on port 23119, under which Better
+//! BibTeX answers JSON-RPC requests to search the library and export
+//! BibTeX. Cited references are appended to the document's
+//! `assets/references.bib`, which is saved with the KMD, and inserted with
+//! pandoc's `[@citekey]` syntax.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::State;
+
+use crate::document_manager::{document_assets_dir, DocumentManager};
+use crate::error::KorppiError;
+
+/// Better BibTeX JSON-RPC endpoint of the local Zotero
+const ZOTERO_RPC_URL: &str = "http://127.0.0.1:23119/better-bibtex/json-rpc";
+
+/// Bibliography file among the document assets
+pub const BIBLIOGRAPHY_FILE: &str = "references.bib";
+
+/// A reference found in the Zotero library
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ZoteroItem {
+    pub citekey: String,
+    pub title: String,
+    /// Family names of the first authors, "Doe, Roe et al."
+    pub authors: String,
+    pub year: Option<String>,
+    /// CSL item type, e.g. "article-journal"
+    pub item_type: String,
+}
+
+/// Result of insert_citation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Citation {
+    /// Text to insert, e.g. "[@doe2020; @roe2021]"
+    pub citation: String,
+    /// Citekeys whose entries were added to the bibliography
+    pub added: Vec<String>,
+}
+
+/// Call a Better BibTeX JSON-RPC method and return its result
+fn rpc(method: &str, params: Value) -> Result<Value, KorppiError> {
+    let request = json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 });
+    let response = match ureq::post(ZOTERO_RPC_URL)
+        .timeout(Duration::from_secs(10))
+        .send_json(request)
+    {
+        Ok(response) => response,
+        Err(ureq::Error::Status(status, _)) => {
+            return Err(KorppiError::ZoteroUnavailable(format!(
+                "Zotero answered with HTTP {}; is Better BibTeX installed?",
+                status
+            )))
+        }
+        Err(e) => return Err(KorppiError::ZoteroUnavailable(format!("Could not reach Zotero: {}", e))),
+    };
+
+    let body: Value = response.into_json()?;
+    if let Some(error) = body.get("error") {
+        let message = error.get("message").and_then(Value::as_str).unwrap_or("request failed");
+        return Err(format!("Zotero: {}", message).into());
+    }
+    Ok(body.get("result").cloned().unwrap_or(Value::Null))
+}
+
+/// Read a CSL-JSON item as returned by `item.search`
+fn parse_item(item: &Value) -> Option<ZoteroItem> {
+    let citekey = ["citekey", "citation-key", "citationKey"]
+        .iter()
+        .find_map(|key| item.get(*key).and_then(Value::as_str))?;
+
+    let names: Vec<&str> = item
+        .get("author")
+        .and_then(Value::as_array)
+        .map(|authors| {
+            authors
+                .iter()
+                .filter_map(|a| a.get("family").or_else(|| a.get("literal")).and_then(Value::as_str))
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut authors = names.iter().take(2).copied().collect::<Vec<_>>().join(", ");
+    if names.len() > 2 {
+        authors.push_str(" et al.");
+    }
+
+    let year = item
+        .pointer("/issued/date-parts/0/0")
+        .and_then(|y| y.as_i64().map(|n| n.to_string()).or_else(|| y.as_str().map(str::to_string)));
+
+    Some(ZoteroItem {
+        citekey: citekey.to_string(),
+        title: item.get("title").and_then(Value::as_str).unwrap_or_default().to_string(),
+        authors,
+        year,
+        item_type: item.get("type").and_then(Value::as_str).unwrap_or_default().to_string(),
+    })
+}
+
+/// Search the Zotero library by author, title or year
+#[tauri::command]
+pub fn search_zotero(query: String) -> Result<Vec<ZoteroItem>, KorppiError> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let result = rpc("item.search", json!([query]))?;
+    Ok(result.as_array().map(|items| items.iter().filter_map(parse_item).collect()).unwrap_or_default())
+}
+
+/// Export references as BibTeX
+fn export_bibtex(citekeys: &[String]) -> Result<String, KorppiError> {
+    let result = rpc("item.export", json!([citekeys, "Better BibTeX"]))?;
+    // Older Better BibTeX versions answer [status, content type, body]
+    let bibtex = match &result {
+        Value::String(bibtex) => Some(bibtex.as_str()),
+        Value::Array(parts) => parts.last().and_then(Value::as_str),
+        _ => None,
+    };
+    bibtex
+        .map(str::to_string)
+        .ok_or_else(|| "Zotero returned no BibTeX".into())
+}
+
+/// Citekeys of the entries of a BibTeX file
+pub(crate) fn bibtex_keys(bibtex: &str) -> HashSet<String> {
+    let re = Regex::new(r"(?m)^\s*@\w+\s*\{\s*([^,\s]+)\s*,").unwrap();
+    re.captures_iter(bibtex).map(|c| c[1].to_string()).collect()
+}
+
+/// Append the entries of `bibtex` that are not in the bibliography yet
+///
+/// Returns the citekeys of the added entries.
+pub(crate) fn append_entries(bib_path: &Path, bibtex: &str) -> Result<Vec<String>, String> {
+    let mut existing = if bib_path.exists() {
+        fs::read_to_string(bib_path).map_err(|e| format!("Failed to read bibliography: {}", e))?
+    } else {
+        String::new()
+    };
+    let mut known = bibtex_keys(&existing);
+
+    let mut added = Vec::new();
+    let starts: Vec<usize> = Regex::new(r"(?m)^\s*@\w+\s*\{").unwrap().find_iter(bibtex).map(|m| m.start()).collect();
+    for (i, start) in starts.iter().enumerate() {
+        let entry = bibtex[*start..starts.get(i + 1).copied().unwrap_or(bibtex.len())].trim();
+        let Some(key) = bibtex_keys(entry).into_iter().next() else {
+            continue;
+        };
+        if !known.insert(key.clone()) {
+            continue;
+        }
+        if !existing.is_empty() && !existing.ends_with("\n\n") {
+            existing.push_str(if existing.ends_with('\n') { "\n" } else { "\n\n" });
+        }
+        existing.push_str(entry);
+        existing.push('\n');
+        added.push(key);
+    }
+
+    if !added.is_empty() {
+        if let Some(parent) = bib_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create assets directory: {}", e))?;
+        }
+        fs::write(bib_path, existing).map_err(|e| format!("Failed to write bibliography: {}", e))?;
+    }
+    Ok(added)
+}
+
+/// Pandoc citation of one or more references
+pub(crate) fn citation_text(citekeys: &[String]) -> String {
+    let keys: Vec<String> = citekeys.iter().map(|k| format!("@{}", k)).collect();
+    format!("[{}]", keys.join("; "))
+}
+
+/// Add references from Zotero to a document's bibliography and return the
+/// citation to insert
+#[tauri::command]
+pub fn insert_citation(
+    manager: State<'_, Mutex<DocumentManager>>,
+    doc_id: String,
+    citekeys: Vec<String>,
+) -> Result<Citation, KorppiError> {
+    if citekeys.is_empty() {
+        return Err(KorppiError::InvalidInput("No references selected".to_string()));
+    }
+    let history_path = {
+        let manager = manager.lock().map_err(|e| e.to_string())?;
+        let doc = manager
+            .documents
+            .get(&doc_id)
+            .ok_or_else(|| KorppiError::DocumentNotFound(doc_id.clone()))?;
+        doc.history_path.clone()
+    };
+
+    let bib_path = document_assets_dir(&history_path).join(BIBLIOGRAPHY_FILE);
+    let known = bibtex_keys(&fs::read_to_string(&bib_path).unwrap_or_default());
+    let missing: Vec<String> = citekeys.iter().filter(|k| !known.contains(*k)).cloned().collect();
+
+    let added = if missing.is_empty() {
+        Vec::new()
+    } else {
+        append_entries(&bib_path, &export_bibtex(&missing)?)?
+    };
+    if !added.is_empty() {
+        let mut manager = manager.lock().map_err(|e| e.to_string())?;
+        if let Some(doc) = manager.documents.get_mut(&doc_id) {
+            doc.handle.is_modified = true;
+        }
+    }
+
+    Ok(Citation {
+        citation: citation_text(&citekeys),
+        added,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_item() {
+        let item = json!({
+            "citekey": "doe2020",
+            "title": "Reproducible Writing",
+            "type": "article-journal",
+            "author": [{"family": "Doe", "given": "Jane"}, {"family": "Roe"}, {"literal": "R Core Team"}],
+            "issued": {"date-parts": [[2020, 5]]}
+        });
+        let parsed = parse_item(&item).unwrap();
+
+        assert_eq!(parsed.citekey, "doe2020");
+        assert_eq!(parsed.authors, "Doe, Roe et al.");
+        assert_eq!(parsed.year.as_deref(), Some("2020"));
+        assert!(parse_item(&json!({"title": "No key"})).is_none());
+        assert_eq!(citation_text(&["doe2020".to_string(), "roe2021".to_string()]), "[@doe2020; @roe2021]");
+    }
+
+    #[test]
+    fn test_append_entries_skips_known_keys() {
+        let dir = TempDir::new().unwrap();
+        let bib_path = dir.path().join("assets").join(BIBLIOGRAPHY_FILE);
+
+        let first = "@article{doe2020,\n  title = {Reproducible Writing},\n}\n";
+        assert_eq!(append_entries(&bib_path, first).unwrap(), vec!["doe2020"]);
+
+        let second = "@article{doe2020,\n  title = {Reproducible Writing},\n}\n\n@book{roe2021,\n  title = {Plain Text},\n}\n";
+        assert_eq!(append_entries(&bib_path, second).unwrap(), vec!["roe2021"]);
+
+        let bib = fs::read_to_string(&bib_path).unwrap();
+        assert_eq!(bibtex_keys(&bib).len(), 2);
+        assert_eq!(bib.matches("doe2020").count(), 1);
+    }
+}
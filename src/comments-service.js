@@ -2,7 +2,7 @@
 // Comments service with Yjs relative position anchoring
 
 import * as Y from "yjs";
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "./tauri-invoke.js";
 import { ydoc, yXmlFragment } from "./yjs-setup.js";
 import { getActiveDocumentId } from "./document-manager.js";
 import { getProfile } from "./profile-service.js";
@@ -209,6 +209,39 @@ export async function restoreComment(commentId) {
     });
 }
 
+/**
+ * Re-anchor comments after the document text was replaced (restore, import).
+ * Found threads come back with empty anchors; the rest are flagged orphaned.
+ * @param {string|null} content - New markdown, or null for the stored text
+ * @returns {Promise<Array>} { comment_id, orphaned, start, end, matched_text }
+ */
+export async function rebaseCommentAnchors(content = null) {
+    const docId = getActiveDocumentId();
+    if (!docId) return [];
+
+    return await invoke("rebase_comment_anchors", {
+        docId,
+        content
+    });
+}
+
+/**
+ * Store fresh Yjs anchors for a comment thread.
+ * @param {number} commentId - Root comment ID
+ * @param {Object} anchor - { startAnchor, endAnchor }
+ */
+export async function updateCommentAnchors(commentId, anchor) {
+    const docId = getActiveDocumentId();
+    if (!docId) throw new Error("No active document");
+
+    await invoke("update_comment_anchors", {
+        docId,
+        commentId,
+        startAnchor: anchor.startAnchor,
+        endAnchor: anchor.endAnchor
+    });
+}
+
 // ============================================================================
 // Helper: Build thread structure from flat list
 // ============================================================================
@@ -12,6 +12,8 @@ import {
     deleteComment,
     markCommentDeleted,
     restoreComment,
+    rebaseCommentAnchors,
+    updateCommentAnchors,
     buildCommentThreads
 } from "./comments-service.js";
 import { getEditorContent, editor, editorViewCtx } from "./editor.js";
@@ -455,8 +457,8 @@ async function checkForOrphanedComments() {
     if (!documentText) return;
 
     for (const comment of unresolvedComments) {
-        // Skip replies
-        if (comment.parent_id !== null) continue;
+        // Skip replies, and orphans flagged by re-anchoring
+        if (comment.parent_id !== null || comment.orphaned) continue;
 
         // Try fuzzy match (simpler and more reliable than Yjs anchors for now)
         const found = documentText.includes(comment.selected_text);
@@ -472,6 +474,57 @@ async function checkForOrphanedComments() {
     }
 }
 
+/**
+ * Find the first occurrence of a text in the editor.
+ * @returns {Object|null} { from, to } ProseMirror positions
+ */
+function findTextInEditor(searchText) {
+    if (!editor || !searchText) return null;
+
+    let position = null;
+    editor.action((ctx) => {
+        const doc = ctx.get(editorViewCtx).state.doc;
+        doc.descendants((node, pos) => {
+            if (position) return false;
+            if (node.isText) {
+                const idx = node.text.indexOf(searchText);
+                if (idx !== -1) {
+                    position = { from: pos + idx, to: pos + idx + searchText.length };
+                    return false;
+                }
+            }
+        });
+    });
+    return position;
+}
+
+/**
+ * Re-anchor comments after the text was replaced wholesale.
+ * The backend matches each thread's selected text against the new content;
+ * found threads get fresh Yjs anchors, the rest are shown as orphaned.
+ * @param {string|null} content - New markdown, or null for the stored text
+ */
+export async function reanchorComments(content = null) {
+    try {
+        const results = await rebaseCommentAnchors(content);
+        const comments = await listComments();
+
+        for (const result of results) {
+            if (result.orphaned) continue;
+            const comment = comments.find(c => c.id === result.comment_id);
+            const position = findTextInEditor(comment?.selected_text);
+            if (!position) continue;
+
+            const anchor = createCommentAnchor(position.from, position.to, comment.selected_text);
+            await updateCommentAnchors(comment.id, anchor);
+        }
+
+        await refreshComments();
+    } catch (err) {
+        console.warn("Failed to re-anchor comments:", err);
+    }
+}
+
 /**
  * Render the comments list in the sidebar.
  * @param {Array} comments - List of comments
@@ -559,9 +612,11 @@ function renderCommentThread(thread, documentText) {
 
     const statusBadge = isDeleted
         ? '<span class="comment-status deleted">⚠ Text deleted</span>'
-        : isResolved
-            ? '<span class="comment-status resolved">✓ Resolved</span>'
-            : '';
+        : thread.orphaned
+            ? '<span class="comment-status deleted">⚠ Text not found</span>'
+            : isResolved
+                ? '<span class="comment-status resolved">✓ Resolved</span>'
+                : '';
 
     return `
         <div class="comment-item ${isDeleted ? 'deleted' : ''} ${isResolved ? 'resolved' : ''}" data-comment-id="${thread.id}">
@@ -6,9 +6,14 @@ import { toggleMark } from "@milkdown/prose/commands";
 import { setBlockType, wrapIn, lift } from "@milkdown/prose/commands";
 import { wrapInList } from "@milkdown/prose/schema-list";
 import { registerFigure, figureRegistry, sectionRegistry, tableRegistry, getReferenceText } from "../milkdown-figure.js";
+import { invoke } from "../tauri-invoke.js";
+import { getActiveDocumentId } from "../document-manager.js";
 
 let editorInstance = null;
 
+// A DOI, bare or as doi: or doi.org link
+const DOI_PATTERN = /^(https?:\/\/(dx\.)?doi\.org\/|doi:)?10\.\d{4,9}\/\S+$/i;
+
 /**
  * Initialize the formatting toolbar
  * @param {Object} editor - The Milkdown editor instance
@@ -48,6 +53,7 @@ export function initFormattingToolbar(editor) {
         { id: 'link', icon: '🔗', title: 'Insert Link', action: 'link' },
         { id: 'figure', icon: '🖼️', title: 'Insert Image', action: 'figure' },
         { id: 'crossref', icon: '§', title: 'Insert Cross-Reference', action: 'crossref' },
+        { id: 'cite', icon: '❞', title: 'Insert Citation from Zotero or by DOI', action: 'citation' },
         { id: 'table', icon: '⊞', title: 'Insert Table', action: 'table' },
         { id: 'break', icon: '↵', title: 'Hard Break', action: 'hardbreak' },
     ];
@@ -84,6 +90,8 @@ export function initFormattingToolbar(editor) {
                     insertFigureCommand();
                 } else if (btn.action === 'crossref') {
                     insertCrossRefCommand();
+                } else if (btn.action === 'citation') {
+                    insertCitationCommand();
                 } else if (btn.action === 'table') {
                     insertTableCommand();
                 } else if (btn.action === 'hardbreak') {
@@ -795,6 +803,160 @@ function showCrossRefDialog(refs, callback) {
     });
 }
 
+/**
+ * Insert a citation picked from Zotero or looked up by DOI
+ * Adds the references to the document bibliography and inserts [@citekey]
+ */
+function insertCitationCommand() {
+    if (!editorInstance) return;
+    const docId = getActiveDocumentId();
+    if (!docId) return;
+
+    showCitationDialog(async ({ citekeys, doi }) => {
+        try {
+            const { citation } = doi
+                ? await invoke('insert_doi_citation', { docId, doi })
+                : await invoke('insert_citation', { docId, citekeys });
+            editorInstance.action((ctx) => {
+                const view = ctx.get(editorViewCtx);
+                const { state, dispatch } = view;
+                const { from } = state.selection;
+
+                dispatch(state.tr.insertText(citation, from));
+                view.focus();
+            });
+        } catch (err) {
+            console.error('Failed to insert citation:', err);
+            alert('Failed to insert citation: ' + err);
+        }
+    });
+}
+
+/**
+ * Show dialog for searching Zotero and selecting references, or entering a DOI
+ */
+function showCitationDialog(callback) {
+    const overlay = document.createElement('div');
+    overlay.className = 'modal';
+    overlay.style.display = 'flex';
+
+    overlay.innerHTML = `
+        <div class="modal-content" style="max-width: 500px;">
+            <div class="modal-header">
+                <h2>Insert Citation</h2>
+            </div>
+            <div class="modal-body">
+                <div class="form-group">
+                    <label for="citation-search">Search Zotero or enter a DOI:</label>
+                    <input type="text" id="citation-search" placeholder="Author, title, year or 10.xxxx/..." style="width: 100%;">
+                </div>
+                <div class="form-group">
+                    <select id="citation-results" multiple size="8" style="width: 100%;"></select>
+                    <small id="citation-status" style="color: var(--text-muted);">Requires Zotero with Better BibTeX running.</small>
+                </div>
+            </div>
+            <div class="modal-footer">
+                <button id="citation-cancel" class="btn-secondary">Cancel</button>
+                <button id="citation-insert" class="btn-primary">Insert</button>
+            </div>
+        </div>
+    `;
+
+    document.body.appendChild(overlay);
+
+    const searchInput = overlay.querySelector('#citation-search');
+    const resultsEl = overlay.querySelector('#citation-results');
+    const statusEl = overlay.querySelector('#citation-status');
+    const insertBtn = overlay.querySelector('#citation-insert');
+    const cancelBtn = overlay.querySelector('#citation-cancel');
+
+    searchInput.focus();
+
+    const cleanup = () => {
+        document.body.removeChild(overlay);
+    };
+
+    let searchTimeout = null;
+    let searchId = 0;
+    searchInput.addEventListener('input', () => {
+        if (searchTimeout) clearTimeout(searchTimeout);
+        searchTimeout = setTimeout(async () => {
+            const id = ++searchId;
+            const doi = searchInput.value.trim();
+            if (DOI_PATTERN.test(doi)) {
+                resultsEl.innerHTML = '';
+                statusEl.textContent = 'Looking up DOI...';
+                try {
+                    const metadata = await invoke('fetch_doi_metadata', { doi });
+                    if (id === searchId) statusEl.textContent = metadata.citation;
+                } catch (err) {
+                    if (id === searchId) statusEl.textContent = String(err);
+                }
+                return;
+            }
+            try {
+                const items = await invoke('search_zotero', { query: searchInput.value });
+                if (id !== searchId) return;
+
+                resultsEl.innerHTML = '';
+                for (const item of items) {
+                    const option = document.createElement('option');
+                    option.value = item.citekey;
+                    option.textContent = `${item.authors || '?'} (${item.year || 'n.d.'}) ${item.title}`;
+                    option.title = `@${item.citekey}`;
+                    resultsEl.appendChild(option);
+                }
+                statusEl.textContent = `${items.length} reference(s) found`;
+            } catch (err) {
+                if (id !== searchId) return;
+                statusEl.textContent = err.hint ? `${err.message} ${err.hint}` : String(err);
+            }
+        }, 300);
+    });
+
+    insertBtn.addEventListener('click', () => {
+        const citekeys = Array.from(resultsEl.selectedOptions).map(o => o.value);
+        const doi = searchInput.value.trim();
+        if (citekeys.length === 0 && DOI_PATTERN.test(doi)) {
+            cleanup();
+            callback({ doi });
+            return;
+        }
+        if (citekeys.length === 0) {
+            resultsEl.focus();
+            return;
+        }
+
+        cleanup();
+        callback({ citekeys });
+    });
+
+    cancelBtn.addEventListener('click', cleanup);
+
+    const handleKeydown = (e) => {
+        if (e.key === 'Enter') {
+            e.preventDefault();
+            insertBtn.click();
+        } else if (e.key === 'Escape') {
+            cleanup();
+        }
+    };
+
+    resultsEl.addEventListener('keydown', handleKeydown);
+    searchInput.addEventListener('keydown', (e) => {
+        if (e.key === 'ArrowDown' && resultsEl.options.length > 0) {
+            e.preventDefault();
+            resultsEl.focus();
+            resultsEl.selectedIndex = 0;
+        } else if (e.key === 'Escape') {
+            cleanup();
+        }
+    });
+
+    overlay.addEventListener('click', (e) => {
+        if (e.target === overlay) cleanup();
+    });
+}
 
 /**
  * Insert hard break (line break within paragraph)
@@ -3,7 +3,7 @@
 
 import { getProfile, saveProfile, initProfile } from "../profile-service.js";
 import { save, open } from "@tauri-apps/plugin-dialog";
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "../tauri-invoke.js";
 
 let profileModal = null;
 let currentProfile = null;
@@ -49,8 +49,8 @@ function updateProfileButton(profile) {
     if (!profileBtn) return;
 
     // Set avatar or initials
-    if (profile.avatar_path) {
-        profileBtn.innerHTML = `<img src="${profile.avatar_path}" alt="Avatar">`;
+    if (profile.avatar_base64) {
+        profileBtn.innerHTML = `<img src="${avatarUrl(profile)}" alt="Avatar">`;
     } else {
         const initials = getInitials(profile.name || 'User');
         profileBtn.textContent = initials;
@@ -65,6 +65,13 @@ function updateProfileButton(profile) {
     }
 }
 
+/**
+ * Data URL of a profile's avatar
+ */
+function avatarUrl(profile) {
+    return `data:image/png;base64,${profile.avatar_base64}`;
+}
+
 /**
  * Get initials from a name
  */
@@ -129,6 +136,15 @@ function setupProfileModal() {
     if (avatarInput) {
         avatarInput.addEventListener('change', handleAvatarUpload);
     }
+
+    // Keep the picked color even if the modal is closed without saving
+    const colorInput = document.getElementById('profile-color');
+    if (colorInput) {
+        colorInput.addEventListener('input', () => {
+            document.getElementById('profile-color-value').textContent = colorInput.value;
+        });
+        colorInput.addEventListener('change', handleColorChange);
+    }
 }
 
 /**
@@ -150,8 +166,8 @@ async function openProfileModal() {
     // Update avatar preview
     const avatarPreview = document.getElementById('profile-avatar-preview');
     if (avatarPreview) {
-        if (currentProfile?.avatar_path) {
-            avatarPreview.innerHTML = `<img src="${currentProfile.avatar_path}" alt="Avatar">`;
+        if (currentProfile?.avatar_base64) {
+            avatarPreview.innerHTML = `<img src="${avatarUrl(currentProfile)}" alt="Avatar">`;
         } else {
             avatarPreview.textContent = getInitials(currentProfile?.name || '');
         }
@@ -183,17 +199,38 @@ async function handleAvatarUpload(e) {
         return;
     }
 
-    // Read as data URL for preview
-    const reader = new FileReader();
-    reader.onload = (e) => {
+    // The backend crops and scales the image and saves it in the profile
+    try {
+        const image = Array.from(new Uint8Array(await file.arrayBuffer()));
+        const profile = await invoke('set_profile_avatar', { image });
+        currentProfile = { ...currentProfile, avatar_base64: profile.avatar_base64 };
+        await initProfile();
+
         const avatarPreview = document.getElementById('profile-avatar-preview');
         if (avatarPreview) {
-            avatarPreview.innerHTML = `<img src="${e.target.result}" alt="Avatar">`;
+            avatarPreview.innerHTML = `<img src="${avatarUrl(currentProfile)}" alt="Avatar">`;
         }
-        // Store the data URL temporarily
-        avatarPreview.dataset.newAvatar = e.target.result;
-    };
-    reader.readAsDataURL(file);
+        updateProfileButton(currentProfile);
+    } catch (err) {
+        console.error('Failed to set avatar:', err);
+        alert('Failed to set avatar: ' + err);
+    } finally {
+        e.target.value = '';
+    }
+}
+
+/**
+ * Save the picked highlight color right away
+ */
+async function handleColorChange(e) {
+    try {
+        const profile = await invoke('set_profile_color', { color: e.target.value });
+        currentProfile = { ...currentProfile, color: profile.color };
+        await initProfile();
+        updateProfileButton(currentProfile);
+    } catch (err) {
+        console.error('Failed to save color:', err);
+    }
 }
 
 /**
@@ -208,15 +245,13 @@ async function handleSaveProfile() {
         return;
     }
 
-    const avatarPreview = document.getElementById('profile-avatar-preview');
-    const newAvatarData = avatarPreview?.dataset.newAvatar;
-
     const profile = {
         id: currentProfile?.id,
         name: name,
         email: document.getElementById('profile-email').value.trim() || null,
-        avatar_path: newAvatarData || currentProfile?.avatar_path || null,
+        avatar_path: currentProfile?.avatar_path || null,
         color: document.getElementById('profile-color').value || '#4fc3f7',
+        avatar_base64: currentProfile?.avatar_base64 || null,
     };
 
     try {
@@ -1,4 +1,5 @@
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "./tauri-invoke.js";
+import { listen } from "@tauri-apps/api/event";
 
 /**
  * Scan for conflicts in the patch history
@@ -19,20 +20,56 @@ export async function getConflicts() {
  * @param {string} conflictId
  * @param {'ResolvedLocal' | 'ResolvedRemote' | 'ResolvedMerged' | 'ResolvedBoth'} resolution
  * @param {string|null} mergedContent - Required if resolution is 'ResolvedMerged'
+ * @param {string|null} patchUuid - Save patch the resolution was applied in, if any
  */
-export async function resolveConflict(conflictId, resolution, mergedContent = null) {
+export async function resolveConflict(conflictId, resolution, mergedContent = null, patchUuid = null) {
     return await invoke("resolve_conflict", {
         resolution: {
             conflict_id: conflictId,
             resolution: resolution,
             merged_content: mergedContent,
+            patch_uuid: patchUuid,
         }
     });
 }
 
+/**
+ * Merge both versions of a conflict word by word against its base
+ * @param {string} conflictId
+ * @returns {Promise<{merged: string, collisions: Array<{base: string, local: string, remote: string}>}>}
+ */
+export async function autoMergeConflict(conflictId) {
+    return await invoke("auto_merge_conflict", { conflictId });
+}
+
+/**
+ * Return a resolved conflict to the unresolved list
+ * @param {string} conflictId
+ */
+export async function reopenConflict(conflictId) {
+    return await invoke("reopen_conflict", { conflictId });
+}
+
+/**
+ * Get past resolutions, newest first
+ * @param {string|null} conflictId - Limit to one conflict, or null for all
+ */
+export async function listResolutionHistory(conflictId = null) {
+    return await invoke("list_resolution_history", { conflictId });
+}
+
 /**
  * Get count of unresolved conflicts
  */
 export async function getConflictCount() {
     return await invoke("get_conflict_count");
 }
+
+/**
+ * Listen for conflicts found automatically after patches are imported
+ * @param {function({doc_id: string, count: number})} callback
+ * @returns {Promise<function>} Unlisten function
+ */
+export async function onImportConflicts(callback) {
+    return await listen("import-conflicts-detected", (event) => callback(event.payload));
+}
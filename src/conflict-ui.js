@@ -1,4 +1,5 @@
-import { getConflicts, resolveConflict, detectConflicts } from "./conflict-service.js";
+import { getConflicts, resolveConflict, detectConflicts, reopenConflict, listResolutionHistory, autoMergeConflict } from "./conflict-service.js";
+import { onConflictDetected } from "./document-events.js";
 
 /**
  * Initialize the conflict resolution UI
@@ -35,9 +36,11 @@ export function initConflictUI() {
         <div class="conflict-header">
             <h2>Conflicts</h2>
             <button id="scan-conflicts">🔍 Scan</button>
+            <button id="conflict-history-toggle">📜 History</button>
             <button id="close-conflicts">✕</button>
         </div>
         <div id="conflict-list"></div>
+        <div id="conflict-history" style="display: none;"></div>
         <div id="conflict-detail" style="display: none;">
             <h3>Resolve Conflict</h3>
             <div class="conflict-versions">
@@ -58,6 +61,8 @@ export function initConflictUI() {
             </div>
             <div class="merge-section">
                 <h4>Or Merge Manually</h4>
+                <button id="auto-merge-btn">🔀 Auto-merge words</button>
+                <div id="auto-merge-status"></div>
                 <textarea id="merge-content" rows="4"></textarea>
                 <button class="resolve-btn" data-resolution="ResolvedMerged">
                     ✓ Use Merged Version
@@ -90,9 +95,20 @@ export function initConflictUI() {
     conflictBtn.addEventListener("click", toggleConflictPanel);
     document.getElementById("close-conflicts").addEventListener("click", toggleConflictPanel);
     document.getElementById("scan-conflicts").addEventListener("click", scanForConflicts);
+    document.getElementById("conflict-history-toggle").addEventListener("click", toggleResolutionHistory);
+    document.getElementById("conflict-history").addEventListener("click", handleReopen);
+    document.getElementById("auto-merge-btn").addEventListener("click", handleAutoMerge);
     document.getElementById("conflict-list").addEventListener("click", handleConflictSelect);
     panel.addEventListener("click", handleResolution);
 
+    // Detection (manual or after an import) announces new conflicts
+    onConflictDetected(async () => {
+        await updateConflictBadge();
+        if (panel.style.display !== "none") {
+            await loadConflicts();
+        }
+    });
+
     // Initial badge update
     updateConflictBadge();
 }
@@ -157,6 +173,73 @@ async function loadConflicts() {
     }
 }
 
+async function toggleResolutionHistory() {
+    const history = document.getElementById("conflict-history");
+    const list = document.getElementById("conflict-list");
+    const showHistory = history.style.display === "none";
+
+    history.style.display = showHistory ? "block" : "none";
+    list.style.display = showHistory ? "none" : "block";
+    document.getElementById("conflict-detail").style.display = "none";
+
+    if (showHistory) {
+        await loadResolutionHistory();
+    } else {
+        await loadConflicts();
+    }
+}
+
+async function loadResolutionHistory() {
+    const history = document.getElementById("conflict-history");
+
+    try {
+        const resolutions = await listResolutionHistory();
+        if (resolutions.length === 0) {
+            history.innerHTML = `<div class="no-conflicts">No resolutions yet</div>`;
+            return;
+        }
+
+        history.innerHTML = resolutions.map(r => `
+            <div class="conflict-item resolution-item">
+                <span class="conflict-type">${formatResolution(r.strategy)}</span>
+                <span class="conflict-authors">
+                    By ${r.author} on ${new Date(r.resolved_at).toLocaleString()}
+                </span>
+                ${r.patch_uuid ? `<span class="conflict-pos">Applied in patch ${r.patch_uuid.slice(0, 8)}</span>` : ""}
+                ${r.reopened_at
+                    ? `<span class="conflict-pos">Reopened ${new Date(r.reopened_at).toLocaleString()}</span>`
+                    : `<button class="reopen-btn" data-id="${r.conflict_id}">↩ Reopen</button>`}
+            </div>
+        `).join("");
+    } catch (err) {
+        history.innerHTML = `<div class="error">Failed to load resolution history</div>`;
+    }
+}
+
+async function handleReopen(event) {
+    const btn = event.target.closest(".reopen-btn");
+    if (!btn) return;
+
+    try {
+        await reopenConflict(btn.dataset.id);
+        await loadResolutionHistory();
+        await updateConflictBadge();
+    } catch (err) {
+        console.error("Failed to reopen conflict:", err);
+        alert("Failed to reopen conflict: " + err);
+    }
+}
+
+function formatResolution(strategy) {
+    const labels = {
+        ResolvedLocal: "Kept mine",
+        ResolvedRemote: "Kept theirs",
+        ResolvedMerged: "Merged manually",
+        ResolvedBoth: "Kept both",
+    };
+    return labels[strategy] || strategy;
+}
+
 function handleConflictSelect(event) {
     const item = event.target.closest(".conflict-item");
     if (!item) return;
@@ -179,11 +262,28 @@ async function showConflictDetail(conflictId) {
         conflict.remote_version.content || "(empty)";
     document.getElementById("merge-content").value =
         (conflict.local_version.content || "") + "\n" + (conflict.remote_version.content || "");
+    document.getElementById("auto-merge-status").textContent = "";
 
     document.getElementById("conflict-list").style.display = "none";
     document.getElementById("conflict-detail").style.display = "block";
 }
 
+async function handleAutoMerge() {
+    if (!currentConflict) return;
+
+    const status = document.getElementById("auto-merge-status");
+    try {
+        const result = await autoMergeConflict(currentConflict.id);
+        document.getElementById("merge-content").value = result.merged;
+        status.textContent = result.collisions.length === 0
+            ? "Merged cleanly"
+            : `${result.collisions.length} colliding change(s) left to edit`;
+    } catch (err) {
+        console.error("Failed to auto-merge conflict:", err);
+        status.textContent = "Auto-merge failed: " + err;
+    }
+}
+
 async function handleResolution(event) {
     const btn = event.target.closest(".resolve-btn");
     if (!btn || !currentConflict) return;
@@ -299,6 +399,11 @@ function addConflictStyles() {
             display: block;
         }
 
+        .reopen-btn {
+            margin-top: 6px;
+            font-size: 12px;
+        }
+
         .no-conflicts {
             text-align: center;
             padding: 40px;
@@ -395,6 +500,3 @@ function addConflictStyles() {
     `;
     document.head.appendChild(style);
 }
-
-// Auto-check for conflicts periodically
-setInterval(updateConflictBadge, 30000);
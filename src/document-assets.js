@@ -0,0 +1,79 @@
+// src/document-assets.js
+// Display and export of images stored with a document (`assets/NAME`),
+// such as HTML import images and rendered Quarto figures.
+//
+// The files live in the document's temp directory, so the editor swaps
+// `assets/` sources for asset URLs when it renders them, and exports
+// rewrite them to asset URLs that the backend maps back to file paths.
+
+import { convertFileSrc } from "@tauri-apps/api/core";
+import { invoke } from "./tauri-invoke.js";
+import { getActiveDocumentId } from "./document-manager.js";
+
+const ASSET_PREFIX = "assets/";
+
+// Asset URL of each asset name of the active document
+let assetUrls = new Map();
+
+/**
+ * Reload the asset list of the active document and update displayed images
+ */
+export async function refreshDocumentAssets() {
+    const docId = getActiveDocumentId();
+    assetUrls = new Map();
+    if (docId) {
+        try {
+            const assets = await invoke("list_document_assets", { docId });
+            for (const asset of assets) {
+                // Cache-bust so a re-rendered figure with the same name reloads
+                assetUrls.set(asset.name, `${convertFileSrc(asset.path)}?v=${Date.now()}`);
+            }
+        } catch (err) {
+            console.error("Failed to list document assets:", err);
+        }
+    }
+    resolveImages(document.getElementById("editor"));
+}
+
+/**
+ * Point `assets/` images under an element to their files
+ * @param {Element|null} root
+ */
+function resolveImages(root) {
+    if (!root) return;
+    for (const img of root.querySelectorAll(`img[src^="${ASSET_PREFIX}"], img[data-asset]`)) {
+        const name = img.dataset.asset || img.getAttribute("src").slice(ASSET_PREFIX.length);
+        const url = assetUrls.get(name);
+        if (url && img.getAttribute("src") !== url) {
+            img.dataset.asset = name;
+            img.setAttribute("src", url);
+        }
+    }
+}
+
+/**
+ * Replace `assets/` image paths with asset URLs, for exports through pandoc
+ * @param {string} markdown
+ * @returns {string}
+ */
+export function withAbsoluteAssetPaths(markdown) {
+    return markdown.replace(/\]\(assets\/([^)\s]+)/g, (match, name) => {
+        const url = assetUrls.get(name);
+        return url ? `](${url.split("?")[0]}` : match;
+    });
+}
+
+/**
+ * Initialize asset display for the editor
+ */
+export function initDocumentAssets() {
+    const editor = document.getElementById("editor");
+    if (editor) {
+        // Images are recreated whenever the editor redraws them
+        new MutationObserver(() => resolveImages(editor))
+            .observe(editor, { childList: true, subtree: true });
+    }
+
+    window.addEventListener("yjs-doc-replaced", () => refreshDocumentAssets());
+    refreshDocumentAssets();
+}
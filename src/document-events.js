@@ -0,0 +1,115 @@
+// src/document-events.js
+// Subscriptions to document lifecycle events emitted by the backend
+
+import { listen } from "@tauri-apps/api/event";
+import { getCurrentWebviewWindow } from "@tauri-apps/api/webviewWindow";
+
+/**
+ * Listen for documents being created, opened or imported (in any window)
+ * @param {function(Object)} callback - Receives the DocumentHandle
+ * @returns {Promise<function>} Unlisten function
+ */
+export async function onDocumentOpened(callback) {
+    return await listen("document-opened", (event) => callback(event.payload));
+}
+
+/**
+ * Listen for documents being saved
+ * @param {function(Object)} callback - Receives the updated DocumentHandle
+ * @returns {Promise<function>} Unlisten function
+ */
+export async function onDocumentSaved(callback) {
+    return await listen("document-saved", (event) => callback(event.payload));
+}
+
+/**
+ * Listen for patches being recorded
+ * @param {function({doc_id: string|null, patch_id: number, uuid: string|null, author: string, kind: string, timestamp: number})} callback
+ * @returns {Promise<function>} Unlisten function
+ */
+export async function onPatchRecorded(callback) {
+    return await listen("patch-recorded", (event) => callback(event.payload));
+}
+
+/**
+ * Listen for newly detected conflicts
+ * @param {function({doc_id: string|null, conflict_ids: string[]})} callback
+ * @returns {Promise<function>} Unlisten function
+ */
+export async function onConflictDetected(callback) {
+    return await listen("conflict-detected", (event) => callback(event.payload));
+}
+
+/**
+ * Listen for comments and replies being added
+ * @param {function({doc_id: string, comment_id: number, parent_id: number|null, author: string})} callback
+ * @returns {Promise<function>} Unlisten function
+ */
+export async function onCommentAdded(callback) {
+    return await listen("comment-added", (event) => callback(event.payload));
+}
+
+/**
+ * Listen for progress of opening and saving KMD files
+ * @param {function({job_id: string, operation: "open"|"save", phase: string, bytes_done: number, bytes_total: number})} callback
+ * @returns {Promise<function>} Unlisten function
+ */
+export async function onFileProgress(callback) {
+    return await listen("file-progress", (event) => callback(event.payload));
+}
+
+/**
+ * Listen for Yjs updates from live session peers (already merged by the backend)
+ * @param {function({doc_id: string, peer: string, update: number[]})} callback
+ * @returns {Promise<function>} Unlisten function
+ */
+export async function onLiveUpdate(callback) {
+    return await listen("live-update", (event) => callback(event.payload));
+}
+
+/**
+ * Listen for awareness data (cursor, name) from live session peers
+ * @param {function({doc_id: string, peer: string, data: number[]})} callback
+ * @returns {Promise<function>} Unlisten function
+ */
+export async function onLiveAwareness(callback) {
+    return await listen("live-awareness", (event) => callback(event.payload));
+}
+
+/**
+ * Listen for live session peers disconnecting ("host" when a joined session ends)
+ * @param {function({doc_id: string, peer: string})} callback
+ * @returns {Promise<function>} Unlisten function
+ */
+export async function onLivePeerLeft(callback) {
+    return await listen("live-peer-left", (event) => callback(event.payload));
+}
+
+/**
+ * Listen for saved documents that are over a soft size limit
+ * @param {function({doc_id: string, total: number, history_file: number, assets: number, warnings: Array<{part: string, size: number, limit: number}>, suggest_compaction: boolean})} callback
+ * @returns {Promise<function>} Unlisten function
+ */
+export async function onDocumentSizeWarning(callback) {
+    return await listen("document-size-warning", (event) => callback(event.payload));
+}
+
+/**
+ * Listen for files handed over by a later launch of the app
+ * (double-clicking a .kmd file while Korppi is running)
+ * @param {function(string)} callback - Receives the absolute file path
+ * @returns {Promise<function>} Unlisten function
+ */
+export async function onOpenFileRequested(callback) {
+    // Sent to one window only; the global listen() would hear it in every window
+    return await getCurrentWebviewWindow().listen("open-file-requested", (event) => callback(event.payload));
+}
+
+/**
+ * Listen for korppi:// links opened from outside the app
+ * @param {function({doc_uuid: string, anchor?: string, section?: string, doc_id: string|null, path: string|null})} callback
+ * @returns {Promise<function>} Unlisten function
+ */
+export async function onPositionLinkOpened(callback) {
+    return await getCurrentWebviewWindow().listen("position-link-opened", (event) => callback(event.payload));
+}
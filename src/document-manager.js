@@ -1,7 +1,7 @@
 // src/document-manager.js
 // Frontend service for managing multiple documents
 
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "./tauri-invoke.js";
 import { save } from "@tauri-apps/plugin-dialog";
 import { getCachedProfile } from "./profile-service.js";
 
@@ -57,21 +57,84 @@ export async function newDocument() {
     return handle;
 }
 
+/**
+ * Check whether a KMD file opens normally or in safe mode, and what safe
+ * mode would refuse or leave out of it.
+ * @param {string} path - File path
+ * @returns {Promise<{path: string, title: string, authors: string[], trusted: boolean, problems: string[], stripped: string[], warnings: string[]}>}
+ */
+export async function checkDocumentTrust(path) {
+    return await invoke("check_document_trust", { path });
+}
+
+/**
+ * Ask before opening a file that is not among the recent documents.
+ * @param {Object} report - Result of checkDocumentTrust
+ * @returns {boolean} True to open the file in safe mode
+ */
+function confirmSafeOpen(report) {
+    if (report.problems.length) {
+        throw new Error(`Cannot open ${report.path}:\n${report.problems.join("\n")}`);
+    }
+    const by = report.authors.length ? ` by ${report.authors.join(", ")}` : "";
+    const left = report.stripped.length
+        ? `\n\nSafe mode leaves out:\n${report.stripped.map(s => `• ${s}`).join("\n")}`
+        : "";
+    const warnings = report.warnings.length ? `\n\nNote:\n${report.warnings.map(w => `• ${w}`).join("\n")}` : "";
+    return confirm(
+        `"${report.title || report.path}"${by} has not been opened on this computer before.\n\n` +
+        `Open it in safe mode? Its contents are checked and scripts and database triggers are removed.` +
+        left + warnings
+    );
+}
+
 /**
  * Open a document from file path (shows file picker if path is null)
+ * Files that are not among the recent documents are checked first and, once
+ * confirmed, opened in safe mode.
  * @param {string|null} path - Optional file path
+ * @param {boolean} readOnly - Open without taking the file lock
+ * @param {string|null} jobId - ID of the progress events, for cancelFileJob
+ * @param {boolean|null} safeMode - Force safe mode on or off; null decides by the recent documents
  * @returns {Promise<Object>} The document handle
  */
-export async function openDocument(path = null) {
-    const handle = await invoke("open_document", { path });
+export async function openDocument(path = null, readOnly = false, jobId = null, safeMode = null) {
+    let prompted = false;
+    if (path && safeMode === null) {
+        const report = await checkDocumentTrust(path);
+        if (!report.trusted) {
+            if (!confirmSafeOpen(report)) {
+                throw new Error("Opening cancelled");
+            }
+            safeMode = true;
+            prompted = true;
+        }
+    }
+    const handle = await invoke("open_document", { path, readOnly, jobId, safeMode });
     openDocuments.set(handle.id, handle);
     setActiveDocument(handle.id);
     notifyListeners("open", handle);
+
+    // Files picked in the dialog are not checked before opening
+    if (handle.safe_mode?.length && !prompted) {
+        alert(`This document was opened in safe mode. Left out:\n${handle.safe_mode.map(s => `• ${s}`).join("\n")}`);
+    }
+
+    // Edits of a session that crashed before saving were replayed from its journal
+    if (handle.recovered) {
+        const { updates, patches, dropped_patches } = handle.recovered;
+        alert(
+            `Korppi did not close properly the last time this document was open. ` +
+            `${updates} unsaved edit(s) and ${patches} patch(es) were recovered` +
+            (dropped_patches.length ? `; ${dropped_patches.length} patch(es) whose edits were lost were left out.` : `.`) +
+            ` Save the document to keep them.`
+        );
+    }
     return handle;
 }
 
 /**
- * Import a document from various formats (markdown, docx, odt)
+ * Import a document from various formats (markdown, docx, odt, html)
  * Shows file picker if path is null
  * For DOCX/ODT, checks if pandoc is available and prompts if not
  * @param {string|null} path - Optional file path
@@ -109,6 +172,30 @@ export async function importDocument(path = null) {
     return result;
 }
 
+/**
+ * Open, import or inspect files dropped on this window.
+ * Opened and imported documents are registered like openDocument/importDocument do;
+ * the last one becomes active.
+ * @param {string[]} paths - Absolute file paths
+ * @returns {Promise<Array<{kind: string, path: string}>>} One result per file:
+ *   "opened" (handle), "imported" (import), "patch_bundle" (manifest, target_doc_id),
+ *   "unsupported" or "failed" (error)
+ */
+export async function handleDroppedFiles(paths) {
+    const results = await invoke("handle_dropped_files", { paths });
+    for (const result of results) {
+        const handle = result.kind === "opened" ? result.handle
+            : result.kind === "imported" ? result.import.handle
+            : null;
+        if (handle) {
+            openDocuments.set(handle.id, handle);
+            setActiveDocument(handle.id);
+            notifyListeners(result.kind === "opened" ? "open" : "import", handle);
+        }
+    }
+    return results;
+}
+
 /**
  * Save the active or specified document
  * @param {string|null} id - Document ID (uses active if null)
@@ -139,6 +226,13 @@ export async function saveDocument(id = null, path = null) {
     const handle = await invoke("save_document", { id: docId, path });
     openDocuments.set(handle.id, handle);
 
+    // The file was changed on disk by someone else; their changes were merged
+    // into the open document and the file was left untouched
+    if (handle.external_merge) {
+        notifyListeners("externalMerge", handle);
+        return handle;
+    }
+
     notifyListeners("save", handle);
     return handle;
 }
@@ -307,6 +401,31 @@ export async function markDocumentModified(id, modified = true) {
     }
 }
 
+/**
+ * Set how the document state and history are compressed on save
+ * Zstd is faster and smaller for large histories; deflate opens in every Korppi version.
+ * @param {string} id - Document ID
+ * @param {"deflate"|"zstd"} compression - Compression method
+ * @returns {Promise<void>}
+ */
+export async function setDocumentCompression(id, compression) {
+    await invoke("set_document_compression", { id, compression });
+    const handle = openDocuments.get(id);
+    if (handle) {
+        handle.is_modified = true;
+        notifyListeners("modify", handle);
+    }
+}
+
+/**
+ * Stop an open or save that is still running
+ * @param {string} jobId - Job ID of its progress events
+ * @returns {Promise<boolean>} False when it already finished
+ */
+export async function cancelFileJob(jobId) {
+    return await invoke("cancel_file_job", { jobId });
+}
+
 /**
  * Update document title
  * @param {string} id - Document ID
@@ -323,13 +442,33 @@ export async function updateDocumentTitle(id, title) {
 }
 
 /**
- * Check for file opened via command line
+ * Get the heading tree of a document, with UTF-16 offsets and section word counts
+ * @param {string} id - Document ID
+ * @returns {Promise<Array<{level: number, title: string, label: string|null, start: number, heading_end: number, end: number, words: number, children: Array}>>}
+ */
+export async function getDocumentOutline(id) {
+    return await invoke("get_document_outline", { docId: id });
+}
+
+/**
+ * Check for the file this window should open on startup
+ * (command line for the main window, "open in new window" for others)
  * @returns {Promise<string|null>} File path or null
  */
 export async function getInitialFile() {
     return await invoke("get_initial_file");
 }
 
+/**
+ * Open a document in a new window, or an empty document if path is null.
+ * Focuses the window already showing the document, if any.
+ * @param {string|null} path - Optional file path
+ * @returns {Promise<string>} Label of the window
+ */
+export async function openInNewWindow(path = null) {
+    return await invoke("open_in_new_window", { path });
+}
+
 /**
  * Add a listener for document changes
  * @param {Function} listener - Callback function(event, document)
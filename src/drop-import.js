@@ -0,0 +1,93 @@
+// src/drop-import.js
+// Files dropped on the window: documents open, imports load their content
+// and patch bundles are applied after confirmation
+
+import { getCurrentWebview } from "@tauri-apps/api/webview";
+import { invoke } from "./tauri-invoke.js";
+import { handleDroppedFiles } from "./document-manager.js";
+import { dryRunImportBundle } from "./kmd-service.js";
+import { setMarkdownContent } from "./editor.js";
+
+/**
+ * Wait for the editor to switch to the newly active document
+ */
+function waitForDocumentSwitch() {
+    return new Promise(resolve => {
+        const handler = () => {
+            window.removeEventListener("yjs-doc-replaced", handler);
+            resolve();
+        };
+        window.addEventListener("yjs-doc-replaced", handler);
+        // Timeout fallback in case the event already fired
+        setTimeout(resolve, 100);
+    });
+}
+
+async function applyBundle(result) {
+    const { manifest, target_doc_id: docId, path } = result;
+    if (!docId) {
+        alert(`${path} contains changes to a document that is not open.\n\nOpen the document first, then drop the bundle again.`);
+        return;
+    }
+    const preview = await dryRunImportBundle(docId, path);
+    const changes = preview.state_changed ? `${preview.hunks.length} change(s) to the text` : "no change to the text";
+    if (!confirm(`Apply ${manifest.patch_count} patch(es) from ${path}?\n\n${preview.imported_patches} new patch(es), ${changes}.`)) {
+        return;
+    }
+    const applied = await invoke("apply_patch_bundle", { id: docId, path });
+    const sender = applied.signed_by ? `Signed by ${applied.signed_by}.` : "The bundle is not signed by a known collaborator.";
+    const unverified = applied.unverified_patches > 0 ? ` ${applied.unverified_patches} patch(es) are marked unverified.` : "";
+    alert(`Imported ${applied.imported_patches} patch(es), ${applied.skipped_patches} already present.\n\n${sender}${unverified}`);
+    window.dispatchEvent(new CustomEvent("patch-status-updated"));
+}
+
+async function onDrop(paths) {
+    let results;
+    try {
+        results = await handleDroppedFiles(paths);
+    } catch (err) {
+        console.error("Failed to handle dropped files:", err);
+        alert("Failed to open dropped files: " + err);
+        return;
+    }
+
+    const problems = [];
+    for (const result of results) {
+        try {
+            switch (result.kind) {
+                case "imported":
+                    if (result.import.content) {
+                        await waitForDocumentSwitch();
+                        setMarkdownContent(result.import.content);
+                        window.dispatchEvent(new CustomEvent("document-changed"));
+                    }
+                    break;
+                case "patch_bundle":
+                    await applyBundle(result);
+                    break;
+                case "unsupported":
+                    problems.push(`${result.path}: unsupported file type`);
+                    break;
+                case "failed":
+                    problems.push(`${result.path}: ${result.error.message}`);
+                    break;
+            }
+        } catch (err) {
+            problems.push(`${result.path}: ${err}`);
+        }
+    }
+    if (problems.length > 0) {
+        alert(`Some files could not be opened:\n${problems.join("\n")}`);
+    }
+}
+
+/**
+ * Route files dropped on the window through the backend
+ */
+export function initDropImport() {
+    getCurrentWebview().onDragDropEvent((event) => {
+        if (event.payload.type === "drop" && event.payload.paths.length > 0) {
+            onDrop(event.payload.paths);
+        }
+    });
+}
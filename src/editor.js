@@ -16,7 +16,7 @@ import { Plugin, PluginKey, TextSelection } from "@milkdown/prose/state";
 import { Decoration, DecorationSet } from "@milkdown/prose/view";
 import { ySyncPlugin, yUndoPlugin, undo, redo } from "y-prosemirror";
 import { keymap } from "@milkdown/prose/keymap";
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "./tauri-invoke.js";
 
 import { ydoc, yXmlFragment, loadInitialDoc, forceSave, enablePersistence, switchDocument, loadDocumentState, isApplyingUpdate } from "./yjs-setup.js";
 import { stepToSemanticPatch } from "./patch-extractor.js";
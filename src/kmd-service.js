@@ -1,7 +1,7 @@
 // src/kmd-service.js
 // Service for KMD (Korppi Markdown Document) file operations
 
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "./tauri-invoke.js";
 import { save, open } from "@tauri-apps/plugin-dialog";
 
 /**
@@ -44,29 +44,116 @@ export async function importDocument() {
  * Export the document as a plain Markdown file.
  * Gets the current editor content and saves it.
  * @param {string} markdownContent - The markdown content to export
+ * @param {Object|null} annotations - Comments and reviews to include:
+ *   { doc_id, include_comments, include_reviews, comment_placement }, where
+ *   comment_placement is "footnotes", "appendix", "criticmarkup" or "html"
+ * @param {string|null} docId - Document whose metadata goes into YAML frontmatter
  * @returns {Promise<string|null>} Export path or null if cancelled
  */
-export async function exportAsMarkdown(markdownContent) {
+export async function exportAsMarkdown(markdownContent, annotations = null, docId = null) {
     const path = await save({
         filters: [{ name: 'Markdown', extensions: ['md'] }],
         defaultPath: 'document.md'
     });
 
     if (path) {
-        await invoke("export_markdown", { path, content: markdownContent });
+        await invoke("export_markdown", { path, content: markdownContent, annotations, docId });
         return path;
     }
     return null;
 }
 
+/**
+ * Export the document as a Quarto (.qmd) or R Markdown (.Rmd) file.
+ * Restores the frontmatter and code chunk headers of the file the document
+ * was imported from; the format follows the chosen extension.
+ * @param {string} markdownContent - The markdown content to export
+ * @param {string} docId - The open document
+ * @returns {Promise<string|null>} Export path or null if cancelled
+ */
+export async function exportAsSource(markdownContent, docId) {
+    const path = await save({
+        filters: [
+            { name: 'Quarto', extensions: ['qmd'] },
+            { name: 'R Markdown', extensions: ['Rmd', 'rmd'] }
+        ],
+        defaultPath: 'document.qmd'
+    });
+
+    if (path) {
+        const command = path.toLowerCase().endsWith(".rmd") ? "export_rmarkdown" : "export_quarto";
+        await invoke(command, { docId, path, content: markdownContent });
+        return path;
+    }
+    return null;
+}
+
+/**
+ * Run the code chunks of an imported Quarto or R Markdown document with
+ * quarto and embed the figures they produce after their chunks.
+ * @param {string} markdownContent - The current editor content
+ * @param {string} docId - Document whose source metadata and assets to use
+ * @returns {Promise<{content: string, figures: Array, unplaced: number}|null>} null if quarto is missing
+ */
+export async function renderQuartoFigures(markdownContent, docId) {
+    const hasQuarto = await invoke("check_quarto_available");
+    if (!hasQuarto) {
+        alert("Rendering figures requires Quarto.\n\nInstall it from https://quarto.org/docs/get-started/ or set its path in the settings.");
+        return null;
+    }
+    return invoke("render_quarto_figures", { docId, content: markdownContent });
+}
+
+/**
+ * List the scripts attached to the figures of a document.
+ * @param {string} docId - Document ID
+ * @returns {Promise<Array<{label: string, script: string, asset: string|null, regenerated_at: number|null}>>}
+ */
+export async function listFigureSources(docId) {
+    return invoke("list_figure_sources", { id: docId });
+}
+
+/**
+ * Attach the R or Python script that draws a figure.
+ * @param {string} docId - Document ID
+ * @param {string} label - Figure label ("fig:cars")
+ * @param {string} scriptPath - Path of the .R or .py script, in the document's folder
+ * @returns {Promise<{label: string, script: string, asset: string|null, regenerated_at: number|null}>}
+ */
+export async function attachFigureSource(docId, label, scriptPath) {
+    return invoke("attach_figure_source", { id: docId, label, scriptPath });
+}
+
+/**
+ * Forget the script attached to a figure.
+ * @param {string} docId - Document ID
+ * @param {string} label - Figure label
+ * @returns {Promise<boolean>} Whether the figure had a script
+ */
+export async function detachFigureSource(docId, label) {
+    return invoke("detach_figure_source", { id: docId, label });
+}
+
+/**
+ * Run the scripts attached to figures and point the figures at the new images.
+ * @param {string} docId - Document ID
+ * @param {string} markdownContent - The current editor content
+ * @param {string[]|null} labels - Figures to regenerate; all of them when null
+ * @returns {Promise<{content: string, figures: Array<{label: string, asset: string|null, placed: boolean, error?: string}>}>}
+ */
+export async function regenerateFigures(docId, markdownContent, labels = null) {
+    return invoke("regenerate_figures", { id: docId, content: markdownContent, labels });
+}
+
 /**
  * Export the document as a DOCX file.
  * Gets the current editor content and converts it to DOCX format.
  * Requires pandoc for proper conversion - shows warning if not available.
  * @param {string} markdownContent - The markdown content to export
+ * @param {string|null} docId - Document whose metadata becomes the document properties
  * @returns {Promise<string|null>} Export path or null if cancelled
  */
-export async function exportAsDocx(markdownContent) {
+export async function exportAsDocx(markdownContent, docId = null) {
     // Check if pandoc is available
     const hasPandoc = await invoke("check_pandoc_available");
 
@@ -84,7 +171,33 @@ export async function exportAsDocx(markdownContent) {
     });
 
     if (path) {
-        await invoke("export_docx", { path, content: markdownContent });
+        await invoke("export_docx", { path, content: markdownContent, docId });
+        return path;
+    }
+    return null;
+}
+
+/**
+ * Export the document as it was at a patch, e.g. a submitted version.
+ * The format follows the extension picked in the save dialog.
+ * @param {string} docId - The document ID
+ * @param {string} patchUuid - UUID of the patch
+ * @returns {Promise<string|null>} Export path or null if cancelled
+ */
+export async function exportAtPatch(docId, patchUuid) {
+    const path = await save({
+        filters: [
+            { name: 'Word Document', extensions: ['docx'] },
+            { name: 'Markdown', extensions: ['md'] },
+            { name: 'HTML', extensions: ['html'] },
+            { name: 'PDF', extensions: ['pdf'] }
+        ],
+        defaultPath: `document-${patchUuid.slice(0, 8)}.docx`
+    });
+
+    if (path) {
+        const format = path.split('.').pop().toLowerCase();
+        await invoke("export_at_patch", { docId, patchUuid, format, path });
         return path;
     }
     return null;
@@ -186,3 +299,248 @@ export async function setDocumentTitle(title) {
 export async function writeTextFile(path, content) {
     return await invoke("write_text_file", { path, content });
 }
+
+/**
+ * Queue an export of a document as of a patch; an "export-finished" event reports the result.
+ * @param {string} docId - Document ID
+ * @param {string} format - "docx", "md", "html" or "pdf"
+ * @param {string} path - Output path
+ * @param {string|null} patchUuid - Patch to export; the newest when null
+ * @returns {Promise<Object>} The queued export record
+ */
+export async function queueExport(docId, format, path, patchUuid = null) {
+    return await invoke("queue_export", { docId, format, path, patchUuid });
+}
+
+/**
+ * List the exports of a document, newest first.
+ * @param {string} docId - Document ID
+ * @returns {Promise<Array<{id: number, format: string, path: string, patch_uuid: string, created_at: number, status: string, error?: string}>>}
+ */
+export async function listExports(docId) {
+    return await invoke("list_exports", { docId });
+}
+
+/**
+ * Export again from the same patch and in the same format as an earlier export.
+ * @param {string} docId - Document ID
+ * @param {number} exportId - ID of the earlier export
+ * @param {string|null} path - New output path; the original one when null
+ * @returns {Promise<Object>} The queued export record
+ */
+export async function regenerateExport(docId, exportId, path = null) {
+    return await invoke("regenerate_export", { docId, exportId, path });
+}
+
+/**
+ * Set where and how often a document is backed up.
+ * @param {string} docId - Document ID
+ * @param {Object|null} backup - {directory, interval_hours, keep_daily, keep_weekly}; null to use the app settings
+ * @returns {Promise<void>}
+ */
+export async function setDocumentBackup(docId, backup) {
+    return await invoke("set_document_backup", { id: docId, backup });
+}
+
+/**
+ * Back up a saved document now, whatever the interval.
+ * @param {string} docId - Document ID
+ * @returns {Promise<{path: string, created_at: number, size: number}>} The new backup
+ */
+export async function runBackupNow(docId) {
+    return await invoke("run_backup_now", { id: docId });
+}
+
+/**
+ * List the backups of a document, newest first.
+ * @param {string} path - Path of the document
+ * @returns {Promise<Array<{path: string, created_at: number, size: number}>>}
+ */
+export async function listBackups(path) {
+    return await invoke("list_backups", { path });
+}
+
+/**
+ * Copy a backup of a document out of the backup folder.
+ * @param {string} path - Path of the document
+ * @param {string} backupPath - Path of the backup, as listed by listBackups
+ * @param {string|null} target - Where to restore it; next to the document when null
+ * @returns {Promise<string>} Path of the restored file
+ */
+export async function restoreFromBackup(path, backupPath, target = null) {
+    return await invoke("restore_from_backup", { path, backupPath, target });
+}
+
+/**
+ * Get the hooks that run for one document only.
+ * @param {string} docId - Document ID
+ * @returns {Promise<Array<Object>>} Hooks: {event, type, command|url, document, enabled}
+ */
+export async function getDocumentHooks(docId) {
+    return await invoke("get_document_hooks", { id: docId });
+}
+
+/**
+ * Replace the hooks that run for one document only.
+ * @param {string} docId - Document ID
+ * @param {Array<Object>} hooks - {event: "save"|"patch-recorded"|"export-finished", type: "command"|"webhook", command|url, enabled}
+ * @returns {Promise<Array<Object>>} The stored hooks
+ */
+export async function setDocumentHooks(docId, hooks) {
+    return await invoke("set_document_hooks", { id: docId, hooks });
+}
+
+/**
+ * Run a hook now with the details of a document, to try it out.
+ * @param {string} docId - Document ID
+ * @param {Object} hook - The hook to run
+ * @returns {Promise<string>} Output of the command, or the HTTP status of the webhook
+ */
+export async function testHook(docId, hook) {
+    return await invoke("test_hook", { id: docId, hook });
+}
+
+/**
+ * Report the patches of a document whose data is malformed.
+ * @param {string} docId - Document ID
+ * @returns {Promise<Array<{patch_id: number, uuid: string|null, kind: string, problem: string}>>}
+ */
+export async function lintHistory(docId) {
+    return await invoke("lint_history", { id: docId });
+}
+
+/**
+ * Report what a document's size is made of and which soft limits it is over.
+ * @param {string} docId - Document ID
+ * @returns {Promise<{yjs_state: number, snapshots: number, patches: number, comments: number, assets: number, history_file: number, total: number, file_size?: number, warnings: Array<{part: string, size: number, limit: number}>, suggest_compaction: boolean}>}
+ */
+export async function getDocumentSizeBreakdown(docId) {
+    return await invoke("get_document_size_breakdown", { docId });
+}
+
+/**
+ * Write the changes between two patches as markdown with CriticMarkup
+ * ({++added++}, {--deleted--}, {~~old~>new~~}).
+ * @param {string} docId - Document ID
+ * @param {number} patchA - Patch whose text is the base
+ * @param {number} patchB - Patch whose changes are marked up
+ * @param {string} path - Output file
+ * @returns {Promise<void>}
+ */
+export async function exportDiffCriticMarkup(docId, patchA, patchB, path) {
+    return await invoke("export_diff_criticmarkup", { docId, patchA, patchB, path });
+}
+
+/**
+ * Set or clear the writing goal of a document.
+ * @param {string} docId - Document ID
+ * @param {number|null} targetWords - Words to reach; null clears the goal
+ * @param {string|null} deadline - Date, "YYYY-MM-DD"
+ * @param {number|null} targetCharacters - Characters to reach
+ * @returns {Promise<void>}
+ */
+export async function setWritingGoal(docId, targetWords, deadline = null, targetCharacters = null) {
+    return await invoke("set_writing_goal", { docId, targetWords, deadline, targetCharacters });
+}
+
+/**
+ * Get the writing goal of a document with its progress per day.
+ * @param {string} docId - Document ID
+ * @returns {Promise<{goal: Object|null, current_words: number, current_characters: number, days: Array<{date: string, words_added: number, words_deleted: number, characters_added: number, snapshots: number}>, streak: number, remaining_words: number, days_left: number|null, words_per_day_needed: number|null, reached: boolean}>}
+ */
+export async function getGoalProgress(docId) {
+    return await invoke("get_goal_progress", { docId });
+}
+
+/**
+ * Get the patches a reviewer has not reviewed, with their changes since the
+ * last patch the reviewer accepted.
+ * @param {string} docId - Document ID
+ * @param {string} reviewerId - Reviewer ID
+ * @returns {Promise<{reviewer_id: string, baseline_uuid: string|null, items: Array<{patch: Object, hunks: Array, cumulative: boolean}>}>}
+ */
+export async function getReviewQueue(docId, reviewerId) {
+    return await invoke("get_review_queue", { docId, reviewerId });
+}
+
+/**
+ * Get the title, abstract, keywords and custom fields of a document.
+ * @param {string} docId - Document ID
+ * @returns {Promise<{title: string, abstract: string|null, keywords: string[], custom_fields: Object<string, string>}>}
+ */
+export async function getDocumentMetadata(docId) {
+    return await invoke("get_document_metadata", { docId });
+}
+
+/**
+ * Set the abstract, keywords and custom fields of a document.
+ * Custom field names start with a letter and use letters, digits, "_" and "-".
+ * @param {string} docId - Document ID
+ * @param {string|null} abstractText - Abstract; empty or null clears it
+ * @param {string[]} keywords - Keywords
+ * @param {Object<string, string>} customFields - Replaces the existing fields
+ * @returns {Promise<{title: string, abstract: string|null, keywords: string[], custom_fields: Object<string, string>}>}
+ */
+export async function setDocumentMetadata(docId, abstractText, keywords = [], customFields = {}) {
+    return await invoke("set_document_metadata", { docId, abstractText, keywords, customFields });
+}
+
+/**
+ * Put a workspace, or some of its documents, in one .kmd-workspace archive
+ * with this computer's profile and, if asked, its encryption key.
+ * @param {string} workspaceId - Workspace ID
+ * @param {string} path - Archive path
+ * @param {string[]|null} documents - Member paths to include; null for all
+ * @param {boolean} includeIdentity - Include the encryption key
+ * @returns {Promise<{manifest: Object, warnings: string[]}>}
+ */
+export async function exportWorkspaceArchive(workspaceId, path, documents = null, includeIdentity = false) {
+    return await invoke("export_workspace_archive", { workspaceId, path, documents, includeIdentity });
+}
+
+/**
+ * Restore a .kmd-workspace archive: its documents are written to targetDir
+ * and the workspace is created with them.
+ * @param {string} path - Archive path
+ * @param {string} targetDir - Folder for the documents
+ * @returns {Promise<{workspace: Object, documents: string[], profile_restored: boolean, identity_restored: boolean, warnings: string[]}>}
+ */
+export async function importWorkspaceArchive(path, targetDir) {
+    return await invoke("import_workspace_archive", { path, targetDir });
+}
+
+/**
+ * List the patches of a document that match a filter, sorted on the backend.
+ * Author names sort in the order of the document language.
+ * @param {string} docId - Document ID
+ * @param {{authors?: string[], kinds?: string[], since?: number, until?: number, review_status?: "pending"|"accepted"|"rejected"|"contested", query?: string, sort_by?: "timestamp"|"author"|"kind", descending?: boolean}} filter
+ * @param {{limit?: number, offset?: number, includeSnapshots?: boolean}} options - Paging in the order of the filter
+ * @returns {Promise<Array<Object>>}
+ */
+export async function listDocumentPatches(docId, filter = {}, { limit = null, offset = null, includeSnapshots = true } = {}) {
+    return await invoke("list_document_patches", { id: docId, filter, limit, offset, includeSnapshots });
+}
+
+/**
+ * Preview applying a patch bundle without changing the document or its history.
+ * @param {string} docId - Document ID
+ * @param {string} path - Bundle path
+ * @returns {Promise<{imported_patches: number, skipped_patches: number, state_changed: boolean, lock_warnings?: Array<Object>, text: string, hunks: Array<Object>}>}
+ */
+export async function dryRunImportBundle(docId, path) {
+    return await invoke("dry_run_import_bundle", { docId, path });
+}
+
+/**
+ * Export the patches, reviews and comments of a document for analysis.
+ * JSON is written to the file at path; CSV writes patches.csv, reviews.csv,
+ * review_comments.csv and comments.csv into the folder at path.
+ * @param {string} docId - Document ID
+ * @param {"json"|"csv"} format - Export format
+ * @param {string} path - File (JSON) or folder (CSV)
+ * @param {boolean} includeSnapshots - Include the full text of each version
+ * @returns {Promise<string[]>} Paths of the files written
+ */
+export async function exportHistoryData(docId, format, path, includeSnapshots = false) {
+    return await invoke("export_history_data", { docId, format, path, includeSnapshots });
+}
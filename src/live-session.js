@@ -0,0 +1,152 @@
+// src/live-session.js
+// Live editing with people on the same network, relayed by the backend
+
+import * as Y from "yjs";
+import { invoke } from "./tauri-invoke.js";
+import { getActiveDocumentId } from "./document-manager.js";
+import { getCachedProfile } from "./profile-service.js";
+import { ydoc, beginApplyingDiskUpdates, endApplyingDiskUpdates } from "./yjs-setup.js";
+import { onLiveUpdate, onLiveAwareness, onLivePeerLeft } from "./document-events.js";
+
+const LIVE_ORIGIN = "live";
+const AWARENESS_INTERVAL_MS = 15 * 1000;
+
+// Document IDs with a live session, and the peers seen in each
+const sessions = new Map();
+let awarenessIntervalId = null;
+
+/**
+ * Initialize the Live Session button and the update relay
+ */
+export function initLiveSession() {
+    const button = document.getElementById("live-session-btn");
+    if (button) {
+        button.addEventListener("click", toggleLiveSession);
+    }
+
+    watchDocument();
+    window.addEventListener("yjs-doc-replaced", watchDocument);
+
+    onLiveUpdate((event) => {
+        if (event.doc_id !== getActiveDocumentId()) return;
+        beginApplyingDiskUpdates();
+        try {
+            Y.applyUpdate(ydoc, new Uint8Array(event.update), LIVE_ORIGIN);
+        } finally {
+            endApplyingDiskUpdates();
+        }
+    });
+
+    onLiveAwareness((event) => {
+        const peers = sessions.get(event.doc_id);
+        if (!peers) return;
+        try {
+            peers.set(event.peer, JSON.parse(new TextDecoder().decode(new Uint8Array(event.data))));
+        } catch (err) {
+            console.warn("Ignoring malformed awareness data:", err);
+        }
+        updateButton();
+    });
+
+    onLivePeerLeft((event) => {
+        const peers = sessions.get(event.doc_id);
+        if (!peers) return;
+        peers.delete(event.peer);
+        if (event.peer === "host") {
+            sessions.delete(event.doc_id);
+            alert("The live session host disconnected.");
+        }
+        updateButton();
+    });
+}
+
+/**
+ * Send local edits of the current Yjs document to live peers
+ */
+function watchDocument() {
+    ydoc.on("update", (update, origin) => {
+        const docId = getActiveDocumentId();
+        if (origin === LIVE_ORIGIN || !docId || !sessions.has(docId)) return;
+        invoke("send_live_update", { docId, update: Array.from(update) }).catch((err) => {
+            console.error("Failed to send live update:", err);
+        });
+    });
+}
+
+/**
+ * Host or join a session for the active document, or leave its session
+ */
+async function toggleLiveSession() {
+    const docId = getActiveDocumentId();
+    if (!docId) return;
+
+    try {
+        if (sessions.has(docId)) {
+            if (confirm("Leave the live session for this document?")) {
+                await invoke("leave_live_session", { docId });
+                sessions.delete(docId);
+            }
+        } else {
+            const found = await invoke("discover_live_sessions", { timeoutMs: null }).catch((err) => {
+                console.warn("Live session discovery failed:", err);
+                return [];
+            });
+            const choices = found.map((s, i) => `${i + 1}. ${s.title || "Untitled"} (${s.author} on ${s.host})`);
+            const message = choices.length > 0
+                ? `Sessions on your network:\n${choices.join("\n")}\n\nEnter a number or an address (ws://...) to join, or leave empty to host this document:`
+                : "Enter the address of a session to join (ws://...), or leave empty to host this document:";
+            const answer = prompt(message);
+            if (answer === null) return;
+            const choice = found[parseInt(answer.trim(), 10) - 1];
+            let url = answer.trim();
+            if (choice) {
+                // Sessions are not advertised with their token
+                const code = prompt("Enter the session code from the host's link (the part after token=):");
+                if (!code) return;
+                url = `${choice.url}/?token=${encodeURIComponent(code.trim())}`;
+            }
+            const info = url
+                ? await invoke("join_live_session", { docId, url })
+                : await invoke("host_live_session", { docId, port: null });
+            sessions.set(docId, new Map());
+            sendAwareness();
+            if (info.role === "host") {
+                alert(`Hosting a live session. Share this link with the people who should join; anyone with it can edit:\n\n${info.url}`);
+            }
+        }
+    } catch (err) {
+        console.error("Live session failed:", err);
+        alert("Live session failed: " + err);
+    }
+    updateButton();
+}
+
+/**
+ * Tell peers who we are; repeated so late joiners learn it too
+ */
+function sendAwareness() {
+    if (!awarenessIntervalId) {
+        awarenessIntervalId = setInterval(sendAwareness, AWARENESS_INTERVAL_MS);
+    }
+    const profile = getCachedProfile();
+    const data = new TextEncoder().encode(JSON.stringify({
+        name: profile?.name || "Local User",
+        color: profile?.color || "#3498db"
+    }));
+    for (const docId of sessions.keys()) {
+        invoke("send_live_awareness", { docId, data: Array.from(data) }).catch(() => { });
+    }
+}
+
+function updateButton() {
+    const button = document.getElementById("live-session-btn");
+    if (!button) return;
+    const peers = sessions.get(getActiveDocumentId());
+    const label = button.querySelector(".label");
+    if (label) {
+        label.textContent = peers ? `Live Session (${peers.size})` : "Live Session";
+    }
+    button.title = peers && peers.size > 0
+        ? `Editing live with ${[...peers.values()].map((p) => p.name).join(", ")}`
+        : "Edit together with people on the same network";
+}
@@ -1,7 +1,7 @@
 import { initEditor, getMarkdown, doUndo, doRedo, setMarkdownContent } from "./editor.js";
 import { fetchPatchList, fetchPatch, renderPatchList, renderPatchDetails, initTimeline } from "./timeline.js";
 import { initConflictUI } from "./conflict-ui.js";
-import { exportAsMarkdown, exportAsDocx } from "./kmd-service.js";
+import { exportAsMarkdown, exportAsDocx, exportAsSource, renderQuartoFigures } from "./kmd-service.js";
 import { forceSave } from "./yjs-setup.js";
 import { startReconciliation } from "./reconcile.js";
 import { getCurrentWindow } from "@tauri-apps/api/window";
@@ -16,7 +16,10 @@ import {
     getRecentDocuments,
     clearRecentDocuments,
     getOpenDocuments,
-    onDocumentChange
+    onDocumentChange,
+    openInNewWindow,
+    closeDocument,
+    getActiveDocumentId
 } from "./document-manager.js";
 import { initDocumentTabs } from "./document-tabs.js";
 import { initKeyboardShortcuts } from "./keyboard-shortcuts.js";
@@ -32,10 +35,17 @@ import { listComments } from "./comments-service.js";
 import { initWelcomeModal } from "./components/welcome-modal.js";
 import { initSearch } from "./search.js";
 import { initAutosave } from "./autosave.js";
+import { initPresence } from "./presence.js";
+import { initLiveSession } from "./live-session.js";
 import { initWordCount } from "./word-count.js";
 import { initSidebarController } from "./components/sidebar-controller.js";
 import { initPatchMergeWizard, openPatchMergeWizard } from "./patch-merge-wizard.js";
 import { initHunkReviewPanel } from "./hunk-review-panel.js";
+import { initSectionLocks } from "./section-locks.js";
+import { onOpenFileRequested } from "./document-events.js";
+import { initPositionLinks } from "./position-links.js";
+import { initDropImport } from "./drop-import.js";
+import { initDocumentAssets, refreshDocumentAssets, withAbsoluteAssetPaths } from "./document-assets.js";
 
 // Store the current markdown content
 let currentMarkdown = "";
@@ -79,7 +89,7 @@ async function confirmExportWithWarnings() {
 
         // Importing profile service dynamically to avoid circular deps if any
         const { getCachedProfile } = await import("./profile-service.js");
-        const { invoke } = await import("@tauri-apps/api/core");
+        const { invoke } = await import("./tauri-invoke.js");
         const { getActiveDocumentId } = await import("./document-manager.js");
 
         const profile = getCachedProfile();
@@ -145,7 +155,17 @@ async function showRecentDocuments() {
                         <span class="doc-path">${doc.path}</span>
                     </div>
                     <span class="doc-date">${new Date(doc.last_opened).toLocaleDateString()}</span>
+                    <button class="open-new-window" title="Open in new window">⧉</button>
                 `;
+                li.querySelector(".open-new-window").addEventListener("click", async (event) => {
+                    event.stopPropagation();
+                    try {
+                        await openInNewWindow(doc.path);
+                    } catch (err) {
+                        console.error("Failed to open window:", err);
+                        alert("Failed to open window: " + err);
+                    }
+                });
                 li.addEventListener("click", async () => {
                     try {
                         await openDocument(doc.path);
@@ -222,6 +242,8 @@ window.addEventListener("DOMContentLoaded", async () => {
     const reconcileBtn = document.getElementById("reconcile-btn");
     const exportMdBtn = document.getElementById("export-md-btn");
     const exportDocxBtn = document.getElementById("export-docx-btn");
+    const exportSourceBtn = document.getElementById("export-source-btn");
+    const renderFiguresBtn = document.getElementById("render-figures-btn");
     const newTabBtn = document.getElementById("new-tab-btn");
     const undoBtn = document.getElementById("undo-btn");
     const redoBtn = document.getElementById("redo-btn");
@@ -302,7 +324,16 @@ window.addEventListener("DOMContentLoaded", async () => {
         saveDocBtn.addEventListener("click", async () => {
             try {
                 await forceSave();
-                await saveDocument();
+                const handle = await saveDocument();
+                if (handle.external_merge) {
+                    const { imported_patches, diverged } = handle.external_merge;
+                    alert(
+                        `The file was changed on disk since it was opened. ` +
+                        `${imported_patches.length} patch(es) were imported and merged` +
+                        (diverged ? `; both versions had edits, review them before saving again.` : `.`) +
+                        ` Save again to write the merged document.`
+                    );
+                }
             } catch (err) {
                 if (!err.toString().includes("cancelled")) {
                     console.error("Failed to save document:", err);
@@ -343,7 +374,7 @@ window.addEventListener("DOMContentLoaded", async () => {
                 if (!proceed) return;
 
                 const markdown = getMarkdown();
-                const path = await exportAsMarkdown(markdown);
+                const path = await exportAsMarkdown(markdown, null, getActiveDocumentId());
             } catch (err) {
                 console.error("Markdown export failed:", err);
                 alert("Markdown export failed: " + err);
@@ -358,8 +389,8 @@ window.addEventListener("DOMContentLoaded", async () => {
                 const proceed = await confirmExportWithWarnings();
                 if (!proceed) return;
 
-                const markdown = getMarkdown();
-                const path = await exportAsDocx(markdown);
+                const markdown = withAbsoluteAssetPaths(getMarkdown());
+                const path = await exportAsDocx(markdown, getActiveDocumentId());
                 if (path) {
                     console.log("DOCX exported successfully to:", path);
                 }
@@ -370,15 +401,61 @@ window.addEventListener("DOMContentLoaded", async () => {
         });
     }
 
+    if (exportSourceBtn) {
+        exportSourceBtn.addEventListener("click", async () => {
+            try {
+                const docId = getActiveDocumentId();
+                if (!docId) return;
+
+                const proceed = await confirmExportWithWarnings();
+                if (!proceed) return;
+
+                await exportAsSource(getMarkdown(), docId);
+            } catch (err) {
+                console.error("Quarto/R Markdown export failed:", err);
+                alert("Quarto/R Markdown export failed: " + err);
+            }
+        });
+    }
+
+    if (renderFiguresBtn) {
+        renderFiguresBtn.addEventListener("click", async () => {
+            const docId = getActiveDocumentId();
+            if (!docId) return;
+
+            renderFiguresBtn.disabled = true;
+            try {
+                const result = await renderQuartoFigures(getMarkdown(), docId);
+                if (!result) return;
+
+                setMarkdownContent(result.content);
+                await refreshDocumentAssets();
+                if (result.unplaced > 0) {
+                    alert(`${result.unplaced} figure(s) came from chunks without a label and were not embedded. Give those chunks a fig- label to show them.`);
+                }
+            } catch (err) {
+                console.error("Rendering figures failed:", err);
+                alert("Rendering figures failed: " + err);
+            } finally {
+                renderFiguresBtn.disabled = false;
+            }
+        });
+    }
+
     // 3. Initialize Conflict UI and Keyboard Shortcuts
     initConflictUI();
     initKeyboardShortcuts();
     initDocumentTabs();
     initSearch();
     initAutosave();
+    initPresence();
+    initLiveSession();
     initWordCount();
     initPatchMergeWizard();
     initHunkReviewPanel();
+    initSectionLocks();
+    initDropImport();
+    initDocumentAssets();
 
     // Wire up Merge Patches button
     const mergePatchesBtn = document.getElementById("merge-patches-btn");
@@ -469,6 +546,17 @@ window.addEventListener("DOMContentLoaded", async () => {
         showRecentDocuments();
     }
 
+    // Files double-clicked while Korppi is running open here
+    onOpenFileRequested(async (path) => {
+        try {
+            await openDocument(path);
+            hideRecentDocuments();
+        } catch (err) {
+            console.error("Failed to open document:", err);
+            alert("Failed to open document: " + err);
+        }
+    });
+
     // 7. Listen for document changes
     onDocumentChange((event, doc) => {
         updateDocumentUI();
@@ -694,8 +782,21 @@ window.addEventListener("DOMContentLoaded", async () => {
         console.error("Failed to initialize editor:", err);
     }
 
+    // korppi:// links jump to a comment or section once the editor is up
+    initPositionLinks().catch((err) => console.error("Failed to open link:", err));
+
     // 9. Handle window close with unsaved changes prompt
     const appWindow = getCurrentWindow();
+
+    // Documents of secondary windows close with them; the main window's
+    // are cleaned up when the app exits
+    const closeWindowDocuments = async () => {
+        if (appWindow.label === "main") return;
+        for (const id of Array.from(getOpenDocuments().keys())) {
+            await closeDocument(id, true).catch(console.error);
+        }
+    };
+
     await appWindow.onCloseRequested(async (event) => {
         // Check for any unsaved documents
         const docs = getOpenDocuments();
@@ -739,6 +840,7 @@ window.addEventListener("DOMContentLoaded", async () => {
             } catch (err) {
                 console.error("Failed to save Yjs state:", err);
             }
+            await closeWindowDocuments();
 
             // Now close the window
             try {
@@ -755,6 +857,7 @@ window.addEventListener("DOMContentLoaded", async () => {
             } catch (err) {
                 console.error("Failed to save Yjs state:", err);
             }
+            await closeWindowDocuments();
         }
     });
 });
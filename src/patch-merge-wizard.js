@@ -1,7 +1,7 @@
 // src/patch-merge-wizard.js
 // Three-way patch merge wizard UI with conflict group and zone-based support
 
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "./tauri-invoke.js";
 import { getActiveDocumentId } from "./document-manager.js";
 import { fetchPatchList, hasSnapshotContent, refreshTimeline } from "./timeline.js";
 import { detectPatchConflicts } from "./conflict-detection.js";
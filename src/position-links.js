@@ -0,0 +1,92 @@
+// src/position-links.js
+// korppi:// links to a comment anchor or a {#sec:label} section
+
+import { invoke } from "./tauri-invoke.js";
+import { openDocument, setActiveDocument, getActiveDocumentId, getDocumentOutline } from "./document-manager.js";
+import { onPositionLinkOpened } from "./document-events.js";
+import { resolveAnchor } from "./comments-service.js";
+import { highlightByText, scrollToEditorRange } from "./editor.js";
+
+/**
+ * Make a link to a position in a document
+ * @param {string} docId
+ * @param {string|null} anchor - Comment anchor (Yjs relative position JSON),
+ *   a section label such as "sec:methods", or null for the document itself
+ * @returns {Promise<string>} korppi:// URL
+ */
+export async function generatePositionLink(docId, anchor = null) {
+    return await invoke("generate_position_link", { docId, anchor });
+}
+
+/**
+ * Find the document a korppi:// link points to
+ * @param {string} link
+ * @returns {Promise<{doc_uuid: string, anchor?: string, section?: string, doc_id: string|null, path: string|null}>}
+ */
+export async function resolvePositionLink(link) {
+    return await invoke("resolve_position_link", { link });
+}
+
+/**
+ * The link this window was started with, already resolved
+ */
+export async function getInitialPositionLink() {
+    return await invoke("get_initial_position_link");
+}
+
+/**
+ * Open the document of a resolved link and jump to its position
+ * @param {Object} resolved - Result of resolvePositionLink
+ */
+export async function openPositionLink(resolved) {
+    let docId = resolved.doc_id;
+    if (docId) {
+        setActiveDocument(docId);
+    } else if (resolved.path) {
+        docId = (await openDocument(resolved.path)).id;
+    } else {
+        alert("The linked document is not open and was not found among the recent documents.");
+        return;
+    }
+
+    if (resolved.anchor) {
+        const range = resolveAnchor(resolved.anchor, resolved.anchor);
+        if (range) {
+            setTimeout(() => scrollToEditorRange(range.from, range.from), 0);
+        }
+    } else if (resolved.section) {
+        const section = findSection(await getDocumentOutline(docId), resolved.section);
+        if (section) {
+            highlightByText(section.title, "point");
+        }
+    }
+}
+
+function findSection(outline, label) {
+    for (const node of outline) {
+        if (node.label === label) return node;
+        const child = findSection(node.children, label);
+        if (child) return child;
+    }
+    return null;
+}
+
+/**
+ * Follow links opened while Korppi runs and the one it was started with
+ */
+export async function initPositionLinks() {
+    onPositionLinkOpened(async (resolved) => {
+        try {
+            await openPositionLink(resolved);
+        } catch (err) {
+            console.error("Failed to open link:", err);
+            alert("Failed to open link: " + err);
+        }
+    });
+
+    const initial = await getInitialPositionLink();
+    if (initial) {
+        // Its document was opened with the window
+        await openPositionLink({ ...initial, doc_id: initial.path ? getActiveDocumentId() : initial.doc_id });
+    }
+}
@@ -0,0 +1,67 @@
+// src/presence.js
+// Shows who else has the active document open on a shared folder
+
+import { invoke } from "./tauri-invoke.js";
+import { getActiveDocumentId, onDocumentChange } from "./document-manager.js";
+
+const HEARTBEAT_INTERVAL_MS = 30 * 1000;
+
+let intervalId = null;
+
+/**
+ * Initialize presence heartbeats and the collaborators indicator
+ */
+export function initPresence() {
+    if (intervalId) {
+        clearInterval(intervalId);
+    }
+    intervalId = setInterval(refreshPresence, HEARTBEAT_INTERVAL_MS);
+
+    onDocumentChange((event) => {
+        if (event === "open" || event === "activeChange" || event === "save") {
+            refreshPresence();
+        }
+    });
+
+    refreshPresence();
+}
+
+/**
+ * Refresh our presence file and show the other collaborators
+ */
+async function refreshPresence() {
+    const docId = getActiveDocumentId();
+    if (!docId) {
+        renderCollaborators([]);
+        return;
+    }
+    try {
+        await invoke("heartbeat_presence", { docId });
+        const collaborators = await invoke("get_active_collaborators", { docId });
+        if (docId === getActiveDocumentId()) {
+            renderCollaborators(collaborators);
+        }
+    } catch (err) {
+        console.error("Presence update failed:", err);
+    }
+}
+
+/**
+ * @param {Array<{author_name: string, color: string, host: string}>} collaborators
+ */
+function renderCollaborators(collaborators) {
+    const row = document.getElementById("presence-row");
+    const list = document.getElementById("active-collaborators");
+    if (!row || !list) return;
+
+    row.hidden = collaborators.length === 0;
+    list.innerHTML = "";
+    for (const person of collaborators) {
+        const badge = document.createElement("span");
+        badge.className = "collaborator-badge";
+        badge.textContent = person.author_name;
+        badge.title = `${person.author_name} on ${person.host}`;
+        badge.style.borderColor = person.color;
+        list.appendChild(badge);
+    }
+}
@@ -1,5 +1,5 @@
 // src/profile-service.js
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "./tauri-invoke.js";
 
 // Cached profile to avoid async calls in hot paths
 let cachedProfile = null;
@@ -71,3 +71,34 @@ export function getCurrentUserInfo() {
 export async function initProfile() {
     return await getProfile();
 }
+
+/**
+ * Get a value stored for the current profile.
+ * @param {string} namespace - Name of the storing module, e.g. "timeline"
+ * @param {string} key - Letters, digits, ".", "_" and "-"
+ * @returns {Promise<any|null>} The stored value, or null
+ */
+export async function getProfileValue(namespace, key) {
+    return await invoke("profile_storage_get", { namespace, key });
+}
+
+/**
+ * Store a JSON value for the current profile (up to 64 KB).
+ * @param {string} namespace - Name of the storing module
+ * @param {string} key - Value name
+ * @param {any} value - Any JSON-serializable value
+ * @returns {Promise<void>}
+ */
+export async function setProfileValue(namespace, key, value) {
+    await invoke("profile_storage_set", { namespace, key, value });
+}
+
+/**
+ * Delete a stored value, or every value of a namespace when no key is given.
+ * @param {string} namespace - Name of the storing module
+ * @param {string|null} key - Value name
+ * @returns {Promise<boolean>} Whether anything was deleted
+ */
+export async function deleteProfileValue(namespace, key = null) {
+    return await invoke("profile_storage_delete", { namespace, key });
+}
@@ -1,7 +1,7 @@
 // src/profile-settings.js
 import { getProfile, saveProfile, getCachedProfile, initProfile } from "./profile-service.js";
 import { save, open } from "@tauri-apps/plugin-dialog";
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "./tauri-invoke.js";
 
 let modal = null;
 let nameInput = null;
@@ -147,6 +147,7 @@ async function handleSave() {
         email: emailInput.value.trim() || null,
         avatar_path: currentProfile.avatar_path || null,
         color: colorInput.value || "#3498db",
+        avatar_base64: currentProfile.avatar_base64 || null,
     };
 
     try {
@@ -1,9 +1,10 @@
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "./tauri-invoke.js";
 import { open } from "@tauri-apps/plugin-dialog";
 import { getActiveDocumentId } from "./document-manager.js";
 import { getMarkdown } from "./editor.js";
 import { showRightSidebar } from "./components/sidebar-controller.js";
 import { getCachedProfile } from "./profile-service.js";
+import { reanchorComments } from "./comments-ui.js";
 
 // In-memory storage for computed hunks during reconciliation
 // Map<documentId, Array<AuthoredHunk>>
@@ -82,6 +83,9 @@ export async function startReconciliation() {
         // Reuse the logic for calculating hunks
         await recalculateReconcileState(baseContent, null); // null patchId means no swap, just pure calc
 
+        // Imported comments carry anchors into the other document's Yjs state
+        await reanchorComments(baseContent);
+
         // Show UI only after first success
         showRightSidebar('timeline');
         window.dispatchEvent(new CustomEvent('reconciliation-imported'));
@@ -171,7 +175,8 @@ export async function recalculateReconcileState(newBaseContent, newBasePatchId =
     // 6. Calculate Hunks
     const hunks = await invoke("calculate_hunks_for_patches", {
         baseContent: newBaseContent,
-        patches: patchInputs
+        patches: patchInputs,
+        docId
     });
 
     // Store in per-document map
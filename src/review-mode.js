@@ -1,7 +1,7 @@
 // src/review-mode.js
 // Multi-author review mode with inline accept/reject controls
 
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "./tauri-invoke.js";
 import { getActiveDocumentId } from "./document-manager.js";
 import { calculateCharDiff } from "./diff-highlighter.js";
 import { getCachedProfile, getCurrentUserInfo } from "./profile-service.js";
@@ -225,6 +225,17 @@ export async function acceptPatch(patchId) {
         updateReviewProgress();
     } catch (err) {
         console.error("Failed to accept patch:", err);
+        // The document may require open comments on the changes to be resolved first
+        const blocking = await invoke("get_patch_blocking_comments", {
+            docId,
+            patchUuid: patch.uuid
+        }).catch(() => []);
+        if (blocking.length > 0) {
+            const list = blocking
+                .map(b => `• ${b.comment.author}: "${b.comment.selected_text}" — ${b.comment.content}`)
+                .join("\n");
+            alert(`${err}\n\nResolve these comments first:\n${list}`);
+        }
     }
 }
 
@@ -243,14 +254,39 @@ export async function rejectPatch(patchId) {
 
     const { id: currentUserId, name: currentUserName } = getCurrentUserInfo();
 
+    // Let the author know why; cancelling the prompt cancels the rejection
+    const reason = prompt("Reason for rejecting (optional):", "");
+    if (reason === null) return;
+
+    // Later changes built on this one can be marked as orphaned with it
+    const descendants = await invoke("get_patch_descendants", {
+        docId,
+        patchUuid: patch.uuid
+    }).catch(() => []);
+    const orphanDescendants = descendants.length > 0 && confirm(
+        `${descendants.length} later ${descendants.length === 1 ? "change builds" : "changes build"} on this one.\n\n` +
+        `Mark ${descendants.length === 1 ? "it" : "them"} as orphaned too?`
+    );
+
     try {
         await invoke("record_document_patch_review", {
             docId,
             patchUuid: patch.uuid,
             reviewerId: currentUserId,
             decision: "rejected",
-            reviewerName: currentUserName
+            reviewerName: currentUserName,
+            orphanDescendants
         });
+        if (reason.trim()) {
+            await invoke("add_review_comment", {
+                docId,
+                patchUuid: patch.uuid,
+                text: reason,
+                hunkId: null,
+                authorId: currentUserId,
+                authorName: currentUserName
+            });
+        }
 
         // Update local state
         if (!reviewState.patchReviews.has(patch.uuid)) {
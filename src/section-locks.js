@@ -0,0 +1,47 @@
+// src/section-locks.js
+// Advisory section locks keyed by {#sec:label}
+
+import { invoke } from "./tauri-invoke.js";
+import { listen } from "@tauri-apps/api/event";
+
+/**
+ * Lock a section for the current user
+ * @param {string} docId
+ * @param {string} label - Section label, e.g. "sec:methods"
+ */
+export async function lockSection(docId, label) {
+    return await invoke("lock_section", { docId, label });
+}
+
+/**
+ * Release a section lock
+ * @param {string} docId
+ * @param {string} label
+ * @param {boolean} force - Release a lock held by someone else
+ */
+export async function unlockSection(docId, label, force = false) {
+    return await invoke("unlock_section", { docId, label, force });
+}
+
+/**
+ * List the locks currently held in a document
+ * @param {string} docId
+ */
+export async function listSectionLocks(docId) {
+    return await invoke("list_section_locks", { docId });
+}
+
+/**
+ * Warn when imported patches change sections locked by someone else
+ */
+export function initSectionLocks() {
+    listen("section-lock-warnings", (event) => {
+        const warnings = event.payload || [];
+        if (warnings.length === 0) return;
+
+        const lines = warnings.map(w =>
+            `• ${w.label} (locked by ${w.holder_name}) changed by ${w.author}`
+        );
+        alert(`Imported changes touch locked sections:\n${lines.join("\n")}`);
+    });
+}
@@ -0,0 +1,42 @@
+// src/tauri-invoke.js
+// Wrapper around Tauri's invoke that turns structured backend errors
+// ({ code, message, hint }) into Error objects
+
+import { invoke as tauriInvoke } from "@tauri-apps/api/core";
+
+/**
+ * Error returned by a backend command.
+ * `code` is stable (e.g. "document_not_found", "pandoc_unavailable", "disk_full"),
+ * `hint` suggests a fix when the backend knows one.
+ */
+export class KorppiError extends Error {
+    constructor({ code, message, hint }) {
+        super(message);
+        this.name = "KorppiError";
+        this.code = code;
+        this.hint = hint || null;
+    }
+
+    // Keeps `"Failed: " + err` readable in existing alerts
+    toString() {
+        return this.hint ? `${this.message}\n\n${this.hint}` : this.message;
+    }
+}
+
+/**
+ * Invoke a backend command, rethrowing structured errors as KorppiError.
+ * Errors of commands that still return plain strings are passed through.
+ * @param {string} command - Command name
+ * @param {Object} [args] - Command arguments
+ * @returns {Promise<any>}
+ */
+export async function invoke(command, args) {
+    try {
+        return await tauriInvoke(command, args);
+    } catch (err) {
+        if (err && typeof err === "object" && typeof err.code === "string" && "message" in err) {
+            throw new KorppiError(err);
+        }
+        throw err;
+    }
+}
@@ -1,4 +1,4 @@
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "./tauri-invoke.js";
 import { forceSave, restoreDocumentState } from "./yjs-setup.js";
 import { getActiveDocumentId, onDocumentChange } from "./document-manager.js";
 import { enterPreview, exitPreview, isPreviewActive } from "./diff-preview.js";
@@ -9,6 +9,9 @@ import { getEditorContent, getMarkdown, setMarkdownContent } from "./editor.js";
 import { getCachedProfile } from "./profile-service.js";
 import { recalculateReconcileState } from './reconcile.js';
 import { resetHunkReview } from './hunk-review-panel.js';
+import { reanchorComments } from './comments-ui.js';
+import { escapeHtml } from './utils.js';
+import { exportAtPatch } from './kmd-service.js';
 
 // Track the currently selected/restored patch
 let restoredPatchId = null;
@@ -23,11 +26,15 @@ let conflictState = {
 // Only set to true on document open or after reconciliation
 let showConflictAlertOnNextRefresh = false;
 
-export async function fetchPatchList() {
+/**
+ * @param {Object|null} filter - Authors, kinds, since/until (ms), review_status,
+ *   query and sort_by/descending, as listDocumentPatches takes them
+ */
+export async function fetchPatchList(filter = null) {
     const docId = getActiveDocumentId();
     if (docId) {
         // Use document-specific patches
-        return await invoke("list_document_patches", { id: docId }).catch(() => []);
+        return await invoke("list_document_patches", { id: docId, filter }).catch(() => []);
     }
     // Fallback to global patches for legacy single-document mode
     return await invoke("list_patches").catch(() => []);
@@ -36,9 +43,8 @@ export async function fetchPatchList() {
 export async function fetchPatch(id) {
     const docId = getActiveDocumentId();
     if (docId) {
-        // Use document-specific patches and filter by ID
-        const patches = await invoke("list_document_patches", { id: docId }).catch(() => []);
-        return patches.find(patch => patch.id === id) || null;
+        // Fetch just this patch, with its snapshot
+        return await invoke("get_document_patch", { id: docId, patchId: id }).catch(() => null);
     }
     // Fallback to global patch for legacy single-document mode
     return await invoke("get_patch", { id }).catch(() => null);
@@ -86,6 +92,7 @@ export async function restoreToPatch(patchId) {
 
                 // Trigger Full Re-calculation (Perspective Switch)
                 await recalculateReconcileState(patch.data.snapshot, patchId);
+                await reanchorComments(patch.data.snapshot);
 
                 // Refresh the timeline to show the restored state
                 await refreshTimeline();
@@ -104,6 +111,7 @@ export async function restoreToPatch(patchId) {
 
                     // Trigger Full Re-calculation (Perspective Switch)
                     await recalculateReconcileState(result.snapshot_content, patchId);
+                    await reanchorComments(result.snapshot_content);
 
                     await refreshTimeline();
                     return true;
@@ -120,6 +128,7 @@ export async function restoreToPatch(patchId) {
 
                 // Trigger Full Re-calculation (Perspective Switch)
                 await recalculateReconcileState(result.snapshot_content, patchId);
+                await reanchorComments(result.snapshot_content);
 
                 await refreshTimeline();
                 return true;
@@ -242,7 +251,7 @@ export function initTimeline() {
 
     // Listen for document changes (open, switch, new) - refresh timeline but don't alert
     onDocumentChange(async (event, doc) => {
-        if (event === "open" || event === "new" || event === "activeChange") {
+        if (event === "open" || event === "new" || event === "activeChange" || event === "externalMerge") {
             // Don't set showConflictAlertOnNextRefresh - we only alert during reconciliation
             await refreshTimeline();
         }
@@ -396,6 +405,11 @@ export async function renderPatchList(patches) {
         if (patch.id === restoredPatchId) {
             div.classList.add("restored");
         }
+        // Built on a rejected change
+        if (patch.orphaned_by) {
+            div.classList.add("orphaned");
+            div.title = `Builds on rejected change ${patch.orphaned_by.slice(0, 8)}`;
+        }
         div.dataset.id = patch.id;
 
         const ts = new Date(patch.timestamp).toLocaleString();
@@ -472,6 +486,13 @@ export async function renderPatchList(patches) {
                     const name = review.reviewer_name || review.reviewer_id;
                     reviewBadges += `<span class="review-badge ${review.decision}" title="${name} ${review.decision}">${icon} ${name}</span>`;
                 }
+                // Reviewers' comments explain their decisions
+                for (const review of reviewerMap.values()) {
+                    const name = review.reviewer_name || review.reviewer_id;
+                    for (const comment of review.comments || []) {
+                        reviewBadges += `<div class="review-comment" style="font-size:0.75rem;color:#666;margin-top:2px;">💬 ${escapeHtml(name)}: ${escapeHtml(comment.content)}</div>`;
+                    }
+                }
                 reviewBadges += '</div>';
             }
         }
@@ -481,12 +502,14 @@ export async function renderPatchList(patches) {
                 <div class="timeline-item-info">
                     <strong>#${patch.id}</strong> - ${patch.kind}
                     <span class="author-badge" style="background-color:${authorColor};color:white;padding:2px 6px;border-radius:3px;font-size:0.75rem;margin-left:6px;">${authorDisplayName}</span>
+                    ${patch.unverified ? `<span class="review-badge unverified" title="Imported from a patch bundle not signed by ${escapeHtml(authorDisplayName)}; the author is not confirmed">? Unverified</span>` : ''}
                     ${conflictInfo ? `<div class="conflict-warning" style="color:#f44336;font-size:0.75rem;margin-top:2px;">${conflictInfo}</div>` : ''}
                     ${reviewBadges}
                 </div>
                 <div class="timeline-item-actions">
                     <button class="preview-btn" data-patch-id="${patch.id}" title="Preview diff">🔍 Preview</button>
                     <button class="restore-btn" data-patch-id="${patch.id}" title="Restore to this version">↩</button>
+                    ${patch.uuid ? `<button class="export-at-btn" data-patch-uuid="${patch.uuid}" title="Export this version">⤓</button>` : ''}
                 </div>
             </div>
             <div class="timeline-timestamp">${ts}</div>
@@ -525,6 +548,20 @@ export async function renderPatchList(patches) {
             await restoreToPatch(patchId);
         });
     });
+
+    // Add click handlers for export buttons
+    list.querySelectorAll('.export-at-btn').forEach(btn => {
+        btn.addEventListener('click', async (e) => {
+            e.stopPropagation();
+            const docId = getActiveDocumentId();
+            if (!docId) return;
+            try {
+                await exportAtPatch(docId, btn.dataset.patchUuid);
+            } catch (err) {
+                alert(`Export failed: ${err}`);
+            }
+        });
+    });
 }
 
 /**
@@ -6,6 +6,7 @@ import { fetchPatchList, hasSnapshotContent } from "./timeline.js";
 import { listComments } from "./comments-service.js";
 import { getActiveDocumentId } from "./document-manager.js";
 import { showRightSidebar } from "./components/sidebar-controller.js";
+import { onCommentAdded, onPatchRecorded } from "./document-events.js";
 
 let updateTimeout = null;
 let pendingUpdateTimeout = null;
@@ -68,8 +69,17 @@ export function initWordCount() {
         debouncedPendingUpdate();
     });
 
-    // Periodic refresh of pending counts (every 30 seconds)
-    setInterval(updatePendingCounts, 30000);
+    // Patches and comments recorded by any window or import
+    onPatchRecorded((event) => {
+        if (!event.doc_id || event.doc_id === getActiveDocumentId()) {
+            debouncedPendingUpdate();
+        }
+    });
+    onCommentAdded((event) => {
+        if (event.doc_id === getActiveDocumentId()) {
+            debouncedPendingUpdate();
+        }
+    });
 }
 
 function debouncedPendingUpdate() {
@@ -154,7 +164,7 @@ async function updatePendingCounts() {
     // Update pending patches
     try {
         // Importing modules dynamically to avoid circular dependencies
-        const { invoke } = await import("@tauri-apps/api/core");
+        const { invoke } = await import("./tauri-invoke.js");
         const { getCachedProfile } = await import("./profile-service.js");
 
         const profile = getCachedProfile();
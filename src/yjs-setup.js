@@ -1,6 +1,6 @@
 // src/yjs-setup.js
 import * as Y from "yjs";
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "./tauri-invoke.js";
 import { setLastPatchUuid } from "./patch-grouper.js";
 import {
     getActiveDocumentId,
@@ -39,19 +39,11 @@ export async function loadDocumentState(docId = null) {
             // console.log("YJS: Loaded state is empty");
         }
 
-        // Load the last patch UUID to continue the history chain
-        const patches = await invoke("list_document_patches", { id });
-        if (patches && patches.length > 0) {
-            // Sort by ID descending to get the latest
-            patches.sort((a, b) => b.id - a.id);
-            const lastPatch = patches[0];
-            if (lastPatch.uuid) {
-                setLastPatchUuid(lastPatch.uuid);
-            }
-        } else {
-            // No patches yet, start fresh chain
-            setLastPatchUuid(null);
-        }
+        // Load the last patch UUID to continue the history chain,
+        // without listing the whole history
+        const summary = await invoke("get_document_patch_summary", { id });
+        // No patches yet: start a fresh chain
+        setLastPatchUuid(summary?.latest_uuid ?? null);
 
     } catch (err) {
         console.warn("Failed to load document state, starting fresh:", err);
@@ -61,6 +53,13 @@ export async function loadDocumentState(docId = null) {
     }
 }
 
+// Pull in the merged state after a save found the file changed on disk
+onDocumentChange(async (event, doc) => {
+    if (event === "externalMerge" && doc && doc.id === currentDocId) {
+        await loadDocumentState(doc.id);
+    }
+});
+
 /**
  * Legacy: Load initial document from the old single-document store
  */